@@ -0,0 +1,94 @@
+extern crate x_lang;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use x_lang::corelib::HostManager;
+use x_lang::eval::{eval_expr, EvalContext};
+use x_lang::parser::{parse_expr_with_options, ParseOptions};
+
+/// Each program is run repeatedly for at least this long.
+const MIN_DURATION: Duration = Duration::from_secs(1);
+
+/// Counts the bytes allocated, so that the peak of an evaluation can be
+/// reported.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+        if !p.is_null() {
+            let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+        System.dealloc(p, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Recursion-heavy programs, where evaluation allocates the most, and
+/// closures made next to large values they do not refer to, which show
+/// how much of its environment a closure keeps alive.
+const CORPUS: &[(&str, &str)] = &[
+    ("fib", include_str!("../test_sources/x/fib.x")),
+    ("list_gen", include_str!("../test_sources/x/list_gen.x")),
+    (
+        "sqrt_bisect",
+        include_str!("../test_sources/x/sqrt_bisect.x"),
+    ),
+    ("list_map", include_str!("../examples/list_map.x")),
+    ("list_sum", include_str!("../examples/list_sum.x")),
+    (
+        "closure_env",
+        include_str!("../test_sources/x/closure_env.x"),
+    ),
+];
+
+/// Times evaluation alone (parsing and typechecking happen once up front),
+/// and reports the most memory a single evaluation had allocated at once.
+///
+/// Compare against a build with `--features plain-alloc` to see the effect
+/// of the evaluator's value pool.
+fn main() {
+    let hm = HostManager::new();
+    let mut opts = ParseOptions::default();
+    opts.add_hosts(hm.get_all());
+
+    for (name, source) in CORPUS {
+        let e = parse_expr_with_options(source, &opts).unwrap();
+        let start = Instant::now();
+        let mut iterations = 0u32;
+        while iterations == 0 || start.elapsed() < MIN_DURATION {
+            let mut ectx = EvalContext::default();
+            ectx.add_hosts(hm.get_all());
+            eval_expr(&e, &mut ectx).unwrap();
+            iterations += 1;
+        }
+        let elapsed = start.elapsed();
+
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(before, Ordering::Relaxed);
+        let mut ectx = EvalContext::default();
+        ectx.add_hosts(hm.get_all());
+        eval_expr(&e, &mut ectx).unwrap();
+        let peak = PEAK.load(Ordering::Relaxed) - before;
+
+        println!(
+            "{:<16} {:>12.1} us/iter ({} iterations) {:>10.1} KB peak",
+            name,
+            elapsed.as_secs_f64() * 1e6 / f64::from(iterations),
+            iterations,
+            peak as f64 / 1024.0
+        );
+    }
+}
@@ -0,0 +1,22 @@
+extern crate x_lang;
+
+use std::time::Instant;
+use x_lang::engine::Engine;
+
+const ITERATIONS: u32 = 100;
+
+/// Times the full pipeline over every embedded example.
+fn main() {
+    let engine = Engine::new();
+    for ex in x_lang::examples() {
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            engine.eval_str(ex.source).unwrap();
+        }
+        println!(
+            "{:<16} {:>10.1} us/iter",
+            ex.name,
+            start.elapsed().as_secs_f64() * 1e6 / f64::from(ITERATIONS)
+        );
+    }
+}
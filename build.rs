@@ -0,0 +1,47 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    generate_grpc();
+}
+
+/// Generates the server and client of the service in `proto/xlang.proto`,
+/// whose messages `src/grpc.rs` defines by hand, so building needs no
+/// `protoc`.
+#[cfg(feature = "grpc")]
+fn generate_grpc() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{}", input))
+            .output_type(format!("crate::grpc::{}", output))
+            .codec_path("tonic::codec::ProstCodec")
+            .build()
+    };
+    let service = Service::builder()
+        .name("Evaluator")
+        .package("xlang")
+        .method(method("parse", "Parse", "ParseRequest", "ParseResponse"))
+        .method(method(
+            "type_check",
+            "TypeCheck",
+            "TypeCheckRequest",
+            "TypeCheckResponse",
+        ))
+        .method(method(
+            "evaluate",
+            "Evaluate",
+            "EvaluateRequest",
+            "EvaluateResponse",
+        ))
+        .method(method(
+            "describe_hosts",
+            "DescribeHosts",
+            "DescribeHostsRequest",
+            "DescribeHostsResponse",
+        ))
+        .build();
+    Builder::new().compile(&[service]);
+}
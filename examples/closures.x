@@ -0,0 +1,5 @@
+(
+    (\compose adder ((compose (adder 1) (adder 10)) 5))
+    (\f g (\x (f (g x))))
+    (\n (\x ($add x n)))
+)
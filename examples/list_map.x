@@ -0,0 +1,22 @@
+(
+    (\map range (
+        (\squares ($add ($list_head squares) ($list_head ($list_tail squares))))
+        (map (\x ($mul x x)) (range 5))
+    ))
+    (
+        \y ((\x (y (x x))) (\x (y (x x)))) # Y Combinator
+        \self (\f xs
+            ($if
+                ($list_is_empty xs)
+                ~
+                ($list_push (f ($list_head xs)) (self f ($list_tail xs)))
+            )
+        )
+    )
+    (
+        \y ((\x (y (x x))) (\x (y (x x)))) # Y Combinator
+        \self (\n
+            ($if ($eq n 0) ~ ($list_push n (self ($sub n 1))))
+        )
+    )
+)
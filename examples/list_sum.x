@@ -0,0 +1,15 @@
+(
+    (\range sum (sum (range 10)))
+    (
+        \y ((\x (y (x x))) (\x (y (x x)))) # Y Combinator
+        \self (\n
+            ($if ($eq n 0) ~ ($list_push n (self ($sub n 1))))
+        )
+    )
+    (
+        \y ((\x (y (x x))) (\x (y (x x)))) # Y Combinator
+        \self (\xs
+            ($if ($list_is_empty xs) 0 ($add ($list_head xs) (self ($list_tail xs))))
+        )
+    )
+)
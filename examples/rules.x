@@ -0,0 +1,10 @@
+(
+    (\discount ($add (discount 120.0 12) (discount 80.0 6)))
+    (\price qty
+        ($if
+            ($and ($gt qty 10) ($gt price 100))
+            ($mul price 0.9)
+            ($if ($gt qty 5) ($mul price 0.95) price)
+        )
+    )
+)
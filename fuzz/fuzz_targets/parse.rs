@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use x_lang::corelib::HostManager;
+use x_lang::parser::{parse_expr_with_options, ParseOptions};
+use x_lang::typeck::{check_expr, TypeResolveState};
+
+fuzz_target!(|data: &str| {
+    let hm = HostManager::new();
+    let mut opts = ParseOptions::default();
+    opts.add_hosts(hm.get_all());
+
+    if let Ok(e) = parse_expr_with_options(data, &opts) {
+        let mut trs = TypeResolveState::default();
+        trs.add_hosts(hm.get_all());
+        let _ = check_expr(&e, &mut trs);
+    }
+});
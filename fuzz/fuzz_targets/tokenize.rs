@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use x_lang::parser::TokenStream;
+
+fuzz_target!(|data: &str| {
+    let mut ts = TokenStream::new(data);
+    while ts.next_token().is_ok() {}
+});
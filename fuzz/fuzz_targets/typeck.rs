@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use x_lang::ast::Expr;
+use x_lang::corelib::HostManager;
+use x_lang::typeck::{check_expr, TypeResolveState};
+
+fuzz_target!(|e: Expr| {
+    let hm = HostManager::new();
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_all());
+    let _ = check_expr(&e, &mut trs);
+});
@@ -0,0 +1,166 @@
+//! `#[xl_host]`, which exposes a Rust function to x-lang scripts as a host
+//! function. Enabled in `x-lang` with the `macros` feature; see
+//! `x_lang::host_fn` for the types it supports.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parse_macro_input, Error, Expr, FnArg, Ident, ItemFn, Lit, LitStr, Meta, Pat, ReturnType,
+};
+
+/// Generates a `HostFunction` named after the function, e.g. `ClampOp` for
+/// `clamp`, with an associated `NAME` holding the name scripts call it by.
+///
+/// ```ignore
+/// /// Clamps `x` to `[lo, hi]`.
+/// #[xl_host("clamp")]
+/// fn clamp(x: i64, lo: i64, hi: i64) -> i64 {
+///     x.max(lo).min(hi)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn xl_host(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let name = parse_macro_input!(attr as LitStr);
+    let f = parse_macro_input!(item as ItemFn);
+    match expand(&name, &f) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(name: &LitStr, f: &ItemFn) -> Result<proc_macro2::TokenStream, Error> {
+    let sig = &f.sig;
+    if !sig.generics.params.is_empty() || sig.asyncness.is_some() {
+        return Err(Error::new_spanned(
+            sig,
+            "host functions cannot be generic or async",
+        ));
+    }
+    let ret = match sig.output {
+        ReturnType::Type(_, ref ty) => ty,
+        ReturnType::Default => {
+            return Err(Error::new_spanned(
+                sig,
+                "host functions must return a value",
+            ))
+        }
+    };
+
+    let mut params = Vec::new();
+    let mut types = Vec::new();
+    for (i, arg) in sig.inputs.iter().enumerate() {
+        let arg = match *arg {
+            FnArg::Typed(ref arg) => arg,
+            FnArg::Receiver(ref r) => {
+                return Err(Error::new_spanned(r, "host functions cannot take self"))
+            }
+        };
+        params.push(match *arg.pat {
+            Pat::Ident(ref p) => p.ident.to_string(),
+            _ => format!("arg{}", i + 1),
+        });
+        types.push(&arg.ty);
+    }
+
+    let fn_name = &sig.ident;
+    let vis = &f.vis;
+    let op = Ident::new(&op_name(&fn_name.to_string()), Span::call_site());
+    let args: Vec<Ident> = (0..types.len())
+        .map(|i| Ident::new(&format!("__arg{}", i), Span::call_site()))
+        .collect();
+    let doc = match doc_of(f) {
+        Some(doc) => quote! {
+            fn doc(&self) -> Option<&str> {
+                Some(#doc)
+            }
+        },
+        None => quote! {},
+    };
+
+    Ok(quote! {
+        #f
+
+        #[derive(Debug)]
+        #vis struct #op;
+
+        impl #op {
+            /// The name scripts call the function by, without the `$`.
+            #vis const NAME: &'static str = #name;
+        }
+
+        impl ::x_lang::host::HostFunction for #op {
+            fn signature(&self) -> Option<::x_lang::host::Signature> {
+                Some(::x_lang::host::Signature::new(vec![
+                    #(::x_lang::host::Param::required(#params)),*
+                ]))
+            }
+
+            #doc
+
+            fn typeck(
+                &self,
+                params: &[::x_lang::ast::DataType],
+            ) -> Result<::x_lang::ast::DataType, ::x_lang::error::TypeError> {
+                ::x_lang::host_fn::typeck_args(
+                    #name,
+                    &[#(<#types as ::x_lang::host_fn::HostArg>::data_type()),*],
+                    params,
+                    <#ret as ::x_lang::host_fn::HostResult>::data_type(),
+                )
+            }
+
+            fn eval<'b, 'c>(
+                &self,
+                ectx: &mut ::x_lang::eval::EvalContext<'b, 'c>,
+                params: &mut dyn Iterator<Item = ::x_lang::eval::LazyValue<'b>>,
+            ) -> Result<::x_lang::eval::RuntimeValue<'b>, ::x_lang::error::RuntimeError> {
+                #(
+                    let #args = {
+                        let v = params.next().unwrap().eval(ectx)?;
+                        <#types as ::x_lang::host_fn::HostArg>::from_runtime(v, ectx)?
+                    };
+                )*
+                ::x_lang::host_fn::HostResult::into_runtime(#fn_name(#(#args),*), ectx)
+            }
+        }
+    })
+}
+
+/// `clamp_all` becomes `ClampAllOp`.
+fn op_name(fn_name: &str) -> String {
+    let mut out = String::new();
+    for part in fn_name.split('_').filter(|p| !p.is_empty()) {
+        let mut chars = part.chars();
+        out.extend(chars.next().unwrap().to_uppercase());
+        out.push_str(chars.as_str());
+    }
+    out.push_str("Op");
+    out
+}
+
+/// The function's doc comment, without the leading space of each line.
+fn doc_of(f: &ItemFn) -> Option<String> {
+    let mut lines = Vec::new();
+    for attr in &f.attrs {
+        if let Meta::NameValue(ref nv) = attr.meta {
+            if !nv.path.is_ident("doc") {
+                continue;
+            }
+            if let Expr::Lit(ref lit) = nv.value {
+                if let Lit::Str(ref s) = lit.lit {
+                    let line = s.value();
+                    lines.push(line.strip_prefix(' ').unwrap_or(&line).to_string());
+                }
+            }
+        }
+    }
+    let doc = lines.join("\n").trim().to_string();
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc)
+    }
+}
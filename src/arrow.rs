@@ -0,0 +1,367 @@
+//! Conversions between Arrow arrays and x-lang values, with the `arrow`
+//! feature, for running scripts over data held by Arrow-based query
+//! engines.
+//!
+//! An array converts to a list of the values in its rows, or to a `Column`
+//! for `Engine::eval_columns`; a `RecordBatch` converts to a list per
+//! column, in schema order. Integer arrays of any width become lists of
+//! ints, float arrays lists of floats, and `Utf8` arrays lists of strings.
+//! x-lang has no null, so arrays with nulls do not convert.
+//!
+//! `BatchSource` instead lets scripts read the columns they need from the
+//! batch the embedder is processing, by name:
+//! `($arrow.float_column "price")`.
+
+use crate::ast::DataType;
+use crate::builtin::ValueType;
+use crate::columnar::Column;
+use crate::corelib::{as_str, list_type, list_value, str_value, string_type, type_mismatch};
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use arrow_array::cast::AsArray;
+use arrow_array::types::*;
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+};
+use arrow_schema::{DataType as ArrowType, SchemaRef};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+fn unsupported(ty: &ArrowType) -> RuntimeError {
+    RuntimeError::Custom(format!("unsupported arrow type {}", ty))
+}
+
+fn check_nulls(array: &dyn Array) -> Result<(), RuntimeError> {
+    match array
+        .nulls()
+        .and_then(|n| (0..n.len()).find(|&i| n.is_null(i)))
+    {
+        Some(i) => Err(RuntimeError::Custom(format!("null in row {}", i))),
+        None => Ok(()),
+    }
+}
+
+/// The values of the numeric or boolean `array` as a column. Integers
+/// wider than 64 bits and strings have none.
+pub fn column_of_array(array: &dyn Array) -> Result<Column, RuntimeError> {
+    check_nulls(array)?;
+    fn ints<T: ArrowPrimitiveType>(array: &dyn Array) -> Column
+    where
+        T::Native: Into<i64>,
+    {
+        Column::Int(
+            array
+                .as_primitive::<T>()
+                .values()
+                .iter()
+                .map(|&x| x.into())
+                .collect(),
+        )
+    }
+    Ok(match *array.data_type() {
+        ArrowType::Int8 => ints::<Int8Type>(array),
+        ArrowType::Int16 => ints::<Int16Type>(array),
+        ArrowType::Int32 => ints::<Int32Type>(array),
+        ArrowType::Int64 => ints::<Int64Type>(array),
+        ArrowType::UInt8 => ints::<UInt8Type>(array),
+        ArrowType::UInt16 => ints::<UInt16Type>(array),
+        ArrowType::UInt32 => ints::<UInt32Type>(array),
+        ArrowType::Float32 => Column::Float(
+            array
+                .as_primitive::<Float32Type>()
+                .values()
+                .iter()
+                .map(|&x| x as f64)
+                .collect(),
+        ),
+        ArrowType::Float64 => Column::Float(array.as_primitive::<Float64Type>().values().to_vec()),
+        ArrowType::Boolean => Column::Bool(array.as_boolean().values().iter().collect()),
+        ref ty => return Err(unsupported(ty)),
+    })
+}
+
+/// The type of the elements of the list an array of type `ty` converts
+/// to, if it converts.
+fn element_type_of(ty: &ArrowType) -> Option<DataType> {
+    Some(match *ty {
+        ArrowType::Int8
+        | ArrowType::Int16
+        | ArrowType::Int32
+        | ArrowType::Int64
+        | ArrowType::UInt8
+        | ArrowType::UInt16
+        | ArrowType::UInt32 => DataType::Value(ValueType::Int),
+        ArrowType::Float32 | ArrowType::Float64 => DataType::Value(ValueType::Float),
+        ArrowType::Boolean => DataType::Value(ValueType::Bool),
+        ArrowType::Utf8 | ArrowType::LargeUtf8 => string_type(),
+        _ => return None,
+    })
+}
+
+/// `column` as an `Int64`, `Float64` or `Boolean` array.
+pub fn array_of_column(column: &Column) -> ArrayRef {
+    match *column {
+        Column::Int(ref v) => Arc::new(Int64Array::from(v.clone())),
+        Column::Float(ref v) => Arc::new(Float64Array::from(v.clone())),
+        Column::Bool(ref v) => Arc::new(BooleanArray::from(v.clone())),
+    }
+}
+
+/// The values of `array` as a list.
+pub fn value_of_array(array: &dyn Array) -> Result<OwnedValue, RuntimeError> {
+    let items = match *array.data_type() {
+        ArrowType::Utf8 => {
+            check_nulls(array)?;
+            array
+                .as_string::<i32>()
+                .iter()
+                .map(|s| owned_str(s.unwrap()))
+                .collect()
+        }
+        ArrowType::LargeUtf8 => {
+            check_nulls(array)?;
+            array
+                .as_string::<i64>()
+                .iter()
+                .map(|s| owned_str(s.unwrap()))
+                .collect()
+        }
+        _ => {
+            let column = column_of_array(array)?;
+            (0..column.len()).map(|i| column.get(i)).collect()
+        }
+    };
+    Ok(OwnedValue::List(items))
+}
+
+fn owned_str(s: &str) -> OwnedValue {
+    match str_value(s) {
+        RuntimeValue::Custom(cv) => OwnedValue::Custom(cv),
+        _ => unreachable!(),
+    }
+}
+
+/// `value`, a list, as an array of type `ty`, one of `Int64`, `Float64`,
+/// `Boolean` and `Utf8`. `~` is the empty array of any of them.
+pub fn array_of_value(value: &OwnedValue, ty: &ArrowType) -> Result<ArrayRef, RuntimeError> {
+    let items = match *value {
+        OwnedValue::List(ref items) => &items[..],
+        OwnedValue::Empty => &[],
+        ref other => {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "expecting a list, found {}",
+                other
+            )))
+        }
+    };
+    let mismatch = |v: &OwnedValue| {
+        RuntimeError::TypeMismatch(format!("cannot store {} in a {} array", v, ty))
+    };
+    Ok(match *ty {
+        ArrowType::Int64 | ArrowType::Float64 | ArrowType::Boolean => {
+            let vt = match *ty {
+                ArrowType::Int64 => ValueType::Int,
+                ArrowType::Float64 => ValueType::Float,
+                _ => ValueType::Bool,
+            };
+            match Column::from_values(vt, items) {
+                Some(column) => array_of_column(&column),
+                None => return Err(mismatch(items.iter().find(|v| !is_of(v, ty)).unwrap())),
+            }
+        }
+        ArrowType::Utf8 => {
+            let mut strings = Vec::with_capacity(items.len());
+            for v in items {
+                match *v {
+                    OwnedValue::Custom(ref cv) => match as_str(&RuntimeValue::Custom(cv.clone())) {
+                        Some(s) => strings.push(s.to_string()),
+                        None => return Err(mismatch(v)),
+                    },
+                    _ => return Err(mismatch(v)),
+                }
+            }
+            Arc::new(StringArray::from(strings))
+        }
+        ref ty => return Err(unsupported(ty)),
+    })
+}
+
+fn is_of(v: &OwnedValue, ty: &ArrowType) -> bool {
+    matches!(
+        (v, ty),
+        (OwnedValue::Int(_), ArrowType::Int64)
+            | (OwnedValue::Float(_), ArrowType::Float64)
+            | (OwnedValue::Bool(_), ArrowType::Boolean)
+    )
+}
+
+/// The columns of `batch` as lists, in schema order.
+pub fn values_of_batch(batch: &RecordBatch) -> Result<Vec<OwnedValue>, RuntimeError> {
+    batch
+        .columns()
+        .iter()
+        .zip(batch.schema().fields())
+        .map(|(c, f)| {
+            value_of_array(c)
+                .map_err(|e| RuntimeError::Custom(format!("column `{}`: {}", f.name(), e)))
+        })
+        .collect()
+}
+
+/// A batch of `schema` with `values` as its columns, each a list converted
+/// by `array_of_value`.
+pub fn batch_of_values(
+    schema: SchemaRef,
+    values: &[OwnedValue],
+) -> Result<RecordBatch, RuntimeError> {
+    if values.len() != schema.fields().len() {
+        return Err(RuntimeError::Custom(format!(
+            "expecting {} columns, found {}",
+            schema.fields().len(),
+            values.len()
+        )));
+    }
+    let columns = schema
+        .fields()
+        .iter()
+        .zip(values)
+        .map(|(f, v)| array_of_value(v, f.data_type()))
+        .collect::<Result<Vec<_>, _>>()?;
+    RecordBatch::try_new(schema, columns).map_err(|e| RuntimeError::Custom(e.to_string()))
+}
+
+/// The record batch scripts read columns from, set by the embedder before
+/// each run.
+///
+/// `register` adds `$arrow.int_column`, `$arrow.float_column`,
+/// `$arrow.bool_column` and `$arrow.string_column`, which take a column
+/// name and return the column of the current batch as a list. Which type a
+/// column has is only known once a batch is set, so asking for a column of
+/// the wrong type, or one the batch lacks, fails at runtime.
+#[derive(Debug, Clone, Default)]
+pub struct BatchSource {
+    current: Rc<RefCell<Option<RecordBatch>>>,
+}
+
+impl BatchSource {
+    pub fn new() -> BatchSource {
+        BatchSource::default()
+    }
+
+    /// Makes `batch` the one columns are read from.
+    pub fn set(&self, batch: RecordBatch) {
+        *self.current.borrow_mut() = Some(batch);
+    }
+
+    pub fn clear(&self) {
+        *self.current.borrow_mut() = None;
+    }
+
+    pub fn register(&self, engine: &mut Engine) {
+        for (name, ty) in [
+            ("int_column", ValueType::Int),
+            ("float_column", ValueType::Float),
+            ("bool_column", ValueType::Bool),
+        ] {
+            engine.add_host_in(
+                "arrow",
+                name,
+                Box::new(ColumnOp {
+                    source: self.clone(),
+                    ty: Some(ty),
+                }),
+            );
+        }
+        engine.add_host_in(
+            "arrow",
+            "string_column",
+            Box::new(ColumnOp {
+                source: self.clone(),
+                ty: None,
+            }),
+        );
+    }
+}
+
+/// Reads a column of the current batch. `ty` is the element type, `None`
+/// for strings.
+#[derive(Debug)]
+struct ColumnOp {
+    source: BatchSource,
+    ty: Option<ValueType>,
+}
+
+impl ColumnOp {
+    fn element_type(&self) -> DataType {
+        match self.ty {
+            Some(ref ty) => DataType::Value(ty.clone()),
+            None => string_type(),
+        }
+    }
+}
+
+impl HostFunction for ColumnOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("name")]))
+    }
+
+    fn doc(&self) -> Option<&str> {
+        Some("The named column of the record batch being processed, as a list.")
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if *ty == string_type() || *ty == DataType::Dynamic => {
+                Ok(list_type(self.element_type()))
+            }
+            _ => Err(TypeError::Custom("column names must be strings".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let name = params.next().unwrap().eval(ectx)?;
+        let name = as_str(&name).ok_or_else(|| type_mismatch("string", &name))?;
+        let array = match *self.source.current.borrow() {
+            Some(ref batch) => batch
+                .column_by_name(name)
+                .cloned()
+                .ok_or_else(|| RuntimeError::Custom(format!("no column `{}`", name)))?,
+            None => return Err(RuntimeError::Custom("no record batch set".into())),
+        };
+        let expected = self.element_type();
+        match element_type_of(array.data_type()) {
+            Some(ref ty) if *ty == expected => {}
+            _ => {
+                return Err(RuntimeError::TypeMismatch(format!(
+                    "column `{}` is of arrow type {}, not a list of {}",
+                    name,
+                    array.data_type(),
+                    expected
+                )))
+            }
+        }
+        let items = match value_of_array(&array)? {
+            OwnedValue::List(items) => items,
+            _ => unreachable!(),
+        };
+        let values = items
+            .into_iter()
+            .map(|item| match item {
+                OwnedValue::Int(x) => RuntimeValue::Int(x),
+                OwnedValue::Float(x) => RuntimeValue::Float(x),
+                OwnedValue::Bool(x) => RuntimeValue::Bool(x),
+                OwnedValue::Custom(cv) => RuntimeValue::Custom(cv),
+                _ => unreachable!(),
+            })
+            .collect();
+        Ok(list_value(ectx, values))
+    }
+}
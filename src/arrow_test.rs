@@ -0,0 +1,108 @@
+use crate::arrow::*;
+use crate::columnar::Column;
+use crate::engine::Engine;
+use crate::error::{Error, RuntimeError};
+use crate::eval::OwnedValue;
+use arrow_array::{Array, ArrayRef, Float32Array, Int32Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use std::sync::Arc;
+
+fn batch() -> RecordBatch {
+    let schema = Schema::new(vec![
+        Field::new("qty", DataType::Int32, false),
+        Field::new("price", DataType::Float32, false),
+        Field::new("sku", DataType::Utf8, false),
+    ]);
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef,
+            Arc::new(Float32Array::from(vec![0.5, 1.5, 2.0])),
+            Arc::new(StringArray::from(vec!["a", "b", "c"])),
+        ],
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_conversions() {
+    let batch = batch();
+    let values = values_of_batch(&batch).unwrap();
+    assert_eq!(
+        values[0],
+        OwnedValue::List(vec![
+            OwnedValue::Int(1),
+            OwnedValue::Int(2),
+            OwnedValue::Int(3)
+        ])
+    );
+    assert_eq!(values[2].to_string(), r#"["a", "b", "c"]"#);
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("qty", DataType::Int64, false),
+        Field::new("price", DataType::Float64, false),
+        Field::new("sku", DataType::Utf8, false),
+    ]));
+    let round_trip = batch_of_values(schema.clone(), &values).unwrap();
+    let back = values_of_batch(&round_trip).unwrap();
+    assert_eq!(back[..2], values[..2]);
+    assert_eq!(back[2].to_string(), values[2].to_string());
+    assert!(batch_of_values(schema, &values[..2]).is_err());
+
+    assert_eq!(
+        column_of_array(batch.column(1).as_ref()).unwrap(),
+        Column::Float(vec![0.5, 1.5, 2.0])
+    );
+    assert!(column_of_array(batch.column(2).as_ref()).is_err());
+    let with_null = Int32Array::from(vec![Some(1), None]);
+    assert!(value_of_array(&with_null).is_err());
+    assert!(array_of_value(&values[0], &DataType::Boolean).is_err());
+    assert_eq!(
+        array_of_value(&OwnedValue::Empty, &DataType::Utf8)
+            .unwrap()
+            .len(),
+        0
+    );
+}
+
+#[test]
+fn test_eval_columns_over_arrays() {
+    let batch = batch();
+    let columns = vec![
+        column_of_array(batch.column(0).as_ref()).unwrap(),
+        column_of_array(batch.column(1).as_ref()).unwrap(),
+    ];
+    let out = Engine::new()
+        .eval_columns(r"(\q p ($mul q p))", &columns)
+        .unwrap();
+    let array = array_of_column(&out);
+    assert_eq!(array.data_type(), &DataType::Float64);
+    assert_eq!(out, Column::Float(vec![0.5, 3.0, 6.0]));
+}
+
+#[test]
+fn test_batch_source() {
+    let mut engine = Engine::new();
+    let source = BatchSource::new();
+    source.register(&mut engine);
+    let script = r#"($list_head ($arrow.string_column "sku"))"#;
+    match engine.eval_str(script) {
+        Err(Error::Runtime(RuntimeError::Custom(_))) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+
+    source.set(batch());
+    assert_eq!(engine.eval_str(script).unwrap(), r#""a""#);
+    assert_eq!(
+        engine
+            .eval_str(r#"($list_head ($arrow.int_column "qty"))"#)
+            .unwrap(),
+        "1"
+    );
+    match engine.eval_str(r#"($arrow.int_column "price")"#) {
+        Err(Error::Runtime(RuntimeError::TypeMismatch(_))) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert!(engine.eval_str(r#"($arrow.int_column "missing")"#).is_err());
+    assert!(engine.eval_str("($arrow.int_column 1)").is_err());
+}
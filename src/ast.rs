@@ -1,10 +1,24 @@
 use crate::builtin::ValueType;
 use crate::error::*;
 use std::any::Any;
-use std::collections::BTreeMap;
-use std::fmt::Debug;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Debug};
+use std::hash::{BuildHasherDefault, Hasher};
 use std::rc::Rc;
 
+mod diff;
+pub mod resolve;
+pub mod visit;
+
+pub use self::diff::{diff, Difference, DifferenceKind, Path, PathSegment};
+
+/// A byte range in the source text.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
     Empty,
@@ -15,26 +29,169 @@ pub enum DataType {
         param_set: BTreeMap<String, Expr>,
     },
     Divergent,
-    Custom(Rc<Box<CustomDataType>>),
+    /// A type only known at runtime, e.g. of data parsed by the host. It is
+    /// accepted wherever any type is expected; host functions check their
+    /// arguments' runtime types instead.
+    Dynamic,
+    Custom(Rc<Box<dyn CustomDataType>>),
 }
 
 pub trait CustomDataType: Debug {
-    fn cdt_eq(&self, other: &CustomDataType) -> bool;
-    fn as_any(&self) -> &Any;
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool;
+    fn as_any(&self) -> &dyn Any;
+
+    /// Writes the type's name as shown in type errors. Defaults to the
+    /// `Debug` output.
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Prints the type compactly, e.g. `int` or `list<float>`. The result type
+/// of a function depends on the arguments it is called with, so functions
+/// are printed with their parameter names: `fn(x, digits)`.
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataType::Empty => write!(f, "empty"),
+            DataType::Value(ValueType::Int) => write!(f, "int"),
+            DataType::Value(ValueType::Float) => write!(f, "float"),
+            DataType::Value(ValueType::Bool) => write!(f, "bool"),
+            DataType::FunctionDecl { ref params, .. } => {
+                write!(f, "fn(")?;
+                for (i, p) in params.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param_name(p))?;
+                }
+                write!(f, ")")
+            }
+            DataType::Divergent => write!(f, "never"),
+            DataType::Dynamic => write!(f, "dyn"),
+            DataType::Custom(ref inner) => inner.display(f),
+        }
+    }
+}
+
+/// Strips renaming suffixes and, for host function parameters, which are
+/// named `$host.param`, the host function name.
+pub(crate) fn param_name(p: &str) -> &str {
+    source_name(p.rsplit('.').next().unwrap())
 }
 
-impl PartialEq for CustomDataType {
-    fn eq(&self, other: &CustomDataType) -> bool {
+impl PartialEq for dyn CustomDataType {
+    fn eq(&self, other: &dyn CustomDataType) -> bool {
         self.cdt_eq(other)
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
 pub struct Expr {
-    #[serde(flatten)]
     pub body: Rc<ExprBody>,
 }
 
+/// Prints the expression as source text.
+///
+/// Bound names are printed without the `#N` suffix added by renaming, so
+/// parsing the output yields the same AST as long as the original source
+/// did not shadow any names. Negative and non-finite numbers have no literal
+/// syntax and are printed in a form the parser rejects.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.body {
+            ExprBody::Apply { .. }
+            | ExprBody::Abstract {
+                body: AbstractBody::Expr(_),
+                ..
+            } => fmt_element(self, f),
+            _ => {
+                write!(f, "(")?;
+                fmt_element(self, f)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Strips the `#N` suffix added by renaming.
+pub(crate) fn source_name(name: &str) -> &str {
+    name.split('#').next().unwrap()
+}
+
+/// Writes `s` as a string literal the parser reads back as `s`.
+pub(crate) fn fmt_string_literal(s: &str, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Writes `b` as a bytes literal, `0x"..."` with two hex digits per byte.
+pub(crate) fn fmt_bytes_literal(b: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "0x\"")?;
+    for x in b {
+        write!(f, "{:02x}", x)?;
+    }
+    write!(f, "\"")
+}
+
+pub(crate) fn fmt_element(e: &Expr, f: &mut fmt::Formatter) -> fmt::Result {
+    match *e.body {
+        ExprBody::Const(ConstExpr::Int(v)) => write!(f, "{}", v),
+        ExprBody::Const(ConstExpr::Float(v)) => {
+            let s = v.to_string();
+            if s.contains('.') || !v.is_finite() {
+                write!(f, "{}", s)
+            } else {
+                write!(f, "{}.0", s)
+            }
+        }
+        ExprBody::Const(ConstExpr::Bool(v)) => write!(f, "{}", v),
+        ExprBody::Const(ConstExpr::Empty) => write!(f, "~"),
+        ExprBody::Const(ConstExpr::Str(ref s)) => fmt_string_literal(s, f),
+        ExprBody::Const(ConstExpr::Bytes(ref b)) => fmt_bytes_literal(b, f),
+        ExprBody::Name(ref n) => write!(f, "{}", source_name(n)),
+        ExprBody::Apply {
+            ref target,
+            ref params,
+        } => {
+            write!(f, "(")?;
+            fmt_element(target, f)?;
+            for p in params {
+                write!(f, " ")?;
+                fmt_element(p, f)?;
+            }
+            write!(f, ")")
+        }
+        ExprBody::Abstract {
+            body: AbstractBody::Host(ref name),
+            ..
+        } => write!(f, "${}", name),
+        ExprBody::Abstract {
+            ref params,
+            body: AbstractBody::Expr(ref body),
+        } => {
+            write!(f, "(\\")?;
+            for p in params {
+                write!(f, "{} ", source_name(p))?;
+            }
+            write!(f, "{})", body)
+        }
+        ExprBody::Match { .. } => write!(f, "<match>"),
+        ExprBody::Never => write!(f, "<never>"),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ExprBody {
     Const(ConstExpr),
@@ -66,70 +223,535 @@ pub enum ConstExpr {
     Float(f64),
     Bool(bool),
     Empty,
+    Str(String),
+    Bytes(Vec<u8>),
 }
 
 #[derive(Default)]
 pub struct RenameContext {
+    /// The number of the binding each name in scope refers to.
     rename_state: BTreeMap<String, usize>,
+    /// The number of bindings of each name so far, so that every binding
+    /// gets a name of its own.
+    bindings: BTreeMap<String, usize>,
+    /// For each scope entered, what its names referred to before.
+    shadowed: Vec<Vec<(String, Option<usize>)>>,
+    globals: BTreeSet<String>,
+    /// Leave names as they are, for parsing source that is renamed later.
+    raw: bool,
 }
 
 impl RenameContext {
-    pub fn with_renamed<T, F: FnOnce(&mut Self) -> T>(&mut self, renames: &[String], f: F) -> T {
+    /// Creates a context in which `globals` may be referenced without being
+    /// bound. Global references are left unrenamed.
+    pub fn with_globals(globals: BTreeSet<String>) -> RenameContext {
+        RenameContext {
+            rename_state: BTreeMap::new(),
+            bindings: BTreeMap::new(),
+            shadowed: Vec::new(),
+            globals,
+            raw: false,
+        }
+    }
+
+    /// Creates a context that renames nothing and accepts unbound names.
+    /// `globals` are the names in scope besides those bound in the source,
+    /// for `in_scope`.
+    pub(crate) fn raw(globals: BTreeSet<String>) -> RenameContext {
+        RenameContext {
+            raw: true,
+            globals,
+            ..RenameContext::default()
+        }
+    }
+
+    /// Whether `name` refers to a binding or global in this context.
+    pub(crate) fn in_scope(&self, name: &str) -> bool {
+        self.rename_state.contains_key(name) || self.globals.contains(name)
+    }
+
+    /// Binds `renames` to fresh names while running `f`, after which the
+    /// names refer to what they did before.
+    pub fn with_renamed<T, S: AsRef<str>, F: FnOnce(&mut Self) -> T>(
+        &mut self,
+        renames: &[S],
+        f: F,
+    ) -> T {
+        self.enter(renames);
+        let ret = f(self);
+        self.leave();
+        ret
+    }
+
+    fn enter<S: AsRef<str>>(&mut self, renames: &[S]) {
+        let mut shadowed = Vec::with_capacity(renames.len());
         for v in renames {
-            if let Some(c) = self.rename_state.get_mut(v) {
-                *c += 1;
-            } else {
-                self.rename_state.insert(v.clone(), 1);
-            }
+            let v = v.as_ref();
+            let n = self.bindings.entry(v.to_string()).or_insert(0);
+            *n += 1;
+            shadowed.push((v.to_string(), self.rename_state.insert(v.to_string(), *n)));
         }
+        self.shadowed.push(shadowed);
+    }
 
-        f(self)
+    fn leave(&mut self) {
+        let shadowed = self
+            .shadowed
+            .pop()
+            .expect("bug: leaving a scope never entered");
+        // In reverse, so that a name bound twice in one scope gets back the
+        // binding from before the scope.
+        for (v, n) in shadowed.into_iter().rev() {
+            match n {
+                Some(n) => self.rename_state.insert(v, n),
+                None => self.rename_state.remove(&v),
+            };
+        }
     }
 
-    pub fn get_renamed(&self, k: &String) -> Result<String, ParseError> {
+    pub fn get_renamed(&self, k: &str) -> Result<String, ParseError> {
+        if self.raw {
+            return Ok(k.to_string());
+        }
         match self.rename_state.get(k) {
             Some(v) => Ok(format!("{}#{}", k, v)),
+            None if self.globals.contains(k) => Ok(k.to_string()),
             None => Err(ParseError::Custom(format!("name not found: {}", k))),
         }
     }
 }
 
+impl visit::Folder for RenameContext {
+    type Error = ParseError;
+
+    fn fold_name(&mut self, _e: &Expr, name: &str) -> Result<Expr, ParseError> {
+        Ok(Expr {
+            body: Rc::new(ExprBody::Name(self.get_renamed(name)?)),
+        })
+    }
+
+    fn fold_never(&mut self, _e: &Expr) -> Result<Expr, ParseError> {
+        Err(ParseError::Custom("never type not expected in ast".into()))
+    }
+
+    fn pre_abstract(&mut self, _e: &Expr, params: &[String]) -> Result<Vec<String>, ParseError> {
+        self.enter(params);
+        params.iter().map(|v| self.get_renamed(v)).collect()
+    }
+
+    fn post_abstract(
+        &mut self,
+        _e: &Expr,
+        params: Vec<String>,
+        body: AbstractBody,
+    ) -> Result<Expr, ParseError> {
+        self.leave();
+        Ok(Expr {
+            body: Rc::new(ExprBody::Abstract { params, body }),
+        })
+    }
+
+    fn pre_match(&mut self, _e: &Expr) -> Result<(), ParseError> {
+        Err(ParseError::Custom("match is not supported".into()))
+    }
+}
+
 pub fn rename_expr(e: &Expr, ctx: &mut RenameContext) -> Result<Expr, ParseError> {
-    Ok(Expr {
-        body: match *e.body {
-            ExprBody::Const(_) => e.body.clone(),
-            ExprBody::Name(ref n) => Rc::new(ExprBody::Name(ctx.get_renamed(n)?)),
-            ExprBody::Apply {
-                ref target,
-                ref params,
-            } => Rc::new(ExprBody::Apply {
-                target: rename_expr(target, ctx)?,
-                params: {
-                    let result: Result<Vec<_>, _> =
-                        params.iter().map(|v| rename_expr(v, ctx)).collect();
-                    result?
-                },
-            }),
-            ExprBody::Abstract {
-                ref params,
-                ref body,
-            } => ctx.with_renamed(params, |ctx| {
-                Ok(Rc::new(ExprBody::Abstract {
-                    params: {
-                        let result: Result<Vec<_>, _> =
-                            params.iter().map(|v| ctx.get_renamed(v)).collect();
-                        result?
-                    },
-                    body: match *body {
-                        AbstractBody::Host(ref v) => AbstractBody::Host(v.clone()),
-                        AbstractBody::Expr(ref e) => AbstractBody::Expr(rename_expr(e, ctx)?),
-                    },
-                }))
-            })?,
-            ExprBody::Match { .. } => unimplemented!(),
-            ExprBody::Never => {
-                return Err(ParseError::Custom("never type not expected in ast".into()));
+    visit::fold(ctx, e)
+}
+
+/// Collects the names referenced but not bound within an expression.
+#[derive(Default)]
+struct FreeVars {
+    /// Number of enclosing functions binding each name.
+    bound: BTreeMap<String, usize>,
+    free: BTreeSet<String>,
+}
+
+impl visit::Visitor for FreeVars {
+    type Error = ();
+
+    fn visit_name(&mut self, _e: &Expr, name: &str) -> Result<(), ()> {
+        if !self.bound.contains_key(name) {
+            self.free.insert(name.to_string());
+        }
+        Ok(())
+    }
+
+    fn pre_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract { ref params, .. } = *e.body {
+            for p in params {
+                *self.bound.entry(p.clone()).or_insert(0) += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn post_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract { ref params, .. } = *e.body {
+            for p in params {
+                let n = self.bound.get_mut(p).unwrap();
+                *n -= 1;
+                if *n == 0 {
+                    self.bound.remove(p);
+                }
             }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the names `e` refers to without binding them: the variables a
+/// closure over `e` has to capture, and any definitions it uses.
+pub fn free_vars(e: &Expr) -> BTreeSet<String> {
+    let mut fv = FreeVars::default();
+    visit::walk(&mut fv, e).unwrap();
+    fv.free
+}
+
+/// What a program needs from its environment, known from its AST alone.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    /// Host functions it calls or refers to, without the `$`.
+    pub hosts: BTreeSet<String>,
+    /// Names it refers to without binding them: the definitions and inputs
+    /// it expects.
+    pub free_names: BTreeSet<String>,
+}
+
+impl Manifest {
+    /// The host functions the program uses that are not in `approved`.
+    pub fn unapproved_hosts(&self, approved: &BTreeSet<String>) -> BTreeSet<String> {
+        self.hosts.difference(approved).cloned().collect()
+    }
+}
+
+/// Returns the host functions and unbound names `e` uses, so that tooling
+/// can vet a script's capabilities before typechecking it.
+pub fn manifest(e: &Expr) -> Manifest {
+    let mut hosts = BTreeSet::new();
+    collect_hosts(e, &mut hosts);
+    Manifest {
+        hosts,
+        free_names: free_vars(e),
+    }
+}
+
+/// Adds the host functions `e` refers to to `out`.
+pub(crate) fn collect_hosts(e: &Expr, out: &mut BTreeSet<String>) {
+    match *e.body {
+        ExprBody::Apply {
+            ref target,
+            ref params,
+        } => {
+            collect_hosts(target, out);
+            params.iter().for_each(|p| collect_hosts(p, out));
+        }
+        ExprBody::Abstract { ref body, .. } => match *body {
+            AbstractBody::Host(ref name) => {
+                out.insert(name.clone());
+            }
+            AbstractBody::Expr(ref e) => collect_hosts(e, out),
+        },
+        ExprBody::Match {
+            ref value,
+            ref branches,
+        } => {
+            collect_hosts(value, out);
+            branches.iter().for_each(|(_, e)| collect_hosts(e, out));
+        }
+        ExprBody::Const(_) | ExprBody::Name(_) | ExprBody::Never => {}
+    }
+}
+
+/// Renames bound names to a form that depends only on their source names
+/// and the structure of the expression. Each parameter becomes its source
+/// name followed by the number of binders enclosing it, counting its own:
+/// `(\x (\y (x y)))` binds `x#1` and `y#2` however it was renamed before.
+/// Free names are kept.
+#[derive(Default)]
+struct Canonicalizer {
+    /// Original and canonical names of the parameters in scope.
+    scope: Vec<(String, String)>,
+}
+
+impl visit::Folder for Canonicalizer {
+    type Error = ();
+
+    fn fold_name(&mut self, e: &Expr, name: &str) -> Result<Expr, ()> {
+        Ok(match self.scope.iter().rev().find(|(old, _)| old == name) {
+            Some((_, new)) => Expr {
+                body: Rc::new(ExprBody::Name(new.clone())),
+            },
+            None => e.clone(),
+        })
+    }
+
+    fn pre_abstract(&mut self, _e: &Expr, params: &[String]) -> Result<Vec<String>, ()> {
+        Ok(params
+            .iter()
+            .map(|p| {
+                let new = format!("{}#{}", source_name(p), self.scope.len() + 1);
+                self.scope.push((p.clone(), new.clone()));
+                new
+            })
+            .collect())
+    }
+
+    fn post_abstract(
+        &mut self,
+        _e: &Expr,
+        params: Vec<String>,
+        body: AbstractBody,
+    ) -> Result<Expr, ()> {
+        let len = self.scope.len() - params.len();
+        self.scope.truncate(len);
+        Ok(Expr {
+            body: Rc::new(ExprBody::Abstract { params, body }),
+        })
+    }
+}
+
+/// Returns `e` with its bound names renamed canonically, so that
+/// expressions differing only in the `#N` suffixes of their bound names
+/// become equal.
+pub fn canonicalize(e: &Expr) -> Expr {
+    visit::fold(&mut Canonicalizer::default(), e).unwrap()
+}
+
+/// Replaces free names with expressions, renaming binders of `e` that
+/// would otherwise capture free names of the replacements.
+struct Substitution<'a, S> {
+    subst: &'a [(S, Expr)],
+    /// Free names of the replacements.
+    captures: BTreeSet<String>,
+    /// Every name in `e` and the replacements, which fresh names avoid.
+    used: BTreeSet<String>,
+    fresh: RenameContext,
+    /// Original and new names of the parameters in scope.
+    scope: Vec<(String, String)>,
+}
+
+impl<'a, S: AsRef<str>> Substitution<'a, S> {
+    fn fresh_name(&mut self, name: &str) -> String {
+        let base = source_name(name);
+        loop {
+            let new = self
+                .fresh
+                .with_renamed(&[base], |ctx| ctx.get_renamed(base))
+                .expect("bug: name just bound");
+            if self.used.insert(new.clone()) {
+                return new;
+            }
+        }
+    }
+}
+
+impl<'a, S: AsRef<str>> visit::Folder for Substitution<'a, S> {
+    type Error = ();
+
+    fn fold_name(&mut self, e: &Expr, name: &str) -> Result<Expr, ()> {
+        if let Some((_, new)) = self.scope.iter().rev().find(|(old, _)| old == name) {
+            return Ok(if new == name {
+                e.clone()
+            } else {
+                Expr {
+                    body: Rc::new(ExprBody::Name(new.clone())),
+                }
+            });
+        }
+        Ok(match self.subst.iter().find(|(k, _)| k.as_ref() == name) {
+            Some((_, v)) => v.clone(),
+            None => e.clone(),
+        })
+    }
+
+    fn pre_abstract(&mut self, _e: &Expr, params: &[String]) -> Result<Vec<String>, ()> {
+        Ok(params
+            .iter()
+            .map(|p| {
+                let new = if self.captures.contains(p) {
+                    self.fresh_name(p)
+                } else {
+                    p.clone()
+                };
+                self.scope.push((p.clone(), new.clone()));
+                new
+            })
+            .collect())
+    }
+
+    fn post_abstract(
+        &mut self,
+        _e: &Expr,
+        params: Vec<String>,
+        body: AbstractBody,
+    ) -> Result<Expr, ()> {
+        let len = self.scope.len() - params.len();
+        self.scope.truncate(len);
+        Ok(Expr {
+            body: Rc::new(ExprBody::Abstract { params, body }),
+        })
+    }
+}
+
+/// Collects every name an expression binds or references.
+#[derive(Default)]
+struct AllNames(BTreeSet<String>);
+
+impl visit::Visitor for AllNames {
+    type Error = ();
+
+    fn visit_name(&mut self, _e: &Expr, name: &str) -> Result<(), ()> {
+        self.0.insert(name.to_string());
+        Ok(())
+    }
+
+    fn pre_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract { ref params, .. } = *e.body {
+            self.0.extend(params.iter().cloned());
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the free occurrences of each name in `subst` with its
+/// expression, for filling in the holes of a template program.
+///
+/// Substitution is capture-avoiding: a parameter of `e` with the same name
+/// as a free name of a replacement is renamed to a fresh `#N` name, so the
+/// replacement still refers to what it did outside `e`. Occurrences bound
+/// within `e` are not replaced.
+pub fn substitute<S: AsRef<str>>(e: &Expr, subst: &[(S, Expr)]) -> Expr {
+    let mut all = AllNames::default();
+    visit::walk(&mut all, e).unwrap();
+    let mut captures = BTreeSet::new();
+    for (_, v) in subst {
+        visit::walk(&mut all, v).unwrap();
+        captures.extend(free_vars(v));
+    }
+    visit::fold(
+        &mut Substitution {
+            subst,
+            captures,
+            used: all.0,
+            fresh: RenameContext::default(),
+            scope: Vec::new(),
         },
-    })
+        e,
+    )
+    .unwrap()
+}
+
+impl Expr {
+    /// A hash of the canonical form of the expression, for deduplicating
+    /// and auditing stored programs.
+    ///
+    /// Computed with FNV-1a over the bincode encoding, so it is the same
+    /// across processes and platforms.
+    pub fn content_hash(&self) -> u64 {
+        let bytes = bincode::serialize(&canonicalize(self)).expect("bug: AST serialization failed");
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+            (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+}
+
+/// Collects the parameters of the lambdas within an expression.
+#[derive(Default)]
+struct BoundVars(BTreeSet<String>);
+
+impl visit::Visitor for BoundVars {
+    type Error = ();
+
+    fn pre_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract { ref params, .. } = *e.body {
+            self.0.extend(params.iter().cloned());
+        }
+        Ok(())
+    }
+}
+
+/// Returns the names bound by lambdas within `e`, including `e` itself.
+pub fn bound_vars(e: &Expr) -> BTreeSet<String> {
+    let mut bv = BoundVars::default();
+    visit::walk(&mut bv, e).unwrap();
+    bv.0
+}
+
+/// Memoized `free_vars` of the expressions evaluation captures an
+/// environment for: functions, and arguments evaluated lazily.
+#[derive(Default, Debug)]
+pub struct CaptureAnalysis {
+    /// Keyed by the address of the expression body. The body is kept alive
+    /// so the address is not reused for another expression.
+    cache: AddressMap<(Rc<ExprBody>, Rc<BTreeSet<String>>)>,
+}
+
+type AddressMap<V> = HashMap<*const ExprBody, V, BuildHasherDefault<AddressHasher>>;
+
+/// Source locations of the nodes of a parsed expression, as returned by
+/// `parser::parse_expr_with_spans`.
+///
+/// Keyed by node address, so it only describes the expression it was parsed
+/// with, which it keeps alive. A node shared by several parents, like the
+/// body of `(x)` and `x` itself, has the span of its innermost occurrence.
+#[derive(Default, Debug)]
+pub struct SpanMap {
+    spans: AddressMap<Span>,
+    root: Option<Expr>,
+}
+
+impl SpanMap {
+    pub fn get(&self, e: &Expr) -> Option<Span> {
+        self.get_at(&*e.body)
+    }
+
+    /// The span of the node whose body is at `body`.
+    pub(crate) fn get_at(&self, body: *const ExprBody) -> Option<Span> {
+        self.spans.get(&body).cloned()
+    }
+
+    pub(crate) fn insert(&mut self, e: &Expr, span: Span) {
+        self.spans.entry(&*e.body).or_insert(span);
+    }
+
+    pub(crate) fn set_root(&mut self, e: &Expr) {
+        self.root = Some(e.clone());
+    }
+}
+
+/// Hashes addresses without the overhead of the default hasher.
+#[derive(Default)]
+struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 << 8 | u64::from(b)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.0 = (n as u64 >> 3).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+impl CaptureAnalysis {
+    pub fn new() -> CaptureAnalysis {
+        CaptureAnalysis::default()
+    }
+
+    /// Returns the names a closure over `e` captures.
+    pub fn captures(&mut self, e: &Expr) -> Rc<BTreeSet<String>> {
+        self.cache
+            .entry(&*e.body as *const ExprBody)
+            .or_insert_with(|| (e.body.clone(), Rc::new(free_vars(e))))
+            .1
+            .clone()
+    }
 }
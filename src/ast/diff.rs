@@ -0,0 +1,213 @@
+//! Structural comparison of expressions.
+//!
+//! `diff` walks two trees side by side and reports the smallest subtrees
+//! that differ, each with the path leading to it from the root. Names are
+//! compared exactly, including their `#N` suffixes, so two expressions have
+//! no differences exactly when they are equal.
+
+use super::*;
+
+/// A step from an expression to one of its children.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PathSegment {
+    /// The function of an application.
+    Target,
+    /// An argument of an application.
+    Param(usize),
+    /// The body of a lambda.
+    Body,
+    /// The scrutinee of a match.
+    Value,
+    Branch(usize),
+}
+
+/// The location of a subtree, as the steps leading to it from the root.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Path(pub Vec<PathSegment>);
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "<root>");
+        }
+        for (i, seg) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ".")?;
+            }
+            match *seg {
+                PathSegment::Target => write!(f, "target")?,
+                PathSegment::Param(i) => write!(f, "params[{}]", i)?,
+                PathSegment::Body => write!(f, "body")?,
+                PathSegment::Value => write!(f, "value")?,
+                PathSegment::Branch(i) => write!(f, "branches[{}]", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DifferenceKind {
+    /// The subtrees differ at their root.
+    Replaced { left: Expr, right: Expr },
+    /// An argument only the right side passes.
+    Added(Expr),
+    /// An argument only the left side passes.
+    Removed(Expr),
+    /// The lambdas take different parameters. Their bodies are compared
+    /// separately.
+    Params {
+        left: Vec<String>,
+        right: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub path: Path,
+    pub kind: DifferenceKind,
+}
+
+/// Prints an expression without the parentheses `Expr` adds around leaves.
+struct Element<'a>(&'a Expr);
+
+impl<'a> fmt::Display for Element<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_element(self.0, f)
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.path)?;
+        match self.kind {
+            DifferenceKind::Replaced {
+                ref left,
+                ref right,
+            } => write!(f, "{} -> {}", Element(left), Element(right)),
+            DifferenceKind::Added(ref e) => write!(f, "added {}", Element(e)),
+            DifferenceKind::Removed(ref e) => write!(f, "removed {}", Element(e)),
+            DifferenceKind::Params {
+                ref left,
+                ref right,
+            } => write!(
+                f,
+                "parameters ({}) -> ({})",
+                left.iter()
+                    .map(|p| source_name(p))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                right
+                    .iter()
+                    .map(|p| source_name(p))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }
+    }
+}
+
+/// Lists where `right` differs from `left`, in source order. Empty if and
+/// only if the two are equal.
+pub fn diff(left: &Expr, right: &Expr) -> Vec<Difference> {
+    let mut out = Vec::new();
+    diff_at(left, right, &mut Vec::new(), &mut out);
+    out
+}
+
+fn diff_at(left: &Expr, right: &Expr, path: &mut Vec<PathSegment>, out: &mut Vec<Difference>) {
+    if Rc::ptr_eq(&left.body, &right.body) {
+        return;
+    }
+    match (&*left.body, &*right.body) {
+        (
+            ExprBody::Apply {
+                target: ref lt,
+                params: ref lp,
+            },
+            ExprBody::Apply {
+                target: ref rt,
+                params: ref rp,
+            },
+        ) => {
+            in_child(path, PathSegment::Target, |path| diff_at(lt, rt, path, out));
+            for (i, (l, r)) in lp.iter().zip(rp.iter()).enumerate() {
+                in_child(path, PathSegment::Param(i), |path| diff_at(l, r, path, out));
+            }
+            for (i, l) in lp.iter().enumerate().skip(rp.len()) {
+                in_child(path, PathSegment::Param(i), |path| {
+                    report(path, DifferenceKind::Removed(l.clone()), out)
+                });
+            }
+            for (i, r) in rp.iter().enumerate().skip(lp.len()) {
+                in_child(path, PathSegment::Param(i), |path| {
+                    report(path, DifferenceKind::Added(r.clone()), out)
+                });
+            }
+        }
+        (
+            ExprBody::Abstract {
+                params: ref lp,
+                body: AbstractBody::Expr(ref lb),
+            },
+            ExprBody::Abstract {
+                params: ref rp,
+                body: AbstractBody::Expr(ref rb),
+            },
+        ) => {
+            if lp != rp {
+                report(
+                    path,
+                    DifferenceKind::Params {
+                        left: lp.clone(),
+                        right: rp.clone(),
+                    },
+                    out,
+                );
+            }
+            in_child(path, PathSegment::Body, |path| diff_at(lb, rb, path, out));
+        }
+        (
+            ExprBody::Match {
+                value: ref lv,
+                branches: ref lbr,
+            },
+            ExprBody::Match {
+                value: ref rv,
+                branches: ref rbr,
+            },
+        ) if lbr.len() == rbr.len() && lbr.iter().zip(rbr.iter()).all(|(l, r)| l.0 == r.0) => {
+            in_child(path, PathSegment::Value, |path| diff_at(lv, rv, path, out));
+            for (i, (l, r)) in lbr.iter().zip(rbr.iter()).enumerate() {
+                in_child(path, PathSegment::Branch(i), |path| {
+                    diff_at(&l.1, &r.1, path, out)
+                });
+            }
+        }
+        (l, r) => {
+            if l != r {
+                report(
+                    path,
+                    DifferenceKind::Replaced {
+                        left: left.clone(),
+                        right: right.clone(),
+                    },
+                    out,
+                );
+            }
+        }
+    }
+}
+
+fn report(path: &[PathSegment], kind: DifferenceKind, out: &mut Vec<Difference>) {
+    out.push(Difference {
+        path: Path(path.to_vec()),
+        kind,
+    });
+}
+
+fn in_child<F: FnOnce(&mut Vec<PathSegment>)>(path: &mut Vec<PathSegment>, seg: PathSegment, f: F) {
+    path.push(seg);
+    f(path);
+    path.pop();
+}
@@ -0,0 +1,131 @@
+//! Resolution of local names to slots, so that evaluation finds a variable
+//! by indexing a vector instead of looking it up by name.
+//!
+//! Functions are closure-converted: the environment of a function body is
+//! its parameters and the variables it captures from enclosing functions,
+//! each kept in a vector. A name bound by the function itself resolves to
+//! `Slot::Param`, one bound further out to `Slot::Capture`, and each
+//! function records where in the enclosing environment its captured
+//! variables come from. Names not bound by any enclosing function refer to
+//! definitions and stay unresolved.
+//!
+//! Resolution results are keyed by the address of each `Name` and
+//! `Abstract` body, so the AST keeps its string form for diagnostics.
+
+use super::*;
+
+/// Where a local variable is found in the environment of a function body.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Slot {
+    Param(usize),
+    Capture(usize),
+}
+
+/// The captured variables of a function.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FunctionLayout {
+    /// Names of captured variables, in slot order.
+    pub captures: Vec<String>,
+    /// Where each captured variable is found in the environment the
+    /// function is created in.
+    pub sources: Vec<Slot>,
+}
+
+#[derive(Default, Debug)]
+pub struct Resolution {
+    names: AddressMap<Slot>,
+    functions: AddressMap<Rc<FunctionLayout>>,
+    /// Keeps resolved expressions alive so their addresses are not reused.
+    roots: Vec<Expr>,
+}
+
+struct Scope<'a> {
+    params: &'a [String],
+    layout: FunctionLayout,
+}
+
+impl Resolution {
+    pub fn new() -> Resolution {
+        Resolution::default()
+    }
+
+    /// Resolves the names in `e`, taking `e` to be outside of any function.
+    pub fn resolve(&mut self, e: &Expr) {
+        self.roots.push(e.clone());
+        self.resolve_in(e, &mut Vec::new());
+    }
+
+    /// Returns the slot a `Name` expression refers to, if it is local.
+    pub fn slot(&self, e: &Expr) -> Option<Slot> {
+        self.names.get(&(&*e.body as *const ExprBody)).cloned()
+    }
+
+    /// Returns the layout of an `Abstract` expression with a body, if it
+    /// has been resolved.
+    pub fn function(&self, e: &Expr) -> Option<&Rc<FunctionLayout>> {
+        self.functions.get(&(&*e.body as *const ExprBody))
+    }
+
+    fn resolve_in<'a>(&mut self, e: &'a Expr, scopes: &mut Vec<Scope<'a>>) {
+        match *e.body {
+            ExprBody::Name(ref name) => {
+                if let Some(slot) = lookup(scopes, name) {
+                    self.names.insert(&*e.body, slot);
+                }
+            }
+            ExprBody::Apply {
+                ref target,
+                ref params,
+            } => {
+                self.resolve_in(target, scopes);
+                for p in params {
+                    self.resolve_in(p, scopes);
+                }
+            }
+            ExprBody::Abstract {
+                ref params,
+                body: AbstractBody::Expr(ref body),
+            } => {
+                scopes.push(Scope {
+                    params,
+                    layout: FunctionLayout::default(),
+                });
+                self.resolve_in(body, scopes);
+                let layout = scopes.pop().unwrap().layout;
+                self.functions.insert(&*e.body, Rc::new(layout));
+            }
+            ExprBody::Match {
+                ref value,
+                ref branches,
+            } => {
+                self.resolve_in(value, scopes);
+                for (_, branch) in branches {
+                    self.resolve_in(branch, scopes);
+                }
+            }
+            ExprBody::Const(_)
+            | ExprBody::Abstract {
+                body: AbstractBody::Host(_),
+                ..
+            }
+            | ExprBody::Never => {}
+        }
+    }
+}
+
+/// Finds `name` in the innermost of `scopes`, capturing it from the
+/// enclosing ones as needed.
+fn lookup(scopes: &mut [Scope], name: &str) -> Option<Slot> {
+    let (scope, outer) = scopes.split_last_mut()?;
+    // Later parameters shadow earlier ones of the same name.
+    if let Some(i) = scope.params.iter().rposition(|p| p == name) {
+        return Some(Slot::Param(i));
+    }
+    if let Some(i) = scope.layout.captures.iter().position(|c| c == name) {
+        return Some(Slot::Capture(i));
+    }
+    let source = lookup(outer, name)?;
+    scope.layout.captures.push(name.to_string());
+    scope.layout.sources.push(source);
+    Some(Slot::Capture(scope.layout.captures.len() - 1))
+}
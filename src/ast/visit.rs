@@ -0,0 +1,198 @@
+//! Traversal of expressions, for passes that inspect or rewrite an AST
+//! without spelling out the recursion over every `ExprBody` variant.
+//!
+//! A `Visitor` is called before and after the children of each compound
+//! expression and once for each leaf. A `Folder` is called the same way
+//! but builds a new expression bottom-up: its `post_*` hooks receive the
+//! already folded children. Children are visited in source order, and the
+//! first error stops the traversal.
+
+use super::*;
+
+pub trait Visitor {
+    type Error;
+
+    fn visit_const(&mut self, _e: &Expr, _c: &ConstExpr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn visit_name(&mut self, _e: &Expr, _name: &str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn visit_never(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn pre_apply(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn post_apply(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called for host functions as well, which have no body to visit.
+    fn pre_abstract(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn post_abstract(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn pre_match(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn post_match(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Walks `e` and its subexpressions with `v`.
+pub fn walk<V: Visitor + ?Sized>(v: &mut V, e: &Expr) -> Result<(), V::Error> {
+    match *e.body {
+        ExprBody::Const(ref c) => v.visit_const(e, c),
+        ExprBody::Name(ref name) => v.visit_name(e, name),
+        ExprBody::Never => v.visit_never(e),
+        ExprBody::Apply {
+            ref target,
+            ref params,
+        } => {
+            v.pre_apply(e)?;
+            walk(v, target)?;
+            for p in params {
+                walk(v, p)?;
+            }
+            v.post_apply(e)
+        }
+        ExprBody::Abstract { ref body, .. } => {
+            v.pre_abstract(e)?;
+            if let AbstractBody::Expr(ref body) = *body {
+                walk(v, body)?;
+            }
+            v.post_abstract(e)
+        }
+        ExprBody::Match {
+            ref value,
+            ref branches,
+        } => {
+            v.pre_match(e)?;
+            walk(v, value)?;
+            for (_, branch) in branches {
+                walk(v, branch)?;
+            }
+            v.post_match(e)
+        }
+    }
+}
+
+/// Rewrites expressions. Every hook defaults to leaving its node as it is,
+/// so a pass only overrides the variants it changes.
+pub trait Folder {
+    type Error;
+
+    fn fold_const(&mut self, e: &Expr, _c: &ConstExpr) -> Result<Expr, Self::Error> {
+        Ok(e.clone())
+    }
+
+    fn fold_name(&mut self, e: &Expr, _name: &str) -> Result<Expr, Self::Error> {
+        Ok(e.clone())
+    }
+
+    fn fold_never(&mut self, e: &Expr) -> Result<Expr, Self::Error> {
+        Ok(e.clone())
+    }
+
+    fn pre_apply(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn post_apply(
+        &mut self,
+        _e: &Expr,
+        target: Expr,
+        params: Vec<Expr>,
+    ) -> Result<Expr, Self::Error> {
+        Ok(Expr {
+            body: Rc::new(ExprBody::Apply { target, params }),
+        })
+    }
+
+    /// Returns the parameter list of the rewritten function. Called before
+    /// its body is folded.
+    fn pre_abstract(&mut self, _e: &Expr, params: &[String]) -> Result<Vec<String>, Self::Error> {
+        Ok(params.to_vec())
+    }
+
+    fn post_abstract(
+        &mut self,
+        _e: &Expr,
+        params: Vec<String>,
+        body: AbstractBody,
+    ) -> Result<Expr, Self::Error> {
+        Ok(Expr {
+            body: Rc::new(ExprBody::Abstract { params, body }),
+        })
+    }
+
+    fn pre_match(&mut self, _e: &Expr) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn post_match(
+        &mut self,
+        _e: &Expr,
+        value: Expr,
+        branches: Vec<(String, Expr)>,
+    ) -> Result<Expr, Self::Error> {
+        Ok(Expr {
+            body: Rc::new(ExprBody::Match { value, branches }),
+        })
+    }
+}
+
+/// Rewrites `e` bottom-up with `f`.
+pub fn fold<F: Folder + ?Sized>(f: &mut F, e: &Expr) -> Result<Expr, F::Error> {
+    match *e.body {
+        ExprBody::Const(ref c) => f.fold_const(e, c),
+        ExprBody::Name(ref name) => f.fold_name(e, name),
+        ExprBody::Never => f.fold_never(e),
+        ExprBody::Apply {
+            ref target,
+            ref params,
+        } => {
+            f.pre_apply(e)?;
+            let target = fold(f, target)?;
+            let params = params
+                .iter()
+                .map(|p| fold(f, p))
+                .collect::<Result<Vec<_>, _>>()?;
+            f.post_apply(e, target, params)
+        }
+        ExprBody::Abstract {
+            ref params,
+            ref body,
+        } => {
+            let params = f.pre_abstract(e, params)?;
+            let body = match *body {
+                AbstractBody::Host(ref name) => AbstractBody::Host(name.clone()),
+                AbstractBody::Expr(ref body) => AbstractBody::Expr(fold(f, body)?),
+            };
+            f.post_abstract(e, params, body)
+        }
+        ExprBody::Match {
+            ref value,
+            ref branches,
+        } => {
+            f.pre_match(e)?;
+            let value = fold(f, value)?;
+            let branches = branches
+                .iter()
+                .map(|(name, branch)| Ok((name.clone(), fold(f, branch)?)))
+                .collect::<Result<Vec<_>, _>>()?;
+            f.post_match(e, value, branches)
+        }
+    }
+}
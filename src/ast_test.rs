@@ -0,0 +1,283 @@
+use crate::ast::*;
+use crate::parser::{parse_expr, parse_expr_with_globals};
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+fn names(names: &[&str]) -> BTreeSet<String> {
+    names.iter().map(|x| x.to_string()).collect()
+}
+
+#[test]
+fn test_free_vars() {
+    let e = parse_expr(r"(\x ($add x 1))").unwrap();
+    assert_eq!(free_vars(&e), names(&[]));
+
+    let e = match *e.body {
+        ExprBody::Abstract {
+            body: AbstractBody::Expr(ref body),
+            ..
+        } => body.clone(),
+        _ => unreachable!(),
+    };
+    assert_eq!(free_vars(&e), names(&["x#1"]));
+
+    let e = parse_expr_with_globals(
+        r"(\x ($lt ($mul x scale) limit))",
+        names(&["limit", "scale"]),
+    )
+    .unwrap();
+    assert_eq!(free_vars(&e), names(&["limit", "scale"]));
+}
+
+#[test]
+fn test_free_vars_shadowing() {
+    // Renaming gives shadowing binders distinct names already; check the
+    // analysis on an unrenamed tree as well.
+    let name = |n: &str| Expr {
+        body: Rc::new(ExprBody::Name(n.into())),
+    };
+    let lambda = |p: &str, body: Expr| Expr {
+        body: Rc::new(ExprBody::Abstract {
+            params: vec![p.into()],
+            body: AbstractBody::Expr(body),
+        }),
+    };
+    let apply = |target: Expr, param: Expr| Expr {
+        body: Rc::new(ExprBody::Apply {
+            target,
+            params: vec![param],
+        }),
+    };
+
+    // (\x ((\x (x)) x y))
+    let e = lambda(
+        "x",
+        apply(lambda("x", name("x")), apply(name("x"), name("y"))),
+    );
+    assert_eq!(free_vars(&e), names(&["y"]));
+    // ((\x (x)) x)
+    let e = apply(lambda("x", name("x")), name("x"));
+    assert_eq!(free_vars(&e), names(&["x"]));
+}
+
+#[test]
+fn test_capture_analysis() {
+    let e = parse_expr(r"(\x y ($add x y))").unwrap();
+    let body = match *e.body {
+        ExprBody::Abstract {
+            body: AbstractBody::Expr(ref body),
+            ..
+        } => body.clone(),
+        _ => unreachable!(),
+    };
+
+    let mut ca = CaptureAnalysis::new();
+    assert!(ca.captures(&e).is_empty());
+    let captured = ca.captures(&body);
+    assert_eq!(*captured, names(&["x#1", "y#1"]));
+    assert!(Rc::ptr_eq(&captured, &ca.captures(&body)));
+}
+
+#[test]
+fn test_resolution() {
+    use crate::ast::resolve::*;
+
+    let e = parse_expr_with_globals(
+        r"(\x y ((\z ($add ($add z x) limit)) y))",
+        names(&["limit"]),
+    )
+    .unwrap();
+    let mut res = Resolution::new();
+    res.resolve(&e);
+
+    let outer = res.function(&e).unwrap();
+    assert!(outer.captures.is_empty());
+
+    let (inner, arg) = match *e.body {
+        ExprBody::Abstract {
+            body: AbstractBody::Expr(ref body),
+            ..
+        } => match *body.body {
+            ExprBody::Apply {
+                ref target,
+                ref params,
+            } => (target.clone(), params[0].clone()),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    assert_eq!(res.slot(&arg), Some(Slot::Param(1)));
+
+    let layout = res.function(&inner).unwrap();
+    assert_eq!(layout.captures, vec!["x#1"]);
+    assert_eq!(layout.sources, vec![Slot::Param(0)]);
+
+    let mut slots = Vec::new();
+    struct Names<'a>(&'a Resolution, &'a mut Vec<(String, Option<Slot>)>);
+    impl<'a> visit::Visitor for Names<'a> {
+        type Error = ();
+        fn visit_name(&mut self, e: &Expr, name: &str) -> Result<(), ()> {
+            self.1.push((name.to_string(), self.0.slot(e)));
+            Ok(())
+        }
+    }
+    visit::walk(&mut Names(&res, &mut slots), &inner).unwrap();
+    assert_eq!(
+        slots,
+        vec![
+            ("z#1".to_string(), Some(Slot::Param(0))),
+            ("x#1".to_string(), Some(Slot::Capture(0))),
+            ("limit".to_string(), None),
+        ]
+    );
+}
+
+#[test]
+fn test_diff() {
+    let a = parse_expr(r"(\x ($add ($mul x 2) 1))").unwrap();
+    assert_eq!(diff(&a, &a.clone()), vec![]);
+    assert_eq!(
+        diff(&a, &parse_expr(r"(\x ($add ($mul x 2) 1))").unwrap()),
+        vec![]
+    );
+
+    let b = parse_expr(r"(\x ($sub ($mul x 3) 1))").unwrap();
+    let d = diff(&a, &b);
+    assert_eq!(
+        d.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+        vec![
+            "body.target: $add -> $sub",
+            "body.params[0].params[1]: 2 -> 3"
+        ]
+    );
+    assert_eq!(
+        d[1].path,
+        Path(vec![
+            PathSegment::Body,
+            PathSegment::Param(0),
+            PathSegment::Param(1)
+        ])
+    );
+
+    let c = parse_expr(r"(\y ($add ($mul y 2)))").unwrap();
+    assert_eq!(
+        diff(&a, &c)
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "<root>: parameters (x) -> (y)",
+            "body.params[0].params[0]: x -> y",
+            "body.params[1]: removed 1",
+        ]
+    );
+    assert_eq!(
+        diff(&c, &a).last().unwrap().kind,
+        DifferenceKind::Added(parse_expr("(1)").unwrap())
+    );
+}
+
+/// Lists parameters and referenced names in source order.
+fn params_and_names(e: &Expr) -> Vec<String> {
+    struct Collect(Vec<String>);
+    impl visit::Visitor for Collect {
+        type Error = ();
+        fn visit_name(&mut self, _e: &Expr, name: &str) -> Result<(), ()> {
+            self.0.push(name.into());
+            Ok(())
+        }
+        fn pre_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+            if let ExprBody::Abstract { ref params, .. } = *e.body {
+                self.0.extend(params.iter().cloned());
+            }
+            Ok(())
+        }
+    }
+    let mut c = Collect(Vec::new());
+    visit::walk(&mut c, e).unwrap();
+    c.0
+}
+
+#[test]
+fn test_canonicalize() {
+    let e = parse_expr(r"(\x (\y ($add x ((\x (x)) y))))").unwrap();
+    // Renaming again appends another suffix, which canonicalization strips.
+    let renamed = RenameContext::default()
+        .with_renamed(&["x#1"], |ctx| rename_expr(&e, ctx))
+        .unwrap();
+    assert_ne!(renamed, e);
+    assert_eq!(canonicalize(&renamed), canonicalize(&e));
+    assert_eq!(
+        params_and_names(&canonicalize(&e)),
+        vec!["x#1", "y#2", "x#1", "x#3", "x#3", "y#2"]
+    );
+
+    let e = parse_expr_with_globals(r"(\x ($add x limit))", names(&["limit"])).unwrap();
+    assert_eq!(canonicalize(&e), e, "free names are kept");
+}
+
+#[test]
+fn test_content_hash() {
+    let e = parse_expr(r"(\x (\y ($add x ((\x (x)) y))))").unwrap();
+    let renamed = RenameContext::default()
+        .with_renamed(&["x#1"], |ctx| rename_expr(&e, ctx))
+        .unwrap();
+    assert_eq!(renamed.content_hash(), e.content_hash());
+    assert_ne!(
+        parse_expr(r"(\x (\y ($add y ((\x (x)) y))))")
+            .unwrap()
+            .content_hash(),
+        e.content_hash()
+    );
+    // Stored hashes must stay valid.
+    assert_eq!(e.content_hash(), 3070679403169182025);
+}
+
+#[test]
+fn test_substitute() {
+    let template = parse_expr_with_globals(
+        r"(\x ($lt x ($mul limit scale)))",
+        names(&["limit", "scale"]),
+    )
+    .unwrap();
+    let e = substitute(
+        &template,
+        &[
+            ("limit", parse_expr("(10)").unwrap()),
+            ("scale", parse_expr("($add 1 2)").unwrap()),
+        ],
+    );
+    assert_eq!(e, parse_expr(r"(\x ($lt x ($mul 10 ($add 1 2))))").unwrap());
+    assert_eq!(substitute(&template, &[] as &[(&str, Expr)]), template);
+
+    // The replacement refers to the `x#1` bound by another lambda, which
+    // the template's own `x#1` must not capture.
+    let hole = parse_expr_with_globals(r"(\x ($add x hole))", names(&["hole"])).unwrap();
+    let outer = parse_expr(r"(\x ($mul x 2))").unwrap();
+    let replacement = match *outer.body {
+        ExprBody::Abstract {
+            body: AbstractBody::Expr(ref body),
+            ..
+        } => body.clone(),
+        _ => unreachable!(),
+    };
+    let e = substitute(&hole, &[("hole", replacement)]);
+    assert_eq!(free_vars(&e), names(&["x#1"]));
+    assert_eq!(params_and_names(&e), vec!["x#2", "x#2", "x#1"]);
+}
+
+#[test]
+fn test_manifest() {
+    let e = parse_expr_with_globals(
+        r"($if ($lt limit 3) ((\f (f 1)) $log) (lookup ($add limit 1)))",
+        names(&["limit", "lookup"]),
+    )
+    .unwrap();
+    let m = manifest(&e);
+    assert_eq!(m.hosts, names(&["add", "if", "log", "lt"]));
+    assert_eq!(m.free_names, names(&["limit", "lookup"]));
+    assert_eq!(
+        m.unapproved_hosts(&names(&["add", "if", "lt"])),
+        names(&["log"])
+    );
+}
@@ -0,0 +1,134 @@
+//! Host functions backed by futures, for calls that wait on I/O.
+//!
+//! The evaluator itself is synchronous, so `eval_expr_async` runs it on a
+//! stack of its own, see `suspend`. When evaluation reaches an async host
+//! call, it suspends there while the driver awaits the call's future, and
+//! carries on with the result. Everything, async calls included, is
+//! evaluated as often as in a synchronous evaluation.
+//!
+//! Arguments and results of async host functions are restricted to `~`,
+//! ints, floats and bools.
+
+use crate::ast::{DataType, Expr};
+use crate::engine::{Engine, RunOptions};
+use crate::error::*;
+use crate::eval::{EvalContext, LazyValue, OwnedValue, RuntimeValue};
+use crate::host::{HostFunction, Signature};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::suspend::Suspendable;
+use crate::suspend::{Resume, Suspension};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Future returned by `AsyncHostFunction::call`.
+pub type HostFuture = Pin<Box<dyn Future<Output = Result<OwnedValue, RuntimeError>>>>;
+
+pub trait AsyncHostFunction: Debug {
+    /// Declared parameters, if the function has a fixed parameter list.
+    fn signature(&self) -> Option<Signature> {
+        None
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError>;
+
+    /// Starts the call. `args` are fully evaluated, with defaults filled in.
+    fn call(&self, args: Vec<OwnedValue>) -> HostFuture;
+}
+
+/// Registers an `AsyncHostFunction` with the synchronous evaluator.
+#[derive(Debug)]
+struct AsyncHostAdapter {
+    inner: Rc<dyn AsyncHostFunction>,
+}
+
+impl HostFunction for AsyncHostAdapter {
+    fn signature(&self) -> Option<Signature> {
+        self.inner.signature()
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        self.inner.typeck(params)
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let mut args = Vec::new();
+        for p in params {
+            let v = p.eval(ectx)?.into_owned(ectx)?;
+            check_plain(&v)?;
+            args.push(v);
+        }
+
+        let why = Suspension::AsyncCall {
+            host: self.inner.clone(),
+            args,
+        };
+        match ectx.suspend(why) {
+            Some(Resume::AsyncResult(v)) => Ok(match v? {
+                OwnedValue::Empty => RuntimeValue::Empty,
+                OwnedValue::Int(v) => RuntimeValue::Int(v),
+                OwnedValue::Float(v) => RuntimeValue::Float(v),
+                OwnedValue::Bool(v) => RuntimeValue::Bool(v),
+                _ => unreachable!(),
+            }),
+            Some(Resume::Steps(_)) => panic!("bug: async call resumed with steps"),
+            None => Err(RuntimeError::AsyncHostCall),
+        }
+    }
+}
+
+fn check_plain(v: &OwnedValue) -> Result<(), RuntimeError> {
+    match *v {
+        OwnedValue::Empty | OwnedValue::Int(_) | OwnedValue::Float(_) | OwnedValue::Bool(_) => {
+            Ok(())
+        }
+        _ => Err(RuntimeError::Custom(format!(
+            "async host functions only take and return plain values, got {}",
+            v
+        ))),
+    }
+}
+
+impl Engine {
+    /// Registers an async host function, callable as `$name` from
+    /// expressions run with `eval_expr_async`. Calling it from a
+    /// synchronous evaluation fails with `RuntimeError::AsyncHostCall`.
+    pub fn add_async_host(&mut self, name: String, hf: Box<dyn AsyncHostFunction>) {
+        let adapter = AsyncHostAdapter {
+            inner: Rc::from(hf),
+        };
+        self.add_host(name, Box::new(adapter));
+    }
+}
+
+/// Typechecks and evaluates `e` on `engine`, awaiting async host calls.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn eval_expr_async(engine: &Engine, e: &Expr) -> Result<OwnedValue, Error> {
+    let ty = engine.check(e)?;
+    let mut run = Suspendable::new(engine.host_state(), |suspender, _| {
+        let opts = RunOptions {
+            suspender: Some(suspender),
+            ..RunOptions::default()
+        };
+        engine.run_in(e, &ty, engine.definitions(), opts, |v, ectx| {
+            v.into_owned(ectx)
+        })
+    })?;
+
+    let mut with = Resume::Steps(0);
+    loop {
+        let (host, args) = match run.resume(with) {
+            Ok(out) => return out,
+            // Only the engine's step limit ends a slice.
+            Err(Suspension::Steps) => return Err(RuntimeError::StepLimit.into()),
+            Err(Suspension::AsyncCall { host, args, .. }) => (host, args),
+        };
+        let result = host.call(args).await;
+        with = Resume::AsyncResult(result.and_then(|v| check_plain(&v).map(|_| v)));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::ast::DataType;
+use crate::async_host::*;
+use crate::builtin::ValueType;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::{EvalContext, LazyValue, OwnedValue, RuntimeValue};
+use crate::host::HostFunction;
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// Polls `f` to completion on the current thread.
+fn block_on<F: Future>(f: F) -> F::Output {
+    let mut f = pin!(f);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+            return v;
+        }
+    }
+}
+
+/// Resolves to `value` after returning `Pending` once, like a network call.
+struct Delayed {
+    value: Option<OwnedValue>,
+    polled: bool,
+}
+
+impl Future for Delayed {
+    type Output = Result<OwnedValue, RuntimeError>;
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, _cx: &mut Context) -> Poll<Self::Output> {
+        if self.polled {
+            Poll::Ready(Ok(self.value.take().unwrap()))
+        } else {
+            self.polled = true;
+            Poll::Pending
+        }
+    }
+}
+
+/// `($fetch n)`: asynchronously returns `n * 10`.
+#[derive(Debug)]
+struct FetchOp {
+    calls: Rc<Cell<usize>>,
+}
+
+impl AsyncHostFunction for FetchOp {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Value(ValueType::Int)] => Ok(DataType::Value(ValueType::Int)),
+            _ => Err(TypeError::Custom("expecting an int".into())),
+        }
+    }
+
+    fn call(&self, args: Vec<OwnedValue>) -> HostFuture {
+        self.calls.set(self.calls.get() + 1);
+        let value = match args[0] {
+            OwnedValue::Int(v) => OwnedValue::Int(v * 10),
+            _ => unreachable!(),
+        };
+        Box::pin(Delayed {
+            value: Some(value),
+            polled: false,
+        })
+    }
+}
+
+#[test]
+fn test_eval_expr_async() {
+    let calls = Rc::new(Cell::new(0));
+    let mut engine = Engine::new();
+    engine.add_async_host(
+        "fetch".into(),
+        Box::new(FetchOp {
+            calls: calls.clone(),
+        }),
+    );
+
+    let e = engine
+        .parse("($add ($fetch 1) ($add ($fetch 2) ($fetch 1)))")
+        .unwrap();
+    assert_eq!(
+        block_on(eval_expr_async(&engine, &e)).unwrap(),
+        OwnedValue::Int(40)
+    );
+    // Identical calls are each awaited, as they need not be idempotent.
+    assert_eq!(calls.get(), 3);
+
+    let e = engine.parse("($fetch ($fetch 3))").unwrap();
+    assert_eq!(
+        block_on(eval_expr_async(&engine, &e)).unwrap(),
+        OwnedValue::Int(300)
+    );
+
+    match engine.eval_str("($fetch 1)") {
+        Err(Error::Runtime(RuntimeError::AsyncHostCall)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+/// `$tick`, which counts its calls.
+#[derive(Debug)]
+struct TickOp(Rc<Cell<usize>>);
+
+impl HostFunction for TickOp {
+    fn typeck(&self, _params: &[DataType]) -> Result<DataType, TypeError> {
+        Ok(DataType::Value(ValueType::Int))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        _ectx: &mut EvalContext<'b, 'c>,
+        _params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        self.0.set(self.0.get() + 1);
+        Ok(RuntimeValue::Int(1))
+    }
+}
+
+#[test]
+fn test_async_side_effects_once() {
+    let calls = Rc::new(Cell::new(0));
+    let ticks = Rc::new(Cell::new(0));
+    let mut engine = Engine::new();
+    engine.add_async_host(
+        "fetch".into(),
+        Box::new(FetchOp {
+            calls: calls.clone(),
+        }),
+    );
+    engine.add_host("tick".into(), Box::new(TickOp(ticks.clone())));
+
+    // Synchronous hosts before, between and after async calls run once
+    // each, as they would in a synchronous evaluation.
+    let e = engine
+        .parse("($add ($tick 0) ($add ($fetch ($tick 1)) ($add ($fetch 2) ($tick 2))))")
+        .unwrap();
+    assert_eq!(
+        block_on(eval_expr_async(&engine, &e)).unwrap(),
+        OwnedValue::Int(32)
+    );
+    assert_eq!(ticks.get(), 3);
+    assert_eq!(calls.get(), 2);
+
+    // Identical calls that are not memoized both reach the host.
+    let e = engine.parse("($add ($fetch 7) ($fetch 7))").unwrap();
+    assert_eq!(
+        block_on(eval_expr_async(&engine, &e)).unwrap(),
+        OwnedValue::Int(140)
+    );
+    assert_eq!(calls.get(), 4);
+
+    // Async calls within `$memo` are awaited rather than cached as errors.
+    let e = engine
+        .parse(r"((\f ($add (f 4) (f 4))) ($memo (\x ($add ($tick x) ($fetch x)))))")
+        .unwrap();
+    assert_eq!(
+        block_on(eval_expr_async(&engine, &e)).unwrap(),
+        OwnedValue::Int(82)
+    );
+    assert_eq!(ticks.get(), 4);
+    assert_eq!(calls.get(), 5);
+
+    // Executions cannot wait for async calls.
+    let mut run = engine.start(&engine.parse("($fetch 1)").unwrap()).unwrap();
+    match run.step(100) {
+        Err(Error::Runtime(RuntimeError::AsyncHostCall)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
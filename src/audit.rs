@@ -0,0 +1,79 @@
+//! Recording of host function calls, so that the decisions a script made
+//! can be explained after the fact.
+//!
+//! With auditing enabled, every completed host function call is appended to
+//! an `AuditLog` together with its arguments and result. Arguments are lazy,
+//! so only those the host function actually evaluated are recorded with a
+//! value. Entries are appended when a call returns, so nested calls come
+//! before the call that made them; `depth` tells them apart.
+
+use crate::error::ParseError;
+use crate::eval::{LazyValue, RuntimeValue};
+
+/// A value as recorded in an audit log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AuditValue {
+    Empty,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// A value without a serializable form, recorded as displayed.
+    Other(String),
+    /// An argument the host function did not evaluate.
+    Unevaluated,
+}
+
+impl AuditValue {
+    pub fn from_value(v: &RuntimeValue) -> AuditValue {
+        match *v {
+            RuntimeValue::Empty => AuditValue::Empty,
+            RuntimeValue::Int(v) => AuditValue::Int(v),
+            RuntimeValue::Float(v) => AuditValue::Float(v),
+            RuntimeValue::Bool(v) => AuditValue::Bool(v),
+            ref v => AuditValue::Other(v.to_string()),
+        }
+    }
+
+    pub(crate) fn from_lazy(v: &LazyValue) -> AuditValue {
+        match v.outcome() {
+            Some(v) => AuditValue::from_value(&v),
+            None => AuditValue::Unevaluated,
+        }
+    }
+}
+
+/// One host function call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Name of the host function, without the leading `$`.
+    pub host: String,
+    pub args: Vec<AuditValue>,
+    pub result: AuditValue,
+    /// Number of host function calls the call was made from.
+    pub depth: u32,
+}
+
+/// An append-only list of host function calls, encoded with bincode.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn push(&mut self, entry: AuditEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("bug: audit log serialization failed")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<AuditLog, ParseError> {
+        bincode::deserialize(bytes)
+            .map_err(|e| ParseError::Custom(format!("invalid audit log: {}", e)))
+    }
+}
@@ -0,0 +1,248 @@
+extern crate bincode;
+extern crate serde_json;
+extern crate x_lang;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use x_lang::bundle::{Bundle, BundleModule, OptimizerSettings};
+use x_lang::corelib::{HostManager, Profile};
+use x_lang::engine::Engine;
+use x_lang::lint::{Level, LintContext, Linter};
+use x_lang::optimize::optimize;
+use x_lang::parser::{parse_expr_with_options, parse_expr_with_spans, ParseOptions};
+use x_lang::reference::Reference;
+
+const USAGE: &str =
+    "usage: xlc bundle [-o OUTPUT] [-O LEVEL] [--sign KEYFILE] [--certify] [--deps-dot] ENTRY.x [MODULE.x ...]
+       xlc compile [-o OUTPUT] [-O LEVEL] [--format bincode|json] FILE.x
+       xlc reference [--format markdown|json] [--profile PROFILE]";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("xlc: {}", msg);
+    process::exit(1);
+}
+
+fn module_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .unwrap_or_else(|| fail(&format!("invalid module path: {}", path)))
+        .to_string()
+}
+
+fn bundle(args: &[String]) {
+    let mut output: Option<String> = None;
+    let mut optimizer = OptimizerSettings::default();
+    let mut inputs: Vec<String> = Vec::new();
+    let mut sign_key: Option<String> = None;
+    let mut deps_dot = false;
+    let mut certify = false;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-o" => output = Some(it.next().unwrap_or_else(|| fail(USAGE)).clone()),
+            "-O" => {
+                optimizer.level = it
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or_else(|| fail("invalid optimization level"))
+            }
+            "--sign" => sign_key = Some(it.next().unwrap_or_else(|| fail(USAGE)).clone()),
+            "--certify" => certify = true,
+            "--deps-dot" => deps_dot = true,
+            _ => inputs.push(arg.clone()),
+        }
+    }
+    if inputs.is_empty() {
+        fail(USAGE);
+    }
+
+    let names: BTreeSet<String> = inputs.iter().map(|x| module_name(x)).collect();
+    let hm = HostManager::new();
+    let mut opts = ParseOptions {
+        globals: names,
+        ..Default::default()
+    };
+    opts.add_hosts(hm.get_all());
+
+    let mut modules = Vec::new();
+    for path in &inputs {
+        let source = fs::read_to_string(path)
+            .unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)));
+        let expr = parse_expr_with_options(&source, &opts)
+            .unwrap_or_else(|e| fail(&format!("{}: parse error: {}", path, e)));
+        let mut metadata = BTreeMap::new();
+        metadata.insert("path".to_string(), path.clone());
+        modules.push(BundleModule {
+            name: module_name(path),
+            expr,
+            metadata,
+        });
+    }
+
+    let entry = module_name(&inputs[0]);
+    let mut bundle = Bundle::new(entry.clone(), modules, optimizer).with_host_abi(&hm.abi());
+    let engine = Engine::new();
+    let program = engine
+        .load_bundle(&bundle.to_bytes())
+        .unwrap_or_else(|e| fail(&format!("bundle does not load: {}", e)));
+    if certify {
+        let ty = engine
+            .check_program(&program)
+            .unwrap_or_else(|e| fail(&format!("bundle does not typecheck: {}", e)));
+        bundle = bundle.with_certificate(&ty);
+    }
+    if let Some(path) = sign_key {
+        sign(&mut bundle, &path);
+    }
+    let bytes = bundle.to_bytes();
+
+    if deps_dot {
+        let program = bundle
+            .clone()
+            .into_program()
+            .unwrap_or_else(|e| fail(&format!("invalid bundle: {}", e)));
+        print!("{}", program.definitions.dependency_graph().to_dot());
+    }
+
+    let output = output.unwrap_or_else(|| format!("{}.xlb", entry));
+    fs::write(&output, &bytes).unwrap_or_else(|e| fail(&format!("cannot write {}: {}", output, e)));
+}
+
+/// Compiles a single program: parses, typechecks and lints it, optimizes
+/// it and checks the result again, then writes its AST encoded with bincode
+/// (for `xleval --compiled`) or as JSON.
+fn compile(args: &[String]) {
+    let mut output: Option<String> = None;
+    let mut level = 0;
+    let mut json = false;
+    let mut input: Option<String> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-o" => output = Some(it.next().unwrap_or_else(|| fail(USAGE)).clone()),
+            "-O" => {
+                level = it
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or_else(|| fail("invalid optimization level"))
+            }
+            "--format" => match it.next().map(|x| x.as_str()) {
+                Some("bincode") => json = false,
+                Some("json") => json = true,
+                _ => fail("--format must be bincode or json"),
+            },
+            _ if input.is_some() => fail(USAGE),
+            _ => input = Some(arg.clone()),
+        }
+    }
+    let path = input.unwrap_or_else(|| fail(USAGE));
+    let source =
+        fs::read_to_string(&path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)));
+
+    let engine = Engine::new();
+    let (ast, spans) = parse_expr_with_spans(&source, &engine.parse_options())
+        .unwrap_or_else(|e| fail(&format!("{}: parse error: {}", path, e)));
+    let (ty, type_warnings) = engine
+        .check_with_warnings(&ast)
+        .unwrap_or_else(|e| fail(&format!("{}: type error: {}", path, e)));
+    let cx = LintContext {
+        spans: Some(&spans),
+        ty: Some(&ty),
+        type_warnings: &type_warnings,
+    };
+    let diagnostics = Linter::new().run(&ast, &cx);
+    for d in &diagnostics {
+        eprintln!("{}", d.render(&source, &path));
+    }
+    if diagnostics.iter().any(|d| d.level == Level::Error) {
+        fail(&format!("{}: lint errors", path));
+    }
+
+    let optimized = optimize(&ast, level);
+    match engine.check(&optimized) {
+        Ok(ref t) if *t == ty => {}
+        Ok(t) => fail(&format!(
+            "bug: optimization changed the type of {} from {} to {}",
+            path, ty, t
+        )),
+        Err(e) => fail(&format!(
+            "bug: optimization broke typechecking of {}: {}",
+            path, e
+        )),
+    }
+
+    let bytes = if json {
+        serde_json::to_vec_pretty(&optimized).expect("bug: AST serialization failed")
+    } else {
+        bincode::serialize(&optimized).expect("bug: AST serialization failed")
+    };
+    let output = output.unwrap_or_else(|| {
+        format!(
+            "{}.{}",
+            module_name(&path),
+            if json { "json" } else { "xlo" }
+        )
+    });
+    fs::write(&output, &bytes).unwrap_or_else(|e| fail(&format!("cannot write {}: {}", output, e)));
+}
+
+/// Prints the language reference for the default engine, or one limited to
+/// a host profile.
+fn reference(args: &[String]) {
+    let mut engine = Engine::new();
+    let mut json = false;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--format" => match it.next().map(|x| x.as_str()) {
+                Some("markdown") => json = false,
+                Some("json") => json = true,
+                _ => fail("--format must be markdown or json"),
+            },
+            "--profile" => match it.next().and_then(|x| Profile::from_name(x)) {
+                Some(profile) => engine.set_profile(profile),
+                None => fail("--profile must be pure-math, data-transform or full-io"),
+            },
+            _ => fail(USAGE),
+        }
+    }
+    let reference = Reference::of(&engine);
+    if json {
+        println!("{}", reference.to_json());
+    } else {
+        print!("{}", reference.to_markdown());
+    }
+}
+
+/// Signs `bundle` with the 32-byte Ed25519 secret key stored in `path`.
+#[cfg(feature = "signing")]
+fn sign(bundle: &mut Bundle, path: &str) {
+    let key = fs::read(path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)));
+    if key.len() != 32 {
+        fail("signing key must be exactly 32 bytes");
+    }
+    let mut secret = [0u8; 32];
+    secret.copy_from_slice(&key);
+    bundle.sign(&ed25519_dalek::SigningKey::from_bytes(&secret));
+}
+
+#[cfg(not(feature = "signing"))]
+fn sign(_: &mut Bundle, _: &str) {
+    fail("xlc was built without the `signing` feature");
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|x| x.as_str()) {
+        Some("bundle") => bundle(&args[1..]),
+        Some("compile") => compile(&args[1..]),
+        Some("reference") => reference(&args[1..]),
+        _ => fail(USAGE),
+    }
+}
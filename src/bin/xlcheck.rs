@@ -0,0 +1,138 @@
+extern crate x_lang;
+
+use std::env;
+use std::fs;
+use std::process;
+use x_lang::engine::Engine;
+use x_lang::lint::{Diagnostic, Level, LintContext, Linter};
+use x_lang::parser::parse_expr_with_spans;
+
+const USAGE: &str = "usage: xlcheck [-A LINT] [-W LINT] [-D LINT] [--deny-warnings] FILE...
+       xlcheck --list
+
+  -A LINT          do not report LINT
+  -W LINT          report LINT as a warning
+  -D LINT          report LINT as an error
+  --deny-warnings  report every warning as an error
+  --list           list the available lints and their default levels
+
+exit codes: 0 no errors, 1 errors found, 2 usage or I/O error";
+
+const EXIT_ERRORS: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("xlcheck: {}\n\n{}", msg, USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+fn list(linter: &Linter) {
+    for lint in linter.lints() {
+        println!(
+            "{:<20} {:<8} {}",
+            lint.name(),
+            lint.default_level(),
+            lint.description()
+        );
+    }
+}
+
+/// Lints the program at `path`, printing what it finds. Returns whether
+/// anything was reported as an error.
+fn check_file(engine: &Engine, linter: &Linter, path: &str) -> bool {
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("xlcheck: cannot read {}: {}", path, e);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    let (ast, spans) = match parse_expr_with_spans(&source, &engine.parse_options()) {
+        Ok(x) => x,
+        Err(e) => {
+            let d = Diagnostic {
+                lint: "parse",
+                level: Level::Error,
+                message: e.to_string(),
+                span: e.span(),
+            };
+            eprintln!("{}", d.render(&source, path));
+            return true;
+        }
+    };
+    let (ty, type_warnings) = match engine.check_with_warnings(&ast) {
+        Ok((ty, warnings)) => (Some(ty), warnings),
+        Err(e) => {
+            let d = Diagnostic {
+                lint: "type",
+                level: Level::Error,
+                message: e.to_string(),
+                span: None,
+            };
+            eprintln!("{}", d.render(&source, path));
+            (None, vec![])
+        }
+    };
+    let cx = LintContext {
+        spans: Some(&spans),
+        ty: ty.as_ref(),
+        type_warnings: &type_warnings,
+    };
+    let diags = linter.run(&ast, &cx);
+    for d in &diags {
+        eprintln!("{}", d.render(&source, path));
+    }
+    ty.is_none() || diags.iter().any(|d| d.level == Level::Error)
+}
+
+fn main() {
+    let mut linter = Linter::new();
+    let mut paths = Vec::new();
+    let mut deny_warnings = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let level = match arg.as_str() {
+            "-A" => Level::Allow,
+            "-W" => Level::Warning,
+            "-D" => Level::Error,
+            "--deny-warnings" => {
+                deny_warnings = true;
+                continue;
+            }
+            "--list" => {
+                list(&linter);
+                return;
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            x if x.starts_with('-') => usage_error(&format!("unknown option: {}", x)),
+            _ => {
+                paths.push(arg);
+                continue;
+            }
+        };
+        let name = args
+            .next()
+            .unwrap_or_else(|| usage_error(&format!("{} requires a lint name", arg)));
+        if let Err(e) = linter.set_level(&name, level) {
+            usage_error(&e);
+        }
+    }
+    if deny_warnings {
+        linter.deny_warnings();
+    }
+    if paths.is_empty() {
+        usage_error("no input files");
+    }
+
+    let engine = Engine::new();
+    let mut failed = false;
+    for path in &paths {
+        failed |= check_file(&engine, &linter, path);
+    }
+    if failed {
+        process::exit(EXIT_ERRORS);
+    }
+}
@@ -0,0 +1,27 @@
+extern crate x_lang;
+
+use std::env;
+use std::fs;
+use std::process;
+use x_lang::disasm::disassemble;
+
+const USAGE: &str = "usage: xldis FILE
+
+Prints the constants, functions, host functions and AST of a program
+compiled with `xlc compile` or of each module of a bundle.";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("xldis: {}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = match args.as_slice() {
+        [path] if path != "-h" && path != "--help" => path,
+        _ => fail(USAGE),
+    };
+    let bytes = fs::read(path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)));
+    let listing = disassemble(&bytes).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    print!("{}", listing);
+}
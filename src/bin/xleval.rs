@@ -1,43 +1,313 @@
+extern crate bincode;
+extern crate serde_json;
 extern crate x_lang;
 
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use x_lang::ast::{DataType, Expr, Span};
+use x_lang::engine::Engine;
+use x_lang::error::Error;
+use x_lang::eval::{Debugger, EvalContext, LazyValue, RuntimeValue};
+use x_lang::warning::Warning;
 
-fn main() {
-    let ast_path = env::args().nth(1).unwrap();
+const USAGE: &str = "usage: xleval [--typecheck-only] [--ast-json] [--trace] [--interactive]
+              [--timeout MS] (-e EXPR | FILE | -)
+       xleval --compiled [--typecheck-only] [--trace] [--timeout MS] FILE.xlo
 
-    let mut ast_text = String::new();
-    File::open(&ast_path)
-        .unwrap()
-        .read_to_string(&mut ast_text)
-        .unwrap();
-    let ast: x_lang::ast::Expr = x_lang::parser::parse_expr(ast_text.as_str()).unwrap();
+  -e EXPR           evaluate EXPR instead of reading a file
+  -                 read the program from stdin
+  --typecheck-only  print the type of the program without evaluating it
+  --ast-json        print the parsed AST as JSON without checking it
+  --compiled        run a program compiled with `xlc compile`
+  --trace           report each pipeline stage on stderr
+  --interactive     pause before each call to show it and the variables it
+                    uses, reading commands from stdin (`help` lists them)
+  --timeout MS      cancel evaluation after MS milliseconds
 
-    let mut trs = x_lang::typeck::TypeResolveState::default();
-    let mut ectx = x_lang::eval::EvalContext::default();
+exit codes: 0 success, 1 runtime error, 2 usage or I/O error,
+            3 parse error, 4 type error";
 
-    let hm = x_lang::corelib::HostManager::new();
+const EXIT_RUNTIME: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_PARSE: i32 = 3;
+const EXIT_TYPE: i32 = 4;
 
-    trs.add_hosts(hm.get_binops());
-    ectx.add_hosts(hm.get_binops());
+#[derive(Default)]
+struct Options {
+    typecheck_only: bool,
+    ast_json: bool,
+    compiled: bool,
+    trace: bool,
+    interactive: bool,
+    timeout: Option<u64>,
+    expr: Option<String>,
+    path: Option<String>,
+}
 
-    trs.add_hosts(hm.get_ifop());
-    ectx.add_hosts(hm.get_ifop());
+fn usage_error(msg: &str) -> ! {
+    eprintln!("xleval: {}\n\n{}", msg, USAGE);
+    process::exit(EXIT_USAGE);
+}
 
-    trs.add_hosts(hm.get_relops());
-    ectx.add_hosts(hm.get_relops());
+fn parse_args() -> Options {
+    let mut opts = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--typecheck-only" => opts.typecheck_only = true,
+            "--ast-json" => opts.ast_json = true,
+            "--compiled" => opts.compiled = true,
+            "--trace" => opts.trace = true,
+            "--interactive" => opts.interactive = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--timeout" => match args.next().map(|x| x.parse()) {
+                Some(Ok(ms)) => opts.timeout = Some(ms),
+                _ => usage_error("--timeout requires a number of milliseconds"),
+            },
+            "-e" => match args.next() {
+                Some(e) => opts.expr = Some(e),
+                None => usage_error("-e requires an expression"),
+            },
+            x if x.starts_with("--") => usage_error(&format!("unknown option: {}", x)),
+            _ if opts.path.is_some() => usage_error("more than one input given"),
+            _ => opts.path = Some(arg),
+        }
+    }
+    if opts.expr.is_some() == opts.path.is_some() {
+        usage_error("expecting exactly one of -e EXPR, FILE or -");
+    }
+    if opts.compiled && (opts.expr.is_some() || opts.ast_json) {
+        usage_error("--compiled requires a FILE and excludes -e and --ast-json");
+    }
+    if opts.interactive && opts.path.as_deref() == Some("-") {
+        usage_error("--interactive reads commands from stdin and cannot read the program from it");
+    }
+    opts
+}
 
-    trs.add_hosts(hm.get_list_ops());
-    ectx.add_hosts(hm.get_list_ops());
+const COMMANDS: &str = "commands:
+  step, s, or an empty line  run to the next call
+  continue, c                run to the end without pausing
+  print NAME, p NAME         show a variable the call uses
+  quit, q                    stop evaluation";
 
-    let ty = x_lang::typeck::check_expr(&ast, &mut trs).unwrap();
-    println!("{:?}", ty);
+/// Pauses before each call for `--interactive`, reading commands from
+/// stdin. Variables are shown without forcing them, so stepping does not
+/// change what the program evaluates.
+#[derive(Debug, Default)]
+struct Stepper {
+    /// Set by `continue`, or when stdin ends.
+    running: bool,
+}
 
-    if ty == x_lang::ast::DataType::Divergent {
-        panic!("error: your program will never terminate");
+fn show(lv: &LazyValue) -> String {
+    match lv.outcome() {
+        Some(v) => v.to_string(),
+        None => "<not evaluated>".into(),
     }
+}
 
-    let ret = x_lang::eval::eval_expr(&ast, &mut ectx).unwrap();
-    println!("ECTX: {:?}\nVALUE: {:?}", ectx, ret);
+impl Debugger for Stepper {
+    fn on_apply(&mut self, e: &Expr, bindings: &[(String, LazyValue)]) -> bool {
+        if self.running {
+            return true;
+        }
+        eprintln!("at {}", e);
+        for (name, lv) in bindings {
+            eprintln!("  {} = {}", name, show(lv));
+        }
+        loop {
+            eprint!("(xleval) ");
+            let _ = io::stderr().flush();
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                self.running = true;
+                return true;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] | ["s"] | ["step"] => return true,
+                ["c"] | ["continue"] => {
+                    self.running = true;
+                    return true;
+                }
+                ["p", name] | ["print", name] => match bindings.iter().find(|(x, _)| x == name) {
+                    Some((_, lv)) => eprintln!("{} = {}", name, show(lv)),
+                    None => eprintln!("`{}` is not used by this call", name),
+                },
+                ["q"] | ["quit"] => return false,
+                _ => eprintln!("{}", COMMANDS),
+            }
+        }
+    }
+}
+
+/// Reads a program compiled with `xlc compile --format bincode`.
+fn read_compiled(opts: &Options) -> (Expr, String) {
+    let path = opts.path.as_ref().unwrap();
+    let bytes = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("xleval: cannot read {}: {}", path, e);
+        process::exit(EXIT_USAGE);
+    });
+    match bincode::deserialize(&bytes) {
+        Ok(ast) => (ast, path.clone()),
+        Err(e) => {
+            eprintln!("xleval: {} is not a compiled program: {}", path, e);
+            process::exit(EXIT_PARSE);
+        }
+    }
+}
+
+/// Returns the source text and a name to refer to it by in diagnostics.
+fn read_input(opts: &Options) -> (String, String) {
+    if let Some(ref e) = opts.expr {
+        return (e.clone(), "<expr>".into());
+    }
+    let path = opts.path.as_ref().unwrap();
+    let source = if path == "-" {
+        let mut s = String::new();
+        io::stdin().read_to_string(&mut s).map(|_| s)
+    } else {
+        fs::read_to_string(path)
+    };
+    match source {
+        Ok(s) => (
+            s,
+            if path == "-" {
+                "<stdin>".into()
+            } else {
+                path.clone()
+            },
+        ),
+        Err(e) => {
+            eprintln!("xleval: cannot read {}: {}", path, e);
+            process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+/// Prints `e` with the offending source line underlined when its location
+/// is known, and exits with the matching status code.
+fn report(e: Error, source: &str, name: &str) -> ! {
+    eprintln!("error: {}", e);
+    if let Error::Parse(ref pe) = e {
+        if let Some(span) = pe.span() {
+            print_span(source, name, span);
+        }
+        if let Some(s) = pe.suggestion() {
+            eprintln!("  = help: {}", s);
+        }
+    }
+    process::exit(match e {
+        Error::Parse(_) => EXIT_PARSE,
+        Error::Type(_) => EXIT_TYPE,
+        Error::Runtime(_) => EXIT_RUNTIME,
+    });
+}
+
+fn print_warnings(warnings: &[Warning]) {
+    for w in warnings {
+        eprintln!("warning: {}", w);
+    }
+}
+
+fn print_span(source: &str, name: &str, span: Span) {
+    let line_start = source[..span.start].rfind('\n').map_or(0, |x| x + 1);
+    let line_end = source[span.start..]
+        .find('\n')
+        .map_or(source.len(), |x| span.start + x);
+    let line_no = source[..span.start].matches('\n').count() + 1;
+    let col = span.start - line_start;
+    let width = span.end.min(line_end).saturating_sub(span.start).max(1);
+    let gutter = " ".repeat(line_no.to_string().len());
+
+    eprintln!("{}--> {}:{}:{}", gutter, name, line_no, col + 1);
+    eprintln!("{} |", gutter);
+    eprintln!("{} | {}", line_no, &source[line_start..line_end]);
+    eprintln!("{} | {}{}", gutter, " ".repeat(col), "^".repeat(width));
+}
+
+fn main() {
+    let opts = parse_args();
+    let mut engine = Engine::new();
+
+    let start = Instant::now();
+    let (ast, source, name) = if opts.compiled {
+        let (ast, name) = read_compiled(&opts);
+        (ast, String::new(), name)
+    } else {
+        let (source, name) = read_input(&opts);
+        let ast = engine
+            .parse(&source)
+            .unwrap_or_else(|e| report(e, &source, &name));
+        (ast, source, name)
+    };
+    if opts.trace {
+        eprintln!("trace: parsed in {:?}", start.elapsed());
+    }
+    if opts.ast_json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&ast).expect("bug: AST serialization failed")
+        );
+        return;
+    }
+
+    let start = Instant::now();
+    let (ty, type_warnings) = engine
+        .check_with_warnings(&ast)
+        .unwrap_or_else(|e| report(e, &source, &name));
+    if opts.trace {
+        eprintln!("trace: typechecked in {:?}: {}", start.elapsed(), ty);
+    }
+    if opts.typecheck_only {
+        print_warnings(&type_warnings);
+        println!("{}", ty);
+        if ty == DataType::Divergent {
+            process::exit(EXIT_TYPE);
+        }
+        return;
+    }
+
+    if let Some(ms) = opts.timeout {
+        let flag = Arc::new(AtomicBool::new(false));
+        engine.set_cancel_flag(Some(flag.clone()));
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(ms));
+            flag.store(true, Ordering::Relaxed);
+        });
+    }
+
+    let start = Instant::now();
+    let trace = opts.trace;
+    let finish = |v: RuntimeValue, _: &mut EvalContext| {
+        if trace {
+            eprintln!("trace: evaluated in {:?}", start.elapsed());
+        }
+        Ok(v.to_string())
+    };
+    let outcome = if opts.interactive {
+        engine.debug_with(&ast, &mut Stepper::default(), finish)
+    } else {
+        engine.evaluate_with(&ast, finish)
+    }
+    .unwrap_or_else(|e| report(e, &source, &name));
+    if trace {
+        eprintln!(
+            "trace: {} steps, {} host calls",
+            outcome.steps,
+            outcome.host_calls.values().sum::<u64>()
+        );
+    }
+    print_warnings(&outcome.warnings);
+    println!("{}", outcome.value);
 }
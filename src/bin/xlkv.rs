@@ -0,0 +1,74 @@
+//! Runs an x-lang script against a toy key-value store. See
+//! `x_lang::kvstore` for the host functions scripts can use.
+
+extern crate x_lang;
+
+use std::env;
+use std::fs;
+use std::process;
+use x_lang::builtin::ValueType;
+use x_lang::engine::Engine;
+use x_lang::eval::OwnedValue;
+use x_lang::kvstore::{run_script, set_input, Capabilities, KvStore};
+
+const USAGE: &str = "usage: xlkv [--read-only] [--fuel N] [--set NS:KEY=VALUE]... \
+                     [--input NAME=INT]... SCRIPT.x";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("xlkv: {}", msg);
+    process::exit(1);
+}
+
+fn parse_int(s: &str) -> i64 {
+    s.parse()
+        .unwrap_or_else(|_| fail(&format!("invalid integer: {}", s)))
+}
+
+fn split(s: &str, sep: char) -> (&str, &str) {
+    s.split_once(sep).unwrap_or_else(|| fail(USAGE))
+}
+
+fn main() {
+    let store = KvStore::new();
+    let mut caps = Capabilities::read_write();
+    let mut inputs: Vec<(String, i64)> = Vec::new();
+    let mut script: Option<String> = None;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--read-only" => caps = Capabilities::read_only(),
+            "--fuel" => store.set_fuel(parse_int(it.next().unwrap_or_else(|| fail(USAGE))) as u64),
+            "--set" => {
+                let (key, value) = split(it.next().unwrap_or_else(|| fail(USAGE)), '=');
+                let (ns, key) = split(key, ':');
+                store.put(parse_int(ns), parse_int(key), parse_int(value));
+            }
+            "--input" => {
+                let (name, value) = split(it.next().unwrap_or_else(|| fail(USAGE)), '=');
+                inputs.push((name.to_string(), parse_int(value)));
+            }
+            _ if script.is_none() => script = Some(arg.clone()),
+            _ => fail(USAGE),
+        }
+    }
+    let script = script.unwrap_or_else(|| fail(USAGE));
+    let source = fs::read_to_string(&script)
+        .unwrap_or_else(|e| fail(&format!("cannot read {}: {}", script, e)));
+
+    let mut engine = Engine::new();
+    store.register(&mut engine, caps);
+    for (name, value) in &inputs {
+        set_input(&mut engine, name, &OwnedValue::Int(*value))
+            .unwrap_or_else(|e| fail(&e.to_string()));
+    }
+
+    match run_script(&engine, &source, ValueType::Int) {
+        Ok(v) => println!("{}", v),
+        Err(e) => fail(&e.to_string()),
+    }
+    for ((ns, key), value) in store.entries() {
+        println!("{}:{}={}", ns, key, value);
+    }
+}
@@ -0,0 +1,96 @@
+extern crate serde_json;
+extern crate x_lang;
+
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::process;
+use std::time::Duration;
+use x_lang::engine::EngineConfig;
+use x_lang::remote::{serve_tcp, server_config, ServerLimits};
+
+const USAGE: &str = "usage: xl-serve (--tcp ADDR | --unix PATH) [--config FILE.json] \
+                     [--max-requests N] [--idle-timeout SECONDS] [--max-frame-bytes N] \
+                     [--max-value-depth N] [--max-connections N]\n\
+                     Settings missing from the configuration file keep the \
+                     restrictive server defaults; an idle timeout of 0 disables it.";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("xl-serve: {}", msg);
+    process::exit(1);
+}
+
+fn number<T: std::str::FromStr>(arg: Option<&String>, what: &str) -> T {
+    arg.and_then(|x| x.parse().ok())
+        .unwrap_or_else(|| fail(&format!("invalid {}", what)))
+}
+
+/// Reads a configuration file, taking the settings it leaves out from
+/// `server_config()` rather than from `EngineConfig::default()`.
+fn read_config(path: &str) -> EngineConfig {
+    let text =
+        fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)));
+    let overrides: serde_json::Value = serde_json::from_str(&text)
+        .unwrap_or_else(|e| fail(&format!("invalid config {}: {}", path, e)));
+    let overrides = match overrides {
+        serde_json::Value::Object(map) => map,
+        _ => fail(&format!("invalid config {}: expected an object", path)),
+    };
+    let mut config =
+        serde_json::to_value(server_config()).expect("bug: config serialization failed");
+    if let serde_json::Value::Object(ref mut map) = config {
+        map.extend(overrides);
+    }
+    serde_json::from_value(config)
+        .unwrap_or_else(|e| fail(&format!("invalid config {}: {}", path, e)))
+}
+
+/// Serves remote evaluation requests, each connection with an engine set up
+/// from the configuration file.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut tcp: Option<String> = None;
+    let mut unix: Option<String> = None;
+    let mut config = server_config();
+    let mut limits = ServerLimits::default();
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--tcp" => tcp = Some(it.next().unwrap_or_else(|| fail(USAGE)).clone()),
+            "--unix" => unix = Some(it.next().unwrap_or_else(|| fail(USAGE)).clone()),
+            "--config" => config = read_config(it.next().unwrap_or_else(|| fail(USAGE))),
+            "--max-requests" => limits.max_requests = Some(number(it.next(), "request limit")),
+            "--idle-timeout" => {
+                limits.idle_timeout = match number(it.next(), "timeout") {
+                    0 => None,
+                    secs => Some(Duration::from_secs(secs)),
+                }
+            }
+            "--max-frame-bytes" => limits.max_frame_bytes = number(it.next(), "frame limit"),
+            "--max-value-depth" => limits.max_value_depth = number(it.next(), "depth limit"),
+            "--max-connections" => limits.max_connections = number(it.next(), "connection limit"),
+            _ => fail(USAGE),
+        }
+    }
+
+    let result = match (tcp, unix) {
+        (Some(addr), None) => {
+            let listener = TcpListener::bind(&addr)
+                .unwrap_or_else(|e| fail(&format!("cannot listen on {}: {}", addr, e)));
+            serve_tcp(listener, config, limits)
+        }
+        #[cfg(unix)]
+        (None, Some(path)) => {
+            let listener = UnixListener::bind(&path)
+                .unwrap_or_else(|e| fail(&format!("cannot listen on {}: {}", path, e)));
+            x_lang::remote::serve_unix(listener, config, limits)
+        }
+        _ => fail(USAGE),
+    };
+    if let Err(e) = result {
+        fail(&e.to_string());
+    }
+}
@@ -0,0 +1,20 @@
+extern crate x_lang;
+
+use std::io::{self, BufRead, Write};
+
+/// Reads one JSON request per line from stdin and writes one JSON response
+/// per line to stdout.
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(out, "{}", x_lang::service::handle_json(&line)).unwrap();
+        out.flush().unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+//! Single-file distribution format for multi-module programs.
+//!
+//! A bundle holds every module of a program as a parsed AST, together with
+//! per-module metadata, the optimizer settings it was built with and the
+//! host functions it requires, optionally with the `HostAbi` they had when
+//! it was built. Bundles are encoded with bincode.
+//!
+//! A bundle built from a program that typechecked can carry a
+//! `TypeCertificate` recording the type of its entry module, which engines
+//! set to trust certificates load without checking the program again.
+//!
+//! Encoded, a bundle is a header, its payload and an optional signature.
+//! The payload is the rest of the bundle, encoded on its own. With the
+//! `signing` feature, a bundle can carry an Ed25519 signature over the
+//! payload bytes, which `from_bytes_verified` checks against the bytes it
+//! was given before decoding any of the payload.
+
+use crate::ast::{collect_hosts, DataType, Expr};
+use crate::definitions::Definitions;
+use crate::error::ParseError;
+use crate::host::HostAbi;
+use crate::program::Program;
+use crate::typeck::TypeDescription;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const BUNDLE_MAGIC: [u8; 4] = *b"XLB\0";
+pub const BUNDLE_FORMAT_VERSION: u32 = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct OptimizerSettings {
+    pub level: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleModule {
+    pub name: String,
+    pub expr: Expr,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// The type a bundle's entry module was checked to have, bound to the
+/// modules and host ABI it was checked with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TypeCertificate {
+    pub ty: TypeDescription,
+    /// `Bundle::checked_digest` when the certificate was issued.
+    pub digest: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bundle {
+    pub magic: [u8; 4],
+    pub format_version: u32,
+    pub entry: String,
+    pub modules: Vec<BundleModule>,
+    pub optimizer: OptimizerSettings,
+    pub required_hosts: BTreeSet<String>,
+    /// Descriptors of the required host functions as they were when the
+    /// bundle was built. Loading checks them against the registered ones.
+    pub host_abi: Option<HostAbi>,
+    pub certificate: Option<TypeCertificate>,
+    /// Signature over the payload, which is encoded without it.
+    #[serde(skip)]
+    pub signature: Option<Vec<u8>>,
+}
+
+/// The encoding of a bundle. Only the header and byte strings are decoded
+/// to read it, so nothing of the payload is decoded before its signature
+/// is checked.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    magic: [u8; 4],
+    format_version: u32,
+    payload: Vec<u8>,
+    signature: Option<Vec<u8>>,
+}
+
+impl Envelope {
+    /// Decodes an envelope, checking its magic and format version. They
+    /// are decoded first, as a fixed header, so that bundles of other
+    /// versions, whose layout may differ, are reported as such.
+    fn from_bytes(bytes: &[u8]) -> Result<Envelope, ParseError> {
+        let (magic, format_version): ([u8; 4], u32) =
+            bincode::deserialize(bytes).map_err(invalid)?;
+        if magic != BUNDLE_MAGIC {
+            return Err(ParseError::Custom("invalid bundle: bad magic".into()));
+        }
+        if format_version != BUNDLE_FORMAT_VERSION {
+            return Err(ParseError::Custom(format!(
+                "unsupported bundle format version {} (expecting {})",
+                format_version, BUNDLE_FORMAT_VERSION
+            )));
+        }
+        bincode::deserialize(bytes).map_err(invalid)
+    }
+
+    fn into_bundle(self) -> Result<Bundle, ParseError> {
+        let mut bundle: Bundle = bincode::deserialize(&self.payload).map_err(invalid)?;
+        bundle.signature = self.signature;
+        Ok(bundle)
+    }
+}
+
+fn invalid(e: bincode::Error) -> ParseError {
+    ParseError::Custom(format!("invalid bundle: {}", e))
+}
+
+impl Bundle {
+    /// Creates a bundle from modules, computing the required host manifest.
+    pub fn new(entry: String, modules: Vec<BundleModule>, optimizer: OptimizerSettings) -> Bundle {
+        let mut required_hosts = BTreeSet::new();
+        for m in &modules {
+            collect_hosts(&m.expr, &mut required_hosts);
+        }
+        Bundle {
+            magic: BUNDLE_MAGIC,
+            format_version: BUNDLE_FORMAT_VERSION,
+            entry,
+            modules,
+            optimizer,
+            required_hosts,
+            host_abi: None,
+            certificate: None,
+            signature: None,
+        }
+    }
+
+    /// Records the descriptors `registered` has for the required host
+    /// functions, e.g. `engine.host_abi()` of the engine the bundle is built
+    /// for.
+    pub fn with_host_abi(mut self, registered: &HostAbi) -> Bundle {
+        self.host_abi = Some(registered.restricted_to(&self.required_hosts));
+        self
+    }
+
+    /// Records that the entry module has type `ty`, as found by e.g.
+    /// `Engine::check_program`. Call this after `with_host_abi`, and before
+    /// signing so that the signature covers the certificate.
+    pub fn with_certificate(mut self, ty: &DataType) -> Bundle {
+        self.certificate = Some(TypeCertificate {
+            ty: TypeDescription::of(ty),
+            digest: self.checked_digest(),
+        });
+        self
+    }
+
+    /// Returns the SHA-256 digest of what typechecking the bundle depends
+    /// on: its entry, modules and host ABI.
+    pub fn checked_digest(&self) -> [u8; 32] {
+        let bytes = bincode::serialize(&(&self.entry, &self.modules, &self.host_abi))
+            .expect("bug: bundle serialization failed");
+        Sha256::digest(&bytes).into()
+    }
+
+    /// Returns the certified type of the entry module, if the bundle has a
+    /// certificate that still matches its contents and a host ABI to check
+    /// the registered host functions against.
+    pub fn certified_type(&self) -> Option<&TypeDescription> {
+        match (&self.certificate, &self.host_abi) {
+            (Some(cert), Some(_)) if cert.digest == self.checked_digest() => Some(&cert.ty),
+            _ => None,
+        }
+    }
+
+    /// Returns the payload of the bundle: its encoding without the
+    /// signature, which is what signatures are computed over.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("bug: bundle serialization failed")
+    }
+
+    #[cfg(feature = "signing")]
+    pub fn sign(&mut self, key: &ed25519_dalek::SigningKey) {
+        use ed25519_dalek::Signer;
+
+        let sig = key.sign(&self.canonical_bytes());
+        self.signature = Some(sig.to_bytes().to_vec());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&Envelope {
+            magic: self.magic,
+            format_version: self.format_version,
+            payload: self.canonical_bytes(),
+            signature: self.signature.clone(),
+        })
+        .expect("bug: bundle serialization failed")
+    }
+
+    /// Decodes a bundle, checking its magic and format version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bundle, ParseError> {
+        Envelope::from_bytes(bytes)?.into_bundle()
+    }
+
+    /// Like `from_bytes`, but rejects bundles whose payload is not signed
+    /// by the holder of `key`. The signature is checked before the payload
+    /// is decoded, so tampered bundles are rejected whatever they contain.
+    #[cfg(feature = "signing")]
+    pub fn from_bytes_verified(
+        bytes: &[u8],
+        key: &ed25519_dalek::VerifyingKey,
+    ) -> Result<Bundle, ParseError> {
+        use ed25519_dalek::{Signature, Verifier};
+
+        let envelope = Envelope::from_bytes(bytes)?;
+        let sig = match envelope.signature {
+            Some(ref v) => Signature::from_slice(v)
+                .map_err(|_| ParseError::Custom("invalid bundle signature".into()))?,
+            None => return Err(ParseError::Custom("bundle is not signed".into())),
+        };
+        key.verify(&envelope.payload, &sig)
+            .map_err(|_| ParseError::Custom("bundle signature verification failed".into()))?;
+        envelope.into_bundle()
+    }
+
+    /// Turns the bundle into a program with one definition per module.
+    pub fn into_program(self) -> Result<Program, ParseError> {
+        let mut definitions = Definitions::default();
+        for m in self.modules {
+            if definitions.get(&m.name).is_some() {
+                return Err(ParseError::Custom(format!("duplicate module: {}", m.name)));
+            }
+            definitions.define(m.name, m.expr);
+        }
+        if definitions.get(&self.entry).is_none() {
+            return Err(ParseError::Custom(format!(
+                "entry module not found: {}",
+                self.entry
+            )));
+        }
+        Ok(Program {
+            definitions,
+            entry: self.entry,
+            certified: None,
+        })
+    }
+}
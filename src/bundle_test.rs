@@ -0,0 +1,380 @@
+use crate::ast::DataType;
+use crate::builtin::ValueType;
+use crate::bundle::*;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::{EvalContext, LazyValue, RuntimeValue};
+use crate::host::{HostFunction, Param, Signature};
+use crate::parser::parse_expr_with_globals;
+use crate::program::Program;
+use crate::typeck::TypeDescription;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+fn module(name: &str, src: &str, globals: &BTreeSet<String>) -> BundleModule {
+    BundleModule {
+        name: name.to_string(),
+        expr: parse_expr_with_globals(src, globals.clone()).unwrap(),
+        metadata: BTreeMap::new(),
+    }
+}
+
+#[test]
+fn test_bundle_roundtrip() {
+    let globals: BTreeSet<String> = vec!["main".to_string(), "util".to_string()]
+        .into_iter()
+        .collect();
+    let bundle = Bundle::new(
+        "main".into(),
+        vec![
+            module("main", "(util 20)", &globals),
+            module("util", r"(\x ($add x 1))", &globals),
+        ],
+        OptimizerSettings::default(),
+    );
+    assert!(bundle.required_hosts.contains("add"));
+
+    let bytes = bundle.to_bytes();
+    let engine = Engine::new();
+    let program = engine.load_bundle(&bytes).unwrap();
+    let v = engine
+        .eval_program_with(&program, |v, _| Ok(v.to_string()))
+        .unwrap();
+    assert_eq!(v, "21");
+
+    assert!(engine.load_bundle(&bytes[..bytes.len() - 1]).is_err());
+
+    let mut bundle = Bundle::from_bytes(&bytes).unwrap();
+    bundle.required_hosts.insert("fetch".into());
+    assert!(engine.load_bundle(&bundle.to_bytes()).is_err());
+}
+
+#[test]
+fn test_bundle_older_version() {
+    // Version 3 bundles had no signature field, so their body does not
+    // decode as the current layout.
+    let old = bincode::serialize(&(BUNDLE_MAGIC, 3u32, "main", 0u64)).unwrap();
+    match Bundle::from_bytes(&old) {
+        Err(ParseError::Custom(msg)) => assert_eq!(
+            msg,
+            format!(
+                "unsupported bundle format version 3 (expecting {})",
+                BUNDLE_FORMAT_VERSION
+            )
+        ),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    let garbage = bincode::serialize(&(*b"XLC\0", BUNDLE_FORMAT_VERSION)).unwrap();
+    assert!(Bundle::from_bytes(&garbage).is_err());
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn test_bundle_signature() {
+    let globals: BTreeSet<String> = vec!["main".to_string()].into_iter().collect();
+    let mut bundle = Bundle::new(
+        "main".into(),
+        vec![module("main", "($add 1 2)", &globals)],
+        OptimizerSettings::default(),
+    );
+    let key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let public_key = key.verifying_key().to_bytes();
+    let engine = Engine::new();
+
+    assert!(engine
+        .load_bundle_verified(&bundle.to_bytes(), &public_key)
+        .is_err());
+
+    bundle.sign(&key);
+    assert!(engine
+        .load_bundle_verified(&bundle.to_bytes(), &public_key)
+        .is_ok());
+
+    bundle.entry = "other".into();
+    assert!(engine
+        .load_bundle_verified(&bundle.to_bytes(), &public_key)
+        .is_err());
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn test_bundle_signature_before_decoding() {
+    let globals: BTreeSet<String> = vec!["main".to_string()].into_iter().collect();
+    let mut bundle = Bundle::new(
+        "main".into(),
+        vec![module("main", "($add 1 2)", &globals)],
+        OptimizerSettings::default(),
+    );
+    let key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let public_key = key.verifying_key().to_bytes();
+    bundle.sign(&key);
+    let engine = Engine::new();
+
+    // A module nested a million calls deep, which would overflow the stack
+    // if it were decoded.
+    let mut payload =
+        bincode::serialize(&(BUNDLE_MAGIC, BUNDLE_FORMAT_VERSION, "main", 1u64, "main")).unwrap();
+    for _ in 0..1_000_000 {
+        payload.extend_from_slice(&2u32.to_le_bytes());
+    }
+    let tampered = bincode::serialize(&(
+        BUNDLE_MAGIC,
+        BUNDLE_FORMAT_VERSION,
+        payload,
+        bundle.signature.clone(),
+    ))
+    .unwrap();
+    match engine.load_bundle_verified(&tampered, &public_key) {
+        Err(Error::Parse(ParseError::Custom(msg))) => {
+            assert_eq!(msg, "bundle signature verification failed")
+        }
+        x => panic!("unexpected result: {:?}", x.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_precompute_constants() {
+    use crate::ast::{ConstExpr, ExprBody};
+    use crate::config::HostConfig;
+
+    let names = [
+        "base", "broken", "greeting", "main", "rate", "scale", "stamp", "vague",
+    ];
+    let globals: BTreeSet<String> = names.iter().map(|n| n.to_string()).collect();
+    let bundle = Bundle::new(
+        "main".into(),
+        vec![
+            module(
+                "main",
+                r#"($add (scale rate) ($config "bonus" 0))"#,
+                &globals,
+            ),
+            module("rate", "($mul base 3)", &globals),
+            module("base", "($div 100 4)", &globals),
+            module("scale", r"(\x ($mul x 2))", &globals),
+            module(
+                "greeting",
+                r#"($json_to_str ($json_parse "\"hi\""))"#,
+                &globals,
+            ),
+            module("stamp", "($now ($duration 0))", &globals),
+            module("vague", "($dyn 5)", &globals),
+            module("broken", "($div 1 0)", &globals),
+        ],
+        OptimizerSettings::default(),
+    );
+    let mut engine = Engine::new();
+
+    let mut program = bundle.clone().into_program().unwrap();
+    engine.check_program(&program).unwrap();
+    assert_eq!(
+        engine.precompute_constants(&mut program),
+        vec!["base", "greeting", "rate"]
+    );
+    assert_eq!(
+        *program.definitions.get("rate").unwrap().expr.body,
+        ExprBody::Const(ConstExpr::Int(75))
+    );
+    assert_eq!(
+        *program.definitions.get("greeting").unwrap().expr.body,
+        ExprBody::Const(ConstExpr::Str("hi".into()))
+    );
+
+    // Programs are folded as they are loaded, and still read the
+    // configuration on every run.
+    let program = engine.load_bundle(&bundle.to_bytes()).unwrap();
+    assert_eq!(
+        *program.definitions.get("base").unwrap().expr.body,
+        ExprBody::Const(ConstExpr::Int(25))
+    );
+    let run = |engine: &Engine| {
+        engine
+            .eval_program_with(&program, |v, _| Ok(v.to_string()))
+            .unwrap()
+    };
+    assert_eq!(run(&engine), "150");
+    let mut config = HostConfig::new();
+    config.set("bonus", 5);
+    engine.host_state_mut().insert(config);
+    assert_eq!(run(&engine), "155");
+}
+
+/// `$score`, whose interface changed between versions.
+#[derive(Debug)]
+struct ScoreOp {
+    version: u32,
+    params: Vec<&'static str>,
+}
+
+impl HostFunction for ScoreOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(
+            self.params.iter().map(|p| Param::required(p)).collect(),
+        ))
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn typeck(&self, _params: &[DataType]) -> Result<DataType, TypeError> {
+        Ok(DataType::Value(ValueType::Int))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        _ectx: &mut EvalContext<'b, 'c>,
+        _params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        Ok(RuntimeValue::Int(1))
+    }
+}
+
+fn score_engine(version: u32, params: Vec<&'static str>) -> Engine {
+    let mut engine = Engine::new();
+    engine.add_host("score".into(), Box::new(ScoreOp { version, params }));
+    engine
+}
+
+#[test]
+fn test_bundle_host_abi() {
+    let globals: BTreeSet<String> = vec!["main".to_string()].into_iter().collect();
+    let built_for = score_engine(1, vec!["user"]);
+    let bundle = Bundle::new(
+        "main".into(),
+        vec![module("main", "($add ($score 7) 1)", &globals)],
+        OptimizerSettings::default(),
+    )
+    .with_host_abi(&built_for.host_abi());
+    let abi = bundle.host_abi.as_ref().unwrap();
+    assert_eq!(
+        abi.hosts.keys().collect::<Vec<_>>(),
+        vec!["add", "score"],
+        "only the required host functions are recorded"
+    );
+    let bytes = bundle.to_bytes();
+    assert!(built_for.load_bundle(&bytes).is_ok());
+
+    let error = |engine: &Engine| match engine.load_bundle(&bytes) {
+        Err(Error::Type(e @ TypeError::IncompatibleHosts(_))) => e.to_string(),
+        other => panic!("unexpected result: {:?}", other),
+    };
+    assert_eq!(
+        error(&score_engine(2, vec!["user", "weight"])),
+        "incompatible host functions: $score is version 2, but the program was built against version 1; \
+         $score takes (user, weight), but the program was built against (user)"
+    );
+    assert_eq!(
+        error(&Engine::new()),
+        "incompatible host functions: $score is not registered"
+    );
+}
+
+#[test]
+fn test_bundle_certificate() {
+    let globals: BTreeSet<String> = vec!["main".to_string()].into_iter().collect();
+    let mut engine = Engine::new();
+    let bundle = Bundle::new(
+        "main".into(),
+        vec![module("main", "($add 1 2)", &globals)],
+        OptimizerSettings::default(),
+    )
+    .with_host_abi(&engine.host_abi());
+    assert_eq!(bundle.certified_type(), None);
+    let program = engine.load_bundle(&bundle.to_bytes()).unwrap();
+    let ty = engine.check_program(&program).unwrap();
+    let bundle = bundle.with_certificate(&ty);
+    assert_eq!(bundle.certified_type(), Some(&TypeDescription::Int));
+
+    // An ill-typed module with a certificate copied from a good one is
+    // only loaded by an engine that trusts certificates, and only while
+    // the certificate matches.
+    let mut forged = Bundle::new(
+        "main".into(),
+        vec![module("main", "($add 1 true)", &globals)],
+        OptimizerSettings::default(),
+    )
+    .with_host_abi(&engine.host_abi());
+    forged.certificate = bundle.certificate.clone();
+    assert_eq!(forged.certified_type(), None);
+    engine.set_trust_certificates(true);
+    assert!(engine.load_bundle(&forged.to_bytes()).is_err());
+
+    let forged = forged.with_certificate(&ty);
+    assert!(engine.load_bundle(&forged.to_bytes()).is_ok());
+    engine.set_trust_certificates(false);
+    assert!(engine.load_bundle(&forged.to_bytes()).is_err());
+}
+
+/// `$counted`, which counts how often it is typechecked.
+#[derive(Debug)]
+struct CountedOp(Rc<Cell<usize>>);
+
+impl HostFunction for CountedOp {
+    fn typeck(&self, _params: &[DataType]) -> Result<DataType, TypeError> {
+        self.0.set(self.0.get() + 1);
+        Ok(DataType::Value(ValueType::Int))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        _ectx: &mut EvalContext<'b, 'c>,
+        _params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        Ok(RuntimeValue::Int(2))
+    }
+}
+
+#[test]
+fn test_certified_program_is_not_checked() {
+    let globals: BTreeSet<String> = vec!["main".to_string(), "base".to_string()]
+        .into_iter()
+        .collect();
+    let checks = Rc::new(Cell::new(0));
+    let mut engine = Engine::new();
+    engine.add_host("counted".into(), Box::new(CountedOp(checks.clone())));
+    let bundle = Bundle::new(
+        "main".into(),
+        vec![
+            module("main", "($add base ($counted 0))", &globals),
+            module("base", "($add 20 20)", &globals),
+        ],
+        OptimizerSettings::default(),
+    )
+    .with_host_abi(&engine.host_abi())
+    .with_certificate(&DataType::Value(ValueType::Int));
+    let bytes = bundle.to_bytes();
+    let run = |engine: &Engine, program: &Program| {
+        engine
+            .eval_program_with(program, |v, _| Ok(v.to_string()))
+            .unwrap()
+    };
+
+    engine.set_trust_certificates(true);
+    let program = engine.load_bundle(&bytes).unwrap();
+    assert_eq!(
+        program.certified_type(),
+        Some(&DataType::Value(ValueType::Int))
+    );
+    assert_eq!(run(&engine, &program), "42");
+    assert_eq!(run(&engine, &program), "42");
+    assert_eq!(checks.get(), 0);
+
+    // Engines that do not trust the certificate check the program.
+    engine.set_trust_certificates(false);
+    assert_eq!(run(&engine, &program), "42");
+    assert!(checks.get() > 0);
+
+    // Neither do programs changed since they were loaded.
+    engine.set_trust_certificates(true);
+    let mut program = engine.load_bundle(&bytes).unwrap();
+    program.definitions.define(
+        "base".into(),
+        parse_expr_with_globals("($add 0 1)", globals).unwrap(),
+    );
+    assert_eq!(program.certified_type(), None);
+    let before = checks.get();
+    assert_eq!(run(&engine, &program), "3");
+    assert!(checks.get() > before);
+}
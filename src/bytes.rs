@@ -0,0 +1,400 @@
+//! Byte strings, for scripts that handle binary payloads and digests.
+//!
+//! A `bytes` value is written as a hex literal, `0x"00ff"`, or decoded from
+//! base64 with `$base64_decode`. It prints as a hex literal too, so printed
+//! values read back as the same bytes.
+
+use crate::ast::{fmt_bytes_literal, CustomDataType, DataType};
+use crate::builtin::ValueType;
+use crate::corelib::{as_str, str_value, string_type, type_mismatch};
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use sha2::{Digest, Sha256};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::Hasher;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct BytesType;
+
+impl CustomDataType for BytesType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<BytesType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bytes")
+    }
+}
+
+pub fn bytes_type() -> DataType {
+    DataType::Custom(Rc::new(Box::new(BytesType)))
+}
+
+fn is_bytes_type(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Custom(ref inner) => inner.as_any().is::<BytesType>(),
+        DataType::Dynamic => true,
+        _ => false,
+    }
+}
+
+/// A byte string, ordered lexicographically. Cloning shares the contents.
+#[derive(Debug, Clone)]
+pub struct Bytes(Rc<[u8]>);
+
+impl Bytes {
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl CustomValue for Bytes {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn value_eq<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<bool>, RuntimeError> {
+        Ok(other
+            .as_any()
+            .downcast_ref::<Bytes>()
+            .map(|o| self.0 == o.0))
+    }
+
+    fn compare<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<Ordering>, RuntimeError> {
+        Ok(other
+            .as_any()
+            .downcast_ref::<Bytes>()
+            .map(|o| self.0.cmp(&o.0)))
+    }
+
+    fn hash<'b, 'c>(
+        &self,
+        state: &mut dyn Hasher,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<bool, RuntimeError> {
+        state.write_usize(self.0.len());
+        state.write(&self.0);
+        Ok(true)
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_bytes_literal(&self.0, f)
+    }
+}
+
+/// Wraps `b` as a runtime value of type `bytes`.
+pub fn bytes_value<'b>(b: &[u8]) -> RuntimeValue<'b> {
+    RuntimeValue::Custom(CustomValueBox::new(Box::new(Bytes(b.into()))))
+}
+
+/// Returns the contents of `v` if it is a byte string.
+pub fn as_bytes<'a>(v: &'a RuntimeValue) -> Option<&'a [u8]> {
+    match *v {
+        RuntimeValue::Custom(ref cv) => cv
+            .inner
+            .as_any()
+            .downcast_ref::<Bytes>()
+            .map(|b| b.as_slice()),
+        _ => None,
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `data` as standard base64, with padding.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes standard base64. Padding is optional, but if present must be
+/// complete.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    let unpadded = match text.iter().position(|&c| c == b'=') {
+        Some(i) => {
+            if !text.len().is_multiple_of(4)
+                || text[i..].iter().any(|&c| c != b'=')
+                || text.len() - i > 2
+            {
+                return None;
+            }
+            &text[..i]
+        }
+        None => text,
+    };
+    if unpadded.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(unpadded.len() / 4 * 3 + 2);
+    for chunk in unpadded.chunks(4) {
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let v = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+            n |= v << (18 - 6 * i);
+        }
+        out.extend_from_slice(&n.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(out)
+}
+
+fn eval_bytes<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: LazyValue<'b>,
+) -> Result<Rc<[u8]>, RuntimeError> {
+    match v.eval(ectx)? {
+        RuntimeValue::Custom(ref cv) if cv.inner.as_any().is::<Bytes>() => {
+            Ok(cv.inner.as_any().downcast_ref::<Bytes>().unwrap().0.clone())
+        }
+        v => Err(type_mismatch("bytes", &v)),
+    }
+}
+
+fn eval_int<'b, 'c>(ectx: &mut EvalContext<'b, 'c>, v: LazyValue<'b>) -> Result<i64, RuntimeError> {
+    match v.eval(ectx)? {
+        RuntimeValue::Int(i) => Ok(i),
+        v => Err(type_mismatch("int", &v)),
+    }
+}
+
+/// Checks that `params` are bytes, except those at `int_params`, which are
+/// ints.
+fn typeck_params(
+    name: &str,
+    params: &[DataType],
+    int_params: &[usize],
+    ret: DataType,
+) -> Result<DataType, TypeError> {
+    if params.contains(&DataType::Divergent) {
+        return Ok(DataType::Divergent);
+    }
+    for (i, ty) in params.iter().enumerate() {
+        let ok = if int_params.contains(&i) {
+            *ty == DataType::Value(ValueType::Int) || *ty == DataType::Dynamic
+        } else {
+            is_bytes_type(ty)
+        };
+        if !ok {
+            return Err(TypeError::Custom(format!(
+                "unsupported type for {}: {}",
+                name, ty
+            )));
+        }
+    }
+    Ok(ret)
+}
+
+/// `($bytes_len b)`: the number of bytes in `b`.
+#[derive(Debug)]
+pub struct BytesLenOp;
+impl HostFunction for BytesLenOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("b")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        typeck_params("bytes_len", params, &[], DataType::Value(ValueType::Int))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let b = eval_bytes(ectx, params.next().unwrap())?;
+        Ok(RuntimeValue::Int(b.len() as i64))
+    }
+}
+
+/// `($bytes_slice b start end)`: the bytes of `b` from `start` up to, but
+/// not including, `end`.
+#[derive(Debug)]
+pub struct BytesSliceOp;
+impl HostFunction for BytesSliceOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("b"),
+            Param::required("start"),
+            Param::required("end"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        typeck_params("bytes_slice", params, &[1, 2], bytes_type())
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let b = eval_bytes(ectx, params.next().unwrap())?;
+        let start = eval_int(ectx, params.next().unwrap())?;
+        let end = eval_int(ectx, params.next().unwrap())?;
+        if start < 0 || start > end || end > b.len() as i64 {
+            return Err(RuntimeError::Custom(format!(
+                "byte range {}..{} out of bounds for {} bytes",
+                start,
+                end,
+                b.len()
+            )));
+        }
+        Ok(bytes_value(&b[start as usize..end as usize]))
+    }
+}
+
+/// `($bytes_concat a b)`: the bytes of `a` followed by those of `b`.
+#[derive(Debug)]
+pub struct BytesConcatOp;
+impl HostFunction for BytesConcatOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("a"),
+            Param::required("b"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        typeck_params("bytes_concat", params, &[], bytes_type())
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let a = eval_bytes(ectx, params.next().unwrap())?;
+        let b = eval_bytes(ectx, params.next().unwrap())?;
+        Ok(bytes_value(&[&a[..], &b[..]].concat()))
+    }
+}
+
+/// `($base64_encode b)`: `b` as a base64 string.
+#[derive(Debug)]
+pub struct Base64EncodeOp;
+impl HostFunction for Base64EncodeOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("b")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        typeck_params("base64_encode", params, &[], string_type())
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let b = eval_bytes(ectx, params.next().unwrap())?;
+        Ok(str_value(&base64_encode(&b)))
+    }
+}
+
+/// `($base64_decode text)`: the bytes encoded by the base64 string `text`.
+#[derive(Debug)]
+pub struct Base64DecodeOp;
+impl HostFunction for Base64DecodeOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("text")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if *ty == string_type() || *ty == DataType::Dynamic => Ok(bytes_type()),
+            _ => Err(TypeError::Custom("base64_decode expects a string".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let text = params.next().unwrap().eval(ectx)?;
+        let s = as_str(&text).ok_or_else(|| type_mismatch("string", &text))?;
+        base64_decode(s)
+            .map(|b| bytes_value(&b))
+            .ok_or_else(|| RuntimeError::Custom(format!("invalid base64: {}", text)))
+    }
+}
+
+/// `($hash_sha256 x)`: the SHA-256 digest of `x`, bytes or a string, which
+/// is hashed as UTF-8.
+#[derive(Debug)]
+pub struct HashSha256Op;
+impl HostFunction for HashSha256Op {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("x")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if is_bytes_type(ty) || *ty == string_type() => Ok(bytes_type()),
+            _ => Err(TypeError::Custom(
+                "hash_sha256 expects bytes or a string".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let x = params.next().unwrap().eval(ectx)?;
+        let data = match as_bytes(&x) {
+            Some(b) => b,
+            None => as_str(&x)
+                .map(|s| s.as_bytes())
+                .ok_or_else(|| type_mismatch("bytes or string", &x))?,
+        };
+        Ok(bytes_value(&Sha256::digest(data)))
+    }
+}
+
+/// `$bytes_len`, `$bytes_slice`, `$bytes_concat`, `$base64_encode`,
+/// `$base64_decode` and `$hash_sha256`.
+pub fn bytes_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![
+        ("bytes_len", Arc::new(BytesLenOp)),
+        ("bytes_slice", Arc::new(BytesSliceOp)),
+        ("bytes_concat", Arc::new(BytesConcatOp)),
+        ("base64_encode", Arc::new(Base64EncodeOp)),
+        ("base64_decode", Arc::new(Base64DecodeOp)),
+        ("hash_sha256", Arc::new(HashSha256Op)),
+    ]
+}
@@ -0,0 +1,100 @@
+use crate::bytes::{base64_decode, base64_encode};
+use crate::corelib::{HostGroup, Profile};
+use crate::engine::Engine;
+use crate::error::*;
+use crate::typeck::TypeDescription;
+
+#[test]
+fn test_base64() {
+    for (raw, encoded) in &[
+        (&b""[..], ""),
+        (&b"f"[..], "Zg=="),
+        (&b"fo"[..], "Zm8="),
+        (&b"foo"[..], "Zm9v"),
+        (&b"foob"[..], "Zm9vYg=="),
+        (&[0xfb, 0xff][..], "+/8="),
+    ] {
+        assert_eq!(base64_encode(raw), *encoded);
+        assert_eq!(base64_decode(encoded).unwrap(), *raw);
+    }
+    assert_eq!(base64_decode("Zm8").unwrap(), b"fo");
+    for bad in &["Z", "Zm9v=", "Zg=a", "Z===", "Zm9*", "Zg="] {
+        assert!(base64_decode(bad).is_none(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_bytes_ops() {
+    let engine = Engine::new();
+    let eval = |s: &str| engine.eval_str(s).unwrap();
+    assert_eq!(eval(r#"(0x"DEADbeef")"#), r#"0x"deadbeef""#);
+    assert_eq!(eval(r#"($bytes_len 0x"deadbeef")"#), "4");
+    assert_eq!(eval(r#"($bytes_slice 0x"deadbeef" 1 3)"#), r#"0x"adbe""#);
+    assert_eq!(eval(r#"($bytes_slice 0x"deadbeef" 4 4)"#), r#"0x"""#);
+    assert_eq!(
+        eval(r#"($bytes_concat 0x"01" ($base64_decode "AgM="))"#),
+        r#"0x"010203""#
+    );
+    assert_eq!(eval(r#"($base64_encode 0x"010203")"#), r#""AQID""#);
+    assert_eq!(eval(r#"($eq 0x"0102" ($base64_decode "AQI="))"#), "true");
+    assert_eq!(eval(r#"($lt 0x"01ff" 0x"02")"#), "true");
+    assert_eq!(
+        eval(r#"($hash_sha256 "abc")"#),
+        r#"0x"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad""#
+    );
+    assert_eq!(
+        eval(r#"($eq ($hash_sha256 "abc") ($hash_sha256 0x"616263"))"#),
+        "true"
+    );
+    assert_eq!(
+        engine.infer_type(r#"($hash_sha256 0x"")"#).unwrap(),
+        TypeDescription::Bytes
+    );
+    assert!(matches!(
+        engine.eval_str(r#"($bytes_len "abc")"#),
+        Err(Error::Type(_))
+    ));
+    assert!(matches!(
+        engine.eval_str(r#"($eq 0x"61" "a")"#),
+        Err(Error::Type(_))
+    ));
+    match engine.eval_str(r#"($bytes_slice 0x"deadbeef" 2 9)"#) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert_eq!(msg, "byte range 2..9 out of bounds for 4 bytes")
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match engine.eval_str(r#"($base64_decode "not base64")"#) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert_eq!(msg, r#"invalid base64: "not base64""#)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_bytes_quote() {
+    let mut engine = Engine::new();
+    assert_eq!(
+        engine
+            .eval_str(r#"(quote ($bytes_len (unquote 0x"0102")))"#)
+            .unwrap(),
+        r#"(quote ($bytes_len 0x"0102"))"#
+    );
+    engine.allow_host_group(HostGroup::Eval);
+    assert_eq!(
+        engine
+            .eval_str(r#"($eval (quote (unquote ($bytes_concat 0x"01" 0x"02"))) 0x"")"#)
+            .unwrap(),
+        r#"0x"0102""#
+    );
+}
+
+#[test]
+fn test_bytes_profiles() {
+    let mut engine = Engine::new();
+    engine.set_profile(Profile::PureMath);
+    assert!(engine.eval_str(r#"($bytes_len 0x"00")"#).is_err());
+    engine.set_profile(Profile::DataTransform);
+    assert_eq!(engine.eval_str(r#"($bytes_len 0x"00")"#).unwrap(), "1");
+}
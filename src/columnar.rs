@@ -0,0 +1,352 @@
+//! Columnar evaluation: applying a script to a batch of rows a column at a
+//! time instead of walking the tree once per row.
+//!
+//! A script whose body only combines its parameters and constants with the
+//! arithmetic, comparison and `$if` core functions is compiled into a
+//! `Plan`, and each operator runs over whole `Column`s in a tight loop.
+//! `$if` evaluates each branch on the rows that take it only, so a branch
+//! guarding against e.g. division by zero behaves as it does per row.
+//! `Engine::eval_columns` falls back to evaluating row by row for other
+//! scripts.
+
+use crate::ast::*;
+use crate::builtin::ValueType;
+use crate::corelib::{BasicBinop, BasicRelop};
+use crate::error::RuntimeError;
+use crate::eval::OwnedValue;
+use std::cmp::Ordering;
+
+/// The values of one parameter for every row of a batch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Int(Vec<i64>),
+    Float(Vec<f64>),
+    Bool(Vec<bool>),
+}
+
+impl Column {
+    pub fn len(&self) -> usize {
+        match *self {
+            Column::Int(ref v) => v.len(),
+            Column::Float(ref v) => v.len(),
+            Column::Bool(ref v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        match *self {
+            Column::Int(_) => ValueType::Int,
+            Column::Float(_) => ValueType::Float,
+            Column::Bool(_) => ValueType::Bool,
+        }
+    }
+
+    /// The value in row `i`.
+    pub fn get(&self, i: usize) -> OwnedValue {
+        match *self {
+            Column::Int(ref v) => OwnedValue::Int(v[i]),
+            Column::Float(ref v) => OwnedValue::Float(v[i]),
+            Column::Bool(ref v) => OwnedValue::Bool(v[i]),
+        }
+    }
+
+    /// Collects `values`, which must all be of type `ty`.
+    pub fn from_values(ty: ValueType, values: &[OwnedValue]) -> Option<Column> {
+        match ty {
+            ValueType::Int => values
+                .iter()
+                .map(|v| match *v {
+                    OwnedValue::Int(x) => Some(x),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .map(Column::Int),
+            ValueType::Float => values
+                .iter()
+                .map(|v| match *v {
+                    OwnedValue::Float(x) => Some(x),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .map(Column::Float),
+            ValueType::Bool => values
+                .iter()
+                .map(|v| match *v {
+                    OwnedValue::Bool(x) => Some(x),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .map(Column::Bool),
+        }
+    }
+
+    /// A literal of the column's type, for typechecking calls.
+    pub(crate) fn witness(&self) -> Expr {
+        let c = match *self {
+            Column::Int(_) => ConstExpr::Int(0),
+            Column::Float(_) => ConstExpr::Float(0.0),
+            Column::Bool(_) => ConstExpr::Bool(false),
+        };
+        Expr {
+            body: std::rc::Rc::new(ExprBody::Const(c)),
+        }
+    }
+
+    /// The column `c` repeated `len` times.
+    fn repeat(c: &ConstExpr, len: usize) -> Column {
+        match *c {
+            ConstExpr::Int(v) => Column::Int(vec![v; len]),
+            ConstExpr::Float(v) => Column::Float(vec![v; len]),
+            ConstExpr::Bool(v) => Column::Bool(vec![v; len]),
+            _ => unreachable!("bug: non-scalar constant in a plan"),
+        }
+    }
+
+    /// The values in `rows`, in that order.
+    fn gather(&self, rows: &[usize]) -> Column {
+        match *self {
+            Column::Int(ref v) => Column::Int(rows.iter().map(|&i| v[i]).collect()),
+            Column::Float(ref v) => Column::Float(rows.iter().map(|&i| v[i]).collect()),
+            Column::Bool(ref v) => Column::Bool(rows.iter().map(|&i| v[i]).collect()),
+        }
+    }
+
+    fn to_floats(&self) -> Option<Vec<f64>> {
+        match *self {
+            Column::Int(ref v) => Some(v.iter().map(|&x| x as f64).collect()),
+            Column::Float(ref v) => Some(v.clone()),
+            Column::Bool(_) => None,
+        }
+    }
+}
+
+/// A core function a plan can apply to columns.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ColumnOp<'a> {
+    Binop(&'a BasicBinop),
+    Relop(&'a BasicRelop),
+    Eq { negate: bool },
+    If,
+}
+
+/// A script body compiled for columnar evaluation.
+#[derive(Debug)]
+pub(crate) enum Plan<'a> {
+    /// The parameter with this index.
+    Param(usize),
+    Const(ConstExpr),
+    Apply {
+        name: String,
+        op: ColumnOp<'a>,
+        args: Vec<Plan<'a>>,
+    },
+}
+
+/// Compiles `target`, a lambda, if its body only uses what plans support.
+/// `core_op` returns the core function a host function name stands for,
+/// if it is one plans can apply.
+pub(crate) fn compile<'a>(
+    target: &Expr,
+    core_op: &dyn Fn(&str) -> Option<ColumnOp<'a>>,
+) -> Option<Plan<'a>> {
+    match *target.body {
+        ExprBody::Abstract {
+            ref params,
+            body: AbstractBody::Expr(ref body),
+        } => compile_body(body, params, core_op),
+        _ => None,
+    }
+}
+
+fn compile_body<'a>(
+    e: &Expr,
+    params: &[String],
+    core_op: &dyn Fn(&str) -> Option<ColumnOp<'a>>,
+) -> Option<Plan<'a>> {
+    match *e.body {
+        ExprBody::Const(ref c @ (ConstExpr::Int(_) | ConstExpr::Float(_) | ConstExpr::Bool(_))) => {
+            Some(Plan::Const(c.clone()))
+        }
+        // Later parameters shadow earlier ones of the same name.
+        ExprBody::Name(ref name) => params.iter().rposition(|p| p == name).map(Plan::Param),
+        ExprBody::Apply {
+            ref target,
+            params: ref args,
+        } => {
+            let name = match *target.body {
+                ExprBody::Abstract {
+                    ref params,
+                    body: AbstractBody::Host(ref name),
+                } if params.is_empty() => name,
+                _ => return None,
+            };
+            let op = core_op(name)?;
+            let arity = match op {
+                ColumnOp::If => 3,
+                _ => 2,
+            };
+            if args.len() != arity {
+                return None;
+            }
+            let args = args
+                .iter()
+                .map(|x| compile_body(x, params, core_op))
+                .collect::<Option<Vec<_>>>()?;
+            Some(Plan::Apply {
+                name: name.clone(),
+                op,
+                args,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Evaluates `plan` over `inputs`, the columns of its parameters, which
+/// have `len` rows. Non-finite float results fail if `strict_floats` is
+/// set, as in `EvalContext::set_strict_floats`.
+pub(crate) fn eval(
+    plan: &Plan,
+    inputs: &[Column],
+    len: usize,
+    strict_floats: bool,
+) -> Result<Column, RuntimeError> {
+    let (name, op, args) = match *plan {
+        Plan::Param(i) => return Ok(inputs[i].clone()),
+        Plan::Const(ref c) => return Ok(Column::repeat(c, len)),
+        Plan::Apply {
+            ref name,
+            op,
+            ref args,
+        } => (name, op, args),
+    };
+    if let ColumnOp::If = op {
+        return eval_if(args, inputs, len, strict_floats);
+    }
+    let a = eval(&args[0], inputs, len, strict_floats)?;
+    let b = eval(&args[1], inputs, len, strict_floats)?;
+    match op {
+        ColumnOp::Binop(op) => {
+            let out = match (&a, &b) {
+                (Column::Int(a), Column::Int(b)) => Column::Int(
+                    a.iter()
+                        .zip(b)
+                        .map(|(&x, &y)| (op.int_op)(x, y))
+                        .collect::<Result<_, _>>()?,
+                ),
+                _ => match (a.to_floats(), b.to_floats()) {
+                    (Some(a), Some(b)) => {
+                        let out: Vec<f64> = a
+                            .iter()
+                            .zip(&b)
+                            .map(|(&x, &y)| (op.float_op)(x, y))
+                            .collect::<Result<_, _>>()?;
+                        if strict_floats && out.iter().any(|x| !x.is_finite()) {
+                            return Err(RuntimeError::NonFiniteFloat { host: name.clone() });
+                        }
+                        Column::Float(out)
+                    }
+                    _ => return Err(mismatch(name, &a, &b)),
+                },
+            };
+            Ok(out)
+        }
+        ColumnOp::Relop(op) => {
+            let out = match (&a, &b) {
+                (Column::Int(a), Column::Int(b)) => a
+                    .iter()
+                    .zip(b)
+                    .map(|(&x, &y)| (op.int_op)(x, y))
+                    .collect::<Result<_, _>>()?,
+                (Column::Bool(a), Column::Bool(b)) => a
+                    .iter()
+                    .zip(b)
+                    .map(|(&x, &y)| (op.bool_op)(x, y))
+                    .collect::<Result<_, _>>()?,
+                _ => match (a.to_floats(), b.to_floats()) {
+                    (Some(a), Some(b)) => a
+                        .iter()
+                        .zip(&b)
+                        .map(|(&x, &y)| (op.float_op)(x, y))
+                        .collect::<Result<_, _>>()?,
+                    _ => return Err(mismatch(name, &a, &b)),
+                },
+            };
+            Ok(Column::Bool(out))
+        }
+        ColumnOp::Eq { negate } => {
+            let out = match (&a, &b) {
+                (Column::Int(a), Column::Int(b)) => {
+                    a.iter().zip(b).map(|(x, y)| (x == y) != negate).collect()
+                }
+                (Column::Bool(a), Column::Bool(b)) => {
+                    a.iter().zip(b).map(|(x, y)| (x == y) != negate).collect()
+                }
+                _ => match (a.to_floats(), b.to_floats()) {
+                    (Some(a), Some(b)) => a
+                        .iter()
+                        .zip(&b)
+                        .map(|(x, y)| (x.partial_cmp(y) == Some(Ordering::Equal)) != negate)
+                        .collect(),
+                    _ => return Err(mismatch(name, &a, &b)),
+                },
+            };
+            Ok(Column::Bool(out))
+        }
+        ColumnOp::If => unreachable!(),
+    }
+}
+
+/// Evaluates each branch of `$if` on the rows that take it.
+fn eval_if(
+    args: &[Plan],
+    inputs: &[Column],
+    len: usize,
+    strict_floats: bool,
+) -> Result<Column, RuntimeError> {
+    let cond = match eval(&args[0], inputs, len, strict_floats)? {
+        Column::Bool(v) => v,
+        _ => return Err(RuntimeError::TypeMismatch("predicate is not a bool".into())),
+    };
+    let (taken, skipped): (Vec<usize>, Vec<usize>) = (0..len).partition(|&i| cond[i]);
+    let branch = |plan: &Plan, rows: &[usize]| {
+        let inputs: Vec<Column> = inputs.iter().map(|c| c.gather(rows)).collect();
+        eval(plan, &inputs, rows.len(), strict_floats)
+    };
+    let then = branch(&args[1], &taken)?;
+    let otherwise = branch(&args[2], &skipped)?;
+    let mut rows = vec![(false, 0); len];
+    for (j, &i) in taken.iter().enumerate() {
+        rows[i] = (true, j);
+    }
+    for (j, &i) in skipped.iter().enumerate() {
+        rows[i] = (false, j);
+    }
+    Ok(match (&then, &otherwise) {
+        (Column::Int(a), Column::Int(b)) => Column::Int(scatter(&rows, a, b)),
+        (Column::Float(a), Column::Float(b)) => Column::Float(scatter(&rows, a, b)),
+        (Column::Bool(a), Column::Bool(b)) => Column::Bool(scatter(&rows, a, b)),
+        _ => return Err(mismatch("if", &then, &otherwise)),
+    })
+}
+
+/// Merges the results of the branches of `$if`, `rows` telling for each
+/// row which branch it took and its index in that branch's results.
+fn scatter<T: Copy>(rows: &[(bool, usize)], then: &[T], otherwise: &[T]) -> Vec<T> {
+    rows.iter()
+        .map(|&(t, j)| if t { then[j] } else { otherwise[j] })
+        .collect()
+}
+
+fn mismatch(name: &str, a: &Column, b: &Column) -> RuntimeError {
+    RuntimeError::TypeMismatch(format!(
+        "unsupported operands for ${}: {} and {} columns",
+        name,
+        DataType::Value(a.value_type()),
+        DataType::Value(b.value_type())
+    ))
+}
@@ -0,0 +1,108 @@
+use crate::columnar::{compile, Column, ColumnOp};
+use crate::engine::Engine;
+use crate::error::{Error, RuntimeError};
+use crate::eval::OwnedValue;
+use crate::parser::parse_expr;
+
+fn rows(engine: &Engine, script: &str, columns: &[Column]) -> Vec<OwnedValue> {
+    let len = columns[0].len();
+    (0..len)
+        .map(|i| {
+            let args: Vec<OwnedValue> = columns.iter().map(|c| c.get(i)).collect();
+            engine.call(script, &args).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn test_eval_columns_matches_rows() {
+    let engine = Engine::new();
+    let columns = [
+        Column::Int(vec![1, 2, 3, 4]),
+        Column::Float(vec![0.5, -1.0, 2.0, 8.0]),
+        Column::Bool(vec![true, false, false, true]),
+    ];
+    let scripts = [
+        r"(\x y _b ($add ($mul x 2) y))",
+        r"(\x y b ($if b ($sub x 1) ($mul x 10)))",
+        r"(\x y b ($and b ($lt y 4)))",
+        r"(\x y _b ($ne x 3))",
+    ];
+    for script in &scripts {
+        let out = engine.eval_columns(*script, &columns).unwrap();
+        let ty = out.value_type();
+        assert_eq!(
+            out,
+            Column::from_values(ty, &rows(&engine, script, &columns)).unwrap(),
+            "{}",
+            script
+        );
+    }
+
+    // Only the rows taking a branch evaluate it.
+    let out = engine
+        .eval_columns(
+            r"(\x d ($if ($eq d 0) 0 ($div x d)))",
+            &[Column::Int(vec![10, 20, 30]), Column::Int(vec![2, 0, 3])],
+        )
+        .unwrap();
+    assert_eq!(out, Column::Int(vec![5, 0, 10]));
+    match engine.eval_columns(
+        r"(\x d ($div x d))",
+        &[Column::Int(vec![10, 20]), Column::Int(vec![2, 0])],
+    ) {
+        Err(Error::Runtime(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_eval_columns_fallback() {
+    let mut engine = Engine::new();
+    engine.define("rate", "(3)").unwrap();
+    let columns = [Column::Int(vec![1, 2, 3])];
+    assert_eq!(
+        engine
+            .eval_columns(r"(\x ($mul x rate))", &columns)
+            .unwrap(),
+        Column::Int(vec![3, 6, 9])
+    );
+
+    assert!(engine.eval_columns(r"(\x ($list x))", &columns).is_err());
+    assert!(engine
+        .eval_columns(
+            r"(\x y ($add x y))",
+            &[Column::Int(vec![1]), Column::Int(vec![])]
+        )
+        .is_err());
+    assert!(engine.eval_columns(r"(\x (x))", &[]).is_err());
+}
+
+#[test]
+fn test_eval_columns_strict_floats() {
+    let mut engine = Engine::new();
+    engine.set_strict_floats(true);
+    match engine.eval_columns(r"(\x ($div x 0.0))", &[Column::Float(vec![1.0, 2.0])]) {
+        Err(Error::Runtime(RuntimeError::NonFiniteFloat { .. })) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_compile() {
+    let core_op = |name: &str| match name {
+        "if" => Some(ColumnOp::If),
+        "eq" => Some(ColumnOp::Eq { negate: false }),
+        _ => None,
+    };
+    let e = parse_expr(r"(\x y ($if ($eq x y) x y))").unwrap();
+    assert!(compile(&e, &core_op).is_some());
+
+    // Hosts the embedder has replaced are not compiled.
+    let e = parse_expr(r"(\x y ($add x y))").unwrap();
+    assert!(compile(&e, &core_op).is_none());
+    let e = parse_expr(r"(\x ($if x))").unwrap();
+    assert!(compile(&e, &core_op).is_none());
+    let e = parse_expr("(1)").unwrap();
+    assert!(compile(&e, &core_op).is_none());
+}
@@ -0,0 +1,255 @@
+//! Deployment configuration and environment variables for scripts.
+//!
+//! The embedder puts a `HostConfig` into the host state, e.g. with
+//! `engine.host_state_mut().insert(config)`, and scripts read its entries
+//! with `($config key default)`. The default gives the entry its type, so a
+//! script is checked against the shape of its configuration before it runs;
+//! it is also the value of entries the configuration does not set.
+//!
+//! `($getenv name default)` reads an environment variable of the process
+//! instead, which may hold a secret. It is in its own group,
+//! `HostGroup::Env`, which no profile includes, and reads only the
+//! variables the `HostConfig` allows with `HostConfig::allow_env`.
+
+use crate::ast::DataType;
+use crate::corelib::{as_str, str_value, string_type, type_mismatch};
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// A configuration entry. Deserializes from the matching JSON scalar.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ConfigValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigValue::Bool(v) => write!(f, "{}", v),
+            ConfigValue::Int(v) => write!(f, "{}", v),
+            ConfigValue::Float(v) => write!(f, "{}", v),
+            ConfigValue::Str(ref v) => write!(f, "{:?}", v),
+        }
+    }
+}
+
+impl From<bool> for ConfigValue {
+    fn from(v: bool) -> ConfigValue {
+        ConfigValue::Bool(v)
+    }
+}
+
+impl From<i64> for ConfigValue {
+    fn from(v: i64) -> ConfigValue {
+        ConfigValue::Int(v)
+    }
+}
+
+impl From<f64> for ConfigValue {
+    fn from(v: f64) -> ConfigValue {
+        ConfigValue::Float(v)
+    }
+}
+
+impl From<&str> for ConfigValue {
+    fn from(v: &str) -> ConfigValue {
+        ConfigValue::Str(v.to_string())
+    }
+}
+
+impl From<String> for ConfigValue {
+    fn from(v: String) -> ConfigValue {
+        ConfigValue::Str(v)
+    }
+}
+
+/// Configuration entries `$config` reads, by key, and the environment
+/// variables `$getenv` may read. Deserializes from a JSON object of
+/// scalars, the entries; variables are only allowed in code.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct HostConfig {
+    values: BTreeMap<String, ConfigValue>,
+    #[serde(skip)]
+    env: BTreeSet<String>,
+}
+
+impl HostConfig {
+    pub fn new() -> HostConfig {
+        HostConfig::default()
+    }
+
+    /// Sets `key` to `value`, returning the previous value.
+    pub fn set<V: Into<ConfigValue>>(&mut self, key: &str, value: V) -> Option<ConfigValue> {
+        self.values.insert(key.to_string(), value.into())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&ConfigValue> {
+        self.values.get(key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<ConfigValue> {
+        self.values.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Lets `$getenv` read the environment variable `name`.
+    pub fn allow_env(&mut self, name: &str) {
+        self.env.insert(name.to_string());
+    }
+
+    pub fn allows_env(&self, name: &str) -> bool {
+        self.env.contains(name)
+    }
+}
+
+fn is_string_type(ty: &DataType) -> bool {
+    *ty == string_type() || *ty == DataType::Dynamic
+}
+
+fn eval_string<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: LazyValue<'b>,
+) -> Result<String, RuntimeError> {
+    let v = v.eval(ectx)?;
+    match as_str(&v) {
+        Some(s) => Ok(s.to_string()),
+        None => Err(type_mismatch("string", &v)),
+    }
+}
+
+/// `($config key default)`: the configuration entry `key`, or `default` if
+/// it is not set. `default` is an int, float, bool or string, and the entry
+/// must be of the same type, except that int entries are accepted for
+/// float defaults.
+#[derive(Debug)]
+pub struct ConfigOp;
+impl HostFunction for ConfigOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("key"),
+            Param::required("default"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent, _] | [_, DataType::Divergent] => Ok(DataType::Divergent),
+            [ref key, _] if !is_string_type(key) => Err(TypeError::Custom(format!(
+                "config key must be a string, found {}",
+                key
+            ))),
+            [_, ref default @ DataType::Value(_)] | [_, ref default @ DataType::Dynamic] => {
+                Ok(default.clone())
+            }
+            [_, ref default] if *default == string_type() => Ok(default.clone()),
+            [_, ref default] => Err(TypeError::Custom(format!(
+                "config default must be an int, float, bool or string, found {}",
+                default
+            ))),
+            _ => Err(TypeError::Custom(
+                "config expects a key and a default".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let key = eval_string(ectx, params.next().unwrap())?;
+        let default = params.next().unwrap().eval(ectx)?;
+        let entry = match ectx.host_state::<HostConfig>().and_then(|c| c.get(&key)) {
+            Some(entry) => entry.clone(),
+            None => return Ok(default),
+        };
+        let value = match (entry, &default) {
+            (ConfigValue::Bool(v), RuntimeValue::Bool(_)) => RuntimeValue::Bool(v),
+            (ConfigValue::Int(v), RuntimeValue::Int(_)) => RuntimeValue::Int(v),
+            (ConfigValue::Float(v), RuntimeValue::Float(_)) => RuntimeValue::Float(v),
+            (ConfigValue::Int(v), RuntimeValue::Float(_)) => RuntimeValue::Float(v as f64),
+            (ConfigValue::Str(ref v), d) if as_str(d).is_some() => str_value(v),
+            (entry, d) => {
+                return Err(RuntimeError::Custom(format!(
+                    "config `{}` holds {}, but its default is {}",
+                    key, entry, d
+                )))
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// `($getenv name default)`: the environment variable `name`, or `default`
+/// if it is not set or not valid UTF-8. Fails unless the `HostConfig`
+/// allows `name`.
+#[derive(Debug)]
+pub struct GetenvOp;
+impl HostFunction for GetenvOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("name"),
+            Param::required("default"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        match params.iter().find(|ty| !is_string_type(ty)) {
+            Some(ty) => Err(TypeError::Custom(format!(
+                "getenv expects strings, found {}",
+                ty
+            ))),
+            None => Ok(string_type()),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let name = eval_string(ectx, params.next().unwrap())?;
+        if !ectx
+            .host_state::<HostConfig>()
+            .is_some_and(|c| c.allows_env(&name))
+        {
+            return Err(RuntimeError::Custom(format!(
+                "reading the environment variable `{}` is not allowed",
+                name
+            )));
+        }
+        match std::env::var(&name) {
+            Ok(v) => Ok(str_value(&v)),
+            Err(_) => params.next().unwrap().eval(ectx),
+        }
+    }
+}
+
+/// `$config`.
+pub fn config_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![("config", Arc::new(ConfigOp))]
+}
+
+/// `$getenv`.
+pub fn env_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![("getenv", Arc::new(GetenvOp))]
+}
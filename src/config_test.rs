@@ -0,0 +1,109 @@
+use crate::config::{ConfigValue, HostConfig};
+use crate::corelib::{HostGroup, Profile};
+use crate::engine::Engine;
+use crate::error::*;
+use crate::typeck::TypeDescription;
+
+fn configured_engine() -> Engine {
+    let mut engine = Engine::new();
+    let mut config = HostConfig::new();
+    config.set("max_items", 20);
+    config.set("ratio", 2);
+    config.set("region", "eu-west");
+    config.set("strict", true);
+    engine.host_state_mut().insert(config);
+    engine
+}
+
+#[test]
+fn test_config_values() {
+    let engine = configured_engine();
+    let eval = |s: &str| engine.eval_str(s).unwrap();
+    assert_eq!(eval(r#"($add ($config "max_items" 10) 1)"#), "21");
+    assert_eq!(eval(r#"($config "ratio" 0.5)"#), "2.0");
+    assert_eq!(eval(r#"($config "region" "us-east")"#), r#""eu-west""#);
+    assert_eq!(eval(r#"($config "strict" false)"#), "true");
+    assert_eq!(eval(r#"($config "missing" 7)"#), "7");
+    assert_eq!(
+        engine.infer_type(r#"($config "ratio" 0.5)"#).unwrap(),
+        TypeDescription::Float
+    );
+    match engine.eval_str(r#"($config "region" 1)"#) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert_eq!(
+                msg,
+                r#"config `region` holds "eu-west", but its default is 1"#
+            )
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(matches!(
+        engine.eval_str(r#"($config "xs" ~)"#),
+        Err(Error::Type(_))
+    ));
+    assert!(matches!(
+        engine.eval_str(r#"($config 1 1)"#),
+        Err(Error::Type(_))
+    ));
+
+    // Without a `HostConfig`, every entry has its default.
+    assert_eq!(
+        Engine::new()
+            .eval_str(r#"($config "max_items" 10)"#)
+            .unwrap(),
+        "10"
+    );
+}
+
+#[test]
+fn test_config_deserialize() {
+    let config: HostConfig =
+        serde_json::from_str(r#"{"a": 1, "b": 1.5, "c": "x", "d": false}"#).unwrap();
+    assert_eq!(config.len(), 4);
+    assert_eq!(config.get("a"), Some(&ConfigValue::Int(1)));
+    assert_eq!(config.get("b"), Some(&ConfigValue::Float(1.5)));
+    assert_eq!(config.get("c"), Some(&ConfigValue::Str("x".into())));
+    assert_eq!(config.get("d"), Some(&ConfigValue::Bool(false)));
+}
+
+#[test]
+fn test_getenv() {
+    std::env::set_var("XLANG_CONFIG_TEST_VAR", "from-env");
+    let mut engine = Engine::new();
+    assert!(matches!(
+        engine.eval_str(r#"($getenv "XLANG_CONFIG_TEST_VAR" "")"#),
+        Err(Error::Type(_))
+    ));
+
+    engine.allow_host_group(HostGroup::Env);
+    assert!(matches!(
+        engine.eval_str(r#"($getenv "XLANG_CONFIG_TEST_VAR" "")"#),
+        Err(Error::Runtime(_))
+    ));
+    let mut config = HostConfig::new();
+    config.allow_env("XLANG_CONFIG_TEST_VAR");
+    config.allow_env("XLANG_CONFIG_TEST_UNSET");
+    engine.host_state_mut().insert(config);
+    assert_eq!(
+        engine
+            .eval_str(r#"($getenv "XLANG_CONFIG_TEST_VAR" "")"#)
+            .unwrap(),
+        r#""from-env""#
+    );
+    assert_eq!(
+        engine
+            .eval_str(r#"($getenv "XLANG_CONFIG_TEST_UNSET" "fallback")"#)
+            .unwrap(),
+        r#""fallback""#
+    );
+    assert!(matches!(
+        engine.eval_str(r#"($getenv "XLANG_CONFIG_TEST_VAR" 0)"#),
+        Err(Error::Type(_))
+    ));
+
+    engine.set_profile(Profile::DataTransform);
+    assert!(engine
+        .eval_str(r#"($getenv "XLANG_CONFIG_TEST_VAR" "")"#)
+        .is_err());
+    assert_eq!(engine.eval_str(r#"($config "a" 1)"#).unwrap(), "1");
+}
@@ -1,24 +1,58 @@
 use crate::ast::*;
 use crate::builtin::*;
+use crate::bytes::{as_bytes, bytes_ops, Bytes, BytesType};
+use crate::config::{config_ops, env_ops};
+use crate::datetime::{clock_ops, time_ops};
+use crate::decimal::*;
 use crate::error::*;
 use crate::eval::*;
-use crate::host::HostFunction;
+use crate::host::{HostAbi, HostFunction, HostInfo, Param, Signature};
+use crate::json::{as_json, json_ops};
+use crate::log::log_ops;
+use crate::typeck::{check_expr, TypeDescription};
 use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::hash::Hasher;
 use std::rc::Rc;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct BasicRelop {
     pub int_op: fn(a: i64, b: i64) -> Result<bool, RuntimeError>,
     pub float_op: fn(a: f64, b: f64) -> Result<bool, RuntimeError>,
     pub bool_op: fn(a: bool, b: bool) -> Result<bool, RuntimeError>,
+    /// Applied to the ordering of two lists or custom values. Operators
+    /// without one only accept ints, floats and bools.
+    pub ordering_op: Option<fn(o: Ordering) -> bool>,
+    /// Whether an int may be compared with a float.
+    pub coercion: NumericCoercion,
 }
 
 impl HostFunction for BasicRelop {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("lhs"),
+            Param::required("rhs"),
+        ]))
+    }
+
+    fn converts_int_to_float(&self, params: &[DataType]) -> bool {
+        self.coercion.converts(params)
+    }
+
     fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
         if params.len() == 2 {
             if params[0] == DataType::Divergent || params[1] == DataType::Divergent {
                 return Ok(DataType::Divergent);
             }
+            if params.contains(&DataType::Dynamic) {
+                // The operands are checked when they are compared.
+                return Ok(DataType::Value(ValueType::Bool));
+            }
+            self.coercion.check_types(&params[0], &params[1])?;
 
             match (&params[0], &params[1]) {
                 (&DataType::Value(ValueType::Int), &DataType::Value(ValueType::Int))
@@ -28,9 +62,20 @@ impl HostFunction for BasicRelop {
                 | (&DataType::Value(ValueType::Bool), &DataType::Value(ValueType::Bool)) => {
                     Ok(DataType::Value(ValueType::Bool))
                 }
-                x => Err(TypeError::Custom(format!(
-                    "unsupported types for rel operator: {:?}",
-                    x
+                (a, b) if self.ordering_op.is_some() && decimal_operands(a, b) => {
+                    Ok(DataType::Value(ValueType::Bool))
+                }
+                (a, b)
+                    if self.ordering_op.is_some()
+                        && is_composite_type(a)
+                        && is_composite_type(b)
+                        && same_comparable_type(a, b) =>
+                {
+                    Ok(DataType::Value(ValueType::Bool))
+                }
+                (a, b) => Err(TypeError::Custom(format!(
+                    "unsupported types for rel operator: {} and {}",
+                    a, b
                 ))),
             }
         } else {
@@ -43,10 +88,11 @@ impl HostFunction for BasicRelop {
     fn eval<'b, 'c>(
         &self,
         ectx: &mut EvalContext<'b, 'c>,
-        params: &mut Iterator<Item = LazyValue<'b>>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
     ) -> Result<RuntimeValue<'b>, RuntimeError> {
         let left = params.next().unwrap().eval(ectx)?;
         let right = params.next().unwrap().eval(ectx)?;
+        self.coercion.check_values(&left, &right)?;
         Ok(match (left, right) {
             (RuntimeValue::Int(a), RuntimeValue::Int(b)) => {
                 RuntimeValue::Bool((self.int_op)(a, b)?)
@@ -63,30 +109,60 @@ impl HostFunction for BasicRelop {
             (RuntimeValue::Bool(a), RuntimeValue::Bool(b)) => {
                 RuntimeValue::Bool((self.bool_op)(a, b)?)
             }
-            _ => unreachable!(),
+            (a, b) => match self.ordering_op {
+                Some(op) => RuntimeValue::Bool(values_cmp(&a, &b, ectx)?.is_some_and(op)),
+                None => {
+                    return Err(RuntimeError::TypeMismatch(format!(
+                        "unsupported operands for rel operator: {} and {}",
+                        a, b
+                    )))
+                }
+            },
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct BasicBinop {
     pub int_op: fn(a: i64, b: i64) -> Result<i64, RuntimeError>,
     pub float_op: fn(a: f64, b: f64) -> Result<f64, RuntimeError>,
+    /// Applied when either operand is a decimal and the other is a decimal
+    /// or an int.
+    pub decimal_op: fn(a: Decimal, b: Decimal) -> Result<Decimal, RuntimeError>,
+    /// Whether an int may be combined with a float.
+    pub coercion: NumericCoercion,
 }
 
 impl HostFunction for BasicBinop {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("lhs"),
+            Param::required("rhs"),
+        ]))
+    }
+
+    fn converts_int_to_float(&self, params: &[DataType]) -> bool {
+        self.coercion.converts(params)
+    }
+
     fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
         if params.len() == 2 {
             if params[0] == DataType::Divergent || params[1] == DataType::Divergent {
                 return Ok(DataType::Divergent);
             }
+            if params.contains(&DataType::Dynamic) {
+                // Whether the result is an int or a float depends on the
+                // operands' runtime types.
+                return Ok(DataType::Dynamic);
+            }
+            self.coercion.check_types(&params[0], &params[1])?;
 
             match (&params[0], &params[1]) {
                 (&DataType::Value(ValueType::Int), &DataType::Value(ValueType::Int)) => {
                     Ok(DataType::Value(ValueType::Int))
                 }
                 (&DataType::Value(ValueType::Int), &DataType::Value(ValueType::Float)) => {
-                    Ok(DataType::Value(ValueType::Int))
+                    Ok(DataType::Value(ValueType::Float))
                 }
                 (&DataType::Value(ValueType::Float), &DataType::Value(ValueType::Int)) => {
                     Ok(DataType::Value(ValueType::Float))
@@ -94,9 +170,16 @@ impl HostFunction for BasicBinop {
                 (&DataType::Value(ValueType::Float), &DataType::Value(ValueType::Float)) => {
                     Ok(DataType::Value(ValueType::Float))
                 }
-                x => Err(TypeError::Custom(format!(
-                    "unsupported types for binary operator: {:?}",
-                    x
+                (a, b) if decimal_operands(a, b) => Ok(decimal_type()),
+                (a, b) if is_decimal_type(a) || is_decimal_type(b) => {
+                    Err(TypeError::Custom(format!(
+                        "cannot mix {} and {}: convert with $decimal or $decimal_to_float",
+                        a, b
+                    )))
+                }
+                (a, b) => Err(TypeError::Custom(format!(
+                    "unsupported types for binary operator: {} and {}",
+                    a, b
                 ))),
             }
         } else {
@@ -109,10 +192,11 @@ impl HostFunction for BasicBinop {
     fn eval<'b, 'c>(
         &self,
         ectx: &mut EvalContext<'b, 'c>,
-        params: &mut Iterator<Item = LazyValue<'b>>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
     ) -> Result<RuntimeValue<'b>, RuntimeError> {
         let left = params.next().unwrap().eval(ectx)?;
         let right = params.next().unwrap().eval(ectx)?;
+        self.coercion.check_values(&left, &right)?;
         Ok(match (left, right) {
             (RuntimeValue::Int(a), RuntimeValue::Int(b)) => RuntimeValue::Int((self.int_op)(a, b)?),
             (RuntimeValue::Int(a), RuntimeValue::Float(b)) => {
@@ -124,20 +208,240 @@ impl HostFunction for BasicBinop {
             (RuntimeValue::Float(a), RuntimeValue::Float(b)) => {
                 RuntimeValue::Float((self.float_op)(a, b)?)
             }
-            _ => unreachable!(),
+            (a, b) => match (as_decimal(&a), as_decimal(&b)) {
+                (Some(a), Some(b)) => decimal_value((self.decimal_op)(a, b)?),
+                _ => {
+                    return Err(RuntimeError::TypeMismatch(format!(
+                        "unsupported operands for binary operator: {} and {}",
+                        a, b
+                    )))
+                }
+            },
         })
     }
 }
 
+/// `$eq` and `$ne`: structural equality on values of the same type.
+///
+/// Ints and floats compare with each other numerically. Lists compare
+/// element by element, and `~` equals only the empty list. Other custom
+/// values are compared through `CustomValue::value_eq`.
+#[derive(Debug)]
+pub struct EqOp {
+    pub negate: bool,
+    /// Whether an int may be compared with a float.
+    pub coercion: NumericCoercion,
+}
+
+/// Whether values of type `ty` can be compared by `EqOp`.
+fn is_comparable_type(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Empty | DataType::Value(_) | DataType::Dynamic => true,
+        DataType::Custom(ref inner) => match inner.as_any().downcast_ref::<ListType>() {
+            Some(list) => is_comparable_type(&list.inner_ty),
+            None => true,
+        },
+        DataType::FunctionDecl { .. } | DataType::Divergent => false,
+    }
+}
+
+/// Lists, `~` and custom values, which the ordering operators compare with
+/// `values_cmp`.
+fn is_composite_type(ty: &DataType) -> bool {
+    matches!(*ty, DataType::Empty | DataType::Custom(_))
+}
+
+/// Whether `a` and `b` are the same type (or can be compared as if they
+/// were) and values of that type can be compared at all.
+fn same_comparable_type(a: &DataType, b: &DataType) -> bool {
+    let numeric = |ty: &DataType| {
+        *ty == DataType::Value(ValueType::Int) || *ty == DataType::Value(ValueType::Float)
+    };
+    let same_type = a == b
+        || *a == DataType::Dynamic
+        || *b == DataType::Dynamic
+        || (numeric(a) && numeric(b))
+        || decimal_operands(a, b)
+        // `~` is the empty list.
+        || (*a == DataType::Empty && is_list_type(b))
+        || (*b == DataType::Empty && is_list_type(a));
+    same_type && is_comparable_type(a) && is_comparable_type(b)
+}
+
+/// Structural equality as used by `$eq`. Both values must have the same
+/// comparable type.
+pub fn values_eq<'b, 'c>(
+    a: &RuntimeValue<'b>,
+    b: &RuntimeValue<'b>,
+    ectx: &mut EvalContext<'b, 'c>,
+) -> Result<bool, RuntimeError> {
+    Ok(match (a, b) {
+        (RuntimeValue::Custom(a), RuntimeValue::Custom(b)) => {
+            if let Some(eq) = a.inner.value_eq(&**b.inner, ectx)? {
+                eq
+            } else if let Some(o) = a.inner.compare(&**b.inner, ectx)? {
+                o == Ordering::Equal
+            } else {
+                return Err(RuntimeError::Custom(format!(
+                    "values do not support equality: {}",
+                    RuntimeValue::Custom(a.clone())
+                )));
+            }
+        }
+        _ => values_cmp(a, b, ectx)? == Some(Ordering::Equal),
+    })
+}
+
+/// Structural ordering as used by the ordering operators. Lists are ordered
+/// lexicographically, with `~` first. Returns `None` for unordered values
+/// such as NaN.
+pub fn values_cmp<'b, 'c>(
+    a: &RuntimeValue<'b>,
+    b: &RuntimeValue<'b>,
+    ectx: &mut EvalContext<'b, 'c>,
+) -> Result<Option<Ordering>, RuntimeError> {
+    Ok(match (a, b) {
+        (&RuntimeValue::Empty, &RuntimeValue::Empty) => Some(Ordering::Equal),
+        (&RuntimeValue::Empty, &RuntimeValue::Custom(_)) => Some(Ordering::Less),
+        (&RuntimeValue::Custom(_), &RuntimeValue::Empty) => Some(Ordering::Greater),
+        (&RuntimeValue::Int(a), &RuntimeValue::Int(b)) => Some(a.cmp(&b)),
+        (&RuntimeValue::Int(a), &RuntimeValue::Float(b)) => (a as f64).partial_cmp(&b),
+        (&RuntimeValue::Float(a), &RuntimeValue::Int(b)) => a.partial_cmp(&(b as f64)),
+        (&RuntimeValue::Float(a), &RuntimeValue::Float(b)) => a.partial_cmp(&b),
+        (&RuntimeValue::Bool(a), &RuntimeValue::Bool(b)) => Some(a.cmp(&b)),
+        (&RuntimeValue::Int(_), &RuntimeValue::Custom(_))
+        | (&RuntimeValue::Custom(_), &RuntimeValue::Int(_)) => match (as_decimal(a), as_decimal(b))
+        {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => {
+                return Err(RuntimeError::TypeMismatch(format!(
+                    "cannot compare {} and {}",
+                    a, b
+                )))
+            }
+        },
+        (RuntimeValue::Custom(a), RuntimeValue::Custom(b)) => {
+            match a.inner.compare(&**b.inner, ectx)? {
+                Some(o) => Some(o),
+                None => {
+                    return Err(RuntimeError::Custom(format!(
+                        "values are not ordered: {}",
+                        RuntimeValue::Custom(a.clone())
+                    )))
+                }
+            }
+        }
+        (a, b) => {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "cannot compare {} and {}",
+                a, b
+            )))
+        }
+    })
+}
+
+/// Hashes `v` into `state` consistently with `values_eq`: equal values,
+/// including an int and a float of the same value, hash the same.
+pub fn value_hash<'b, 'c>(
+    v: &RuntimeValue<'b>,
+    state: &mut dyn Hasher,
+    ectx: &mut EvalContext<'b, 'c>,
+) -> Result<(), RuntimeError> {
+    match *v {
+        // `~` hashes like the end of a list.
+        RuntimeValue::Empty => state.write_u8(0),
+        RuntimeValue::Int(v) => {
+            state.write_u8(1);
+            state.write_i64(v);
+        }
+        RuntimeValue::Float(v) => {
+            state.write_u8(1);
+            if v.fract() == 0.0 && v >= i64::MIN as f64 && v < i64::MAX as f64 {
+                state.write_i64(v as i64);
+            } else {
+                state.write_u64(v.to_bits());
+            }
+        }
+        RuntimeValue::Bool(v) => {
+            state.write_u8(2);
+            state.write_u8(v as u8);
+        }
+        RuntimeValue::Custom(ref cv) => {
+            if !cv.inner.hash(state, ectx)? {
+                return Err(RuntimeError::Custom(format!(
+                    "value cannot be hashed: {}",
+                    v
+                )));
+            }
+        }
+        _ => return Err(RuntimeError::Custom("functions cannot be hashed".into())),
+    }
+    Ok(())
+}
+
+impl HostFunction for EqOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("lhs"),
+            Param::required("rhs"),
+        ]))
+    }
+
+    fn converts_int_to_float(&self, params: &[DataType]) -> bool {
+        self.coercion.converts(params)
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.len() != 2 {
+            return Err(TypeError::Custom(
+                "invalid param count for equality operator".into(),
+            ));
+        }
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        self.coercion.check_types(&params[0], &params[1])?;
+
+        if same_comparable_type(&params[0], &params[1]) {
+            Ok(DataType::Value(ValueType::Bool))
+        } else {
+            Err(TypeError::Custom(format!(
+                "cannot compare values of types {} and {}",
+                params[0], params[1]
+            )))
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let left = params.next().unwrap().eval(ectx)?;
+        let right = params.next().unwrap().eval(ectx)?;
+        self.coercion.check_values(&left, &right)?;
+        let eq = values_eq(&left, &right, ectx)?;
+        Ok(RuntimeValue::Bool(eq != self.negate))
+    }
+}
+
 #[derive(Debug)]
 pub struct IfOp;
 impl HostFunction for IfOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("cond"),
+            Param::required("then"),
+            Param::required("else"),
+        ]))
+    }
+
     fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
         if params.len() == 3 {
             if params[0] == DataType::Divergent {
                 Ok(DataType::Divergent)
             } else {
-                if params[0] != DataType::Value(ValueType::Bool) {
+                if params[0] != DataType::Value(ValueType::Bool) && params[0] != DataType::Dynamic {
                     return Err(TypeError::Custom(
                         "if predicate must be of bool type".into(),
                     ));
@@ -145,10 +449,15 @@ impl HostFunction for IfOp {
 
                 if params[1] == DataType::Divergent {
                     Ok(params[2].clone())
-                } else if params[2] == DataType::Divergent {
+                } else if params[2] == DataType::Divergent || params[1] == params[2] {
                     Ok(params[1].clone())
-                } else if params[1] == params[2] {
+                } else if params[1] == DataType::Empty && is_list_type(&params[2]) {
+                    // `~` is the empty list.
+                    Ok(params[2].clone())
+                } else if params[2] == DataType::Empty && is_list_type(&params[1]) {
                     Ok(params[1].clone())
+                } else if params[1] == DataType::Dynamic || params[2] == DataType::Dynamic {
+                    Ok(DataType::Dynamic)
                 } else {
                     Err(TypeError::Custom(
                         "invalid operand types for if operator".into(),
@@ -165,12 +474,12 @@ impl HostFunction for IfOp {
     fn eval<'b, 'c>(
         &self,
         ectx: &mut EvalContext<'b, 'c>,
-        params: &mut Iterator<Item = LazyValue<'b>>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
     ) -> Result<RuntimeValue<'b>, RuntimeError> {
         let predicate = if let RuntimeValue::Bool(x) = params.next().unwrap().eval(ectx)? {
             x
         } else {
-            panic!("bug: type mismatch")
+            return Err(RuntimeError::TypeMismatch("predicate is not a bool".into()));
         };
 
         if predicate {
@@ -181,11 +490,276 @@ impl HostFunction for IfOp {
     }
 }
 
+/// `(the ty value)` is parsed into `($the (\$type#ty ($type#ty)) witness
+/// value)`, where the parameter name records how `ty` was written and
+/// `witness` is an expression of type `ty`. Typeck checks that `value` has
+/// that type or is `dyn`; `(the dyn value)` forgets the type of `value`.
+///
+/// At runtime `$the` returns `value` after checking that it has the shape
+/// of the witness, which catches `dyn` values of the wrong type where they
+/// enter typed code. List elements are not checked here; the operations
+/// using them check their types.
+#[derive(Debug)]
+pub struct TheOp;
+impl HostFunction for TheOp {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        let name = match params.first() {
+            Some(DataType::FunctionDecl { ref params, .. }) if params.len() == 1 => {
+                source_name(params[0].trim_start_matches("$type#"))
+            }
+            _ => return Err(TypeError::Custom("expecting a type annotation".into())),
+        };
+        if params.len() != 3 {
+            return Err(TypeError::Custom(
+                "invalid param count for the operator".into(),
+            ));
+        }
+        let (expected, found) = (&params[1], &params[2]);
+        if *found == DataType::Divergent || found == expected {
+            return Ok(found.clone());
+        }
+        if is_consistent(found, expected) {
+            return Ok(expected.clone());
+        }
+        let ty = TypeDescription::of(expected);
+        Err(TypeError::Mismatch {
+            what: "annotated value".into(),
+            expected: if ty.to_string() == name {
+                ty
+            } else {
+                TypeDescription::Named {
+                    name: name.to_string(),
+                    ty: Box::new(ty),
+                }
+            },
+            found: TypeDescription::of(found),
+        })
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let witness = params.nth(1).unwrap().eval(ectx)?;
+        let value = params.next().unwrap().eval(ectx)?;
+        match shape_mismatch(&witness, &value) {
+            Some(expected) => Err(type_mismatch(expected, &value)),
+            None => Ok(value),
+        }
+    }
+}
+
+/// Whether a value of type `found` may be used as one of type `expected`,
+/// checking at runtime where either is `dyn`.
+fn is_consistent(found: &DataType, expected: &DataType) -> bool {
+    let list_inner = |ty: &DataType| match *ty {
+        DataType::Custom(ref inner) => inner
+            .as_any()
+            .downcast_ref::<ListType>()
+            .map(|l| l.inner_ty.clone()),
+        _ => None,
+    };
+    match (found, expected) {
+        _ if found == expected => true,
+        (DataType::Dynamic, _) | (_, DataType::Dynamic) => true,
+        // `~` is the empty list.
+        (DataType::Empty, ty) => is_list_type(ty),
+        _ => match (list_inner(found), list_inner(expected)) {
+            (Some(f), Some(e)) => is_consistent(&f, &e),
+            _ => false,
+        },
+    }
+}
+
+/// Describes what `value` should have been if it does not have the shape of
+/// `witness`, the value of a witness expression built by the parser.
+fn shape_mismatch(witness: &RuntimeValue, value: &RuntimeValue) -> Option<&'static str> {
+    let is_list = |v: &RuntimeValue| match *v {
+        RuntimeValue::Empty => true,
+        RuntimeValue::Custom(ref cv) => cv.inner.as_any().is::<List>(),
+        _ => false,
+    };
+    let expected = match *witness {
+        // The witness of `dyn`.
+        RuntimeValue::Host(name) if name == "dyn" => return None,
+        RuntimeValue::Int(_) => "int",
+        RuntimeValue::Float(_) => "float",
+        RuntimeValue::Bool(_) => "bool",
+        RuntimeValue::Empty => "~",
+        ref w if as_str(w).is_some() => "string",
+        ref w if as_decimal(w).is_some() => "decimal",
+        ref w if as_bytes(w).is_some() => "bytes",
+        RuntimeValue::Custom(_) => "list",
+        _ => return None,
+    };
+    let ok = match (witness, value) {
+        (RuntimeValue::Int(_), RuntimeValue::Int(_))
+        | (RuntimeValue::Float(_), RuntimeValue::Float(_))
+        | (RuntimeValue::Bool(_), RuntimeValue::Bool(_))
+        | (RuntimeValue::Empty, RuntimeValue::Empty) => true,
+        (w, v) if as_str(w).is_some() => as_str(v).is_some(),
+        (w, RuntimeValue::Custom(ref cv)) if as_decimal(w).is_some() => {
+            cv.inner.as_any().is::<Decimal>()
+        }
+        (w, v) if as_bytes(w).is_some() => as_bytes(v).is_some(),
+        (RuntimeValue::Custom(_), v) => is_list(v),
+        _ => false,
+    };
+    if ok {
+        None
+    } else {
+        Some(expected)
+    }
+}
+
+/// `($dyn value)`: `value`, with its type forgotten so that typeck accepts
+/// it wherever any type is expected. Host functions can return `dyn` too,
+/// e.g. for data parsed at runtime.
+#[derive(Debug)]
+pub struct DynOp;
+impl HostFunction for DynOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("value")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [_] => Ok(DataType::Dynamic),
+            _ => Err(TypeError::Custom("expecting exactly 1 param".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        params.next().unwrap().eval(ectx)
+    }
+}
+
+/// The type of string literals.
+#[derive(Debug)]
+pub struct StringType;
+
+impl CustomDataType for StringType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<StringType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "string")
+    }
+}
+
+pub fn string_type() -> DataType {
+    DataType::Custom(Rc::new(Box::new(StringType)))
+}
+
+/// A string, ordered by its bytes. Cloning shares the contents.
+#[derive(Debug, Clone)]
+pub struct Str(Rc<str>);
+
+impl Str {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl CustomValue for Str {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn value_eq<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<bool>, RuntimeError> {
+        Ok(other.as_any().downcast_ref::<Str>().map(|o| self.0 == o.0))
+    }
+
+    fn compare<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<Ordering>, RuntimeError> {
+        Ok(other
+            .as_any()
+            .downcast_ref::<Str>()
+            .map(|o| self.0.cmp(&o.0)))
+    }
+
+    fn hash<'b, 'c>(
+        &self,
+        state: &mut dyn Hasher,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<bool, RuntimeError> {
+        state.write(self.0.as_bytes());
+        state.write_u8(0xff);
+        Ok(true)
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_string_literal(&self.0, f)
+    }
+}
+
+/// Wraps `s` as a runtime value of type `string`.
+pub fn str_value<'b>(s: &str) -> RuntimeValue<'b> {
+    RuntimeValue::Custom(CustomValueBox::new(Box::new(Str(s.into()))))
+}
+
+/// Returns the contents of `v` if it is a string.
+pub fn as_str<'a>(v: &'a RuntimeValue) -> Option<&'a str> {
+    match *v {
+        RuntimeValue::Custom(ref cv) => cv.inner.as_any().downcast_ref::<Str>().map(|s| s.as_str()),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ListType {
     inner_ty: DataType,
 }
 
+impl ListType {
+    pub fn inner_type(&self) -> &DataType {
+        &self.inner_ty
+    }
+}
+
+/// The type of lists of `inner`.
+pub fn list_type(inner: DataType) -> DataType {
+    DataType::Custom(Rc::new(Box::new(ListType { inner_ty: inner })))
+}
+
+pub(crate) fn is_list_type(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Custom(ref inner) => inner.as_any().is::<ListType>(),
+        _ => false,
+    }
+}
+
+/// Error for an argument of the wrong type, which typeck would have
+/// rejected.
+pub(crate) fn type_mismatch(expected: &str, found: &RuntimeValue) -> RuntimeError {
+    RuntimeError::TypeMismatch(format!("expecting {}, found {}", expected, found))
+}
+
+fn as_list(cv: &CustomValueBox) -> Result<&List, RuntimeError> {
+    cv.inner
+        .as_any()
+        .downcast_ref::<List>()
+        .ok_or_else(|| type_mismatch("list", &RuntimeValue::Custom(cv.clone())))
+}
+
 #[derive(Debug, Clone)]
 pub struct List {
     head: Rc<ListNode>,
@@ -193,25 +767,126 @@ pub struct List {
 
 #[derive(Debug)]
 pub struct ListNode {
-    value: SlotRef,
-    pool: SlotReleasePool,
+    value: ScopedSlot,
     next: Option<Rc<ListNode>>,
 }
 
-impl Drop for ListNode {
-    fn drop(&mut self) {
-        self.pool.put(self.value);
+impl List {
+    /// Evaluates and returns the elements of the list, head first.
+    pub fn values<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Vec<RuntimeValue<'b>>, RuntimeError> {
+        let mut out = Vec::new();
+        let mut node = Some(&self.head);
+        while let Some(n) = node {
+            out.push(n.value.read(ectx)?.eval(ectx)?);
+            node = n.next.as_ref();
+        }
+        Ok(out)
+    }
+
+    /// Returns the elements of the list, head first, if they have all
+    /// been evaluated.
+    pub(crate) fn evaluated<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Option<Vec<RuntimeValue<'b>>> {
+        let mut out = Vec::new();
+        let mut node = Some(&self.head);
+        while let Some(n) = node {
+            out.push(n.value.read(ectx).ok()?.outcome()?);
+            node = n.next.as_ref();
+        }
+        Some(out)
+    }
+}
+
+/// Builds a list of `values`, head first, for host functions that return
+/// lists. No values gives `~`.
+pub fn list_value<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    values: Vec<RuntimeValue<'b>>,
+) -> RuntimeValue<'b> {
+    let mut next: Option<Rc<ListNode>> = None;
+    for v in values.into_iter().rev() {
+        next = Some(Rc::new(ListNode {
+            value: ectx.write_scoped_slot(LazyValue::from_value(v)),
+            next,
+        }));
+    }
+    match next {
+        Some(head) => RuntimeValue::Custom(CustomValueBox::new(Box::new(List { head }))),
+        None => RuntimeValue::Empty,
     }
 }
 
 impl CustomValue for List {
-    fn as_any(&self) -> &Any {
+    fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn to_owned_value<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<OwnedValue>, RuntimeError> {
+        let mut out = Vec::new();
+        for v in self.values(ectx)? {
+            out.push(v.into_owned(ectx)?);
+        }
+        Ok(Some(OwnedValue::List(out)))
+    }
+
+    fn compare<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<Ordering>, RuntimeError> {
+        let other = match other.as_any().downcast_ref::<List>() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let mut a = Some(&self.head);
+        let mut b = Some(&other.head);
+        loop {
+            match (a, b) {
+                (None, None) => return Ok(Some(Ordering::Equal)),
+                (None, Some(_)) => return Ok(Some(Ordering::Less)),
+                (Some(_), None) => return Ok(Some(Ordering::Greater)),
+                (Some(x), Some(y)) => {
+                    // Shared tails are equal without looking at them.
+                    if Rc::ptr_eq(x, y) {
+                        return Ok(Some(Ordering::Equal));
+                    }
+                    let xv = x.value.read(ectx)?.eval(ectx)?;
+                    let yv = y.value.read(ectx)?.eval(ectx)?;
+                    match values_cmp(&xv, &yv, ectx)? {
+                        Some(Ordering::Equal) => {}
+                        o => return Ok(o),
+                    }
+                    a = x.next.as_ref();
+                    b = y.next.as_ref();
+                }
+            }
+        }
+    }
+
+    fn hash<'b, 'c>(
+        &self,
+        state: &mut dyn Hasher,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<bool, RuntimeError> {
+        for v in self.values(ectx)? {
+            state.write_u8(3);
+            value_hash(&v, state, ectx)?;
+        }
+        state.write_u8(0);
+        Ok(true)
+    }
 }
 
 impl CustomDataType for ListType {
-    fn cdt_eq(&self, other: &CustomDataType) -> bool {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
         let other = match other.as_any().downcast_ref::<ListType>() {
             Some(v) => v,
             None => return false,
@@ -219,18 +894,26 @@ impl CustomDataType for ListType {
         self.inner_ty == other.inner_ty
     }
 
-    fn as_any(&self) -> &::std::any::Any {
+    fn as_any(&self) -> &dyn ::std::any::Any {
         self
     }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "list<{}>", self.inner_ty)
+    }
 }
 
 #[derive(Debug)]
 pub struct ListHeadOp;
 impl HostFunction for ListHeadOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("list")]))
+    }
+
     fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
         if params.len() == 1 {
-            if params[0] == DataType::Divergent {
-                return Ok(DataType::Divergent);
+            if params[0] == DataType::Divergent || params[0] == DataType::Dynamic {
+                return Ok(params[0].clone());
             }
 
             if let DataType::Custom(ref inner) = params[0] {
@@ -250,16 +933,89 @@ impl HostFunction for ListHeadOp {
     fn eval<'b, 'c>(
         &self,
         ectx: &mut EvalContext<'b, 'c>,
-        params: &mut Iterator<Item = LazyValue<'b>>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let list = params.next().unwrap().eval(ectx)?;
+
+        match list {
+            RuntimeValue::Custom(ref cv) => as_list(cv)?.head.value.read(ectx)?.eval(ectx),
+            RuntimeValue::Empty => Err(RuntimeError::Custom("empty list".into())),
+            v => Err(type_mismatch("list", &v)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ListTailOp;
+impl HostFunction for ListTailOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("list")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.len() == 1 {
+            if params[0] == DataType::Divergent || params[0] == DataType::Dynamic {
+                return Ok(params[0].clone());
+            }
+
+            match params[0] {
+                ref ty if is_list_type(ty) => Ok(ty.clone()),
+                _ => Err(TypeError::Custom("not a list".into())),
+            }
+        } else {
+            Err(TypeError::Custom("invalid param count".into()))
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
     ) -> Result<RuntimeValue<'b>, RuntimeError> {
         let list = params.next().unwrap().eval(ectx)?;
 
         match list {
-            RuntimeValue::Custom(cv) => ectx
-                .read_slot(cv.inner.as_any().downcast_ref::<List>().unwrap().head.value)
-                .eval(ectx),
+            RuntimeValue::Custom(ref cv) => match as_list(cv)?.head.next {
+                Some(ref next) => Ok(RuntimeValue::Custom(CustomValueBox::new(Box::new(List {
+                    head: next.clone(),
+                })))),
+                None => Ok(RuntimeValue::Empty),
+            },
             RuntimeValue::Empty => Err(RuntimeError::Custom("empty list".into())),
-            _ => unreachable!(),
+            v => Err(type_mismatch("list", &v)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ListIsEmptyOp;
+impl HostFunction for ListIsEmptyOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("list")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.len() == 1 {
+            match params[0] {
+                DataType::Divergent => Ok(DataType::Divergent),
+                DataType::Empty | DataType::Dynamic => Ok(DataType::Value(ValueType::Bool)),
+                ref ty if is_list_type(ty) => Ok(DataType::Value(ValueType::Bool)),
+                _ => Err(TypeError::Custom("not a list".into())),
+            }
+        } else {
+            Err(TypeError::Custom("invalid param count".into()))
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Empty => Ok(RuntimeValue::Bool(true)),
+            RuntimeValue::Custom(_) => Ok(RuntimeValue::Bool(false)),
+            v => Err(type_mismatch("list", &v)),
         }
     }
 }
@@ -267,12 +1023,20 @@ impl HostFunction for ListHeadOp {
 #[derive(Debug)]
 pub struct ListPushOp;
 impl HostFunction for ListPushOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("value"),
+            Param::required("list"),
+        ]))
+    }
+
     fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
         if params.len() == 2 {
             if params[0] == DataType::Divergent {
                 return Ok(DataType::Divergent);
             }
             match params[1] {
+                DataType::Dynamic => Ok(DataType::Dynamic),
                 DataType::Empty | DataType::Divergent => {
                     Ok(DataType::Custom(Rc::new(Box::new(ListType {
                         inner_ty: params[0].clone(),
@@ -282,6 +1046,12 @@ impl HostFunction for ListPushOp {
                     if let Some(list) = (**inner).as_any().downcast_ref::<ListType>() {
                         if list.inner_ty == params[0] {
                             Ok(DataType::Custom(Rc::new(Box::new(list.clone()))))
+                        } else if list.inner_ty == DataType::Dynamic
+                            || params[0] == DataType::Dynamic
+                        {
+                            Ok(DataType::Custom(Rc::new(Box::new(ListType {
+                                inner_ty: DataType::Dynamic,
+                            }))))
                         } else {
                             Err(TypeError::Custom("list type mismatch".into()))
                         }
@@ -299,7 +1069,7 @@ impl HostFunction for ListPushOp {
     fn eval<'b, 'c>(
         &self,
         ectx: &mut EvalContext<'b, 'c>,
-        params: &mut Iterator<Item = LazyValue<'b>>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
     ) -> Result<RuntimeValue<'b>, RuntimeError> {
         let val = params.next().unwrap();
         let list = params.next().unwrap().eval(ectx)?;
@@ -307,115 +1077,1242 @@ impl HostFunction for ListPushOp {
         match list {
             RuntimeValue::Empty => Ok(RuntimeValue::Custom(CustomValueBox::new(Box::new(List {
                 head: Rc::new(ListNode {
-                    value: ectx.write_slot(val),
-                    pool: ectx.release_pool.clone(),
+                    value: ectx.write_scoped_slot(val),
                     next: None,
                 }),
             })))),
-            RuntimeValue::Custom(cv) => {
+            RuntimeValue::Custom(ref cv) => {
+                let next = as_list(cv)?.head.clone();
                 Ok(RuntimeValue::Custom(CustomValueBox::new(Box::new(List {
                     head: Rc::new(ListNode {
-                        value: ectx.write_slot(val),
-                        pool: ectx.release_pool.clone(),
-                        next: Some(
-                            cv.inner
-                                .as_any()
-                                .downcast_ref::<List>()
-                                .unwrap()
-                                .head
-                                .clone(),
-                        ),
+                        value: ectx.write_scoped_slot(val),
+                        next: Some(next),
                     }),
                 }))))
             }
-            _ => unreachable!(),
+            v => Err(type_mismatch("list", &v)),
         }
     }
 }
 
-pub struct HostManager {
-    binops: Vec<(&'static str, BasicBinop)>,
-    relops: Vec<(&'static str, BasicRelop)>,
-    ifop: IfOp,
-    list_push_op: ListPushOp,
+/// `(round x digits=0)`: rounds `x` to `digits` decimal places. Decimals
+/// stay decimals; ints and floats are rounded as floats.
+#[derive(Debug)]
+pub struct RoundOp;
+impl HostFunction for RoundOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("x"),
+            Param::optional("digits", ConstExpr::Int(0)),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+
+        match (&params[0], &params[1]) {
+            (&DataType::Value(ValueType::Int), &DataType::Value(ValueType::Int))
+            | (&DataType::Value(ValueType::Float), &DataType::Value(ValueType::Int))
+            | (&DataType::Dynamic, _)
+            | (_, &DataType::Dynamic) => Ok(DataType::Value(ValueType::Float)),
+            (x, &DataType::Value(ValueType::Int)) if is_decimal_type(x) => Ok(decimal_type()),
+            (x, digits) => Err(TypeError::Custom(format!(
+                "unsupported types for round: {} and {}",
+                x, digits
+            ))),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let x = match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Int(v) => v as f64,
+            RuntimeValue::Float(v) => v,
+            ref v if as_decimal(v).is_some() => {
+                let digits = match params.next().unwrap().eval(ectx)? {
+                    RuntimeValue::Int(v) => v,
+                    v => return Err(type_mismatch("int", &v)),
+                };
+                return as_decimal(v)
+                    .unwrap()
+                    .round(digits)
+                    .map(decimal_value)
+                    .ok_or_else(decimal_overflow);
+            }
+            v => return Err(type_mismatch("number", &v)),
+        };
+        let digits = match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Int(v) => v,
+            v => return Err(type_mismatch("int", &v)),
+        };
+        let factor = 10f64.powi(digits as i32);
+        Ok(RuntimeValue::Float((x * factor).round() / factor))
+    }
+}
+
+/// `(to_float x)`: converts the int or float `x` to a float, for mixing
+/// ints and floats under `NumericCoercion::Forbidden`.
+#[derive(Debug)]
+pub struct ToFloatOp;
+impl HostFunction for ToFloatOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("x")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match params[0] {
+            DataType::Divergent => Ok(DataType::Divergent),
+            DataType::Value(ValueType::Int)
+            | DataType::Value(ValueType::Float)
+            | DataType::Dynamic => Ok(DataType::Value(ValueType::Float)),
+            ref x => Err(TypeError::Custom(format!(
+                "unsupported type for to_float: {}",
+                x
+            ))),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Int(v) => Ok(RuntimeValue::Float(v as f64)),
+            RuntimeValue::Float(v) => Ok(RuntimeValue::Float(v)),
+            v => Err(type_mismatch("number", &v)),
+        }
+    }
+}
+
+/// The type of a value deferred with `$delay`.
+#[derive(Debug, Clone)]
+pub struct DelayedType {
+    inner_ty: DataType,
+}
+
+impl DelayedType {
+    pub fn inner_type(&self) -> &DataType {
+        &self.inner_ty
+    }
+}
+
+impl CustomDataType for DelayedType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        let other = match other.as_any().downcast_ref::<DelayedType>() {
+            Some(v) => v,
+            None => return false,
+        };
+        self.inner_ty == other.inner_ty
+    }
+
+    fn as_any(&self) -> &dyn ::std::any::Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "delayed<{}>", self.inner_ty)
+    }
+}
+
+/// A value that is not evaluated until it is forced, even when the
+/// context evaluates arguments eagerly.
+#[derive(Debug)]
+pub struct Delayed {
+    value: ScopedSlot,
+}
+
+impl CustomValue for Delayed {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<delayed>")
+    }
+}
+
+/// `(delay value)`: defers `value` until it is passed to `$force`.
+#[derive(Debug)]
+pub struct DelayOp;
+impl HostFunction for DelayOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("value")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.len() == 1 {
+            Ok(DataType::Custom(Rc::new(Box::new(DelayedType {
+                inner_ty: params[0].clone(),
+            }))))
+        } else {
+            Err(TypeError::Custom("expecting exactly 1 param".into()))
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let value = params.next().unwrap();
+        Ok(RuntimeValue::Custom(CustomValueBox::new(Box::new(
+            Delayed {
+                value: ectx.write_scoped_slot(value),
+            },
+        ))))
+    }
+}
+
+/// `(force delayed)`: evaluates a value deferred with `$delay`.
+#[derive(Debug)]
+pub struct ForceOp;
+impl HostFunction for ForceOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("delayed")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.len() != 1 {
+            return Err(TypeError::Custom("expecting exactly 1 param".into()));
+        }
+        match params[0] {
+            DataType::Divergent | DataType::Dynamic => Ok(params[0].clone()),
+            DataType::Custom(ref inner) => match inner.as_any().downcast_ref::<DelayedType>() {
+                Some(delayed) => Ok(delayed.inner_ty.clone()),
+                None => Err(TypeError::Custom(format!(
+                    "cannot force a value of type {}",
+                    params[0]
+                ))),
+            },
+            ref other => Err(TypeError::Custom(format!(
+                "cannot force a value of type {}",
+                other
+            ))),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Custom(ref cv) if cv.inner.as_any().is::<Delayed>() => {
+                let delayed = cv.inner.as_any().downcast_ref::<Delayed>().unwrap();
+                delayed.value.read(ectx)?.eval(ectx)
+            }
+            v => Err(type_mismatch("delayed value", &v)),
+        }
+    }
+}
+
+/// `(memo f)`: wraps `f` with a cache of its results by argument, kept in
+/// the evaluation context. Arguments are evaluated before the lookup and
+/// must be hashable.
+#[derive(Debug)]
+pub struct MemoOp;
+impl HostFunction for MemoOp {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.len() != 1 {
+            return Err(TypeError::Custom("expecting exactly 1 param".into()));
+        }
+        match params[0] {
+            DataType::FunctionDecl { .. } | DataType::Divergent | DataType::Dynamic => {
+                Ok(params[0].clone())
+            }
+            ref other => Err(TypeError::Custom(format!(
+                "cannot memoize a value of type {}",
+                other
+            ))),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let f = params.next().unwrap();
+        let args: Vec<LazyValue<'b>> = params.collect();
+
+        // Calls of the wrapper come back here with the arguments appended.
+        if args.is_empty() {
+            let name = ectx.current_host().expect("bug: memo called directly");
+            return Ok(RuntimeValue::PartialHost {
+                name,
+                args: vec![f],
+            });
+        }
+
+        let mut values = Vec::with_capacity(args.len());
+        let mut hasher = DefaultHasher::new();
+        for arg in &args {
+            let v = arg.eval(ectx)?;
+            value_hash(&v, &mut hasher, ectx)?;
+            values.push(v);
+        }
+        let key = (f.cell_id(), hasher.finish());
+
+        let candidates: Vec<(Vec<RuntimeValue<'b>>, RuntimeValue<'b>)> = ectx
+            .memo_table()
+            .get(&key)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|e| (e.args.clone(), e.result.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        'candidates: for (cached_args, result) in candidates {
+            for (a, b) in cached_args.iter().zip(&values) {
+                if !values_eq(a, b, ectx)? {
+                    continue 'candidates;
+                }
+            }
+            return Ok(result);
+        }
+
+        let function = f.eval(ectx)?;
+        let result = apply_value(function, args, ectx)?;
+        ectx.memo_table().entry(key).or_default().push(MemoEntry {
+            _function: f,
+            args: values,
+            result: result.clone(),
+        });
+        Ok(result)
+    }
+}
+
+/// The type of expressions built with `(quote ...)`.
+#[derive(Debug, Clone)]
+pub struct QuotedType;
+
+impl CustomDataType for QuotedType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<QuotedType>()
+    }
+
+    fn as_any(&self) -> &dyn ::std::any::Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expr")
+    }
+}
+
+fn quoted_type() -> DataType {
+    DataType::Custom(Rc::new(Box::new(QuotedType)))
+}
+
+/// An expression built with `(quote ...)`. Equal if the expressions are
+/// equal up to renaming.
+#[derive(Debug)]
+pub struct Quoted {
+    expr: Expr,
+}
+
+impl Quoted {
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+}
+
+impl CustomValue for Quoted {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn value_eq<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<bool>, RuntimeError> {
+        Ok(other
+            .as_any()
+            .downcast_ref::<Quoted>()
+            .map(|other| canonicalize(&self.expr) == canonicalize(&other.expr)))
+    }
+
+    fn hash<'b, 'c>(
+        &self,
+        state: &mut dyn Hasher,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<bool, RuntimeError> {
+        state.write_u64(self.expr.content_hash());
+        Ok(true)
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(quote {})", self.expr)
+    }
+}
+
+fn as_quoted<'a>(v: &'a RuntimeValue) -> Option<&'a Quoted> {
+    match *v {
+        RuntimeValue::Custom(ref cv) => cv.inner.as_any().downcast_ref::<Quoted>(),
+        _ => None,
+    }
+}
+
+/// Returns the literal for `v`, if values of its type have one.
+pub(crate) fn const_of(v: &RuntimeValue) -> Option<ConstExpr> {
+    match *v {
+        RuntimeValue::Empty => Some(ConstExpr::Empty),
+        RuntimeValue::Int(v) => Some(ConstExpr::Int(v)),
+        RuntimeValue::Float(v) => Some(ConstExpr::Float(v)),
+        RuntimeValue::Bool(v) => Some(ConstExpr::Bool(v)),
+        ref other => as_str(other)
+            .map(|s| ConstExpr::Str(s.to_string()))
+            .or_else(|| as_bytes(other).map(|b| ConstExpr::Bytes(b.to_vec()))),
+    }
+}
+
+/// Returns an expression that evaluates to `v`, for passing values from
+/// the host to scripts. Lists are built with `$list_push` and JSON
+/// documents with `$json_parse`. Functions, which have lost their
+/// captured variables, host functions and custom values other than
+/// strings, bytes and JSON documents have none.
+pub(crate) fn literal_of(v: &OwnedValue) -> Option<Expr> {
+    let body = match *v {
+        OwnedValue::Empty => ExprBody::Const(ConstExpr::Empty),
+        OwnedValue::Int(v) => ExprBody::Const(ConstExpr::Int(v)),
+        OwnedValue::Float(v) => ExprBody::Const(ConstExpr::Float(v)),
+        OwnedValue::Bool(v) => ExprBody::Const(ConstExpr::Bool(v)),
+        OwnedValue::List(ref items) => {
+            let push = Expr {
+                body: Rc::new(ExprBody::Abstract {
+                    params: vec![],
+                    body: AbstractBody::Host("list_push".into()),
+                }),
+            };
+            let mut list = Expr {
+                body: Rc::new(ExprBody::Const(ConstExpr::Empty)),
+            };
+            for item in items.iter().rev() {
+                list = Expr {
+                    body: Rc::new(ExprBody::Apply {
+                        target: push.clone(),
+                        params: vec![literal_of(item)?, list],
+                    }),
+                };
+            }
+            return Some(list);
+        }
+        OwnedValue::Custom(ref cv) => {
+            let v = RuntimeValue::Custom(cv.clone());
+            match as_json(&v) {
+                Some(doc) => ExprBody::Apply {
+                    target: Expr {
+                        body: Rc::new(ExprBody::Abstract {
+                            params: vec![],
+                            body: AbstractBody::Host("json_parse".into()),
+                        }),
+                    },
+                    params: vec![Expr {
+                        body: Rc::new(ExprBody::Const(ConstExpr::Str(doc.to_string()))),
+                    }],
+                },
+                None => ExprBody::Const(const_of(&v)?),
+            }
+        }
+        OwnedValue::Function { .. } | OwnedValue::Host(_) | OwnedValue::PartialHost { .. } => {
+            return None
+        }
+    };
+    Some(Expr {
+        body: Rc::new(body),
+    })
+}
+
+/// Returns `v` as a value of `ectx`, for the values `literal_of` accepts.
+/// Custom values are shared: those converted to an `OwnedValue` as they
+/// are do not refer to the context that produced them.
+pub(crate) fn runtime_value_of<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: &OwnedValue,
+) -> Option<RuntimeValue<'b>> {
+    Some(match *v {
+        OwnedValue::Empty => RuntimeValue::Empty,
+        OwnedValue::Int(v) => RuntimeValue::Int(v),
+        OwnedValue::Float(v) => RuntimeValue::Float(v),
+        OwnedValue::Bool(v) => RuntimeValue::Bool(v),
+        OwnedValue::List(ref items) => {
+            let mut values = Vec::new();
+            for item in items {
+                values.push(runtime_value_of(ectx, item)?);
+            }
+            list_value(ectx, values)
+        }
+        OwnedValue::Custom(ref cv) => RuntimeValue::Custom(cv.clone()),
+        OwnedValue::Function { .. } | OwnedValue::Host(_) | OwnedValue::PartialHost { .. } => {
+            return None
+        }
+    })
+}
+
+/// Whether values of type `ty` can be spliced into and returned from
+/// quoted expressions.
+fn is_quotable(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Empty | DataType::Value(_) => true,
+        DataType::Custom(ref inner) => {
+            inner.as_any().is::<QuotedType>()
+                || inner.as_any().is::<StringType>()
+                || inner.as_any().is::<BytesType>()
+        }
+        _ => false,
+    }
+}
+
+/// `(quote body)` is parsed into `($quote (\holes... body) values...)`,
+/// which builds `body` with each hole replaced by the matching value.
+/// Values are numbers, bools, `~` or other quoted expressions.
+#[derive(Debug)]
+pub struct QuoteOp;
+impl HostFunction for QuoteOp {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        let holes = match params.first() {
+            Some(DataType::FunctionDecl { ref params, .. }) => params.len(),
+            _ => return Err(TypeError::Custom("expecting a quote template".into())),
+        };
+        if params.len() != holes + 1 {
+            return Err(TypeError::Custom(format!(
+                "expecting {} values to splice",
+                holes
+            )));
+        }
+        match params[1..]
+            .iter()
+            .find(|ty| !is_quotable(ty) && **ty != DataType::Dynamic)
+        {
+            Some(ty) => Err(TypeError::Custom(format!(
+                "cannot unquote a value of type {}",
+                ty
+            ))),
+            None => Ok(quoted_type()),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let (holes, body) = match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Function { params, body, .. } => (params, body),
+            ref other => return Err(type_mismatch("quote template", other)),
+        };
+        let mut subst = Vec::with_capacity(holes.len());
+        for (hole, value) in holes.iter().zip(params) {
+            let value = value.eval(ectx)?;
+            let e = match const_of(&value) {
+                Some(e) => e,
+                None => match as_quoted(&value) {
+                    Some(q) => {
+                        subst.push((hole.as_str(), q.expr.clone()));
+                        continue;
+                    }
+                    None => return Err(type_mismatch("a quotable value", &value)),
+                },
+            };
+            subst.push((
+                hole.as_str(),
+                Expr {
+                    body: Rc::new(ExprBody::Const(e)),
+                },
+            ));
+        }
+        if subst.len() != holes.len() {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "expecting {} values to splice",
+                holes.len()
+            )));
+        }
+        Ok(RuntimeValue::Custom(CustomValueBox::new(Box::new(
+            Quoted {
+                expr: substitute(body, &subst),
+            },
+        ))))
+    }
+}
+
+/// `(eval fragment like)`: typechecks and evaluates the quoted expression
+/// `fragment`. Its value must be a number, bool, `~` or quoted expression
+/// of the same type as `like`, e.g. `0` for an int, which the result of
+/// `$eval` takes.
+#[derive(Debug)]
+pub struct EvalOp;
+impl HostFunction for EvalOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("fragment"),
+            Param::required("like"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.len() != 2 {
+            return Err(TypeError::Custom("expecting exactly 2 params".into()));
+        }
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        if params[0] != quoted_type() && params[0] != DataType::Dynamic {
+            return Err(TypeError::Custom(format!(
+                "cannot evaluate a value of type {}",
+                params[0]
+            )));
+        }
+        if !is_quotable(&params[1]) {
+            return Err(TypeError::Custom(format!(
+                "cannot evaluate to a value of type {}",
+                params[1]
+            )));
+        }
+        Ok(params[1].clone())
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let fragment = params.next().unwrap().eval(ectx)?;
+        let expr = match as_quoted(&fragment) {
+            Some(q) => q.expr.clone(),
+            None => return Err(type_mismatch("quoted expression", &fragment)),
+        };
+        let like = params.next().unwrap().eval(ectx)?;
+
+        check_expr(&expr, &mut ectx.type_state())
+            .map_err(|e| RuntimeError::TypeMismatch(format!("in quoted expression: {}", e)))?;
+        ectx.eval_nested(&expr, |v| {
+            Ok(match (v, &like) {
+                (RuntimeValue::Empty, RuntimeValue::Empty) => RuntimeValue::Empty,
+                (RuntimeValue::Int(v), RuntimeValue::Int(_)) => RuntimeValue::Int(v),
+                (RuntimeValue::Float(v), RuntimeValue::Float(_)) => RuntimeValue::Float(v),
+                (RuntimeValue::Bool(v), RuntimeValue::Bool(_)) => RuntimeValue::Bool(v),
+                (RuntimeValue::Custom(cv), like)
+                    if as_quoted(like).is_some() && cv.inner.as_any().is::<Quoted>() =>
+                {
+                    RuntimeValue::Custom(cv)
+                }
+                (RuntimeValue::Custom(cv), like)
+                    if as_str(like).is_some() && cv.inner.as_any().is::<Str>() =>
+                {
+                    RuntimeValue::Custom(cv)
+                }
+                (RuntimeValue::Custom(cv), like)
+                    if as_bytes(like).is_some() && cv.inner.as_any().is::<Bytes>() =>
+                {
+                    RuntimeValue::Custom(cv)
+                }
+                (v, like) => {
+                    return Err(RuntimeError::TypeMismatch(format!(
+                        "expecting a value like {}, found {}",
+                        like, v
+                    )))
+                }
+            })
+        })
+    }
+}
+
+/// A group of related host functions. Profiles select which groups a
+/// `HostManager` registers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostGroup {
+    /// `$add`, `$sub`, `$mul`, `$div` and `$mod`.
+    Arithmetic,
+    /// `$eq`, `$ne`, the ordering operators, `$and` and `$or`.
+    Comparison,
+    /// `$if`, `$the`, which `(the ...)` annotations call, and `$dyn`.
+    Control,
+    /// `$list_push`, `$list_head`, `$list_tail` and `$list_is_empty`.
+    List,
+    /// `$round`, `$to_float`, `$decimal`, `$decimal_div` and
+    /// `$decimal_to_float`.
+    Math,
+    /// `$delay` and `$force`.
+    Strictness,
+    /// `$memo`.
+    Memo,
+    /// `$quote`, which `(quote ...)` forms call.
+    Quote,
+    /// `$json_parse`, `$json_get` and the other functions of `crate::json`.
+    Json,
+    /// `$timestamp`, `$duration`, `$time_add`, `$time_diff` and
+    /// `$duration_seconds`.
+    Time,
+    /// `$now`, which reads the system clock.
+    Clock,
+    /// `$regex_match`, `$regex_capture` and `$regex_replace`. Empty unless
+    /// built with the `regex` feature.
+    Regex,
+    /// `$bytes_len`, `$bytes_slice`, `$bytes_concat`, `$base64_encode`,
+    /// `$base64_decode` and `$hash_sha256`.
+    Bytes,
+    /// `$config`, which reads the embedder's `HostConfig`.
+    Config,
+    /// `$log`, which writes to the embedder's `LogSink`.
+    Log,
+    /// `$getenv`, which reads the environment of the process. No profile
+    /// includes it, since the environment often holds secrets; register it
+    /// with `HostManager::allow`, and allow the variables scripts may read
+    /// with `HostConfig::allow_env`.
+    Env,
+    /// `$eval`. No profile includes it, since a script that evaluates
+    /// expressions it builds at runtime is hard to review; register it with
+    /// `HostManager::allow`.
+    Eval,
+    /// Host functions the embedder registers besides the core library, e.g.
+    /// with `Engine::add_host`. These usually perform I/O.
+    Io,
+}
+
+impl HostGroup {
+    pub fn name(self) -> &'static str {
+        match self {
+            HostGroup::Arithmetic => "arithmetic",
+            HostGroup::Comparison => "comparison",
+            HostGroup::Control => "control",
+            HostGroup::List => "list",
+            HostGroup::Math => "math",
+            HostGroup::Strictness => "strictness",
+            HostGroup::Memo => "memo",
+            HostGroup::Quote => "quote",
+            HostGroup::Json => "json",
+            HostGroup::Time => "time",
+            HostGroup::Clock => "clock",
+            HostGroup::Regex => "regex",
+            HostGroup::Bytes => "bytes",
+            HostGroup::Config => "config",
+            HostGroup::Log => "log",
+            HostGroup::Env => "env",
+            HostGroup::Eval => "eval",
+            HostGroup::Io => "io",
+        }
+    }
+
+    /// Whether the functions of this group always return the same result
+    /// for the same arguments. `Config` and `Env` depend on the deployment
+    /// and `Eval` on the functions quoted expressions call; `Log` returns
+    /// its argument, but calls must not be skipped; `Io` is the embedder's,
+    /// and nothing is known about them.
+    pub fn is_pure(self) -> bool {
+        !matches!(
+            self,
+            HostGroup::Clock
+                | HostGroup::Config
+                | HostGroup::Log
+                | HostGroup::Env
+                | HostGroup::Io
+                | HostGroup::Eval
+        )
+    }
+
+    pub const ALL: &'static [HostGroup] = &[
+        HostGroup::Arithmetic,
+        HostGroup::Comparison,
+        HostGroup::Control,
+        HostGroup::List,
+        HostGroup::Math,
+        HostGroup::Strictness,
+        HostGroup::Memo,
+        HostGroup::Quote,
+        HostGroup::Json,
+        HostGroup::Time,
+        HostGroup::Clock,
+        HostGroup::Regex,
+        HostGroup::Bytes,
+        HostGroup::Config,
+        HostGroup::Log,
+        HostGroup::Env,
+        HostGroup::Io,
+        HostGroup::Eval,
+    ];
+}
+
+/// A named selection of host function groups, for giving different
+/// scripts different capabilities.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    /// Arithmetic, comparisons, `$if`, `$the`, `$dyn`, `$round` and the
+    /// decimal functions.
+    PureMath,
+    /// `PureMath` plus lists, `$delay`/`$force`, `$memo`, `$quote`, the
+    /// JSON, regex and bytes functions, `$config`, `$log` and the time
+    /// functions except `$now`.
+    DataTransform,
+    /// Every group except `HostGroup::Env` and `HostGroup::Eval`,
+    /// including `$now` and the embedder's own host functions.
+    FullIo,
+}
+
+impl Profile {
+    pub fn groups(self) -> Vec<HostGroup> {
+        match self {
+            Profile::PureMath => vec![
+                HostGroup::Arithmetic,
+                HostGroup::Comparison,
+                HostGroup::Control,
+                HostGroup::Math,
+            ],
+            Profile::DataTransform => vec![
+                HostGroup::Arithmetic,
+                HostGroup::Comparison,
+                HostGroup::Control,
+                HostGroup::Math,
+                HostGroup::List,
+                HostGroup::Strictness,
+                HostGroup::Memo,
+                HostGroup::Quote,
+                HostGroup::Json,
+                HostGroup::Time,
+                HostGroup::Regex,
+                HostGroup::Bytes,
+                HostGroup::Config,
+                HostGroup::Log,
+            ],
+            Profile::FullIo => HostGroup::ALL
+                .iter()
+                .cloned()
+                .filter(|g| *g != HostGroup::Env && *g != HostGroup::Eval)
+                .collect(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::PureMath => "pure-math",
+            Profile::DataTransform => "data-transform",
+            Profile::FullIo => "full-io",
+        }
+    }
+
+    /// Looks up a profile by `name`, e.g. from a configuration file.
+    pub fn from_name(name: &str) -> Option<Profile> {
+        [Profile::PureMath, Profile::DataTransform, Profile::FullIo]
+            .iter()
+            .cloned()
+            .find(|p| p.name() == name)
+    }
+}
+
+/// How `$div` and `$mod` treat quotients that are not whole.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Division {
+    /// Int quotients are rounded toward zero and remainders have the sign
+    /// of the dividend, as in Rust and C: `($mod -7 2)` is `-1`. Float and
+    /// decimal quotients are exact.
+    #[default]
+    Truncated,
+    /// Quotients are rounded down and remainders have the sign of the
+    /// divisor, as in Python: `($mod -7 2)` is `1`. `$div_floor` and
+    /// `$mod_floor`.
+    Floor,
+    /// Quotients are rounded so that remainders are never negative:
+    /// `($mod 7 -2)` is `1`. `$div_euclid` and `$rem_euclid`.
+    Euclidean,
+}
+
+impl Division {
+    pub fn name(self) -> &'static str {
+        match self {
+            Division::Truncated => "truncated",
+            Division::Floor => "floor",
+            Division::Euclidean => "euclidean",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Division> {
+        [Division::Truncated, Division::Floor, Division::Euclidean]
+            .iter()
+            .cloned()
+            .find(|d| d.name() == name)
+    }
+}
+
+/// What the arithmetic and comparison operators do with an int and a
+/// float.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum NumericCoercion {
+    /// The int is converted to a float, whichever operand it is, and
+    /// arithmetic gives a float: `($add 1 0.5)` and `($add 0.5 1)` are both
+    /// `1.5`.
+    #[default]
+    IntToFloat,
+    /// Mixing them is a type error, or a runtime error for `dyn` operands;
+    /// convert the int with `$to_float`.
+    Forbidden,
+}
+
+impl NumericCoercion {
+    pub fn name(self) -> &'static str {
+        match self {
+            NumericCoercion::IntToFloat => "int-to-float",
+            NumericCoercion::Forbidden => "forbidden",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<NumericCoercion> {
+        [NumericCoercion::IntToFloat, NumericCoercion::Forbidden]
+            .iter()
+            .cloned()
+            .find(|c| c.name() == name)
+    }
+
+    /// Whether operands of types `params` are an int and a float, which
+    /// this converts the int of.
+    fn converts(self, params: &[DataType]) -> bool {
+        self == NumericCoercion::IntToFloat && params.len() == 2 && mixed(&params[0], &params[1])
+    }
+
+    fn check_types(self, a: &DataType, b: &DataType) -> Result<(), TypeError> {
+        if self == NumericCoercion::Forbidden && mixed(a, b) {
+            return Err(TypeError::Custom(format!(
+                "cannot mix {} and {}: convert the int with $to_float",
+                a, b
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_values(self, a: &RuntimeValue, b: &RuntimeValue) -> Result<(), RuntimeError> {
+        let mixed = matches!(
+            (a, b),
+            (RuntimeValue::Int(_), RuntimeValue::Float(_))
+                | (RuntimeValue::Float(_), RuntimeValue::Int(_))
+        );
+        if self == NumericCoercion::Forbidden && mixed {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "cannot mix {} and {}: convert the int with $to_float",
+                a, b
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `a` and `b` are an int and a float, in either order.
+fn mixed(a: &DataType, b: &DataType) -> bool {
+    let int = DataType::Value(ValueType::Int);
+    let float = DataType::Value(ValueType::Float);
+    (*a == int && *b == float) || (*a == float && *b == int)
+}
+
+/// Whether a truncated remainder `r` of dividing by `b` must be moved by
+/// `b` to get the remainder `division` gives.
+fn adjusts_remainder(division: Division, r_negative: bool, b_negative: bool, r_zero: bool) -> bool {
+    match division {
+        Division::Truncated => false,
+        Division::Floor => !r_zero && r_negative != b_negative,
+        Division::Euclidean => r_negative,
+    }
+}
+
+fn int_rem(a: i64, b: i64, division: Division) -> Result<i64, RuntimeError> {
+    if b == 0 {
+        return Err(RuntimeError::DivByZero);
+    }
+    let r = a.checked_rem(b).ok_or(RuntimeError::IntOverflow)?;
+    let r = if !adjusts_remainder(division, r < 0, b < 0, r == 0) {
+        Some(r)
+    } else if division == Division::Euclidean && b < 0 {
+        r.checked_sub(b)
+    } else {
+        r.checked_add(b)
+    };
+    r.ok_or(RuntimeError::IntOverflow)
+}
+
+/// `(a - r) / b` for the remainder `r` `division` gives, which is whole.
+fn int_div(a: i64, b: i64, division: Division) -> Result<i64, RuntimeError> {
+    let r = int_rem(a, b, division)?;
+    a.checked_sub(r)
+        .and_then(|x| x.checked_div(b))
+        .ok_or(RuntimeError::IntOverflow)
+}
+
+fn float_rem(a: f64, b: f64, division: Division) -> f64 {
+    let r = a % b;
+    if !adjusts_remainder(division, r < 0.0, b < 0.0, r == 0.0) {
+        r
+    } else if division == Division::Euclidean && b < 0.0 {
+        r - b
+    } else {
+        r + b
+    }
+}
+
+fn decimal_rem(a: Decimal, b: Decimal, division: Division) -> Result<Decimal, RuntimeError> {
+    if b.is_zero() {
+        return Err(RuntimeError::DivByZero);
+    }
+    let zero = Decimal::from_int(0);
+    let r = a.checked_rem(b).ok_or_else(decimal_overflow)?;
+    let r = if !adjusts_remainder(division, r < zero, b < zero, r.is_zero()) {
+        Some(r)
+    } else if division == Division::Euclidean && b < zero {
+        r.checked_sub(b)
+    } else {
+        r.checked_add(b)
+    };
+    r.ok_or_else(decimal_overflow)
+}
+
+/// `(a - r) / b` for the remainder `r` `division` gives, which is whole.
+fn decimal_div(a: Decimal, b: Decimal, division: Division) -> Result<Decimal, RuntimeError> {
+    let r = decimal_rem(a, b, division)?;
+    a.checked_sub(r)
+        .and_then(|x| x.checked_div(b))
+        .ok_or_else(decimal_overflow)
+}
+
+/// The quotient and remainder operators with `division` semantics.
+fn division_ops(division: Division) -> (BasicBinop, BasicBinop) {
+    match division {
+        Division::Truncated => (
+            BasicBinop {
+                int_op: |a, b| int_div(a, b, Division::Truncated),
+                float_op: |a, b| Ok(a / b),
+                decimal_op: |a, b| {
+                    if b.is_zero() {
+                        Err(RuntimeError::DivByZero)
+                    } else {
+                        a.checked_div(b).ok_or_else(|| {
+                            RuntimeError::Custom(
+                                "inexact decimal quotient: round it with $decimal_div".into(),
+                            )
+                        })
+                    }
+                },
+                coercion: NumericCoercion::default(),
+            },
+            BasicBinop {
+                int_op: |a, b| int_rem(a, b, Division::Truncated),
+                float_op: |a, b| Ok(float_rem(a, b, Division::Truncated)),
+                decimal_op: |a, b| decimal_rem(a, b, Division::Truncated),
+                coercion: NumericCoercion::default(),
+            },
+        ),
+        Division::Floor => (
+            BasicBinop {
+                int_op: |a, b| int_div(a, b, Division::Floor),
+                float_op: |a, b| Ok((a / b).floor()),
+                decimal_op: |a, b| decimal_div(a, b, Division::Floor),
+                coercion: NumericCoercion::default(),
+            },
+            BasicBinop {
+                int_op: |a, b| int_rem(a, b, Division::Floor),
+                float_op: |a, b| Ok(float_rem(a, b, Division::Floor)),
+                decimal_op: |a, b| decimal_rem(a, b, Division::Floor),
+                coercion: NumericCoercion::default(),
+            },
+        ),
+        Division::Euclidean => (
+            BasicBinop {
+                int_op: |a, b| int_div(a, b, Division::Euclidean),
+                float_op: |a, b| Ok(a.div_euclid(b)),
+                decimal_op: |a, b| decimal_div(a, b, Division::Euclidean),
+                coercion: NumericCoercion::default(),
+            },
+            BasicBinop {
+                int_op: |a, b| int_rem(a, b, Division::Euclidean),
+                float_op: |a, b| Ok(a.rem_euclid(b)),
+                decimal_op: |a, b| decimal_rem(a, b, Division::Euclidean),
+                coercion: NumericCoercion::default(),
+            },
+        ),
+    }
+}
+
+/// Describes the core library host function `name`. Several names share
+/// an implementation, so their doc text is kept here by name.
+pub(crate) fn describe_core(name: &str, group: HostGroup, hf: &dyn HostFunction) -> HostInfo {
+    let mut info = HostInfo::of(name, group.name(), hf);
+    if info.doc.is_none() {
+        info.doc = core_doc(name).map(|x| x.to_string());
+    }
+    info
+}
+
+fn core_doc(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "add" => "Adds two numbers. An int and a float give a float.",
+        "sub" => "Subtracts `rhs` from `lhs`.",
+        "mul" => "Multiplies two numbers.",
+        "div" => "Divides `lhs` by `rhs`, rounding int quotients as the engine's `Division` says.",
+        "mod" => "The remainder of `$div`.",
+        "div_floor" => "Divides `lhs` by `rhs`, rounding the quotient down.",
+        "mod_floor" => "The remainder of `$div_floor`, with the sign of `rhs`.",
+        "div_euclid" => "Divides `lhs` by `rhs` so that `$rem_euclid` is never negative.",
+        "rem_euclid" => "The remainder of `$div_euclid`, never negative.",
+        "eq" => "Whether two values of the same type are structurally equal.",
+        "ne" => "Whether two values of the same type differ.",
+        "and" => "Whether both operands are true or nonzero.",
+        "or" => "Whether either operand is true or nonzero.",
+        "lt" => "Whether `lhs` is less than `rhs`.",
+        "le" => "Whether `lhs` is less than or equal to `rhs`.",
+        "gt" => "Whether `lhs` is greater than `rhs`.",
+        "ge" => "Whether `lhs` is greater than or equal to `rhs`.",
+        "if" => "Evaluates `then` if `cond` is true and `else` otherwise.",
+        "the" => "Checks a value against a type; what `(the ty value)` calls.",
+        "dyn" => "Gives `value` the `dyn` type, deferring checks on it to runtime.",
+        "list_push" => "Prepends `value` to `list`.",
+        "list_head" => "The first element of a non-empty list.",
+        "list_tail" => "A non-empty list without its first element.",
+        "list_is_empty" => "Whether `list` has no elements.",
+        "round" => "Rounds `x` to `digits` decimal places.",
+        "to_float" => "Converts an int or a float to a float.",
+        "decimal" => "Converts an int, a decimal or a string such as `\"-12.50\"` to a decimal.",
+        "decimal_div" => "`a / b` rounded half away from zero to `places` digits after the point.",
+        "decimal_to_float" => "Converts a decimal to the nearest float.",
+        "delay" => "Defers evaluating `value` until it is passed to `$force`.",
+        "force" => "Evaluates a value deferred with `$delay`.",
+        "memo" => "Wraps a function so that calls with the same arguments are evaluated once.",
+        "quote" => "The expression it is given, unevaluated; what `(quote ...)` calls.",
+        "eval" => "Typechecks and evaluates the quoted expression `fragment`, which must have the type of `like`.",
+        "json_parse" => "Parses JSON text.",
+        "json_get" => "The member `key` (a string) of an object or element `key` (an int) of an array; `null` if missing.",
+        "json_type" => "The type of a JSON value: `null`, `bool`, `number`, `string`, `array` or `object`.",
+        "json_to_int" => "A JSON integer as an int.",
+        "json_to_float" => "A JSON number as a float.",
+        "json_to_bool" => "A JSON boolean as a bool.",
+        "json_to_str" => "A JSON string as a string.",
+        "json_stringify" => "Serializes a JSON value to text.",
+        "timestamp" => "Parses an ISO-8601 date or date and time.",
+        "duration" => "`x` seconds if it is an int, or the ISO-8601 duration `x` if it is a string.",
+        "time_add" => "The timestamp or duration `t` moved by the duration `d`.",
+        "time_diff" => "The duration from `b` to `a`, two timestamps or two durations.",
+        "duration_seconds" => "The whole seconds in `d`, rounded towards zero.",
+        "now" => "The current time, shifted by the duration `offset`.",
+        "regex_match" => "Whether `pattern` matches somewhere in `text`.",
+        "regex_capture" => "The first match of `pattern` in `text` followed by its capture groups, or `~`.",
+        "regex_replace" => "`text` with every match of `pattern` replaced by `replacement`.",
+        "bytes_len" => "The number of bytes in `b`.",
+        "bytes_slice" => "The bytes of `b` from `start` up to `end`.",
+        "bytes_concat" => "The bytes of `a` followed by those of `b`.",
+        "base64_encode" => "Encodes bytes as base64 text.",
+        "base64_decode" => "Decodes base64 text to bytes.",
+        "hash_sha256" => "The SHA-256 hash of a string or bytes.",
+        "config" => "The embedder's configuration value `key`, or `default` if unset.",
+        "log" => "Logs `message` and `value` at `level` (`\"debug\"`, `\"info\"`, `\"warn\"` or `\"error\"`) to the embedder, and returns `value`.",
+        "getenv" => "The environment variable `name`, or `default` if unset.",
+        _ => return None,
+    })
+}
+
+/// Borrows host functions kept as `(name, Arc)` pairs for `get_group`.
+fn named_hosts<'a>(
+    ops: &'a [(&'static str, Arc<dyn HostFunction>)],
+) -> Vec<(String, &'a dyn HostFunction)> {
+    ops.iter().map(|(k, v)| ((*k).into(), &**v)).collect()
+}
+
+pub struct HostManager {
+    /// Groups registered by `get_all`.
+    groups: BTreeSet<HostGroup>,
+    /// Names `get_all` leaves out even if their group is registered.
+    denied: BTreeSet<String>,
+    binops: Vec<(&'static str, BasicBinop)>,
+    relops: Vec<(&'static str, BasicRelop)>,
+    eq_op: EqOp,
+    ne_op: EqOp,
+    ifop: IfOp,
+    the_op: TheOp,
+    dyn_op: DynOp,
+    list_push_op: ListPushOp,
     list_head_op: ListHeadOp,
+    list_tail_op: ListTailOp,
+    list_is_empty_op: ListIsEmptyOp,
+    round_op: RoundOp,
+    to_float_op: ToFloatOp,
+    delay_op: DelayOp,
+    force_op: ForceOp,
+    memo_op: MemoOp,
+    quote_op: QuoteOp,
+    eval_op: EvalOp,
+    decimal_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+    json_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+    time_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+    clock_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+    regex_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+    bytes_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+    config_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+    log_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+    env_ops: Vec<(&'static str, Arc<dyn HostFunction>)>,
+}
+
+impl Default for HostManager {
+    fn default() -> HostManager {
+        HostManager::new()
+    }
 }
 
 impl HostManager {
     pub fn new() -> HostManager {
         HostManager {
+            groups: Profile::FullIo.groups().into_iter().collect(),
+            denied: BTreeSet::new(),
             binops: vec![
                 (
                     "add",
                     BasicBinop {
-                        int_op: |a, b| Ok(a + b),
+                        int_op: |a, b| a.checked_add(b).ok_or(RuntimeError::IntOverflow),
                         float_op: |a, b| Ok(a + b),
+                        decimal_op: |a, b| a.checked_add(b).ok_or_else(decimal_overflow),
+                        coercion: NumericCoercion::default(),
                     },
                 ),
                 (
                     "sub",
                     BasicBinop {
-                        int_op: |a, b| Ok(a - b),
+                        int_op: |a, b| a.checked_sub(b).ok_or(RuntimeError::IntOverflow),
                         float_op: |a, b| Ok(a - b),
+                        decimal_op: |a, b| a.checked_sub(b).ok_or_else(decimal_overflow),
+                        coercion: NumericCoercion::default(),
                     },
                 ),
                 (
                     "mul",
                     BasicBinop {
-                        int_op: |a, b| Ok(a * b),
+                        int_op: |a, b| a.checked_mul(b).ok_or(RuntimeError::IntOverflow),
                         float_op: |a, b| Ok(a * b),
+                        decimal_op: |a, b| a.checked_mul(b).ok_or_else(decimal_overflow),
+                        coercion: NumericCoercion::default(),
                     },
                 ),
-                (
-                    "div",
-                    BasicBinop {
-                        int_op: |a, b| {
-                            if b == 0 {
-                                Err(RuntimeError::DivByZero)
-                            } else {
-                                Ok(a / b)
-                            }
-                        },
-                        float_op: |a, b| Ok(a / b),
-                    },
-                ),
-                (
-                    "mod",
-                    BasicBinop {
-                        int_op: |a, b| {
-                            if b == 0 {
-                                Err(RuntimeError::DivByZero)
-                            } else {
-                                Ok(a % b)
-                            }
-                        },
-                        float_op: |a, b| Ok(a % b),
-                    },
-                ),
+                ("div", division_ops(Division::Truncated).0),
+                ("mod", division_ops(Division::Truncated).1),
+                ("div_floor", division_ops(Division::Floor).0),
+                ("mod_floor", division_ops(Division::Floor).1),
+                ("div_euclid", division_ops(Division::Euclidean).0),
+                ("rem_euclid", division_ops(Division::Euclidean).1),
             ],
             relops: vec![
-                (
-                    "eq",
-                    BasicRelop {
-                        int_op: |a, b| Ok(a == b),
-                        float_op: |a, b| Ok(a == b),
-                        bool_op: |a, b| Ok(a == b),
-                    },
-                ),
-                (
-                    "ne",
-                    BasicRelop {
-                        int_op: |a, b| Ok(a != b),
-                        float_op: |a, b| Ok(a != b),
-                        bool_op: |a, b| Ok(a != b),
-                    },
-                ),
                 (
                     "and",
                     BasicRelop {
                         int_op: |a, b| Ok(a != 0 && b != 0),
                         float_op: |a, b| Ok(a != 0.0 && b != 0.0),
                         bool_op: |a, b| Ok(a && b),
+                        ordering_op: None,
+                        coercion: NumericCoercion::default(),
                     },
                 ),
                 (
@@ -424,6 +2321,8 @@ impl HostManager {
                         int_op: |a, b| Ok(a != 0 || b != 0),
                         float_op: |a, b| Ok(a != 0.0 || b != 0.0),
                         bool_op: |a, b| Ok(a || b),
+                        ordering_op: None,
+                        coercion: NumericCoercion::default(),
                     },
                 ),
                 (
@@ -431,7 +2330,9 @@ impl HostManager {
                     BasicRelop {
                         int_op: |a, b| Ok(a < b),
                         float_op: |a, b| Ok(a < b),
-                        bool_op: |a, b| Ok(a < b),
+                        bool_op: |a, b| Ok(!a & b),
+                        ordering_op: Some(|o| o == Ordering::Less),
+                        coercion: NumericCoercion::default(),
                     },
                 ),
                 (
@@ -440,6 +2341,8 @@ impl HostManager {
                         int_op: |a, b| Ok(a <= b),
                         float_op: |a, b| Ok(a <= b),
                         bool_op: |a, b| Ok(a <= b),
+                        ordering_op: Some(|o| o != Ordering::Greater),
+                        coercion: NumericCoercion::default(),
                     },
                 ),
                 (
@@ -447,7 +2350,9 @@ impl HostManager {
                     BasicRelop {
                         int_op: |a, b| Ok(a > b),
                         float_op: |a, b| Ok(a > b),
-                        bool_op: |a, b| Ok(a > b),
+                        bool_op: |a, b| Ok(a & !b),
+                        ordering_op: Some(|o| o == Ordering::Greater),
+                        coercion: NumericCoercion::default(),
                     },
                 ),
                 (
@@ -456,12 +2361,45 @@ impl HostManager {
                         int_op: |a, b| Ok(a >= b),
                         float_op: |a, b| Ok(a >= b),
                         bool_op: |a, b| Ok(a >= b),
+                        ordering_op: Some(|o| o != Ordering::Less),
+                        coercion: NumericCoercion::default(),
                     },
                 ),
             ],
+            eq_op: EqOp {
+                negate: false,
+                coercion: NumericCoercion::default(),
+            },
+            ne_op: EqOp {
+                negate: true,
+                coercion: NumericCoercion::default(),
+            },
             ifop: IfOp,
+            the_op: TheOp,
+            dyn_op: DynOp,
             list_push_op: ListPushOp,
             list_head_op: ListHeadOp,
+            list_tail_op: ListTailOp,
+            list_is_empty_op: ListIsEmptyOp,
+            round_op: RoundOp,
+            to_float_op: ToFloatOp,
+            delay_op: DelayOp,
+            force_op: ForceOp,
+            memo_op: MemoOp,
+            quote_op: QuoteOp,
+            eval_op: EvalOp,
+            decimal_ops: decimal_ops(),
+            json_ops: json_ops(),
+            time_ops: time_ops(),
+            clock_ops: clock_ops(),
+            #[cfg(feature = "regex")]
+            regex_ops: crate::pattern::regex_ops(),
+            #[cfg(not(feature = "regex"))]
+            regex_ops: Vec::new(),
+            bytes_ops: bytes_ops(),
+            config_ops: config_ops(),
+            log_ops: log_ops(),
+            env_ops: env_ops(),
         }
     }
 
@@ -472,19 +2410,244 @@ impl HostManager {
     }
 
     pub fn get_relops(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
+        vec![
+            ("eq".into(), &self.eq_op as &dyn HostFunction),
+            ("ne".into(), &self.ne_op as &dyn HostFunction),
+        ]
+        .into_iter()
+        .chain(
+            self.relops
+                .iter()
+                .map(|(k, v)| ((*k).into(), v as &dyn HostFunction)),
+        )
+    }
+
+    /// The arithmetic operator registered as `name`, e.g. for applying it
+    /// to whole columns.
+    pub(crate) fn binop(&self, name: &str) -> Option<&BasicBinop> {
+        self.binops
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, op)| op)
+    }
+
+    /// The comparison or logical operator registered as `name`, other
+    /// than `$eq` and `$ne`.
+    pub(crate) fn relop(&self, name: &str) -> Option<&BasicRelop> {
         self.relops
             .iter()
-            .map(|(k, v)| ((*k).into(), v as &dyn HostFunction))
+            .find(|(k, _)| *k == name)
+            .map(|(_, op)| op)
     }
 
     pub fn get_ifop(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
         ::std::iter::once(("if".into(), &self.ifop as &dyn HostFunction))
     }
 
+    /// `$if`, `$the` and `$dyn`.
+    pub fn get_control_ops(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
+        self.get_ifop().chain(vec![
+            ("the".into(), &self.the_op as &dyn HostFunction),
+            ("dyn".into(), &self.dyn_op as &dyn HostFunction),
+        ])
+    }
+
+    /// Creates a manager that only registers the groups in `profile`.
+    pub fn with_profile(profile: Profile) -> HostManager {
+        let mut hm = HostManager::new();
+        hm.set_profile(profile);
+        hm
+    }
+
+    /// Registers the groups in `profile` instead of the current ones. The
+    /// deny list is kept.
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.groups = profile.groups().into_iter().collect();
+    }
+
+    /// Makes `$div` and `$mod` follow `division`. `$div_floor`,
+    /// `$mod_floor`, `$div_euclid` and `$rem_euclid` are always available.
+    pub fn set_division(&mut self, division: Division) {
+        let (div, rem) = division_ops(division);
+        for (name, op) in &mut self.binops {
+            let coercion = op.coercion;
+            match *name {
+                "div" => *op = div,
+                "mod" => *op = rem,
+                _ => continue,
+            }
+            op.coercion = coercion;
+        }
+    }
+
+    /// Makes the arithmetic and comparison operators follow `coercion`
+    /// when given an int and a float.
+    pub fn set_numeric_coercion(&mut self, coercion: NumericCoercion) {
+        for (_, op) in &mut self.binops {
+            op.coercion = coercion;
+        }
+        for (_, op) in &mut self.relops {
+            op.coercion = coercion;
+        }
+        self.eq_op.coercion = coercion;
+        self.ne_op.coercion = coercion;
+    }
+
+    /// Registers `group` in addition to the current groups.
+    pub fn allow(&mut self, group: HostGroup) {
+        self.groups.insert(group);
+    }
+
+    pub fn allows(&self, group: HostGroup) -> bool {
+        self.groups.contains(&group)
+    }
+
+    /// Leaves the host function `name` out of `get_all`, whatever its group.
+    pub fn deny(&mut self, name: &str) {
+        self.denied.insert(name.to_string());
+    }
+
+    /// Descriptors of the host functions `get_all` registers.
+    pub fn abi(&self) -> HostAbi {
+        HostAbi::of(self.get_all())
+    }
+
+    pub fn is_denied(&self, name: &str) -> bool {
+        self.denied.contains(name)
+    }
+
+    /// Returns the host functions in `group`, regardless of the profile and
+    /// deny list.
+    pub fn get_group(&self, group: HostGroup) -> Vec<(String, &dyn HostFunction)> {
+        match group {
+            HostGroup::Arithmetic => self.get_binops().collect(),
+            HostGroup::Comparison => self.get_relops().collect(),
+            HostGroup::Control => self.get_control_ops().collect(),
+            HostGroup::List => self.get_list_ops().collect(),
+            HostGroup::Math => self.get_math_ops().collect(),
+            HostGroup::Strictness => self.get_strictness_ops().collect(),
+            HostGroup::Memo => self.get_memo_op().collect(),
+            HostGroup::Quote => vec![("quote".into(), &self.quote_op as &dyn HostFunction)],
+            HostGroup::Json => named_hosts(&self.json_ops),
+            HostGroup::Time => named_hosts(&self.time_ops),
+            HostGroup::Clock => named_hosts(&self.clock_ops),
+            HostGroup::Regex => named_hosts(&self.regex_ops),
+            HostGroup::Bytes => named_hosts(&self.bytes_ops),
+            HostGroup::Config => named_hosts(&self.config_ops),
+            HostGroup::Log => named_hosts(&self.log_ops),
+            HostGroup::Env => named_hosts(&self.env_ops),
+            HostGroup::Eval => vec![("eval".into(), &self.eval_op as &dyn HostFunction)],
+            HostGroup::Io => Vec::new(),
+        }
+    }
+
+    /// Describes the host functions `get_all` returns, in the same order.
+    pub fn describe_all(&self) -> Vec<HostInfo> {
+        HostGroup::ALL
+            .iter()
+            .filter(|g| self.allows(**g))
+            .flat_map(|g| {
+                self.get_group(*g)
+                    .into_iter()
+                    .map(move |(k, hf)| describe_core(&k, *g, hf))
+            })
+            .filter(|info| !self.is_denied(&info.name))
+            .collect()
+    }
+
+    /// The group of the core library host function `name`, if there is one.
+    pub fn group_of(&self, name: &str) -> Option<HostGroup> {
+        HostGroup::ALL
+            .iter()
+            .cloned()
+            .find(|g| self.get_group(*g).iter().any(|(k, _)| k == name))
+    }
+
+    /// Returns the host functions of the core library in the registered
+    /// groups, except denied ones.
+    pub fn get_all(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
+        HostGroup::ALL
+            .iter()
+            .filter(move |g| self.allows(**g))
+            .flat_map(move |g| self.get_group(*g))
+            .filter(move |(k, _)| !self.is_denied(k))
+    }
+
+    /// Moves the host functions `get_all` returns into `Arc`s, for
+    /// registration with `add_hosts_owned`.
+    pub fn into_owned_hosts(self) -> Vec<(String, Arc<dyn HostFunction>)> {
+        let allowed: BTreeSet<String> = self.get_all().map(|(k, _)| k).collect();
+        let mut hosts: Vec<(String, Arc<dyn HostFunction>)> = Vec::new();
+        for (k, v) in self.binops {
+            hosts.push((k.into(), Arc::new(v)));
+        }
+        hosts.push(("eq".into(), Arc::new(self.eq_op)));
+        hosts.push(("ne".into(), Arc::new(self.ne_op)));
+        for (k, v) in self.relops {
+            hosts.push((k.into(), Arc::new(v)));
+        }
+        hosts.push(("if".into(), Arc::new(self.ifop)));
+        hosts.push(("the".into(), Arc::new(self.the_op)));
+        hosts.push(("dyn".into(), Arc::new(self.dyn_op)));
+        hosts.push(("list_push".into(), Arc::new(self.list_push_op)));
+        hosts.push(("list_head".into(), Arc::new(self.list_head_op)));
+        hosts.push(("list_tail".into(), Arc::new(self.list_tail_op)));
+        hosts.push(("list_is_empty".into(), Arc::new(self.list_is_empty_op)));
+        hosts.push(("round".into(), Arc::new(self.round_op)));
+        hosts.push(("to_float".into(), Arc::new(self.to_float_op)));
+        hosts.extend(self.decimal_ops.into_iter().map(|(k, v)| (k.into(), v)));
+        hosts.push(("delay".into(), Arc::new(self.delay_op)));
+        hosts.push(("force".into(), Arc::new(self.force_op)));
+        hosts.push(("memo".into(), Arc::new(self.memo_op)));
+        hosts.push(("quote".into(), Arc::new(self.quote_op)));
+        hosts.push(("eval".into(), Arc::new(self.eval_op)));
+        for ops in [
+            self.json_ops,
+            self.time_ops,
+            self.clock_ops,
+            self.regex_ops,
+            self.bytes_ops,
+            self.config_ops,
+            self.log_ops,
+            self.env_ops,
+        ] {
+            hosts.extend(ops.into_iter().map(|(k, v)| (k.into(), v)));
+        }
+        hosts.retain(|(k, _)| allowed.contains(k));
+        hosts
+    }
+
+    pub fn get_math_ops(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
+        vec![
+            ("round".into(), &self.round_op as &dyn HostFunction),
+            ("to_float".into(), &self.to_float_op as &dyn HostFunction),
+        ]
+        .into_iter()
+        .chain(self.decimal_ops.iter().map(|(k, v)| ((*k).into(), &**v)))
+    }
+
+    /// `$delay` and `$force`, for deferring evaluation in eager mode.
+    pub fn get_strictness_ops(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
+        vec![
+            ("delay".into(), &self.delay_op as &dyn HostFunction),
+            ("force".into(), &self.force_op as &dyn HostFunction),
+        ]
+        .into_iter()
+    }
+
+    pub fn get_memo_op(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
+        ::std::iter::once(("memo".into(), &self.memo_op as &dyn HostFunction))
+    }
+
     pub fn get_list_ops(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
         vec![
             ("list_push".into(), &self.list_push_op as &dyn HostFunction),
             ("list_head".into(), &self.list_head_op as &dyn HostFunction),
+            ("list_tail".into(), &self.list_tail_op as &dyn HostFunction),
+            (
+                "list_is_empty".into(),
+                &self.list_is_empty_op as &dyn HostFunction,
+            ),
         ]
         .into_iter()
     }
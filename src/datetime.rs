@@ -0,0 +1,794 @@
+//! Timestamps and durations, for rules with time-based conditions.
+//!
+//! A `timestamp` is an instant in UTC with millisecond precision, made with
+//! `$timestamp` from ISO-8601 text such as `"2024-03-01T12:00:00+02:00"` or
+//! `"2024-03-01"`. A `duration` is a signed span of milliseconds, made with
+//! `$duration` from a number of seconds or ISO-8601 text such as
+//! `"PT1H30M"`. Years and months vary in length, so durations are limited to
+//! weeks, days, hours, minutes and seconds.
+//!
+//! Timestamps and durations are compared with the ordering operators and
+//! `$eq`, moved with `$time_add` and subtracted with `$time_diff`.
+//!
+//! `$now` reads the system clock and is in its own group,
+//! `HostGroup::Clock`, which only `Profile::FullIo` includes: scripts that
+//! can read it no longer evaluate to the same result every time.
+
+use crate::ast::{CustomDataType, DataType};
+use crate::builtin::ValueType;
+use crate::corelib::{as_str, string_type, type_mismatch};
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::Hasher;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MILLIS_PER_SECOND: i64 = 1000;
+const MILLIS_PER_MINUTE: i64 = 60 * MILLIS_PER_SECOND;
+const MILLIS_PER_HOUR: i64 = 60 * MILLIS_PER_MINUTE;
+const MILLIS_PER_DAY: i64 = 24 * MILLIS_PER_HOUR;
+
+#[derive(Debug)]
+pub struct TimestampType;
+
+impl CustomDataType for TimestampType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<TimestampType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "timestamp")
+    }
+}
+
+#[derive(Debug)]
+pub struct DurationType;
+
+impl CustomDataType for DurationType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<DurationType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "duration")
+    }
+}
+
+pub fn timestamp_type() -> DataType {
+    DataType::Custom(Rc::new(Box::new(TimestampType)))
+}
+
+pub fn duration_type() -> DataType {
+    DataType::Custom(Rc::new(Box::new(DurationType)))
+}
+
+fn is_timestamp_type(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Custom(ref inner) => inner.as_any().is::<TimestampType>(),
+        DataType::Dynamic => true,
+        _ => false,
+    }
+}
+
+fn is_duration_type(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Custom(ref inner) => inner.as_any().is::<DurationType>(),
+        DataType::Dynamic => true,
+        _ => false,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+struct Cursor<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(s: &'a str) -> Cursor<'a> {
+        Cursor {
+            s: s.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.pos == self.s.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Exactly `n` digits.
+    fn digits(&mut self, n: usize) -> Option<u32> {
+        let mut v = 0;
+        for _ in 0..n {
+            match self.peek() {
+                Some(c) if c.is_ascii_digit() => v = v * 10 + (c - b'0') as u32,
+                _ => return None,
+            }
+            self.pos += 1;
+        }
+        Some(v)
+    }
+
+    /// One or more digits.
+    fn number(&mut self) -> Option<i64> {
+        let start = self.pos;
+        let mut v: i64 = 0;
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+            v = v.checked_mul(10)?.checked_add((c - b'0') as i64)?;
+            self.pos += 1;
+        }
+        if self.pos == start {
+            None
+        } else {
+            Some(v)
+        }
+    }
+
+    /// The digits after a decimal point, as milliseconds. Digits beyond
+    /// milliseconds are dropped.
+    fn fraction_millis(&mut self) -> Option<i64> {
+        let start = self.pos;
+        let mut millis = 0;
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+            if self.pos - start < 3 {
+                millis = millis * 10 + (c - b'0') as i64;
+            }
+            self.pos += 1;
+        }
+        match self.pos - start {
+            0 => None,
+            n if n < 3 => Some(millis * 10i64.pow((3 - n) as u32)),
+            _ => Some(millis),
+        }
+    }
+}
+
+/// An instant, in milliseconds since 1970-01-01T00:00:00Z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    millis: i64,
+}
+
+impl Timestamp {
+    pub fn from_unix_millis(millis: i64) -> Timestamp {
+        Timestamp { millis }
+    }
+
+    pub fn unix_millis(&self) -> i64 {
+        self.millis
+    }
+
+    /// Parses an ISO-8601 date, `YYYY-MM-DD`, which stands for midnight
+    /// UTC, or date and time, `YYYY-MM-DDTHH:MM[:SS[.fff]]` followed by `Z`
+    /// or an offset such as `+02:00`.
+    pub fn parse(s: &str) -> Option<Timestamp> {
+        let mut c = Cursor::new(s);
+        let year = c.digits(4)? as i64;
+        if !c.eat(b'-') {
+            return None;
+        }
+        let month = c.digits(2)?;
+        if !c.eat(b'-') {
+            return None;
+        }
+        let day = c.digits(2)?;
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        let days = days_from_civil(year, month, day);
+        if c.done() {
+            return Some(Timestamp {
+                millis: days * MILLIS_PER_DAY,
+            });
+        }
+
+        if !c.eat(b'T') {
+            return None;
+        }
+        let hour = c.digits(2)?;
+        if !c.eat(b':') {
+            return None;
+        }
+        let minute = c.digits(2)?;
+        let (mut second, mut millis) = (0, 0);
+        if c.eat(b':') {
+            second = c.digits(2)?;
+            if c.eat(b'.') {
+                millis = c.fraction_millis()?;
+            }
+        }
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+
+        let offset = if c.eat(b'Z') {
+            0
+        } else {
+            let sign = if c.eat(b'+') {
+                1
+            } else if c.eat(b'-') {
+                -1
+            } else {
+                return None;
+            };
+            let hours = c.digits(2)?;
+            c.eat(b':');
+            let minutes = c.digits(2)?;
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours as i64 * MILLIS_PER_HOUR + minutes as i64 * MILLIS_PER_MINUTE)
+        };
+        if !c.done() {
+            return None;
+        }
+        Some(Timestamp {
+            millis: days * MILLIS_PER_DAY
+                + hour as i64 * MILLIS_PER_HOUR
+                + minute as i64 * MILLIS_PER_MINUTE
+                + second as i64 * MILLIS_PER_SECOND
+                + millis
+                - offset,
+        })
+    }
+
+    pub fn checked_add(self, d: Duration) -> Option<Timestamp> {
+        self.millis
+            .checked_add(d.millis)
+            .map(Timestamp::from_unix_millis)
+    }
+
+    /// The duration from `other` to `self`.
+    pub fn checked_sub(self, other: Timestamp) -> Option<Duration> {
+        self.millis
+            .checked_sub(other.millis)
+            .map(Duration::from_millis)
+    }
+}
+
+/// Formats as ISO-8601 in UTC, e.g. `2024-03-01T10:00:00Z`, with
+/// milliseconds only if there are any.
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.millis.div_euclid(MILLIS_PER_DAY));
+        let t = self.millis.rem_euclid(MILLIS_PER_DAY);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            t / MILLIS_PER_HOUR,
+            t % MILLIS_PER_HOUR / MILLIS_PER_MINUTE,
+            t % MILLIS_PER_MINUTE / MILLIS_PER_SECOND,
+        )?;
+        if t % MILLIS_PER_SECOND != 0 {
+            write!(f, ".{:03}", t % MILLIS_PER_SECOND)?;
+        }
+        write!(f, "Z")
+    }
+}
+
+/// A span of time, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration {
+    millis: i64,
+}
+
+/// Sums the components of one part of an ISO-8601 duration, e.g. `1H30M`,
+/// whose units must appear in the order of `units`. Returns `None` if the
+/// part is malformed or empty.
+fn duration_part(part: &str, units: &[(u8, i64)]) -> Option<i64> {
+    let mut c = Cursor::new(part);
+    let mut next_unit = 0;
+    let mut total: i64 = 0;
+    while !c.done() {
+        let n = c.number()?;
+        let fraction = if c.eat(b'.') {
+            Some(c.fraction_millis()?)
+        } else {
+            None
+        };
+        let unit = c.peek()?;
+        c.pos += 1;
+        let i = next_unit + units[next_unit..].iter().position(|u| u.0 == unit)?;
+        next_unit = i + 1;
+        let millis = match fraction {
+            // Only seconds may have a fraction.
+            Some(_) if units[i].1 != MILLIS_PER_SECOND => return None,
+            Some(frac) => n.checked_mul(MILLIS_PER_SECOND)?.checked_add(frac)?,
+            None => n.checked_mul(units[i].1)?,
+        };
+        total = total.checked_add(millis)?;
+    }
+    if next_unit == 0 {
+        None
+    } else {
+        Some(total)
+    }
+}
+
+impl Duration {
+    pub fn from_millis(millis: i64) -> Duration {
+        Duration { millis }
+    }
+
+    pub fn millis(&self) -> i64 {
+        self.millis
+    }
+
+    /// Parses an ISO-8601 duration such as `P1DT12H` or `PT0.5S`, with an
+    /// optional leading `-`. Years and months are not accepted, and days are
+    /// 24 hours.
+    pub fn parse(s: &str) -> Option<Duration> {
+        let (negative, rest) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let rest = rest.strip_prefix('P')?;
+        let (date, time) = match rest.find('T') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        let date_millis = if date.is_empty() && time.is_some() {
+            0
+        } else {
+            duration_part(date, &[(b'W', 7 * MILLIS_PER_DAY), (b'D', MILLIS_PER_DAY)])?
+        };
+        let time_millis = match time {
+            Some(time) => duration_part(
+                time,
+                &[
+                    (b'H', MILLIS_PER_HOUR),
+                    (b'M', MILLIS_PER_MINUTE),
+                    (b'S', MILLIS_PER_SECOND),
+                ],
+            )?,
+            None => 0,
+        };
+        let millis = date_millis.checked_add(time_millis)?;
+        Some(Duration {
+            millis: if negative { -millis } else { millis },
+        })
+    }
+
+    pub fn checked_add(self, other: Duration) -> Option<Duration> {
+        self.millis
+            .checked_add(other.millis)
+            .map(Duration::from_millis)
+    }
+
+    pub fn checked_sub(self, other: Duration) -> Option<Duration> {
+        self.millis
+            .checked_sub(other.millis)
+            .map(Duration::from_millis)
+    }
+}
+
+/// Formats as ISO-8601, e.g. `P1DT2H30M` or `-PT1.5S`.
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.millis < 0 {
+            write!(f, "-")?;
+        }
+        let total = self.millis.unsigned_abs();
+        let day = MILLIS_PER_DAY as u64;
+        let (days, t) = (total / day, total % day);
+        write!(f, "P")?;
+        if days > 0 {
+            write!(f, "{}D", days)?;
+        }
+        if t == 0 && days > 0 {
+            return Ok(());
+        }
+        write!(f, "T")?;
+        let (hours, minutes) = (
+            t / MILLIS_PER_HOUR as u64,
+            t % MILLIS_PER_HOUR as u64 / MILLIS_PER_MINUTE as u64,
+        );
+        let millis = t % MILLIS_PER_MINUTE as u64;
+        if hours > 0 {
+            write!(f, "{}H", hours)?;
+        }
+        if minutes > 0 {
+            write!(f, "{}M", minutes)?;
+        }
+        if millis > 0 || t == 0 {
+            write!(f, "{}", millis / 1000)?;
+            if !millis.is_multiple_of(1000) {
+                let frac = format!("{:03}", millis % 1000);
+                write!(f, ".{}", frac.trim_end_matches('0'))?;
+            }
+            write!(f, "S")?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! impl_time_value {
+    ($ty:ident) => {
+        impl CustomValue for $ty {
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+
+            fn value_eq<'b, 'c>(
+                &self,
+                other: &dyn CustomValue,
+                _ectx: &mut EvalContext<'b, 'c>,
+            ) -> Result<Option<bool>, RuntimeError> {
+                Ok(other.as_any().downcast_ref::<$ty>().map(|o| self == o))
+            }
+
+            fn compare<'b, 'c>(
+                &self,
+                other: &dyn CustomValue,
+                _ectx: &mut EvalContext<'b, 'c>,
+            ) -> Result<Option<Ordering>, RuntimeError> {
+                Ok(other.as_any().downcast_ref::<$ty>().map(|o| self.cmp(o)))
+            }
+
+            fn hash<'b, 'c>(
+                &self,
+                state: &mut dyn Hasher,
+                _ectx: &mut EvalContext<'b, 'c>,
+            ) -> Result<bool, RuntimeError> {
+                state.write(stringify!($ty).as_bytes());
+                state.write_i64(self.millis);
+                Ok(true)
+            }
+
+            fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self)
+            }
+        }
+    };
+}
+
+impl_time_value!(Timestamp);
+impl_time_value!(Duration);
+
+/// Wraps `t` as a runtime value of type `timestamp`.
+pub fn timestamp_value<'b>(t: Timestamp) -> RuntimeValue<'b> {
+    RuntimeValue::Custom(CustomValueBox::new(Box::new(t)))
+}
+
+/// Wraps `d` as a runtime value of type `duration`.
+pub fn duration_value<'b>(d: Duration) -> RuntimeValue<'b> {
+    RuntimeValue::Custom(CustomValueBox::new(Box::new(d)))
+}
+
+fn downcast<T: Copy + 'static>(v: &RuntimeValue) -> Option<T> {
+    match *v {
+        RuntimeValue::Custom(ref cv) => cv.inner.as_any().downcast_ref::<T>().cloned(),
+        _ => None,
+    }
+}
+
+fn time_overflow() -> RuntimeError {
+    RuntimeError::Custom("time overflow".into())
+}
+
+/// `($timestamp text)`: parses an ISO-8601 date or date and time.
+#[derive(Debug)]
+pub struct TimestampOp;
+impl HostFunction for TimestampOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("text")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if *ty == string_type() || *ty == DataType::Dynamic => Ok(timestamp_type()),
+            _ => Err(TypeError::Custom("timestamp expects a string".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let text = params.next().unwrap().eval(ectx)?;
+        match as_str(&text) {
+            Some(s) => Timestamp::parse(s)
+                .map(timestamp_value)
+                .ok_or_else(|| RuntimeError::Custom(format!("invalid timestamp: {}", text))),
+            None => Err(type_mismatch("string", &text)),
+        }
+    }
+}
+
+/// `($duration x)`: `x` seconds if it is an int, or the ISO-8601 duration
+/// `x` if it is a string.
+#[derive(Debug)]
+pub struct DurationOp;
+impl HostFunction for DurationOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("x")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty]
+                if *ty == DataType::Value(ValueType::Int)
+                    || *ty == string_type()
+                    || *ty == DataType::Dynamic =>
+            {
+                Ok(duration_type())
+            }
+            _ => Err(TypeError::Custom(
+                "duration expects an int or a string".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let x = params.next().unwrap().eval(ectx)?;
+        if let RuntimeValue::Int(seconds) = x {
+            return seconds
+                .checked_mul(MILLIS_PER_SECOND)
+                .map(|ms| duration_value(Duration::from_millis(ms)))
+                .ok_or_else(time_overflow);
+        }
+        match as_str(&x) {
+            Some(s) => Duration::parse(s)
+                .map(duration_value)
+                .ok_or_else(|| RuntimeError::Custom(format!("invalid duration: {}", x))),
+            None => Err(type_mismatch("int or string", &x)),
+        }
+    }
+}
+
+/// `($time_add t d)`: the timestamp or duration `t` moved by the duration
+/// `d`.
+#[derive(Debug)]
+pub struct TimeAddOp;
+impl HostFunction for TimeAddOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("t"),
+            Param::required("d"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        match *params {
+            [ref t, ref d]
+                if is_duration_type(d) && (is_timestamp_type(t) || is_duration_type(t)) =>
+            {
+                Ok(t.clone())
+            }
+            _ => Err(TypeError::Custom(
+                "time_add expects a timestamp or duration and a duration".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let t = params.next().unwrap().eval(ectx)?;
+        let d = params.next().unwrap().eval(ectx)?;
+        let d: Duration = downcast(&d).ok_or_else(|| type_mismatch("duration", &d))?;
+        if let Some(t) = downcast::<Timestamp>(&t) {
+            t.checked_add(d)
+                .map(timestamp_value)
+                .ok_or_else(time_overflow)
+        } else if let Some(t) = downcast::<Duration>(&t) {
+            t.checked_add(d)
+                .map(duration_value)
+                .ok_or_else(time_overflow)
+        } else {
+            Err(type_mismatch("timestamp or duration", &t))
+        }
+    }
+}
+
+/// `($time_diff a b)`: the duration from `b` to `a`, two timestamps or two
+/// durations.
+#[derive(Debug)]
+pub struct TimeDiffOp;
+impl HostFunction for TimeDiffOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("a"),
+            Param::required("b"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        match *params {
+            [ref a, ref b]
+                if (is_timestamp_type(a) && is_timestamp_type(b))
+                    || (is_duration_type(a) && is_duration_type(b)) =>
+            {
+                Ok(duration_type())
+            }
+            _ => Err(TypeError::Custom(
+                "time_diff expects two timestamps or two durations".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let a = params.next().unwrap().eval(ectx)?;
+        let b = params.next().unwrap().eval(ectx)?;
+        let diff = match (downcast::<Timestamp>(&a), downcast::<Timestamp>(&b)) {
+            (Some(a), Some(b)) => a.checked_sub(b),
+            _ => match (downcast::<Duration>(&a), downcast::<Duration>(&b)) {
+                (Some(a), Some(b)) => a.checked_sub(b),
+                _ => {
+                    return Err(RuntimeError::TypeMismatch(format!(
+                        "expecting two timestamps or two durations, found {} and {}",
+                        a, b
+                    )))
+                }
+            },
+        };
+        diff.map(duration_value).ok_or_else(time_overflow)
+    }
+}
+
+/// `($duration_seconds d)`: the whole seconds in `d`, rounded towards zero.
+#[derive(Debug)]
+pub struct DurationSecondsOp;
+impl HostFunction for DurationSecondsOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("d")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if is_duration_type(ty) => Ok(DataType::Value(ValueType::Int)),
+            _ => Err(TypeError::Custom(
+                "duration_seconds expects a duration".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let d = params.next().unwrap().eval(ectx)?;
+        match downcast::<Duration>(&d) {
+            Some(d) => Ok(RuntimeValue::Int(d.millis() / MILLIS_PER_SECOND)),
+            None => Err(type_mismatch("duration", &d)),
+        }
+    }
+}
+
+/// `($now offset)`: the current time moved by the duration `offset`, e.g.
+/// `($now ($duration "-P30D"))` for thirty days ago. Host functions are
+/// only called when applied, so the current time itself is
+/// `($now ($duration 0))`.
+#[derive(Debug)]
+pub struct NowOp;
+impl HostFunction for NowOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("offset")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if is_duration_type(ty) => Ok(timestamp_type()),
+            _ => Err(TypeError::Custom("now expects a duration".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let offset = params.next().unwrap().eval(ectx)?;
+        let offset: Duration =
+            downcast(&offset).ok_or_else(|| type_mismatch("duration", &offset))?;
+        let millis = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as i64,
+            Err(e) => -(e.duration().as_millis() as i64),
+        };
+        Timestamp::from_unix_millis(millis)
+            .checked_add(offset)
+            .map(timestamp_value)
+            .ok_or_else(time_overflow)
+    }
+}
+
+/// `$timestamp`, `$duration`, `$time_add`, `$time_diff` and
+/// `$duration_seconds`.
+pub fn time_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![
+        ("timestamp", Arc::new(TimestampOp)),
+        ("duration", Arc::new(DurationOp)),
+        ("time_add", Arc::new(TimeAddOp)),
+        ("time_diff", Arc::new(TimeDiffOp)),
+        ("duration_seconds", Arc::new(DurationSecondsOp)),
+    ]
+}
+
+/// `$now`.
+pub fn clock_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![("now", Arc::new(NowOp))]
+}
@@ -0,0 +1,127 @@
+use crate::corelib::Profile;
+use crate::datetime::{Duration, Timestamp};
+use crate::engine::Engine;
+use crate::error::*;
+use crate::typeck::TypeDescription;
+
+#[test]
+fn test_timestamp_parse() {
+    let ts = |s: &str| Timestamp::parse(s).map(|t| t.to_string());
+    assert_eq!(ts("1970-01-01").unwrap(), "1970-01-01T00:00:00Z");
+    assert_eq!(
+        Timestamp::parse("1970-01-02T00:00:01.5Z")
+            .unwrap()
+            .unix_millis(),
+        86_401_500
+    );
+    assert_eq!(
+        ts("2024-03-01T12:00:00+02:00").unwrap(),
+        "2024-03-01T10:00:00Z"
+    );
+    assert_eq!(ts("2024-02-29T23:30-0100").unwrap(), "2024-03-01T00:30:00Z");
+    assert_eq!(
+        ts("1969-12-31T23:59:59.999Z").unwrap(),
+        "1969-12-31T23:59:59.999Z"
+    );
+    assert_eq!(
+        ts("2000-01-01T00:00:00.1234567Z").unwrap(),
+        "2000-01-01T00:00:00.123Z"
+    );
+    for bad in &[
+        "2023-02-29",
+        "2024-13-01",
+        "2024-03-01T24:00Z",
+        "2024-03-01T12:00",
+        "2024-03-01T12:00Zjunk",
+        "24-03-01",
+        "",
+    ] {
+        assert!(Timestamp::parse(bad).is_none(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_duration_parse() {
+    let dur = |s: &str| Duration::parse(s).map(|d| d.to_string());
+    assert_eq!(dur("PT1H30M").unwrap(), "PT1H30M");
+    assert_eq!(dur("P1W").unwrap(), "P7D");
+    assert_eq!(dur("P1DT36H").unwrap(), "P2DT12H");
+    assert_eq!(dur("-PT1.5S").unwrap(), "-PT1.5S");
+    assert_eq!(dur("PT0S").unwrap(), "PT0S");
+    assert_eq!(Duration::parse("PT2M").unwrap().millis(), 120_000);
+    for bad in &[
+        "P", "PT", "P1DT", "P1M", "P1Y", "PT1M1H", "PT1.5M", "1H", "PT1H1H",
+    ] {
+        assert!(Duration::parse(bad).is_none(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_time_ops() {
+    let engine = Engine::new();
+    let eval = |s: &str| engine.eval_str(s).unwrap();
+    assert_eq!(
+        eval(r#"($time_add ($timestamp "2024-01-31") ($duration "P1DT1H"))"#),
+        "2024-02-01T01:00:00Z"
+    );
+    assert_eq!(
+        eval(r#"($time_diff ($timestamp "2024-03-01") ($timestamp "2024-02-01"))"#),
+        "P29D"
+    );
+    assert_eq!(
+        eval(r#"($time_add ($duration 90) ($duration "PT30S"))"#),
+        "PT2M"
+    );
+    assert_eq!(
+        eval(r#"($duration_seconds ($duration "-PT1M1.9S"))"#),
+        "-61"
+    );
+    assert_eq!(
+        eval(r#"($lt ($timestamp "2024-01-01") ($timestamp "2023-12-31T23:00:00-02:00"))"#),
+        "true"
+    );
+    assert_eq!(
+        eval(r#"($eq ($duration "PT60M") ($duration "PT1H"))"#),
+        "true"
+    );
+    assert_eq!(
+        engine
+            .infer_type(r#"($time_add ($timestamp "2024-01-01") ($duration 1))"#)
+            .unwrap(),
+        TypeDescription::Custom("timestamp".into())
+    );
+    assert!(matches!(
+        engine.eval_str(r#"($lt ($timestamp "2024-01-01") ($duration 1))"#),
+        Err(Error::Type(_))
+    ));
+    assert!(matches!(
+        engine.eval_str(r#"($time_add ($duration 1) ($timestamp "2024-01-01"))"#),
+        Err(Error::Type(_))
+    ));
+    match engine.eval_str(r#"($timestamp "2024-02-30")"#) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert_eq!(msg, r#"invalid timestamp: "2024-02-30""#)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_now_is_gated() {
+    let mut engine = Engine::new();
+    assert_eq!(
+        engine
+            .eval_str(r#"($lt ($timestamp "2020-01-01") ($now ($duration 0)))"#)
+            .unwrap(),
+        "true"
+    );
+    assert_eq!(
+        engine
+            .eval_str(r#"($gt ($now ($duration "P1D")) ($now ($duration 0)))"#)
+            .unwrap(),
+        "true"
+    );
+    engine.set_profile(Profile::DataTransform);
+    assert!(engine.eval_str(r#"($timestamp "2020-01-01")"#).is_ok());
+    assert!(engine.eval_str("($now ($duration 0))").is_err());
+}
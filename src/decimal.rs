@@ -0,0 +1,520 @@
+//! Exact decimal numbers, for rules that cannot tolerate the rounding of
+//! `float` or the overflow of `int`.
+//!
+//! A `decimal` is an integer of up to 38 digits scaled by a power of ten,
+//! with at most `MAX_SCALE` digits after the point. Scripts make decimals
+//! with `$decimal`, from ints or from strings such as `"12.50"`.
+//!
+//! `$add`, `$sub`, `$mul` and `$mod` are exact on decimals, and fail rather
+//! than round or overflow. Ints mixed with decimals are promoted to
+//! decimals, while mixing floats with decimals is a type error: convert one
+//! side with `$decimal` or `$decimal_to_float`. `$div` fails if the quotient
+//! has more than `MAX_SCALE` digits after the point; `$decimal_div` rounds
+//! it to a given number of places instead. `$round` rounds decimals half
+//! away from zero, as it does floats.
+
+use crate::ast::{CustomDataType, DataType};
+use crate::builtin::ValueType;
+use crate::corelib::{as_str, string_type, type_mismatch};
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::Hasher;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The most digits a decimal has after the point.
+pub const MAX_SCALE: u32 = 28;
+
+#[derive(Debug)]
+pub struct DecimalType;
+
+impl CustomDataType for DecimalType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<DecimalType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "decimal")
+    }
+}
+
+pub fn decimal_type() -> DataType {
+    DataType::Custom(Rc::new(Box::new(DecimalType)))
+}
+
+pub fn is_decimal_type(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Custom(ref inner) => inner.as_any().is::<DecimalType>(),
+        _ => false,
+    }
+}
+
+/// Whether an arithmetic or ordering operator on `a` and `b` works on
+/// decimals: both are ints or decimals, and at least one is a decimal.
+pub(crate) fn decimal_operands(a: &DataType, b: &DataType) -> bool {
+    let operand = |ty: &DataType| *ty == DataType::Value(ValueType::Int) || is_decimal_type(ty);
+    operand(a) && operand(b) && (is_decimal_type(a) || is_decimal_type(b))
+}
+
+/// `mantissa / 10^scale`, with trailing zeros after the point removed so
+/// that equal numbers have equal representations.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+fn pow10(n: u32) -> Option<i128> {
+    10i128.checked_pow(n)
+}
+
+/// `q` rounded half away from zero, given the remainder `r` of dividing by
+/// `den`.
+fn round_quotient(q: i128, r: i128, den: i128) -> Option<i128> {
+    if r.unsigned_abs() >= den.unsigned_abs() - r.unsigned_abs() {
+        if (r < 0) != (den < 0) {
+            q.checked_sub(1)
+        } else {
+            q.checked_add(1)
+        }
+    } else {
+        Some(q)
+    }
+}
+
+impl Decimal {
+    /// `mantissa / 10^scale`, or `None` if that has more than `MAX_SCALE`
+    /// digits after the point.
+    pub fn new(mut mantissa: i128, mut scale: u32) -> Option<Decimal> {
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        if scale > MAX_SCALE {
+            return None;
+        }
+        Some(Decimal { mantissa, scale })
+    }
+
+    pub fn from_int(v: i64) -> Decimal {
+        Decimal {
+            mantissa: v as i128,
+            scale: 0,
+        }
+    }
+
+    /// Parses a number such as `-12.50`. Exponents are not accepted.
+    pub fn parse(s: &str) -> Option<Decimal> {
+        let (negative, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (int_part, frac_part) = match digits.find('.') {
+            Some(i) => (&digits[..i], &digits[i + 1..]),
+            None => (digits, ""),
+        };
+        if int_part.is_empty() && frac_part.is_empty() {
+            return None;
+        }
+        let mut mantissa: i128 = 0;
+        for c in int_part.bytes().chain(frac_part.bytes()) {
+            if !c.is_ascii_digit() {
+                return None;
+            }
+            mantissa = mantissa.checked_mul(10)?.checked_add((c - b'0') as i128)?;
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+        Decimal::new(mantissa, frac_part.len() as u32)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    /// The value as an `i64`, if it is an integer in range.
+    pub fn to_int(&self) -> Option<i64> {
+        if self.scale == 0 && self.mantissa >= i64::MIN as i128 && self.mantissa <= i64::MAX as i128
+        {
+            Some(self.mantissa as i64)
+        } else {
+            None
+        }
+    }
+
+    /// The nearest float.
+    pub fn to_float(&self) -> f64 {
+        self.to_string().parse().unwrap()
+    }
+
+    /// The mantissa at `scale`, which must be at least `self.scale`.
+    fn rescale(&self, scale: u32) -> Option<i128> {
+        self.mantissa.checked_mul(pow10(scale - self.scale)?)
+    }
+
+    pub fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        Decimal::new(
+            self.rescale(scale)?.checked_add(other.rescale(scale)?)?,
+            scale,
+        )
+    }
+
+    pub fn checked_sub(self, other: Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        Decimal::new(
+            self.rescale(scale)?.checked_sub(other.rescale(scale)?)?,
+            scale,
+        )
+    }
+
+    pub fn checked_mul(self, other: Decimal) -> Option<Decimal> {
+        Decimal::new(
+            self.mantissa.checked_mul(other.mantissa)?,
+            self.scale + other.scale,
+        )
+    }
+
+    /// The remainder of dividing by `other`, with the sign of `self`.
+    /// `None` if `other` is zero.
+    pub fn checked_rem(self, other: Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        Decimal::new(
+            self.rescale(scale)?.checked_rem(other.rescale(scale)?)?,
+            scale,
+        )
+    }
+
+    /// `self / other` as `(q, r, den)`, where `q` is the quotient truncated
+    /// to `places` digits after the point and `r / den` is what was cut off,
+    /// scaled so that `q` is an integer.
+    fn div_parts(self, other: Decimal, places: u32) -> Option<(i128, i128, i128)> {
+        // self / other = (m1 * 10^s2) / (m2 * 10^s1)
+        let num = self.mantissa.checked_mul(pow10(other.scale + places)?)?;
+        let den = other.mantissa.checked_mul(pow10(self.scale)?)?;
+        Some((num.checked_div(den)?, num.checked_rem(den)?, den))
+    }
+
+    /// The exact quotient, or `None` if `other` is zero or the quotient has
+    /// more than `MAX_SCALE` digits after the point.
+    pub fn checked_div(self, other: Decimal) -> Option<Decimal> {
+        for places in 0..=MAX_SCALE {
+            match self.div_parts(other, places)? {
+                (q, 0, _) => return Decimal::new(q, places),
+                _ => continue,
+            }
+        }
+        None
+    }
+
+    /// The quotient rounded half away from zero to `places` digits after
+    /// the point. `None` if `other` is zero or the result overflows.
+    pub fn div_rounded(self, other: Decimal, places: u32) -> Option<Decimal> {
+        let (q, r, den) = self.div_parts(other, places.min(MAX_SCALE))?;
+        Decimal::new(round_quotient(q, r, den)?, places.min(MAX_SCALE))
+    }
+
+    /// Rounds half away from zero to `places` digits after the point, or to
+    /// a multiple of `10^-places` if `places` is negative.
+    pub fn round(self, places: i64) -> Option<Decimal> {
+        if places >= self.scale as i64 {
+            return Some(self);
+        }
+        let dropped = (self.scale as i64 - places) as u32;
+        let den = match pow10(dropped) {
+            Some(den) => den,
+            // Rounding away more digits than an i128 has.
+            None => return Some(Decimal::from_int(0)),
+        };
+        let q = round_quotient(self.mantissa / den, self.mantissa % den, den)?;
+        if places >= 0 {
+            Decimal::new(q, places as u32)
+        } else {
+            Decimal::new(q.checked_mul(pow10((-places) as u32)?)?, 0)
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.mantissa == other.mantissa && self.scale == other.scale
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        // Compare the integer parts first, so that nothing overflows.
+        let split = |d: &Decimal| {
+            let unit = pow10(d.scale).unwrap();
+            let frac = d.mantissa % unit;
+            (
+                d.mantissa / unit,
+                frac * pow10(MAX_SCALE - d.scale).unwrap(),
+            )
+        };
+        split(self).cmp(&split(other))
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        if self.mantissa < 0 {
+            write!(f, "-")?;
+        }
+        if scale == 0 {
+            write!(f, "{}", digits)
+        } else if digits.len() > scale {
+            let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+            write!(f, "{}.{}", int_part, frac_part)
+        } else {
+            write!(f, "0.{}{}", "0".repeat(scale - digits.len()), digits)
+        }
+    }
+}
+
+impl CustomValue for Decimal {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn value_eq<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<bool>, RuntimeError> {
+        Ok(other.as_any().downcast_ref::<Decimal>().map(|o| self == o))
+    }
+
+    fn compare<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<Ordering>, RuntimeError> {
+        Ok(other
+            .as_any()
+            .downcast_ref::<Decimal>()
+            .map(|o| self.cmp(o)))
+    }
+
+    fn hash<'b, 'c>(
+        &self,
+        state: &mut dyn Hasher,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<bool, RuntimeError> {
+        // Integers hash like ints, which they are equal to.
+        match self.to_int() {
+            Some(v) => {
+                state.write_u8(1);
+                state.write_i64(v);
+            }
+            None => {
+                state.write_u8(3);
+                state.write_i128(self.mantissa);
+                state.write_u32(self.scale);
+            }
+        }
+        Ok(true)
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Wraps `d` as a runtime value of type `decimal`.
+pub fn decimal_value<'b>(d: Decimal) -> RuntimeValue<'b> {
+    RuntimeValue::Custom(CustomValueBox::new(Box::new(d)))
+}
+
+/// Returns `v` as a decimal if it is a decimal or an int.
+pub fn as_decimal(v: &RuntimeValue) -> Option<Decimal> {
+    match *v {
+        RuntimeValue::Int(v) => Some(Decimal::from_int(v)),
+        RuntimeValue::Custom(ref cv) => cv.inner.as_any().downcast_ref::<Decimal>().cloned(),
+        _ => None,
+    }
+}
+
+pub(crate) fn decimal_overflow() -> RuntimeError {
+    RuntimeError::Custom("decimal overflow".into())
+}
+
+fn eval_decimal<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: LazyValue<'b>,
+) -> Result<Decimal, RuntimeError> {
+    let v = v.eval(ectx)?;
+    as_decimal(&v)
+        .ok_or_else(|| RuntimeError::TypeMismatch(format!("expecting decimal or int, found {}", v)))
+}
+
+/// `($decimal x)`: `x`, an int, a decimal or a string such as `"-12.50"`,
+/// as a decimal.
+#[derive(Debug)]
+pub struct DecimalOp;
+impl HostFunction for DecimalOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("x")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty]
+                if *ty == DataType::Value(ValueType::Int)
+                    || *ty == string_type()
+                    || *ty == DataType::Dynamic
+                    || is_decimal_type(ty) =>
+            {
+                Ok(decimal_type())
+            }
+            [ref ty] => Err(TypeError::Custom(format!(
+                "decimal expects an int, a decimal or a string, found {}",
+                ty
+            ))),
+            _ => Err(TypeError::Custom("invalid param count for decimal".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let x = params.next().unwrap().eval(ectx)?;
+        if let Some(d) = as_decimal(&x) {
+            return Ok(decimal_value(d));
+        }
+        match as_str(&x) {
+            Some(s) => Decimal::parse(s)
+                .map(decimal_value)
+                .ok_or_else(|| RuntimeError::Custom(format!("invalid decimal: {}", x))),
+            None => Err(RuntimeError::TypeMismatch(format!(
+                "expecting an int, a decimal or a string, found {}",
+                x
+            ))),
+        }
+    }
+}
+
+/// `($decimal_div a b places)`: `a / b` rounded half away from zero to
+/// `places` digits after the point.
+#[derive(Debug)]
+pub struct DecimalDivOp;
+impl HostFunction for DecimalDivOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("a"),
+            Param::required("b"),
+            Param::required("places"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        let operand = |ty: &DataType| {
+            *ty == DataType::Value(ValueType::Int)
+                || *ty == DataType::Dynamic
+                || is_decimal_type(ty)
+        };
+        match *params {
+            [ref a, ref b, ref places]
+                if operand(a)
+                    && operand(b)
+                    && (*places == DataType::Value(ValueType::Int)
+                        || *places == DataType::Dynamic) =>
+            {
+                Ok(decimal_type())
+            }
+            _ => Err(TypeError::Custom(
+                "decimal_div expects two decimals or ints and an int".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let a = eval_decimal(ectx, params.next().unwrap())?;
+        let b = eval_decimal(ectx, params.next().unwrap())?;
+        let places = match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Int(v) if v >= 0 && v <= MAX_SCALE as i64 => v as u32,
+            RuntimeValue::Int(v) => {
+                return Err(RuntimeError::Custom(format!(
+                    "decimal places out of range: {}",
+                    v
+                )))
+            }
+            v => return Err(type_mismatch("int", &v)),
+        };
+        if b.is_zero() {
+            return Err(RuntimeError::DivByZero);
+        }
+        a.div_rounded(b, places)
+            .map(decimal_value)
+            .ok_or_else(decimal_overflow)
+    }
+}
+
+/// `($decimal_to_float d)`: the float nearest to `d`.
+#[derive(Debug)]
+pub struct DecimalToFloatOp;
+impl HostFunction for DecimalToFloatOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("d")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if is_decimal_type(ty) || *ty == DataType::Dynamic => {
+                Ok(DataType::Value(ValueType::Float))
+            }
+            _ => Err(TypeError::Custom(
+                "decimal_to_float expects a decimal".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let d = eval_decimal(ectx, params.next().unwrap())?;
+        Ok(RuntimeValue::Float(d.to_float()))
+    }
+}
+
+/// `$decimal`, `$decimal_div` and `$decimal_to_float`.
+pub fn decimal_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![
+        ("decimal", Arc::new(DecimalOp)),
+        ("decimal_div", Arc::new(DecimalDivOp)),
+        ("decimal_to_float", Arc::new(DecimalToFloatOp)),
+    ]
+}
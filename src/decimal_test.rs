@@ -0,0 +1,117 @@
+use crate::corelib::Profile;
+use crate::decimal::Decimal;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::typeck::TypeDescription;
+
+#[test]
+fn test_decimal_repr() {
+    let d = |s: &str| Decimal::parse(s).unwrap();
+    assert_eq!(d("12.50").to_string(), "12.5");
+    assert_eq!(d(".5").to_string(), "0.5");
+    assert_eq!(d("-0.05").to_string(), "-0.05");
+    assert_eq!(d("-3.").to_string(), "-3");
+    assert_eq!(d("1.10"), d("1.1"));
+    assert!(d("-1.5") < d("-1.2"));
+    assert!(d("100000000000000000000") > d("99999999999999999999.99"));
+    assert!(Decimal::parse("1.2.3").is_none());
+    assert!(Decimal::parse("1e3").is_none());
+    assert!(Decimal::parse("-").is_none());
+    assert_eq!(d("2.5").round(0).unwrap().to_string(), "3");
+    assert_eq!(d("-2.5").round(0).unwrap().to_string(), "-3");
+    assert_eq!(d("1250").round(-2).unwrap().to_string(), "1300");
+    assert_eq!(d("2").div_rounded(d("3"), 4).unwrap().to_string(), "0.6667");
+    assert_eq!(d("1").checked_div(d("8")).unwrap().to_string(), "0.125");
+    assert!(d("1").checked_div(d("3")).is_none());
+}
+
+#[test]
+fn test_decimal_arithmetic() {
+    let engine = Engine::new();
+    let eval = |s: &str| engine.eval_str(s).unwrap();
+    assert_eq!(eval(r#"($add ($decimal "0.1") ($decimal "0.2"))"#), "0.3");
+    assert_eq!(
+        eval(r#"($eq ($add ($decimal "0.1") ($decimal "0.2")) ($decimal "0.3"))"#),
+        "true"
+    );
+    assert_eq!(eval(r#"($mul ($decimal "19.99") 3)"#), "59.97");
+    assert_eq!(eval(r#"($sub 1 ($decimal "0.01"))"#), "0.99");
+    assert_eq!(eval(r#"($mod ($decimal "7.5") 2)"#), "1.5");
+    assert_eq!(eval("($div ($decimal 1) 4)"), "0.25");
+    assert_eq!(eval("($decimal_div 2 3 2)"), "0.67");
+    assert_eq!(eval(r#"($decimal_div ($decimal "-2") 3 2)"#), "-0.67");
+    assert_eq!(eval(r#"($round ($decimal "2.345") 2)"#), "2.35");
+    assert_eq!(eval(r#"($decimal_to_float ($decimal "0.5"))"#), "0.5");
+    // Beyond the range of an int.
+    assert_eq!(
+        eval("($mul ($decimal 9223372036854775807) 10)"),
+        "92233720368547758070"
+    );
+    assert_eq!(eval(r#"($lt 1 ($decimal "1.5"))"#), "true");
+    assert_eq!(eval(r#"($ge ($decimal "1.5") ($decimal "1.50"))"#), "true");
+    assert_eq!(eval(r#"($eq ($decimal "2.0") 2)"#), "true");
+    assert_eq!(
+        engine.infer_type(r#"($add 1 ($decimal "1"))"#).unwrap(),
+        TypeDescription::Decimal
+    );
+    assert_eq!(
+        engine
+            .infer_type(r#"(the decimal ($decimal "1"))"#)
+            .unwrap(),
+        TypeDescription::Decimal
+    );
+}
+
+#[test]
+fn test_decimal_errors() {
+    let engine = Engine::new();
+    match engine.eval_str(r#"($add ($decimal "1") 1.5)"#) {
+        Err(Error::Type(TypeError::Custom(msg))) => assert_eq!(
+            msg,
+            "cannot mix decimal and float: convert with $decimal or $decimal_to_float"
+        ),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(matches!(
+        engine.eval_str(r#"($lt ($decimal "1") 1.5)"#),
+        Err(Error::Type(_))
+    ));
+    assert!(matches!(
+        engine.eval_str("(the decimal 1)"),
+        Err(Error::Type(_))
+    ));
+    match engine.eval_str("($div ($decimal 1) 3)") {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert_eq!(msg, "inexact decimal quotient: round it with $decimal_div")
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(matches!(
+        engine.eval_str("($div ($decimal 1) 0)"),
+        Err(Error::Runtime(RuntimeError::DivByZero))
+    ));
+    match engine.eval_str(r#"($decimal "1.2.3")"#) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert_eq!(msg, r#"invalid decimal: "1.2.3""#)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match engine
+        .eval_str(r#"($mul ($decimal "100000000000000000000") ($decimal "100000000000000000000"))"#)
+    {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => assert_eq!(msg, "decimal overflow"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_decimal_profiles() {
+    let mut engine = Engine::new();
+    engine.set_profile(Profile::PureMath);
+    assert_eq!(
+        engine
+            .eval_str(r#"($decimal_div ($decimal "10") 4 1)"#)
+            .unwrap(),
+        "2.5"
+    );
+}
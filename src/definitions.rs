@@ -0,0 +1,237 @@
+use crate::ast::*;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A named top-level definition.
+///
+/// Definitions are parsed with their own name (and every other defined name)
+/// visible as a global, so their bodies only refer to other definitions by
+/// unrenamed name.
+#[derive(Debug)]
+pub struct Definition {
+    pub expr: Expr,
+    pub generation: u64,
+    ty: RefCell<Option<DataType>>,
+}
+
+impl Definition {
+    /// Returns the cached type of this definition, if it has been checked
+    /// since it was last (re)defined.
+    pub fn cached_type(&self) -> Option<DataType> {
+        self.ty.borrow().clone()
+    }
+
+    pub(crate) fn set_cached_type(&self, ty: DataType) {
+        *self.ty.borrow_mut() = Some(ty);
+    }
+}
+
+/// Session-scoped store of definitions shared between `TypeResolveState`
+/// and `EvalContext`.
+///
+/// Types are cached on the definitions themselves; evaluated values are
+/// cached per `EvalContext` and keyed by the definition generation, so
+/// replacing a definition invalidates both.
+#[derive(Debug, Default)]
+pub struct Definitions {
+    entries: BTreeMap<String, Definition>,
+    next_generation: u64,
+}
+
+impl Definitions {
+    /// Adds or replaces a definition, returning the old expression if any.
+    ///
+    /// Cached types of the replaced definition and of every definition that
+    /// (transitively) refers to it are dropped.
+    pub fn define(&mut self, name: String, expr: Expr) -> Option<Expr> {
+        self.next_generation += 1;
+        let old = self.entries.insert(
+            name.clone(),
+            Definition {
+                expr,
+                generation: self.next_generation,
+                ty: RefCell::new(None),
+            },
+        );
+        if old.is_some() {
+            self.invalidate_dependents(&name);
+        }
+        old.map(|v| v.expr)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Expr> {
+        self.next_generation += 1;
+        let old = self.entries.remove(name);
+        if old.is_some() {
+            self.invalidate_dependents(name);
+        }
+        old.map(|v| v.expr)
+    }
+
+    /// Returns a counter that changes whenever a definition is added,
+    /// replaced or removed.
+    pub fn generation(&self) -> u64 {
+        self.next_generation
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Definition> {
+        self.entries.get(name)
+    }
+
+    /// Returns the set of defined names, for use as parser globals.
+    pub fn names(&self) -> BTreeSet<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Returns the host functions `name` may call, directly or through the
+    /// definitions it refers to.
+    pub fn hosts_used(&self, name: &str) -> BTreeSet<String> {
+        let graph = self.dependency_graph();
+        graph
+            .reachable(&[name])
+            .iter()
+            .flat_map(|n| graph.hosts[n].iter().cloned())
+            .collect()
+    }
+
+    /// Returns which definitions and host functions each definition refers
+    /// to.
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        for (name, def) in &self.entries {
+            let mut hosts = BTreeSet::new();
+            collect_hosts(&def.expr, &mut hosts);
+            let refs = free_vars(&def.expr)
+                .into_iter()
+                .filter(|n| self.entries.contains_key(n))
+                .collect();
+            graph.definitions.insert(name.clone(), refs);
+            graph.hosts.insert(name.clone(), hosts);
+        }
+        graph
+    }
+
+    fn invalidate_dependents(&mut self, name: &str) {
+        let mut dirty: BTreeSet<String> = BTreeSet::new();
+        dirty.insert(name.to_string());
+
+        loop {
+            let mut changed = false;
+            for (k, def) in &self.entries {
+                if !dirty.contains(k) && dirty.iter().any(|d| refers_to(&def.expr, d)) {
+                    dirty.insert(k.clone());
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        for k in &dirty {
+            if let Some(def) = self.entries.get_mut(k) {
+                *def.ty.get_mut() = None;
+            }
+        }
+    }
+}
+
+/// Which definitions and host functions each definition of a
+/// `Definitions` store refers to, for finding unused definitions and the
+/// definitions a change affects.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependencyGraph {
+    /// For each definition, the definitions it refers to.
+    pub definitions: BTreeMap<String, BTreeSet<String>>,
+    /// For each definition, the host functions it calls, without the `$`.
+    pub hosts: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DependencyGraph {
+    /// Returns `roots` and the definitions they depend on, directly or
+    /// through others. Names that are not defined are left out.
+    pub fn reachable<S: AsRef<str>>(&self, roots: &[S]) -> BTreeSet<String> {
+        let mut seen = BTreeSet::new();
+        let mut pending: Vec<&str> = roots.iter().map(|r| r.as_ref()).collect();
+        while let Some(n) = pending.pop() {
+            if let Some(refs) = self.definitions.get(n) {
+                if seen.insert(n.to_string()) {
+                    pending.extend(refs.iter().map(|r| r.as_str()));
+                }
+            }
+        }
+        seen
+    }
+
+    /// Returns the definitions `roots` do not depend on, which can be
+    /// removed without changing what `roots` evaluate to.
+    pub fn unreachable<S: AsRef<str>>(&self, roots: &[S]) -> BTreeSet<String> {
+        let reachable = self.reachable(roots);
+        self.definitions
+            .keys()
+            .filter(|n| !reachable.contains(*n))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the definitions that depend on `name`, directly or through
+    /// others: those a change to `name` may affect.
+    pub fn dependents(&self, name: &str) -> BTreeSet<String> {
+        let mut out: BTreeSet<String> = BTreeSet::new();
+        let mut pending = vec![name];
+        while let Some(n) = pending.pop() {
+            for (k, refs) in &self.definitions {
+                if refs.contains(n) && out.insert(k.clone()) {
+                    pending.push(k);
+                }
+            }
+        }
+        out
+    }
+
+    /// Renders the graph in Graphviz DOT format. Host functions are drawn
+    /// as boxes, with dashed edges.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph deps {\n");
+        for (name, refs) in &self.definitions {
+            out.push_str(&format!("    {:?};\n", name));
+            for r in refs {
+                out.push_str(&format!("    {:?} -> {:?};\n", name, r));
+            }
+        }
+        let hosts: BTreeSet<&String> = self.hosts.values().flatten().collect();
+        for h in hosts {
+            out.push_str(&format!("    {:?} [shape=box];\n", format!("${}", h)));
+        }
+        for (name, hosts) in &self.hosts {
+            for h in hosts {
+                out.push_str(&format!(
+                    "    {:?} -> {:?} [style=dashed];\n",
+                    name,
+                    format!("${}", h)
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn refers_to(e: &Expr, name: &str) -> bool {
+    match *e.body {
+        ExprBody::Name(ref n) => n == name,
+        ExprBody::Apply {
+            ref target,
+            ref params,
+        } => refers_to(target, name) || params.iter().any(|p| refers_to(p, name)),
+        ExprBody::Abstract {
+            body: AbstractBody::Expr(ref e),
+            ..
+        } => refers_to(e, name),
+        ExprBody::Match {
+            ref value,
+            ref branches,
+        } => refers_to(value, name) || branches.iter().any(|(_, e)| refers_to(e, name)),
+        _ => false,
+    }
+}
@@ -0,0 +1,90 @@
+use crate::ast::*;
+use crate::builtin::ValueType;
+use crate::corelib::HostManager;
+use crate::definitions::Definitions;
+use crate::eval::*;
+use crate::parser::parse_expr_with_globals;
+use crate::typeck::*;
+
+fn define(defs: &mut Definitions, name: &str, src: &str) {
+    let mut globals = defs.names();
+    globals.insert(name.to_string());
+    let e = parse_expr_with_globals(src, globals).unwrap();
+    defs.define(name.to_string(), e);
+}
+
+#[test]
+fn test_definitions() {
+    let hm = HostManager::new();
+    let mut defs = Definitions::default();
+    define(&mut defs, "double", r"(\x ($add x x))");
+    define(&mut defs, "four", "(double 2)");
+
+    let e = parse_expr_with_globals("(four)", defs.names()).unwrap();
+    {
+        let mut trs = TypeResolveState::default();
+        trs.add_hosts(hm.get_binops());
+        trs.set_definitions(&defs);
+        assert_eq!(
+            check_expr(&e, &mut trs).unwrap(),
+            DataType::Value(ValueType::Int)
+        );
+        assert!(defs.get("four").unwrap().cached_type().is_some());
+
+        let mut ectx = EvalContext::default();
+        ectx.add_hosts(hm.get_binops());
+        ectx.set_definitions(&defs);
+        match eval_expr(&e, &mut ectx).unwrap() {
+            RuntimeValue::Int(4) => {}
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    define(&mut defs, "double", r"(\x ($mul x 2.5))");
+    assert!(defs.get("four").unwrap().cached_type().is_none());
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_binops());
+    ectx.set_definitions(&defs);
+    match eval_expr(&e, &mut ectx).unwrap() {
+        RuntimeValue::Float(5.0) => {}
+        v => panic!("unexpected value: {:?}", v),
+    }
+}
+
+#[test]
+fn test_dependency_graph() {
+    let mut defs = Definitions::default();
+    define(&mut defs, "double", r"(\x ($add x x))");
+    define(&mut defs, "four", "(double 2)");
+    define(&mut defs, "eight", "($mul four 2)");
+    define(&mut defs, "unused", r#"($str_len "x")"#);
+    define(
+        &mut defs,
+        "countdown",
+        r"(\n ($if ($eq n 0) 0 (countdown ($sub n 1))))",
+    );
+
+    let graph = defs.dependency_graph();
+    let set = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+    assert_eq!(graph.definitions["eight"], set(&["four"]));
+    assert_eq!(graph.definitions["countdown"], set(&["countdown"]));
+    assert_eq!(graph.hosts["eight"], set(&["mul"]));
+    assert_eq!(
+        graph.reachable(&["eight"]),
+        set(&["double", "eight", "four"])
+    );
+    assert_eq!(graph.unreachable(&["eight"]), set(&["countdown", "unused"]));
+    assert_eq!(graph.dependents("double"), set(&["eight", "four"]));
+    assert_eq!(defs.hosts_used("eight"), set(&["add", "mul"]));
+
+    let dot = graph.to_dot();
+    assert!(dot.starts_with("digraph deps {\n"), "{}", dot);
+    assert!(dot.contains("    \"eight\" -> \"four\";\n"), "{}", dot);
+    assert!(dot.contains("    \"$mul\" [shape=box];\n"), "{}", dot);
+    assert!(
+        dot.contains("    \"eight\" -> \"$mul\" [style=dashed];\n"),
+        "{}",
+        dot
+    );
+}
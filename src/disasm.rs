@@ -0,0 +1,160 @@
+//! Readable listings of compiled artifacts, for inspecting stored programs
+//! without the source they were compiled from. Used by `xldis`.
+
+use crate::ast::resolve::Resolution;
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+use crate::bundle::{Bundle, BUNDLE_MAGIC};
+use crate::error::ParseError;
+use std::fmt::{self, Write};
+
+/// A program as it is stored by `xlc`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Artifact {
+    /// Written by `xlc compile`, in either output format.
+    Compiled(Expr),
+    /// Written by `xlc bundle`.
+    Bundle(Bundle),
+}
+
+impl Artifact {
+    /// Decodes an artifact, telling the formats apart by their content.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Artifact, ParseError> {
+        if bytes.starts_with(&BUNDLE_MAGIC) {
+            return Bundle::from_bytes(bytes).map(Artifact::Bundle);
+        }
+        if let Ok(e) = bincode::deserialize(bytes) {
+            return Ok(Artifact::Compiled(e));
+        }
+        serde_json::from_slice(bytes)
+            .map(Artifact::Compiled)
+            .map_err(|_| ParseError::Custom("not a compiled program or bundle".into()))
+    }
+
+    /// Returns the listing of the artifact.
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        match *self {
+            Artifact::Compiled(ref e) => {
+                writeln!(out, "compiled program").unwrap();
+                list_expr(e, &mut out);
+            }
+            Artifact::Bundle(ref b) => list_bundle(b, &mut out),
+        }
+        out
+    }
+}
+
+/// Decodes an artifact and returns its listing.
+pub fn disassemble(bytes: &[u8]) -> Result<String, ParseError> {
+    Artifact::from_bytes(bytes).map(|a| a.listing())
+}
+
+fn list_bundle(b: &Bundle, out: &mut String) {
+    writeln!(out, "bundle format {}", b.format_version).unwrap();
+    writeln!(out, "entry: {}", b.entry).unwrap();
+    writeln!(out, "optimization level: {}", b.optimizer.level).unwrap();
+    if let Some(ty) = b.certified_type() {
+        writeln!(out, "certified type: {}", ty).unwrap();
+    }
+    writeln!(out, "signed: {}", b.signature.is_some()).unwrap();
+    writeln!(out, "required hosts:").unwrap();
+    for name in &b.required_hosts {
+        match b.host_abi.as_ref().and_then(|abi| abi.hosts.get(name)) {
+            Some(d) => writeln!(out, "  ${} v{}", name, d.version).unwrap(),
+            None => writeln!(out, "  ${}", name).unwrap(),
+        }
+    }
+    for m in &b.modules {
+        writeln!(out).unwrap();
+        writeln!(out, "module {}", m.name).unwrap();
+        for (k, v) in &m.metadata {
+            writeln!(out, "  {}: {}", k, v).unwrap();
+        }
+        list_expr(&m.expr, out);
+    }
+}
+
+fn list_expr(e: &Expr, out: &mut String) {
+    let e = canonicalize(e);
+    let mut collector = Collector::default();
+    visit::walk(&mut collector, &e).unwrap();
+    let mut resolution = Resolution::new();
+    resolution.resolve(&e);
+    let m = manifest(&e);
+
+    writeln!(out, "constants:").unwrap();
+    for (i, (c, count)) in collector.constants.iter().enumerate() {
+        writeln!(out, "  c{} {} (used {}x)", i, Element(c), count).unwrap();
+    }
+    writeln!(out, "functions:").unwrap();
+    for (i, f) in collector.functions.iter().enumerate() {
+        let params = match *f.body {
+            ExprBody::Abstract { ref params, .. } => source_names(params),
+            _ => unreachable!(),
+        };
+        write!(out, "  f{} \\{}", i, params).unwrap();
+        if let Some(layout) = resolution.function(f) {
+            if !layout.captures.is_empty() {
+                write!(out, " captures {}", source_names(&layout.captures)).unwrap();
+            }
+        }
+        writeln!(out).unwrap();
+    }
+    writeln!(out, "hosts:").unwrap();
+    for name in &m.hosts {
+        writeln!(out, "  ${}", name).unwrap();
+    }
+    writeln!(out, "free names:").unwrap();
+    for name in &m.free_names {
+        writeln!(out, "  {}", name).unwrap();
+    }
+    writeln!(out, "ast:").unwrap();
+    writeln!(out, "  {}", e).unwrap();
+}
+
+fn source_names(names: &[String]) -> String {
+    let names: Vec<&str> = names.iter().map(|x| source_name(x)).collect();
+    names.join(" ")
+}
+
+/// Collects the distinct constants, in order of first use with the number
+/// of uses, and the functions with a body, outermost first.
+#[derive(Default)]
+struct Collector {
+    constants: Vec<(Expr, usize)>,
+    functions: Vec<Expr>,
+}
+
+impl Visitor for Collector {
+    type Error = ();
+
+    fn visit_const(&mut self, e: &Expr, _c: &ConstExpr) -> Result<(), ()> {
+        match self.constants.iter_mut().find(|(c, _)| c == e) {
+            Some((_, count)) => *count += 1,
+            None => self.constants.push((e.clone(), 1)),
+        }
+        Ok(())
+    }
+
+    fn pre_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract {
+            body: AbstractBody::Expr(_),
+            ..
+        } = *e.body
+        {
+            self.functions.push(e.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Prints an expression without the parentheses `Expr`'s `Display` puts
+/// around atoms.
+struct Element<'a>(&'a Expr);
+
+impl fmt::Display for Element<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_element(self.0, f)
+    }
+}
@@ -0,0 +1,50 @@
+use crate::bundle::*;
+use crate::disasm::*;
+use crate::parser::{parse_expr, parse_expr_with_globals};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[test]
+fn test_disassemble_compiled() {
+    let ast = parse_expr(r"((\x (\y ($add x ($add y 1)))) 1)").unwrap();
+    let listing = disassemble(&bincode::serialize(&ast).unwrap()).unwrap();
+    assert_eq!(
+        listing,
+        r"compiled program
+constants:
+  c0 1 (used 2x)
+functions:
+  f0 \x
+  f1 \y captures x
+hosts:
+  $add
+free names:
+ast:
+  ((\x (\y ($add x ($add y 1)))) 1)
+"
+    );
+    assert_eq!(
+        Artifact::from_bytes(&serde_json::to_vec(&ast).unwrap()).unwrap(),
+        Artifact::Compiled(ast)
+    );
+    assert!(disassemble(b"garbage").is_err());
+}
+
+#[test]
+fn test_disassemble_bundle() {
+    let globals: BTreeSet<String> = vec!["main".to_string()].into_iter().collect();
+    let mut metadata = BTreeMap::new();
+    metadata.insert("path".to_string(), "main.x".to_string());
+    let bundle = Bundle::new(
+        "main".into(),
+        vec![BundleModule {
+            name: "main".into(),
+            expr: parse_expr_with_globals("($mul 2 2)", globals).unwrap(),
+            metadata,
+        }],
+        OptimizerSettings { level: 1 },
+    );
+    let listing = disassemble(&bundle.to_bytes()).unwrap();
+    assert!(listing.starts_with("bundle format 5\nentry: main\noptimization level: 1\n"));
+    assert!(listing.contains("required hosts:\n  $mul\n"));
+    assert!(listing.contains("module main\n  path: main.x\nconstants:\n  c0 2 (used 2x)\n"));
+}
@@ -0,0 +1,1309 @@
+//! High-level embedding entry point.
+//!
+//! An `Engine` owns the core library, any additional host functions and a
+//! `Definitions` store, and runs the parse → typecheck → eval pipeline
+//! against them.
+
+use crate::ast::{DataType, Expr, ExprBody};
+use crate::audit::AuditLog;
+use crate::bundle::Bundle;
+use crate::columnar::{self, Column, ColumnOp};
+use crate::corelib::{
+    const_of, describe_core, literal_of, runtime_value_of, Division, HostGroup, HostManager,
+    NumericCoercion, Profile,
+};
+use crate::definitions::Definitions;
+use crate::error::*;
+use crate::eval::{
+    apply_value, eval_expr, Debugger, EvalContext, EvalOutcome, HostState, LazyValue, OwnedValue,
+    RecordedStep, RuntimeValue,
+};
+use crate::host::{qualified_name, verify_hosts, HostAbi, HostFunction, HostInfo};
+use crate::journal::Journal;
+use crate::log::LogSink;
+use crate::metrics::Metrics;
+use crate::optimize::optimize;
+use crate::parser::{parse_expr_with_options, ParseOptions};
+use crate::program::Program;
+use crate::recursion::divergence_message;
+use crate::suspend::Suspender;
+use crate::termination::{check_termination, HostResolution};
+use crate::typeck::{check_against, check_expr, TypeDescription, TypeResolveState};
+use crate::warning::Warning;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Default number of sources whose parsed and checked form is cached.
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// Most steps `Engine::precompute_constants` spends on a definition before
+/// leaving it to be evaluated by every run instead.
+pub const PRECOMPUTE_STEP_LIMIT: u64 = 100_000;
+
+/// Default of `Engine::set_depth_limit`: as deep as evaluation can nest
+/// within the 2 MiB stack of threads spawned by `std::thread`, which each
+/// level uses about 6 KiB of in debug builds and 1.5 KiB of in release
+/// builds.
+pub const DEFAULT_DEPTH_LIMIT: u32 = if cfg!(debug_assertions) { 256 } else { 1024 };
+
+pub struct Engine {
+    hm: HostManager,
+    hosts: Vec<(String, Box<dyn HostFunction>)>,
+    /// Alternative names of host functions, by alias.
+    aliases: BTreeMap<String, String>,
+    definitions: Definitions,
+    cache: RefCell<PreparedCache>,
+    leak_check: bool,
+    eager: bool,
+    require_termination: bool,
+    trust_certificates: bool,
+    strict_floats: bool,
+    depth_limit: Option<u32>,
+    step_limit: Option<u64>,
+    slot_limit: Option<usize>,
+    opt_level: u32,
+    cancel_flag: Option<Arc<AtomicBool>>,
+    host_state: RefCell<HostState>,
+    log_sink: Option<Rc<dyn LogSink>>,
+    /// Type aliases declared with `define_type`.
+    types: BTreeMap<String, TypeDescription>,
+}
+
+impl Default for Engine {
+    fn default() -> Engine {
+        Engine {
+            hm: HostManager::default(),
+            hosts: vec![],
+            aliases: BTreeMap::new(),
+            definitions: Definitions::default(),
+            cache: RefCell::default(),
+            leak_check: false,
+            eager: false,
+            require_termination: false,
+            trust_certificates: false,
+            strict_floats: false,
+            depth_limit: Some(DEFAULT_DEPTH_LIMIT),
+            step_limit: None,
+            slot_limit: None,
+            opt_level: 0,
+            cancel_flag: None,
+            host_state: RefCell::default(),
+            log_sink: None,
+            types: BTreeMap::new(),
+        }
+    }
+}
+
+/// A script for `Engine::call`: source text, or an AST compiled ahead of
+/// time, e.g. by `xlc compile`.
+#[derive(Debug, Clone, Copy)]
+pub enum Script<'a> {
+    Source(&'a str),
+    Compiled(&'a Expr),
+}
+
+impl<'a> From<&'a str> for Script<'a> {
+    fn from(source: &'a str) -> Script<'a> {
+        Script::Source(source)
+    }
+}
+
+impl<'a> From<&'a Expr> for Script<'a> {
+    fn from(e: &'a Expr) -> Script<'a> {
+        Script::Compiled(e)
+    }
+}
+
+/// Per-evaluation settings for `Engine::run_in`.
+#[derive(Default)]
+pub(crate) struct RunOptions<'a> {
+    /// Fail with `RuntimeError::StepLimit` after this many steps.
+    pub step_limit: Option<u64>,
+    /// Where to store the host function calls made.
+    pub audit: Option<&'a mut AuditLog>,
+    /// Where to store the resources used.
+    pub metrics: Option<&'a mut Metrics>,
+    /// Where to store the warnings raised.
+    pub warnings: Option<&'a mut Vec<Warning>>,
+    /// What to hand every call to before evaluating it.
+    pub debugger: Option<&'a mut dyn Debugger>,
+    /// Where to store the value of every expression evaluated.
+    pub recording: Option<&'a mut Vec<RecordedStep>>,
+    /// How to suspend at the step limit instead of failing, for
+    /// evaluations running on a stack of their own.
+    pub suspender: Option<Suspender<'a>>,
+    /// Where to journal calls to host functions with side effects, and
+    /// what to replay them from.
+    pub journal: Option<Rc<RefCell<Journal>>>,
+}
+
+/// Hit and miss counts of the prepared-expression cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// The settings of an `Engine` that deployments choose, for reading from a
+/// configuration file with serde. Fields missing from the file keep their
+/// defaults, which are those of `Engine::new`.
+///
+/// ```json
+/// {"step_limit": 100000, "profile": "data-transform", "deny_hosts": ["now"]}
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct EngineConfig {
+    /// See `Engine::set_step_limit`.
+    pub step_limit: Option<u64>,
+    /// See `Engine::set_slot_limit`.
+    pub slot_limit: Option<usize>,
+    /// See `Engine::set_depth_limit`.
+    pub depth_limit: Option<u32>,
+    pub profile: Profile,
+    /// Groups allowed in addition to those of `profile`.
+    pub allow_groups: Vec<HostGroup>,
+    /// Host functions denied, by name without the `$`.
+    pub deny_hosts: Vec<String>,
+    pub division: Division,
+    pub numeric_coercion: NumericCoercion,
+    pub strict_floats: bool,
+    pub eager: bool,
+    pub require_termination: bool,
+    /// See `Engine::set_opt_level`.
+    pub opt_level: u32,
+    pub cache_capacity: usize,
+}
+
+impl Default for EngineConfig {
+    fn default() -> EngineConfig {
+        EngineConfig {
+            step_limit: None,
+            slot_limit: None,
+            depth_limit: Some(DEFAULT_DEPTH_LIMIT),
+            profile: Profile::FullIo,
+            allow_groups: vec![],
+            deny_hosts: vec![],
+            division: Division::default(),
+            numeric_coercion: NumericCoercion::default(),
+            strict_floats: false,
+            eager: false,
+            require_termination: false,
+            opt_level: 0,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+        }
+    }
+}
+
+struct Prepared {
+    expr: Expr,
+    ty: DataType,
+    /// `Definitions::generation` the entry was checked against.
+    generation: u64,
+    last_used: u64,
+}
+
+/// Least-recently-used map from source text to its parsed and checked form.
+struct PreparedCache {
+    entries: HashMap<String, Prepared>,
+    capacity: usize,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl Default for PreparedCache {
+    fn default() -> PreparedCache {
+        PreparedCache {
+            entries: HashMap::new(),
+            capacity: DEFAULT_CACHE_CAPACITY,
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+}
+
+impl PreparedCache {
+    fn get(&mut self, source: &str, generation: u64) -> Option<(Expr, DataType)> {
+        self.tick += 1;
+        match self.entries.get_mut(source) {
+            Some(p) if p.generation == generation => {
+                p.last_used = self.tick;
+                self.stats.hits += 1;
+                Some((p.expr.clone(), p.ty.clone()))
+            }
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, source: &str, expr: Expr, ty: DataType, generation: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(source) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, p)| p.last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = oldest {
+                self.entries.remove(&k);
+            }
+        }
+        self.entries.insert(
+            source.to_string(),
+            Prepared {
+                expr,
+                ty,
+                generation,
+                last_used: self.tick,
+            },
+        );
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::default()
+    }
+
+    /// An engine set up as `config` says.
+    pub fn with_config(config: &EngineConfig) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_profile(config.profile);
+        for &group in &config.allow_groups {
+            engine.allow_host_group(group);
+        }
+        for name in &config.deny_hosts {
+            engine.deny_host(name);
+        }
+        engine.set_division(config.division);
+        engine.set_numeric_coercion(config.numeric_coercion);
+        engine.set_step_limit(config.step_limit);
+        engine.set_slot_limit(config.slot_limit);
+        engine.set_depth_limit(config.depth_limit);
+        engine.set_strict_floats(config.strict_floats);
+        engine.set_eager(config.eager);
+        engine.set_require_termination(config.require_termination);
+        engine.set_opt_level(config.opt_level);
+        engine.set_cache_capacity(config.cache_capacity);
+        engine
+    }
+
+    /// Registers an additional host function, callable as `$name`.
+    pub fn add_host(&mut self, name: String, hf: Box<dyn HostFunction>) {
+        self.hosts.push((name, hf));
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    /// Registers an additional host function in `namespace`, callable as
+    /// `$namespace.name`, so that libraries using the same names do not
+    /// collide.
+    pub fn add_host_in(&mut self, namespace: &str, name: &str, hf: Box<dyn HostFunction>) {
+        self.add_host(qualified_name(namespace, name), hf);
+    }
+
+    /// Makes `$alias` call the host function `target`, e.g. a short name
+    /// for a namespaced one. `target` is looked up whenever scripts are
+    /// checked or run, among the host functions that are not aliases; the
+    /// alias is only available while `target` is.
+    ///
+    /// Returns false, registering nothing, if `alias` already names a host
+    /// function of the core library, in any group, or one added with
+    /// `add_host`, or another alias, so that an alias never changes what an
+    /// existing name calls.
+    pub fn alias_host(&mut self, alias: &str, target: &str) -> bool {
+        if self.hm.group_of(alias).is_some()
+            || self.hosts.iter().any(|(k, _)| k == alias)
+            || self.aliases.contains_key(alias)
+        {
+            return false;
+        }
+        self.aliases.insert(alias.to_string(), target.to_string());
+        self.cache.borrow_mut().entries.clear();
+        true
+    }
+
+    /// Restricts the host functions scripts can use to the groups in
+    /// `profile`. Host functions added with `add_host` belong to
+    /// `HostGroup::Io`.
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.hm.set_profile(profile);
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    /// Makes the host functions in `group` available in addition to those
+    /// of the current profile, e.g. `HostGroup::Eval`, which no profile
+    /// includes.
+    pub fn allow_host_group(&mut self, group: HostGroup) {
+        self.hm.allow(group);
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    /// Makes `$div` and `$mod` round the way `division` says. See
+    /// `HostManager::set_division`.
+    pub fn set_division(&mut self, division: Division) {
+        self.hm.set_division(division);
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    /// Makes arithmetic and comparisons of an int with a float follow
+    /// `coercion`. See `HostManager::set_numeric_coercion`.
+    pub fn set_numeric_coercion(&mut self, coercion: NumericCoercion) {
+        self.hm.set_numeric_coercion(coercion);
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    /// Makes the host function `name` unavailable to scripts, whether it
+    /// comes from the core library or `add_host`.
+    pub fn deny_host(&mut self, name: &str) {
+        self.hm.deny(name);
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    /// Sets how many sources `prepare` remembers. Zero disables caching.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        let cache = self.cache.get_mut();
+        cache.capacity = capacity;
+        cache.entries.clear();
+    }
+
+    /// Values host functions can reach through `EvalContext::host_state`
+    /// during every evaluation run by this engine.
+    pub fn host_state_mut(&mut self) -> &mut HostState {
+        self.host_state.get_mut()
+    }
+
+    pub(crate) fn host_state(&self) -> &RefCell<HostState> {
+        &self.host_state
+    }
+
+    /// When enabled, evaluation fails with `RuntimeError::LeakedSlots` if a
+    /// host function leaves slots allocated after the result is consumed.
+    /// Meant for testing host functions.
+    pub fn set_leak_check(&mut self, enabled: bool) {
+        self.leak_check = enabled;
+    }
+
+    /// Evaluates arguments of lambda calls before the call instead of on
+    /// first use. See `EvalContext::set_eager`.
+    pub fn set_eager(&mut self, eager: bool) {
+        self.eager = eager;
+    }
+
+    /// Makes evaluations fail with `RuntimeError::NonFiniteFloat` when a host
+    /// function returns NaN or infinity. See
+    /// `EvalContext::set_strict_floats`.
+    pub fn set_strict_floats(&mut self, strict: bool) {
+        self.strict_floats = strict;
+    }
+
+    /// Sends what scripts log with `$log` to `sink`. See
+    /// `EvalContext::set_log_sink`.
+    pub fn set_log_sink(&mut self, sink: Option<Rc<dyn LogSink>>) {
+        self.log_sink = sink;
+    }
+
+    /// Makes evaluations fail with `RuntimeError::StackOverflow` instead of
+    /// nesting deeper than `limit`, `DEFAULT_DEPTH_LIMIT` unless set. Raise
+    /// it only for threads with larger stacks; `None` removes the limit,
+    /// leaving deep recursion to abort the process. See
+    /// `EvalContext::set_depth_limit`.
+    pub fn set_depth_limit(&mut self, limit: Option<u32>) {
+        self.depth_limit = limit;
+    }
+
+    /// Makes evaluations fail with `RuntimeError::StepLimit` after `limit`
+    /// steps, unless the caller sets a limit of its own. Executions are
+    /// held to `limit` in total, across slices and restarts. See
+    /// `EvalContext::set_step_limit`.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    pub(crate) fn step_limit(&self) -> Option<u64> {
+        self.step_limit
+    }
+
+    /// Makes evaluations fail with `RuntimeError::SlotLimit` once more than
+    /// `limit` slots are allocated. See `EvalContext::set_slot_limit`.
+    pub fn set_slot_limit(&mut self, limit: Option<usize>) {
+        self.slot_limit = limit;
+    }
+
+    /// Makes `prepare` optimize programs at `level` after checking them.
+    /// See `optimize::optimize`, whose caveat about replacing core library
+    /// functions applies.
+    pub fn set_opt_level(&mut self, level: u32) {
+        self.opt_level = level;
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    /// Rejects programs using recursion that `check_termination` cannot
+    /// prove terminating, instead of relying on step limits to stop them.
+    pub fn set_require_termination(&mut self, required: bool) {
+        self.require_termination = required;
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    /// Makes `load_bundle` skip typechecking bundles whose `TypeCertificate`
+    /// matches their contents. Only enable this for bundles from a trusted
+    /// pipeline, e.g. with `load_bundle_verified`: anyone can issue a
+    /// certificate. Certificates are not trusted while termination is
+    /// required, since they do not record that it was proven.
+    pub fn set_trust_certificates(&mut self, trusted: bool) {
+        self.trust_certificates = trusted;
+    }
+
+    fn trusts_certificates(&self) -> bool {
+        self.trust_certificates && !self.require_termination
+    }
+
+    /// Makes evaluations fail with `RuntimeError::Cancelled` once `flag` is
+    /// set. See `EvalContext::set_cancel_flag`.
+    pub fn set_cancel_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.cancel_flag = flag;
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.borrow().stats
+    }
+
+    /// Parses and typechecks `source` against the engine's definitions.
+    ///
+    /// Results are cached by source text, so preparing the same source again
+    /// skips both steps until a definition or host function changes.
+    pub fn prepare(&self, source: &str) -> Result<(Expr, DataType), Error> {
+        let generation = self.definitions.generation();
+        if let Some(hit) = self.cache.borrow_mut().get(source, generation) {
+            return Ok(hit);
+        }
+        let mut e = self.parse(source)?;
+        let ty = self.check(&e)?;
+        if self.opt_level > 0 {
+            e = optimize(&e, self.opt_level);
+        }
+        self.cache
+            .borrow_mut()
+            .insert(source, e.clone(), ty.clone(), generation);
+        Ok((e, ty))
+    }
+
+    pub fn host_functions(&self) -> impl Iterator<Item = (String, &dyn HostFunction)> {
+        let io = self.hm.allows(HostGroup::Io);
+        let mut hosts: Vec<(String, &dyn HostFunction)> = self
+            .hm
+            .get_all()
+            .chain(
+                self.hosts
+                    .iter()
+                    .filter(move |(k, _)| io && !self.hm.is_denied(k))
+                    .map(|(k, v)| (k.clone(), &**v as &dyn HostFunction)),
+            )
+            .collect();
+        let aliased: Vec<(String, &dyn HostFunction)> = self
+            .aliases
+            .iter()
+            .filter(|(alias, _)| !self.hm.is_denied(alias))
+            // A host function added after the alias keeps its name.
+            .filter(|(alias, _)| !self.hosts.iter().any(|(k, _)| k == *alias))
+            .filter_map(|(alias, target)| {
+                let (_, hf) = hosts.iter().find(|(k, _)| k == target)?;
+                Some((alias.clone(), *hf))
+            })
+            .collect();
+        hosts.extend(aliased);
+        hosts.into_iter()
+    }
+
+    /// The core function each name of `host_functions` calls, for the names
+    /// calling one rather than a function added with `add_host`.
+    fn core_host_names(&self) -> BTreeMap<String, String> {
+        self.host_functions()
+            .map(|(name, _)| {
+                let target = self.aliases.get(&name).unwrap_or(&name).clone();
+                (name, target)
+            })
+            .filter(|(_, target)| {
+                !self.hosts.iter().any(|(k, _)| k == target) && self.hm.group_of(target).is_some()
+            })
+            .collect()
+    }
+
+    /// Describes the host functions available to scripts, in the order of
+    /// `host_functions`. Host functions added with `add_host` are in
+    /// `HostGroup::Io`, and aliases are described like their targets.
+    pub fn describe_hosts(&self) -> Vec<HostInfo> {
+        self.host_functions()
+            .map(|(name, hf)| {
+                let target = self.aliases.get(&name).unwrap_or(&name);
+                let mut info = match self.hm.group_of(target) {
+                    Some(group) => describe_core(target, group, hf),
+                    None => HostInfo::of(target, HostGroup::Io.name(), hf),
+                };
+                if *target != name {
+                    info.signature = hf.signature().map(|sig| format!("${}{}", name, sig));
+                    info.name = name;
+                }
+                info
+            })
+            .collect()
+    }
+
+    /// Descriptors of the host functions available to scripts, to record
+    /// with programs built for this engine. See `Bundle::with_host_abi`.
+    pub fn host_abi(&self) -> HostAbi {
+        HostAbi::of(self.host_functions())
+    }
+
+    pub fn definitions(&self) -> &Definitions {
+        &self.definitions
+    }
+
+    pub fn definitions_mut(&mut self) -> &mut Definitions {
+        &mut self.definitions
+    }
+
+    /// Parses `source` and adds (or replaces) it as a definition named `name`.
+    pub fn define(&mut self, name: &str, source: &str) -> Result<(), Error> {
+        let mut opts = self.parse_options();
+        opts.globals.insert(name.to_string());
+        let e = parse_expr_with_options(source, &opts)?;
+        self.definitions.define(name.to_string(), e);
+        Ok(())
+    }
+
+    /// Declares `name` as an alias of the type expression `source`, e.g.
+    /// `(list float)`. Scripts can then annotate values with
+    /// `(the name ...)`, and the host can describe its own parameters with
+    /// `named_type`.
+    pub fn define_type(&mut self, name: &str, source: &str) -> Result<(), Error> {
+        let mut opts = self.parse_options();
+        opts.define_type(name, source)?;
+        self.types = opts.types;
+        self.cache.borrow_mut().entries.clear();
+        Ok(())
+    }
+
+    /// Describes the alias `name` declared with `define_type`, e.g. for
+    /// `typeck::function_type`.
+    pub fn named_type(&self, name: &str) -> Option<TypeDescription> {
+        self.types.get(name).map(|ty| TypeDescription::Named {
+            name: name.to_string(),
+            ty: Box::new(ty.clone()),
+        })
+    }
+
+    pub fn parse_options(&self) -> ParseOptions {
+        let mut opts = ParseOptions {
+            globals: self.definitions.names(),
+            types: self.types.clone(),
+            ..Default::default()
+        };
+        opts.add_hosts(self.host_functions());
+        opts.add_definitions(&self.definitions);
+        opts
+    }
+
+    pub fn parse(&self, source: &str) -> Result<Expr, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::parse(source);
+        Ok(parse_expr_with_options(source, &self.parse_options())?)
+    }
+
+    pub fn check(&self, e: &Expr) -> Result<DataType, Error> {
+        self.check_in(e, &self.definitions)
+    }
+
+    /// Like `check`, but also returns what checking found suspicious, such
+    /// as floats compared for equality. The types of definitions are cached,
+    /// so warnings within a definition come from the first check reaching
+    /// it.
+    pub fn check_with_warnings(&self, e: &Expr) -> Result<(DataType, Vec<Warning>), Error> {
+        self.check_warned(e, &self.definitions)
+    }
+
+    fn check_in(&self, e: &Expr, defs: &Definitions) -> Result<DataType, Error> {
+        self.check_warned(e, defs).map(|(ty, _)| ty)
+    }
+
+    fn check_warned(
+        &self,
+        e: &Expr,
+        defs: &Definitions,
+    ) -> Result<(DataType, Vec<Warning>), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::typecheck(e);
+        let mut trs = TypeResolveState::default();
+        trs.add_hosts(self.host_functions());
+        trs.set_definitions(defs);
+        if self.require_termination {
+            trs.record_host_args();
+        }
+        let ty = check_expr(e, &mut trs)?;
+        if self.require_termination {
+            let hosts = HostResolution {
+                core: self.core_host_names(),
+                args: trs.take_host_args(),
+            };
+            if let Some(unproven) = check_termination(e, Some(defs), &hosts).into_iter().next() {
+                return Err(TypeError::Custom(unproven.to_string()).into());
+            }
+        }
+        Ok((ty, trs.warnings().to_vec()))
+    }
+
+    /// Typechecks and evaluates `e`, passing the value to `f` while the
+    /// evaluation context it may refer to is still alive.
+    pub fn eval_with<T, F>(&self, e: &Expr, f: F) -> Result<T, Error>
+    where
+        F: for<'b, 'c> FnOnce(
+            RuntimeValue<'b>,
+            &mut EvalContext<'b, 'c>,
+        ) -> Result<T, RuntimeError>,
+    {
+        self.eval_in(e, &self.definitions, f)
+    }
+
+    fn eval_in<T, F>(&self, e: &Expr, defs: &Definitions, f: F) -> Result<T, Error>
+    where
+        F: for<'b, 'c> FnOnce(
+            RuntimeValue<'b>,
+            &mut EvalContext<'b, 'c>,
+        ) -> Result<T, RuntimeError>,
+    {
+        let ty = self.check_in(e, defs)?;
+        self.run_in(e, &ty, defs, RunOptions::default(), f)
+    }
+
+    /// Like `eval_with`, but also reports the steps and host function calls
+    /// of the evaluation, and the warnings of checking and evaluating `e`.
+    pub fn evaluate_with<T, F>(&self, e: &Expr, f: F) -> Result<EvalOutcome<T>, Error>
+    where
+        F: for<'b, 'c> FnOnce(
+            RuntimeValue<'b>,
+            &mut EvalContext<'b, 'c>,
+        ) -> Result<T, RuntimeError>,
+    {
+        self.evaluate_in(e, None, f)
+    }
+
+    /// Like `evaluate_with`, but hands every call to `debugger` before
+    /// evaluating it, e.g. to step through the program.
+    pub fn debug_with<T, F>(
+        &self,
+        e: &Expr,
+        debugger: &mut dyn Debugger,
+        f: F,
+    ) -> Result<EvalOutcome<T>, Error>
+    where
+        F: for<'b, 'c> FnOnce(
+            RuntimeValue<'b>,
+            &mut EvalContext<'b, 'c>,
+        ) -> Result<T, RuntimeError>,
+    {
+        self.evaluate_in(e, Some(debugger), f)
+    }
+
+    fn evaluate_in<T, F>(
+        &self,
+        e: &Expr,
+        debugger: Option<&mut dyn Debugger>,
+        f: F,
+    ) -> Result<EvalOutcome<T>, Error>
+    where
+        F: for<'b, 'c> FnOnce(
+            RuntimeValue<'b>,
+            &mut EvalContext<'b, 'c>,
+        ) -> Result<T, RuntimeError>,
+    {
+        let (ty, mut warnings) = self.check_with_warnings(e)?;
+        let mut metrics = Metrics::default();
+        let mut eval_warnings = Vec::new();
+        let opts = RunOptions {
+            metrics: Some(&mut metrics),
+            warnings: Some(&mut eval_warnings),
+            debugger: debugger.map(|d| d as &mut dyn Debugger),
+            ..RunOptions::default()
+        };
+        let value = self.run_in(e, &ty, &self.definitions, opts, f)?;
+        warnings.extend(eval_warnings);
+        Ok(EvalOutcome {
+            value,
+            steps: metrics.steps,
+            host_calls: metrics.host_calls,
+            warnings,
+        })
+    }
+
+    /// Evaluates `e`, which has already been checked to be of type `ty`.
+    pub(crate) fn run_in<T, F>(
+        &self,
+        e: &Expr,
+        ty: &DataType,
+        defs: &Definitions,
+        opts: RunOptions,
+        f: F,
+    ) -> Result<T, Error>
+    where
+        F: for<'b, 'c> FnOnce(
+            RuntimeValue<'b>,
+            &mut EvalContext<'b, 'c>,
+        ) -> Result<T, RuntimeError>,
+    {
+        if *ty == DataType::Divergent {
+            return Err(Error::Type(TypeError::Custom(divergence_message(
+                e,
+                Some(defs),
+            ))));
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::eval(e);
+        let mut ectx = self.context(defs);
+        ectx.set_step_limit(opts.step_limit.or(self.step_limit));
+        ectx.set_audit(opts.audit.is_some());
+        ectx.set_debugger(opts.debugger.map(|d| d as &mut dyn Debugger));
+        ectx.set_recording(opts.recording.is_some());
+        ectx.set_suspender(opts.suspender);
+        ectx.set_journal(opts.journal);
+        let out = self
+            .verify_context(&ectx)
+            .and_then(|_| eval_expr(e, &mut ectx))
+            .and_then(|value| f(value, &mut ectx));
+        let leaked = if self.leak_check {
+            ectx.check_leaks()
+        } else {
+            vec![]
+        };
+        self.host_state
+            .replace(ectx.set_host_state(HostState::default()));
+        if let Some(log) = opts.audit {
+            *log = ectx.take_audit_log().unwrap_or_default();
+        }
+        if let Some(recording) = opts.recording {
+            *recording = ectx.take_recording().unwrap_or_default();
+        }
+        if let Some(metrics) = opts.metrics {
+            *metrics = ectx.metrics();
+        }
+        if let Some(warnings) = opts.warnings {
+            *warnings = ectx.warnings().to_vec();
+        }
+
+        let out = out?;
+        if !leaked.is_empty() {
+            return Err(RuntimeError::LeakedSlots(leaked).into());
+        }
+        Ok(out)
+    }
+
+    /// An evaluation context with the settings and host state of this
+    /// engine. The host state must be put back once evaluation ends.
+    fn context<'b, 'c>(&'c self, defs: &'b Definitions) -> EvalContext<'b, 'c> {
+        let mut ectx = EvalContext::default();
+        ectx.add_hosts(self.host_functions());
+        ectx.set_definitions(defs);
+        ectx.set_leak_check(self.leak_check);
+        ectx.set_eager(self.eager);
+        ectx.set_strict_floats(self.strict_floats);
+        ectx.set_cancel_flag(self.cancel_flag.clone());
+        ectx.set_depth_limit(self.depth_limit);
+        ectx.set_slot_limit(self.slot_limit);
+        ectx.set_log_sink(self.log_sink.clone());
+        ectx.set_host_state(self.host_state.take());
+        ectx
+    }
+
+    fn verify_context(&self, ectx: &EvalContext) -> Result<(), RuntimeError> {
+        let mut trs = TypeResolveState::default();
+        trs.add_hosts(self.host_functions());
+        verify_hosts(&trs, ectx)
+    }
+
+    /// Calls `script`, a function of one parameter, with each of `inputs`,
+    /// e.g. to transform the rows of a table.
+    ///
+    /// The script is parsed and checked once, and calls are checked once
+    /// for each type of input seen. Every input is evaluated afresh, with
+    /// nothing left from the previous one, but in the same context, which
+    /// saves setting one up per input as `call` does. Returns the result
+    /// for each input, or fails if the script itself is unusable.
+    pub fn map_over<'a, S, I>(
+        &self,
+        script: S,
+        inputs: I,
+    ) -> Result<Vec<Result<OwnedValue, Error>>, Error>
+    where
+        S: Into<Script<'a>>,
+        I: IntoIterator<Item = OwnedValue>,
+    {
+        let (target, ty) = match script.into() {
+            Script::Source(source) => self.prepare(source)?,
+            Script::Compiled(e) => (e.clone(), self.check(e)?),
+        };
+        match ty {
+            DataType::FunctionDecl { ref params, .. } if params.len() == 1 => {}
+            DataType::Dynamic => {}
+            ref ty => {
+                return Err(TypeError::Custom(format!(
+                    "script is not a function of one parameter but {}",
+                    ty
+                ))
+                .into())
+            }
+        }
+
+        self.apply_each(&target, inputs.into_iter().map(|x| vec![x]))
+    }
+
+    /// Calls `target` with each of `rows` in turn, in a context reset
+    /// between rows.
+    fn apply_each<I>(&self, target: &Expr, rows: I) -> Result<Vec<Result<OwnedValue, Error>>, Error>
+    where
+        I: Iterator<Item = Vec<OwnedValue>>,
+    {
+        let mut ectx = self.context(&self.definitions);
+        if let Err(e) = self.verify_context(&ectx) {
+            self.host_state
+                .replace(ectx.set_host_state(HostState::default()));
+            return Err(e.into());
+        }
+        let mut checked: Vec<Vec<DataType>> = Vec::new();
+        let mut out = Vec::new();
+        for args in rows {
+            // Steps accumulate across rows, so each row gets its own limit.
+            let limit = self.step_limit.map(|l| ectx.steps().saturating_add(l));
+            ectx.set_step_limit(limit);
+            let result = self.check_args(target, &args, &mut checked).and_then(|_| {
+                let f = eval_expr(target, &mut ectx)?;
+                let mut values = Vec::new();
+                for arg in &args {
+                    let v = runtime_value_of(&mut ectx, arg).unwrap();
+                    values.push(LazyValue::from_value(v));
+                }
+                let v = apply_value(f, values, &mut ectx)?;
+                let v = v.into_owned(&mut ectx)?;
+                let leaked = if self.leak_check {
+                    ectx.check_leaks()
+                } else {
+                    vec![]
+                };
+                if !leaked.is_empty() {
+                    return Err(RuntimeError::LeakedSlots(leaked).into());
+                }
+                Ok(v)
+            });
+            ectx.reset();
+            out.push(result);
+        }
+        self.host_state
+            .replace(ectx.set_host_state(HostState::default()));
+        Ok(out)
+    }
+
+    /// Checks that `target` can be called with `args`, unless arguments of
+    /// the same types have been, recording the types checked in `checked`.
+    fn check_args(
+        &self,
+        target: &Expr,
+        args: &[OwnedValue],
+        checked: &mut Vec<Vec<DataType>>,
+    ) -> Result<(), Error> {
+        let mut params = Vec::new();
+        let mut types = Vec::new();
+        for arg in args {
+            let e = literal_of(arg)
+                .ok_or_else(|| TypeError::Custom(format!("cannot pass {} to a script", arg)))?;
+            types.push(self.check(&e)?);
+            params.push(e);
+        }
+        if checked.contains(&types) {
+            return Ok(());
+        }
+        let call = Expr {
+            body: Rc::new(ExprBody::Apply {
+                target: target.clone(),
+                params,
+            }),
+        };
+        if self.check(&call)? == DataType::Divergent {
+            return Err(
+                TypeError::Custom(divergence_message(&call, Some(&self.definitions))).into(),
+            );
+        }
+        checked.push(types);
+        Ok(())
+    }
+
+    /// Calls `script`, a function with a parameter for each of `columns`,
+    /// on every row of the columns, which must have the same length, and
+    /// returns the results as a column.
+    ///
+    /// Scripts that only combine their parameters and constants with the
+    /// arithmetic, comparison and logical operators and `$if` run a column
+    /// at a time, much faster than row by row; others are evaluated row by
+    /// row as by `map_over`. Either way, the first row that fails fails the
+    /// whole batch. See `crate::columnar`.
+    pub fn eval_columns<'a, S: Into<Script<'a>>>(
+        &self,
+        script: S,
+        columns: &[Column],
+    ) -> Result<Column, Error> {
+        let (target, _) = match script.into() {
+            Script::Source(source) => self.prepare(source)?,
+            Script::Compiled(e) => (e.clone(), self.check(e)?),
+        };
+        let len = match columns.first() {
+            Some(c) => c.len(),
+            None => return Err(TypeError::Custom("no columns to evaluate over".into()).into()),
+        };
+        if columns.iter().any(|c| c.len() != len) {
+            return Err(TypeError::Custom("columns differ in length".into()).into());
+        }
+        let call = Expr {
+            body: Rc::new(ExprBody::Apply {
+                target: target.clone(),
+                params: columns.iter().map(|c| c.witness()).collect(),
+            }),
+        };
+        let ty = match self.check(&call)? {
+            DataType::Value(ty) => ty,
+            ty => {
+                return Err(TypeError::Custom(format!(
+                    "columnar scripts must return an int, float or bool, not {}",
+                    ty
+                ))
+                .into())
+            }
+        };
+
+        if let Some(plan) = columnar::compile(&target, &|name| self.column_op(name)) {
+            return Ok(columnar::eval(&plan, columns, len, self.strict_floats)?);
+        }
+        let rows = (0..len).map(|i| columns.iter().map(|c| c.get(i)).collect());
+        let values = self
+            .apply_each(&target, rows)?
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        Column::from_values(ty, &values).ok_or_else(|| {
+            RuntimeError::TypeMismatch("script returned values of different types".into()).into()
+        })
+    }
+
+    /// The core function `$name` calls, if columnar plans can apply it.
+    /// Names the embedder has taken over or made aliases have none.
+    fn column_op(&self, name: &str) -> Option<ColumnOp<'_>> {
+        if self.aliases.contains_key(name)
+            || self.hosts.iter().any(|(k, _)| k == name)
+            || !self.hm.get_all().any(|(k, _)| k == name)
+        {
+            return None;
+        }
+        match name {
+            "eq" => Some(ColumnOp::Eq { negate: false }),
+            "ne" => Some(ColumnOp::Eq { negate: true }),
+            "if" => Some(ColumnOp::If),
+            _ => self
+                .hm
+                .binop(name)
+                .map(ColumnOp::Binop)
+                .or_else(|| self.hm.relop(name).map(ColumnOp::Relop)),
+        }
+    }
+
+    /// Describes the type of the value `source` evaluates to.
+    pub fn infer_type(&self, source: &str) -> Result<TypeDescription, Error> {
+        let (_, ty) = self.prepare(source)?;
+        Ok(TypeDescription::of(&ty))
+    }
+
+    /// Describes the type of the value `source` evaluates to when applied to
+    /// arguments of types `args`, e.g. to check that a script is a function
+    /// from `int` to `bool`.
+    pub fn infer_call_type(
+        &self,
+        source: &str,
+        args: &[TypeDescription],
+    ) -> Result<TypeDescription, Error> {
+        let (target, _) = self.prepare(source)?;
+        let mut params = Vec::new();
+        for arg in args {
+            params.push(arg.witness().ok_or_else(|| {
+                TypeError::Custom(format!("cannot pass arguments of type {}", arg))
+            })?);
+        }
+        let call = Expr {
+            body: Rc::new(ExprBody::Apply { target, params }),
+        };
+        Ok(TypeDescription::of(&self.check(&call)?))
+    }
+
+    /// Calls `script`, which must evaluate to a function of `args.len()`
+    /// parameters, with `args`, e.g. to run a rule for each event.
+    ///
+    /// The call is typechecked with the arguments in place, so arguments the
+    /// function cannot take are rejected before anything is evaluated.
+    /// Arguments must be plain values, strings, bytes or lists of those.
+    pub fn call<'a, S: Into<Script<'a>>>(
+        &self,
+        script: S,
+        args: &[OwnedValue],
+    ) -> Result<OwnedValue, Error> {
+        self.call_typed(script, args).map(|(v, _)| v)
+    }
+
+    /// Like `call`, but also returns the type the call was checked to have.
+    pub fn call_typed<'a, S: Into<Script<'a>>>(
+        &self,
+        script: S,
+        args: &[OwnedValue],
+    ) -> Result<(OwnedValue, DataType), Error> {
+        let (target, ty) = match script.into() {
+            Script::Source(source) => self.prepare(source)?,
+            Script::Compiled(e) => (e.clone(), self.check(e)?),
+        };
+        match ty {
+            DataType::FunctionDecl { ref params, .. } if params.len() == args.len() => {}
+            DataType::FunctionDecl { ref params, .. } => {
+                return Err(TypeError::Custom(format!(
+                    "script takes {} arguments, got {}",
+                    params.len(),
+                    args.len()
+                ))
+                .into())
+            }
+            DataType::Dynamic | DataType::Divergent => {}
+            ref ty => {
+                return Err(
+                    TypeError::Custom(format!("script is not a function but {}", ty)).into(),
+                )
+            }
+        }
+        let mut params = Vec::new();
+        for arg in args {
+            params
+                .push(literal_of(arg).ok_or_else(|| {
+                    TypeError::Custom(format!("cannot pass {} to a script", arg))
+                })?);
+        }
+        let call = Expr {
+            body: Rc::new(ExprBody::Apply { target, params }),
+        };
+        let ty = self.check(&call)?;
+        let v = self.run_in(
+            &call,
+            &ty,
+            &self.definitions,
+            RunOptions::default(),
+            |v, ectx| v.into_owned(ectx),
+        )?;
+        Ok((v, ty))
+    }
+
+    /// Parses `source` and checks it against a type the host declares,
+    /// e.g. one built with `typeck::function_type`.
+    pub fn check_against(&self, source: &str, expected: &DataType) -> Result<(), Error> {
+        let e = self.parse(source)?;
+        let mut trs = TypeResolveState::default();
+        trs.add_hosts(self.host_functions());
+        trs.set_definitions(&self.definitions);
+        Ok(check_against(&e, expected, &mut trs)?)
+    }
+
+    /// Runs `source` through the whole pipeline and returns the displayed
+    /// value.
+    pub fn eval_str(&self, source: &str) -> Result<String, Error> {
+        let (e, ty) = self.prepare(source)?;
+        self.run_in(&e, &ty, &self.definitions, RunOptions::default(), |v, _| {
+            Ok(v.to_string())
+        })
+    }
+
+    /// Runs `source` through the whole pipeline and returns a value that
+    /// does not borrow from the parsed expression.
+    pub fn eval_owned(&self, source: &str) -> Result<OwnedValue, Error> {
+        let (e, ty) = self.prepare(source)?;
+        self.run_in(
+            &e,
+            &ty,
+            &self.definitions,
+            RunOptions::default(),
+            |v, ectx| v.into_owned(ectx),
+        )
+    }
+
+    /// Like `eval_owned`, but also returns a log of every host function
+    /// call the evaluation made.
+    pub fn eval_audited(&self, source: &str) -> Result<(OwnedValue, AuditLog), Error> {
+        let (e, ty) = self.prepare(source)?;
+        let mut log = AuditLog::default();
+        let opts = RunOptions {
+            audit: Some(&mut log),
+            ..RunOptions::default()
+        };
+        let v = self.run_in(&e, &ty, &self.definitions, opts, |v, ectx| {
+            v.into_owned(ectx)
+        })?;
+        Ok((v, log))
+    }
+
+    /// Like `eval_owned`, but also reports the resources the evaluation
+    /// used.
+    pub fn eval_metered(&self, source: &str) -> Result<(OwnedValue, Metrics), Error> {
+        let (e, ty) = self.prepare(source)?;
+        let mut metrics = Metrics::default();
+        let opts = RunOptions {
+            metrics: Some(&mut metrics),
+            ..RunOptions::default()
+        };
+        let v = self.run_in(&e, &ty, &self.definitions, opts, |v, ectx| {
+            v.into_owned(ectx)
+        })?;
+        Ok((v, metrics))
+    }
+
+    /// Decodes a bundle and checks that it can run on this engine: every
+    /// host function it requires must be registered and its entry module
+    /// must typecheck, unless it has a certificate this engine trusts (see
+    /// `set_trust_certificates`).
+    ///
+    /// Bundles with a trusted certificate are loaded as built, without
+    /// `precompute_constants`, which typechecks, and `eval_program_with`
+    /// runs them with the certified type instead of checking them again.
+    pub fn load_bundle(&self, bytes: &[u8]) -> Result<Program, Error> {
+        self.load_decoded_bundle(Bundle::from_bytes(bytes)?)
+    }
+
+    /// Like `load_bundle`, but rejects bundles that are not signed by the
+    /// holder of `public_key`.
+    #[cfg(feature = "signing")]
+    pub fn load_bundle_verified(
+        &self,
+        bytes: &[u8],
+        public_key: &[u8; 32],
+    ) -> Result<Program, Error> {
+        let key = ed25519_dalek::VerifyingKey::from_bytes(public_key)
+            .map_err(|_| ParseError::Custom("invalid public key".into()))?;
+        self.load_decoded_bundle(Bundle::from_bytes_verified(bytes, &key)?)
+    }
+
+    fn load_decoded_bundle(&self, bundle: Bundle) -> Result<Program, Error> {
+        if let Some(ref abi) = bundle.host_abi {
+            let mismatches = abi.mismatches(&self.host_abi());
+            if !mismatches.is_empty() {
+                return Err(TypeError::IncompatibleHosts(mismatches).into());
+            }
+        }
+        let registered: BTreeSet<String> = self.host_functions().map(|(k, _)| k).collect();
+        let missing: Vec<&String> = bundle.required_hosts.difference(&registered).collect();
+        if !missing.is_empty() {
+            return Err(Error::Type(TypeError::Custom(format!(
+                "bundle requires unregistered host functions: {:?}",
+                missing
+            ))));
+        }
+
+        let certified = if self.trusts_certificates() {
+            bundle.certified_type().and_then(|ty| ty.data_type())
+        } else {
+            None
+        };
+        let mut program = bundle.into_program()?;
+        match certified {
+            Some(ty) => program.certified = Some((program.definitions.generation(), ty)),
+            None => {
+                self.check_program(&program)?;
+                self.precompute_constants(&mut program);
+            }
+        }
+        Ok(program)
+    }
+
+    /// Evaluates the constant definitions of `program` once and replaces
+    /// each with its value, so that runs of the program no longer compute
+    /// them. A definition is constant if its value has a literal: an int,
+    /// float, bool, string, bytes or `~`.
+    ///
+    /// Only definitions whose host functions, including those of the
+    /// definitions they refer to, are all in pure groups (see
+    /// `HostGroup::is_pure`) are evaluated. Those that fail, or take more
+    /// than `PRECOMPUTE_STEP_LIMIT` steps, are left as they are.
+    ///
+    /// Returns the names of the replaced definitions. `load_bundle` calls
+    /// this on the programs it loads.
+    pub fn precompute_constants(&self, program: &mut Program) -> Vec<String> {
+        let pure = self.pure_hosts();
+        let defs = &program.definitions;
+        let mut values = Vec::new();
+        for name in defs.names() {
+            if !defs.hosts_used(&name).is_subset(&pure) {
+                continue;
+            }
+            let e = Expr {
+                body: Rc::new(ExprBody::Name(name.clone())),
+            };
+            let ty = match self.check_in(&e, defs) {
+                Ok(ty) => ty,
+                Err(_) => continue,
+            };
+            let opts = RunOptions {
+                step_limit: Some(PRECOMPUTE_STEP_LIMIT),
+                ..RunOptions::default()
+            };
+            let value = match self.run_in(&e, &ty, defs, opts, |v, _| Ok(const_of(&v))) {
+                Ok(Some(c)) => Expr {
+                    body: Rc::new(ExprBody::Const(c)),
+                },
+                _ => continue,
+            };
+            // A `dyn` definition stays one even if its value has a literal.
+            if self.check_in(&value, defs).ok() == Some(ty) {
+                values.push((name, value));
+            }
+        }
+        for (name, value) in &values {
+            program.definitions.define(name.clone(), value.clone());
+        }
+        values.into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// Names of the host functions in pure groups that expressions may
+    /// call, see `HostGroup::is_pure`.
+    pub(crate) fn pure_hosts(&self) -> BTreeSet<String> {
+        HostGroup::ALL
+            .iter()
+            .filter(|g| g.is_pure() && self.hm.allows(**g))
+            .flat_map(|g| self.hm.get_group(*g))
+            .map(|(k, _)| k)
+            .filter(|k| !self.hm.is_denied(k) && !self.hosts.iter().any(|(h, _)| h == k))
+            .collect()
+    }
+
+    pub fn check_program(&self, program: &Program) -> Result<DataType, Error> {
+        self.check_in(&program.main_expr(), &program.definitions)
+    }
+
+    /// Like `eval_with`, but evaluates the entry module of `program` against
+    /// the program's own definitions.
+    pub fn eval_program_with<T, F>(&self, program: &Program, f: F) -> Result<T, Error>
+    where
+        F: for<'b, 'c> FnOnce(
+            RuntimeValue<'b>,
+            &mut EvalContext<'b, 'c>,
+        ) -> Result<T, RuntimeError>,
+    {
+        let e = program.main_expr();
+        match program.certified_type() {
+            Some(ty) if self.trusts_certificates() => {
+                self.run_in(&e, ty, &program.definitions, RunOptions::default(), f)
+            }
+            _ => self.eval_in(&e, &program.definitions, f),
+        }
+    }
+}
@@ -0,0 +1,867 @@
+use crate::ast::DataType;
+use crate::ast::Expr;
+use crate::audit::{AuditLog, AuditValue};
+use crate::builtin::ValueType;
+use crate::corelib::{Division, HostGroup, NumericCoercion, Profile};
+use crate::engine::{CacheStats, Engine, EngineConfig, DEFAULT_DEPTH_LIMIT};
+use crate::error::{Error, RuntimeError, TypeError};
+use crate::eval::{Debugger, LazyValue, OwnedValue};
+use crate::typeck::{function_type, TypeDescription};
+
+#[test]
+fn test_engine_eval_str() {
+    let mut engine = Engine::new();
+    engine.define("sq", r"(\x ($mul x x))").unwrap();
+    assert_eq!(engine.eval_str("(sq 7)").unwrap(), "49");
+    engine.define("diff", r"(\a b ($sub a b))").unwrap();
+    assert_eq!(engine.eval_str("(diff (b 1) (a 5))").unwrap(), "4");
+    assert_eq!(
+        engine
+            .eval_str(r"((\f g (f (g 1))) (\a ($add a 1)) (\b ($mul b 10)))")
+            .unwrap(),
+        "11"
+    );
+
+    match engine.eval_str(r"((\x (x x)) (\x (x x)))") {
+        Err(Error::Type(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_engine_list_traversal() {
+    let engine = Engine::new();
+    assert_eq!(
+        engine
+            .eval_str("($list_head ($list_tail ($list_push 1 ($list_push 2 ~))))")
+            .unwrap(),
+        "2"
+    );
+    assert_eq!(engine.eval_str("($list_is_empty ~)").unwrap(), "true");
+    assert_eq!(
+        engine
+            .eval_str("($list_is_empty ($list_push 1 ~))")
+            .unwrap(),
+        "false"
+    );
+}
+
+fn eval_list(engine: &Engine, n: i64) -> OwnedValue {
+    // The parsed expression is dropped before the value is returned.
+    engine
+        .eval_owned(&format!("($list_push {} ($list_push 2 ~))", n))
+        .unwrap()
+}
+
+#[test]
+fn test_engine_eval_owned() {
+    let engine = Engine::new();
+    let v = eval_list(&engine, 1);
+    assert_eq!(
+        v,
+        OwnedValue::List(vec![OwnedValue::Int(1), OwnedValue::Int(2)])
+    );
+    assert_eq!(v.to_string(), "[1, 2]");
+
+    match engine.eval_owned(r"(\x y ($add x y))").unwrap() {
+        OwnedValue::Function { params, .. } => assert_eq!(params, vec!["x#1", "y#1"]),
+        v => panic!("unexpected value: {:?}", v),
+    }
+}
+
+#[test]
+fn test_engine_call() {
+    let engine = Engine::new();
+    let rule = r"(\amount limit ($gt amount limit))";
+    let call = |args: &[OwnedValue]| engine.call(rule, args);
+    assert_eq!(
+        call(&[OwnedValue::Int(120), OwnedValue::Int(100)]).unwrap(),
+        OwnedValue::Bool(true)
+    );
+    assert!(call(&[OwnedValue::Int(1)]).is_err());
+    assert!(call(&[OwnedValue::Int(1), OwnedValue::Bool(true)]).is_err());
+    assert!(engine.call("(1)", &[]).is_err());
+
+    // Lists and strings are passed as the literals that build them.
+    let head = engine.parse(r"(\xs ($list_head xs))").unwrap();
+    let xs = OwnedValue::List(vec![OwnedValue::Int(1), OwnedValue::Int(2)]);
+    assert_eq!(
+        engine.call(&head, std::slice::from_ref(&xs)).unwrap(),
+        OwnedValue::Int(1)
+    );
+    let name = engine.eval_owned(r#"("ada")"#).unwrap();
+    assert_eq!(
+        engine.call(r#"(\s ($eq s "ada"))"#, &[name]).unwrap(),
+        OwnedValue::Bool(true)
+    );
+    let f = engine.eval_owned(r"(\x (x))").unwrap();
+    assert!(engine.call(rule, &[f, xs]).is_err());
+}
+
+#[test]
+fn test_engine_map_over() {
+    let mut engine = Engine::new();
+    engine.set_leak_check(true);
+    engine.define("rate", "(2)").unwrap();
+    let inputs = vec![
+        OwnedValue::Int(1),
+        OwnedValue::Float(1.5),
+        OwnedValue::Int(3),
+        OwnedValue::Bool(true),
+    ];
+    let results = engine.map_over(r"(\x ($mul x rate))", inputs).unwrap();
+    assert_eq!(results.len(), 4);
+    assert_eq!(*results[0].as_ref().unwrap(), OwnedValue::Int(2));
+    assert_eq!(*results[1].as_ref().unwrap(), OwnedValue::Float(3.0));
+    assert_eq!(*results[2].as_ref().unwrap(), OwnedValue::Int(6));
+    assert!(matches!(results[3], Err(Error::Type(_))));
+
+    // Each input gets a list of its own, released before the next.
+    let rows = (0..3).map(|i| OwnedValue::List(vec![OwnedValue::Int(i), OwnedValue::Int(10)]));
+    let heads: Vec<OwnedValue> = engine
+        .map_over(r"(\row ($list_head row))", rows)
+        .unwrap()
+        .into_iter()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(
+        heads,
+        vec![OwnedValue::Int(0), OwnedValue::Int(1), OwnedValue::Int(2)]
+    );
+
+    assert!(engine.map_over(r"(\x y ($add x y))", vec![]).is_err());
+    assert!(engine.map_over("(1)", vec![]).is_err());
+}
+
+#[test]
+fn test_engine_prepare_cache() {
+    let mut engine = Engine::new();
+    engine.define("k", "(2)").unwrap();
+    assert_eq!(engine.eval_str("($mul k 21)").unwrap(), "42");
+    assert_eq!(engine.eval_str("($mul k 21)").unwrap(), "42");
+    assert_eq!(engine.cache_stats(), CacheStats { hits: 1, misses: 1 });
+
+    // Changing a definition invalidates entries checked against the old one.
+    engine.define("k", "(3)").unwrap();
+    assert_eq!(engine.eval_str("($mul k 21)").unwrap(), "63");
+    assert_eq!(engine.cache_stats(), CacheStats { hits: 1, misses: 2 });
+
+    engine.set_cache_capacity(1);
+    engine.eval_str("(1)").unwrap();
+    engine.eval_str("(2)").unwrap();
+    engine.eval_str("(1)").unwrap();
+    assert_eq!(engine.cache_stats(), CacheStats { hits: 1, misses: 5 });
+}
+
+#[test]
+fn test_engine_partial_application() {
+    let engine = Engine::new();
+    let add3 = r"(\x y z ($add x ($mul y z)))";
+
+    let (_, ty) = engine.prepare(&format!("({} 1 2)", add3)).unwrap();
+    match ty {
+        DataType::FunctionDecl { params, .. } => assert_eq!(params, vec!["z#1"]),
+        ty => panic!("unexpected type: {:?}", ty),
+    }
+    assert_eq!(
+        engine.eval_str(&format!("(({} 1 2) 3)", add3)).unwrap(),
+        "7"
+    );
+    assert_eq!(
+        engine
+            .eval_str(&format!("((\\f ($add (f 1 1) ((f 1) 2))) ({} 3))", add3))
+            .unwrap(),
+        "9"
+    );
+
+    match engine.eval_str(&format!("({} 1 2 3 4)", add3)) {
+        Err(Error::Type(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_engine_host_partial_application() {
+    let engine = Engine::new();
+    assert_eq!(engine.eval_str("(($add 1) 2)").unwrap(), "3");
+    assert_eq!(engine.eval_str(r"((\f (f 3)) ($mul 10))").unwrap(), "30");
+    assert_eq!(
+        engine.eval_str(r"((\f x y (f x y)) $sub 10 3)").unwrap(),
+        "7"
+    );
+    assert_eq!(engine.eval_str("($sub (rhs 1) (lhs 5))").unwrap(), "4");
+
+    let (_, ty) = engine.prepare("($if true)").unwrap();
+    match ty {
+        DataType::FunctionDecl { params, .. } => {
+            assert_eq!(params, vec!["$if.then", "$if.else"])
+        }
+        ty => panic!("unexpected type: {:?}", ty),
+    }
+    match engine.eval_str("(($add 1) true)") {
+        Err(Error::Type(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_structural_eq() {
+    let engine = Engine::new();
+    let eval = |src: &str| engine.eval_str(src).unwrap();
+    assert_eq!(eval("($eq ~ ~)"), "true");
+    assert_eq!(eval("($eq 1 1.0)"), "true");
+    assert_eq!(eval("($eq ($list_push 1 ~) ~)"), "false");
+    assert_eq!(
+        eval("($eq ($list_push 1 ($list_push 2 ~)) ($list_push 1 ($list_push 2 ~)))"),
+        "true"
+    );
+    assert_eq!(
+        eval("($ne ($list_push 1 ($list_push 2 ~)) ($list_push 1 ($list_push 3 ~)))"),
+        "true"
+    );
+    assert_eq!(
+        eval("($eq ($list_push 1 ~) ($list_push 1 ($list_push 2 ~)))"),
+        "false"
+    );
+    assert_eq!(
+        eval("($eq ($list_push ($list_push 1 ~) ~) ($list_push ($list_push 1 ~) ~))"),
+        "true"
+    );
+
+    for src in &[
+        "($eq 1 true)",
+        "($eq ($list_push 1 ~) ($list_push true ~))",
+        r"($eq (\x ($add x 1)) (\x ($add x 1)))",
+        r"($eq ($list_push (\x ($add x 1)) ~) ~)",
+    ] {
+        match engine.eval_str(src) {
+            Err(Error::Type(_)) => {}
+            x => panic!("unexpected result for {}: {:?}", src, x),
+        }
+    }
+}
+
+#[test]
+fn test_engine_eval_audited() {
+    let mut engine = Engine::new();
+    engine
+        .define(
+            "price",
+            r"(\qty ($if ($gt qty 10) ($mul qty 9) ($mul qty 10)))",
+        )
+        .unwrap();
+    let (v, log) = engine.eval_audited("(price 12)").unwrap();
+    assert_eq!(v, OwnedValue::Int(108));
+
+    let calls: Vec<(&str, u32)> = log
+        .entries()
+        .iter()
+        .map(|x| (x.host.as_str(), x.depth))
+        .collect();
+    assert_eq!(calls, vec![("gt", 1), ("mul", 1), ("if", 0)]);
+    let if_call = &log.entries()[2];
+    assert_eq!(
+        if_call.args,
+        vec![
+            AuditValue::Bool(true),
+            AuditValue::Int(108),
+            AuditValue::Unevaluated
+        ]
+    );
+    assert_eq!(if_call.result, AuditValue::Int(108));
+    assert_eq!(AuditLog::from_bytes(&log.to_bytes()).unwrap(), log);
+
+    // Auditing is off for plain evaluation.
+    assert_eq!(engine.eval_owned("(price 1)").unwrap(), OwnedValue::Int(10));
+}
+
+#[test]
+fn test_engine_infer_type() {
+    let engine = Engine::new();
+    assert_eq!(
+        engine.infer_type("($add 1 2.5)").unwrap(),
+        TypeDescription::Float
+    );
+    let list = engine.infer_type("($list_push 1 ~)").unwrap();
+    assert_eq!(list, TypeDescription::List(Box::new(TypeDescription::Int)));
+    assert_eq!(list.to_string(), "list<int>");
+
+    let script = r"(\age limit ($lt age limit))";
+    let f = engine.infer_type(script).unwrap();
+    assert_eq!(f.to_string(), "fn(age, limit)");
+    assert_eq!(
+        engine
+            .infer_call_type(script, &[TypeDescription::Int, TypeDescription::Int])
+            .unwrap(),
+        TypeDescription::Bool
+    );
+    assert!(engine
+        .infer_call_type(script, &[TypeDescription::Int, TypeDescription::Bool])
+        .is_err());
+    assert_eq!(
+        engine
+            .infer_call_type(
+                "($list_head)",
+                &[TypeDescription::List(Box::new(TypeDescription::Float))]
+            )
+            .unwrap(),
+        TypeDescription::Float
+    );
+    assert_eq!(
+        engine.infer_type("($round)").unwrap(),
+        TypeDescription::Function {
+            params: vec!["x".into(), "digits".into()]
+        }
+    );
+}
+
+#[test]
+fn test_engine_check_against() {
+    let engine = Engine::new();
+    let policy = function_type(
+        &[TypeDescription::Int, TypeDescription::Int],
+        &TypeDescription::Bool,
+    )
+    .unwrap();
+
+    engine
+        .check_against(r"(\age limit ($lt age limit))", &policy)
+        .unwrap();
+    engine.check_against("($lt)", &policy).unwrap();
+
+    match engine.check_against(r"(\age limit ($add age limit))", &policy) {
+        Err(Error::Type(e)) => {
+            assert_eq!(e.to_string(), "return value: expecting bool, found int")
+        }
+        _ => panic!("expecting a type error"),
+    }
+    match engine.check_against(r"(\age ($lt age 18))", &policy) {
+        Err(Error::Type(e)) => {
+            assert_eq!(
+                e.to_string(),
+                "result: expecting fn(arg0, arg1), found fn(age)"
+            )
+        }
+        _ => panic!("expecting a type error"),
+    }
+    match engine.check_against("(1)", &policy) {
+        Err(Error::Type(TypeError::Mismatch { found, .. })) => {
+            assert_eq!(found, TypeDescription::Int)
+        }
+        _ => panic!("expecting a type error"),
+    }
+    assert!(engine
+        .check_against(r"(\age limit ($lt age ~))", &policy)
+        .is_err());
+
+    let int = DataType::Value(ValueType::Int);
+    engine.check_against("($add 1 2)", &int).unwrap();
+    assert!(engine.check_against("($add 1 2.0)", &int).is_err());
+}
+
+#[test]
+fn test_engine_eager() {
+    let mut engine = Engine::new();
+    engine.set_leak_check(true);
+    let ignore_second = r"((\x y (x)) 1 ($div 1 0))";
+    assert_eq!(engine.eval_str(ignore_second).unwrap(), "1");
+
+    engine.set_eager(true);
+    match engine.eval_str(ignore_second) {
+        Err(Error::Runtime(RuntimeError::DivByZero)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert_eq!(
+        engine
+            .eval_str(r"((\x y (x)) 1 ($delay ($div 1 0)))")
+            .unwrap(),
+        "1"
+    );
+    assert_eq!(
+        engine
+            .eval_str(r"((\d ($add ($force d) 1)) ($delay ($mul 2 3)))")
+            .unwrap(),
+        "7"
+    );
+    // Host function arguments stay lazy.
+    assert_eq!(engine.eval_str("($if true 1 ($div 1 0))").unwrap(), "1");
+
+    assert_eq!(
+        engine.infer_type("($delay 1)").unwrap(),
+        TypeDescription::Custom("delayed<int>".into())
+    );
+    assert!(engine.infer_type("($force 1)").is_err());
+}
+
+#[test]
+fn test_engine_memo() {
+    let mut engine = Engine::new();
+    engine.set_leak_check(true);
+    engine
+        .define(
+            "fib",
+            r"($memo (\n ($if ($lt n 2) n ($add (fib ($sub n 1)) (fib ($sub n 2))))))",
+        )
+        .unwrap();
+    // Exponential without the cache.
+    assert_eq!(engine.eval_str("(fib 80)").unwrap(), "23416728348467685");
+    assert_eq!(engine.infer_type("(fib 10)").unwrap(), TypeDescription::Int);
+
+    // Each wrapper has a cache of its own, and list arguments are compared
+    // structurally.
+    engine
+        .define("len", r"($memo (\l ($if ($list_is_empty l) 0 1)))")
+        .unwrap();
+    assert_eq!(
+        engine
+            .eval_str("($add (len ($list_push 1 ~)) (len ($list_push 1 ~)))")
+            .unwrap(),
+        "2"
+    );
+    assert!(engine.infer_type("($memo 1)").is_err());
+}
+
+#[test]
+fn test_engine_eval_metered() {
+    let engine = Engine::new();
+    let (v, metrics) = engine
+        .eval_metered("($list_head ($list_push ($add 1 2) ($list_push 4 ~)))")
+        .unwrap();
+    assert_eq!(v, OwnedValue::Int(3));
+    assert_eq!(metrics.host_calls["list_push"], 2);
+    assert_eq!(metrics.host_calls["add"], 1);
+    assert_eq!(metrics.total_host_calls(), 4);
+    assert_eq!(metrics.slots_allocated, 2);
+    assert_eq!(metrics.peak_slots, 2);
+    assert!(metrics.steps > 0);
+    assert!(metrics.peak_depth > 1 && u64::from(metrics.peak_depth) <= metrics.steps);
+
+    let (_, deeper) = engine
+        .eval_metered(r"((\x ($add x ($add x ($add x 1)))) 1)")
+        .unwrap();
+    assert!(deeper.peak_depth > metrics.peak_depth);
+}
+
+#[test]
+fn test_engine_depth_limit() {
+    let mut engine = Engine::new();
+    engine
+        .define("sum", r"(\n ($if ($le n 0) 0 ($add n (sum ($sub n 1)))))")
+        .unwrap();
+    engine.set_depth_limit(Some(100));
+    assert_eq!(engine.eval_str("(sum 10)").unwrap(), "55");
+    match engine.eval_str("(sum 100000)") {
+        Err(Error::Runtime(RuntimeError::StackOverflow { depth })) => assert_eq!(depth, 100),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    // The engine is still usable afterwards.
+    assert_eq!(engine.eval_str("(sum 3)").unwrap(), "6");
+
+    engine.set_depth_limit(None);
+    let (_, metrics) = engine.eval_metered("(sum 50)").unwrap();
+    assert!(metrics.peak_depth > 100);
+}
+
+#[test]
+fn test_engine_default_depth_limit() {
+    let mut engine = Engine::with_config(&EngineConfig::default());
+    engine
+        .define("sum", r"(\n ($if ($le n 0) 0 ($add n (sum ($sub n 1)))))")
+        .unwrap();
+    // Runs on a test thread, whose stack is the 2 MiB the limit is sized
+    // for.
+    match engine.eval_str("(sum 1000000)") {
+        Err(Error::Runtime(RuntimeError::StackOverflow { depth })) => {
+            assert_eq!(depth, DEFAULT_DEPTH_LIMIT)
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_engine_slot_limit() {
+    let mut engine = Engine::new();
+    engine
+        .define(
+            "build",
+            r"(\n ($if ($le n 0) ~ ($list_push n (build ($sub n 1)))))",
+        )
+        .unwrap();
+    // Building a list of 100 nests deeper than the default depth limit.
+    engine.set_depth_limit(None);
+    engine.set_slot_limit(Some(50));
+    assert_eq!(engine.eval_str("($list_head (build 10))").unwrap(), "10");
+    match engine.eval_str("($list_head (build 100))") {
+        Err(Error::Runtime(RuntimeError::SlotLimit { limit })) => assert_eq!(limit, 50),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    engine.set_slot_limit(None);
+    assert_eq!(engine.eval_str("($list_head (build 100))").unwrap(), "100");
+}
+
+#[test]
+fn test_engine_config() {
+    let config: EngineConfig = serde_json::from_str(
+        r#"{
+            "step_limit": 1000,
+            "profile": "pure-math",
+            "allow_groups": ["list"],
+            "deny_hosts": ["mul"],
+            "division": "floor",
+            "opt_level": 1
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(config.profile, Profile::PureMath);
+    assert_eq!(config.division, Division::Floor);
+    assert_eq!(config.numeric_coercion, NumericCoercion::IntToFloat);
+    assert_eq!(config.slot_limit, None);
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(serde_json::from_str::<EngineConfig>(&json).unwrap(), config);
+
+    let mut engine = Engine::with_config(&config);
+    assert_eq!(engine.eval_str("($mod ($sub 0 7) 2)").unwrap(), "1");
+    assert_eq!(
+        engine.eval_str("($list_head ($list_push 1 ~))").unwrap(),
+        "1"
+    );
+    match engine.eval_str("($mul 2 3)") {
+        Err(Error::Parse(_)) | Err(Error::Type(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    match engine.eval_str("$json_parse") {
+        Err(Error::Parse(_)) | Err(Error::Type(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    engine
+        .define("loop", r"(\n ($if ($le n 0) 0 (loop ($sub n 1))))")
+        .unwrap();
+    match engine.eval_str("(loop 100000)") {
+        Err(Error::Runtime(RuntimeError::StepLimit)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert_eq!(
+        engine
+            .map_over(r"(\n (loop n))", vec![OwnedValue::Int(10); 100])
+            .unwrap()
+            .len(),
+        100
+    );
+}
+
+#[test]
+fn test_engine_opt_level() {
+    let mut engine = Engine::new();
+    engine.set_opt_level(1);
+    let (e, _) = engine.prepare("($if true ($add 1 2) 0)").unwrap();
+    assert_eq!(e.to_string(), "(3)");
+    engine.set_opt_level(0);
+    let (e, _) = engine.prepare("($if true ($add 1 2) 0)").unwrap();
+    assert_eq!(e.to_string(), "($if true ($add 1 2) 0)");
+}
+
+#[test]
+fn test_engine_strict_floats() {
+    let mut engine = Engine::new();
+    assert_eq!(engine.eval_str("($div 1.0 0.0)").unwrap(), "inf");
+    engine.set_strict_floats(true);
+    match engine.eval_str("($add 1.0 ($div 0.0 0.0))") {
+        Err(Error::Runtime(RuntimeError::NonFiniteFloat { host })) => assert_eq!(host, "div"),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert_eq!(engine.eval_str("($div 1.0 4.0)").unwrap(), "0.25");
+    engine.set_strict_floats(false);
+    let e = engine
+        .parse("($sub ($div 1.0 0.0) ($div 1.0 0.0))")
+        .unwrap();
+    let outcome = engine.evaluate_with(&e, |v, _| Ok(v.to_string())).unwrap();
+    assert_eq!(outcome.value, "NaN");
+    assert_eq!(outcome.host_calls["div"], 2);
+    assert_eq!(
+        outcome.warnings.len(),
+        2,
+        "`$div` and `$sub` are reported once each"
+    );
+
+    engine.define("same", r"(\a b ($eq a b))").unwrap();
+    let e = engine
+        .parse("($and (same 0.1 0.2) ($and (same 0.3 0.4) (same 1 1)))")
+        .unwrap();
+    let (_, warnings) = engine.check_with_warnings(&e).unwrap();
+    assert_eq!(warnings.len(), 1, "each comparison is reported once");
+    assert_eq!(
+        warnings[0].to_string(),
+        "comparing floats with `$eq` is unreliable; compare their difference against a tolerance instead"
+    );
+}
+
+#[test]
+fn test_engine_division() {
+    let mut engine = Engine::new();
+    let cases = [
+        ("($div ($sub 0 7) 2)", "-3"),
+        ("($mod ($sub 0 7) 2)", "-1"),
+        ("($div_floor ($sub 0 7) 2)", "-4"),
+        ("($mod_floor ($sub 0 7) 2)", "1"),
+        ("($mod_floor 7 ($sub 0 2))", "-1"),
+        ("($div_euclid 7 ($sub 0 2))", "-3"),
+        ("($rem_euclid 7 ($sub 0 2))", "1"),
+        ("($rem_euclid ($sub 0 7) ($sub 0 2))", "1"),
+        ("($div_floor ($sub 0.0 7.0) 2)", "-4.0"),
+        ("($rem_euclid ($sub 0.0 7.5) 2.0)", "0.5"),
+        ("($mod_floor ($decimal \"-7.5\") 2)", "0.5"),
+        ("($div_euclid ($decimal \"-7.5\") 2)", "-4"),
+    ];
+    for &(source, expected) in &cases {
+        assert_eq!(engine.eval_str(source).unwrap(), expected, "{}", source);
+    }
+    match engine.eval_str("($div_floor 1 0)") {
+        Err(Error::Runtime(RuntimeError::DivByZero)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+
+    engine.set_division(Division::Floor);
+    assert_eq!(engine.eval_str("($div ($sub 0 7) 2)").unwrap(), "-4");
+    assert_eq!(engine.eval_str("($mod ($sub 0 7) 2)").unwrap(), "1");
+    engine.set_division(Division::Euclidean);
+    assert_eq!(engine.eval_str("($mod 7 ($sub 0 2))").unwrap(), "1");
+    engine.set_division(Division::Truncated);
+    assert_eq!(engine.eval_str("($mod ($sub 0 7) 2)").unwrap(), "-1");
+    assert_eq!(Division::from_name("floor"), Some(Division::Floor));
+}
+
+#[test]
+fn test_engine_int_overflow() {
+    let engine = Engine::new();
+    let max = "9223372036854775807";
+    let min = "($sub ($sub 0 9223372036854775807) 1)";
+    for source in &[
+        format!("($add {} 1)", max),
+        format!("($sub {} 1)", min),
+        format!("($mul {} 2)", max),
+        format!("($mul {} ($sub 0 1))", min),
+    ] {
+        match engine.eval_str(source) {
+            Err(Error::Runtime(RuntimeError::IntOverflow)) => {}
+            x => panic!("unexpected result for {}: {:?}", source, x),
+        }
+    }
+    assert_eq!(
+        engine
+            .eval_str(&format!("($add {} ($sub 0 1))", max))
+            .unwrap(),
+        "9223372036854775806"
+    );
+}
+
+#[test]
+fn test_engine_division_overflow() {
+    let mut engine = Engine::new();
+    let min = "($sub ($sub 0 9223372036854775807) 1)";
+    for &division in &[Division::Truncated, Division::Floor, Division::Euclidean] {
+        engine.set_division(division);
+        for op in &[
+            "div",
+            "mod",
+            "div_floor",
+            "mod_floor",
+            "div_euclid",
+            "rem_euclid",
+        ] {
+            let source = format!("(${} {} ($sub 0 1))", op, min);
+            match engine.eval_str(&source) {
+                Err(Error::Runtime(RuntimeError::IntOverflow)) => {}
+                x => panic!("unexpected result for {} ({:?}): {:?}", source, division, x),
+            }
+        }
+        let source = format!("($div {} 1)", min);
+        assert_eq!(engine.eval_str(&source).unwrap(), "-9223372036854775808");
+    }
+}
+
+#[test]
+fn test_engine_numeric_coercion() {
+    let mut engine = Engine::new();
+    assert_eq!(engine.eval_str("($add 1 0.5)").unwrap(), "1.5");
+    assert_eq!(engine.eval_str("($add 0.5 1)").unwrap(), "1.5");
+    assert_eq!(engine.eval_str("($lt 1 1.5)").unwrap(), "true");
+
+    engine.set_numeric_coercion(NumericCoercion::Forbidden);
+    engine.set_division(Division::Floor);
+    for source in &["($add 1 0.5)", "($div 0.5 1)", "($lt 1 1.5)", "($eq 1 1.0)"] {
+        match engine.eval_str(source) {
+            Err(Error::Type(TypeError::Custom(msg))) => {
+                assert!(msg.contains("$to_float"), "{}", msg)
+            }
+            x => panic!("unexpected result for {}: {:?}", source, x),
+        }
+    }
+    assert_eq!(engine.eval_str("($add ($to_float 1) 0.5)").unwrap(), "1.5");
+    assert_eq!(engine.eval_str("($add 1 2)").unwrap(), "3");
+    match engine.eval_str("($add ($dyn 1) 0.5)") {
+        Err(Error::Runtime(RuntimeError::TypeMismatch(_))) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+
+    engine.set_numeric_coercion(NumericCoercion::IntToFloat);
+    assert_eq!(engine.eval_str("($div 0.5 1)").unwrap(), "0.0");
+    assert_eq!(
+        NumericCoercion::from_name("forbidden"),
+        Some(NumericCoercion::Forbidden)
+    );
+}
+
+#[test]
+fn test_engine_quote_eval() {
+    let mut engine = Engine::new();
+    engine
+        .define(
+            "scaled",
+            r"(\rule k (quote ($mul (unquote rule) (unquote k))))",
+        )
+        .unwrap();
+    assert_eq!(
+        engine.eval_str("(scaled (quote ($add 1 2)) 10)").unwrap(),
+        "(quote ($mul ($add 1 2) 10))"
+    );
+    assert_eq!(
+        engine.eval_str("($eq (quote (1)) (quote (1)))").unwrap(),
+        "true"
+    );
+
+    // Evaluating quoted expressions needs the `Eval` group.
+    let program = "($eval (scaled (quote ($add 1 2)) 10) 0)";
+    assert!(matches!(engine.eval_str(program), Err(Error::Type(_))));
+    engine.allow_host_group(HostGroup::Eval);
+    assert_eq!(engine.eval_str(program).unwrap(), "30");
+    assert_eq!(engine.infer_type(program).unwrap(), TypeDescription::Int);
+    assert_eq!(
+        engine
+            .eval_str("($eval (quote (quote ($add 1 (unquote 2)))) (quote (0)))")
+            .unwrap(),
+        "(quote ($add 1 2))"
+    );
+
+    match engine.eval_str("($eval (quote true) 0)") {
+        Err(Error::Runtime(RuntimeError::TypeMismatch(_))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match engine.eval_str("($eval (quote ($add 1 true)) 0)") {
+        Err(Error::Runtime(RuntimeError::TypeMismatch(msg))) => {
+            assert!(msg.starts_with("in quoted expression"), "{}", msg)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_engine_type_aliases() {
+    let engine = Engine::new();
+    let program = r"(type Money float) (type Prices (list Money))
+        (\price (the Money ($mul price 1.5)))";
+    assert_eq!(
+        engine
+            .infer_call_type(program, &[TypeDescription::Float])
+            .unwrap(),
+        TypeDescription::Float
+    );
+    assert_eq!(
+        engine
+            .eval_str(r"(type Money float) ((\price (the Money ($mul price 1.5))) 2.0)")
+            .unwrap(),
+        "3.0"
+    );
+    assert_eq!(
+        engine
+            .eval_str("(type Prices (list float)) (the Prices ~)")
+            .unwrap(),
+        "~"
+    );
+
+    let err = engine
+        .eval_str(r"(type Money float) (the Money ($add 1 2))")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type error: annotated value: expecting Money (float), found int"
+    );
+    let err = engine
+        .eval_str(r"(type Money float) (the (list Money) (the Money 1.0))")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type error: annotated value: expecting list<Money> (list<float>), found float"
+    );
+    assert!(engine.eval_str("(the int true)").is_err());
+
+    let mut engine = Engine::new();
+    engine.define_type("Money", "float").unwrap();
+    let money = engine.named_type("Money").unwrap();
+    assert_eq!(money.to_string(), "Money");
+    assert_eq!(money.expand(), TypeDescription::Float);
+    assert_eq!(engine.eval_str("(the Money 2.5)").unwrap(), "2.5");
+    assert_eq!(
+        engine
+            .infer_call_type(r"(\x (the Money x))", &[money])
+            .unwrap(),
+        TypeDescription::Float
+    );
+    assert!(engine.define_type("Money", "int").is_err());
+    assert!(engine.named_type("Price").is_none());
+}
+
+/// Records each call and its bindings, stopping after `stop_after` calls.
+#[derive(Debug, Default)]
+struct Recorder {
+    calls: Vec<String>,
+    stop_after: Option<usize>,
+}
+
+impl Debugger for Recorder {
+    fn on_apply(&mut self, e: &Expr, bindings: &[(String, LazyValue)]) -> bool {
+        if self.stop_after == Some(self.calls.len()) {
+            return false;
+        }
+        let bindings: Vec<String> = bindings
+            .iter()
+            .map(|(name, lv)| match lv.outcome() {
+                Some(v) => format!("{}={}", name, v),
+                None => format!("{}=?", name),
+            })
+            .collect();
+        self.calls.push(format!("{} [{}]", e, bindings.join(" ")));
+        true
+    }
+}
+
+#[test]
+fn test_engine_debug_with() {
+    let engine = Engine::new();
+    let e = engine
+        .parse(r"((\x y ($add x ((\z ($mul z y)) x))) 1 2)")
+        .unwrap();
+    let mut recorder = Recorder::default();
+    let outcome = engine
+        .debug_with(&e, &mut recorder, |v, _| Ok(v.to_string()))
+        .unwrap();
+    assert_eq!(outcome.value, "3");
+    assert_eq!(
+        recorder.calls,
+        vec![
+            r"((\x y ($add x ((\z ($mul z y)) x))) 1 2) []",
+            r"($add x ((\z ($mul z y)) x)) [x=? y=?]",
+            r"((\z ($mul z y)) x) [y=? x=1]",
+            r"($mul z y) [z=1 y=?]",
+        ]
+    );
+
+    let mut recorder = Recorder {
+        stop_after: Some(1),
+        ..Recorder::default()
+    };
+    match engine.debug_with(&e, &mut recorder, |v, _| Ok(v.to_string())) {
+        Err(Error::Runtime(RuntimeError::Cancelled)) => {}
+        other => panic!("{:?}", other.map(|x| x.value)),
+    }
+    assert_eq!(recorder.calls.len(), 1);
+}
@@ -1,22 +1,276 @@
+use crate::ast::Span;
+use crate::eval::LeakedSlot;
+use crate::host::{AbiMismatch, HostSetDiff};
+use crate::parser::MAX_NESTING_DEPTH;
+use crate::typeck::TypeDescription;
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::fmt;
+
 #[derive(Debug)]
 pub enum ParseError {
     InvalidUtf8,
-    InvalidNumber,
+    InvalidNumber { literal: String, span: Span },
+    NumberOverflow { literal: String, span: Span },
     InvalidToken,
     UnexpectedEnd,
     ExpectingExprBegin,
     ExpectingExprBody,
     BracketMismatch,
+    NestingTooDeep,
     Custom(String),
 }
 
-#[derive(Debug)]
+impl ParseError {
+    /// Returns the source span the error refers to, if known.
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            ParseError::InvalidNumber { span, .. } | ParseError::NumberOverflow { span, .. } => {
+                Some(span)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a hint on how to fix the error, if there is one.
+    pub fn suggestion(&self) -> Option<String> {
+        match *self {
+            ParseError::NumberOverflow { ref literal, .. } => Some(if literal.contains('.') {
+                format!("`{}` is out of range for a float literal", literal)
+            } else {
+                format!(
+                    "`{}` does not fit in a 64-bit integer; use a float literal (`{}.0`) \
+                     or a big integer literal (`{}n`) instead",
+                    literal, literal, literal
+                )
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::InvalidUtf8 => write!(f, "source is not valid UTF-8"),
+            ParseError::InvalidNumber { ref literal, .. } => {
+                write!(f, "invalid number literal `{}`", literal)
+            }
+            ParseError::NumberOverflow { ref literal, .. } => {
+                write!(f, "number literal `{}` is out of range", literal)
+            }
+            ParseError::InvalidToken => write!(f, "invalid token"),
+            ParseError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            ParseError::ExpectingExprBegin => write!(f, "expecting `(`"),
+            ParseError::ExpectingExprBody => write!(f, "expecting an expression"),
+            ParseError::BracketMismatch => write!(f, "mismatched brackets"),
+            ParseError::NestingTooDeep => write!(
+                f,
+                "expressions are nested more than {} levels deep",
+                MAX_NESTING_DEPTH
+            ),
+            ParseError::Custom(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl ::std::error::Error for ParseError {}
+
+#[derive(Debug, Clone)]
 pub enum TypeError {
+    /// A value of the wrong type where a declared one is expected.
+    Mismatch {
+        /// What the value is, e.g. `result` or `return value`.
+        what: String,
+        expected: TypeDescription,
+        found: TypeDescription,
+    },
+    /// The registered host functions differ from those a program was built
+    /// against.
+    IncompatibleHosts(Vec<AbiMismatch>),
     Custom(String),
 }
 
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TypeError::Mismatch {
+                ref what,
+                ref expected,
+                ref found,
+            } => {
+                write!(f, "{}: expecting {}", what, expected)?;
+                let expanded = expected.expand();
+                if expanded != *expected {
+                    write!(f, " ({})", expanded)?;
+                }
+                write!(f, ", found {}", found)
+            }
+            TypeError::IncompatibleHosts(ref mismatches) => {
+                write!(f, "incompatible host functions: ")?;
+                for (i, m) in mismatches.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", m)?;
+                }
+                Ok(())
+            }
+            TypeError::Custom(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl ::std::error::Error for TypeError {}
+
 #[derive(Debug)]
 pub enum RuntimeError {
     DivByZero,
+    /// An int operation whose result does not fit in an int.
+    IntOverflow,
+    /// A `SlotRef` was used after its slot had been released.
+    StaleSlot,
+    /// Slots still allocated after evaluation with leak checking enabled.
+    LeakedSlots(Vec<LeakedSlot>),
+    /// An async host function was called outside of `eval_expr_async`.
+    AsyncHostCall,
+    /// Evaluation ran for more steps than allowed.
+    StepLimit,
+    /// Evaluation nested `depth` expressions deep, the most allowed, e.g.
+    /// in recursion that is not in tail position.
+    StackOverflow {
+        depth: u32,
+    },
+    /// More than `limit` slots were allocated at the same time.
+    SlotLimit {
+        limit: usize,
+    },
+    /// The host function `host` returned NaN or an infinite float while
+    /// strict floats were enabled.
+    NonFiniteFloat {
+        host: String,
+    },
+    /// The cancel flag was set while evaluating.
+    Cancelled,
+    /// A value was needed to compute itself. `binding` is the variable or
+    /// definition it was reached through, if any.
+    CircularEvaluation {
+        binding: Option<String>,
+    },
+    /// A name that is neither bound locally nor defined, e.g. because the
+    /// expression was not typechecked against the definitions in use.
+    UnboundName(String),
+    /// A host function that is not registered with the evaluation context.
+    HostNotRegistered(String),
+    /// A value of the wrong type, which typeck would have rejected.
+    TypeMismatch(String),
+    /// Typeck and evaluation were set up with different host functions.
+    HostSetMismatch(HostSetDiff),
     Custom(String),
 }
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeError::DivByZero => write!(f, "division by zero"),
+            RuntimeError::IntOverflow => write!(f, "integer overflow"),
+            RuntimeError::StaleSlot => write!(f, "stale slot reference"),
+            RuntimeError::AsyncHostCall => {
+                write!(f, "async host function called from synchronous evaluation")
+            }
+            RuntimeError::StepLimit => write!(f, "step limit exceeded"),
+            RuntimeError::StackOverflow { depth } => {
+                write!(f, "stack overflow: evaluation nested {} levels deep", depth)
+            }
+            RuntimeError::SlotLimit { limit } => {
+                write!(
+                    f,
+                    "memory limit exceeded: more than {} slots allocated",
+                    limit
+                )
+            }
+            RuntimeError::NonFiniteFloat { ref host } => {
+                write!(f, "${} returned a float that is NaN or infinite", host)
+            }
+            RuntimeError::Cancelled => write!(f, "evaluation cancelled"),
+            RuntimeError::CircularEvaluation {
+                binding: Some(ref name),
+            } => {
+                write!(f, "circular evaluation of {}", name)
+            }
+            RuntimeError::CircularEvaluation { binding: None } => {
+                write!(f, "circular evaluation")
+            }
+            RuntimeError::LeakedSlots(ref leaked) => {
+                write!(f, "{} slot(s) leaked", leaked.len())?;
+                let hosts: BTreeSet<&str> =
+                    leaked.iter().filter_map(|x| x.host.as_deref()).collect();
+                if !hosts.is_empty() {
+                    let hosts: Vec<String> = hosts.iter().map(|x| format!("${}", x)).collect();
+                    write!(f, " by {}", hosts.join(", "))?;
+                }
+                Ok(())
+            }
+            RuntimeError::UnboundName(ref name) => write!(f, "unbound name: {}", name),
+            RuntimeError::HostNotRegistered(ref name) => {
+                write!(f, "host function not registered: ${}", name)
+            }
+            RuntimeError::TypeMismatch(ref msg) => write!(f, "type mismatch: {}", msg),
+            RuntimeError::HostSetMismatch(ref diff) => {
+                write!(f, "host functions differ between typeck and eval: {}", diff)
+            }
+            RuntimeError::Custom(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl ::std::error::Error for RuntimeError {}
+
+/// Any error produced by the parse → typecheck → eval pipeline.
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError),
+    Type(TypeError),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Parse(ref e) => write!(f, "parse error: {}", e),
+            Error::Type(ref e) => write!(f, "type error: {}", e),
+            Error::Runtime(ref e) => write!(f, "runtime error: {}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error {
+        Error::Parse(e)
+    }
+}
+
+impl From<TypeError> for Error {
+    fn from(e: TypeError) -> Error {
+        Error::Type(e)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(e: RuntimeError) -> Error {
+        Error::Runtime(e)
+    }
+}
+
+/// The message a panic was raised with, for reporting it as an error.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "panicked"
+    }
+}
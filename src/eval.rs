@@ -1,13 +1,26 @@
+use crate::ast::resolve::{Resolution, Slot};
 use crate::ast::*;
+use crate::audit::{AuditEntry, AuditLog, AuditValue};
+use crate::definitions::Definitions;
 use crate::error::*;
 use crate::host::*;
-use rpds::RedBlackTreeMap;
+use crate::journal::{self, Call, Journal};
+use crate::log::LogSink;
+use crate::metrics::Metrics;
+use crate::pool::ValuePool;
+use crate::suspend::{Resume, Suspender, Suspension};
+use crate::typeck::TypeResolveState;
+use crate::warning::{Warning, WarningSink};
 use slab::Slab;
-use std::any::Any;
+use std::any::{Any, TypeId};
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::fmt::Debug;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::hash::Hasher;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub enum RuntimeValue<'b> {
@@ -16,29 +29,224 @@ pub enum RuntimeValue<'b> {
     Float(f64),
     Bool(bool),
     Function {
+        /// Parameters not supplied yet.
         params: &'b [String],
         body: &'b Expr,
-        context_values: RedBlackTreeMap<&'b String, LazyValue<'b>>,
+        /// Captured variables and the arguments supplied so far.
+        env: Env<'b>,
     },
     Host(&'b String),
+    /// A host function applied to fewer arguments than it requires.
+    PartialHost {
+        name: &'b String,
+        args: Vec<LazyValue<'b>>,
+    },
+    Custom(CustomValueBox),
+}
+
+impl<'b> fmt::Display for RuntimeValue<'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeValue::Empty => write!(f, "~"),
+            RuntimeValue::Int(v) => write!(f, "{}", v),
+            RuntimeValue::Float(v) => write!(f, "{:?}", v),
+            RuntimeValue::Bool(v) => write!(f, "{}", v),
+            RuntimeValue::Function { .. } => write!(f, "<function>"),
+            RuntimeValue::Host(name) => write!(f, "${}", name),
+            RuntimeValue::PartialHost { .. } => write!(f, "<function>"),
+            RuntimeValue::Custom(ref v) => v.inner.display(f),
+        }
+    }
+}
+
+impl<'b> RuntimeValue<'b> {
+    /// Copies the value out of the AST and `ectx`, so it can be kept after
+    /// both are dropped. Lists are evaluated element by element.
+    pub fn into_owned<'c>(
+        self,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<OwnedValue, RuntimeError> {
+        Ok(match self {
+            RuntimeValue::Empty => OwnedValue::Empty,
+            RuntimeValue::Int(v) => OwnedValue::Int(v),
+            RuntimeValue::Float(v) => OwnedValue::Float(v),
+            RuntimeValue::Bool(v) => OwnedValue::Bool(v),
+            RuntimeValue::Function { params, body, .. } => OwnedValue::Function {
+                params: params.to_vec(),
+                body: body.clone(),
+            },
+            RuntimeValue::Host(name) => OwnedValue::Host(name.clone()),
+            RuntimeValue::PartialHost { name, args } => OwnedValue::PartialHost {
+                name: name.clone(),
+                supplied: args.len(),
+            },
+            RuntimeValue::Custom(cv) => match cv.inner.to_owned_value(ectx)? {
+                Some(v) => v,
+                None => OwnedValue::Custom(cv),
+            },
+        })
+    }
+}
+
+/// A runtime value that owns everything it refers to.
+///
+/// Closures keep their parameters and body but not their captured
+/// environment, so they can be inspected but not called.
+#[derive(Debug, Clone)]
+pub enum OwnedValue {
+    Empty,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Function {
+        params: Vec<String>,
+        body: Expr,
+    },
+    Host(String),
+    /// A partially applied host function and the number of arguments it
+    /// has been given.
+    PartialHost {
+        name: String,
+        supplied: usize,
+    },
+    List(Vec<OwnedValue>),
     Custom(CustomValueBox),
 }
 
+impl PartialEq for OwnedValue {
+    fn eq(&self, other: &OwnedValue) -> bool {
+        match (self, other) {
+            (OwnedValue::Empty, OwnedValue::Empty) => true,
+            (OwnedValue::Int(a), OwnedValue::Int(b)) => a == b,
+            (OwnedValue::Float(a), OwnedValue::Float(b)) => a == b,
+            (OwnedValue::Bool(a), OwnedValue::Bool(b)) => a == b,
+            (
+                OwnedValue::Function {
+                    params: p1,
+                    body: b1,
+                },
+                OwnedValue::Function {
+                    params: p2,
+                    body: b2,
+                },
+            ) => p1 == p2 && b1 == b2,
+            (OwnedValue::Host(a), OwnedValue::Host(b)) => a == b,
+            (
+                OwnedValue::PartialHost {
+                    name: n1,
+                    supplied: s1,
+                },
+                OwnedValue::PartialHost {
+                    name: n2,
+                    supplied: s2,
+                },
+            ) => n1 == n2 && s1 == s2,
+            (OwnedValue::List(a), OwnedValue::List(b)) => a == b,
+            (OwnedValue::Custom(a), OwnedValue::Custom(b)) => Rc::ptr_eq(&a.inner, &b.inner),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for OwnedValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OwnedValue::Empty => write!(f, "~"),
+            OwnedValue::Int(v) => write!(f, "{}", v),
+            OwnedValue::Float(v) => write!(f, "{:?}", v),
+            OwnedValue::Bool(v) => write!(f, "{}", v),
+            OwnedValue::Function { .. } => write!(f, "<function>"),
+            OwnedValue::Host(ref name) => write!(f, "${}", name),
+            OwnedValue::PartialHost { .. } => write!(f, "<function>"),
+            OwnedValue::List(ref values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+            OwnedValue::Custom(ref v) => v.inner.display(f),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CustomValueBox {
-    pub inner: Rc<Box<CustomValue>>,
+    pub inner: Rc<Box<dyn CustomValue>>,
 }
 
 impl CustomValueBox {
-    pub fn new(inner: Box<CustomValue>) -> CustomValueBox {
+    pub fn new(inner: Box<dyn CustomValue>) -> CustomValueBox {
         CustomValueBox {
             inner: Rc::new(inner),
         }
     }
 }
 
+/// Receives control before each call is evaluated, e.g. to let a user step
+/// through a program. See `EvalContext::set_debugger`.
+pub trait Debugger: Debug {
+    /// Called before the call `e` is evaluated, with the local variables it
+    /// refers to by their source names. Variables not evaluated yet have no
+    /// `outcome`; inspecting them must not force them. Returning false
+    /// stops evaluation with `RuntimeError::Cancelled`.
+    fn on_apply(&mut self, e: &Expr, bindings: &[(String, LazyValue)]) -> bool;
+}
+
 pub trait CustomValue: Debug {
-    fn as_any(&self) -> &Any;
+    fn as_any(&self) -> &dyn Any;
+
+    /// Converts the value into an `OwnedValue` for `RuntimeValue::into_owned`.
+    /// Values that do not refer to the evaluation context can keep the
+    /// default, which preserves them as `OwnedValue::Custom`.
+    fn to_owned_value<'b, 'c>(
+        &self,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<OwnedValue>, RuntimeError> {
+        Ok(None)
+    }
+
+    /// Compares the value with `other` for `$eq` and `$ne`. `other` always
+    /// has the same type as `self` according to typeck. Returns `None` if the
+    /// value does not support equality; `$eq` then falls back to `compare`,
+    /// and fails at runtime if that is not supported either.
+    fn value_eq<'b, 'c>(
+        &self,
+        _other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<bool>, RuntimeError> {
+        Ok(None)
+    }
+
+    /// Orders the value against `other`, which has the same type, for
+    /// `$lt`, `$le`, `$gt` and `$ge`. Returns `None` if the value is not
+    /// ordered.
+    fn compare<'b, 'c>(
+        &self,
+        _other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<Ordering>, RuntimeError> {
+        Ok(None)
+    }
+
+    /// Feeds the value into `state`, e.g. to use it as a map key. Values
+    /// that are equal must hash the same. Returns `false` if the value
+    /// cannot be hashed.
+    fn hash<'b, 'c>(
+        &self,
+        _state: &mut dyn Hasher,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<bool, RuntimeError> {
+        Ok(false)
+    }
+
+    /// Writes the value as shown by `RuntimeValue` and `OwnedValue`.
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<custom value>")
+    }
 }
 
 impl Clone for CustomValueBox {
@@ -51,17 +259,173 @@ impl Clone for CustomValueBox {
 
 #[derive(Clone, Debug)]
 pub struct LazyValue<'b> {
-    expr: &'b Expr,
-    context_values: RedBlackTreeMap<&'b String, LazyValue<'b>>,
+    expr: Option<&'b Expr>,
+    env: Env<'b>,
     outcome: Rc<RefCell<Option<RuntimeValue<'b>>>>,
 }
 
+/// The variables in scope in a function body, laid out as described in
+/// `ast::resolve`.
+#[derive(Clone, Debug, Default)]
+pub struct Env<'b> {
+    params: Rc<[LazyValue<'b>]>,
+    captures: Rc<[LazyValue<'b>]>,
+}
+
+impl<'b> Env<'b> {
+    pub fn get(&self, slot: Slot) -> &LazyValue<'b> {
+        match slot {
+            Slot::Param(i) => &self.params[i],
+            Slot::Capture(i) => &self.captures[i],
+        }
+    }
+
+    pub fn params(&self) -> &[LazyValue<'b>] {
+        &self.params
+    }
+
+    pub fn captures(&self) -> &[LazyValue<'b>] {
+        &self.captures
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct EvalContext<'b, 'c> {
-    values: RedBlackTreeMap<&'b String, LazyValue<'b>>,
-    host_functions: HashMap<String, &'c dyn HostFunction>,
-    slots: Slab<LazyValue<'b>>,
+    values: Env<'b>,
+    host_functions: HashMap<String, HostHandle<'c>>,
+    slots: Slab<(u64, LazyValue<'b>)>,
+    definitions: Option<&'b Definitions>,
+    definition_values: HashMap<String, (u64, LazyValue<'b>)>,
     pub release_pool: SlotReleasePool,
+    value_pool: ValuePool<'b>,
+    /// Generation of the most recently written slot. Never reset, so that
+    /// refs from before `reset` are detected as stale too.
+    slot_generation: u64,
+    /// Host function currently being evaluated, for leak reports.
+    current_host: Option<&'b String>,
+    /// When leak checking is enabled, the host function (if any) that wrote
+    /// each live slot.
+    slot_origins: Option<HashMap<usize, Option<&'b String>>>,
+    host_state: HostState,
+    /// Number of expressions evaluated so far.
+    steps: u64,
+    step_limit: Option<u64>,
+    /// Most expressions allowed to be evaluated at once.
+    depth_limit: Option<u32>,
+    /// Most slots allowed to be allocated at once.
+    slot_limit: Option<usize>,
+    cancel_flag: Option<Arc<AtomicBool>>,
+    /// Host function calls recorded so far, if auditing is enabled.
+    audit: Option<AuditLog>,
+    /// Where to journal calls to host functions with side effects, and
+    /// what to replay them from, for executions.
+    journal: Option<Rc<RefCell<Journal>>>,
+    /// Values expressions evaluated to so far, if recording is enabled.
+    recording: Option<Vec<RecordedStep>>,
+    /// Number of host function calls in progress.
+    host_depth: u32,
+    resolution: Resolution,
+    /// Whether arguments of lambda calls are evaluated before the call.
+    eager: bool,
+    /// Whether host functions returning NaN or infinity fail.
+    strict_floats: bool,
+    memo: MemoTable<'b>,
+    /// Patterns compiled by the regex host functions. Kept across `reset`.
+    #[cfg(feature = "regex")]
+    regex_cache: crate::pattern::RegexCache,
+    /// Number of expressions being evaluated, and the most there were.
+    depth: u32,
+    peak_depth: u32,
+    slots_allocated: u64,
+    peak_slots: usize,
+    host_calls: HashMap<&'b String, u64>,
+    warnings: WarningSink,
+    debugger: Option<&'c mut dyn Debugger>,
+    /// Set when evaluation runs on a stack of its own, see `suspend`.
+    suspender: Option<Suspender<'c>>,
+    log_sink: Option<Rc<dyn LogSink>>,
+    /// Spans of the host function calls in progress, innermost last. Kept
+    /// here rather than on the stack, which deep recursion needs.
+    #[cfg(feature = "tracing")]
+    host_spans: Vec<crate::trace::Timed>,
+}
+
+/// The result of `evaluate`: the value and what computing it took.
+#[derive(Debug)]
+pub struct EvalOutcome<V> {
+    pub value: V,
+    /// Number of expressions evaluated.
+    pub steps: u64,
+    /// Number of calls of each host function, by name without the `$`.
+    pub host_calls: BTreeMap<String, u64>,
+    /// Warnings not raised before in the same context.
+    pub warnings: Vec<Warning>,
+}
+
+impl<V> EvalOutcome<V> {
+    pub fn map<U, F: FnOnce(V) -> U>(self, f: F) -> EvalOutcome<U> {
+        EvalOutcome {
+            value: f(self.value),
+            steps: self.steps,
+            host_calls: self.host_calls,
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// Results of functions wrapped with `$memo`, keyed by the cell of the
+/// wrapped function value and a hash of the arguments.
+pub(crate) type MemoTable<'b> = HashMap<(usize, u64), Vec<MemoEntry<'b>>>;
+
+#[derive(Debug)]
+pub(crate) struct MemoEntry<'b> {
+    /// Keeps the cell alive so that its address is not reused.
+    pub _function: LazyValue<'b>,
+    pub args: Vec<RuntimeValue<'b>>,
+    pub result: RuntimeValue<'b>,
+}
+
+/// Embedder-provided values for host functions to use, keyed by type.
+///
+/// At most one value of each type is stored. Wrap shared handles such as
+/// database connections in an `Rc` so a host function can clone one out and
+/// keep using it while evaluating its arguments.
+#[derive(Default)]
+pub struct HostState {
+    values: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl HostState {
+    /// Stores `value`, returning the previous value of the same type.
+    pub fn insert<T: Any>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .map(|old| *old.downcast::<T>().unwrap())
+    }
+
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .map(|v| v.downcast_ref::<T>().unwrap())
+    }
+
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.values
+            .get_mut(&TypeId::of::<T>())
+            .map(|v| v.downcast_mut::<T>().unwrap())
+    }
+
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.values
+            .remove(&TypeId::of::<T>())
+            .map(|v| *v.downcast::<T>().unwrap())
+    }
+}
+
+impl Debug for HostState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HostState({} values)", self.values.len())
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -74,17 +438,78 @@ impl SlotReleasePool {
         self.pool.borrow_mut().push(r);
     }
 
+    /// Frees every slot put into the pool, including those put in while
+    /// dropping the values of released slots.
     pub fn release<'b, 'c>(&self, ctx: &mut EvalContext<'b, 'c>) {
-        let pool = ::std::mem::replace(&mut *self.pool.borrow_mut(), Vec::new());
-        for r in pool {
-            ctx.slots.remove(r.id);
+        loop {
+            let pool = ::std::mem::take(&mut *self.pool.borrow_mut());
+            if pool.is_empty() {
+                break;
+            }
+            for r in pool {
+                if ctx.slots.get(r.id).map(|x| x.0) == Some(r.generation) {
+                    ctx.slots.remove(r.id);
+                    if let Some(ref mut origins) = ctx.slot_origins {
+                        origins.remove(&r.id);
+                    }
+                } else {
+                    debug_assert!(false, "bug: stale SlotRef released");
+                }
+            }
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// A slot that is put into the context's release pool when dropped, so
+/// host functions cannot forget to release it.
+///
+/// Custom values that keep evaluated data in the context should hold their
+/// slots through `ScopedSlot`s.
+#[derive(Debug)]
+pub struct ScopedSlot {
+    slot: SlotRef,
+    pool: SlotReleasePool,
+}
+
+impl ScopedSlot {
+    /// Returns a ref that does not keep the slot alive. Reading it after
+    /// the `ScopedSlot` is dropped and released fails with
+    /// `RuntimeError::StaleSlot`.
+    pub fn downgrade(&self) -> SlotRef {
+        self.slot
+    }
+
+    pub fn read<'b, 'c>(
+        &self,
+        ctx: &mut EvalContext<'b, 'c>,
+    ) -> Result<LazyValue<'b>, RuntimeError> {
+        ctx.read_slot(self.slot)
+    }
+}
+
+impl Drop for ScopedSlot {
+    fn drop(&mut self) {
+        self.pool.put(self.slot);
+    }
+}
+
+/// A slot that was still allocated when leaks were checked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeakedSlot {
+    pub slot: SlotRef,
+    /// Host function that wrote the slot, or `None` if it was written
+    /// outside of any host function call.
+    pub host: Option<String>,
+}
+
+/// A reference to a value stored with `EvalContext::write_slot`.
+///
+/// Slots are reused once released; the generation tells a ref to the
+/// current occupant from a stale one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct SlotRef {
     id: usize,
+    generation: u64,
 }
 
 impl<'b, 'c> EvalContext<'b, 'c> {
@@ -92,28 +517,618 @@ impl<'b, 'c> EvalContext<'b, 'c> {
         &mut self,
         host_functions: H,
     ) {
-        self.host_functions.extend(host_functions);
+        self.host_functions.extend(
+            host_functions
+                .into_iter()
+                .map(|(k, v)| (k, HostHandle::Borrowed(v))),
+        );
+    }
+
+    /// Registers host functions under `namespace`, callable as
+    /// `$namespace.name`.
+    pub fn add_hosts_in<H: IntoIterator<Item = (String, &'c dyn HostFunction)>>(
+        &mut self,
+        namespace: &str,
+        host_functions: H,
+    ) {
+        self.add_hosts(
+            host_functions
+                .into_iter()
+                .map(|(k, v)| (qualified_name(namespace, &k), v)),
+        );
+    }
+
+    /// Makes `$alias` call the host function registered as `target`.
+    /// Returns false, registering nothing, if there is no such function or
+    /// `alias` already names a host function or alias.
+    pub fn alias_host(&mut self, alias: &str, target: &str) -> bool {
+        if self.host_functions.contains_key(alias) {
+            return false;
+        }
+        match self.host_functions.get(target) {
+            Some(hf) => {
+                let hf = hf.clone();
+                self.host_functions.insert(alias.to_string(), hf);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of the registered host functions, without the `$`.
+    pub fn host_names(&self) -> impl Iterator<Item = &str> {
+        self.host_functions.keys().map(|k| k.as_str())
+    }
+
+    /// Registers host functions owned by this context, so that it does not
+    /// borrow from whoever provides them.
+    pub fn add_hosts_owned<H: IntoIterator<Item = (String, Arc<dyn HostFunction>)>>(
+        &mut self,
+        host_functions: H,
+    ) {
+        self.host_functions.extend(
+            host_functions
+                .into_iter()
+                .map(|(k, v)| (k, HostHandle::Owned(v))),
+        );
+    }
+
+    /// Makes the definitions in `defs` resolvable as global names.
+    ///
+    /// Values of definitions are computed at most once per generation and
+    /// cached in this context.
+    pub fn set_definitions(&mut self, defs: &'b Definitions) {
+        self.definitions = Some(defs);
+    }
+
+    fn definition_value(&mut self, name: &str) -> Option<LazyValue<'b>> {
+        let def = self.definitions?.get(name)?;
+        if let Some((generation, ref lv)) = self.definition_values.get(name) {
+            if *generation == def.generation {
+                return Some(lv.clone());
+            }
+        }
+
+        let lv = LazyValue::new(&def.expr, Env::default());
+        self.definition_values
+            .insert(name.to_string(), (def.generation, lv.clone()));
+        Some(lv)
+    }
+
+    /// Drops all state left over from previous evaluations (values, slots,
+    /// pending releases and cached definition values) while keeping the
+    /// registered host functions and definitions.
+    ///
+    /// Values produced before the reset must not be used afterwards; reading
+    /// a slot written before the reset fails with `RuntimeError::StaleSlot`.
+    pub fn reset(&mut self) {
+        self.values = Env::default();
+        self.slots.clear();
+        if let Some(ref mut origins) = self.slot_origins {
+            origins.clear();
+        }
+        self.definition_values.clear();
+        self.memo.clear();
+        self.resolution = Resolution::new();
+        self.value_pool.clear();
+        // Custom values from earlier runs may still hold the old pool and
+        // put their slots into it when dropped. Those must not be released
+        // from the new slab.
+        self.release_pool = SlotReleasePool::default();
     }
 
+    /// Stores `v` in a new slot. The slot stays allocated until its ref is
+    /// put into `release_pool`; prefer `write_scoped_slot`, which does that
+    /// automatically.
     pub fn write_slot(&mut self, v: LazyValue<'b>) -> SlotRef {
+        self.slot_generation += 1;
+        let id = self.slots.insert((self.slot_generation, v));
+        self.slots_allocated += 1;
+        self.peak_slots = self.peak_slots.max(self.slots.len());
+        if let Some(ref mut origins) = self.slot_origins {
+            origins.insert(id, self.current_host);
+        }
         SlotRef {
-            id: self.slots.insert(v),
+            id,
+            generation: self.slot_generation,
+        }
+    }
+
+    /// Stores `v` in a new slot that is released once the returned guard is
+    /// dropped.
+    pub fn write_scoped_slot(&mut self, v: LazyValue<'b>) -> ScopedSlot {
+        ScopedSlot {
+            slot: self.write_slot(v),
+            pool: self.release_pool.clone(),
+        }
+    }
+
+    /// Returns the embedder-provided value of type `T`, if any.
+    pub fn host_state<T: Any>(&self) -> Option<&T> {
+        self.host_state.get()
+    }
+
+    pub fn host_state_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.host_state.get_mut()
+    }
+
+    /// Replaces the values host functions can reach through `host_state`,
+    /// returning the previous ones. `reset` keeps them.
+    pub fn set_host_state(&mut self, state: HostState) -> HostState {
+        ::std::mem::replace(&mut self.host_state, state)
+    }
+
+    /// Number of expressions evaluated by this context so far, across runs.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Warnings raised by the evaluations in this context so far.
+    pub fn warnings(&self) -> &[Warning] {
+        self.warnings.warnings()
+    }
+
+    fn warn(&mut self, w: Warning) {
+        self.warnings.warn(w);
+    }
+
+    /// Resource usage of the evaluations in this context so far.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            steps: self.steps,
+            peak_depth: self.peak_depth,
+            slots_allocated: self.slots_allocated,
+            peak_slots: self.peak_slots,
+            host_calls: self
+                .host_calls
+                .iter()
+                .map(|(k, v)| ((*k).clone(), *v))
+                .collect(),
         }
     }
 
-    pub fn read_slot(&mut self, r: SlotRef) -> LazyValue<'b> {
-        self.slots[r.id].clone()
+    /// Makes evaluation fail with `RuntimeError::StepLimit` once `steps`
+    /// would exceed `limit`, warning with `Warning::FuelLow` once it
+    /// reaches 90% of it.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Makes evaluation fail with `RuntimeError::StackOverflow` instead of
+    /// nesting more than `limit` expressions, so that deep recursion is
+    /// stopped before it exhausts the native stack. Each level can take
+    /// several kilobytes of stack in debug builds, much less in release
+    /// builds.
+    pub fn set_depth_limit(&mut self, limit: Option<u32>) {
+        self.depth_limit = limit;
+    }
+
+    /// Makes evaluation fail with `RuntimeError::SlotLimit` once more than
+    /// `limit` slots are allocated at the same time, which bounds the memory
+    /// lists and other values written by host functions take.
+    pub fn set_slot_limit(&mut self, limit: Option<usize>) {
+        self.slot_limit = limit;
+    }
+
+    /// Lets evaluation suspend through `suspender` instead of failing with
+    /// `RuntimeError::StepLimit`, until it is resumed with more steps.
+    pub(crate) fn set_suspender(&mut self, suspender: Option<Suspender<'c>>) {
+        self.suspender = suspender;
+    }
+
+    /// Suspends evaluation until its driver resumes it, returning what it
+    /// was resumed with, or `None` if evaluation cannot suspend because it
+    /// does not run on a stack of its own.
+    pub(crate) fn suspend(&mut self, why: Suspension) -> Option<Resume> {
+        let suspender = self.suspender?;
+        Some(suspender.suspend(&mut self.host_state, why))
+    }
+
+    /// Name of the host function being evaluated, if any.
+    pub fn current_host(&self) -> Option<&'b String> {
+        self.current_host
+    }
+
+    pub(crate) fn memo_table(&mut self) -> &mut MemoTable<'b> {
+        &mut self.memo
+    }
+
+    /// Patterns compiled by `$regex_match` and the other regex host
+    /// functions so far.
+    #[cfg(feature = "regex")]
+    pub fn regex_cache(&mut self) -> &mut crate::pattern::RegexCache {
+        &mut self.regex_cache
+    }
+
+    /// A type state with the host functions and definitions of this
+    /// context, for checking expressions built during evaluation.
+    pub(crate) fn type_state(&self) -> TypeResolveState<'_> {
+        let mut trs = TypeResolveState::default();
+        trs.add_hosts(
+            self.host_functions
+                .iter()
+                .map(|(k, v)| (k.clone(), &**v as &dyn HostFunction)),
+        );
+        if let Some(defs) = self.definitions {
+            trs.set_definitions(defs);
+        }
+        trs
+    }
+
+    /// Evaluates `e`, an expression built during this evaluation, and
+    /// passes the result to `f`.
+    ///
+    /// `e` does not live as long as the values of this context, so it is
+    /// evaluated in a context of its own. That context shares the host
+    /// functions, definitions and host state of this one, and its steps
+    /// and depth count against this one's limits.
+    pub(crate) fn eval_nested<T, F>(&mut self, e: &Expr, f: F) -> Result<T, RuntimeError>
+    where
+        F: for<'x> FnOnce(RuntimeValue<'x>) -> Result<T, RuntimeError>,
+    {
+        let mut nested = EvalContext {
+            host_functions: self.host_functions.clone(),
+            definitions: self.definitions,
+            step_limit: self.step_limit.map(|l| l.saturating_sub(self.steps)),
+            depth: self.depth,
+            depth_limit: self.depth_limit,
+            slot_limit: self.slot_limit.map(|l| l.saturating_sub(self.slots.len())),
+            cancel_flag: self.cancel_flag.clone(),
+            eager: self.eager,
+            strict_floats: self.strict_floats,
+            host_state: ::std::mem::take(&mut self.host_state),
+            #[cfg(feature = "regex")]
+            regex_cache: ::std::mem::take(&mut self.regex_cache),
+            ..EvalContext::default()
+        };
+        let ret = eval_expr(e, &mut nested).and_then(f);
+        self.host_state = ::std::mem::take(&mut nested.host_state);
+        #[cfg(feature = "regex")]
+        {
+            self.regex_cache = ::std::mem::take(&mut nested.regex_cache);
+        }
+        self.steps += nested.steps;
+        for w in nested.warnings.warnings() {
+            self.warn(w.clone());
+        }
+        ret
+    }
+
+    /// Switches between lazy evaluation, the default, and eager evaluation.
+    ///
+    /// In eager mode the arguments of a lambda are evaluated before its body,
+    /// in order. Arguments of host functions stay lazy, so `$if` only
+    /// evaluates the branch it takes and `$delay` can defer a value until
+    /// it is passed to `$force`.
+    pub fn set_eager(&mut self, eager: bool) {
+        self.eager = eager;
+    }
+
+    /// Makes host functions that return NaN or an infinite float, such as
+    /// `$div` by `0.0` or `$mul` overflowing, fail with
+    /// `RuntimeError::NonFiniteFloat` instead of letting the value
+    /// propagate.
+    pub fn set_strict_floats(&mut self, strict: bool) {
+        self.strict_floats = strict;
+    }
+
+    /// Makes evaluation fail with `RuntimeError::Cancelled` soon after
+    /// `flag` is set, e.g. by a supervising thread. The flag is polled every
+    /// `CANCEL_CHECK_INTERVAL` steps and is not cleared by the context.
+    pub fn set_cancel_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.cancel_flag = flag;
+    }
+
+    /// Makes evaluation call `debugger` before every call it evaluates.
+    /// Expressions built during evaluation, such as those `$eval` runs, are
+    /// not shown to it.
+    pub fn set_debugger(&mut self, debugger: Option<&'c mut dyn Debugger>) {
+        self.debugger = debugger;
+    }
+
+    // Not inlined into `call_host`, whose frame deep recursion repeats.
+    #[cfg(feature = "tracing")]
+    #[inline(never)]
+    fn enter_host_span(&mut self, name: &str) {
+        self.host_spans.push(crate::trace::host_call(name));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[inline(never)]
+    fn exit_host_span(&mut self) {
+        self.host_spans.pop();
+    }
+
+    /// Sends what scripts log with `$log` to `sink`. Without one, logged
+    /// records are dropped.
+    pub fn set_log_sink(&mut self, sink: Option<Rc<dyn LogSink>>) {
+        self.log_sink = sink;
+    }
+
+    pub fn log_sink(&self) -> Option<&dyn LogSink> {
+        self.log_sink.as_deref()
+    }
+
+    /// Enables or disables recording host function calls. Enabling starts
+    /// a new, empty log.
+    pub fn set_audit(&mut self, enabled: bool) {
+        self.audit = if enabled {
+            Some(AuditLog::default())
+        } else {
+            None
+        };
+    }
+
+    fn audit_call(&mut self, name: &str, args: &[LazyValue<'b>], result: &RuntimeValue<'b>) {
+        let entry = AuditEntry {
+            host: name.to_string(),
+            args: args.iter().map(AuditValue::from_lazy).collect(),
+            result: AuditValue::from_value(result),
+            depth: self.host_depth,
+        };
+        if let Some(ref mut log) = self.audit {
+            log.push(entry);
+        }
+    }
+
+    /// Returns the calls recorded since auditing was enabled, and stops
+    /// recording.
+    pub fn take_audit_log(&mut self) -> Option<AuditLog> {
+        self.audit.take()
+    }
+
+    pub(crate) fn set_journal(&mut self, journal: Option<Rc<RefCell<Journal>>>) {
+        self.journal = journal;
+    }
+
+    /// Starts journaling a call to host function `name`, or replays it.
+    fn begin_journaled(&mut self, name: &str, args: &[LazyValue<'b>]) -> JournalCall<'b> {
+        let journal = match self.journal {
+            Some(ref journal) if journal.borrow().journals(name) => journal.clone(),
+            _ => return JournalCall::Unjournaled,
+        };
+        let call = journal.borrow_mut().begin(name, args);
+        match call {
+            Ok(Call::Live(index)) => JournalCall::Live(index),
+            Ok(Call::Replay(index, entry)) => {
+                JournalCall::Replayed(journal::replay(&journal, index, entry, args, self))
+            }
+            Err(e) => JournalCall::Replayed(Err(e)),
+        }
+    }
+
+    fn note_forced(&mut self, cell: usize) {
+        if let Some(ref journal) = self.journal {
+            journal.borrow_mut().note_forced(cell);
+        }
+    }
+
+    fn end_journaled(&mut self, index: usize, ret: &Result<RuntimeValue<'b>, RuntimeError>) {
+        let result = ret.as_ref().ok().map(|v| journal::saved_value(v, self));
+        if let Some(ref journal) = self.journal {
+            journal.borrow_mut().end(index, result);
+        }
+    }
+
+    /// Enables or disables recording the value of every expression
+    /// evaluated, for `inspect::Recording`. Enabling starts a new, empty
+    /// record.
+    pub(crate) fn set_recording(&mut self, enabled: bool) {
+        self.recording = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    pub(crate) fn take_recording(&mut self) -> Option<Vec<RecordedStep>> {
+        self.recording.take()
+    }
+
+    /// Number of slots currently allocated.
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Enables or disables recording which host function wrote each slot,
+    /// for `check_leaks`. Only slots written while enabled are attributed.
+    pub fn set_leak_check(&mut self, enabled: bool) {
+        self.slot_origins = if enabled { Some(HashMap::new()) } else { None };
+    }
+
+    /// Ends the current evaluation and reports the slots that are still
+    /// allocated.
+    ///
+    /// Cached definition values and pending releases are dropped first, so
+    /// once every value obtained from the context has been dropped, any slot
+    /// left over has leaked: some host function wrote it without putting it
+    /// into the release pool.
+    pub fn check_leaks(&mut self) -> Vec<LeakedSlot> {
+        self.values = Env::default();
+        self.definition_values.clear();
+        self.memo.clear();
+        let pool = self.release_pool.clone();
+        pool.release(self);
+
+        let origins = self.slot_origins.as_ref();
+        self.slots
+            .iter()
+            .map(|(id, &(generation, _))| LeakedSlot {
+                slot: SlotRef { id, generation },
+                host: origins.and_then(|o| o.get(&id).cloned().flatten()).cloned(),
+            })
+            .collect()
+    }
+
+    /// Creates a closure of the function `e`, capturing the variables its
+    /// body refers to from the current environment. Functions outside of
+    /// any function resolved so far are resolved on first use.
+    fn make_closure(&mut self, e: &Expr) -> Rc<[LazyValue<'b>]> {
+        if self.resolution.function(e).is_none() {
+            self.resolution.resolve(e);
+        }
+        let layout = self.resolution.function(e).unwrap();
+        layout
+            .sources
+            .iter()
+            .map(|s| self.values.get(*s).clone())
+            .collect()
+    }
+
+    /// Wraps the argument `x` for lazy evaluation in the current scope.
+    ///
+    /// An argument naming a local variable shares that variable's value,
+    /// and one without local variables needs no environment. Other
+    /// arguments share the current environment rather than trimming it like
+    /// closures do: it is already limited to the enclosing closure's
+    /// captures and parameters, and rebuilding it for every argument would
+    /// double evaluation time.
+    fn lazy_param(&mut self, x: &'b Expr, pooled: bool) -> LazyValue<'b> {
+        let env = match *x.body {
+            ExprBody::Name(_) => match self.resolution.slot(x) {
+                Some(slot) => return self.values.get(slot).clone(),
+                None => Env::default(),
+            },
+            ExprBody::Const(_) => Env::default(),
+            _ => self.values.clone(),
+        };
+        LazyValue {
+            expr: Some(x),
+            env,
+            outcome: if pooled {
+                self.value_pool.alloc()
+            } else {
+                Rc::new(RefCell::new(None))
+            },
+        }
+    }
+
+    /// Collects the local variables `e` refers to, for the debugger. Inside
+    /// lambdas in `e`, only the variables they capture are in this scope.
+    fn bindings(&self, e: &Expr, out: &mut Vec<(String, LazyValue<'b>)>) {
+        let mut bind = |name: &str, slot: Slot| {
+            let name = source_name(name);
+            if !out.iter().any(|(x, _)| x == name) {
+                out.push((name.to_string(), self.values.get(slot).clone()));
+            }
+        };
+        match *e.body {
+            ExprBody::Name(ref name) => {
+                if let Some(slot) = self.resolution.slot(e) {
+                    bind(name, slot);
+                }
+            }
+            ExprBody::Abstract {
+                body: AbstractBody::Expr(_),
+                ..
+            } => {
+                if let Some(layout) = self.resolution.function(e) {
+                    for (name, slot) in layout.captures.iter().zip(&layout.sources) {
+                        bind(name, *slot);
+                    }
+                }
+            }
+            ExprBody::Apply {
+                ref target,
+                ref params,
+            } => {
+                self.bindings(target, out);
+                for p in params {
+                    self.bindings(p, out);
+                }
+            }
+            ExprBody::Match {
+                ref value,
+                ref branches,
+            } => {
+                self.bindings(value, out);
+                for (_, branch) in branches {
+                    self.bindings(branch, out);
+                }
+            }
+            ExprBody::Const(_)
+            | ExprBody::Abstract {
+                body: AbstractBody::Host(_),
+                ..
+            }
+            | ExprBody::Never => {}
+        }
+    }
+
+    /// Reads a slot, failing with `RuntimeError::StaleSlot` if it has been
+    /// released since `r` was handed out.
+    pub fn read_slot(&mut self, r: SlotRef) -> Result<LazyValue<'b>, RuntimeError> {
+        match self.slots.get(r.id) {
+            Some((generation, v)) if *generation == r.generation => Ok(v.clone()),
+            _ => Err(RuntimeError::StaleSlot),
+        }
     }
 }
 
+/// Number of steps between checks of the cancel flag.
+pub const CANCEL_CHECK_INTERVAL: u64 = 256;
+
 pub fn eval_expr<'b, 'c>(
     e: &'b Expr,
     ctx: &mut EvalContext<'b, 'c>,
 ) -> Result<RuntimeValue<'b>, RuntimeError> {
+    if ctx.step_limit == Some(ctx.steps) && !next_slice(ctx) {
+        return Err(RuntimeError::StepLimit);
+    }
+    if ctx.depth_limit == Some(ctx.depth) {
+        return Err(RuntimeError::StackOverflow { depth: ctx.depth });
+    }
+    if ctx.steps.is_multiple_of(CANCEL_CHECK_INTERVAL) {
+        if let Some(ref flag) = ctx.cancel_flag {
+            if flag.load(AtomicOrdering::Relaxed) {
+                return Err(RuntimeError::Cancelled);
+            }
+        }
+    }
+    ctx.steps += 1;
+    if let Some(limit) = ctx.step_limit {
+        if ctx.steps == limit - limit / 10 && ctx.suspender.is_none() {
+            ctx.warn(Warning::FuelLow { limit });
+        }
+    }
+    if ctx.debugger.is_some() && !pause(e, ctx) {
+        return Err(RuntimeError::Cancelled);
+    }
+    ctx.depth += 1;
+    ctx.peak_depth = ctx.peak_depth.max(ctx.depth);
     let ret = _do_eval_expr(e, ctx);
+    ctx.depth -= 1;
+    if ctx.recording.is_some() {
+        record(e, &ret, ctx);
+    }
     let pool = ctx.release_pool.clone();
     pool.release(ctx);
-    ret
+    match ctx.slot_limit {
+        Some(limit) if ctx.slots.len() > limit => Err(RuntimeError::SlotLimit { limit }),
+        _ => ret,
+    }
+}
+
+/// Like `eval_expr`, but also reports the steps, host function calls and
+/// warnings of this evaluation alone, not counting earlier ones in `ctx`.
+pub fn evaluate<'b, 'c>(
+    e: &'b Expr,
+    ctx: &mut EvalContext<'b, 'c>,
+) -> Result<EvalOutcome<RuntimeValue<'b>>, RuntimeError> {
+    let steps = ctx.steps;
+    let mut host_calls = ctx.metrics().host_calls;
+    let warnings = ctx.warnings().len();
+    let value = eval_expr(e, ctx)?;
+    for (name, n) in ctx.metrics().host_calls {
+        let before = host_calls.get(&name).cloned().unwrap_or(0);
+        if n == before {
+            host_calls.remove(&name);
+        } else {
+            host_calls.insert(name, n - before);
+        }
+    }
+    Ok(EvalOutcome {
+        value,
+        steps: ctx.steps - steps,
+        host_calls,
+        warnings: ctx.warnings()[warnings..].to_vec(),
+    })
 }
 
 fn _do_eval_expr<'b, 'c>(
@@ -125,10 +1140,13 @@ fn _do_eval_expr<'b, 'c>(
             ref params,
             ref body,
         } => Ok(match *body {
-            AbstractBody::Expr(ref e) => RuntimeValue::Function {
-                params: params,
-                body: e,
-                context_values: ctx.values.clone(),
+            AbstractBody::Expr(ref body) => RuntimeValue::Function {
+                params,
+                body,
+                env: Env {
+                    params: Rc::new([]),
+                    captures: ctx.make_closure(e),
+                },
             },
             AbstractBody::Host(ref name) => RuntimeValue::Host(name),
         }),
@@ -140,84 +1158,356 @@ fn _do_eval_expr<'b, 'c>(
             let target = eval_expr(target, ctx)?;
 
             match target {
-                RuntimeValue::Function {
-                    params,
-                    body,
-                    mut context_values,
-                } => {
-                    apply_params.iter().enumerate().for_each(|(i, x)| {
-                        context_values = context_values.insert(
-                            &params[i],
-                            LazyValue {
-                                expr: x,
-                                context_values: ctx.values.clone(),
-                                outcome: Rc::new(RefCell::new(None)),
-                            },
-                        );
-                    });
-
-                    ::std::mem::swap(&mut context_values, &mut ctx.values);
+                RuntimeValue::Function { params, body, env } => {
+                    let mark = ctx.value_pool.mark();
+                    let mut args = env.params.to_vec();
+                    for x in apply_params {
+                        let lv = ctx.lazy_param(x, true);
+                        args.push(lv);
+                    }
+                    if ctx.eager {
+                        let start = args.len() - apply_params.len();
+                        if let Err(e) = args[start..]
+                            .iter()
+                            .try_for_each(|lv| lv.eval(ctx).map(drop))
+                        {
+                            drop(args);
+                            ctx.value_pool.release(mark);
+                            return Err(e);
+                        }
+                    }
+                    let mut env = Env {
+                        params: args.into(),
+                        captures: env.captures,
+                    };
+
+                    if apply_params.len() < params.len() {
+                        ctx.value_pool.forget(mark);
+                        return Ok(RuntimeValue::Function {
+                            params: &params[apply_params.len()..],
+                            body,
+                            env,
+                        });
+                    }
+
+                    ::std::mem::swap(&mut env, &mut ctx.values);
                     let ret = eval_expr(body, ctx);
-                    ::std::mem::swap(&mut context_values, &mut ctx.values);
+                    ::std::mem::swap(&mut env, &mut ctx.values);
 
+                    drop(env);
+                    ctx.value_pool.release(mark);
                     ret
                 }
                 RuntimeValue::Host(name) => {
-                    let hf = ctx
-                        .host_functions
-                        .get(name)
-                        .unwrap_or_else(|| panic!("bug: host function not found"));
-                    let values = ctx.values.clone();
-                    hf.eval(
-                        ctx,
-                        &mut apply_params.iter().map(|x| LazyValue {
-                            expr: x,
-                            context_values: values.clone(),
-                            outcome: Rc::new(RefCell::new(None)),
-                        }),
-                    )
+                    let args = apply_params
+                        .iter()
+                        .map(|x| ctx.lazy_param(x, false))
+                        .collect();
+                    call_host(name, args, ctx)
+                }
+                RuntimeValue::PartialHost { name, mut args } => {
+                    for x in apply_params {
+                        let lv = ctx.lazy_param(x, false);
+                        args.push(lv);
+                    }
+                    call_host(name, args, ctx)
                 }
                 _ => {
-                    if apply_params.len() == 0 {
+                    if apply_params.is_empty() {
                         Ok(target)
                     } else {
-                        panic!("bug: type mismatch");
+                        Err(RuntimeError::TypeMismatch(format!(
+                            "cannot apply {}",
+                            target
+                        )))
                     }
                 }
             }
         }
-        ExprBody::Const(ref ce) => Ok(match *ce {
-            ConstExpr::Bool(v) => RuntimeValue::Bool(v),
-            ConstExpr::Int(v) => RuntimeValue::Int(v),
-            ConstExpr::Float(v) => RuntimeValue::Float(v),
-            ConstExpr::Empty => RuntimeValue::Empty,
-        }),
+        ExprBody::Const(ref ce) => Ok(const_value(ce)),
         ExprBody::Match { .. } => unimplemented!(),
         ExprBody::Name(ref name) => {
-            let lv: LazyValue<'b> =
-                ctx.values.get(name).cloned().unwrap_or_else(|| {
-                    panic!("bug: name not found: {} {:?}", name, ctx.values.iter())
-                });
-            lv.eval(ctx)
+            let lv: LazyValue<'b> = match ctx.resolution.slot(e) {
+                Some(slot) => ctx.values.get(slot).clone(),
+                None => match ctx.definition_value(name) {
+                    Some(lv) => lv,
+                    None => return Err(RuntimeError::UnboundName(source_name(name).to_string())),
+                },
+            };
+            lv.eval(ctx).map_err(|e| match e {
+                RuntimeError::CircularEvaluation { binding: None } => {
+                    RuntimeError::CircularEvaluation {
+                        binding: Some(source_name(name).to_string()),
+                    }
+                }
+                e => e,
+            })
         }
         ExprBody::Never => unreachable!(),
     }
 }
 
+/// Suspends evaluation once the step limit is reached, if it runs on a
+/// stack of its own, until it is resumed with more steps. Returns whether
+/// it can go on. Kept out of `eval_expr` like `pause`.
+#[inline(never)]
+fn next_slice<'b, 'c>(ctx: &mut EvalContext<'b, 'c>) -> bool {
+    loop {
+        match ctx.suspend(Suspension::Steps) {
+            None => return false,
+            Some(Resume::Steps(0)) => {}
+            Some(Resume::Steps(n)) => {
+                ctx.step_limit = Some(ctx.steps.saturating_add(n));
+                return true;
+            }
+            #[cfg(feature = "async")]
+            Some(Resume::AsyncResult(_)) => panic!("bug: slice resumed with an async result"),
+        }
+    }
+}
+
+/// Hands `e` to the debugger if it is a call. Kept out of `eval_expr` so
+/// that its locals do not enlarge every level of recursion.
+#[inline(never)]
+fn pause<'b, 'c>(e: &'b Expr, ctx: &mut EvalContext<'b, 'c>) -> bool {
+    if let ExprBody::Apply { .. } = *e.body {
+    } else {
+        return true;
+    }
+    let debugger = ctx.debugger.take().unwrap();
+    let mut bindings = Vec::new();
+    ctx.bindings(e, &mut bindings);
+    let resume = debugger.on_apply(e, &bindings);
+    ctx.debugger = Some(debugger);
+    resume
+}
+
+/// A value an expression evaluated to, with the number of steps evaluated
+/// by then.
+#[derive(Debug)]
+pub(crate) struct RecordedStep {
+    pub body: *const ExprBody,
+    pub steps: u64,
+    pub value: AuditValue,
+}
+
+/// Records the value of `e`, if evaluating it succeeded. Kept out of
+/// `eval_expr` like `pause`.
+#[inline(never)]
+fn record<'b, 'c>(
+    e: &'b Expr,
+    ret: &Result<RuntimeValue<'b>, RuntimeError>,
+    ctx: &mut EvalContext<'b, 'c>,
+) {
+    if let Ok(ref v) = *ret {
+        let step = RecordedStep {
+            body: &*e.body,
+            steps: ctx.steps,
+            value: AuditValue::from_value(v),
+        };
+        ctx.recording.as_mut().unwrap().push(step);
+    }
+}
+
+/// Applies the function value `f` to `args`, for host functions that take
+/// functions as arguments. Lambdas get their arguments evaluated first in
+/// eager mode, as in a call written in the source.
+pub fn apply_value<'b, 'c>(
+    f: RuntimeValue<'b>,
+    mut args: Vec<LazyValue<'b>>,
+    ctx: &mut EvalContext<'b, 'c>,
+) -> Result<RuntimeValue<'b>, RuntimeError> {
+    match f {
+        RuntimeValue::Function { params, body, env } => {
+            if ctx.eager {
+                for lv in &args {
+                    lv.eval(ctx)?;
+                }
+            }
+            let rest = if args.len() > params.len() {
+                args.split_off(params.len())
+            } else {
+                Vec::new()
+            };
+            let n = args.len();
+            let mut env = Env {
+                params: env.params.iter().cloned().chain(args).collect(),
+                captures: env.captures,
+            };
+            if n < params.len() {
+                return Ok(RuntimeValue::Function {
+                    params: &params[n..],
+                    body,
+                    env,
+                });
+            }
+
+            ::std::mem::swap(&mut env, &mut ctx.values);
+            let ret = eval_expr(body, ctx);
+            ::std::mem::swap(&mut env, &mut ctx.values);
+            if rest.is_empty() {
+                ret
+            } else {
+                apply_value(ret?, rest, ctx)
+            }
+        }
+        RuntimeValue::Host(name) => call_host(name, args, ctx),
+        RuntimeValue::PartialHost {
+            name,
+            args: mut bound,
+        } => {
+            bound.extend(args);
+            call_host(name, bound, ctx)
+        }
+        _ => {
+            if args.is_empty() {
+                Ok(f)
+            } else {
+                Err(RuntimeError::TypeMismatch(format!("cannot apply {}", f)))
+            }
+        }
+    }
+}
+
+/// What `EvalContext::begin_journaled` made of a call.
+enum JournalCall<'b> {
+    Unjournaled,
+    /// The call is journaled at this index.
+    Live(usize),
+    /// What the replayed call returned.
+    Replayed(Result<RuntimeValue<'b>, RuntimeError>),
+}
+
+/// Calls host function `name` with `args`, those from earlier partial
+/// applications first. Returns a `PartialHost` if that is still fewer than
+/// its required parameters.
+fn call_host<'b, 'c>(
+    name: &'b String,
+    mut args: Vec<LazyValue<'b>>,
+    ctx: &mut EvalContext<'b, 'c>,
+) -> Result<RuntimeValue<'b>, RuntimeError> {
+    let hf = match ctx.host_functions.get(name) {
+        Some(hf) => hf.clone(),
+        None => return Err(RuntimeError::HostNotRegistered(name.clone())),
+    };
+
+    let sig = hf.signature();
+    if let Some(ref sig) = sig {
+        if args.len() < sig.min_params() {
+            return Ok(RuntimeValue::PartialHost { name, args });
+        }
+    }
+    let defaults = sig
+        .map(|sig| sig.defaults_from(args.len()))
+        .unwrap_or_default();
+    args.extend(
+        defaults
+            .iter()
+            .map(|c| LazyValue::from_value(const_value(c))),
+    );
+    let journaled = match ctx.journal {
+        Some(_) => match ctx.begin_journaled(name, &args) {
+            JournalCall::Live(index) => Some(index),
+            JournalCall::Replayed(ret) => return ret,
+            JournalCall::Unjournaled => None,
+        },
+        None => None,
+    };
+    let audited_args = ctx.audit.as_ref().map(|_| args.clone());
+    *ctx.host_calls.entry(name).or_insert(0) += 1;
+
+    let outer_host = ctx.current_host.replace(name);
+    ctx.host_depth += 1;
+    #[cfg(feature = "tracing")]
+    ctx.enter_host_span(name);
+    let ret = hf.eval(ctx, &mut args.into_iter());
+    #[cfg(feature = "tracing")]
+    ctx.exit_host_span();
+    ctx.host_depth -= 1;
+    ctx.current_host = outer_host;
+
+    if let Some(index) = journaled {
+        ctx.end_journaled(index, &ret);
+    }
+
+    if let (Some(args), Ok(ref v)) = (audited_args, &ret) {
+        ctx.audit_call(name, &args, v);
+    }
+    match ret {
+        Ok(RuntimeValue::Float(v)) if !v.is_finite() => {
+            if ctx.strict_floats {
+                return Err(RuntimeError::NonFiniteFloat { host: name.clone() });
+            }
+            ctx.warn(Warning::NonFiniteFloat { host: name.clone() });
+            Ok(RuntimeValue::Float(v))
+        }
+        ret => ret,
+    }
+}
+
+fn const_value<'b>(ce: &ConstExpr) -> RuntimeValue<'b> {
+    match *ce {
+        ConstExpr::Bool(v) => RuntimeValue::Bool(v),
+        ConstExpr::Int(v) => RuntimeValue::Int(v),
+        ConstExpr::Float(v) => RuntimeValue::Float(v),
+        ConstExpr::Empty => RuntimeValue::Empty,
+        ConstExpr::Str(ref s) => crate::corelib::str_value(s),
+        ConstExpr::Bytes(ref b) => crate::bytes::bytes_value(b),
+    }
+}
+
 impl<'b> LazyValue<'b> {
+    pub fn new(expr: &'b Expr, env: Env<'b>) -> LazyValue<'b> {
+        LazyValue {
+            expr: Some(expr),
+            env,
+            outcome: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Identifies the cell holding the value, which clones share.
+    pub(crate) fn cell_id(&self) -> usize {
+        Rc::as_ptr(&self.outcome) as *const () as usize
+    }
+
+    /// Returns the value if it has been evaluated already.
+    pub fn outcome(&self) -> Option<RuntimeValue<'b>> {
+        self.outcome.try_borrow().ok()?.clone()
+    }
+
+    /// Creates an already-evaluated lazy value.
+    pub fn from_value(v: RuntimeValue<'b>) -> LazyValue<'b> {
+        LazyValue {
+            expr: None,
+            env: Env::default(),
+            outcome: Rc::new(RefCell::new(Some(v))),
+        }
+    }
+
     pub fn eval<'c>(
         &self,
         ctx: &mut EvalContext<'b, 'c>,
     ) -> Result<RuntimeValue<'b>, RuntimeError> {
-        let mut outcome = self.outcome.borrow_mut(); // a lazy value should never be evaluated recursively
+        let mut outcome = match self.outcome.try_borrow_mut() {
+            Ok(v) => v,
+            // Still being evaluated further up: the value depends on itself.
+            // The `Name` it was reached through fills in the binding.
+            Err(_) => return Err(RuntimeError::CircularEvaluation { binding: None }),
+        };
         if let Some(ref oc) = *outcome {
             return Ok(oc.clone());
         }
 
-        let mut new_values = self.context_values.clone();
+        if ctx.journal.is_some() {
+            ctx.note_forced(self.cell_id());
+        }
+        let mut new_values = self.env.clone();
 
         ::std::mem::swap(&mut new_values, &mut ctx.values);
-        let ret = eval_expr(self.expr, ctx);
+        let ret = eval_expr(
+            self.expr.expect("bug: lazy value without expr or outcome"),
+            ctx,
+        );
         ::std::mem::swap(&mut new_values, &mut ctx.values);
 
         let ret = ret?;
@@ -0,0 +1,262 @@
+use crate::ast::DataType;
+use crate::corelib::HostManager;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::*;
+use crate::host::HostFunction;
+use crate::parser::{parse_expr, parse_expr_with_globals};
+use crate::warning::Warning;
+use std::collections::BTreeSet;
+
+fn eval_int<'b, 'c>(src: &'b crate::ast::Expr, ectx: &mut EvalContext<'b, 'c>) -> i64 {
+    match eval_expr(src, ectx).unwrap() {
+        RuntimeValue::Int(v) => v,
+        v => panic!("unexpected value: {:?}", v),
+    }
+}
+
+#[test]
+fn test_reset_keeps_hosts() {
+    let hm = HostManager::new();
+    let first = parse_expr("($list_head ($list_push 1 ($list_push 2 ~)))").unwrap();
+    let second = parse_expr("($add 40 2)").unwrap();
+    let third = parse_expr("($list_push 3 ~)").unwrap();
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    let list = eval_expr(&third, &mut ectx).unwrap();
+    assert_eq!(eval_int(&first, &mut ectx), 1);
+
+    ectx.reset();
+    assert_eq!(eval_int(&second, &mut ectx), 42);
+    assert_eq!(eval_int(&first, &mut ectx), 1);
+
+    // Dropping a list from before the reset must not release slots that
+    // now belong to someone else.
+    drop(list);
+    let pool = ectx.release_pool.clone();
+    pool.release(&mut ectx);
+    assert_eq!(eval_int(&first, &mut ectx), 1);
+}
+
+#[test]
+fn test_stale_slot() {
+    let mut ectx = EvalContext::default();
+    let r = ectx.write_slot(LazyValue::from_value(RuntimeValue::Int(1)));
+    assert!(ectx.read_slot(r).is_ok());
+
+    ectx.release_pool.put(r);
+    let pool = ectx.release_pool.clone();
+    pool.release(&mut ectx);
+    // The new value takes over the released slot.
+    let r2 = ectx.write_slot(LazyValue::from_value(RuntimeValue::Int(2)));
+    match ectx.read_slot(r) {
+        Err(RuntimeError::StaleSlot) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert!(ectx.read_slot(r2).is_ok());
+
+    ectx.reset();
+    ectx.write_slot(LazyValue::from_value(RuntimeValue::Int(3)));
+    match ectx.read_slot(r2) {
+        Err(RuntimeError::StaleSlot) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_captured_args_survive_calls() {
+    // The argument cells of the inner calls are recycled; the ones captured
+    // by the returned closures must not be.
+    let hm = HostManager::new();
+    let e = parse_expr(
+        r"((\mk ($add ((mk 1) 10) ($add ((\z ($mul z z)) 3) ((mk 100) 1000)))) (\x (\y ($add x y))))",
+    )
+    .unwrap();
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    assert_eq!(eval_int(&e, &mut ectx), 1120);
+}
+
+#[test]
+fn test_scoped_slot() {
+    let mut ectx = EvalContext::default();
+    let slot = ectx.write_scoped_slot(LazyValue::from_value(RuntimeValue::Int(1)));
+    let weak = slot.downgrade();
+    assert_eq!(ectx.slot_count(), 1);
+    assert!(slot.read(&mut ectx).is_ok());
+
+    drop(slot);
+    let pool = ectx.release_pool.clone();
+    pool.release(&mut ectx);
+    assert_eq!(ectx.slot_count(), 0);
+    match ectx.read_slot(weak) {
+        Err(RuntimeError::StaleSlot) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+/// `($leak x)`: returns `x`, leaving a copy in a slot nobody releases.
+#[derive(Debug)]
+struct LeakOp;
+
+impl HostFunction for LeakOp {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        Ok(params[0].clone())
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let v = params.next().unwrap();
+        ectx.write_slot(v.clone());
+        v.eval(ectx)
+    }
+}
+
+#[test]
+fn test_leak_check() {
+    let mut engine = Engine::new();
+    engine.add_host("leak".into(), Box::new(LeakOp));
+    engine.set_leak_check(true);
+
+    // Lists release their slots once dropped.
+    assert_eq!(
+        engine
+            .eval_owned("($list_push 1 ($list_push 2 ~))")
+            .unwrap()
+            .to_string(),
+        "[1, 2]"
+    );
+    match engine.eval_str("($add ($leak 1) ($leak 2))") {
+        Err(Error::Runtime(RuntimeError::LeakedSlots(ref leaked))) => {
+            assert_eq!(leaked.len(), 2);
+            assert!(leaked.iter().all(|x| x.host.as_deref() == Some("leak")));
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+
+    engine.set_leak_check(false);
+    assert_eq!(engine.eval_str("($leak 1)").unwrap(), "1");
+}
+
+#[test]
+fn test_closure_captures() {
+    let hm = HostManager::new();
+    let e = parse_expr(r"((\unused n (\x ($add x n))) ($list_push 1 ~) 2)").unwrap();
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    match eval_expr(&e, &mut ectx).unwrap() {
+        RuntimeValue::Function { env, .. } => {
+            assert_eq!(env.captures().len(), 1);
+            assert!(env.params().is_empty());
+            match env.captures()[0].eval(&mut ectx).unwrap() {
+                RuntimeValue::Int(2) => {}
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+        v => panic!("unexpected value: {:?}", v),
+    }
+
+    let e = parse_expr(r"((\unused n ((\x ($add x n)) n)) ($list_push 1 ~) 2)").unwrap();
+    assert_eq!(eval_int(&e, &mut ectx), 4);
+}
+
+#[test]
+fn test_circular_evaluation() {
+    let hm = HostManager::new();
+    let mut defs = crate::definitions::Definitions::default();
+    let globals: BTreeSet<String> = std::iter::once("x".to_string()).collect();
+    let x = parse_expr_with_globals("($add x 1)", globals.clone()).unwrap();
+    defs.define("x".into(), x);
+    let e = parse_expr_with_globals("($mul x 2)", globals).unwrap();
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    ectx.set_definitions(&defs);
+    match eval_expr(&e, &mut ectx) {
+        Err(RuntimeError::CircularEvaluation { binding }) => {
+            assert_eq!(binding.as_deref(), Some("x"))
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_unchecked_errors() {
+    let hm = HostManager::new();
+    let globals: BTreeSet<String> = std::iter::once("y".to_string()).collect();
+    let unbound = parse_expr_with_globals("($add y 1)", globals).unwrap();
+    let unregistered = parse_expr("($nope 1)").unwrap();
+    let not_function = parse_expr("(($add 1 1) 2)").unwrap();
+    let bad_operand = parse_expr("($add true 1)").unwrap();
+    let bad_list = parse_expr("($list_head 1)").unwrap();
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    match eval_expr(&unbound, &mut ectx) {
+        Err(RuntimeError::UnboundName(name)) => assert_eq!(name, "y"),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    match eval_expr(&unregistered, &mut ectx) {
+        Err(RuntimeError::HostNotRegistered(name)) => assert_eq!(name, "nope"),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    for e in [&not_function, &bad_operand, &bad_list] {
+        match eval_expr(e, &mut ectx) {
+            Err(RuntimeError::TypeMismatch(_)) => {}
+            x => panic!("unexpected result: {:?}", x),
+        }
+    }
+}
+
+#[test]
+fn test_cancel_flag() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let hm = HostManager::new();
+    let e =
+        parse_expr(r"((\f (f f 0)) (\self n ($if ($lt n 200) (self self ($add n 1)) n)))").unwrap();
+    let flag = Arc::new(AtomicBool::new(false));
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    ectx.set_cancel_flag(Some(flag.clone()));
+    assert_eq!(eval_int(&e, &mut ectx), 200);
+
+    flag.store(true, Ordering::Relaxed);
+    match eval_expr(&e, &mut ectx) {
+        Err(RuntimeError::Cancelled) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    ectx.set_cancel_flag(None);
+    assert_eq!(eval_int(&e, &mut ectx), 200);
+}
+
+#[test]
+fn test_evaluate_outcome() {
+    let hm = HostManager::new();
+    let first = parse_expr("($div 1.0 0.0)").unwrap();
+    let second = parse_expr("($mul ($add 1 2) ($add 3 4))").unwrap();
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    let outcome = evaluate(&first, &mut ectx).unwrap();
+    assert_eq!(outcome.value.to_string(), "inf");
+    assert_eq!(
+        outcome.warnings,
+        vec![Warning::NonFiniteFloat { host: "div".into() }]
+    );
+
+    // Only the second evaluation is counted.
+    let outcome = evaluate(&second, &mut ectx).unwrap();
+    assert_eq!(outcome.value.to_string(), "21");
+    assert_eq!(outcome.steps, 10);
+    assert_eq!(outcome.host_calls.get("add"), Some(&2));
+    assert_eq!(outcome.host_calls.get("div"), None);
+    assert!(outcome.warnings.is_empty());
+    assert_eq!(ectx.warnings().len(), 1);
+}
@@ -0,0 +1,45 @@
+//! Example programs shipped with the library.
+//!
+//! The sources live in the crate's `examples/` directory and are embedded
+//! at compile time. They double as a regression corpus: every example must
+//! typecheck and evaluate with the core library.
+
+#[derive(Debug, Clone, Copy)]
+pub struct Example {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub source: &'static str,
+}
+
+static EXAMPLES: &[Example] = &[
+    Example {
+        name: "closures",
+        description: "Function composition over closures capturing their arguments",
+        source: include_str!("../examples/closures.x"),
+    },
+    Example {
+        name: "list_map",
+        description: "Mapping a lambda over a generated list",
+        source: include_str!("../examples/list_map.x"),
+    },
+    Example {
+        name: "list_sum",
+        description: "Generating a list with recursion and folding it into a sum",
+        source: include_str!("../examples/list_sum.x"),
+    },
+    Example {
+        name: "rules",
+        description: "A tiered pricing rule with nested conditions",
+        source: include_str!("../examples/rules.x"),
+    },
+];
+
+/// Returns all embedded example programs.
+pub fn examples() -> &'static [Example] {
+    EXAMPLES
+}
+
+/// Looks up an example by name.
+pub fn example(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|x| x.name == name)
+}
@@ -0,0 +1,243 @@
+//! Resumable evaluation, for running many scripts cooperatively on one
+//! thread.
+//!
+//! An `Execution` evaluates its expression on a stack of its own, see
+//! `suspend`. `Execution::step` runs it until it has evaluated the steps
+//! asked for and then suspends it where it is, so stepping an execution to
+//! completion in slices costs the same steps as evaluating it at once, and
+//! every host function is called as often as it would be then. Scripts
+//! nest as deep as on a thread spawned by `std::thread`.
+//!
+//! The step limit set with `Engine::set_step_limit` is the budget of an
+//! execution as a whole, however it is sliced.
+//!
+//! A `Snapshot` of an execution persists it, e.g. to resume it after a
+//! process restart. The environment and pending thunks of a suspended
+//! execution live on its stack, so a snapshot records how to rebuild them
+//! instead: the expression, the steps spent on it and the journal of its
+//! calls to host functions with side effects, see `journal`.
+//! `Engine::resume` replays the journal up to the steps spent, so host
+//! functions whose calls returned before the snapshot are not called
+//! again, and the resumed execution carries on with the budget left.
+//! Executions that got a value without a journaled form, such as a
+//! function, from such a call cannot be snapshotted.
+
+use crate::ast::Expr;
+use crate::engine::{Engine, RunOptions};
+use crate::error::*;
+use crate::eval::OwnedValue;
+use crate::journal::{Journal, JournalEntry};
+use crate::metrics::Metrics;
+use crate::remote::WireValue;
+use crate::suspend::{Resume, Suspendable, Suspension};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"XLS\0";
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 3;
+
+/// Outcome of `Execution::step`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionStatus {
+    /// The step budget ran out before evaluation finished.
+    Suspended,
+    Finished(OwnedValue),
+}
+
+enum State<'a> {
+    /// Suspended, or not started yet.
+    Running(Suspendable<'a, (Result<OwnedValue, Error>, u64)>),
+    Finished(OwnedValue),
+    Failed,
+}
+
+/// An evaluation started with `Engine::start`.
+pub struct Execution<'a> {
+    expr: Expr,
+    state: State<'a>,
+    /// Steps evaluated so far.
+    steps: u64,
+    /// Most steps allowed in total.
+    budget: Option<u64>,
+    journal: Rc<RefCell<Journal>>,
+}
+
+impl Engine {
+    /// Typechecks `e` and returns an execution of it that has not run any
+    /// steps yet.
+    pub fn start(&self, e: &Expr) -> Result<Execution<'_>, Error> {
+        self.start_with(e, vec![])
+    }
+
+    /// Rebuilds the execution `snapshot` was taken of by replaying it up to
+    /// the steps spent. The expression is checked again against this
+    /// engine, and fails to replay if the host functions it calls behave
+    /// differently.
+    pub fn resume(&self, snapshot: Snapshot) -> Result<Execution<'_>, Error> {
+        let mut run = self.start_with(&snapshot.expr, snapshot.journal)?;
+        run.step(snapshot.steps)?;
+        Ok(run)
+    }
+
+    fn start_with(&self, e: &Expr, journal: Vec<JournalEntry>) -> Result<Execution<'_>, Error> {
+        let ty = self.check(e)?;
+        let expr = e.clone();
+        let journal = Journal::new(self.pure_hosts(), journal);
+        let replayed = journal.clone();
+        let run = Suspendable::new(self.host_state(), move |suspender, first| {
+            let n = first.steps();
+            let mut metrics = Metrics::default();
+            let opts = RunOptions {
+                step_limit: Some(n),
+                metrics: Some(&mut metrics),
+                suspender: Some(suspender),
+                journal: Some(replayed),
+                ..RunOptions::default()
+            };
+            let out = self.run_in(&expr, &ty, self.definitions(), opts, |v, ectx| {
+                v.into_owned(ectx)
+            });
+            (out, metrics.steps)
+        })?;
+        Ok(Execution {
+            expr: e.clone(),
+            state: State::Running(run),
+            steps: 0,
+            budget: self.step_limit(),
+            journal,
+        })
+    }
+}
+
+impl<'a> Execution<'a> {
+    /// Runs up to `n` more steps, failing with `RuntimeError::StepLimit`
+    /// if that exceeds the budget. Once the execution has finished, returns
+    /// its value again without running anything. Once it has failed, fails
+    /// with `RuntimeError::Custom`.
+    pub fn step(&mut self, n: u64) -> Result<ExecutionStatus, Error> {
+        let allowed = match self.budget {
+            Some(budget) => n.min(budget.saturating_sub(self.steps)),
+            None => n,
+        };
+        let out = match self.state {
+            State::Running(ref mut run) => {
+                let out = run.resume(Resume::Steps(allowed));
+                // Executions cannot wait for async calls, which fail as in
+                // synchronous evaluations.
+                #[cfg(feature = "async")]
+                let out = {
+                    let mut out = out;
+                    while let Err(Suspension::AsyncCall { .. }) = out {
+                        out = run.resume(Resume::AsyncResult(Err(RuntimeError::AsyncHostCall)));
+                    }
+                    out
+                };
+                out
+            }
+            State::Finished(ref v) => return Ok(ExecutionStatus::Finished(v.clone())),
+            State::Failed => {
+                return Err(RuntimeError::Custom("execution has already failed".into()).into())
+            }
+        };
+        match out {
+            Err(Suspension::Steps) => {
+                self.steps = self.steps.saturating_add(allowed);
+                if allowed < n {
+                    self.state = State::Failed;
+                    return Err(RuntimeError::StepLimit.into());
+                }
+                Ok(ExecutionStatus::Suspended)
+            }
+            #[cfg(feature = "async")]
+            Err(Suspension::AsyncCall { .. }) => unreachable!(),
+            Ok((out, steps)) => {
+                self.steps = steps;
+                match out {
+                    Ok(v) => {
+                        self.state = State::Finished(v.clone());
+                        Ok(ExecutionStatus::Finished(v))
+                    }
+                    Err(e) => {
+                        self.state = State::Failed;
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Steps evaluated so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Finished(_))
+    }
+
+    /// Records what `Engine::resume` needs to rebuild the execution,
+    /// possibly in another process. Fails if the execution has failed, or
+    /// got a value without a journaled form from a host function with side
+    /// effects.
+    pub fn snapshot(&self) -> Result<Snapshot, Error> {
+        if let State::Failed = self.state {
+            return Err(RuntimeError::Custom("execution has already failed".into()).into());
+        }
+        let journal = self.journal.borrow().entries().to_vec();
+        for entry in &journal {
+            if let Some(WireValue::Opaque(ref v)) = entry.result {
+                return Err(RuntimeError::Custom(format!(
+                    "cannot snapshot {} returned by ${}",
+                    v, entry.host
+                ))
+                .into());
+            }
+        }
+        Ok(Snapshot {
+            magic: SNAPSHOT_MAGIC,
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            expr: self.expr.clone(),
+            steps: self.steps,
+            journal,
+        })
+    }
+}
+
+/// What `Engine::resume` rebuilds an `Execution` from, encoded with
+/// bincode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub magic: [u8; 4],
+    pub format_version: u32,
+    pub expr: Expr,
+    /// Steps spent on the execution so far.
+    pub steps: u64,
+    /// Calls made to host functions with side effects, in the order they
+    /// were made.
+    pub journal: Vec<JournalEntry>,
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("bug: snapshot serialization failed")
+    }
+
+    /// Decodes a snapshot, checking its magic and format version. They are
+    /// decoded first, as a fixed header, so that snapshots of other
+    /// versions, whose layout may differ, are reported as such.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, ParseError> {
+        let invalid = |e: bincode::Error| ParseError::Custom(format!("invalid snapshot: {}", e));
+        let (magic, format_version): ([u8; 4], u32) =
+            bincode::deserialize(bytes).map_err(invalid)?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err(ParseError::Custom("invalid snapshot: bad magic".into()));
+        }
+        if format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(ParseError::Custom(format!(
+                "unsupported snapshot format version {} (expecting {})",
+                format_version, SNAPSHOT_FORMAT_VERSION
+            )));
+        }
+        bincode::deserialize(bytes).map_err(invalid)
+    }
+}
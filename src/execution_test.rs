@@ -0,0 +1,248 @@
+use crate::ast::DataType;
+use crate::builtin::ValueType;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::{EvalContext, LazyValue, OwnedValue, RuntimeValue};
+use crate::execution::{ExecutionStatus, Snapshot};
+use crate::host::HostFunction;
+use std::cell::Cell;
+use std::rc::Rc;
+
+const SUM_TO: &str = r"
+(
+    (\fix n (fix fix n))
+    (\self n ($if ($eq n 0) 0 ($add n (self self ($sub n 1)))))
+    N
+)
+";
+
+#[test]
+fn test_round_robin() {
+    let engine = Engine::new();
+    let short = engine.parse(&SUM_TO.replace('N', "10")).unwrap();
+    let long = engine.parse(&SUM_TO.replace('N', "50")).unwrap();
+    let mut runs = vec![engine.start(&long).unwrap(), engine.start(&short).unwrap()];
+
+    let mut finished = Vec::new();
+    while !runs.is_empty() {
+        let mut i = 0;
+        while i < runs.len() {
+            match runs[i].step(50).unwrap() {
+                ExecutionStatus::Suspended => i += 1,
+                ExecutionStatus::Finished(v) => {
+                    finished.push(v);
+                    runs.remove(i);
+                }
+            }
+        }
+    }
+    assert_eq!(finished, vec![OwnedValue::Int(55), OwnedValue::Int(1275)]);
+}
+
+#[test]
+fn test_step_results() {
+    let engine = Engine::new();
+    let e = engine.parse("($add 1 ($mul 2 3))").unwrap();
+    let mut run = engine.start(&e).unwrap();
+    assert_eq!(run.step(1).unwrap(), ExecutionStatus::Suspended);
+    assert_eq!(run.steps(), 1);
+    assert!(!run.is_finished());
+    assert_eq!(
+        run.step(100).unwrap(),
+        ExecutionStatus::Finished(OwnedValue::Int(7))
+    );
+    assert_eq!(
+        run.step(0).unwrap(),
+        ExecutionStatus::Finished(OwnedValue::Int(7))
+    );
+
+    let e = engine.parse("($div 1 0)").unwrap();
+    let mut run = engine.start(&e).unwrap();
+    match run.step(100) {
+        Err(Error::Runtime(RuntimeError::DivByZero)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+/// `$tick`, which counts its calls and returns the count.
+#[derive(Debug)]
+struct TickOp(Rc<Cell<u64>>);
+
+impl HostFunction for TickOp {
+    fn typeck(&self, _params: &[DataType]) -> Result<DataType, TypeError> {
+        Ok(DataType::Value(ValueType::Int))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        _ectx: &mut EvalContext<'b, 'c>,
+        _params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        self.0.set(self.0.get() + 1);
+        Ok(RuntimeValue::Int(self.0.get() as i64))
+    }
+}
+
+#[test]
+fn test_steps_run_once() {
+    let ticks = Rc::new(Cell::new(0));
+    let mut engine = Engine::new();
+    engine.add_host("tick".into(), Box::new(TickOp(ticks.clone())));
+    let src = SUM_TO
+        .replace('N', "50")
+        .replace("($add n", "($add ($tick n)");
+    let e = engine.parse(&src).unwrap();
+    let total = engine
+        .evaluate_with(&e, |v, ectx| v.into_owned(ectx))
+        .unwrap()
+        .steps;
+    assert_eq!(ticks.get(), 50);
+
+    // Every slice runs exactly the steps asked for, picking up where the
+    // last one stopped.
+    ticks.set(0);
+    let mut run = engine.start(&e).unwrap();
+    let mut slices = 0;
+    while run.step(3).unwrap() == ExecutionStatus::Suspended {
+        slices += 1;
+        assert_eq!(run.steps(), slices * 3);
+    }
+    assert_eq!(run.steps(), total);
+    assert_eq!(slices, (total - 1) / 3);
+    assert_eq!(ticks.get(), 50);
+    assert_eq!(
+        run.step(1).unwrap(),
+        ExecutionStatus::Finished(OwnedValue::Int(1275))
+    );
+}
+
+#[test]
+fn test_deep_execution() {
+    // Deeper than the depth limit allows fails as it would at once,
+    // rather than overflowing the execution's stack.
+    let engine = Engine::new();
+    let e = engine.parse(&SUM_TO.replace('N', "100000")).unwrap();
+    let mut run = engine.start(&e).unwrap();
+    match run.step(u64::MAX) {
+        Err(Error::Runtime(RuntimeError::StackOverflow { .. })) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert!(run.step(1).is_err());
+}
+
+/// `$pick a b`, which evaluates `b` before `a` and returns `a - b`.
+#[derive(Debug)]
+struct PickOp;
+
+impl HostFunction for PickOp {
+    fn typeck(&self, _params: &[DataType]) -> Result<DataType, TypeError> {
+        Ok(DataType::Value(ValueType::Int))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let a = params.next().unwrap();
+        let b = params.next().unwrap().eval(ectx)?;
+        match (a.eval(ectx)?, b) {
+            (RuntimeValue::Int(a), RuntimeValue::Int(b)) => Ok(RuntimeValue::Int(a - b)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_snapshot() {
+    let ticks = Rc::new(Cell::new(0));
+    let mut engine = Engine::new();
+    engine.add_host("tick".into(), Box::new(TickOp(ticks.clone())));
+    engine.add_host("pick".into(), Box::new(PickOp));
+    let sum = SUM_TO
+        .replace('N', "5")
+        .replace("($add n", "($add ($tick n)");
+    // `t` is first evaluated by `$pick`, which evaluates its arguments out
+    // of order.
+    let src = format!(
+        r"((\t ($add ($pick ($tick 0) ($add t ($tick 0))) ($add t {}))) ($tick 0))",
+        sum
+    );
+    let e = engine.parse(&src).unwrap();
+    let out = engine
+        .evaluate_with(&e, |v, ectx| v.into_owned(ectx))
+        .unwrap();
+    let (value, total) = (out.value, out.steps);
+    let calls = ticks.get();
+    assert_eq!(calls, 8);
+
+    // Wherever the snapshot is taken, the resumed execution picks up where
+    // it was, making only the calls not made before.
+    for n in 1..total {
+        ticks.set(0);
+        let mut run = engine.start(&e).unwrap();
+        assert_eq!(run.step(n).unwrap(), ExecutionStatus::Suspended);
+        let bytes = run.snapshot().unwrap().to_bytes();
+        drop(run);
+
+        let made = ticks.get();
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(snapshot.steps, n);
+        let mut run = engine.resume(snapshot).unwrap();
+        assert_eq!(run.steps(), n);
+        assert_eq!(ticks.get(), made, "snapshot at step {}", n);
+        assert_eq!(
+            run.step(u64::MAX).unwrap(),
+            ExecutionStatus::Finished(value.clone())
+        );
+        assert_eq!(run.steps(), total);
+        assert_eq!(ticks.get(), calls, "snapshot at step {}", n);
+    }
+
+    // Resuming does not reset the budget.
+    engine.set_step_limit(Some(total - 1));
+    let mut run = engine.start(&e).unwrap();
+    run.step(10).unwrap();
+    let snapshot = run.snapshot().unwrap();
+    let mut run = engine.resume(snapshot.clone()).unwrap();
+    match run.step(u64::MAX) {
+        Err(Error::Runtime(RuntimeError::StepLimit)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert_eq!(run.steps(), total - 1);
+    assert!(run.snapshot().is_err());
+
+    // A journal the expression does not replay is rejected.
+    let mut other = snapshot;
+    other.expr = engine.parse(&src.replace("$pick", "$sub")).unwrap();
+    match engine.resume(other) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert!(msg.contains("does not replay"), "{}", msg)
+        }
+        x => panic!("unexpected result: {:?}", x.map(|_| ())),
+    };
+}
+
+#[test]
+fn test_snapshot_bytes() {
+    let engine = Engine::new();
+    let e = engine.parse(&SUM_TO.replace('N', "20")).unwrap();
+    let mut run = engine.start(&e).unwrap();
+    run.step(30).unwrap();
+    let bytes = run.snapshot().unwrap().to_bytes();
+
+    assert!(Snapshot::from_bytes(b"garbage").is_err());
+    let mut bad = Snapshot::from_bytes(&bytes).unwrap();
+    bad.format_version += 1;
+    match Snapshot::from_bytes(&bad.to_bytes()) {
+        Err(ParseError::Custom(msg)) => assert!(msg.contains("unsupported"), "{}", msg),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    // Older layouts are reported by version, not as a decoding error.
+    let mut old = bytes[..8].to_vec();
+    old[4..8].copy_from_slice(&2u32.to_le_bytes());
+    match Snapshot::from_bytes(&old) {
+        Err(ParseError::Custom(msg)) => assert!(msg.contains("version 2"), "{}", msg),
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
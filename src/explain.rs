@@ -0,0 +1,139 @@
+//! Explaining the decisions of rules that evaluate to a bool, for showing
+//! end users why a rule accepted or rejected something.
+//!
+//! `Engine::explain` runs a program with auditing enabled and rebuilds,
+//! from the host function calls it made, the tree of comparisons it
+//! evaluated and the `$if` branches it took. Other calls are left out,
+//! with the conditions evaluated within them lifted into their place, so
+//! `($and ($lt x 3) ($add 1 ($if ($gt y 0) 1 2)))` explains as an `and`
+//! comparison of an `lt` comparison and a branch on a `gt` comparison.
+//! Conditions never evaluated, like those of the branch not taken, are not
+//! part of the explanation.
+
+use crate::ast::DataType;
+use crate::audit::{AuditLog, AuditValue};
+use crate::builtin::ValueType;
+use crate::engine::{Engine, RunOptions};
+use crate::error::*;
+use crate::eval::RuntimeValue;
+
+/// Host functions explained as comparisons.
+pub const COMPARISONS: &[&str] = &["eq", "ne", "lt", "le", "gt", "ge", "and", "or"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BranchTaken {
+    Then,
+    Else,
+}
+
+/// A condition that contributed to a decision, with the conditions that
+/// contributed to it in the order they were evaluated.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Condition {
+    /// A call of one of `COMPARISONS`, named by `op` without the `$`.
+    Comparison {
+        op: String,
+        lhs: AuditValue,
+        rhs: AuditValue,
+        result: bool,
+        children: Vec<Condition>,
+    },
+    /// A call of `$if`. `children` holds the conditions within the
+    /// predicate followed by those within the branch taken.
+    Branch {
+        predicate: bool,
+        taken: BranchTaken,
+        children: Vec<Condition>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub result: bool,
+    /// The outermost conditions evaluated, in order.
+    pub conditions: Vec<Condition>,
+}
+
+impl Explanation {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("bug: explanation serialization failed")
+    }
+
+    /// Rebuilds the conditions evaluated from the calls in `log`.
+    fn from_log(result: bool, log: &AuditLog) -> Explanation {
+        // Entries are appended when calls return, so the calls a call made
+        // are on top of the stack, deeper than it, when it is reached.
+        let mut stack: Vec<(u32, Condition)> = Vec::new();
+        for entry in log.entries() {
+            let split = stack
+                .iter()
+                .rposition(|(depth, _)| *depth <= entry.depth)
+                .map_or(0, |i| i + 1);
+            let children: Vec<Condition> = stack.drain(split..).map(|(_, c)| c).collect();
+            let condition = match (entry.host.as_str(), &entry.args[..], &entry.result) {
+                ("if", [AuditValue::Bool(predicate), ..], _) => Condition::Branch {
+                    predicate: *predicate,
+                    taken: if *predicate {
+                        BranchTaken::Then
+                    } else {
+                        BranchTaken::Else
+                    },
+                    children,
+                },
+                (op, [lhs, rhs], AuditValue::Bool(result)) if COMPARISONS.contains(&op) => {
+                    Condition::Comparison {
+                        op: op.to_string(),
+                        lhs: lhs.clone(),
+                        rhs: rhs.clone(),
+                        result: *result,
+                        children,
+                    }
+                }
+                _ => {
+                    stack.extend(children.into_iter().map(|c| (entry.depth, c)));
+                    continue;
+                }
+            };
+            stack.push((entry.depth, condition));
+        }
+        Explanation {
+            result,
+            conditions: stack.into_iter().map(|(_, c)| c).collect(),
+        }
+    }
+}
+
+impl Engine {
+    /// Evaluates `source`, which must evaluate to a bool, and explains the
+    /// result. The program is run as written, without the optimizations
+    /// `Engine::set_opt_level` enables, so that conditions folded into
+    /// constants are still explained.
+    pub fn explain(&self, source: &str) -> Result<Explanation, Error> {
+        let e = self.parse(source)?;
+        let ty = self.check(&e)?;
+        match ty {
+            DataType::Value(ValueType::Bool) | DataType::Dynamic | DataType::Divergent => {}
+            _ => {
+                return Err(TypeError::Custom(
+                    "only programs evaluating to a bool can be explained".into(),
+                )
+                .into())
+            }
+        }
+        let mut log = AuditLog::default();
+        let opts = RunOptions {
+            audit: Some(&mut log),
+            ..RunOptions::default()
+        };
+        let result = self.run_in(&e, &ty, self.definitions(), opts, |v, _| match v {
+            RuntimeValue::Bool(x) => Ok(x),
+            v => Err(RuntimeError::TypeMismatch(format!(
+                "explained program evaluated to {} instead of a bool",
+                v
+            ))),
+        })?;
+        Ok(Explanation::from_log(result, &log))
+    }
+}
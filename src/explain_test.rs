@@ -0,0 +1,74 @@
+use crate::audit::AuditValue;
+use crate::engine::Engine;
+use crate::explain::{BranchTaken, Condition, Explanation};
+
+fn comparison(op: &str, lhs: i64, rhs: i64, result: bool, children: Vec<Condition>) -> Condition {
+    Condition::Comparison {
+        op: op.into(),
+        lhs: AuditValue::Int(lhs),
+        rhs: AuditValue::Int(rhs),
+        result,
+        children,
+    }
+}
+
+#[test]
+fn test_explain() {
+    let engine = Engine::new();
+    let explanation = engine
+        .explain(r"((\x y ($and ($lt x 3) ($eq ($add 1 ($if ($gt y 0) 1 2)) 2))) 1 5)")
+        .unwrap();
+    let and = Condition::Comparison {
+        op: "and".into(),
+        lhs: AuditValue::Bool(true),
+        rhs: AuditValue::Bool(true),
+        result: true,
+        children: vec![
+            comparison("lt", 1, 3, true, vec![]),
+            comparison(
+                "eq",
+                2,
+                2,
+                true,
+                vec![Condition::Branch {
+                    predicate: true,
+                    taken: BranchTaken::Then,
+                    children: vec![comparison("gt", 5, 0, true, vec![])],
+                }],
+            ),
+        ],
+    };
+    assert_eq!(
+        explanation,
+        Explanation {
+            result: true,
+            conditions: vec![and],
+        }
+    );
+
+    let back: Explanation = serde_json::from_str(&explanation.to_json()).unwrap();
+    assert_eq!(back, explanation);
+    assert!(explanation.to_json().contains(r#""kind": "comparison""#));
+}
+
+#[test]
+fn test_explain_branches() {
+    let engine = Engine::new();
+    let explanation = engine
+        .explain(r"($if ($ge 2 7) ($lt 1 2) ($ne 4 4))")
+        .unwrap();
+    assert!(!explanation.result);
+    assert_eq!(
+        explanation.conditions,
+        vec![Condition::Branch {
+            predicate: false,
+            taken: BranchTaken::Else,
+            children: vec![
+                comparison("ge", 2, 7, false, vec![]),
+                comparison("ne", 4, 4, false, vec![]),
+            ],
+        }]
+    );
+
+    assert!(engine.explain("($add 1 2)").is_err());
+}
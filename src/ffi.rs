@@ -0,0 +1,478 @@
+//! C ABI for embedding x-lang in non-Rust hosts.
+//!
+//! Engines and programs are opaque handles created and destroyed through
+//! this API. Every fallible function returns one of the `XL_*` status codes;
+//! on failure, a description of the error can be read with
+//! `xl_engine_last_error`. Panics never unwind into the caller: they are
+//! reported as `XL_ERR_INTERNAL`.
+//!
+//! Host functions registered from C take and return primitive `XlValue`s
+//! and have a fixed parameter list declared at registration time.
+
+use crate::ast::{DataType, Expr};
+use crate::builtin::ValueType;
+use crate::corelib::HostManager;
+use crate::error::*;
+use crate::eval::{eval_expr, EvalContext, LazyValue, RuntimeValue};
+use crate::host::{verify_hosts, HostFunction};
+use crate::parser::parse_expr;
+use crate::recursion::divergence_message;
+use crate::typeck::{check_expr, TypeResolveState};
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+pub const XL_OK: c_int = 0;
+pub const XL_ERR_INVALID_ARGUMENT: c_int = 1;
+pub const XL_ERR_PARSE: c_int = 2;
+pub const XL_ERR_TYPE: c_int = 3;
+pub const XL_ERR_RUNTIME: c_int = 4;
+pub const XL_ERR_UNSUPPORTED_VALUE: c_int = 5;
+/// The engine panicked. The engine may be freed, but the state of the
+/// host functions it called is unknown.
+pub const XL_ERR_INTERNAL: c_int = 6;
+
+pub const XL_TYPE_EMPTY: u32 = 0;
+pub const XL_TYPE_INT: u32 = 1;
+pub const XL_TYPE_FLOAT: u32 = 2;
+pub const XL_TYPE_BOOL: u32 = 3;
+
+/// A primitive value passed across the C boundary.
+///
+/// `int_value` holds ints and bools (0 or 1); `float_value` holds floats.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct XlValue {
+    pub tag: u32,
+    pub int_value: i64,
+    pub float_value: f64,
+}
+
+/// Host callback. Returns 0 on success and writes the result to `out`.
+pub type XlHostFn = extern "C" fn(
+    user_data: *mut c_void,
+    args: *const XlValue,
+    nargs: usize,
+    out: *mut XlValue,
+) -> c_int;
+
+pub struct XlEngine {
+    hm: HostManager,
+    hosts: Vec<(String, CHostFunction)>,
+    last_error: Option<CString>,
+}
+
+pub struct XlProgram {
+    expr: Expr,
+}
+
+struct CHostFunction {
+    param_tags: Vec<u32>,
+    ret_tag: u32,
+    callback: XlHostFn,
+    user_data: *mut c_void,
+}
+
+impl fmt::Debug for CHostFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CHostFunction")
+            .field("param_tags", &self.param_tags)
+            .field("ret_tag", &self.ret_tag)
+            .finish()
+    }
+}
+
+fn tag_type(tag: u32) -> Option<DataType> {
+    match tag {
+        XL_TYPE_EMPTY => Some(DataType::Empty),
+        XL_TYPE_INT => Some(DataType::Value(ValueType::Int)),
+        XL_TYPE_FLOAT => Some(DataType::Value(ValueType::Float)),
+        XL_TYPE_BOOL => Some(DataType::Value(ValueType::Bool)),
+        _ => None,
+    }
+}
+
+fn type_tag(ty: &DataType) -> Option<u32> {
+    match *ty {
+        DataType::Empty => Some(XL_TYPE_EMPTY),
+        DataType::Value(ValueType::Int) => Some(XL_TYPE_INT),
+        DataType::Value(ValueType::Float) => Some(XL_TYPE_FLOAT),
+        DataType::Value(ValueType::Bool) => Some(XL_TYPE_BOOL),
+        _ => None,
+    }
+}
+
+fn to_xl_value(v: &RuntimeValue) -> Option<XlValue> {
+    let mut out = XlValue::default();
+    match *v {
+        RuntimeValue::Empty => out.tag = XL_TYPE_EMPTY,
+        RuntimeValue::Int(x) => {
+            out.tag = XL_TYPE_INT;
+            out.int_value = x;
+        }
+        RuntimeValue::Float(x) => {
+            out.tag = XL_TYPE_FLOAT;
+            out.float_value = x;
+        }
+        RuntimeValue::Bool(x) => {
+            out.tag = XL_TYPE_BOOL;
+            out.int_value = x as i64;
+        }
+        _ => return None,
+    }
+    Some(out)
+}
+
+fn from_xl_value<'b>(v: &XlValue) -> Option<RuntimeValue<'b>> {
+    match v.tag {
+        XL_TYPE_EMPTY => Some(RuntimeValue::Empty),
+        XL_TYPE_INT => Some(RuntimeValue::Int(v.int_value)),
+        XL_TYPE_FLOAT => Some(RuntimeValue::Float(v.float_value)),
+        XL_TYPE_BOOL => Some(RuntimeValue::Bool(v.int_value != 0)),
+        _ => None,
+    }
+}
+
+impl HostFunction for CHostFunction {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        if params.len() != self.param_tags.len() {
+            return Err(TypeError::Custom(format!(
+                "expecting {} params, got {}",
+                self.param_tags.len(),
+                params.len()
+            )));
+        }
+        for (i, (ty, tag)) in params.iter().zip(self.param_tags.iter()).enumerate() {
+            if Some(ty.clone()) != tag_type(*tag) {
+                return Err(TypeError::Custom(format!(
+                    "type mismatch for param {}: {}",
+                    i, ty
+                )));
+            }
+        }
+        Ok(tag_type(self.ret_tag).unwrap())
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let mut args: Vec<XlValue> = Vec::with_capacity(self.param_tags.len());
+        for p in params {
+            let v = p.eval(ectx)?;
+            match to_xl_value(&v) {
+                Some(v) => args.push(v),
+                None => {
+                    return Err(RuntimeError::TypeMismatch(format!(
+                        "host functions registered from C take primitive values, found {}",
+                        v
+                    )))
+                }
+            }
+        }
+
+        let mut out = XlValue::default();
+        let code = (self.callback)(self.user_data, args.as_ptr(), args.len(), &mut out);
+        if code != 0 {
+            return Err(RuntimeError::Custom(format!(
+                "host function returned error code {}",
+                code
+            )));
+        }
+        if out.tag != self.ret_tag {
+            return Err(RuntimeError::Custom(
+                "host function returned a value of the wrong type".into(),
+            ));
+        }
+        Ok(from_xl_value(&out).unwrap())
+    }
+}
+
+impl XlEngine {
+    fn fail(&mut self, code: c_int, msg: String) -> c_int {
+        self.last_error = CString::new(msg).ok();
+        code
+    }
+
+    fn type_state(&self) -> TypeResolveState<'_> {
+        let mut trs = TypeResolveState::default();
+        trs.add_hosts(self.hm.get_all());
+        trs.add_hosts(
+            self.hosts
+                .iter()
+                .map(|(k, v)| (k.clone(), v as &dyn HostFunction)),
+        );
+        trs
+    }
+
+    fn check(&mut self, program: &XlProgram) -> Result<DataType, (c_int, String)> {
+        let mut trs = self.type_state();
+        check_expr(&program.expr, &mut trs).map_err(|e| (XL_ERR_TYPE, e.to_string()))
+    }
+}
+
+/// Runs the body of an entry point taking `engine`, reporting a panic as
+/// `XL_ERR_INTERNAL` instead of unwinding into the caller, which is
+/// undefined behavior.
+///
+/// # Safety
+///
+/// `engine` must be null or a valid engine handle.
+pub(crate) unsafe fn guard<F: FnOnce() -> c_int>(engine: *mut XlEngine, f: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(code) => code,
+        Err(payload) => {
+            let msg = format!("internal error: {}", panic_message(&*payload));
+            // The body's borrow of the engine ended with the unwinding.
+            match engine.as_mut() {
+                Some(engine) => engine.fail(XL_ERR_INTERNAL, msg),
+                None => XL_ERR_INTERNAL,
+            }
+        }
+    }
+}
+
+/// Creates a new engine with the core library registered, or returns null
+/// if that fails.
+#[no_mangle]
+pub extern "C" fn xl_engine_new() -> *mut XlEngine {
+    panic::catch_unwind(|| {
+        Box::into_raw(Box::new(XlEngine {
+            hm: HostManager::new(),
+            hosts: Vec::new(),
+            last_error: None,
+        }))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `engine` must be null or a handle returned by `xl_engine_new` that has
+/// not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn xl_engine_free(engine: *mut XlEngine) {
+    if !engine.is_null() {
+        // A panic while dropping leaks what is left of the engine.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(engine))));
+    }
+}
+
+/// Returns the message of the last error on `engine`, or null. The string
+/// is owned by the engine and valid until the next call on it.
+///
+/// # Safety
+///
+/// `engine` must be a valid engine handle.
+#[no_mangle]
+pub unsafe extern "C" fn xl_engine_last_error(engine: *const XlEngine) -> *const c_char {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        match engine.as_ref().and_then(|e| e.last_error.as_ref()) {
+            Some(msg) => msg.as_ptr(),
+            None => ptr::null(),
+        }
+    }))
+    .unwrap_or(ptr::null())
+}
+
+/// Registers a host function callable as `$name`. Fails with
+/// `XL_ERR_INVALID_ARGUMENT` if a core library or registered host function
+/// is already called that.
+///
+/// # Safety
+///
+/// `engine` must be a valid engine handle, `name` a NUL-terminated string
+/// and `param_tags` must point to `nparams` tags. `callback` will be called
+/// with `user_data` for as long as the engine lives.
+#[no_mangle]
+pub unsafe extern "C" fn xl_engine_register_host(
+    engine: *mut XlEngine,
+    name: *const c_char,
+    param_tags: *const u32,
+    nparams: usize,
+    ret_tag: u32,
+    callback: XlHostFn,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(engine, || {
+        let engine = match engine.as_mut() {
+            Some(v) => v,
+            None => return XL_ERR_INVALID_ARGUMENT,
+        };
+        if name.is_null() || (param_tags.is_null() && nparams != 0) {
+            return engine.fail(XL_ERR_INVALID_ARGUMENT, "null argument".into());
+        }
+        let name = match CStr::from_ptr(name).to_str() {
+            Ok(v) => v.to_string(),
+            Err(_) => return engine.fail(XL_ERR_INVALID_ARGUMENT, "invalid utf-8 in name".into()),
+        };
+        let param_tags = if nparams == 0 {
+            Vec::new()
+        } else {
+            ::std::slice::from_raw_parts(param_tags, nparams).to_vec()
+        };
+        if param_tags
+            .iter()
+            .chain(::std::iter::once(&ret_tag))
+            .any(|t| tag_type(*t).is_none())
+        {
+            return engine.fail(XL_ERR_INVALID_ARGUMENT, "invalid type tag".into());
+        }
+        if engine.hm.get_all().any(|(k, _)| k == name)
+            || engine.hosts.iter().any(|(k, _)| *k == name)
+        {
+            return engine.fail(
+                XL_ERR_INVALID_ARGUMENT,
+                format!("host function ${} is already registered", name),
+            );
+        }
+
+        engine.hosts.push((
+            name,
+            CHostFunction {
+                param_tags,
+                ret_tag,
+                callback,
+                user_data,
+            },
+        ));
+        XL_OK
+    })
+}
+
+/// Parses `source` into a new program handle written to `out`.
+///
+/// # Safety
+///
+/// `engine` must be a valid engine handle, `source` a NUL-terminated string
+/// and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn xl_parse(
+    engine: *mut XlEngine,
+    source: *const c_char,
+    out: *mut *mut XlProgram,
+) -> c_int {
+    guard(engine, || {
+        let engine = match engine.as_mut() {
+            Some(v) => v,
+            None => return XL_ERR_INVALID_ARGUMENT,
+        };
+        if source.is_null() || out.is_null() {
+            return engine.fail(XL_ERR_INVALID_ARGUMENT, "null argument".into());
+        }
+        let source = match CStr::from_ptr(source).to_str() {
+            Ok(v) => v,
+            Err(_) => return engine.fail(XL_ERR_PARSE, ParseError::InvalidUtf8.to_string()),
+        };
+        match parse_expr(source) {
+            Ok(expr) => {
+                *out = Box::into_raw(Box::new(XlProgram { expr }));
+                XL_OK
+            }
+            Err(e) => engine.fail(XL_ERR_PARSE, e.to_string()),
+        }
+    })
+}
+
+/// # Safety
+///
+/// `program` must be null or a handle returned by `xl_parse` that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn xl_program_free(program: *mut XlProgram) {
+    if !program.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(program))));
+    }
+}
+
+/// Typechecks `program`, writing the `XL_TYPE_*` tag of its type to `out`.
+/// Programs of non-primitive type report `XL_ERR_UNSUPPORTED_VALUE`.
+///
+/// # Safety
+///
+/// All pointers must be valid handles or pointers.
+#[no_mangle]
+pub unsafe extern "C" fn xl_typecheck(
+    engine: *mut XlEngine,
+    program: *const XlProgram,
+    out: *mut u32,
+) -> c_int {
+    guard(engine, || {
+        let engine = match engine.as_mut() {
+            Some(v) => v,
+            None => return XL_ERR_INVALID_ARGUMENT,
+        };
+        let program = match program.as_ref() {
+            Some(v) if !out.is_null() => v,
+            _ => return engine.fail(XL_ERR_INVALID_ARGUMENT, "null argument".into()),
+        };
+        match engine.check(program) {
+            Ok(ty) => match type_tag(&ty) {
+                Some(tag) => {
+                    *out = tag;
+                    XL_OK
+                }
+                None => engine.fail(XL_ERR_UNSUPPORTED_VALUE, ty.to_string()),
+            },
+            Err((code, msg)) => engine.fail(code, msg),
+        }
+    })
+}
+
+/// Typechecks and evaluates `program`, writing its value to `out`.
+///
+/// # Safety
+///
+/// All pointers must be valid handles or pointers.
+#[no_mangle]
+pub unsafe extern "C" fn xl_eval(
+    engine: *mut XlEngine,
+    program: *const XlProgram,
+    out: *mut XlValue,
+) -> c_int {
+    guard(engine, || {
+        let engine = match engine.as_mut() {
+            Some(v) => v,
+            None => return XL_ERR_INVALID_ARGUMENT,
+        };
+        let program = match program.as_ref() {
+            Some(v) if !out.is_null() => v,
+            _ => return engine.fail(XL_ERR_INVALID_ARGUMENT, "null argument".into()),
+        };
+        match engine.check(program) {
+            Ok(DataType::Divergent) => {
+                return engine.fail(XL_ERR_TYPE, divergence_message(&program.expr, None))
+            }
+            Ok(_) => {}
+            Err((code, msg)) => return engine.fail(code, msg),
+        }
+
+        let result = {
+            let mut ectx = EvalContext::default();
+            ectx.add_hosts(engine.hm.get_all());
+            ectx.add_hosts(
+                engine
+                    .hosts
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v as &dyn HostFunction)),
+            );
+            verify_hosts(&engine.type_state(), &ectx)
+                .and_then(|_| eval_expr(&program.expr, &mut ectx))
+                .map(|v| to_xl_value(&v).ok_or_else(|| v.to_string()))
+        };
+        match result {
+            Ok(Ok(v)) => {
+                *out = v;
+                XL_OK
+            }
+            Ok(Err(v)) => engine.fail(XL_ERR_UNSUPPORTED_VALUE, v),
+            Err(e) => engine.fail(XL_ERR_RUNTIME, e.to_string()),
+        }
+    })
+}
@@ -0,0 +1,126 @@
+use crate::ffi::*;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+extern "C" fn triple(
+    _user_data: *mut c_void,
+    args: *const XlValue,
+    nargs: usize,
+    out: *mut XlValue,
+) -> c_int {
+    unsafe {
+        assert_eq!(nargs, 1);
+        (*out).tag = XL_TYPE_INT;
+        (*out).int_value = (*args).int_value * 3;
+    }
+    0
+}
+
+#[test]
+fn test_ffi_roundtrip() {
+    unsafe {
+        let engine = xl_engine_new();
+        let name = CString::new("triple").unwrap();
+        let params = [XL_TYPE_INT];
+        assert_eq!(
+            xl_engine_register_host(
+                engine,
+                name.as_ptr(),
+                params.as_ptr(),
+                1,
+                XL_TYPE_INT,
+                triple,
+                ptr::null_mut()
+            ),
+            XL_OK
+        );
+
+        let src = CString::new("($add ($triple 4) 2)").unwrap();
+        let mut program = ptr::null_mut();
+        assert_eq!(xl_parse(engine, src.as_ptr(), &mut program), XL_OK);
+
+        let mut tag = 0;
+        assert_eq!(xl_typecheck(engine, program, &mut tag), XL_OK);
+        assert_eq!(tag, XL_TYPE_INT);
+
+        let mut value = XlValue::default();
+        assert_eq!(xl_eval(engine, program, &mut value), XL_OK);
+        assert_eq!(value.int_value, 14);
+        xl_program_free(program);
+
+        let bad = CString::new("($triple 1.5)").unwrap();
+        assert_eq!(xl_parse(engine, bad.as_ptr(), &mut program), XL_OK);
+        assert_eq!(xl_eval(engine, program, &mut value), XL_ERR_TYPE);
+        assert!(!xl_engine_last_error(engine).is_null());
+        xl_program_free(program);
+
+        // Errors are reported as displayed.
+        let bad = CString::new("($add 1").unwrap();
+        assert_eq!(xl_parse(engine, bad.as_ptr(), &mut program), XL_ERR_PARSE);
+        let msg = CStr::from_ptr(xl_engine_last_error(engine))
+            .to_str()
+            .unwrap();
+        assert_eq!(msg, "unexpected end of input");
+        let bad = CString::new("($div 1 0)").unwrap();
+        assert_eq!(xl_parse(engine, bad.as_ptr(), &mut program), XL_OK);
+        assert_eq!(xl_eval(engine, program, &mut value), XL_ERR_RUNTIME);
+        let msg = CStr::from_ptr(xl_engine_last_error(engine))
+            .to_str()
+            .unwrap();
+        assert_eq!(msg, "division by zero");
+        xl_program_free(program);
+
+        xl_engine_free(engine);
+    }
+}
+
+#[test]
+fn test_ffi_duplicate_host() {
+    unsafe {
+        let engine = xl_engine_new();
+        let params = [XL_TYPE_INT];
+        let register = |name: &str| {
+            let name = CString::new(name).unwrap();
+            xl_engine_register_host(
+                engine,
+                name.as_ptr(),
+                params.as_ptr(),
+                1,
+                XL_TYPE_INT,
+                triple,
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(register("triple"), XL_OK);
+        assert_eq!(register("triple"), XL_ERR_INVALID_ARGUMENT);
+        assert_eq!(register("add"), XL_ERR_INVALID_ARGUMENT);
+        let msg = CStr::from_ptr(xl_engine_last_error(engine))
+            .to_str()
+            .unwrap();
+        assert_eq!(msg, "host function $add is already registered");
+
+        // The original `$add` is still the one called.
+        let src = CString::new("($add ($triple 1) 2)").unwrap();
+        let mut program = ptr::null_mut();
+        assert_eq!(xl_parse(engine, src.as_ptr(), &mut program), XL_OK);
+        let mut value = XlValue::default();
+        assert_eq!(xl_eval(engine, program, &mut value), XL_OK);
+        assert_eq!(value.int_value, 5);
+        xl_program_free(program);
+
+        xl_engine_free(engine);
+    }
+}
+
+#[test]
+fn test_ffi_panics_are_errors() {
+    unsafe {
+        let engine = xl_engine_new();
+        assert_eq!(guard(engine, || panic!("boom")), XL_ERR_INTERNAL);
+        let msg = std::ffi::CStr::from_ptr(xl_engine_last_error(engine));
+        assert_eq!(msg.to_str().unwrap(), "internal error: boom");
+        assert_eq!(guard(ptr::null_mut(), || panic!("boom")), XL_ERR_INTERNAL);
+        xl_engine_free(engine);
+    }
+}
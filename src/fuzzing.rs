@@ -0,0 +1,77 @@
+//! Helpers for fuzzing the parser and typechecker.
+//!
+//! Generated expressions have bounded depth and only refer to names bound
+//! by an enclosing lambda (plus the occasional unbound one), using the
+//! renamed `name#N` form that the parser produces.
+
+use crate::ast::*;
+use arbitrary::{Arbitrary, Result, Unstructured};
+use std::rc::Rc;
+
+const MAX_DEPTH: usize = 8;
+const HOSTS: &[&str] = &[
+    "add",
+    "sub",
+    "mul",
+    "div",
+    "mod",
+    "eq",
+    "lt",
+    "and",
+    "if",
+    "list_push",
+    "list_head",
+    "round",
+];
+
+fn gen_const(u: &mut Unstructured) -> Result<ConstExpr> {
+    Ok(match u.int_in_range(0..=3)? {
+        0 => ConstExpr::Int(u.arbitrary()?),
+        1 => ConstExpr::Float(u.arbitrary()?),
+        2 => ConstExpr::Bool(u.arbitrary()?),
+        _ => ConstExpr::Empty,
+    })
+}
+
+fn gen_expr(u: &mut Unstructured, scope: &mut Vec<String>, depth: usize) -> Result<Expr> {
+    let leaf = depth >= MAX_DEPTH || u.is_empty();
+    let body = match u.int_in_range(0..=if leaf { 2 } else { 5 })? {
+        0 => ExprBody::Const(gen_const(u)?),
+        1 if !scope.is_empty() => ExprBody::Name(u.choose(scope)?.clone()),
+        1 => ExprBody::Name("unbound".into()),
+        2 => ExprBody::Abstract {
+            params: vec![],
+            body: AbstractBody::Host(u.choose(HOSTS)?.to_string()),
+        },
+        3 | 4 => {
+            let target = gen_expr(u, scope, depth + 1)?;
+            let n = u.int_in_range(1..=3)?;
+            let mut params = Vec::with_capacity(n);
+            for _ in 0..n {
+                params.push(gen_expr(u, scope, depth + 1)?);
+            }
+            ExprBody::Apply { target, params }
+        }
+        _ => {
+            let n = u.int_in_range(1..=3)?;
+            let params: Vec<String> = (0..n).map(|i| format!("p{}#{}", i, depth + 1)).collect();
+            let old_len = scope.len();
+            scope.extend(params.iter().cloned());
+            let body = gen_expr(u, scope, depth + 1);
+            scope.truncate(old_len);
+            ExprBody::Abstract {
+                params,
+                body: AbstractBody::Expr(body?),
+            }
+        }
+    };
+    Ok(Expr {
+        body: Rc::new(body),
+    })
+}
+
+impl<'a> Arbitrary<'a> for Expr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        gen_expr(u, &mut Vec::new(), 0)
+    }
+}
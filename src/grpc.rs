@@ -0,0 +1,392 @@
+//! A gRPC interface to the engine, for clients in other languages.
+//!
+//! `GrpcService` implements the `Evaluator` service of `proto/xlang.proto`,
+//! whose messages are defined here by hand, so that building needs no
+//! `protoc`. Values are mapped to protobuf as `remote::WireValue`s are, and
+//! scripts are evaluated as with `remote::handle`; errors in scripts are
+//! answered with an `Error` message, and gRPC statuses are kept for failures
+//! of the call itself.
+//!
+//! Engines are not `Send`, so the service builds one from its
+//! `EngineConfig` for each call, set up further by `GrpcService::with_setup`
+//! if host functions need registering, and evaluates on a blocking thread.
+//!
+//! ```no_run
+//! # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+//! use x_lang::engine::EngineConfig;
+//! use x_lang::grpc::{EvaluatorServer, GrpcService};
+//!
+//! tonic::transport::Server::builder()
+//!     .add_service(EvaluatorServer::new(GrpcService::new(EngineConfig::default())))
+//!     .serve("127.0.0.1:50051".parse()?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::engine::{Engine, EngineConfig};
+use crate::error::Error as XError;
+use crate::host;
+use crate::remote::{self, RemoteError, RemoteErrorKind, RemoteRequest, RemoteResponse, WireValue};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/xlang.Evaluator.rs"));
+}
+
+pub use self::generated::evaluator_client::EvaluatorClient;
+pub use self::generated::evaluator_server::{Evaluator, EvaluatorServer};
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Empty {}
+
+/// A value; one without a kind is taken as empty.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Value {
+    #[prost(oneof = "value::Kind", tags = "1, 2, 3, 4, 5, 6, 7, 8")]
+    pub kind: Option<value::Kind>,
+}
+
+pub mod value {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Kind {
+        #[prost(message, tag = "1")]
+        Empty(super::Empty),
+        #[prost(int64, tag = "2")]
+        Int(i64),
+        #[prost(double, tag = "3")]
+        Float(f64),
+        #[prost(bool, tag = "4")]
+        Bool(bool),
+        #[prost(string, tag = "5")]
+        String(String),
+        #[prost(bytes = "vec", tag = "6")]
+        Bytes(Vec<u8>),
+        #[prost(message, tag = "7")]
+        List(super::ValueList),
+        /// A value without a wire form, as displayed.
+        #[prost(string, tag = "8")]
+        Opaque(String),
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ValueList {
+    #[prost(message, repeated, tag = "1")]
+    pub items: Vec<Value>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ErrorKind {
+    Request = 0,
+    Parse = 1,
+    Type = 2,
+    Runtime = 3,
+    Limit = 4,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Error {
+    #[prost(enumeration = "ErrorKind", tag = "1")]
+    pub kind: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ParseRequest {
+    #[prost(string, tag = "1")]
+    pub source: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ParseResponse {
+    #[prost(oneof = "parse_response::Result", tags = "1, 2")]
+    pub result: Option<parse_response::Result>,
+}
+
+pub mod parse_response {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Result {
+        /// The program in normal form.
+        #[prost(string, tag = "1")]
+        Expr(String),
+        #[prost(message, tag = "2")]
+        Error(super::Error),
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TypeCheckRequest {
+    #[prost(string, tag = "1")]
+    pub source: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TypeCheckResponse {
+    #[prost(oneof = "type_check_response::Result", tags = "1, 2")]
+    pub result: Option<type_check_response::Result>,
+}
+
+pub mod type_check_response {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Result {
+        #[prost(string, tag = "1")]
+        Type(String),
+        #[prost(message, tag = "2")]
+        Error(super::Error),
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EvaluateRequest {
+    #[prost(string, tag = "1")]
+    pub source: String,
+    #[prost(message, repeated, tag = "2")]
+    pub args: Vec<Value>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Evaluation {
+    #[prost(string, tag = "1")]
+    pub r#type: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EvaluateResponse {
+    #[prost(oneof = "evaluate_response::Result", tags = "1, 2")]
+    pub result: Option<evaluate_response::Result>,
+}
+
+pub mod evaluate_response {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Result {
+        #[prost(message, tag = "1")]
+        Value(super::Evaluation),
+        #[prost(message, tag = "2")]
+        Error(super::Error),
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DescribeHostsRequest {}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HostInfo {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub group: String,
+    #[prost(string, optional, tag = "3")]
+    pub signature: Option<String>,
+    #[prost(uint32, tag = "4")]
+    pub version: u32,
+    #[prost(string, optional, tag = "5")]
+    pub doc: Option<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DescribeHostsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub hosts: Vec<HostInfo>,
+}
+
+impl From<WireValue> for Value {
+    fn from(v: WireValue) -> Value {
+        use self::value::Kind;
+        let kind = match v {
+            WireValue::Empty => Kind::Empty(Empty {}),
+            WireValue::Int(x) => Kind::Int(x),
+            WireValue::Float(x) => Kind::Float(x),
+            WireValue::Bool(x) => Kind::Bool(x),
+            WireValue::String(x) => Kind::String(x),
+            WireValue::Bytes(x) => Kind::Bytes(x),
+            WireValue::List(items) => Kind::List(ValueList {
+                items: items.into_iter().map(Value::from).collect(),
+            }),
+            WireValue::Opaque(x) => Kind::Opaque(x),
+        };
+        Value { kind: Some(kind) }
+    }
+}
+
+impl From<Value> for WireValue {
+    fn from(v: Value) -> WireValue {
+        use self::value::Kind;
+        match v.kind {
+            None | Some(Kind::Empty(_)) => WireValue::Empty,
+            Some(Kind::Int(x)) => WireValue::Int(x),
+            Some(Kind::Float(x)) => WireValue::Float(x),
+            Some(Kind::Bool(x)) => WireValue::Bool(x),
+            Some(Kind::String(x)) => WireValue::String(x),
+            Some(Kind::Bytes(x)) => WireValue::Bytes(x),
+            Some(Kind::List(list)) => {
+                WireValue::List(list.items.into_iter().map(WireValue::from).collect())
+            }
+            Some(Kind::Opaque(x)) => WireValue::Opaque(x),
+        }
+    }
+}
+
+impl From<RemoteError> for Error {
+    fn from(e: RemoteError) -> Error {
+        let kind = match e.kind {
+            RemoteErrorKind::Request => ErrorKind::Request,
+            RemoteErrorKind::Parse => ErrorKind::Parse,
+            RemoteErrorKind::Type => ErrorKind::Type,
+            RemoteErrorKind::Runtime => ErrorKind::Runtime,
+            RemoteErrorKind::Limit => ErrorKind::Limit,
+        };
+        Error {
+            kind: kind as i32,
+            message: e.message,
+        }
+    }
+}
+
+impl From<XError> for Error {
+    fn from(e: XError) -> Error {
+        RemoteError::from(e).into()
+    }
+}
+
+impl From<host::HostInfo> for HostInfo {
+    fn from(info: host::HostInfo) -> HostInfo {
+        HostInfo {
+            name: info.name,
+            group: info.group,
+            signature: info.signature,
+            version: info.version,
+            doc: info.doc,
+        }
+    }
+}
+
+type Setup = Arc<dyn Fn(&mut Engine) + Send + Sync>;
+
+/// The `Evaluator` service. See the module documentation.
+#[derive(Clone)]
+pub struct GrpcService {
+    config: EngineConfig,
+    setup: Option<Setup>,
+}
+
+impl GrpcService {
+    pub fn new(config: EngineConfig) -> GrpcService {
+        GrpcService {
+            config,
+            setup: None,
+        }
+    }
+
+    /// Runs `setup` on the engine of every call after building it from the
+    /// configuration, e.g. to add host functions.
+    pub fn with_setup<F>(mut self, setup: F) -> GrpcService
+    where
+        F: Fn(&mut Engine) + Send + Sync + 'static,
+    {
+        self.setup = Some(Arc::new(setup));
+        self
+    }
+
+    fn engine(&self) -> Engine {
+        let mut engine = Engine::with_config(&self.config);
+        if let Some(ref setup) = self.setup {
+            setup(&mut engine);
+        }
+        engine
+    }
+
+    /// Runs `f` with a fresh engine on a blocking thread.
+    async fn with_engine<T, F>(&self, f: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Engine) -> T + Send + 'static,
+    {
+        let service = self.clone();
+        tokio::task::spawn_blocking(move || f(&service.engine()))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+#[tonic::async_trait]
+impl Evaluator for GrpcService {
+    async fn parse(
+        &self,
+        request: Request<ParseRequest>,
+    ) -> Result<Response<ParseResponse>, Status> {
+        use self::parse_response::Result as R;
+        let source = request.into_inner().source;
+        let result = self
+            .with_engine(move |engine| match engine.parse(&source) {
+                Ok(e) => R::Expr(e.to_string()),
+                Err(e) => R::Error(e.into()),
+            })
+            .await?;
+        Ok(Response::new(ParseResponse {
+            result: Some(result),
+        }))
+    }
+
+    async fn type_check(
+        &self,
+        request: Request<TypeCheckRequest>,
+    ) -> Result<Response<TypeCheckResponse>, Status> {
+        use self::type_check_response::Result as R;
+        let source = request.into_inner().source;
+        let result = self
+            .with_engine(move |engine| match engine.infer_type(&source) {
+                Ok(ty) => R::Type(ty.to_string()),
+                Err(e) => R::Error(e.into()),
+            })
+            .await?;
+        Ok(Response::new(TypeCheckResponse {
+            result: Some(result),
+        }))
+    }
+
+    async fn evaluate(
+        &self,
+        request: Request<EvaluateRequest>,
+    ) -> Result<Response<EvaluateResponse>, Status> {
+        use self::evaluate_response::Result as R;
+        let request = request.into_inner();
+        let request = RemoteRequest {
+            source: request.source,
+            args: request.args.into_iter().map(WireValue::from).collect(),
+        };
+        let result = self
+            .with_engine(move |engine| match remote::handle(engine, &request) {
+                RemoteResponse::Value { ty, value } => R::Value(Evaluation {
+                    r#type: ty.to_string(),
+                    value: Some(value.into()),
+                }),
+                RemoteResponse::Error(e) => R::Error(e.into()),
+            })
+            .await?;
+        Ok(Response::new(EvaluateResponse {
+            result: Some(result),
+        }))
+    }
+
+    async fn describe_hosts(
+        &self,
+        _: Request<DescribeHostsRequest>,
+    ) -> Result<Response<DescribeHostsResponse>, Status> {
+        let hosts = self
+            .with_engine(|engine| {
+                engine
+                    .describe_hosts()
+                    .into_iter()
+                    .map(HostInfo::from)
+                    .collect()
+            })
+            .await?;
+        Ok(Response::new(DescribeHostsResponse { hosts }))
+    }
+}
@@ -0,0 +1,162 @@
+use crate::engine::EngineConfig;
+use crate::grpc::*;
+use crate::log::LogOp;
+use std::convert::TryFrom;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Endpoint, Server};
+use tonic::Request;
+
+fn service() -> GrpcService {
+    let config = EngineConfig {
+        step_limit: Some(20),
+        ..EngineConfig::default()
+    };
+    GrpcService::new(config).with_setup(|engine| engine.add_host("trace".into(), Box::new(LogOp)))
+}
+
+fn int(x: i64) -> Value {
+    Value {
+        kind: Some(value::Kind::Int(x)),
+    }
+}
+
+fn error_kind(e: &Error) -> ErrorKind {
+    ErrorKind::try_from(e.kind).unwrap()
+}
+
+#[tokio::test]
+async fn test_grpc_parse_and_type_check() {
+    use self::parse_response::Result as P;
+    use self::type_check_response::Result as T;
+    let service = service();
+
+    let parsed = service
+        .parse(Request::new(ParseRequest {
+            source: "($add 1 2)".into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(matches!(parsed.result, Some(P::Expr(_))));
+    let parsed = service
+        .parse(Request::new(ParseRequest { source: "(".into() }))
+        .await
+        .unwrap()
+        .into_inner();
+    match parsed.result {
+        Some(P::Error(e)) => assert_eq!(error_kind(&e), ErrorKind::Parse),
+        r => panic!("unexpected result: {:?}", r),
+    }
+
+    let checked = service
+        .type_check(Request::new(TypeCheckRequest {
+            source: "($add 1 2)".into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(checked.result, Some(T::Type("int".into())));
+    let checked = service
+        .type_check(Request::new(TypeCheckRequest {
+            source: "($add 1 true)".into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    match checked.result {
+        Some(T::Error(e)) => assert_eq!(error_kind(&e), ErrorKind::Type),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn test_grpc_evaluate() {
+    use self::evaluate_response::Result as R;
+    let service = service();
+
+    let evaluated = service
+        .evaluate(Request::new(EvaluateRequest {
+            source: r"(\x y ($mul x y))".into(),
+            args: vec![int(6), int(7)],
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    match evaluated.result {
+        Some(R::Value(v)) => {
+            assert_eq!(v.r#type, "int");
+            assert_eq!(v.value, Some(int(42)));
+        }
+        r => panic!("unexpected result: {:?}", r),
+    }
+
+    let spin = r"((\f (f (f (f (f 1))))) (\y ($add y ($add y y))))";
+    let evaluated = service
+        .evaluate(Request::new(EvaluateRequest {
+            source: spin.into(),
+            args: vec![],
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    match evaluated.result {
+        Some(R::Error(e)) => assert_eq!(error_kind(&e), ErrorKind::Limit),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn test_grpc_describe_hosts() {
+    let hosts = service()
+        .describe_hosts(Request::new(DescribeHostsRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .hosts;
+    assert!(hosts.iter().any(|h| h.name == "add"));
+    assert!(hosts.iter().any(|h| h.name == "trace" && h.group == "io"));
+}
+
+#[tokio::test]
+async fn test_grpc_client() {
+    use self::evaluate_response::Result as R;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(EvaluatorServer::new(service()))
+            .serve_with_incoming(incoming),
+    );
+
+    let channel = Endpoint::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut client = EvaluatorClient::new(channel);
+    let list = Value {
+        kind: Some(value::Kind::List(ValueList {
+            items: vec![int(1), int(2)],
+        })),
+    };
+    let evaluated = client
+        .evaluate(EvaluateRequest {
+            source: r"(\xs ($list_tail xs))".into(),
+            args: vec![list],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    match evaluated.result {
+        Some(R::Value(v)) => assert_eq!(
+            v.value,
+            Some(Value {
+                kind: Some(value::Kind::List(ValueList {
+                    items: vec![int(2)]
+                })),
+            })
+        ),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
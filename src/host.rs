@@ -1,13 +1,403 @@
-use crate::ast::DataType;
+use crate::ast::{fmt_element, ConstExpr, DataType, Expr, ExprBody};
 use crate::error::*;
 use crate::eval::{EvalContext, LazyValue, RuntimeValue};
-use std::fmt::Debug;
+use crate::typeck::TypeResolveState;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Debug};
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The name of the host function `name` in `namespace`, which scripts call
+/// as `$namespace.name`. Namespaces keep host functions of different
+/// libraries apart, and may be nested, as in `myco.geo.lookup`.
+pub fn qualified_name(namespace: &str, name: &str) -> String {
+    format!("{}.{}", namespace, name)
+}
+
+/// A declared host function parameter.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub default: Option<ConstExpr>,
+}
+
+impl Param {
+    pub fn required(name: &str) -> Param {
+        Param {
+            name: name.to_string(),
+            default: None,
+        }
+    }
+
+    pub fn optional(name: &str, default: ConstExpr) -> Param {
+        Param {
+            name: name.to_string(),
+            default: Some(default),
+        }
+    }
+}
+
+/// Declared parameter list of a host function.
+///
+/// Parameters with defaults must come after all required ones. Call sites
+/// may omit any number of trailing defaulted parameters; typeck and eval
+/// fill them in before the host function sees them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Param>,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, p) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", p.name)?;
+            if let Some(ref default) = p.default {
+                write!(f, " = ")?;
+                fmt_element(
+                    &Expr {
+                        body: Rc::new(ExprBody::Const(default.clone())),
+                    },
+                    f,
+                )?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+impl Signature {
+    pub fn new(params: Vec<Param>) -> Signature {
+        Signature { params }
+    }
+
+    pub fn min_params(&self) -> usize {
+        self.params
+            .iter()
+            .take_while(|p| p.default.is_none())
+            .count()
+    }
+
+    /// Checks a call with `n` arguments against this signature.
+    pub fn check_arity(&self, n: usize) -> Result<(), TypeError> {
+        if n < self.min_params() || n > self.params.len() {
+            Err(TypeError::Custom(format!(
+                "expecting {} to {} params, got {}",
+                self.min_params(),
+                self.params.len(),
+                n
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the default values for the parameters after the first `n`.
+    pub fn defaults_from(&self, n: usize) -> Vec<ConstExpr> {
+        self.params
+            .iter()
+            .skip(n)
+            .filter_map(|p| p.default.clone())
+            .collect()
+    }
+}
 
 pub trait HostFunction: Debug {
+    /// Declared parameters, if the function has a fixed parameter list.
+    fn signature(&self) -> Option<Signature> {
+        None
+    }
+
+    /// Version of the function's behavior and types. Bump it when programs
+    /// written against the previous version should no longer load, e.g.
+    /// because the type of its result changed. See `HostAbi`.
+    fn version(&self) -> u32 {
+        0
+    }
+
+    /// What the function does, in a sentence or two of markdown, for
+    /// tooling to show next to its signature. See `HostInfo`.
+    fn doc(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether a call with arguments of types `params` converts an int
+    /// argument to a float, as the arithmetic and comparison operators do
+    /// with mixed operands. Typeck warns about such calls.
+    fn converts_int_to_float(&self, _params: &[DataType]) -> bool {
+        false
+    }
+
     fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError>;
     fn eval<'b, 'c>(
         &self,
         ectx: &mut EvalContext<'b, 'c>,
-        params: &mut Iterator<Item = LazyValue<'b>>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
     ) -> Result<RuntimeValue<'b>, RuntimeError>;
 }
+
+/// Lets one host function be registered with several engines, e.g. every
+/// tenant of an `EnginePool`.
+impl<T: HostFunction + ?Sized> HostFunction for Arc<T> {
+    fn signature(&self) -> Option<Signature> {
+        (**self).signature()
+    }
+
+    fn version(&self) -> u32 {
+        (**self).version()
+    }
+
+    fn doc(&self) -> Option<&str> {
+        (**self).doc()
+    }
+
+    fn converts_int_to_float(&self, params: &[DataType]) -> bool {
+        (**self).converts_int_to_float(params)
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        (**self).typeck(params)
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        (**self).eval(ectx, params)
+    }
+}
+
+/// What tooling shows about a host function: how to call it and what it
+/// does.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HostInfo {
+    /// Name without the `$`.
+    pub name: String,
+    /// Name of the `HostGroup` the function belongs to.
+    pub group: String,
+    /// How to call the function, e.g. `$round(x, digits = 0)`, if it has
+    /// a fixed parameter list.
+    pub signature: Option<String>,
+    pub version: u32,
+    pub doc: Option<String>,
+}
+
+impl HostInfo {
+    /// Describes `hf`, registered as `name` in `group`, with its own doc
+    /// text.
+    pub fn of(name: &str, group: &str, hf: &dyn HostFunction) -> HostInfo {
+        HostInfo {
+            name: name.to_string(),
+            group: group.to_string(),
+            signature: hf.signature().map(|sig| format!("${}{}", name, sig)),
+            version: hf.version(),
+            doc: hf.doc().map(|x| x.to_string()),
+        }
+    }
+}
+
+/// What a program relies on about a host function.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HostDescriptor {
+    pub version: u32,
+    pub signature: Option<Signature>,
+}
+
+impl HostDescriptor {
+    pub fn of(hf: &dyn HostFunction) -> HostDescriptor {
+        HostDescriptor {
+            version: hf.version(),
+            signature: hf.signature(),
+        }
+    }
+}
+
+/// Descriptors of a set of host functions by name, recorded when a program
+/// is built so that it can be checked against the host functions available
+/// where it is loaded.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HostAbi {
+    pub hosts: BTreeMap<String, HostDescriptor>,
+}
+
+impl HostAbi {
+    pub fn of<'a, H: IntoIterator<Item = (String, &'a dyn HostFunction)>>(hosts: H) -> HostAbi {
+        HostAbi {
+            hosts: hosts
+                .into_iter()
+                .map(|(k, v)| (k, HostDescriptor::of(v)))
+                .collect(),
+        }
+    }
+
+    /// The descriptors of the functions in `names` only.
+    pub fn restricted_to(&self, names: &BTreeSet<String>) -> HostAbi {
+        HostAbi {
+            hosts: self
+                .hosts
+                .iter()
+                .filter(|(k, _)| names.contains(*k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+
+    /// How the functions in `registered` differ from those this ABI
+    /// describes. Functions `registered` has in addition do not matter.
+    pub fn mismatches(&self, registered: &HostAbi) -> Vec<AbiMismatch> {
+        let mut out = Vec::new();
+        for (name, expected) in &self.hosts {
+            let found = match registered.hosts.get(name) {
+                Some(found) => found,
+                None => {
+                    out.push(AbiMismatch::Missing(name.clone()));
+                    continue;
+                }
+            };
+            if found.version != expected.version {
+                out.push(AbiMismatch::Version {
+                    name: name.clone(),
+                    expected: expected.version,
+                    found: found.version,
+                });
+            }
+            if found.signature != expected.signature {
+                out.push(AbiMismatch::Signature {
+                    name: name.clone(),
+                    expected: expected.signature.clone(),
+                    found: found.signature.clone(),
+                });
+            }
+        }
+        out
+    }
+}
+
+/// A way in which a registered host function differs from what a program
+/// was built against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiMismatch {
+    Missing(String),
+    Version {
+        name: String,
+        expected: u32,
+        found: u32,
+    },
+    Signature {
+        name: String,
+        expected: Option<Signature>,
+        found: Option<Signature>,
+    },
+}
+
+impl fmt::Display for AbiMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let signature = |s: &Option<Signature>| match *s {
+            Some(ref s) => s.to_string(),
+            None => "any parameters".to_string(),
+        };
+        match *self {
+            AbiMismatch::Missing(ref name) => write!(f, "${} is not registered", name),
+            AbiMismatch::Version {
+                ref name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "${} is version {}, but the program was built against version {}",
+                name, found, expected
+            ),
+            AbiMismatch::Signature {
+                ref name,
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "${} takes {}, but the program was built against {}",
+                name,
+                signature(found),
+                signature(expected)
+            ),
+        }
+    }
+}
+
+/// Host functions registered with only one of a `TypeResolveState` and the
+/// `EvalContext` that evaluates what it checked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostSetDiff {
+    /// Accepted by typeck, but calling them fails at runtime.
+    pub typeck_only: BTreeSet<String>,
+    /// Available at runtime, but rejected by typeck.
+    pub eval_only: BTreeSet<String>,
+}
+
+impl HostSetDiff {
+    pub fn between(trs: &TypeResolveState, ectx: &EvalContext) -> HostSetDiff {
+        let typeck: BTreeSet<&str> = trs.host_names().collect();
+        let eval: BTreeSet<&str> = ectx.host_names().collect();
+        HostSetDiff {
+            typeck_only: typeck.difference(&eval).map(|x| x.to_string()).collect(),
+            eval_only: eval.difference(&typeck).map(|x| x.to_string()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.typeck_only.is_empty() && self.eval_only.is_empty()
+    }
+}
+
+impl fmt::Display for HostSetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sides = [
+            ("typeck only", &self.typeck_only),
+            ("eval only", &self.eval_only),
+        ];
+        let mut first = true;
+        for (label, names) in sides.iter().filter(|(_, names)| !names.is_empty()) {
+            let names: Vec<String> = names.iter().map(|x| format!("${}", x)).collect();
+            if !first {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}: {}", label, names.join(", "))?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// Fails with `RuntimeError::HostSetMismatch` unless `trs` and `ectx`
+/// register the same host functions. Call before evaluating an expression
+/// checked with `trs` in `ectx`.
+pub fn verify_hosts(trs: &TypeResolveState, ectx: &EvalContext) -> Result<(), RuntimeError> {
+    let diff = HostSetDiff::between(trs, ectx);
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(RuntimeError::HostSetMismatch(diff))
+    }
+}
+
+/// A registered host function, either borrowed from its owner or shared
+/// through an `Arc`.
+#[derive(Debug, Clone)]
+pub(crate) enum HostHandle<'a> {
+    Borrowed(&'a dyn HostFunction),
+    Owned(Arc<dyn HostFunction>),
+}
+
+impl<'a> Deref for HostHandle<'a> {
+    type Target = dyn HostFunction + 'a;
+
+    fn deref(&self) -> &Self::Target {
+        match *self {
+            HostHandle::Borrowed(hf) => hf,
+            HostHandle::Owned(ref hf) => &**hf,
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Conversions between Rust types and script values for host functions
+//! written as plain Rust functions.
+//!
+//! `#[xl_host("name")]`, from the `macros` feature, generates the
+//! `HostFunction` for a function whose parameters implement `HostArg` and
+//! whose result implements `HostResult`:
+//!
+//! ```ignore
+//! /// Clamps `x` to `[lo, hi]`.
+//! #[xl_host("clamp")]
+//! fn clamp(x: i64, lo: i64, hi: i64) -> i64 {
+//!     x.max(lo).min(hi)
+//! }
+//!
+//! engine.add_host(ClampOp::NAME.into(), Box::new(ClampOp));
+//! ```
+//!
+//! The generated type is named after the function in camel case with an
+//! `Op` suffix. Its signature names the function's parameters, typeck
+//! requires each argument to have the type of its parameter, and the doc
+//! comment becomes the host's `HostFunction::doc`. Functions returning a
+//! `Result` fail with `RuntimeError::Custom` on `Err`.
+
+use crate::ast::DataType;
+use crate::builtin::ValueType;
+use crate::corelib::{
+    as_str, is_list_type, list_type, list_value, str_value, string_type, type_mismatch, List,
+};
+use crate::error::*;
+use crate::eval::*;
+use std::fmt::Display;
+
+/// A Rust type host functions can take as a parameter.
+pub trait HostArg: Sized {
+    /// The type scripts must pass.
+    fn data_type() -> DataType;
+
+    fn from_runtime<'b, 'c>(
+        v: RuntimeValue<'b>,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Self, RuntimeError>;
+}
+
+/// A Rust type host functions can return.
+pub trait HostResult {
+    /// The type scripts receive.
+    fn data_type() -> DataType;
+
+    fn into_runtime<'b, 'c>(
+        self,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError>;
+}
+
+macro_rules! scalar {
+    ($t:ty, $variant:ident) => {
+        impl HostArg for $t {
+            fn data_type() -> DataType {
+                DataType::Value(ValueType::$variant)
+            }
+
+            fn from_runtime<'b, 'c>(
+                v: RuntimeValue<'b>,
+                _ectx: &mut EvalContext<'b, 'c>,
+            ) -> Result<Self, RuntimeError> {
+                match v {
+                    RuntimeValue::$variant(x) => Ok(x),
+                    v => Err(type_mismatch(stringify!($t), &v)),
+                }
+            }
+        }
+
+        impl HostResult for $t {
+            fn data_type() -> DataType {
+                DataType::Value(ValueType::$variant)
+            }
+
+            fn into_runtime<'b, 'c>(
+                self,
+                _ectx: &mut EvalContext<'b, 'c>,
+            ) -> Result<RuntimeValue<'b>, RuntimeError> {
+                Ok(RuntimeValue::$variant(self))
+            }
+        }
+    };
+}
+
+scalar!(i64, Int);
+scalar!(f64, Float);
+scalar!(bool, Bool);
+
+impl HostArg for String {
+    fn data_type() -> DataType {
+        string_type()
+    }
+
+    fn from_runtime<'b, 'c>(
+        v: RuntimeValue<'b>,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Self, RuntimeError> {
+        as_str(&v)
+            .map(|s| s.to_string())
+            .ok_or_else(|| type_mismatch("string", &v))
+    }
+}
+
+impl HostResult for String {
+    fn data_type() -> DataType {
+        string_type()
+    }
+
+    fn into_runtime<'b, 'c>(
+        self,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        Ok(str_value(&self))
+    }
+}
+
+impl<T: HostArg> HostArg for Vec<T> {
+    fn data_type() -> DataType {
+        list_type(T::data_type())
+    }
+
+    fn from_runtime<'b, 'c>(
+        v: RuntimeValue<'b>,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Self, RuntimeError> {
+        let values = match v {
+            RuntimeValue::Empty => return Ok(Vec::new()),
+            RuntimeValue::Custom(ref cv) => match cv.inner.as_any().downcast_ref::<List>() {
+                Some(list) => list.values(ectx)?,
+                None => return Err(type_mismatch("list", &v)),
+            },
+            v => return Err(type_mismatch("list", &v)),
+        };
+        values
+            .into_iter()
+            .map(|x| T::from_runtime(x, ectx))
+            .collect()
+    }
+}
+
+impl<T: HostResult> HostResult for Vec<T> {
+    fn data_type() -> DataType {
+        list_type(T::data_type())
+    }
+
+    fn into_runtime<'b, 'c>(
+        self,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let values = self
+            .into_iter()
+            .map(|x| x.into_runtime(ectx))
+            .collect::<Result<_, _>>()?;
+        Ok(list_value(ectx, values))
+    }
+}
+
+impl<T: HostResult, E: Display> HostResult for Result<T, E> {
+    fn data_type() -> DataType {
+        T::data_type()
+    }
+
+    fn into_runtime<'b, 'c>(
+        self,
+        ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        match self {
+            Ok(v) => v.into_runtime(ectx),
+            Err(e) => Err(RuntimeError::Custom(e.to_string())),
+        }
+    }
+}
+
+/// Typeck for a host function `name` taking arguments of types `expected`
+/// and returning `ret`, as generated by `#[xl_host]`.
+pub fn typeck_args(
+    name: &str,
+    expected: &[DataType],
+    params: &[DataType],
+    ret: DataType,
+) -> Result<DataType, TypeError> {
+    if params.len() != expected.len() {
+        return Err(TypeError::Custom(format!(
+            "${} expects {} params, got {}",
+            name,
+            expected.len(),
+            params.len()
+        )));
+    }
+    if params.contains(&DataType::Divergent) {
+        return Ok(DataType::Divergent);
+    }
+    for (i, (want, got)) in expected.iter().zip(params).enumerate() {
+        let accepted = want == got
+            || *got == DataType::Dynamic
+            // `~` is the empty list.
+            || (*got == DataType::Empty && is_list_type(want));
+        if !accepted {
+            return Err(TypeError::Custom(format!(
+                "${} expects {} for param {}, got {}",
+                name,
+                want,
+                i + 1,
+                got
+            )));
+        }
+    }
+    Ok(ret)
+}
@@ -0,0 +1,68 @@
+use crate::engine::Engine;
+use crate::error::{Error, RuntimeError};
+use crate::xl_host;
+
+/// Clamps `x` to `[lo, hi]`.
+#[xl_host("clamp")]
+fn clamp(x: i64, lo: i64, hi: i64) -> i64 {
+    x.max(lo).min(hi)
+}
+
+#[xl_host("scale_all")]
+fn scale_all(xs: Vec<f64>, factor: f64) -> Vec<f64> {
+    xs.into_iter().map(|x| x * factor).collect()
+}
+
+#[xl_host("parse_int")]
+fn parse_int(s: String) -> Result<i64, std::num::ParseIntError> {
+    s.parse()
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.add_host(ClampOp::NAME.into(), Box::new(ClampOp));
+    engine.add_host(ScaleAllOp::NAME.into(), Box::new(ScaleAllOp));
+    engine.add_host(ParseIntOp::NAME.into(), Box::new(ParseIntOp));
+    engine
+}
+
+#[test]
+fn test_xl_host() {
+    let engine = engine();
+    assert_eq!(engine.eval_str("($clamp 12 0 10)").unwrap(), "10");
+    assert_eq!(
+        engine.eval_str("($clamp (hi 5) (lo 1) (x 3))").unwrap(),
+        "3"
+    );
+    assert_eq!(
+        engine
+            .eval_str("($list_head ($scale_all ($list_push 1.5 ~) 2.0))")
+            .unwrap(),
+        "3.0"
+    );
+    assert_eq!(engine.eval_str("($scale_all ~ 2.0)").unwrap(), "~");
+    assert_eq!(engine.eval_str(r#"($parse_int "42")"#).unwrap(), "42");
+    match engine.eval_str(r#"($parse_int "x")"#) {
+        Err(Error::Runtime(RuntimeError::Custom(_))) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_xl_host_typeck() {
+    let engine = engine();
+    match engine.eval_str("($clamp 1.5 0 10)") {
+        Err(Error::Type(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert!(engine.eval_str("($clamp 1 2 3 4)").is_err());
+    assert!(engine.eval_str(r#"($parse_int 1)"#).is_err());
+
+    let info = engine
+        .describe_hosts()
+        .into_iter()
+        .find(|h| h.name == "clamp")
+        .unwrap();
+    assert_eq!(info.signature.as_deref(), Some("$clamp(x, lo, hi)"));
+    assert_eq!(info.doc.as_deref(), Some("Clamps `x` to `[lo, hi]`."));
+}
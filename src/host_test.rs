@@ -0,0 +1,574 @@
+use crate::ast::*;
+use crate::builtin::ValueType;
+use crate::corelib::{value_hash, HostGroup, HostManager, List, Profile};
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{verify_hosts, HostFunction, HostSetDiff, Param, Signature};
+use crate::parser::parse_expr;
+use crate::typeck::*;
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::Hasher;
+use std::rc::Rc;
+
+fn eval_float(src: &str) -> f64 {
+    let hm = HostManager::new();
+    let e = parse_expr(src).unwrap();
+
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_all());
+    assert_eq!(
+        check_expr(&e, &mut trs).unwrap(),
+        DataType::Value(ValueType::Float)
+    );
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    match eval_expr(&e, &mut ectx).unwrap() {
+        RuntimeValue::Float(v) => v,
+        v => panic!("unexpected value: {:?}", v),
+    }
+}
+
+#[test]
+fn test_default_params() {
+    assert_eq!(eval_float("($round 2.71828)"), 3.0);
+    assert_eq!(eval_float("($round 2.71828 2)"), 2.72);
+
+    let hm = HostManager::new();
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_all());
+    assert!(check_expr(&parse_expr("($round 1.0 2 3)").unwrap(), &mut trs).is_err());
+}
+
+/// Contexts holding only owned hosts do not borrow anything but the AST, so
+/// they can be created in one place and moved elsewhere.
+fn owned_contexts<'b>() -> (TypeResolveState<'static>, EvalContext<'b, 'static>) {
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts_owned(HostManager::new().into_owned_hosts());
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts_owned(HostManager::new().into_owned_hosts());
+    (trs, ectx)
+}
+
+#[test]
+fn test_owned_hosts() {
+    let e = parse_expr("($if ($lt 1 2) ($add 40 2) 0)").unwrap();
+    let (mut trs, mut ectx) = owned_contexts();
+    assert_eq!(
+        check_expr(&e, &mut trs).unwrap(),
+        DataType::Value(ValueType::Int)
+    );
+    match eval_expr(&e, &mut ectx).unwrap() {
+        RuntimeValue::Int(42) => {}
+        v => panic!("unexpected value: {:?}", v),
+    }
+}
+
+/// `($version n)`: an opaque custom value ordered by `n`.
+#[derive(Debug)]
+struct VersionOp;
+
+#[derive(Debug)]
+struct Version(i64);
+
+#[derive(Debug)]
+struct VersionType;
+
+impl CustomDataType for VersionType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<VersionType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl CustomValue for Version {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn compare<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<Ordering>, RuntimeError> {
+        Ok(other
+            .as_any()
+            .downcast_ref::<Version>()
+            .map(|o| self.0.cmp(&o.0)))
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl HostFunction for VersionOp {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Value(ValueType::Int)] => {
+                Ok(DataType::Custom(Rc::new(Box::new(VersionType))))
+            }
+            _ => Err(TypeError::Custom("expecting an int".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Int(v) => Ok(RuntimeValue::Custom(CustomValueBox::new(Box::new(
+                Version(v),
+            )))),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_custom_value_hooks() {
+    let mut engine = Engine::new();
+    engine.add_host("version".into(), Box::new(VersionOp));
+    let eval = |src: &str| engine.eval_str(src).unwrap();
+
+    assert_eq!(eval("($version 3)"), "v3");
+    assert_eq!(eval("($lt ($version 2) ($version 10))"), "true");
+    assert_eq!(eval("($ge ($version 2) ($version 10))"), "false");
+    // `$eq` falls back to `compare`.
+    assert_eq!(eval("($eq ($version 7) ($version 7))"), "true");
+    assert_eq!(
+        eval("($lt ($list_push ($version 1) ~) ($list_push ($version 2) ~))"),
+        "true"
+    );
+    assert!(engine.eval_str("($lt ($version 1) 2)").is_err());
+    assert!(engine.eval_str("($and ($version 1) ($version 2))").is_err());
+}
+
+#[test]
+fn test_list_ordering_and_hash() {
+    let engine = Engine::new();
+    let eval = |src: &str| engine.eval_str(src).unwrap();
+    assert_eq!(eval("($lt ~ ($list_push 1 ~))"), "true");
+    assert_eq!(
+        eval("($lt ($list_push 1 ($list_push 2 ~)) ($list_push 1 ($list_push 3 ~)))"),
+        "true"
+    );
+    assert_eq!(eval("($le ($list_push 1 ~) ($list_push 1 ~))"), "true");
+    assert_eq!(
+        eval("($gt ($list_push 2 ~) ($list_push 1 ($list_push 9 ~)))"),
+        "true"
+    );
+
+    let hashes = engine
+        .eval_with(
+            &engine
+                .parse(
+                    "($list_push ($list_push 1 ($list_push 2 ~)) \
+                     ($list_push ($list_push 1 ($list_push 2 ~)) \
+                     ($list_push ($list_push 2 ($list_push 1 ~)) ~)))",
+                )
+                .unwrap(),
+            |v, ectx| {
+                let list = match v {
+                    RuntimeValue::Custom(cv) => cv,
+                    v => panic!("unexpected value: {:?}", v),
+                };
+                let mut out = Vec::new();
+                for item in list
+                    .inner
+                    .as_any()
+                    .downcast_ref::<List>()
+                    .unwrap()
+                    .values(ectx)?
+                {
+                    let mut state = DefaultHasher::new();
+                    value_hash(&item, &mut state, ectx)?;
+                    out.push(state.finish());
+                }
+                Ok(out)
+            },
+        )
+        .unwrap();
+    assert_eq!(hashes[0], hashes[1]);
+    assert_ne!(hashes[0], hashes[2]);
+}
+
+struct UserDb {
+    ages: BTreeMap<i64, i64>,
+}
+
+/// `($lookup_age id)`: reads a user's age from the embedder's `UserDb`.
+#[derive(Debug)]
+struct LookupAgeOp;
+
+impl HostFunction for LookupAgeOp {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Value(ValueType::Int)] => Ok(DataType::Value(ValueType::Int)),
+            _ => Err(TypeError::Custom("expecting a user id".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let id = match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Int(v) => v,
+            _ => unreachable!(),
+        };
+        let db = ectx
+            .host_state::<UserDb>()
+            .ok_or_else(|| RuntimeError::Custom("no user database".into()))?;
+        match db.ages.get(&id) {
+            Some(&age) => Ok(RuntimeValue::Int(age)),
+            None => Err(RuntimeError::Custom(format!("no such user: {}", id))),
+        }
+    }
+}
+
+#[test]
+fn test_host_state() {
+    let mut engine = Engine::new();
+    engine.add_host("lookup_age".into(), Box::new(LookupAgeOp));
+    assert!(engine.eval_str("($lookup_age 1)").is_err());
+
+    let mut ages = BTreeMap::new();
+    ages.insert(1, 30);
+    ages.insert(2, 12);
+    assert!(engine.host_state_mut().insert(UserDb { ages }).is_none());
+    assert_eq!(
+        engine
+            .eval_str("($add ($lookup_age 1) ($lookup_age 2))")
+            .unwrap(),
+        "42"
+    );
+    // The state is handed back after each evaluation, even a failed one.
+    assert!(engine.eval_str("($lookup_age 3)").is_err());
+    engine
+        .host_state_mut()
+        .get_mut::<UserDb>()
+        .unwrap()
+        .ages
+        .insert(3, 7);
+    assert_eq!(engine.eval_str("($lookup_age 3)").unwrap(), "7");
+}
+
+/// Returns its int regardless of the argument.
+#[derive(Debug)]
+struct ConstIntOp(i64);
+impl HostFunction for ConstIntOp {
+    fn typeck(&self, _params: &[DataType]) -> Result<DataType, TypeError> {
+        Ok(DataType::Value(ValueType::Int))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        _ectx: &mut EvalContext<'b, 'c>,
+        _params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        Ok(RuntimeValue::Int(self.0))
+    }
+}
+
+#[test]
+fn test_host_namespaces() {
+    let mut engine = Engine::new();
+    engine.add_host_in("geo", "lookup", Box::new(ConstIntOp(1)));
+    engine.add_host_in("billing", "lookup", Box::new(ConstIntOp(20)));
+    assert_eq!(
+        engine
+            .eval_str("($add ($geo.lookup 0) ($billing.lookup 0))")
+            .unwrap(),
+        "21"
+    );
+    assert!(engine.eval_str("($lookup 0)").is_err());
+
+    engine.alias_host("lookup", "billing.lookup");
+    engine.alias_host("plus", "add");
+    assert_eq!(engine.eval_str("($plus ($lookup 0) 1)").unwrap(), "21");
+    // Aliases follow their targets' availability.
+    engine.deny_host("add");
+    assert!(engine.eval_str("($plus 1 1)").is_err());
+
+    let hm = HostManager::new();
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts_in("core", hm.get_binops());
+    assert!(trs.alias_host("sum", "core.add"));
+    assert!(!trs.alias_host("product", "mul"));
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts_in("core", hm.get_binops());
+    assert!(ectx.alias_host("sum", "core.add"));
+    verify_hosts(&trs, &ectx).unwrap();
+
+    let e = parse_expr("($sum ($core.mul 2 3) 1)").unwrap();
+    assert_eq!(
+        check_expr(&e, &mut trs).unwrap(),
+        DataType::Value(ValueType::Int)
+    );
+    match eval_expr(&e, &mut ectx).unwrap() {
+        RuntimeValue::Int(v) => assert_eq!(v, 7),
+        v => panic!("unexpected value: {:?}", v),
+    }
+}
+
+#[test]
+fn test_host_alias_collisions() {
+    let mut engine = Engine::new();
+    engine.add_host("lookup".into(), Box::new(ConstIntOp(1)));
+    assert!(!engine.alias_host("sub", "add"));
+    assert!(!engine.alias_host("getenv", "add"));
+    assert!(!engine.alias_host("lookup", "add"));
+    assert!(engine.alias_host("plus", "add"));
+    assert!(!engine.alias_host("plus", "mul"));
+    assert_eq!(engine.eval_str("($sub 3 1)").unwrap(), "2");
+    assert_eq!(engine.eval_str("($plus 3 1)").unwrap(), "4");
+
+    engine.alias_host("minus", "sub");
+    engine.add_host("minus".into(), Box::new(ConstIntOp(7)));
+    assert_eq!(engine.eval_str("($minus 3)").unwrap(), "7");
+
+    let hm = HostManager::new();
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_binops());
+    assert!(!trs.alias_host("sub", "add"));
+    assert!(trs.alias_host("plus", "add"));
+    assert!(!trs.alias_host("plus", "mul"));
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_binops());
+    assert!(!ectx.alias_host("sub", "add"));
+    assert!(ectx.alias_host("plus", "add"));
+    assert!(!ectx.alias_host("plus", "mul"));
+}
+
+/// A documented host function with a fixed parameter list.
+#[derive(Debug)]
+struct LookupOp;
+impl HostFunction for LookupOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("id")]))
+    }
+
+    fn doc(&self) -> Option<&str> {
+        Some("Looks up a customer by `id`.")
+    }
+
+    fn typeck(&self, _params: &[DataType]) -> Result<DataType, TypeError> {
+        Ok(DataType::Value(ValueType::Int))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        _ectx: &mut EvalContext<'b, 'c>,
+        _params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        Ok(RuntimeValue::Int(0))
+    }
+}
+
+#[test]
+fn test_describe_hosts() {
+    let mut hm = HostManager::new();
+    let all = hm.describe_all();
+    assert_eq!(all.len(), hm.get_all().count());
+    assert!(all.iter().all(|info| info.doc.is_some()), "{:?}", all);
+    let round = all.iter().find(|info| info.name == "round").unwrap();
+    assert_eq!(round.group, "math");
+    assert_eq!(round.signature.as_deref(), Some("$round(x, digits = 0)"));
+    hm.deny("round");
+    assert!(hm.describe_all().iter().all(|info| info.name != "round"));
+
+    let mut engine = Engine::new();
+    engine.add_host_in("crm", "lookup", Box::new(LookupOp));
+    engine.alias_host("lookup", "crm.lookup");
+    engine.alias_host("plus", "add");
+    let hosts = engine.describe_hosts();
+    let find = |name: &str| hosts.iter().find(|info| info.name == name).unwrap();
+    assert_eq!(find("crm.lookup").group, "io");
+    assert_eq!(
+        find("crm.lookup").doc.as_deref(),
+        Some("Looks up a customer by `id`.")
+    );
+    assert_eq!(find("lookup").signature.as_deref(), Some("$lookup(id)"));
+    assert_eq!(find("plus").group, "arithmetic");
+    assert_eq!(find("plus").doc, find("add").doc);
+}
+
+#[test]
+fn test_host_set_diff() {
+    let hm = HostManager::new();
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_binops().chain(hm.get_ifop()));
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(
+        hm.get_binops()
+            .chain(hm.get_math_ops().filter(|(k, _)| k == "round")),
+    );
+
+    let diff = HostSetDiff::between(&trs, &ectx);
+    assert_eq!(
+        diff.typeck_only,
+        std::iter::once("if".to_string()).collect()
+    );
+    assert_eq!(
+        diff.eval_only,
+        std::iter::once("round".to_string()).collect()
+    );
+    match verify_hosts(&trs, &ectx) {
+        Err(RuntimeError::HostSetMismatch(d)) => assert_eq!(d, diff),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert_eq!(diff.to_string(), "typeck only: $if; eval only: $round");
+
+    ectx.add_hosts(hm.get_ifop());
+    trs.add_hosts(hm.get_math_ops().filter(|(k, _)| k == "round"));
+    assert!(HostSetDiff::between(&trs, &ectx).is_empty());
+    assert!(verify_hosts(&trs, &ectx).is_ok());
+}
+
+#[test]
+fn test_profiles() {
+    let names = |hm: &HostManager| -> Vec<String> { hm.get_all().map(|(k, _)| k).collect() };
+
+    let mut hm = HostManager::with_profile(Profile::PureMath);
+    let pure = names(&hm);
+    assert!(pure.contains(&"add".to_string()) && pure.contains(&"round".to_string()));
+    assert!(!pure.contains(&"list_push".to_string()));
+    hm.deny("div");
+    assert!(!names(&hm).contains(&"div".to_string()));
+    let owned: Vec<String> = hm.into_owned_hosts().into_iter().map(|(k, _)| k).collect();
+    assert_eq!(owned.len(), pure.len() - 1);
+
+    assert_eq!(
+        Profile::from_name("data-transform"),
+        Some(Profile::DataTransform)
+    );
+    assert_eq!(Profile::from_name("nope"), None);
+    let hm = HostManager::with_profile(Profile::DataTransform);
+    assert!(names(&hm).contains(&"memo".to_string()));
+    let full = Profile::FullIo.groups();
+    assert!(full.contains(&HostGroup::Io) && !full.contains(&HostGroup::Eval));
+
+    let mut engine = Engine::new();
+    engine.add_host("native_round".into(), Box::new(crate::corelib::RoundOp));
+    assert_eq!(engine.eval_str("($native_round 2.4)").unwrap(), "2.0");
+    engine.set_profile(Profile::DataTransform);
+    assert!(engine.eval_str("($native_round 2.4)").is_err());
+    assert_eq!(
+        engine.eval_str("($list_head ($list_push 1 ~))").unwrap(),
+        "1"
+    );
+    engine.set_profile(Profile::PureMath);
+    assert!(engine.eval_str("($list_head ($list_push 1 ~))").is_err());
+
+    engine.set_profile(Profile::FullIo);
+    engine.deny_host("native_round");
+    engine.deny_host("add");
+    assert!(engine.eval_str("($native_round 2.4)").is_err());
+    assert!(engine.eval_str("($add 1 2)").is_err());
+    assert_eq!(engine.eval_str("($sub 3 2)").unwrap(), "1");
+}
+
+/// `($field i)`: reads field `i` of a loosely-typed record, like a host
+/// function parsing JSON would, so its type is only known at runtime.
+#[derive(Debug)]
+struct FieldOp;
+
+impl HostFunction for FieldOp {
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Value(ValueType::Int)] => Ok(DataType::Dynamic),
+            _ => Err(TypeError::Custom("expecting a field index".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        match params.next().unwrap().eval(ectx)? {
+            RuntimeValue::Int(0) => Ok(RuntimeValue::Int(30)),
+            RuntimeValue::Int(1) => Ok(RuntimeValue::Bool(true)),
+            RuntimeValue::Int(_) => Ok(RuntimeValue::Float(1.5)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_dynamic_type() {
+    let mut engine = Engine::new();
+    engine.add_host("field".into(), Box::new(FieldOp));
+    assert_eq!(
+        engine.infer_type("($field 0)").unwrap(),
+        TypeDescription::Dynamic
+    );
+    assert_eq!(
+        engine.infer_type("($add ($field 0) 1)").unwrap(),
+        TypeDescription::Dynamic
+    );
+    assert_eq!(
+        engine.infer_type("(the int ($field 0))").unwrap(),
+        TypeDescription::Int
+    );
+    assert_eq!(
+        engine.infer_type("(the dyn 1)").unwrap(),
+        TypeDescription::Dynamic
+    );
+    assert_eq!(
+        engine.eval_str("($mul (the int ($field 0)) 2)").unwrap(),
+        "60"
+    );
+    assert_eq!(
+        engine.eval_str("($if ($field 1) ($field 2) 0)").unwrap(),
+        "1.5"
+    );
+    assert_eq!(
+        engine
+            .eval_owned("(the (list int) ($list_push ($field 0) ~))")
+            .unwrap(),
+        OwnedValue::List(vec![OwnedValue::Int(30)])
+    );
+    assert_eq!(
+        engine
+            .infer_call_type(r"(\x ($add x 1))", &[TypeDescription::Dynamic])
+            .unwrap(),
+        TypeDescription::Dynamic
+    );
+    let int = DataType::Value(ValueType::Int);
+    assert!(engine.check_against("($field 1)", &int).is_ok());
+
+    // The runtime type is checked where a dynamic value enters typed code,
+    // and by host functions using it.
+    match engine.eval_str("(the int ($field 1))") {
+        Err(Error::Runtime(RuntimeError::TypeMismatch(msg))) => {
+            assert_eq!(msg, "expecting int, found true")
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match engine.eval_str("($add ($field 1) 1)") {
+        Err(Error::Runtime(RuntimeError::TypeMismatch(_))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Static types are still checked.
+    assert!(matches!(
+        engine.eval_str("($add (the bool ($field 1)) 1)"),
+        Err(Error::Type(_))
+    ));
+    assert!(matches!(
+        engine.eval_str("(the int true)"),
+        Err(Error::Type(_))
+    ));
+}
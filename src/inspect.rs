@@ -0,0 +1,117 @@
+//! Recording what every part of a program evaluated to, for inspecting a
+//! run after the fact.
+//!
+//! `Engine::eval_recorded` runs a program while recording, for each
+//! subexpression of its source, every value it produced, so that a
+//! debugger UI can show the value of the subexpression under the cursor
+//! or step through them in the order they were computed. A subexpression
+//! evaluated several times, like the body of a function called in a loop,
+//! has a value for each time; one never evaluated has none.
+//!
+//! Values are recorded as in audit logs, with those that have no
+//! serializable form as displayed. Recordings serialize with serde, e.g. to
+//! JSON for a UI. They grow with every step, so keep a step limit on engines
+//! recording untrusted programs.
+
+use crate::ast::Span;
+use crate::audit::AuditValue;
+use crate::engine::{Engine, RunOptions};
+use crate::error::*;
+use crate::parser::parse_expr_with_spans;
+
+/// A value the subexpression at `span` evaluated to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedValue {
+    pub span: Span,
+    /// Number of steps evaluated when the value was produced, which orders
+    /// the values of a run in time.
+    pub steps: u64,
+    pub value: AuditValue,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Recording {
+    source: String,
+    result: Result<AuditValue, String>,
+    values: Vec<RecordedValue>,
+}
+
+impl Recording {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// What the program evaluated to, or the runtime error it failed with.
+    /// Values recorded before a failure are kept.
+    pub fn result(&self) -> Result<&AuditValue, &str> {
+        match self.result {
+            Ok(ref v) => Ok(v),
+            Err(ref e) => Err(e),
+        }
+    }
+
+    /// Every value recorded, in the order they were produced.
+    pub fn values(&self) -> &[RecordedValue] {
+        &self.values
+    }
+
+    /// The values the subexpression at `span` evaluated to, in order.
+    pub fn values_at(&self, span: Span) -> Vec<&AuditValue> {
+        self.values
+            .iter()
+            .filter(|v| v.span == span)
+            .map(|v| &v.value)
+            .collect()
+    }
+
+    /// The span of the innermost subexpression with recorded values that
+    /// contains the byte `offset` of the source.
+    pub fn innermost(&self, offset: usize) -> Option<Span> {
+        self.values
+            .iter()
+            .map(|v| v.span)
+            .filter(|span| span.start <= offset && offset < span.end)
+            .min_by_key(|span| span.end - span.start)
+    }
+}
+
+impl Engine {
+    /// Evaluates `source`, recording the values of its subexpressions. Fails
+    /// if it does not parse or check; a runtime error ends up in the
+    /// recording instead.
+    ///
+    /// The program is run as written, without the optimizations
+    /// `Engine::set_opt_level` enables, so that every value belongs to a
+    /// part of the source.
+    pub fn eval_recorded(&self, source: &str) -> Result<Recording, Error> {
+        let (e, spans) = parse_expr_with_spans(source, &self.parse_options())?;
+        let ty = self.check(&e)?;
+        let mut steps = Vec::new();
+        let opts = RunOptions {
+            recording: Some(&mut steps),
+            ..RunOptions::default()
+        };
+        let result = match self.run_in(&e, &ty, self.definitions(), opts, |v, _| {
+            Ok(AuditValue::from_value(&v))
+        }) {
+            Ok(v) => Ok(v),
+            Err(Error::Runtime(e)) => Err(e.to_string()),
+            Err(e) => return Err(e),
+        };
+        let values = steps
+            .into_iter()
+            .filter_map(|step| {
+                Some(RecordedValue {
+                    span: spans.get_at(step.body)?,
+                    steps: step.steps,
+                    value: step.value,
+                })
+            })
+            .collect();
+        Ok(Recording {
+            source: source.to_string(),
+            result,
+            values,
+        })
+    }
+}
@@ -0,0 +1,64 @@
+use crate::ast::Span;
+use crate::audit::AuditValue;
+use crate::engine::Engine;
+use crate::inspect::Recording;
+
+fn span_of(source: &str, part: &str) -> Span {
+    let start = source.find(part).unwrap();
+    Span {
+        start,
+        end: start + part.len(),
+    }
+}
+
+#[test]
+fn test_eval_recorded() {
+    let engine = Engine::new();
+    let source = r"((\f ($add (f 1) (f 2))) (\x ($mul x 10)))";
+    let recording = engine.eval_recorded(source).unwrap();
+    assert_eq!(recording.result(), Ok(&AuditValue::Int(30)));
+    assert_eq!(
+        recording.values_at(span_of(source, "($mul x 10)")),
+        vec![&AuditValue::Int(10), &AuditValue::Int(20)]
+    );
+    assert_eq!(
+        recording.values_at(span_of(source, "(f 2)")),
+        vec![&AuditValue::Int(20)]
+    );
+    assert_eq!(
+        recording.values_at(Span {
+            start: 0,
+            end: source.len()
+        }),
+        vec![&AuditValue::Int(30)]
+    );
+
+    let values = recording.values();
+    assert!(values.windows(2).all(|w| w[0].steps <= w[1].steps));
+    assert_eq!(values.last().unwrap().value, AuditValue::Int(30));
+
+    let offset = source.find("10").unwrap();
+    assert_eq!(recording.innermost(offset), Some(span_of(source, "10")));
+    assert_eq!(recording.innermost(source.len()), None);
+}
+
+#[test]
+fn test_eval_recorded_failure() {
+    let engine = Engine::new();
+    let source = r"($add ($mul 2 3) ($div 1 0))";
+    let recording = engine.eval_recorded(source).unwrap();
+    assert!(recording.result().is_err());
+    assert_eq!(
+        recording.values_at(span_of(source, "($mul 2 3)")),
+        vec![&AuditValue::Int(6)]
+    );
+    assert!(recording
+        .values_at(span_of(source, "($div 1 0)"))
+        .is_empty());
+
+    let json = serde_json::to_string(&recording).unwrap();
+    let back: Recording = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, recording);
+
+    assert!(engine.eval_recorded("($add 1 true)").is_err());
+}
@@ -0,0 +1,199 @@
+//! Journaling of the calls an `Execution` makes to host functions with side
+//! effects, so that a snapshot of it can be resumed without making them
+//! again.
+//!
+//! Everything an execution has in progress, its environment and pending
+//! thunks, follows from its expression and from what its host functions
+//! returned. Those in pure groups (see `HostGroup::is_pure`) return the
+//! same when called again; calls to the others are journaled. Entries are
+//! appended when a call starts, so nested calls come after the call that
+//! made them.
+//!
+//! Replaying a journal evaluates the expression again, answering each
+//! journaled call that returned from its entry instead of calling the host
+//! function. The arguments the call evaluated are evaluated again, in the
+//! same order, so that thunks they share with the rest of the expression
+//! end up as they were. Calls that had not returned, because the execution
+//! was suspended during them, are made again.
+
+use crate::bytes::{as_bytes, bytes_value};
+use crate::corelib::{as_str, list_value, str_value, List};
+use crate::error::RuntimeError;
+use crate::eval::{EvalContext, LazyValue, RuntimeValue};
+use crate::remote::WireValue;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+/// One call to a host function with side effects.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// Name of the host function, without the leading `$`.
+    pub host: String,
+    /// Indices of the arguments the call evaluated, in the order it did,
+    /// each with the number of entries made during the call before.
+    pub forced: Vec<(u32, u32)>,
+    /// Number of entries made during the call.
+    pub nested: u32,
+    /// What the call returned, or `None` if it had not returned.
+    pub result: Option<WireValue>,
+}
+
+/// How `Journal::begin` wants a call made.
+pub(crate) enum Call {
+    /// By calling the host function, then `Journal::end` with the index.
+    Live(usize),
+    /// From the entry at the index, with `replay`.
+    Replay(usize, JournalEntry),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    /// Host functions whose calls are not journaled.
+    pure: BTreeSet<String>,
+    entries: Vec<JournalEntry>,
+    /// Index of the entry of the next call, `entries.len()` once the
+    /// journal has been replayed.
+    next: usize,
+    /// Calls made live that have not returned, with the value cells of
+    /// their arguments.
+    open: Vec<(usize, Vec<usize>)>,
+}
+
+impl Journal {
+    pub fn new(pure: BTreeSet<String>, entries: Vec<JournalEntry>) -> Rc<RefCell<Journal>> {
+        Rc::new(RefCell::new(Journal {
+            pure,
+            entries,
+            ..Journal::default()
+        }))
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub fn journals(&self, host: &str) -> bool {
+        !self.pure.contains(host)
+    }
+
+    pub fn begin(&mut self, host: &str, args: &[LazyValue]) -> Result<Call, RuntimeError> {
+        let index = self.next;
+        match self.entries.get_mut(index) {
+            Some(entry) if entry.host != host => {
+                return Err(RuntimeError::Custom(format!(
+                    "execution does not replay its journal: expecting ${}, called ${}",
+                    entry.host, host
+                )))
+            }
+            Some(entry) if entry.result.is_some() => return Ok(Call::Replay(index, entry.clone())),
+            Some(entry) => entry.forced.clear(),
+            None => self.entries.push(JournalEntry {
+                host: host.to_string(),
+                forced: vec![],
+                nested: 0,
+                result: None,
+            }),
+        }
+        self.next = index + 1;
+        self.open
+            .push((index, args.iter().map(LazyValue::cell_id).collect()));
+        Ok(Call::Live(index))
+    }
+
+    /// Records what the live call at `index` returned, `None` if it failed.
+    pub fn end(&mut self, index: usize, result: Option<WireValue>) {
+        self.open.pop();
+        let entry = &mut self.entries[index];
+        entry.result = result;
+        entry.nested = (self.next - index - 1) as u32;
+    }
+
+    /// Records that the value cell `cell` is being evaluated, for the live
+    /// calls it is an argument of.
+    pub fn note_forced(&mut self, cell: usize) {
+        for (index, cells) in &self.open {
+            for (i, _) in cells.iter().enumerate().filter(|(_, c)| **c == cell) {
+                let made = (self.next - index - 1) as u32;
+                self.entries[*index].forced.push((i as u32, made));
+            }
+        }
+    }
+}
+
+/// Makes the call at `index` from its entry.
+pub(crate) fn replay<'b, 'c>(
+    journal: &Rc<RefCell<Journal>>,
+    index: usize,
+    entry: JournalEntry,
+    args: &[LazyValue<'b>],
+    ectx: &mut EvalContext<'b, 'c>,
+) -> Result<RuntimeValue<'b>, RuntimeError> {
+    let corrupt = || RuntimeError::Custom(format!("corrupt journal entry for ${}", entry.host));
+    for &(arg, made) in &entry.forced {
+        journal.borrow_mut().next = index + 1 + made as usize;
+        args.get(arg as usize).ok_or_else(corrupt)?.eval(ectx)?;
+    }
+    journal.borrow_mut().next = index + 1 + entry.nested as usize;
+    restored_value(entry.result.as_ref().ok_or_else(corrupt)?, ectx)
+}
+
+/// The journaled form of `v`, `WireValue::Opaque` if it has none. Lists
+/// have one only if their elements have all been evaluated: evaluating them
+/// here would make calls that replaying does not.
+pub(crate) fn saved_value<'b, 'c>(
+    v: &RuntimeValue<'b>,
+    ectx: &mut EvalContext<'b, 'c>,
+) -> WireValue {
+    match *v {
+        RuntimeValue::Empty => WireValue::Empty,
+        RuntimeValue::Int(x) => WireValue::Int(x),
+        RuntimeValue::Float(x) => WireValue::Float(x),
+        RuntimeValue::Bool(x) => WireValue::Bool(x),
+        RuntimeValue::Custom(ref cv) => {
+            if let Some(s) = as_str(v) {
+                return WireValue::String(s.to_string());
+            }
+            if let Some(b) = as_bytes(v) {
+                return WireValue::Bytes(b.to_vec());
+            }
+            match cv.inner.as_any().downcast_ref::<List>() {
+                Some(list) => match list.evaluated(ectx) {
+                    Some(items) => {
+                        WireValue::List(items.iter().map(|x| saved_value(x, ectx)).collect())
+                    }
+                    None => WireValue::Opaque(v.to_string()),
+                },
+                None => WireValue::Opaque(v.to_string()),
+            }
+        }
+        ref v => WireValue::Opaque(v.to_string()),
+    }
+}
+
+fn restored_value<'b, 'c>(
+    v: &WireValue,
+    ectx: &mut EvalContext<'b, 'c>,
+) -> Result<RuntimeValue<'b>, RuntimeError> {
+    Ok(match *v {
+        WireValue::Empty => RuntimeValue::Empty,
+        WireValue::Int(x) => RuntimeValue::Int(x),
+        WireValue::Float(x) => RuntimeValue::Float(x),
+        WireValue::Bool(x) => RuntimeValue::Bool(x),
+        WireValue::String(ref s) => str_value(s),
+        WireValue::Bytes(ref b) => bytes_value(b),
+        WireValue::List(ref items) => {
+            let mut values = Vec::new();
+            for x in items {
+                values.push(restored_value(x, ectx)?);
+            }
+            list_value(ectx, values)
+        }
+        WireValue::Opaque(ref s) => {
+            return Err(RuntimeError::Custom(format!(
+                "journaled value {} cannot be restored",
+                s
+            )))
+        }
+    })
+}
@@ -0,0 +1,393 @@
+//! JSON documents as values, for scripts that transform payloads the host
+//! passes in.
+//!
+//! A `json` value is an immutable JSON document. Scripts read it with
+//! `$json_get`, inspect it with `$json_type`, convert leaves to x-lang
+//! values with `$json_to_int` and friends, and print it back with
+//! `$json_stringify`. Hosts pass documents in by defining them with
+//! `json_source`, or by returning `json_value` from their own host
+//! functions.
+
+use crate::ast::{fmt_string_literal, CustomDataType, DataType};
+use crate::builtin::ValueType;
+use crate::corelib::{as_str, str_value, string_type};
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use serde_json::Value;
+use std::any::Any;
+use std::fmt;
+use std::hash::Hasher;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct JsonType;
+
+impl CustomDataType for JsonType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<JsonType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "json")
+    }
+}
+
+pub fn json_type() -> DataType {
+    DataType::Custom(Rc::new(Box::new(JsonType)))
+}
+
+fn is_json_type(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Custom(ref inner) => inner.as_any().is::<JsonType>(),
+        DataType::Dynamic => true,
+        _ => false,
+    }
+}
+
+fn is_string_type(ty: &DataType) -> bool {
+    *ty == string_type() || *ty == DataType::Dynamic
+}
+
+/// A JSON document. Cloning shares it.
+#[derive(Debug, Clone)]
+pub struct Json {
+    value: Rc<Value>,
+}
+
+impl Json {
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+}
+
+impl CustomValue for Json {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn value_eq<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<bool>, RuntimeError> {
+        Ok(other
+            .as_any()
+            .downcast_ref::<Json>()
+            .map(|o| self.value == o.value))
+    }
+
+    fn hash<'b, 'c>(
+        &self,
+        state: &mut dyn Hasher,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<bool, RuntimeError> {
+        // Objects print their keys in order, so equal documents print the
+        // same.
+        state.write(self.value.to_string().as_bytes());
+        Ok(true)
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+/// Wraps `value` as a runtime value of type `json`.
+pub fn json_value<'b>(value: Value) -> RuntimeValue<'b> {
+    RuntimeValue::Custom(CustomValueBox::new(Box::new(Json {
+        value: Rc::new(value),
+    })))
+}
+
+/// Returns the document `v` holds if it is of type `json`.
+pub fn as_json<'a>(v: &'a RuntimeValue) -> Option<&'a Value> {
+    match *v {
+        RuntimeValue::Custom(ref cv) => cv.inner.as_any().downcast_ref::<Json>().map(|j| j.value()),
+        _ => None,
+    }
+}
+
+/// Returns source text evaluating to `value`, e.g. for
+/// `Engine::define("payload", &json_source(&v))`.
+pub fn json_source(value: &Value) -> String {
+    struct Literal(String);
+    impl fmt::Display for Literal {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            fmt_string_literal(&self.0, f)
+        }
+    }
+    format!("($json_parse {})", Literal(value.to_string()))
+}
+
+fn eval_json<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: LazyValue<'b>,
+) -> Result<Rc<Value>, RuntimeError> {
+    match v.eval(ectx)? {
+        RuntimeValue::Custom(ref cv) if cv.inner.as_any().is::<Json>() => Ok(cv
+            .inner
+            .as_any()
+            .downcast_ref::<Json>()
+            .unwrap()
+            .value
+            .clone()),
+        v => Err(RuntimeError::TypeMismatch(format!(
+            "expecting json, found {}",
+            v
+        ))),
+    }
+}
+
+fn eval_string<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: LazyValue<'b>,
+) -> Result<String, RuntimeError> {
+    let v = v.eval(ectx)?;
+    match as_str(&v) {
+        Some(s) => Ok(s.to_string()),
+        None => Err(RuntimeError::TypeMismatch(format!(
+            "expecting string, found {}",
+            v
+        ))),
+    }
+}
+
+/// `($json_parse text)`: parses a JSON document.
+#[derive(Debug)]
+pub struct JsonParseOp;
+impl HostFunction for JsonParseOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("text")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if is_string_type(ty) => Ok(json_type()),
+            _ => Err(TypeError::Custom("json_parse expects a string".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let text = eval_string(ectx, params.next().unwrap())?;
+        serde_json::from_str(&text)
+            .map(json_value)
+            .map_err(|e| RuntimeError::Custom(format!("invalid JSON: {}", e)))
+    }
+}
+
+/// `($json_get doc key)`: the member `key` (a string) of an object or the
+/// element at index `key` (an int) of an array. Anything missing is `null`,
+/// so lookups can be chained and checked once with `$json_type`.
+#[derive(Debug)]
+pub struct JsonGetOp;
+impl HostFunction for JsonGetOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("doc"),
+            Param::required("key"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        match *params {
+            [ref doc, ref key]
+                if is_json_type(doc)
+                    && (is_string_type(key) || *key == DataType::Value(ValueType::Int)) =>
+            {
+                Ok(json_type())
+            }
+            _ => Err(TypeError::Custom(
+                "json_get expects a json document and a string or int key".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let doc = eval_json(ectx, params.next().unwrap())?;
+        let key = params.next().unwrap().eval(ectx)?;
+        let member = match key {
+            RuntimeValue::Int(i) if i >= 0 => doc.get(i as usize),
+            RuntimeValue::Int(_) => None,
+            ref k => match as_str(k) {
+                Some(k) => doc.get(k),
+                None => {
+                    return Err(RuntimeError::TypeMismatch(format!(
+                        "expecting a string or int key, found {}",
+                        k
+                    )))
+                }
+            },
+        };
+        Ok(json_value(member.cloned().unwrap_or(Value::Null)))
+    }
+}
+
+/// `($json_type doc)`: one of `"null"`, `"bool"`, `"number"`, `"string"`,
+/// `"array"` and `"object"`.
+#[derive(Debug)]
+pub struct JsonTypeOp;
+impl HostFunction for JsonTypeOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("doc")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if is_json_type(ty) => Ok(string_type()),
+            _ => Err(TypeError::Custom(
+                "json_type expects a json document".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let doc = eval_json(ectx, params.next().unwrap())?;
+        Ok(str_value(match *doc {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }))
+    }
+}
+
+/// What `JsonToOp` converts a JSON leaf to.
+#[derive(Debug, Clone, Copy)]
+pub enum JsonLeaf {
+    Int,
+    Float,
+    Bool,
+    Str,
+}
+
+/// `($json_to_int doc)`, `$json_to_float`, `$json_to_bool` and
+/// `$json_to_str`: the value of a JSON leaf, which must have the matching
+/// JSON type. Any number converts to a float, but only integers in range
+/// convert to an int.
+#[derive(Debug)]
+pub struct JsonToOp {
+    pub leaf: JsonLeaf,
+}
+
+impl HostFunction for JsonToOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("doc")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if is_json_type(ty) => Ok(match self.leaf {
+                JsonLeaf::Int => DataType::Value(ValueType::Int),
+                JsonLeaf::Float => DataType::Value(ValueType::Float),
+                JsonLeaf::Bool => DataType::Value(ValueType::Bool),
+                JsonLeaf::Str => string_type(),
+            }),
+            _ => Err(TypeError::Custom("expecting a json document".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let doc = eval_json(ectx, params.next().unwrap())?;
+        let (v, expected) = match self.leaf {
+            JsonLeaf::Int => (doc.as_i64().map(RuntimeValue::Int), "an integer"),
+            JsonLeaf::Float => (doc.as_f64().map(RuntimeValue::Float), "a number"),
+            JsonLeaf::Bool => (doc.as_bool().map(RuntimeValue::Bool), "a bool"),
+            JsonLeaf::Str => (doc.as_str().map(str_value), "a string"),
+        };
+        v.ok_or_else(|| {
+            RuntimeError::TypeMismatch(format!("expecting {} in JSON, found {}", expected, doc))
+        })
+    }
+}
+
+/// `($json_stringify doc)`: the document as compact JSON text.
+#[derive(Debug)]
+pub struct JsonStringifyOp;
+impl HostFunction for JsonStringifyOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("doc")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [ref ty] if is_json_type(ty) => Ok(string_type()),
+            _ => Err(TypeError::Custom(
+                "json_stringify expects a json document".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let doc = eval_json(ectx, params.next().unwrap())?;
+        Ok(str_value(&doc.to_string()))
+    }
+}
+
+/// The host functions of `HostGroup::Json`.
+pub fn json_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![
+        ("json_parse", Arc::new(JsonParseOp)),
+        ("json_get", Arc::new(JsonGetOp)),
+        ("json_type", Arc::new(JsonTypeOp)),
+        (
+            "json_to_int",
+            Arc::new(JsonToOp {
+                leaf: JsonLeaf::Int,
+            }),
+        ),
+        (
+            "json_to_float",
+            Arc::new(JsonToOp {
+                leaf: JsonLeaf::Float,
+            }),
+        ),
+        (
+            "json_to_bool",
+            Arc::new(JsonToOp {
+                leaf: JsonLeaf::Bool,
+            }),
+        ),
+        (
+            "json_to_str",
+            Arc::new(JsonToOp {
+                leaf: JsonLeaf::Str,
+            }),
+        ),
+        ("json_stringify", Arc::new(JsonStringifyOp)),
+    ]
+}
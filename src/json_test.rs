@@ -0,0 +1,104 @@
+use crate::corelib::Profile;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::json::json_source;
+use crate::typeck::TypeDescription;
+
+fn payload_engine() -> Engine {
+    let mut engine = Engine::new();
+    let payload = serde_json::json!({
+        "user": { "name": "Ada \"A\"", "age": 36, "admin": false },
+        "scores": [1.5, 2, 3],
+        "note": null
+    });
+    engine.define("payload", &json_source(&payload)).unwrap();
+    engine
+}
+
+#[test]
+fn test_json_reads() {
+    let engine = payload_engine();
+    let eval = |s: &str| engine.eval_str(s).unwrap();
+    assert_eq!(
+        eval(r#"($json_to_str ($json_get ($json_get payload "user") "name"))"#),
+        r#""Ada \"A\"""#
+    );
+    assert_eq!(
+        eval(r#"($add ($json_to_int ($json_get ($json_get payload "user") "age")) 1)"#),
+        "37"
+    );
+    assert_eq!(
+        eval(r#"($json_to_float ($json_get ($json_get payload "scores") 1))"#),
+        "2.0"
+    );
+    assert_eq!(
+        eval(r#"($json_to_bool ($json_get ($json_get payload "user") "admin"))"#),
+        "false"
+    );
+    assert_eq!(
+        eval(r#"($json_type ($json_get payload "note"))"#),
+        r#""null""#
+    );
+    assert_eq!(
+        eval(r#"($json_type ($json_get ($json_get payload "missing") 3))"#),
+        r#""null""#
+    );
+    assert_eq!(
+        eval(r#"($json_type ($json_get payload "scores"))"#),
+        r#""array""#
+    );
+    assert_eq!(
+        eval(r#"($json_stringify ($json_get payload "scores"))"#),
+        r#""[1.5,2,3]""#
+    );
+    assert_eq!(
+        eval(r#"($eq ($json_get payload "scores") ($json_parse "[1.5, 2, 3]"))"#),
+        "true"
+    );
+    assert_eq!(eval(r#"($eq ($json_type payload) "object")"#), "true");
+    assert_eq!(
+        engine.infer_type(r#"($json_get payload "user")"#).unwrap(),
+        TypeDescription::Custom("json".into())
+    );
+}
+
+#[test]
+fn test_json_errors() {
+    let engine = payload_engine();
+    match engine.eval_str(r#"($json_to_int ($json_get payload "scores"))"#) {
+        Err(Error::Runtime(RuntimeError::TypeMismatch(msg))) => {
+            assert_eq!(msg, "expecting an integer in JSON, found [1.5,2,3]")
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match engine.eval_str(r#"($json_parse "{")"#) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert!(msg.starts_with("invalid JSON"), "{}", msg)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(matches!(
+        engine.eval_str(r#"($json_get payload true)"#),
+        Err(Error::Type(_))
+    ));
+    assert!(matches!(
+        engine.eval_str("($json_parse 1)"),
+        Err(Error::Type(_))
+    ));
+}
+
+#[test]
+fn test_json_profiles() {
+    let mut engine = payload_engine();
+    engine.set_profile(Profile::DataTransform);
+    assert_eq!(
+        engine
+            .eval_str(r#"($json_type ($json_parse "1"))"#)
+            .unwrap(),
+        r#""number""#
+    );
+    engine.set_profile(Profile::PureMath);
+    assert!(engine
+        .eval_str(r#"($json_type ($json_parse "1"))"#)
+        .is_err());
+}
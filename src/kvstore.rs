@@ -0,0 +1,356 @@
+//! Example embedding: scripting a toy key-value store.
+//!
+//! This module is meant to be read (and copied) as a template for embedding
+//! x-lang in an application. It shows the pieces most embedders need:
+//!
+//! - a *handle* type (`KvHandle`) that scripts obtain from one host function
+//!   and pass to others, with its own `CustomDataType` so typeck rejects
+//!   misuse;
+//! - *effectful* host functions that read and write state owned by the
+//!   embedder. Writes return the handle they were given, so a script orders
+//!   its effects by threading the handle through nested calls:
+//!   `($kv_get ($kv_put h 1 10) 1)` always sees the write;
+//! - *capability gating*: only the host functions a script is allowed to use
+//!   are registered, so a read-only script that tries to write is rejected
+//!   before it runs;
+//! - a *fuel* limit shared by all store operations;
+//! - *typed input and output*: inputs are bound as definitions and the
+//!   result type is checked before evaluation.
+//!
+//! The store maps `(namespace, key)` pairs to integers; a handle refers to
+//! one namespace.
+
+use crate::ast::{ConstExpr, CustomDataType, DataType};
+use crate::builtin::ValueType;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Contents of a store, keyed by `(namespace, key)`.
+pub type Entries = BTreeMap<(i64, i64), i64>;
+
+/// Which store operations a script may perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Capabilities {
+    pub fn read_only() -> Capabilities {
+        Capabilities {
+            read: true,
+            write: false,
+        }
+    }
+
+    pub fn read_write() -> Capabilities {
+        Capabilities {
+            read: true,
+            write: true,
+        }
+    }
+}
+
+/// A key-value store shared between the embedder and the host functions it
+/// registers.
+///
+/// Cloning a `KvStore` yields another reference to the same store.
+#[derive(Debug, Clone)]
+pub struct KvStore {
+    entries: Rc<RefCell<Entries>>,
+    fuel: Rc<Cell<u64>>,
+}
+
+impl Default for KvStore {
+    fn default() -> KvStore {
+        KvStore {
+            entries: Rc::new(RefCell::new(Entries::new())),
+            fuel: Rc::new(Cell::new(u64::MAX)),
+        }
+    }
+}
+
+impl KvStore {
+    /// Creates an empty store with unlimited fuel.
+    pub fn new() -> KvStore {
+        KvStore::default()
+    }
+
+    pub fn get(&self, ns: i64, key: i64) -> Option<i64> {
+        self.entries.borrow().get(&(ns, key)).cloned()
+    }
+
+    pub fn put(&self, ns: i64, key: i64, value: i64) {
+        self.entries.borrow_mut().insert((ns, key), value);
+    }
+
+    /// Returns a copy of the current contents.
+    pub fn entries(&self) -> Entries {
+        self.entries.borrow().clone()
+    }
+
+    /// Sets how many store operations scripts may perform from now on.
+    pub fn set_fuel(&self, fuel: u64) {
+        self.fuel.set(fuel);
+    }
+
+    pub fn fuel(&self) -> u64 {
+        self.fuel.get()
+    }
+
+    /// Registers `$kv_open` and the operations allowed by `caps` on `engine`.
+    ///
+    /// - `($kv_open ns)`: returns a handle to namespace `ns`.
+    /// - `($kv_get h key default=0)`: reads `key`, or returns `default`.
+    /// - `($kv_has h key)`: whether `key` is present.
+    /// - `($kv_put h key value)`: writes `key` and returns `h`.
+    /// - `($kv_delete h key)`: removes `key` and returns `h`.
+    pub fn register(&self, engine: &mut Engine, caps: Capabilities) {
+        engine.add_host("kv_open".into(), Box::new(OpenOp));
+        if caps.read {
+            engine.add_host("kv_get".into(), Box::new(self.op(OpKind::Get)));
+            engine.add_host("kv_has".into(), Box::new(self.op(OpKind::Has)));
+        }
+        if caps.write {
+            engine.add_host("kv_put".into(), Box::new(self.op(OpKind::Put)));
+            engine.add_host("kv_delete".into(), Box::new(self.op(OpKind::Delete)));
+        }
+    }
+
+    fn op(&self, kind: OpKind) -> StoreOp {
+        StoreOp {
+            kind,
+            store: self.clone(),
+        }
+    }
+
+    fn consume_fuel(&self) -> Result<(), RuntimeError> {
+        match self.fuel.get() {
+            0 => Err(RuntimeError::Custom("out of fuel".into())),
+            n => {
+                self.fuel.set(n - 1);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Binds `value` as the definition `name`, so scripts can refer to it.
+pub fn set_input(engine: &mut Engine, name: &str, value: &OwnedValue) -> Result<(), Error> {
+    match *value {
+        OwnedValue::Int(_) | OwnedValue::Float(_) | OwnedValue::Bool(_) => {
+            engine.define(name, &format!("({})", value))
+        }
+        _ => Err(Error::Type(TypeError::Custom(format!(
+            "input `{}` must be an int, float or bool",
+            name
+        )))),
+    }
+}
+
+/// Typechecks `source`, requires its result to be of type `expected` and
+/// runs it.
+pub fn run_script(engine: &Engine, source: &str, expected: ValueType) -> Result<OwnedValue, Error> {
+    let (_, ty) = engine.prepare(source)?;
+    if ty != DataType::Value(expected.clone()) {
+        return Err(Error::Type(TypeError::Custom(format!(
+            "script returns {}, expecting {}",
+            ty,
+            DataType::Value(expected)
+        ))));
+    }
+    engine.eval_owned(source)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KvHandleType;
+
+impl CustomDataType for KvHandleType {
+    fn cdt_eq(&self, other: &dyn CustomDataType) -> bool {
+        other.as_any().is::<KvHandleType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "kv_handle")
+    }
+}
+
+fn handle_type() -> DataType {
+    DataType::Custom(Rc::new(Box::new(KvHandleType)))
+}
+
+fn is_handle_type(ty: &DataType) -> bool {
+    match *ty {
+        DataType::Custom(ref inner) => inner.as_any().is::<KvHandleType>(),
+        _ => false,
+    }
+}
+
+/// A script's reference to one namespace of the store.
+#[derive(Debug, Clone)]
+pub struct KvHandle {
+    pub ns: i64,
+}
+
+impl CustomValue for KvHandle {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn compare<'b, 'c>(
+        &self,
+        other: &dyn CustomValue,
+        _ectx: &mut EvalContext<'b, 'c>,
+    ) -> Result<Option<Ordering>, RuntimeError> {
+        Ok(other
+            .as_any()
+            .downcast_ref::<KvHandle>()
+            .map(|o| self.ns.cmp(&o.ns)))
+    }
+
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<namespace {}>", self.ns)
+    }
+}
+
+fn eval_handle<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: LazyValue<'b>,
+) -> Result<i64, RuntimeError> {
+    match v.eval(ectx)? {
+        RuntimeValue::Custom(cv) => Ok(cv.inner.as_any().downcast_ref::<KvHandle>().unwrap().ns),
+        _ => unreachable!(),
+    }
+}
+
+fn eval_int<'b, 'c>(ectx: &mut EvalContext<'b, 'c>, v: LazyValue<'b>) -> Result<i64, RuntimeError> {
+    match v.eval(ectx)? {
+        RuntimeValue::Int(v) => Ok(v),
+        _ => unreachable!(),
+    }
+}
+
+#[derive(Debug)]
+struct OpenOp;
+impl HostFunction for OpenOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![Param::required("ns")]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        match *params {
+            [DataType::Divergent] => Ok(DataType::Divergent),
+            [DataType::Value(ValueType::Int)] => Ok(handle_type()),
+            _ => Err(TypeError::Custom("namespace must be an int".into())),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let ns = eval_int(ectx, params.next().unwrap())?;
+        Ok(RuntimeValue::Custom(CustomValueBox::new(Box::new(
+            KvHandle { ns },
+        ))))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OpKind {
+    Get,
+    Has,
+    Put,
+    Delete,
+}
+
+#[derive(Debug)]
+struct StoreOp {
+    kind: OpKind,
+    store: KvStore,
+}
+
+impl HostFunction for StoreOp {
+    fn signature(&self) -> Option<Signature> {
+        let mut params = vec![Param::required("handle"), Param::required("key")];
+        match self.kind {
+            OpKind::Get => params.push(Param::optional("default", ConstExpr::Int(0))),
+            OpKind::Put => params.push(Param::required("value")),
+            OpKind::Has | OpKind::Delete => {}
+        }
+        Some(Signature::new(params))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        if params.is_empty() || !is_handle_type(&params[0]) {
+            return Err(TypeError::Custom("expecting a store handle".into()));
+        }
+        if params[1..]
+            .iter()
+            .any(|p| *p != DataType::Value(ValueType::Int))
+        {
+            return Err(TypeError::Custom(
+                "store keys and values must be ints".into(),
+            ));
+        }
+        Ok(match self.kind {
+            OpKind::Get => DataType::Value(ValueType::Int),
+            OpKind::Has => DataType::Value(ValueType::Bool),
+            OpKind::Put | OpKind::Delete => handle_type(),
+        })
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        // The handle is forced first, so any effects it depends on happen
+        // before this one.
+        let ns = eval_handle(ectx, params.next().unwrap())?;
+        let key = eval_int(ectx, params.next().unwrap())?;
+        let handle = || RuntimeValue::Custom(CustomValueBox::new(Box::new(KvHandle { ns })));
+
+        match self.kind {
+            OpKind::Get => {
+                let default = params.next().unwrap();
+                self.store.consume_fuel()?;
+                match self.store.get(ns, key) {
+                    Some(v) => Ok(RuntimeValue::Int(v)),
+                    None => default.eval(ectx),
+                }
+            }
+            OpKind::Has => {
+                self.store.consume_fuel()?;
+                Ok(RuntimeValue::Bool(self.store.get(ns, key).is_some()))
+            }
+            OpKind::Put => {
+                let value = eval_int(ectx, params.next().unwrap())?;
+                self.store.consume_fuel()?;
+                self.store.put(ns, key, value);
+                Ok(handle())
+            }
+            OpKind::Delete => {
+                self.store.consume_fuel()?;
+                self.store.entries.borrow_mut().remove(&(ns, key));
+                Ok(handle())
+            }
+        }
+    }
+}
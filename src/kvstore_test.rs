@@ -0,0 +1,94 @@
+use crate::builtin::ValueType;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::OwnedValue;
+use crate::kvstore::*;
+
+fn engine(store: &KvStore, caps: Capabilities) -> Engine {
+    let mut engine = Engine::new();
+    store.register(&mut engine, caps);
+    engine
+}
+
+#[test]
+fn test_effects_are_ordered_by_handle() {
+    let store = KvStore::new();
+    store.put(0, 1, 5);
+    let engine = engine(&store, Capabilities::read_write());
+
+    let v = run_script(
+        &engine,
+        r"((\h ($add ($kv_get ($kv_put h 2 ($kv_get h 1)) 2) ($kv_get h 3 100))) ($kv_open 0))",
+        ValueType::Int,
+    )
+    .unwrap();
+    assert_eq!(v, OwnedValue::Int(105));
+    assert_eq!(store.get(0, 2), Some(5));
+
+    run_script(
+        &engine,
+        "($kv_has ($kv_delete ($kv_open 0) 1) 1)",
+        ValueType::Bool,
+    )
+    .unwrap();
+    assert_eq!(store.get(0, 1), None);
+}
+
+#[test]
+fn test_capabilities() {
+    let store = KvStore::new();
+    let engine = engine(&store, Capabilities::read_only());
+
+    assert!(run_script(&engine, "($kv_get ($kv_open 0) 1)", ValueType::Int).is_ok());
+    assert!(run_script(&engine, "($kv_put ($kv_open 0) 1 2)", ValueType::Int).is_err());
+    assert!(store.entries().is_empty());
+}
+
+#[test]
+fn test_handles_are_typed() {
+    let store = KvStore::new();
+    let engine = engine(&store, Capabilities::read_write());
+
+    match run_script(&engine, "($kv_get 0 1)", ValueType::Int) {
+        Err(Error::Type(_)) => {}
+        v => panic!("unexpected result: {:?}", v),
+    }
+    match run_script(&engine, "($kv_get ($kv_open 0) 1)", ValueType::Bool) {
+        Err(Error::Type(_)) => {}
+        v => panic!("unexpected result: {:?}", v),
+    }
+}
+
+#[test]
+fn test_fuel() {
+    let store = KvStore::new();
+    let engine = engine(&store, Capabilities::read_write());
+    store.set_fuel(2);
+
+    let src = r"((\h ($kv_get ($kv_put ($kv_put h 1 1) 2 2) 1)) ($kv_open 0))";
+    match run_script(&engine, src, ValueType::Int) {
+        Err(Error::Runtime(RuntimeError::Custom(ref msg))) if msg == "out of fuel" => {}
+        v => panic!("unexpected result: {:?}", v),
+    }
+    // Effects performed before running out are kept.
+    assert_eq!(store.entries().len(), 2);
+    assert_eq!(store.fuel(), 0);
+
+    store.set_fuel(3);
+    assert_eq!(
+        run_script(&engine, src, ValueType::Int).unwrap(),
+        OwnedValue::Int(1)
+    );
+}
+
+#[test]
+fn test_inputs() {
+    let store = KvStore::new();
+    let mut engine = engine(&store, Capabilities::read_write());
+    set_input(&mut engine, "limit", &OwnedValue::Int(3)).unwrap();
+    set_input(&mut engine, "scale", &OwnedValue::Float(0.5)).unwrap();
+    assert!(set_input(&mut engine, "xs", &OwnedValue::List(vec![])).is_err());
+
+    let v = run_script(&engine, "($mul ($add limit 1) scale)", ValueType::Float).unwrap();
+    assert_eq!(v, OwnedValue::Float(2.0));
+}
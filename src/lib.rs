@@ -1,18 +1,201 @@
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+#[cfg(feature = "arrow")]
+extern crate arrow_schema;
 extern crate serde;
+extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
 extern crate bincode;
-extern crate rpds;
+#[cfg(not(target_arch = "wasm32"))]
+extern crate corosensei;
+#[cfg(feature = "signing")]
+extern crate ed25519_dalek;
+#[cfg(any(test, feature = "testing"))]
+extern crate proptest;
+#[cfg(feature = "grpc")]
+extern crate prost;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "regex")]
+extern crate regex;
+extern crate sha2;
 extern crate slab;
+#[cfg(feature = "grpc")]
+extern crate tokio;
+#[cfg(feature = "grpc")]
+extern crate tonic;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "macros")]
+extern crate x_lang_macros;
 
+// Lets code generated by `#[xl_host]` refer to this crate as `x_lang` in
+// its own tests.
+#[cfg(all(test, feature = "macros"))]
+extern crate self as x_lang;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod ast;
+#[cfg(feature = "async")]
+pub mod async_host;
+pub mod audit;
 pub mod builtin;
+pub mod bundle;
+pub mod bytes;
+pub mod columnar;
+pub mod config;
 pub mod corelib;
+pub mod datetime;
+pub mod decimal;
+pub mod definitions;
+pub mod disasm;
+pub mod engine;
 pub mod error;
 pub mod eval;
+pub mod examples;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod execution;
+pub mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod host;
+pub mod host_fn;
+pub mod inspect;
+pub mod journal;
+pub mod json;
+#[cfg(feature = "example-kv")]
+pub mod kvstore;
+pub mod lint;
+pub mod log;
+pub mod macros;
+pub mod marshal;
+pub mod metrics;
+pub mod optimize;
 pub mod parser;
+#[cfg(feature = "regex")]
+pub mod pattern;
+mod pool;
+pub mod program;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod recursion;
+pub mod reference;
+pub mod reload;
+pub mod remote;
+pub mod service;
+pub mod shadow;
+pub mod store;
+mod suspend;
+pub mod tenant;
+pub mod termination;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "tracing")]
+pub mod trace;
 pub mod typeck;
+pub mod warning;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use crate::examples::examples;
+#[cfg(feature = "macros")]
+pub use x_lang_macros::xl_host;
 
+#[cfg(all(test, feature = "arrow"))]
+mod arrow_test;
+#[cfg(test)]
+mod ast_test;
+#[cfg(all(test, feature = "async"))]
+mod async_host_test;
+#[cfg(test)]
+mod bundle_test;
+#[cfg(test)]
+mod bytes_test;
+#[cfg(test)]
+mod columnar_test;
+#[cfg(test)]
+mod config_test;
+#[cfg(test)]
+mod datetime_test;
+#[cfg(test)]
+mod decimal_test;
+#[cfg(test)]
+mod definitions_test;
+#[cfg(test)]
+mod disasm_test;
+#[cfg(test)]
+mod engine_test;
+#[cfg(test)]
+mod eval_test;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod execution_test;
+#[cfg(test)]
+mod explain_test;
+#[cfg(all(test, feature = "ffi"))]
+mod ffi_test;
+#[cfg(all(test, feature = "grpc"))]
+mod grpc_test;
+#[cfg(all(test, feature = "macros"))]
+mod host_fn_test;
+#[cfg(test)]
+mod host_test;
+#[cfg(test)]
+mod inspect_test;
+#[cfg(test)]
+mod json_test;
+#[cfg(all(test, feature = "example-kv"))]
+mod kvstore_test;
+#[cfg(test)]
+mod lint_test;
+#[cfg(test)]
+mod log_test;
+#[cfg(test)]
+mod macros_test;
+#[cfg(test)]
+mod marshal_test;
+#[cfg(test)]
+mod optimize_test;
+#[cfg(test)]
+mod parser_test;
+#[cfg(all(test, feature = "regex"))]
+mod pattern_test;
+#[cfg(test)]
+mod recursion_test;
+#[cfg(test)]
+mod reference_test;
+#[cfg(test)]
+mod reload_test;
+#[cfg(test)]
+mod remote_test;
+#[cfg(test)]
+mod service_test;
+#[cfg(test)]
+mod session_test;
+#[cfg(test)]
+mod shadow_test;
+#[cfg(test)]
+mod store_test;
+#[cfg(test)]
+mod tenant_test;
+#[cfg(test)]
+mod termination_test;
+#[cfg(test)]
+mod testing_test;
+#[cfg(all(test, feature = "tracing"))]
+mod trace_test;
 #[cfg(test)]
 mod typeck_test;
+#[cfg(test)]
+mod visit_test;
+#[cfg(test)]
+mod warning_test;
@@ -0,0 +1,436 @@
+//! Warnings about programs that typecheck but probably do not do what
+//! their author meant, for gating rule files in CI (see the `xlcheck`
+//! binary).
+//!
+//! Each `Lint` looks at the whole expression and reports what it finds; a
+//! `Linter` runs a set of lints and assigns each finding the level
+//! configured for its lint. Lints are identified by kebab-case names such
+//! as `unused-parameter`.
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+use crate::recursion::find_cycles;
+use crate::warning::Warning;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How a lint's findings are treated.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Level {
+    /// Not reported.
+    Allow,
+    Warning,
+    /// Reported as an error, which fails `xlcheck`.
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match *self {
+            Level::Allow => "allow",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        })
+    }
+}
+
+/// What lints know about the program besides its AST.
+#[derive(Default)]
+pub struct LintContext<'a> {
+    pub spans: Option<&'a SpanMap>,
+    /// The type of the program, if it typechecked.
+    pub ty: Option<&'a DataType>,
+    /// What typechecking the program warned about.
+    pub type_warnings: &'a [Warning],
+}
+
+impl<'a> LintContext<'a> {
+    fn span(&self, e: &Expr) -> Option<Span> {
+        self.spans.and_then(|s| s.get(e))
+    }
+}
+
+/// A finding of a lint, before a level is assigned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub lint: &'static str,
+    pub level: Level,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    /// Formats the diagnostic like a compiler would, pointing into `source`
+    /// (read from `file`) when the location is known.
+    pub fn render(&self, source: &str, file: &str) -> String {
+        let span = match self.span {
+            Some(span) => span,
+            None => return format!("{}: {}[{}]: {}", file, self.level, self.lint, self.message),
+        };
+        let line_start = source[..span.start].rfind('\n').map_or(0, |x| x + 1);
+        let line_end = source[span.start..]
+            .find('\n')
+            .map_or(source.len(), |x| span.start + x);
+        let line_no = source[..span.start].matches('\n').count() + 1;
+        let col = span.start - line_start;
+        let width = span.end.min(line_end).saturating_sub(span.start).max(1);
+        format!(
+            "{}:{}:{}: {}[{}]: {}\n  | {}\n  | {}{}",
+            file,
+            line_no,
+            col + 1,
+            self.level,
+            self.lint,
+            self.message,
+            &source[line_start..line_end],
+            " ".repeat(col),
+            "^".repeat(width)
+        )
+    }
+}
+
+pub trait Lint {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    fn default_level(&self) -> Level {
+        Level::Warning
+    }
+
+    fn check(&self, e: &Expr, cx: &LintContext, out: &mut Vec<Finding>);
+}
+
+/// Runs a set of lints with configurable levels.
+pub struct Linter {
+    lints: Vec<Box<dyn Lint>>,
+    levels: BTreeMap<&'static str, Level>,
+}
+
+impl Default for Linter {
+    fn default() -> Linter {
+        Linter::new()
+    }
+}
+
+impl Linter {
+    /// Creates a linter running the built-in lints at their default levels.
+    pub fn new() -> Linter {
+        let mut linter = Linter {
+            lints: Vec::new(),
+            levels: BTreeMap::new(),
+        };
+        linter.add(Box::new(UnusedParameter));
+        linter.add(Box::new(ShadowedBinding));
+        linter.add(Box::new(ConstantCondition));
+        linter.add(Box::new(ConstantDivergence));
+        linter.add(Box::new(FloatEquality));
+        linter.add(Box::new(IntToFloat));
+        linter
+    }
+
+    pub fn add(&mut self, lint: Box<dyn Lint>) {
+        self.levels.insert(lint.name(), lint.default_level());
+        self.lints.push(lint);
+    }
+
+    pub fn lints(&self) -> impl Iterator<Item = &dyn Lint> {
+        self.lints.iter().map(|l| &**l)
+    }
+
+    /// Sets the level of the lint `name`. Fails if there is no such lint.
+    pub fn set_level(&mut self, name: &str, level: Level) -> Result<(), String> {
+        match self.levels.iter_mut().find(|(k, _)| **k == name) {
+            Some((_, l)) => {
+                *l = level;
+                Ok(())
+            }
+            None => Err(format!("unknown lint: {}", name)),
+        }
+    }
+
+    /// Reports every warning as an error.
+    pub fn deny_warnings(&mut self) {
+        for level in self.levels.values_mut() {
+            if *level == Level::Warning {
+                *level = Level::Error;
+            }
+        }
+    }
+
+    /// Runs the lints that are not allowed, returning their findings in
+    /// source order.
+    pub fn run(&self, e: &Expr, cx: &LintContext) -> Vec<Diagnostic> {
+        let mut out = Vec::new();
+        for lint in &self.lints {
+            let level = self.levels[lint.name()];
+            if level == Level::Allow {
+                continue;
+            }
+            let mut findings = Vec::new();
+            lint.check(e, cx, &mut findings);
+            out.extend(findings.into_iter().map(|f| Diagnostic {
+                lint: lint.name(),
+                level,
+                message: f.message,
+                span: f.span,
+            }));
+        }
+        out.sort_by_key(|d| d.span.map(|s| s.start));
+        out
+    }
+}
+
+/// Walks `e` with `v`, which never fails.
+fn walk_all<V: Visitor<Error = ()>>(v: &mut V, e: &Expr) {
+    visit::walk(v, e).unwrap();
+}
+
+/// A lambda parameter its body never refers to. Parameters starting with
+/// `_` are exempt.
+pub struct UnusedParameter;
+
+struct UnusedParameterVisitor<'a, 'b> {
+    cx: &'a LintContext<'b>,
+    out: &'a mut Vec<Finding>,
+}
+
+impl<'a, 'b> Visitor for UnusedParameterVisitor<'a, 'b> {
+    type Error = ();
+
+    fn pre_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract {
+            ref params,
+            body: AbstractBody::Expr(ref body),
+        } = *e.body
+        {
+            let used = free_vars(body);
+            for p in params {
+                if !used.contains(p) && !source_name(p).starts_with('_') {
+                    self.out.push(Finding {
+                        message: format!("unused parameter `{}`", source_name(p)),
+                        span: self.cx.span(e),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Lint for UnusedParameter {
+    fn name(&self) -> &'static str {
+        "unused-parameter"
+    }
+
+    fn description(&self) -> &'static str {
+        "a lambda parameter that its body never uses"
+    }
+
+    fn check(&self, e: &Expr, cx: &LintContext, out: &mut Vec<Finding>) {
+        walk_all(&mut UnusedParameterVisitor { cx, out }, e);
+    }
+}
+
+/// A lambda parameter with the same name as a parameter of an enclosing
+/// lambda, which it hides.
+pub struct ShadowedBinding;
+
+struct ShadowedBindingVisitor<'a, 'b> {
+    cx: &'a LintContext<'b>,
+    out: &'a mut Vec<Finding>,
+    /// Source names of the parameters in scope.
+    scope: Vec<String>,
+}
+
+impl<'a, 'b> Visitor for ShadowedBindingVisitor<'a, 'b> {
+    type Error = ();
+
+    fn pre_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract { ref params, .. } = *e.body {
+            for p in params {
+                let name = source_name(p);
+                if self.scope.iter().any(|s| s == name) {
+                    self.out.push(Finding {
+                        message: format!("parameter `{}` shadows an outer binding", name),
+                        span: self.cx.span(e),
+                    });
+                }
+            }
+            self.scope
+                .extend(params.iter().map(|p| source_name(p).to_string()));
+        }
+        Ok(())
+    }
+
+    fn post_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract { ref params, .. } = *e.body {
+            let len = self.scope.len() - params.len();
+            self.scope.truncate(len);
+        }
+        Ok(())
+    }
+}
+
+impl Lint for ShadowedBinding {
+    fn name(&self) -> &'static str {
+        "shadowed-binding"
+    }
+
+    fn description(&self) -> &'static str {
+        "a lambda parameter that hides one of an enclosing lambda"
+    }
+
+    fn check(&self, e: &Expr, cx: &LintContext, out: &mut Vec<Finding>) {
+        walk_all(
+            &mut ShadowedBindingVisitor {
+                cx,
+                out,
+                scope: Vec::new(),
+            },
+            e,
+        );
+    }
+}
+
+/// An `$if` whose condition is a literal, so one branch is dead.
+pub struct ConstantCondition;
+
+struct ConstantConditionVisitor<'a, 'b> {
+    cx: &'a LintContext<'b>,
+    out: &'a mut Vec<Finding>,
+}
+
+impl<'a, 'b> Visitor for ConstantConditionVisitor<'a, 'b> {
+    type Error = ();
+
+    fn pre_apply(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Apply {
+            ref target,
+            ref params,
+        } = *e.body
+        {
+            let is_if = match *target.body {
+                ExprBody::Abstract {
+                    body: AbstractBody::Host(ref name),
+                    ..
+                } => name == "if",
+                _ => false,
+            };
+            if let (true, Some(cond)) = (is_if, params.first()) {
+                if let ExprBody::Const(ConstExpr::Bool(v)) = *cond.body {
+                    self.out.push(Finding {
+                        message: format!("condition is always {}", v),
+                        span: self.cx.span(cond).or_else(|| self.cx.span(e)),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Lint for ConstantCondition {
+    fn name(&self) -> &'static str {
+        "constant-condition"
+    }
+
+    fn description(&self) -> &'static str {
+        "an `$if` whose condition is always true or always false"
+    }
+
+    fn check(&self, e: &Expr, cx: &LintContext, out: &mut Vec<Finding>) {
+        walk_all(&mut ConstantConditionVisitor { cx, out }, e);
+    }
+}
+
+/// A program that typechecks as never terminating, whatever its inputs.
+pub struct ConstantDivergence;
+
+impl Lint for ConstantDivergence {
+    fn name(&self) -> &'static str {
+        "constant-divergence"
+    }
+
+    fn description(&self) -> &'static str {
+        "a program that never terminates"
+    }
+
+    fn default_level(&self) -> Level {
+        Level::Error
+    }
+
+    fn check(&self, e: &Expr, cx: &LintContext, out: &mut Vec<Finding>) {
+        if cx.ty == Some(&DataType::Divergent) {
+            let cycles = find_cycles(e, None);
+            let message = match cycles.first() {
+                Some(cycle) => format!("program never terminates: it recurses through {}", cycle),
+                None => "program never terminates".into(),
+            };
+            let span = cycles
+                .first()
+                .and_then(|c| cx.spans.and_then(|s| c.span(s)))
+                .or_else(|| cx.span(e));
+            out.push(Finding { message, span });
+        }
+    }
+}
+
+/// Floats compared with `$eq` or `$ne`, found while typechecking.
+pub struct FloatEquality;
+
+impl Lint for FloatEquality {
+    fn name(&self) -> &'static str {
+        "float-equality"
+    }
+
+    fn description(&self) -> &'static str {
+        "floats compared for exact equality"
+    }
+
+    fn check(&self, _e: &Expr, cx: &LintContext, out: &mut Vec<Finding>) {
+        for w in cx.type_warnings {
+            if let Warning::FloatEquality { ref expr, .. } = *w {
+                out.push(Finding {
+                    message: w.to_string(),
+                    span: cx.span(expr),
+                });
+            }
+        }
+    }
+}
+
+/// An int converted to a float to be combined with one, found while
+/// typechecking. Allowed by default.
+pub struct IntToFloat;
+
+impl Lint for IntToFloat {
+    fn name(&self) -> &'static str {
+        "int-to-float"
+    }
+
+    fn description(&self) -> &'static str {
+        "an int implicitly converted to a float"
+    }
+
+    fn default_level(&self) -> Level {
+        Level::Allow
+    }
+
+    fn check(&self, _e: &Expr, cx: &LintContext, out: &mut Vec<Finding>) {
+        for w in cx.type_warnings {
+            if let Warning::IntToFloat { ref expr, .. } = *w {
+                out.push(Finding {
+                    message: w.to_string(),
+                    span: cx.span(expr),
+                });
+            }
+        }
+    }
+}
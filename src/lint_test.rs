@@ -0,0 +1,108 @@
+use crate::ast::DataType;
+use crate::engine::Engine;
+use crate::lint::*;
+use crate::parser::{parse_expr_with_spans, ParseOptions};
+
+fn lint(
+    linter: &Linter,
+    source: &str,
+    ty: Option<&DataType>,
+) -> Vec<(&'static str, Level, String)> {
+    let (e, spans) = parse_expr_with_spans(source, &ParseOptions::default()).unwrap();
+    let cx = LintContext {
+        spans: Some(&spans),
+        ty,
+        type_warnings: &[],
+    };
+    linter
+        .run(&e, &cx)
+        .into_iter()
+        .map(|d| {
+            let span = d.span.unwrap();
+            (d.lint, d.level, source[span.start..span.end].to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn test_builtin_lints() {
+    let linter = Linter::new();
+    assert_eq!(lint(&linter, r"(\x ($add x 1))", None), vec![]);
+    assert_eq!(
+        lint(&linter, r"(\x (\_y ($add x 1)))", None),
+        vec![],
+        "parameters starting with `_` may be unused"
+    );
+    assert_eq!(
+        lint(&linter, r"(\x (\y ($add x 1)))", None),
+        vec![("unused-parameter", Level::Warning, r"\y ($add x 1)".into())]
+    );
+    assert_eq!(
+        lint(&linter, r"(\x ($add x ((\x ($add x 1)) 2)))", None),
+        vec![("shadowed-binding", Level::Warning, r"\x ($add x 1)".into())]
+    );
+    assert_eq!(
+        lint(&linter, r"(\x ($if false x 1))", None),
+        vec![("constant-condition", Level::Warning, "false".into())]
+    );
+    assert_eq!(
+        lint(
+            &linter,
+            "((\\x (x x)) (\\x (x x)))",
+            Some(&DataType::Divergent)
+        ),
+        vec![("constant-divergence", Level::Error, "(x x)".into())],
+        "the span is that of the recursive call"
+    );
+}
+
+#[test]
+fn test_lint_levels() {
+    let mut linter = Linter::new();
+    assert!(linter.set_level("no-such-lint", Level::Allow).is_err());
+
+    linter.set_level("unused-parameter", Level::Allow).unwrap();
+    linter.deny_warnings();
+    assert_eq!(
+        lint(&linter, r"(\x (\y ($if true x 1)))", None),
+        vec![("constant-condition", Level::Error, "true".into())]
+    );
+}
+
+#[test]
+fn test_render() {
+    let source = "(\\x\n  ($if true x 1))";
+    let (e, spans) = parse_expr_with_spans(source, &ParseOptions::default()).unwrap();
+    let cx = LintContext {
+        spans: Some(&spans),
+        ty: None,
+        type_warnings: &[],
+    };
+    let diags = Linter::new().run(&e, &cx);
+    assert_eq!(
+        diags[0].render(source, "rule.xl"),
+        "rule.xl:2:8: warning[constant-condition]: condition is always true\n  |   ($if true x 1))\n  |        ^^^^"
+    );
+}
+
+#[test]
+fn test_float_equality() {
+    let engine = Engine::new();
+    let source = r"((\x ($if ($eq x 0.5) 1 ($if ($ne 1 2) 2 3))) 1.5)";
+    let (e, spans) = parse_expr_with_spans(source, &engine.parse_options()).unwrap();
+    let (ty, warnings) = engine.check_with_warnings(&e).unwrap();
+    let cx = LintContext {
+        spans: Some(&spans),
+        ty: Some(&ty),
+        type_warnings: &warnings,
+    };
+    let found: Vec<(&str, String)> = Linter::new()
+        .run(&e, &cx)
+        .into_iter()
+        .map(|d| {
+            let span = d.span.unwrap();
+            (d.lint, source[span.start..span.end].to_string())
+        })
+        .collect();
+    assert_eq!(found, vec![("float-equality", "($eq x 0.5)".to_string())]);
+}
@@ -0,0 +1,148 @@
+//! Logging from scripts into the embedder's own logging.
+//!
+//! `($log level message value)` hands a `LogRecord` to the `LogSink`
+//! installed with `EvalContext::set_log_sink` or `Engine::set_log_sink`,
+//! and returns `value`, so a call can wrap any expression to trace it
+//! without changing what the script computes. Without a sink, records are
+//! dropped; scripts never write to stdout or stderr themselves.
+
+use crate::ast::DataType;
+use crate::audit::AuditValue;
+use crate::corelib::{as_str, string_type, type_mismatch};
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        match name {
+            "debug" => Some(LogLevel::Debug),
+            "info" => Some(LogLevel::Info),
+            "warn" => Some(LogLevel::Warn),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+}
+
+/// One call of `$log`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub message: String,
+    pub value: AuditValue,
+}
+
+/// Where `$log` sends its records.
+pub trait LogSink: Debug {
+    fn log(&self, record: LogRecord);
+}
+
+/// A sink keeping the records in memory, e.g. to attach them to the
+/// response of a request or to inspect them in tests.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    records: RefCell<Vec<LogRecord>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    /// Removes and returns the records logged so far.
+    pub fn take(&self) -> Vec<LogRecord> {
+        self.records.borrow_mut().split_off(0)
+    }
+}
+
+impl LogSink for MemorySink {
+    fn log(&self, record: LogRecord) {
+        self.records.borrow_mut().push(record);
+    }
+}
+
+/// `($log level message value)`: logs `message` and `value` at `level`,
+/// one of `"debug"`, `"info"`, `"warn"` and `"error"`, and returns `value`.
+#[derive(Debug)]
+pub struct LogOp;
+impl HostFunction for LogOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("level"),
+            Param::required("message"),
+            Param::required("value"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        if params.contains(&DataType::Divergent) {
+            return Ok(DataType::Divergent);
+        }
+        match *params {
+            [ref level, ref message, ref value] => {
+                for ty in &[level, message] {
+                    if **ty != string_type() && **ty != DataType::Dynamic {
+                        return Err(TypeError::Custom(format!(
+                            "log level and message must be strings, found {}",
+                            ty
+                        )));
+                    }
+                }
+                Ok(value.clone())
+            }
+            _ => Err(TypeError::Custom(
+                "log expects a level, a message and a value".into(),
+            )),
+        }
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let level = params.next().unwrap().eval(ectx)?;
+        let level = as_str(&level).ok_or_else(|| type_mismatch("string", &level))?;
+        let level = LogLevel::from_name(level)
+            .ok_or_else(|| RuntimeError::Custom(format!("unknown log level `{}`", level)))?;
+        let message = params.next().unwrap().eval(ectx)?;
+        let message = as_str(&message)
+            .ok_or_else(|| type_mismatch("string", &message))?
+            .to_string();
+        let value = params.next().unwrap().eval(ectx)?;
+        if let Some(sink) = ectx.log_sink() {
+            sink.log(LogRecord {
+                level,
+                message,
+                value: AuditValue::from_value(&value),
+            });
+        }
+        Ok(value)
+    }
+}
+
+/// The host functions of `HostGroup::Log`.
+pub fn log_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![("log", Arc::new(LogOp))]
+}
@@ -0,0 +1,62 @@
+use crate::audit::AuditValue;
+use crate::corelib::{HostGroup, HostManager, Profile};
+use crate::engine::Engine;
+use crate::error::{Error, RuntimeError};
+use crate::log::{LogLevel, LogRecord, MemorySink};
+use std::rc::Rc;
+
+#[test]
+fn test_log() {
+    let mut engine = Engine::new();
+    // Without a sink, records are dropped.
+    assert_eq!(
+        engine
+            .eval_str(r#"($add 1 ($log "debug" "x" 41))"#)
+            .unwrap(),
+        "42"
+    );
+
+    let sink = Rc::new(MemorySink::new());
+    engine.set_log_sink(Some(sink.clone()));
+    assert_eq!(
+        engine
+            .eval_str(r#"($log "info" "done" ($mul ($log "debug" "x" 3) 2))"#)
+            .unwrap(),
+        "6"
+    );
+    assert_eq!(
+        sink.take(),
+        vec![
+            LogRecord {
+                level: LogLevel::Debug,
+                message: "x".into(),
+                value: AuditValue::Int(3),
+            },
+            LogRecord {
+                level: LogLevel::Info,
+                message: "done".into(),
+                value: AuditValue::Int(6),
+            },
+        ]
+    );
+    assert!(sink.take().is_empty());
+
+    match engine.eval_str(r#"($log "loud" "x" 1)"#) {
+        Err(Error::Runtime(RuntimeError::Custom(_))) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    match engine.eval_str(r#"($log "info" 1 1)"#) {
+        Err(Error::Type(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_log_group() {
+    assert!(!HostGroup::Log.is_pure());
+    let hm = HostManager::with_profile(Profile::PureMath);
+    assert!(!hm.get_all().any(|(k, _)| k == "log"));
+    let hm = HostManager::with_profile(Profile::DataTransform);
+    assert_eq!(hm.group_of("log"), Some(HostGroup::Log));
+    assert!(hm.get_all().any(|(k, _)| k == "log"));
+}
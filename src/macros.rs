@@ -0,0 +1,181 @@
+//! Macros defined in source with `(defmacro name (params...) template)`.
+//!
+//! Macro definitions come before the program expression. When there are
+//! any, the program is parsed without renaming, each call `(name args...)`
+//! is replaced with `template`, its parameters replaced with the argument
+//! expressions, and only then are bound names renamed.
+//!
+//! Expansion is hygienic: binders introduced by a template are renamed
+//! where they would capture names in the arguments, and a template may not
+//! refer to a global the call site binds locally.
+
+use crate::ast::*;
+use crate::error::ParseError;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Maximum number of macro calls being expanded within one another, which
+/// stops macros that expand to themselves.
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub params: Vec<String>,
+    /// Parsed without renaming.
+    pub template: Expr,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MacroTable {
+    macros: BTreeMap<String, Macro>,
+}
+
+impl MacroTable {
+    pub fn define(&mut self, name: &str, m: Macro) -> Result<(), ParseError> {
+        if self.macros.contains_key(name) {
+            return Err(ParseError::Custom(format!(
+                "macro `{}` is defined twice",
+                name
+            )));
+        }
+        self.macros.insert(name.to_string(), m);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.macros.keys()
+    }
+}
+
+struct Expander<'a> {
+    macros: &'a MacroTable,
+    /// Names bound around the expression being expanded.
+    scope: Vec<String>,
+    depth: usize,
+}
+
+impl<'a> Expander<'a> {
+    /// Returns the macro `name` refers to, unless a local binding hides it.
+    fn lookup(&self, name: &str) -> Option<&'a Macro> {
+        if self.scope.iter().any(|s| s == name) {
+            None
+        } else {
+            self.macros.get(name)
+        }
+    }
+
+    fn expand_call(&mut self, name: &str, m: &Macro, args: &[Expr]) -> Result<Expr, ParseError> {
+        if args.len() != m.params.len() {
+            return Err(ParseError::Custom(format!(
+                "macro `{}` takes {} arguments, {} given",
+                name,
+                m.params.len(),
+                args.len()
+            )));
+        }
+        if self.depth == MAX_EXPANSION_DEPTH {
+            return Err(ParseError::Custom(format!(
+                "expansion of macro `{}` is nested too deeply",
+                name
+            )));
+        }
+        if let Some(v) = free_vars(&m.template)
+            .into_iter()
+            .find(|v| !m.params.contains(v) && self.scope.contains(v))
+        {
+            return Err(ParseError::Custom(format!(
+                "macro `{}` refers to `{}`, which is bound where the macro is used",
+                name, v
+            )));
+        }
+
+        let subst: Vec<(&str, Expr)> = m
+            .params
+            .iter()
+            .map(|p| p.as_str())
+            .zip(args.iter().cloned())
+            .collect();
+        let expanded = substitute(&m.template, &subst);
+        self.depth += 1;
+        let ret = self.expand(&expanded);
+        self.depth -= 1;
+        ret
+    }
+
+    fn expand(&mut self, e: &Expr) -> Result<Expr, ParseError> {
+        let body = match *e.body {
+            ExprBody::Const(_) | ExprBody::Never => return Ok(e.clone()),
+            ExprBody::Name(ref name) => {
+                return match self.lookup(name) {
+                    Some(m) => self.expand_call(name, m, &[]),
+                    None => Ok(e.clone()),
+                };
+            }
+            ExprBody::Apply {
+                ref target,
+                ref params,
+            } => {
+                if let ExprBody::Name(ref name) = *target.body {
+                    if let Some(m) = self.lookup(name) {
+                        return self.expand_call(name, m, params);
+                    }
+                }
+                ExprBody::Apply {
+                    target: self.expand(target)?,
+                    params: params
+                        .iter()
+                        .map(|p| self.expand(p))
+                        .collect::<Result<_, _>>()?,
+                }
+            }
+            ExprBody::Abstract {
+                ref params,
+                body: AbstractBody::Expr(ref body),
+            } => {
+                self.scope.extend(params.iter().cloned());
+                let body = self.expand(body);
+                let len = self.scope.len() - params.len();
+                self.scope.truncate(len);
+                ExprBody::Abstract {
+                    params: params.clone(),
+                    body: AbstractBody::Expr(body?),
+                }
+            }
+            ExprBody::Abstract {
+                body: AbstractBody::Host(_),
+                ..
+            } => return Ok(e.clone()),
+            ExprBody::Match {
+                ref value,
+                ref branches,
+            } => ExprBody::Match {
+                value: self.expand(value)?,
+                branches: branches
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), self.expand(v)?)))
+                    .collect::<Result<_, ParseError>>()?,
+            },
+        };
+        Ok(Expr {
+            body: Rc::new(body),
+        })
+    }
+}
+
+/// Expands the macro calls in `e`, which was parsed without renaming.
+pub fn expand(e: &Expr, macros: &MacroTable) -> Result<Expr, ParseError> {
+    Expander {
+        macros,
+        scope: Vec::new(),
+        depth: 0,
+    }
+    .expand(e)
+}
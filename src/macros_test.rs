@@ -0,0 +1,93 @@
+use crate::engine::Engine;
+use crate::error::{Error, ParseError};
+use crate::parser::{parse_expr, parse_expr_with_globals};
+use std::collections::BTreeSet;
+
+fn parse_error(source: &str) -> String {
+    match parse_expr(source) {
+        Err(ParseError::Custom(msg)) => msg,
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_macro_expansion() {
+    let engine = Engine::new();
+    let eval = |s: &str| engine.eval_str(s).unwrap();
+    assert_eq!(
+        eval(
+            r"(defmacro cond3 (c1 a c2 b e) ($if c1 a ($if c2 b e)))
+              (defmacro sign (n) (cond3 ($lt n 0) ($sub 0 1) ($gt n 0) 1 0))
+              ($add (sign 5) ($add (sign 0) (sign ($sub 0 5))))"
+        ),
+        "0"
+    );
+    assert_eq!(eval("(defmacro five () (5)) ($add (five) 1)"), "6");
+
+    // Expands to the same AST as writing the call out by hand.
+    assert_eq!(
+        parse_expr(r"(defmacro unless (c a b) ($if c b a)) (\x (unless x 1 2))").unwrap(),
+        parse_expr(r"(\x ($if x 2 1))").unwrap()
+    );
+}
+
+#[test]
+fn test_macro_hygiene() {
+    let engine = Engine::new();
+    // The template's `t` does not capture the argument's.
+    assert_eq!(
+        engine
+            .eval_str(r"(defmacro rsub (a b) ((\t ($sub b t)) a)) ((\t (rsub t 10)) 3)")
+            .unwrap(),
+        "7"
+    );
+    // A local binding hides a macro of the same name.
+    assert_eq!(
+        engine
+            .eval_str(r"(defmacro m (a) ($add a 1)) ((\m (m)) 3)")
+            .unwrap(),
+        "3"
+    );
+
+    let globals: BTreeSet<String> = std::iter::once("limit".to_string()).collect();
+    assert!(parse_expr_with_globals(
+        r"(defmacro over (x) ($gt x limit)) (\y (over y))",
+        globals.clone()
+    )
+    .is_ok());
+    match parse_expr_with_globals(
+        r"(defmacro over (x) ($gt x limit)) (\limit (over 3))",
+        globals,
+    ) {
+        Err(ParseError::Custom(msg)) => assert_eq!(
+            msg,
+            "macro `over` refers to `limit`, which is bound where the macro is used"
+        ),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_macro_errors() {
+    assert_eq!(
+        parse_error("(defmacro m (a) (a)) (m 1 2)"),
+        "macro `m` takes 1 arguments, 2 given"
+    );
+    assert_eq!(
+        parse_error("(defmacro m (a) (a)) (defmacro m (b) (b)) (m 1)"),
+        "macro `m` is defined twice"
+    );
+    assert_eq!(
+        parse_error("(defmacro m (x) (m x)) (m 1)"),
+        "expansion of macro `m` is nested too deeply"
+    );
+    // Quotes in expanded code may not refer to local variables either.
+    assert_eq!(
+        parse_error(r"(defmacro m (a) (quote ($add a 1))) (\x (m x))"),
+        "`x` is bound outside the quote; splice it in with (unquote x)"
+    );
+    match Engine::new().eval_str("(defmacro m (a) (a))") {
+        Err(Error::Parse(ParseError::UnexpectedEnd)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,98 @@
+//! Conversions between the host's serde types and script values, for
+//! passing domain structs to `Engine::call` and reading results back.
+//!
+//! x-lang has no record type, so structs and maps become `json` documents,
+//! which scripts read with `$json_get`. Everything else maps onto the
+//! closest x-lang value: integers to ints, other numbers to floats, strings
+//! to strings, sequences to lists, and `None` and `()` to `~`.
+//!
+//! `~` is also the empty list, so it reads back as `None`, `()` or an
+//! empty sequence, whichever the target type accepts. This only works at
+//! the top level: an empty list nested in a list reads back as null.
+
+use crate::bytes::as_bytes;
+use crate::corelib::{as_str, str_value};
+use crate::error::RuntimeError;
+use crate::eval::{OwnedValue, RuntimeValue};
+use crate::json::{as_json, json_value};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+/// `v` as a script value.
+pub fn to_value<T: Serialize + ?Sized>(v: &T) -> Result<OwnedValue, RuntimeError> {
+    let json = serde_json::to_value(v).map_err(|e| RuntimeError::Custom(e.to_string()))?;
+    from_json(json)
+}
+
+fn from_json(v: Value) -> Result<OwnedValue, RuntimeError> {
+    Ok(match v {
+        Value::Null => OwnedValue::Empty,
+        Value::Bool(b) => OwnedValue::Bool(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(x) => OwnedValue::Int(x),
+            None if n.is_f64() => OwnedValue::Float(n.as_f64().unwrap()),
+            None => {
+                return Err(RuntimeError::Custom(format!(
+                    "integer {} does not fit in an int",
+                    n
+                )))
+            }
+        },
+        Value::String(s) => owned(str_value(&s)),
+        Value::Array(items) => {
+            OwnedValue::List(items.into_iter().map(from_json).collect::<Result<_, _>>()?)
+        }
+        v @ Value::Object(_) => owned(json_value(v)),
+    })
+}
+
+fn owned(v: RuntimeValue) -> OwnedValue {
+    match v {
+        RuntimeValue::Custom(cv) => OwnedValue::Custom(cv),
+        _ => unreachable!(),
+    }
+}
+
+/// Reads `v` as a `T`.
+pub fn from_value<T: DeserializeOwned>(v: &OwnedValue) -> Result<T, RuntimeError> {
+    let json = to_json(v)?;
+    let is_empty = json == Value::Null;
+    serde_json::from_value(json).or_else(|e| {
+        let e = RuntimeError::TypeMismatch(e.to_string());
+        if is_empty {
+            serde_json::from_value(Value::Array(vec![])).map_err(|_| e)
+        } else {
+            Err(e)
+        }
+    })
+}
+
+fn to_json(v: &OwnedValue) -> Result<Value, RuntimeError> {
+    Ok(match *v {
+        OwnedValue::Empty => Value::Null,
+        OwnedValue::Int(x) => Value::from(x),
+        OwnedValue::Float(x) => Value::Number(Number::from_f64(x).ok_or_else(|| {
+            RuntimeError::TypeMismatch(format!("cannot read non-finite float {}", x))
+        })?),
+        OwnedValue::Bool(b) => Value::Bool(b),
+        OwnedValue::List(ref items) => {
+            Value::Array(items.iter().map(to_json).collect::<Result<_, _>>()?)
+        }
+        OwnedValue::Custom(ref cv) => {
+            let v = RuntimeValue::Custom(cv.clone());
+            if let Some(s) = as_str(&v) {
+                Value::from(s)
+            } else if let Some(b) = as_bytes(&v) {
+                Value::from(b)
+            } else if let Some(doc) = as_json(&v) {
+                doc.clone()
+            } else {
+                return Err(RuntimeError::TypeMismatch(format!("cannot read {}", v)));
+            }
+        }
+        OwnedValue::Function { .. } | OwnedValue::Host(_) | OwnedValue::PartialHost { .. } => {
+            return Err(RuntimeError::TypeMismatch(format!("cannot read {}", v)))
+        }
+    })
+}
@@ -0,0 +1,91 @@
+use crate::engine::Engine;
+use crate::error::RuntimeError;
+use crate::eval::OwnedValue;
+use crate::marshal::{from_value, to_value};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Item {
+    sku: String,
+    qty: i64,
+    price: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Order {
+    id: u32,
+    items: Vec<Item>,
+    note: Option<String>,
+}
+
+#[test]
+fn test_to_value() {
+    assert_eq!(to_value(&42u8).unwrap(), OwnedValue::Int(42));
+    assert_eq!(to_value(&1.5).unwrap(), OwnedValue::Float(1.5));
+    assert_eq!(to_value(&None::<i64>).unwrap(), OwnedValue::Empty);
+    assert_eq!(
+        to_value(&vec![true, false]).unwrap(),
+        OwnedValue::List(vec![OwnedValue::Bool(true), OwnedValue::Bool(false)])
+    );
+    assert_eq!(to_value("hi").unwrap().to_string(), r#""hi""#);
+    assert!(to_value(&u64::MAX).is_err());
+}
+
+#[test]
+fn test_round_trip_through_script() {
+    let order = Order {
+        id: 7,
+        items: vec![
+            Item {
+                sku: "a".into(),
+                qty: 2,
+                price: 1.25,
+            },
+            Item {
+                sku: "b".into(),
+                qty: 1,
+                price: 4.0,
+            },
+        ],
+        note: None,
+    };
+    let engine = Engine::new();
+    let arg = to_value(&order).unwrap();
+    let id = engine
+        .call(
+            r#"(\o ($json_to_int ($json_get o "id")))"#,
+            std::slice::from_ref(&arg),
+        )
+        .unwrap();
+    assert_eq!(from_value::<i64>(&id).unwrap(), 7);
+
+    // Records pass through scripts unchanged.
+    let same = engine.call(r"(\o (o))", &[arg]).unwrap();
+    assert_eq!(from_value::<Order>(&same).unwrap(), order);
+
+    let items = to_value(&order.items).unwrap();
+    let skus = engine
+        .call(
+            r#"(\items ($list_push ($json_to_str ($json_get ($list_head items) "sku")) ~))"#,
+            &[items],
+        )
+        .unwrap();
+    assert_eq!(from_value::<Vec<String>>(&skus).unwrap(), vec!["a"]);
+}
+
+#[test]
+fn test_from_value() {
+    assert!(from_value::<Vec<i64>>(&OwnedValue::Empty)
+        .unwrap()
+        .is_empty());
+    assert_eq!(from_value::<Option<i64>>(&OwnedValue::Empty).unwrap(), None);
+    let mut map = BTreeMap::new();
+    map.insert("k".to_string(), 1);
+    let back: BTreeMap<String, i64> = from_value(&to_value(&map).unwrap()).unwrap();
+    assert_eq!(back, map);
+    match from_value::<String>(&OwnedValue::Int(1)) {
+        Err(RuntimeError::TypeMismatch(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert!(from_value::<f64>(&OwnedValue::Float(f64::NAN)).is_err());
+}
@@ -0,0 +1,28 @@
+//! Resource usage of evaluations, for billing and capacity planning.
+//!
+//! Unlike the step limit, metrics never stop an evaluation; they are read
+//! from the `EvalContext` afterwards. Counters accumulate over every
+//! evaluation in the context, like `EvalContext::steps`.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    /// Number of expressions evaluated.
+    pub steps: u64,
+    /// Deepest nesting of expressions being evaluated at the same time,
+    /// which bounds the native stack used.
+    pub peak_depth: u32,
+    /// Number of slots written by host functions, e.g. one per list element.
+    pub slots_allocated: u64,
+    /// Most slots allocated at the same time.
+    pub peak_slots: usize,
+    /// Number of calls of each host function, by name without the `$`.
+    pub host_calls: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    pub fn total_host_calls(&self) -> u64 {
+        self.host_calls.values().sum()
+    }
+}
@@ -0,0 +1,70 @@
+//! Rewrites that make programs cheaper to evaluate without changing what
+//! they evaluate to, applied by `xlc compile` and by engines with an
+//! optimization level set with `Engine::set_opt_level`.
+//!
+//! Passes assume host functions named like core library ones behave like
+//! them, so optimize programs for engines that do not replace the core
+//! library.
+
+use crate::ast::visit::{self, Folder};
+use crate::ast::*;
+use std::rc::Rc;
+
+/// Highest optimization level with passes of its own.
+pub const MAX_LEVEL: u32 = 1;
+
+/// Optimizes `e` at `level`:
+///
+/// - 0 leaves it as it is;
+/// - 1 and above fold `$if` on literal conditions and int `$add`, `$sub`
+///   and `$mul` on literal operands.
+pub fn optimize(e: &Expr, level: u32) -> Expr {
+    if level == 0 {
+        return e.clone();
+    }
+    visit::fold(&mut ConstantFolder, e).unwrap()
+}
+
+struct ConstantFolder;
+
+impl Folder for ConstantFolder {
+    type Error = ();
+
+    fn post_apply(&mut self, _e: &Expr, target: Expr, params: Vec<Expr>) -> Result<Expr, ()> {
+        let host = match *target.body {
+            ExprBody::Abstract {
+                body: AbstractBody::Host(ref name),
+                ..
+            } => Some(name.as_str()),
+            _ => None,
+        };
+        let consts: Vec<Option<&ConstExpr>> = params
+            .iter()
+            .map(|p| match *p.body {
+                ExprBody::Const(ref c) => Some(c),
+                _ => None,
+            })
+            .collect();
+        let folded = match (host, consts.as_slice()) {
+            (Some("if"), [Some(ConstExpr::Bool(c)), _, _]) => {
+                return Ok(params[if *c { 1 } else { 2 }].clone())
+            }
+            // Overflowing operations are left for evaluation to report.
+            (Some(op), [Some(ConstExpr::Int(a)), Some(ConstExpr::Int(b))]) => match op {
+                "add" => a.checked_add(*b),
+                "sub" => a.checked_sub(*b),
+                "mul" => a.checked_mul(*b),
+                _ => None,
+            },
+            _ => None,
+        };
+        Ok(match folded {
+            Some(v) => Expr {
+                body: Rc::new(ExprBody::Const(ConstExpr::Int(v))),
+            },
+            None => Expr {
+                body: Rc::new(ExprBody::Apply { target, params }),
+            },
+        })
+    }
+}
@@ -0,0 +1,24 @@
+use crate::optimize::*;
+use crate::parser::parse_expr;
+
+fn optimized(src: &str, level: u32) -> String {
+    optimize(&parse_expr(src).unwrap(), level).to_string()
+}
+
+#[test]
+fn test_optimize() {
+    assert_eq!(optimized("($add 1 ($mul 2 3))", 0), "($add 1 ($mul 2 3))");
+    assert_eq!(optimized("($add 1 ($mul 2 3))", 1), "(7)");
+    assert_eq!(
+        optimized(r#"($if ($lt 1 2) "a" ($if true "b" "c"))"#, 1),
+        r#"($if ($lt 1 2) "a" "b")"#
+    );
+    assert_eq!(
+        optimized(r"((\x ($sub x ($sub 3 1))) 5)", 1),
+        r"((\x ($sub x 2)) 5)"
+    );
+    assert_eq!(
+        optimized("($add 9223372036854775807 1)", 1),
+        "($add 9223372036854775807 1)"
+    );
+}
@@ -1,10 +1,33 @@
 use crate::ast::*;
+use crate::definitions::Definitions;
 use crate::error::*;
+use crate::host::{HostFunction, Param, Signature};
+use crate::macros::{self, Macro, MacroTable};
+use crate::typeck::TypeDescription;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::num::IntErrorKind;
 use std::rc::Rc;
 
+/// Maximum nesting depth of parentheses accepted by the parser.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
 pub struct TokenStream<'a> {
     raw: &'a [u8],
     pos: usize,
+    depth: usize,
+    /// Where the most recently read token starts.
+    token_start: usize,
+    /// Spans of the expressions parsed so far, if requested.
+    spans: Option<SpanMap>,
+    /// For each enclosing `quote`, the expressions unquoted in it so far.
+    quotes: Vec<Vec<Expr>>,
+    /// Whether any `quote` has been parsed.
+    quoted: bool,
+    /// Whether any call with named arguments has been parsed.
+    named: bool,
+    /// Type aliases declared in the source.
+    types: BTreeMap<String, TypeDescription>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -17,6 +40,9 @@ pub enum Token<'a> {
     EmptyLiteral,
     IntLiteral(i64),
     FloatLiteral(f64),
+    /// Borrowed from the source unless it has escapes.
+    StringLiteral(Cow<'a, str>),
+    BytesLiteral(Vec<u8>),
 }
 
 fn token_end<F: Fn(u8) -> bool>(raw: &[u8], begin: usize, predicate: F) -> usize {
@@ -33,7 +59,101 @@ impl<'a> TokenStream<'a> {
         TokenStream {
             raw: raw.as_bytes(),
             pos: 0,
+            depth: 0,
+            token_start: 0,
+            spans: None,
+            quotes: Vec::new(),
+            quoted: false,
+            named: false,
+            types: BTreeMap::new(),
+        }
+    }
+
+    /// Consumes the next token if it is `expected`.
+    fn next_if(&mut self, expected: &Token) -> Result<bool, ParseError> {
+        let (pos, depth, token_start) = (self.pos, self.depth, self.token_start);
+        if self.next_token()? == *expected {
+            return Ok(true);
+        }
+        self.pos = pos;
+        self.depth = depth;
+        self.token_start = token_start;
+        Ok(false)
+    }
+
+    /// Records that `e` was parsed from `start` up to the current position.
+    fn record_span(&mut self, e: &Expr, start: usize) {
+        if let Some(ref mut spans) = self.spans {
+            spans.insert(
+                e,
+                Span {
+                    start,
+                    end: self.pos,
+                },
+            );
+        }
+    }
+
+    /// Reads the rest of a string literal after its opening quote.
+    /// Supported escapes are `\"`, `\\`, `\n`, `\r` and `\t`.
+    fn string_literal(&mut self) -> Result<Cow<'a, str>, ParseError> {
+        let end = token_end(self.raw, self.pos, |x| x == b'"' || x == b'\\');
+        if self.raw.get(end) == Some(&b'"') {
+            let s = ::std::str::from_utf8(&self.raw[self.pos..end])
+                .map_err(|_| ParseError::InvalidUtf8)?;
+            self.pos = end + 1;
+            return Ok(Cow::Borrowed(s));
+        }
+        let mut out = Vec::new();
+        loop {
+            let ch = match self.raw.get(self.pos) {
+                Some(&ch) => ch,
+                None => return Err(ParseError::Custom("unterminated string literal".into())),
+            };
+            self.pos += 1;
+            match ch {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.raw.get(self.pos) {
+                        Some(b'"') => b'"',
+                        Some(b'\\') => b'\\',
+                        Some(b'n') => b'\n',
+                        Some(b'r') => b'\r',
+                        Some(b't') => b'\t',
+                        _ => {
+                            return Err(ParseError::Custom(
+                                "invalid escape in string literal".into(),
+                            ))
+                        }
+                    };
+                    self.pos += 1;
+                    out.push(escaped);
+                }
+                ch => out.push(ch),
+            }
+        }
+        String::from_utf8(out)
+            .map(Cow::Owned)
+            .map_err(|_| ParseError::InvalidUtf8)
+    }
+
+    /// Reads the hex digits of a bytes literal, after the opening `0x"`.
+    fn bytes_literal(&mut self) -> Result<Vec<u8>, ParseError> {
+        let start = self.pos;
+        self.pos = token_end(self.raw, self.pos, |x| x == b'"');
+        if self.pos == self.raw.len() {
+            return Err(ParseError::Custom("unterminated bytes literal".into()));
+        }
+        let digits = &self.raw[start..self.pos];
+        self.pos += 1;
+        if !digits.len().is_multiple_of(2) || !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(ParseError::Custom("invalid hex in bytes literal".into()));
         }
+        let nibble = |x: u8| (x as char).to_digit(16).unwrap() as u8;
+        Ok(digits
+            .chunks(2)
+            .map(|pair| nibble(pair[0]) << 4 | nibble(pair[1]))
+            .collect())
     }
 
     pub fn next_token(&mut self) -> Result<Token<'a>, ParseError> {
@@ -41,28 +161,42 @@ impl<'a> TokenStream<'a> {
             return Err(ParseError::UnexpectedEnd);
         }
 
+        self.token_start = self.pos;
         let ch = self.raw[self.pos];
         self.pos += 1;
 
         let ret = match ch {
-            b'(' => Ok(Token::ExprBegin),
-            b')' => Ok(Token::ExprEnd),
+            b'(' => {
+                self.depth += 1;
+                if self.depth > MAX_NESTING_DEPTH {
+                    return Err(ParseError::NestingTooDeep);
+                }
+                Ok(Token::ExprBegin)
+            }
+            b')' => {
+                self.depth = self.depth.saturating_sub(1);
+                Ok(Token::ExprEnd)
+            }
             b'\\' => Ok(Token::Lambda),
             b'~' => Ok(Token::EmptyLiteral),
             b'$' => {
                 let start = self.pos;
                 self.pos = token_end(self.raw, self.pos, |x| {
-                    !(x.is_ascii_alphanumeric() || x == b'_')
+                    !(x.is_ascii_alphanumeric() || x == b'_' || x == b'.')
                 });
-                Ok(Token::HostFunction(
-                    ::std::str::from_utf8(&self.raw[start..self.pos])
-                        .map_err(|_| ParseError::InvalidUtf8)?,
-                ))
+                let name = ::std::str::from_utf8(&self.raw[start..self.pos])
+                    .map_err(|_| ParseError::InvalidUtf8)?;
+                // Namespaced names such as `$myco.lookup`.
+                if name.contains('.') && name.split('.').any(|x| x.is_empty()) {
+                    return Err(ParseError::InvalidToken);
+                }
+                Ok(Token::HostFunction(name))
             }
             b'#' => {
                 self.pos = token_end(self.raw, self.pos, |x| x == b'\r' || x == b'\n');
                 self.next_token()
             }
+            b'"' => self.string_literal().map(Token::StringLiteral),
             x if x.is_ascii_alphabetic() || x == b'_' => {
                 let start = self.pos - 1;
                 self.pos = token_end(self.raw, self.pos, |x| {
@@ -73,20 +207,42 @@ impl<'a> TokenStream<'a> {
                         .map_err(|_| ParseError::InvalidUtf8)?,
                 ))
             }
+            b'0' if self.raw[self.pos..].starts_with(b"x\"") => {
+                self.pos += 2;
+                self.bytes_literal().map(Token::BytesLiteral)
+            }
             x if x.is_ascii_digit() => {
                 let start = self.pos - 1;
                 self.pos = token_end(self.raw, self.pos, |x| !x.is_ascii_digit() && x != b'.');
+                let span = Span {
+                    start,
+                    end: self.pos,
+                };
                 Ok(::std::str::from_utf8(&self.raw[start..self.pos])
                     .map_err(|_| ParseError::InvalidUtf8)
                     .and_then(|v| {
-                        if v.find(|x| x == '.').is_some() {
-                            v.parse::<f64>()
-                                .map(Token::FloatLiteral)
-                                .map_err(|_| ParseError::InvalidNumber)
+                        let invalid = || ParseError::InvalidNumber {
+                            literal: v.to_string(),
+                            span,
+                        };
+                        let overflow = || ParseError::NumberOverflow {
+                            literal: v.to_string(),
+                            span,
+                        };
+                        if v.contains('.') {
+                            match v.parse::<f64>() {
+                                Ok(x) if x.is_infinite() => Err(overflow()),
+                                Ok(x) => Ok(Token::FloatLiteral(x)),
+                                Err(_) => Err(invalid()),
+                            }
                         } else {
-                            v.parse::<i64>()
-                                .map(Token::IntLiteral)
-                                .map_err(|_| ParseError::InvalidNumber)
+                            v.parse::<i64>().map(Token::IntLiteral).map_err(|e| {
+                                if *e.kind() == IntErrorKind::PosOverflow {
+                                    overflow()
+                                } else {
+                                    invalid()
+                                }
+                            })
                         }
                     })?)
             }
@@ -101,80 +257,410 @@ impl<'a> TokenStream<'a> {
     }
 }
 
+/// Source text as a tree of tokens, borrowing names and string literals
+/// from the source, for tools that read programs without evaluating them,
+/// such as formatters and indexers. Building one allocates only for lists
+/// and for literals with escapes or bytes: names are neither copied nor
+/// renamed, and macros, named arguments and forms such as `quote` are left
+/// as written.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Syntax<'a> {
+    /// A token other than a parenthesis.
+    Token(Token<'a>),
+    /// A parenthesized list.
+    List(Vec<Syntax<'a>>),
+}
+
+/// Parses `input` into its top-level forms, e.g. the `defmacro` and `type`
+/// declarations and the expression of a program, without building an AST.
+/// Only the tokens and parentheses are checked.
+pub fn parse_syntax(input: &str) -> Result<Vec<Syntax<'_>>, ParseError> {
+    let mut ts = TokenStream::new(input);
+    let mut forms = Vec::new();
+    loop {
+        match ts.next_token() {
+            Ok(Token::ExprBegin) => forms.push(Syntax::List(_parse_syntax(&mut ts)?)),
+            Ok(Token::ExprEnd) => return Err(ParseError::BracketMismatch),
+            Ok(_) => return Err(ParseError::ExpectingExprBegin),
+            Err(ParseError::UnexpectedEnd) => return Ok(forms),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Parses the rest of a list, after its opening parenthesis.
+fn _parse_syntax<'a>(ts: &mut TokenStream<'a>) -> Result<Vec<Syntax<'a>>, ParseError> {
+    let mut items = Vec::new();
+    loop {
+        match ts.next_token()? {
+            Token::ExprEnd => return Ok(items),
+            Token::ExprBegin => items.push(Syntax::List(_parse_syntax(ts)?)),
+            tk => items.push(Syntax::Token(tk)),
+        }
+    }
+}
+
+/// Options controlling how source text is turned into an AST.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Names that may be referenced without being bound, e.g. the names in a
+    /// `Definitions` store.
+    pub globals: BTreeSet<String>,
+
+    /// Host function signatures used to resolve named arguments.
+    pub host_signatures: BTreeMap<String, Signature>,
+
+    /// Signatures of globals, such as definitions bound to lambdas, used to
+    /// resolve named arguments.
+    pub signatures: BTreeMap<String, Signature>,
+
+    /// Type aliases usable in `(the ty value)`, besides those the source
+    /// declares.
+    pub types: BTreeMap<String, TypeDescription>,
+}
+
+impl ParseOptions {
+    /// Records the signatures of `host_functions` for named-argument calls.
+    pub fn add_hosts<'a, H: IntoIterator<Item = (String, &'a dyn HostFunction)>>(
+        &mut self,
+        host_functions: H,
+    ) {
+        for (name, hf) in host_functions {
+            if let Some(sig) = hf.signature() {
+                self.host_signatures.insert(name, sig);
+            }
+        }
+    }
+
+    /// Records the parameters of the definitions bound to lambdas, for
+    /// named-argument calls.
+    pub fn add_definitions(&mut self, definitions: &Definitions) {
+        for name in definitions.names() {
+            let def = definitions.get(&name).unwrap();
+            if let ExprBody::Abstract {
+                ref params,
+                body: AbstractBody::Expr(_),
+            } = *def.expr.body
+            {
+                let params = params.iter().map(|p| Param::required(source_name(p)));
+                self.signatures
+                    .insert(name, Signature::new(params.collect()));
+            }
+        }
+    }
+
+    /// Declares `name` as an alias of the type expression `source`, e.g.
+    /// `(list float)`, as `(type name ty)` would.
+    pub fn define_type(&mut self, name: &str, source: &str) -> Result<(), ParseError> {
+        check_type_name(name, &self.types)?;
+        let mut ts = TokenStream::new(source);
+        let tk = ts.next_token()?;
+        let ty = _parse_type(&mut ts, tk, self)?;
+        if token_end(ts.raw, ts.pos, |x| !x.is_ascii_whitespace()) != ts.raw.len() {
+            return Err(ParseError::Custom(format!(
+                "type `{}` takes a single type expression",
+                name
+            )));
+        }
+        self.types.insert(name.to_string(), ty);
+        Ok(())
+    }
+}
+
 pub fn parse_expr(input: &str) -> Result<Expr, ParseError> {
+    parse_expr_with_options(input, &ParseOptions::default())
+}
+
+/// Parses an expression that may refer to `globals` (e.g. the names in a
+/// `Definitions` store) without binding them.
+pub fn parse_expr_with_globals(input: &str, globals: BTreeSet<String>) -> Result<Expr, ParseError> {
+    parse_expr_with_options(
+        input,
+        &ParseOptions {
+            globals,
+            ..Default::default()
+        },
+    )
+}
+
+pub fn parse_expr_with_options(input: &str, opts: &ParseOptions) -> Result<Expr, ParseError> {
+    parse_root(&mut TokenStream::new(input), opts)
+}
+
+/// Like `parse_expr_with_options`, but also returns where in `input` each
+/// node of the expression came from, for diagnostics.
+pub fn parse_expr_with_spans(
+    input: &str,
+    opts: &ParseOptions,
+) -> Result<(Expr, SpanMap), ParseError> {
     let mut ts = TokenStream::new(input);
-    match ts.next_token()? {
-        Token::ExprBegin => {
-            let ret = rename_expr(&_parse_expr(&mut ts)?, &mut RenameContext::default());
-            if token_end(ts.raw, ts.pos, |x| !x.is_ascii_whitespace()) != ts.raw.len() {
-                return Err(ParseError::BracketMismatch);
+    ts.spans = Some(SpanMap::default());
+    let e = parse_root(&mut ts, opts)?;
+    let mut spans = ts.spans.take().unwrap();
+    spans.set_root(&e);
+    Ok((e, spans))
+}
+
+fn parse_root(ts: &mut TokenStream, opts: &ParseOptions) -> Result<Expr, ParseError> {
+    let mut macros = MacroTable::default();
+    loop {
+        if ts.next_token()? != Token::ExprBegin {
+            return Err(ParseError::ExpectingExprBegin);
+        }
+        let start = ts.token_start;
+        if ts.next_if(&Token::Identifier("defmacro"))? {
+            _parse_defmacro(ts, opts, &mut macros)?;
+            continue;
+        }
+        if ts.next_if(&Token::Identifier("type"))? {
+            _parse_type_decl(ts, opts)?;
+            continue;
+        }
+
+        let ret = if macros.is_empty() {
+            let mut ctx = RenameContext::with_globals(opts.globals.clone());
+            _parse_expr(ts, opts, &mut ctx)?
+        } else {
+            let raw = _parse_expr(ts, opts, &mut RenameContext::raw(in_scope(opts, &macros)))?;
+            // Spans refer to the nodes of the raw AST, which renaming replaces.
+            if let Some(ref mut spans) = ts.spans {
+                *spans = SpanMap::default();
             }
+            let expanded = macros::expand(&raw, &macros)?;
+            rename_expr(
+                &expanded,
+                &mut RenameContext::with_globals(opts.globals.clone()),
+            )?
+        };
+        ts.record_span(&ret, start);
+        let ret = if ts.named {
+            resolve_named_calls(&ret, opts, ts.spans.as_mut())?
+        } else {
             ret
+        };
+        if token_end(ts.raw, ts.pos, |x| !x.is_ascii_whitespace()) != ts.raw.len() {
+            return Err(ParseError::BracketMismatch);
         }
-        _ => Err(ParseError::ExpectingExprBegin),
+        if ts.quoted {
+            visit::walk(&mut QuoteCheck, &ret)?;
+        }
+        return Ok(ret);
     }
 }
 
-fn _parse_expr<'a>(input: &mut TokenStream<'a>) -> Result<Expr, ParseError> {
-    let mut apply_target: Option<Expr> = None;
-    let mut apply_params: Vec<Expr> = Vec::new();
+/// Names in scope in source that uses `macros`, besides those it binds.
+fn in_scope(opts: &ParseOptions, macros: &MacroTable) -> BTreeSet<String> {
+    let mut names = opts.globals.clone();
+    names.extend(macros.names().cloned());
+    names
+}
 
+/// Parses the rest of `(defmacro name (params...) template)`.
+fn _parse_defmacro(
+    ts: &mut TokenStream,
+    opts: &ParseOptions,
+    macros: &mut MacroTable,
+) -> Result<(), ParseError> {
+    let name = match ts.next_token()? {
+        Token::Identifier(name) => name,
+        _ => return Err(ParseError::Custom("expecting a macro name".into())),
+    };
+    if ts.next_token()? != Token::ExprBegin {
+        return Err(ParseError::Custom(format!(
+            "expecting the parameter list of macro `{}`",
+            name
+        )));
+    }
+    let mut params = Vec::new();
     loop {
-        let e = match input.next_token()? {
-            Token::Identifier(id) => Expr {
-                body: Rc::new(match id {
-                    "true" => ExprBody::Const(ConstExpr::Bool(true)),
-                    "false" => ExprBody::Const(ConstExpr::Bool(false)),
-                    _ => ExprBody::Name(id.to_string()),
-                }),
-            },
-            Token::EmptyLiteral => Expr {
-                body: Rc::new(ExprBody::Const(ConstExpr::Empty)),
-            },
-            Token::IntLiteral(v) => Expr {
-                body: Rc::new(ExprBody::Const(ConstExpr::Int(v))),
-            },
-            Token::FloatLiteral(v) => Expr {
-                body: Rc::new(ExprBody::Const(ConstExpr::Float(v))),
-            },
-            Token::ExprBegin => _parse_expr(input)?,
+        match ts.next_token()? {
+            Token::Identifier(p) => params.push(p.to_string()),
             Token::ExprEnd => break,
-            Token::Lambda => {
-                let mut param_names: Vec<String> = Vec::new();
-                let end_tk = loop {
-                    let tk = input.next_token()?;
-                    if let Token::Identifier(id) = tk {
-                        param_names.push(id.to_string());
-                    } else {
-                        break tk;
-                    }
-                };
-                if end_tk != Token::ExprBegin {
-                    return Err(ParseError::ExpectingExprBegin);
-                }
-                let body = _parse_expr(input)?;
-                Expr {
-                    body: Rc::new(ExprBody::Abstract {
-                        params: param_names,
-                        body: AbstractBody::Expr(body),
-                    }),
-                }
+            _ => return Err(ParseError::InvalidToken),
+        }
+    }
+    let mut names = in_scope(opts, macros);
+    names.insert(name.to_string());
+    names.extend(params.iter().cloned());
+    let tk = ts.next_token()?;
+    let template = _parse_element(ts, tk, opts, &mut RenameContext::raw(names))?;
+    if ts.next_token()? != Token::ExprEnd {
+        return Err(ParseError::Custom(format!(
+            "macro `{}` takes a single template",
+            name
+        )));
+    }
+    macros.define(name, Macro { params, template })
+}
+
+/// Types that are not aliases.
+const BUILTIN_TYPES: &[&str] = &[
+    "int", "float", "decimal", "bool", "string", "bytes", "empty", "dyn", "list",
+];
+
+/// Checks that `name` can be declared as a type alias besides `types`.
+fn check_type_name(
+    name: &str,
+    types: &BTreeMap<String, TypeDescription>,
+) -> Result<(), ParseError> {
+    if BUILTIN_TYPES.contains(&name) {
+        return Err(ParseError::Custom(format!(
+            "cannot redefine built-in type `{}`",
+            name
+        )));
+    }
+    if types.contains_key(name) {
+        return Err(ParseError::Custom(format!(
+            "type `{}` is defined twice",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Parses the rest of `(type name ty)`.
+fn _parse_type_decl(ts: &mut TokenStream, opts: &ParseOptions) -> Result<(), ParseError> {
+    let name = match ts.next_token()? {
+        Token::Identifier(name) => name,
+        _ => return Err(ParseError::Custom("expecting a type name".into())),
+    };
+    check_type_name(name, &opts.types)?;
+    check_type_name(name, &ts.types)?;
+    let tk = ts.next_token()?;
+    let ty = _parse_type(ts, tk, opts)?;
+    if ts.next_token()? != Token::ExprEnd {
+        return Err(ParseError::Custom(format!(
+            "type `{}` takes a single type expression",
+            name
+        )));
+    }
+    ts.types.insert(name.to_string(), ty);
+    Ok(())
+}
+
+/// Parses a type expression: `int`, `float`, `decimal`, `bool`, `string`,
+/// `bytes`, `empty`, `dyn`, `(list ty)` or the name of an alias.
+fn _parse_type(
+    ts: &mut TokenStream,
+    tk: Token,
+    opts: &ParseOptions,
+) -> Result<TypeDescription, ParseError> {
+    match tk {
+        Token::Identifier("int") => Ok(TypeDescription::Int),
+        Token::Identifier("float") => Ok(TypeDescription::Float),
+        Token::Identifier("decimal") => Ok(TypeDescription::Decimal),
+        Token::Identifier("bool") => Ok(TypeDescription::Bool),
+        Token::Identifier("string") => Ok(TypeDescription::String),
+        Token::Identifier("bytes") => Ok(TypeDescription::Bytes),
+        Token::Identifier("empty") => Ok(TypeDescription::Empty),
+        Token::Identifier("dyn") => Ok(TypeDescription::Dynamic),
+        Token::Identifier(name) => match ts.types.get(name).or_else(|| opts.types.get(name)) {
+            Some(ty) => Ok(TypeDescription::Named {
+                name: name.to_string(),
+                ty: Box::new(ty.clone()),
+            }),
+            None => Err(ParseError::Custom(format!("unknown type `{}`", name))),
+        },
+        Token::ExprBegin => {
+            if !ts.next_if(&Token::Identifier("list"))? {
+                return Err(ParseError::Custom("expecting (list ty)".into()));
             }
-            Token::HostFunction(name) => Expr {
+            let tk = ts.next_token()?;
+            let inner = _parse_type(ts, tk, opts)?;
+            if ts.next_token()? != Token::ExprEnd {
+                return Err(ParseError::Custom("expecting (list ty)".into()));
+            }
+            Ok(TypeDescription::List(Box::new(inner)))
+        }
+        _ => Err(ParseError::Custom("expecting a type".into())),
+    }
+}
+
+/// Parses the rest of `(the ty value)`, which becomes
+/// `($the (\$type#ty ($type#ty)) witness value)`; see `corelib::TheOp`.
+fn _parse_the<'a>(
+    input: &mut TokenStream<'a>,
+    opts: &ParseOptions,
+    ctx: &mut RenameContext,
+) -> Result<Expr, ParseError> {
+    let tk = input.next_token()?;
+    let ty = _parse_type(input, tk, opts)?;
+    let witness = ty
+        .witness()
+        .ok_or_else(|| ParseError::Custom(format!("cannot annotate values of type {}", ty)))?;
+    let value = _parse_operand(input, opts, ctx, "the")?;
+
+    let name = format!("$type#{}", ty);
+    let carrier = Expr {
+        body: Rc::new(ExprBody::Abstract {
+            params: vec![name.clone()],
+            body: AbstractBody::Expr(Expr {
+                body: Rc::new(ExprBody::Name(name)),
+            }),
+        }),
+    };
+    Ok(Expr {
+        body: Rc::new(ExprBody::Apply {
+            target: Expr {
                 body: Rc::new(ExprBody::Abstract {
                     params: vec![],
-                    body: AbstractBody::Host(name.to_string()),
+                    body: AbstractBody::Host("the".into()),
                 }),
             },
+            params: vec![carrier, witness, value],
+        }),
+    })
+}
+
+/// Parses the rest of a parenthesized expression. Bound names are renamed
+/// with `ctx` as they are read, like `rename_expr` does, so each identifier
+/// is copied out of the source only once.
+fn _parse_expr<'a>(
+    input: &mut TokenStream<'a>,
+    opts: &ParseOptions,
+    ctx: &mut RenameContext,
+) -> Result<Expr, ParseError> {
+    let mut apply_target: Option<Expr> = None;
+    let mut apply_params: Vec<Expr> = Vec::new();
+    let mut named_params: Vec<(String, Expr)> = Vec::new();
+
+    loop {
+        let e = match input.next_token()? {
+            Token::ExprEnd => break,
+            Token::Identifier("quote") if apply_target.is_none() => {
+                return _parse_quote(input, opts, ctx);
+            }
+            Token::Identifier("unquote") if apply_target.is_none() => {
+                return _parse_unquote(input, opts, ctx);
+            }
+            Token::Identifier("the") if apply_target.is_none() => {
+                return _parse_the(input, opts, ctx);
+            }
+            Token::ExprBegin if apply_target.is_some() => match _parse_named(input, opts, ctx)? {
+                Some(named) => {
+                    named_params.push(named);
+                    continue;
+                }
+                None => _parse_element(input, Token::ExprBegin, opts, ctx)?,
+            },
+            tk => _parse_element(input, tk, opts, ctx)?,
         };
         if apply_target.is_none() {
             apply_target = Some(e);
+        } else if !named_params.is_empty() {
+            return Err(ParseError::Custom(
+                "positional argument after named arguments".into(),
+            ));
         } else {
             apply_params.push(e);
         }
     }
     if let Some(apply_target) = apply_target {
-        Ok(if apply_params.len() == 0 {
+        if !named_params.is_empty() {
+            input.named = true;
+            return Ok(named_call(apply_target, apply_params, named_params));
+        }
+        Ok(if apply_params.is_empty() {
             apply_target
         } else {
             Expr {
@@ -188,3 +674,527 @@ fn _parse_expr<'a>(input: &mut TokenStream<'a>) -> Result<Expr, ParseError> {
         Err(ParseError::ExpectingExprBody)
     }
 }
+
+/// Names that begin forms rather than refer to anything.
+const FORM_NAMES: &[&str] = &["quote", "unquote", "the", "true", "false"];
+
+/// Parses the rest of a named argument `(name value)`, after its opening
+/// parenthesis. An argument is named if it starts with a name that is not
+/// in scope; otherwise nothing is consumed and `None` is returned.
+fn _parse_named<'a>(
+    input: &mut TokenStream<'a>,
+    opts: &ParseOptions,
+    ctx: &mut RenameContext,
+) -> Result<Option<(String, Expr)>, ParseError> {
+    let (pos, depth, token_start) = (input.pos, input.depth, input.token_start);
+    let name = match input.next_token()? {
+        Token::Identifier(name) if !FORM_NAMES.contains(&name) && !ctx.in_scope(name) => name,
+        _ => {
+            input.pos = pos;
+            input.depth = depth;
+            input.token_start = token_start;
+            return Ok(None);
+        }
+    };
+    let tk = input.next_token()?;
+    if tk == Token::ExprEnd {
+        return Err(ParseError::Custom(format!("name not found: {}", name)));
+    }
+    let value = _parse_element(input, tk, opts, ctx)?;
+    if input.next_token()? != Token::ExprEnd {
+        return Err(ParseError::Custom(format!(
+            "named argument `{}` takes a single value",
+            name
+        )));
+    }
+    Ok(Some((name.to_string(), value)))
+}
+
+/// Parses the single expression and closing parenthesis that end a
+/// `(quote ...)`, `(unquote ...)` or `(the ty ...)` form.
+fn _parse_operand<'a>(
+    input: &mut TokenStream<'a>,
+    opts: &ParseOptions,
+    ctx: &mut RenameContext,
+    form: &str,
+) -> Result<Expr, ParseError> {
+    let tk = input.next_token()?;
+    if tk == Token::ExprEnd {
+        return Err(ParseError::Custom(format!(
+            "{} requires an expression",
+            form
+        )));
+    }
+    let e = _parse_element(input, tk, opts, ctx)?;
+    if input.next_token()? != Token::ExprEnd {
+        return Err(ParseError::Custom(format!(
+            "{} takes exactly one expression",
+            form
+        )));
+    }
+    Ok(e)
+}
+
+/// Name of the parameter standing for the `i`th `unquote` of a quote. The
+/// suffix keeps it apart from renamed source names.
+fn hole_name(i: usize) -> String {
+    format!("unquote_{}#q", i + 1)
+}
+
+/// Parses the rest of `(quote body)`, which becomes
+/// `($quote (\holes... body) values...)`: each `(unquote value)` in `body`
+/// is replaced with a hole, and `$quote` fills the holes with the values
+/// when it builds the expression.
+fn _parse_quote<'a>(
+    input: &mut TokenStream<'a>,
+    opts: &ParseOptions,
+    ctx: &mut RenameContext,
+) -> Result<Expr, ParseError> {
+    input.quotes.push(Vec::new());
+    let body = _parse_operand(input, opts, ctx, "quote");
+    let values = input.quotes.pop().unwrap();
+    let body = body?;
+
+    input.quoted = true;
+    let holes: Vec<String> = (0..values.len()).map(hole_name).collect();
+    let bound = bound_vars(&body);
+    for v in &values {
+        if let Some(name) = free_vars(v).into_iter().find(|n| bound.contains(n)) {
+            return Err(ParseError::Custom(format!(
+                "`{}` is bound inside the quote and cannot be unquoted",
+                source_name(&name)
+            )));
+        }
+    }
+
+    let mut params = vec![Expr {
+        body: Rc::new(ExprBody::Abstract {
+            params: holes,
+            body: AbstractBody::Expr(body),
+        }),
+    }];
+    params.extend(values);
+    Ok(Expr {
+        body: Rc::new(ExprBody::Apply {
+            target: Expr {
+                body: Rc::new(ExprBody::Abstract {
+                    params: vec![],
+                    body: AbstractBody::Host("quote".into()),
+                }),
+            },
+            params,
+        }),
+    })
+}
+
+/// Checks that quoted expressions only refer to globals and their holes.
+/// A quoted expression is evaluated on its own, where local variables of
+/// the program are not in scope.
+///
+/// Runs on the renamed AST, since macro expansion may introduce quotes.
+/// Renamed names are local; globals are not renamed.
+struct QuoteCheck;
+
+impl visit::Visitor for QuoteCheck {
+    type Error = ParseError;
+
+    fn pre_apply(&mut self, e: &Expr) -> Result<(), ParseError> {
+        let (target, params) = match *e.body {
+            ExprBody::Apply {
+                ref target,
+                ref params,
+            } => (target, params),
+            _ => return Ok(()),
+        };
+        match *target.body {
+            ExprBody::Abstract {
+                body: AbstractBody::Host(ref name),
+                ..
+            } if name == "quote" => {}
+            _ => return Ok(()),
+        }
+        let template = match params.first() {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        if let ExprBody::Abstract {
+            params: ref holes,
+            body: AbstractBody::Expr(ref body),
+        } = *template.body
+        {
+            if let Some(name) = free_vars(body)
+                .into_iter()
+                .find(|n| n.contains('#') && !holes.contains(n))
+            {
+                let name = source_name(&name);
+                return Err(ParseError::Custom(format!(
+                    "`{}` is bound outside the quote; splice it in with (unquote {})",
+                    name, name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the rest of `(unquote value)` within a quote.
+fn _parse_unquote<'a>(
+    input: &mut TokenStream<'a>,
+    opts: &ParseOptions,
+    ctx: &mut RenameContext,
+) -> Result<Expr, ParseError> {
+    let value = _parse_operand(input, opts, ctx, "unquote")?;
+    let values = match input.quotes.last_mut() {
+        Some(v) => v,
+        None => return Err(ParseError::Custom("unquote outside of quote".into())),
+    };
+    let name = hole_name(values.len());
+    values.push(value);
+    Ok(Expr {
+        body: Rc::new(ExprBody::Name(name)),
+    })
+}
+
+fn _parse_element<'a>(
+    input: &mut TokenStream<'a>,
+    tk: Token<'a>,
+    opts: &ParseOptions,
+    ctx: &mut RenameContext,
+) -> Result<Expr, ParseError> {
+    let start = input.token_start;
+    let e = match tk {
+        Token::Identifier(id) => Expr {
+            body: Rc::new(match id {
+                "true" => ExprBody::Const(ConstExpr::Bool(true)),
+                "false" => ExprBody::Const(ConstExpr::Bool(false)),
+                _ => ExprBody::Name(ctx.get_renamed(id)?),
+            }),
+        },
+        Token::EmptyLiteral => Expr {
+            body: Rc::new(ExprBody::Const(ConstExpr::Empty)),
+        },
+        Token::IntLiteral(v) => Expr {
+            body: Rc::new(ExprBody::Const(ConstExpr::Int(v))),
+        },
+        Token::FloatLiteral(v) => Expr {
+            body: Rc::new(ExprBody::Const(ConstExpr::Float(v))),
+        },
+        Token::StringLiteral(s) => Expr {
+            body: Rc::new(ExprBody::Const(ConstExpr::Str(s.into_owned()))),
+        },
+        Token::BytesLiteral(b) => Expr {
+            body: Rc::new(ExprBody::Const(ConstExpr::Bytes(b))),
+        },
+        Token::ExprBegin => _parse_expr(input, opts, ctx)?,
+        Token::ExprEnd => return Err(ParseError::BracketMismatch),
+        Token::Lambda => {
+            let mut param_names: Vec<&'a str> = Vec::new();
+            let end_tk = loop {
+                let tk = input.next_token()?;
+                if let Token::Identifier(id) = tk {
+                    param_names.push(id);
+                } else {
+                    break tk;
+                }
+            };
+            if end_tk != Token::ExprBegin {
+                return Err(ParseError::ExpectingExprBegin);
+            }
+            let body_start = input.token_start;
+            ctx.with_renamed(&param_names, |ctx| {
+                let params = param_names
+                    .iter()
+                    .map(|p| ctx.get_renamed(p))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let body = _parse_expr(input, opts, ctx)?;
+                input.record_span(&body, body_start);
+                Ok(Expr {
+                    body: Rc::new(ExprBody::Abstract {
+                        params,
+                        body: AbstractBody::Expr(body),
+                    }),
+                })
+            })?
+        }
+        Token::HostFunction(name) => Expr {
+            body: Rc::new(ExprBody::Abstract {
+                params: vec![],
+                body: AbstractBody::Host(name.to_string()),
+            }),
+        },
+    };
+    input.record_span(&e, start);
+    Ok(e)
+}
+
+/// Host function a call with named arguments is parsed into, as
+/// `($named# target n positional... "name" value...)` with `n` positional
+/// arguments. The parameters of the target may only be known once the
+/// whole expression has been parsed, e.g. for a lambda bound by a `let`
+/// that follows, so `resolve_named_calls` replaces these afterwards.
+const NAMED_CALL: &str = "named#";
+
+fn named_call(target: Expr, positional: Vec<Expr>, named: Vec<(String, Expr)>) -> Expr {
+    let mut params = vec![
+        target,
+        Expr {
+            body: Rc::new(ExprBody::Const(ConstExpr::Int(positional.len() as i64))),
+        },
+    ];
+    params.extend(positional);
+    for (name, value) in named {
+        params.push(Expr {
+            body: Rc::new(ExprBody::Const(ConstExpr::Str(name))),
+        });
+        params.push(value);
+    }
+    Expr {
+        body: Rc::new(ExprBody::Apply {
+            target: Expr {
+                body: Rc::new(ExprBody::Abstract {
+                    params: vec![],
+                    body: AbstractBody::Host(NAMED_CALL.into()),
+                }),
+            },
+            params,
+        }),
+    }
+}
+
+/// Replaces the calls `named_call` built in `e` with plain calls, moving
+/// the spans of replaced nodes over to their replacements.
+fn resolve_named_calls(
+    e: &Expr,
+    opts: &ParseOptions,
+    spans: Option<&mut SpanMap>,
+) -> Result<Expr, ParseError> {
+    let mut bound = BoundLambdas::default();
+    visit::walk(&mut bound, e)?;
+    visit::fold(
+        &mut NamedCalls {
+            opts,
+            locals: bound.0,
+            spans,
+        },
+        e,
+    )
+}
+
+/// Parameters of the lambdas bound to local names by applying a lambda
+/// literal to them, as in `((\f body) (\a b ...))`. Renamed names are
+/// unique, so the parameters are keyed by them.
+#[derive(Default)]
+struct BoundLambdas(BTreeMap<String, Vec<String>>);
+
+impl visit::Visitor for BoundLambdas {
+    type Error = ParseError;
+
+    fn pre_apply(&mut self, e: &Expr) -> Result<(), ParseError> {
+        if let ExprBody::Apply {
+            ref target,
+            ref params,
+        } = *e.body
+        {
+            if let ExprBody::Abstract {
+                params: ref names,
+                body: AbstractBody::Expr(_),
+            } = *target.body
+            {
+                for (name, value) in names.iter().zip(params) {
+                    if let Some(params) = lambda_params(value) {
+                        self.0.insert(name.clone(), params);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The source names of the parameters of `e`, if it is a lambda literal.
+fn lambda_params(e: &Expr) -> Option<Vec<String>> {
+    match *e.body {
+        ExprBody::Abstract {
+            ref params,
+            body: AbstractBody::Expr(_),
+        } => Some(params.iter().map(|p| source_name(p).to_string()).collect()),
+        _ => None,
+    }
+}
+
+struct NamedCalls<'a> {
+    opts: &'a ParseOptions,
+    locals: BTreeMap<String, Vec<String>>,
+    spans: Option<&'a mut SpanMap>,
+}
+
+impl<'a> NamedCalls<'a> {
+    /// Gives `new` the span of `old`, which it replaces.
+    fn replace(&mut self, old: &Expr, new: Expr) -> Expr {
+        if let Some(ref mut spans) = self.spans {
+            if let Some(span) = spans.get(old) {
+                spans.insert(&new, span);
+            }
+        }
+        new
+    }
+
+    /// The parameters of `target` and their defaults, if known.
+    fn params_of(&self, target: &Expr) -> Option<Vec<(String, Option<ConstExpr>)>> {
+        let sig = match *target.body {
+            ExprBody::Abstract {
+                body: AbstractBody::Host(ref name),
+                ..
+            } => self.opts.host_signatures.get(name)?,
+            ExprBody::Name(ref name) => match self.locals.get(name) {
+                Some(params) => return Some(params.iter().map(|p| (p.clone(), None)).collect()),
+                None => self.opts.signatures.get(name)?,
+            },
+            _ => {
+                let params = lambda_params(target)?;
+                return Some(params.into_iter().map(|p| (p, None)).collect());
+            }
+        };
+        Some(
+            sig.params
+                .iter()
+                .map(|p| (p.name.clone(), p.default.clone()))
+                .collect(),
+        )
+    }
+}
+
+impl<'a> visit::Folder for NamedCalls<'a> {
+    type Error = ParseError;
+
+    fn post_apply(
+        &mut self,
+        e: &Expr,
+        target: Expr,
+        params: Vec<Expr>,
+    ) -> Result<Expr, ParseError> {
+        let is_named_call = match *target.body {
+            ExprBody::Abstract {
+                body: AbstractBody::Host(ref name),
+                ..
+            } => name == NAMED_CALL,
+            _ => false,
+        };
+        if !is_named_call {
+            let new = Expr {
+                body: Rc::new(ExprBody::Apply { target, params }),
+            };
+            return Ok(self.replace(e, new));
+        }
+
+        let mut params = params.into_iter();
+        let target = params.next().unwrap();
+        let n = match *params.next().unwrap().body {
+            ExprBody::Const(ConstExpr::Int(n)) => n as usize,
+            _ => unreachable!("bug: malformed named call"),
+        };
+        let positional: Vec<Expr> = params.by_ref().take(n).collect();
+        let mut named = Vec::new();
+        while let (Some(name), Some(value)) = (params.next(), params.next()) {
+            match *name.body {
+                ExprBody::Const(ConstExpr::Str(ref name)) => named.push((name.clone(), value)),
+                _ => unreachable!("bug: malformed named call"),
+            }
+        }
+        let signature = match self.params_of(&target) {
+            Some(x) => x,
+            None => {
+                return Err(ParseError::Custom(format!(
+                    "name not found: {}",
+                    named[0].0
+                )))
+            }
+        };
+        let params = reorder_named_params(&signature, positional, named)?;
+        let new = Expr {
+            body: Rc::new(ExprBody::Apply { target, params }),
+        };
+        Ok(self.replace(e, new))
+    }
+
+    fn post_abstract(
+        &mut self,
+        e: &Expr,
+        params: Vec<String>,
+        body: AbstractBody,
+    ) -> Result<Expr, ParseError> {
+        let new = Expr {
+            body: Rc::new(ExprBody::Abstract { params, body }),
+        };
+        Ok(self.replace(e, new))
+    }
+
+    fn post_match(
+        &mut self,
+        e: &Expr,
+        value: Expr,
+        branches: Vec<(String, Expr)>,
+    ) -> Result<Expr, ParseError> {
+        let new = Expr {
+            body: Rc::new(ExprBody::Match { value, branches }),
+        };
+        Ok(self.replace(e, new))
+    }
+}
+
+/// Merges named arguments into the positional argument list of a call to
+/// a target with parameters `params`, following their order.
+///
+/// Skipped parameters that have defaults are filled in; trailing ones are
+/// left for typeck and eval to fill.
+fn reorder_named_params(
+    params: &[(String, Option<ConstExpr>)],
+    positional: Vec<Expr>,
+    named: Vec<(String, Expr)>,
+) -> Result<Vec<Expr>, ParseError> {
+    if positional.len() > params.len() {
+        return Err(ParseError::Custom("too many arguments".into()));
+    }
+
+    let mut slots: Vec<Option<Expr>> = positional.into_iter().map(Some).collect();
+    slots.resize(params.len(), None);
+
+    for (name, value) in named {
+        let i = match params.iter().position(|(p, _)| *p == name) {
+            Some(i) => i,
+            None => {
+                return Err(ParseError::Custom(format!(
+                    "`{}` is neither a name in scope nor a parameter of the call target",
+                    name
+                )))
+            }
+        };
+        if slots[i].is_some() {
+            return Err(ParseError::Custom(format!(
+                "parameter `{}` specified more than once",
+                name
+            )));
+        }
+        slots[i] = Some(value);
+    }
+
+    while slots.last().map(|v| v.is_none()).unwrap_or(false) && params[slots.len() - 1].1.is_some()
+    {
+        slots.pop();
+    }
+
+    slots
+        .into_iter()
+        .zip(params.iter())
+        .map(|(slot, (name, default))| match (slot, default) {
+            (Some(v), _) => Ok(v),
+            (None, Some(c)) => Ok(Expr {
+                body: Rc::new(ExprBody::Const(c.clone())),
+            }),
+            (None, None) => Err(ParseError::Custom(format!(
+                "missing argument for parameter `{}`",
+                name
+            ))),
+        })
+        .collect()
+}
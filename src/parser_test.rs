@@ -0,0 +1,330 @@
+use crate::ast::Span;
+use crate::error::ParseError;
+use crate::parser::*;
+
+#[test]
+fn test_number_literal_errors() {
+    match parse_expr("($add 1 99999999999999999999)") {
+        Err(ParseError::NumberOverflow { literal, span }) => {
+            assert_eq!(literal, "99999999999999999999");
+            assert_eq!(span, Span { start: 8, end: 28 });
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+
+    match parse_expr("($add 1 1.2.3)") {
+        Err(ParseError::InvalidNumber { literal, span }) => {
+            assert_eq!(literal, "1.2.3");
+            assert_eq!(span, Span { start: 8, end: 13 });
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+
+    assert_eq!(
+        parse_expr("(1.2.3)").unwrap_err().to_string(),
+        "invalid number literal `1.2.3`"
+    );
+
+    let huge_float = format!("({}.0)", "9".repeat(400));
+    match parse_expr(&huge_float) {
+        Err(e @ ParseError::NumberOverflow { .. }) => assert!(e.suggestion().is_some()),
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_named_arguments() {
+    let hm = crate::corelib::HostManager::new();
+    let mut opts = ParseOptions::default();
+    opts.add_hosts(hm.get_all());
+
+    assert_eq!(
+        parse_expr_with_options("($round (digits 2) (x 1.5))", &opts).unwrap(),
+        parse_expr("($round 1.5 2)").unwrap()
+    );
+    assert_eq!(
+        parse_expr_with_options("($round (x 1.5))", &opts).unwrap(),
+        parse_expr("($round 1.5)").unwrap()
+    );
+    assert_eq!(
+        parse_expr(r"((\a b ($sub a b)) (b 1) (a 2))").unwrap(),
+        parse_expr(r"((\a b ($sub a b)) 2 1)").unwrap()
+    );
+    assert_eq!(
+        parse_expr(r"((\f (f (b 1) (a 2))) (\a b ($sub a b)))").unwrap(),
+        parse_expr(r"((\f (f 2 1)) (\a b ($sub a b)))").unwrap()
+    );
+    assert_eq!(
+        parse_expr("(defmacro sub (x y) ($sub x y)) ((\\f (f (b 1) (a 2))) (\\a b (sub a b)))")
+            .unwrap(),
+        parse_expr(r"((\f (f 2 1)) (\a b ($sub a b)))").unwrap()
+    );
+
+    assert!(parse_expr_with_options("($round 1.5 (x 2.5))", &opts).is_err());
+    assert!(parse_expr_with_options("($round (y 1))", &opts).is_err());
+    assert!(parse_expr_with_options("($round (x 1) 2)", &opts).is_err());
+    assert!(parse_expr_with_options("($round (x 1 2))", &opts).is_err());
+    assert!(parse_expr(r"((\a b ($sub a b)) (a 1))").is_err());
+    assert!(parse_expr(r"((\f (f (a 1))) 1)").is_err());
+    assert!(parse_expr("($add (a 1))").is_err());
+    match parse_expr(r"((\a (a)) (b))") {
+        Err(ParseError::Custom(ref m)) => assert_eq!(m, "name not found: b"),
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_nesting_limit() {
+    let deep = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+    match parse_expr(&deep) {
+        Err(ParseError::NestingTooDeep) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_renaming_matches_rename_expr() {
+    use crate::ast::*;
+    use std::rc::Rc;
+
+    let name = |n: &str| Expr {
+        body: Rc::new(ExprBody::Name(n.into())),
+    };
+    let lambda = |p: &[&str], body: Expr| Expr {
+        body: Rc::new(ExprBody::Abstract {
+            params: p.iter().map(|p| p.to_string()).collect(),
+            body: AbstractBody::Expr(body),
+        }),
+    };
+    let apply = |target: Expr, params: Vec<Expr>| Expr {
+        body: Rc::new(ExprBody::Apply { target, params }),
+    };
+
+    let source = r"(\x y ((\x (x)) y x g))";
+    let raw = lambda(
+        &["x", "y"],
+        apply(
+            lambda(&["x"], name("x")),
+            vec![name("y"), name("x"), name("g")],
+        ),
+    );
+    let globals: std::collections::BTreeSet<String> = vec!["g".to_string()].into_iter().collect();
+    assert_eq!(
+        parse_expr_with_globals(source, globals.clone()).unwrap(),
+        rename_expr(&raw, &mut RenameContext::with_globals(globals)).unwrap()
+    );
+    match parse_expr(r"(\x (y))") {
+        Err(ParseError::Custom(msg)) => assert_eq!(msg, "name not found: y"),
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_shadowing_ends_with_lambda() {
+    use crate::ast::*;
+
+    let e = parse_expr(r"(\x ($add ((\x (x)) 10) x))").unwrap();
+    let (outer, body) = match *e.body {
+        ExprBody::Abstract {
+            ref params,
+            body: AbstractBody::Expr(ref body),
+        } => (params[0].clone(), body.clone()),
+        _ => panic!("not a lambda: {:?}", e),
+    };
+    match *body.body {
+        ExprBody::Apply { ref params, .. } => {
+            assert_eq!(*params[1].body, ExprBody::Name(outer));
+        }
+        _ => panic!("not an application: {:?}", body),
+    }
+
+    let engine = crate::engine::Engine::new();
+    assert_eq!(
+        engine.eval_str(r"((\x ($add ((\x (x)) 10) x)) 1)").unwrap(),
+        "11"
+    );
+}
+
+#[test]
+fn test_parse_with_spans() {
+    let source = r"(\x ($add x 10))";
+    let (e, spans) = parse_expr_with_spans(source, &ParseOptions::default()).unwrap();
+    assert_eq!(e, parse_expr(source).unwrap());
+    let body = match *e.body {
+        crate::ast::ExprBody::Abstract {
+            body: crate::ast::AbstractBody::Expr(ref body),
+            ..
+        } => body,
+        _ => panic!("expecting a lambda"),
+    };
+    let span = spans.get(body).unwrap();
+    assert_eq!(&source[span.start..span.end], "($add x 10)");
+    let arg = match *body.body {
+        crate::ast::ExprBody::Apply { ref params, .. } => &params[1],
+        _ => panic!("expecting an application"),
+    };
+    assert_eq!(spans.get(arg), Some(Span { start: 12, end: 14 }));
+}
+
+#[test]
+fn test_quote_errors() {
+    let err = |s: &str| match parse_expr(s) {
+        Err(ParseError::Custom(msg)) => msg,
+        other => panic!("unexpected result: {:?}", other),
+    };
+    assert_eq!(err("(unquote 1)"), "unquote outside of quote");
+    assert_eq!(err("(quote)"), "quote requires an expression");
+    assert_eq!(err("(quote 1 2)"), "quote takes exactly one expression");
+    assert_eq!(
+        err(r"(\x (quote ($add x 1)))"),
+        "`x` is bound outside the quote; splice it in with (unquote x)"
+    );
+    assert_eq!(
+        err(r"(quote (\y (unquote y)))"),
+        "`y` is bound inside the quote and cannot be unquoted"
+    );
+    assert!(parse_expr(r"(\x (quote ($add (unquote x) 1)))").is_ok());
+}
+
+#[test]
+fn test_type_decl_errors() {
+    let err = |s: &str| match parse_expr(s) {
+        Err(ParseError::Custom(msg)) => msg,
+        other => panic!("unexpected result: {:?}", other),
+    };
+    assert_eq!(
+        err("(type int float) (1)"),
+        "cannot redefine built-in type `int`"
+    );
+    assert_eq!(
+        err("(type Money float) (type Money int) (1)"),
+        "type `Money` is defined twice"
+    );
+    assert_eq!(err("(type Money cents) (1)"), "unknown type `cents`");
+    assert_eq!(err("(the Money 1)"), "unknown type `Money`");
+    assert_eq!(err("(type Prices (map float)) (1)"), "expecting (list ty)");
+    assert_eq!(
+        err("(type Money float int) (1)"),
+        "type `Money` takes a single type expression"
+    );
+    assert_eq!(err("(the int)"), "the requires an expression");
+    assert!(parse_expr("(type Money float) (type Prices (list Money)) (the Prices ~)").is_ok());
+
+    let mut opts = ParseOptions::default();
+    opts.define_type("Money", "float").unwrap();
+    assert!(opts.define_type("Money", "int").is_err());
+    assert!(parse_expr_with_options("(the Money 1.5)", &opts).is_ok());
+}
+
+#[test]
+fn test_string_literals() {
+    use crate::ast::{ConstExpr, ExprBody};
+
+    let e = parse_expr(r#"("a \"b\"\\\n")"#).unwrap();
+    assert_eq!(
+        *e.body,
+        ExprBody::Const(ConstExpr::Str("a \"b\"\\\n".into()))
+    );
+    assert_eq!(parse_expr(&e.to_string()).unwrap(), e);
+    match parse_expr(r#"("unterminated)"#) {
+        Err(ParseError::Custom(msg)) => assert_eq!(msg, "unterminated string literal"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match parse_expr(r#"("\q")"#) {
+        Err(ParseError::Custom(msg)) => assert_eq!(msg, "invalid escape in string literal"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_bytes_literals() {
+    use crate::ast::{ConstExpr, ExprBody};
+
+    let e = parse_expr(r#"(0x"00Ff10")"#).unwrap();
+    assert_eq!(
+        *e.body,
+        ExprBody::Const(ConstExpr::Bytes(vec![0x00, 0xff, 0x10]))
+    );
+    assert_eq!(e.to_string(), r#"(0x"00ff10")"#);
+    assert_eq!(parse_expr(&e.to_string()).unwrap(), e);
+    assert!(parse_expr(r#"(0x"")"#).is_ok());
+    assert!(parse_expr("(the bytes 0x\"01\")").is_ok());
+    match parse_expr(r#"(0x"0a)"#) {
+        Err(ParseError::Custom(msg)) => assert_eq!(msg, "unterminated bytes literal"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    for bad in &[r#"(0x"abc")"#, r#"(0x"zz")"#, r#"(0x"+1")"#] {
+        match parse_expr(bad) {
+            Err(ParseError::Custom(msg)) => assert_eq!(msg, "invalid hex in bytes literal"),
+            other => panic!("unexpected result for {}: {:?}", bad, other),
+        }
+    }
+}
+
+#[test]
+fn test_namespaced_host_names() {
+    let e = parse_expr("($myco.geo.lookup 1)").unwrap();
+    assert_eq!(e.to_string(), "($myco.geo.lookup 1)");
+    assert_eq!(parse_expr(&e.to_string()).unwrap(), e);
+    for bad in &["($myco. 1)", "($.lookup 1)", "($myco..lookup 1)"] {
+        match parse_expr(bad) {
+            Err(ParseError::InvalidToken) => {}
+            other => panic!("unexpected result for {}: {:?}", bad, other),
+        }
+    }
+}
+
+#[test]
+fn test_parse_syntax() {
+    use std::borrow::Cow;
+
+    let src =
+        "(defmacro twice (x) ($add x x))\n(\\x ($concat \"a\\tb\" \"cd\" (twice x))) # done\n";
+    let forms = parse_syntax(src).unwrap();
+    assert_eq!(forms.len(), 2);
+    let items = match forms[1] {
+        Syntax::List(ref items) => items,
+        ref x => panic!("unexpected form: {:?}", x),
+    };
+    assert_eq!(items[0], Syntax::Token(Token::Lambda));
+    assert_eq!(items[1], Syntax::Token(Token::Identifier("x")));
+    let call = match items[2] {
+        Syntax::List(ref call) => call,
+        ref x => panic!("unexpected form: {:?}", x),
+    };
+    assert_eq!(call[0], Syntax::Token(Token::HostFunction("concat")));
+
+    // Names and literals without escapes point into the source.
+    let within = |s: &str| src.as_bytes().as_ptr_range().contains(&s.as_ptr());
+    match (&items[1], &call[1], &call[2]) {
+        (
+            Syntax::Token(Token::Identifier(name)),
+            Syntax::Token(Token::StringLiteral(Cow::Owned(escaped))),
+            Syntax::Token(Token::StringLiteral(Cow::Borrowed(plain))),
+        ) => {
+            assert!(within(name));
+            assert_eq!(escaped, "a\tb");
+            assert_eq!(*plain, "cd");
+            assert!(within(plain));
+        }
+        x => panic!("unexpected tokens: {:?}", x),
+    }
+
+    assert_eq!(parse_syntax("").unwrap(), vec![]);
+    for (bad, err) in &[
+        ("(a))", ParseError::BracketMismatch),
+        ("a", ParseError::ExpectingExprBegin),
+        ("((a)", ParseError::UnexpectedEnd),
+        (
+            "(\"a)",
+            ParseError::Custom("unterminated string literal".into()),
+        ),
+    ] {
+        assert_eq!(parse_syntax(bad).unwrap_err().to_string(), err.to_string());
+    }
+    let deep = format!("{}{}", "(".repeat(10_000), ")".repeat(10_000));
+    assert_eq!(
+        parse_syntax(&deep).unwrap_err().to_string(),
+        ParseError::NestingTooDeep.to_string()
+    );
+}
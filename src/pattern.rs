@@ -0,0 +1,190 @@
+//! Regular expressions over strings, with the `regex` feature.
+//!
+//! Patterns use the syntax of the `regex` crate and match anywhere in the
+//! text unless anchored with `^` and `$`. Rules usually pass the same
+//! literal pattern on every call, so compiled patterns are kept in the
+//! `EvalContext` and reused by later calls, also across `reset`.
+
+use crate::ast::DataType;
+use crate::builtin::ValueType;
+use crate::corelib::{as_str, list_type, list_value, str_value, string_type, type_mismatch};
+use crate::error::*;
+use crate::eval::*;
+use crate::host::{HostFunction, Param, Signature};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Most patterns an `EvalContext` keeps compiled. Scripts that build
+/// patterns at runtime could otherwise grow the cache without bound; once
+/// it is full, it is emptied and starts over.
+pub const MAX_CACHED_PATTERNS: usize = 256;
+
+/// Compiled patterns, by source.
+#[derive(Debug, Default)]
+pub struct RegexCache {
+    patterns: HashMap<String, Regex>,
+}
+
+impl RegexCache {
+    /// Returns `pattern` compiled, compiling it if it is not cached yet.
+    pub fn get(&mut self, pattern: &str) -> Result<Regex, RuntimeError> {
+        if let Some(re) = self.patterns.get(pattern) {
+            return Ok(re.clone());
+        }
+        let re = Regex::new(pattern)
+            .map_err(|e| RuntimeError::Custom(format!("invalid regex: {}", e)))?;
+        if self.patterns.len() >= MAX_CACHED_PATTERNS {
+            self.patterns.clear();
+        }
+        self.patterns.insert(pattern.to_string(), re.clone());
+        Ok(re)
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+fn is_string_type(ty: &DataType) -> bool {
+    *ty == string_type() || *ty == DataType::Dynamic
+}
+
+/// Checks that the arguments of `name` are all strings.
+fn typeck_strings(name: &str, params: &[DataType], ret: DataType) -> Result<DataType, TypeError> {
+    if params.contains(&DataType::Divergent) {
+        return Ok(DataType::Divergent);
+    }
+    match params.iter().find(|ty| !is_string_type(ty)) {
+        Some(ty) => Err(TypeError::Custom(format!(
+            "{} expects strings, found {}",
+            name, ty
+        ))),
+        None => Ok(ret),
+    }
+}
+
+fn eval_string<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: LazyValue<'b>,
+) -> Result<String, RuntimeError> {
+    let v = v.eval(ectx)?;
+    match as_str(&v) {
+        Some(s) => Ok(s.to_string()),
+        None => Err(type_mismatch("string", &v)),
+    }
+}
+
+/// Evaluates the pattern argument and compiles it through the cache.
+fn eval_regex<'b, 'c>(
+    ectx: &mut EvalContext<'b, 'c>,
+    v: LazyValue<'b>,
+) -> Result<Regex, RuntimeError> {
+    let pattern = eval_string(ectx, v)?;
+    ectx.regex_cache().get(&pattern)
+}
+
+/// `($regex_match pattern text)`: whether `pattern` matches somewhere in
+/// `text`.
+#[derive(Debug)]
+pub struct RegexMatchOp;
+impl HostFunction for RegexMatchOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("pattern"),
+            Param::required("text"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        typeck_strings("regex_match", params, DataType::Value(ValueType::Bool))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let re = eval_regex(ectx, params.next().unwrap())?;
+        let text = eval_string(ectx, params.next().unwrap())?;
+        Ok(RuntimeValue::Bool(re.is_match(&text)))
+    }
+}
+
+/// `($regex_capture pattern text)`: the first match of `pattern` in `text`
+/// followed by its capture groups, or `~` if there is none. Groups that
+/// did not take part in the match are empty strings.
+#[derive(Debug)]
+pub struct RegexCaptureOp;
+impl HostFunction for RegexCaptureOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("pattern"),
+            Param::required("text"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        typeck_strings("regex_capture", params, list_type(string_type()))
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let re = eval_regex(ectx, params.next().unwrap())?;
+        let text = eval_string(ectx, params.next().unwrap())?;
+        let groups = match re.captures(&text) {
+            Some(caps) => caps
+                .iter()
+                .map(|m| str_value(m.map_or("", |m| m.as_str())))
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(list_value(ectx, groups))
+    }
+}
+
+/// `($regex_replace pattern text replacement)`: `text` with every match of
+/// `pattern` replaced. `$1` or `${name}` in `replacement` stand for capture
+/// groups, and `$$` for a literal `$`.
+#[derive(Debug)]
+pub struct RegexReplaceOp;
+impl HostFunction for RegexReplaceOp {
+    fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(vec![
+            Param::required("pattern"),
+            Param::required("text"),
+            Param::required("replacement"),
+        ]))
+    }
+
+    fn typeck(&self, params: &[DataType]) -> Result<DataType, TypeError> {
+        typeck_strings("regex_replace", params, string_type())
+    }
+
+    fn eval<'b, 'c>(
+        &self,
+        ectx: &mut EvalContext<'b, 'c>,
+        params: &mut dyn Iterator<Item = LazyValue<'b>>,
+    ) -> Result<RuntimeValue<'b>, RuntimeError> {
+        let re = eval_regex(ectx, params.next().unwrap())?;
+        let text = eval_string(ectx, params.next().unwrap())?;
+        let replacement = eval_string(ectx, params.next().unwrap())?;
+        Ok(str_value(&re.replace_all(&text, replacement.as_str())))
+    }
+}
+
+/// `$regex_match`, `$regex_capture` and `$regex_replace`.
+pub fn regex_ops() -> Vec<(&'static str, Arc<dyn HostFunction>)> {
+    vec![
+        ("regex_match", Arc::new(RegexMatchOp)),
+        ("regex_capture", Arc::new(RegexCaptureOp)),
+        ("regex_replace", Arc::new(RegexReplaceOp)),
+    ]
+}
@@ -0,0 +1,72 @@
+use crate::corelib::{HostManager, Profile};
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::{eval_expr, EvalContext, RuntimeValue};
+use crate::parser::parse_expr;
+
+#[test]
+fn test_regex_ops() {
+    let engine = Engine::new();
+    let eval = |s: &str| engine.eval_str(s).unwrap();
+    assert_eq!(eval(r#"($regex_match "^[A-Z]{2}-\\d+$" "DE-123")"#), "true");
+    assert_eq!(eval(r#"($regex_match "^\\d+$" "12a")"#), "false");
+    assert_eq!(
+        eval(
+            r#"($eq ($regex_capture "(\\w+)@(\\w+)?\\.com" "mail ada@.com")
+                    ($list_push "ada@.com" ($list_push "ada" ($list_push "" ~))))"#
+        ),
+        "true"
+    );
+    assert_eq!(
+        eval(r#"($list_is_empty ($regex_capture "x" "abc"))"#),
+        "true"
+    );
+    assert_eq!(
+        eval(r#"($regex_replace "(\\d+)-(\\d+)" "1-2, 30-40" "$2-$1")"#),
+        r#""2-1, 40-30""#
+    );
+    assert!(matches!(
+        engine.eval_str(r#"($regex_match "a" 1)"#),
+        Err(Error::Type(_))
+    ));
+    match engine.eval_str(r#"($regex_match "(" "a")"#) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert!(msg.starts_with("invalid regex"), "{}", msg)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_regex_cache() {
+    let hm = HostManager::new();
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    let first = parse_expr(r#"($regex_match "^a+$" "aaa")"#).unwrap();
+    let second =
+        parse_expr(r#"($if ($regex_match "^a+$" "b") false ($regex_match "^b+$" "bb"))"#).unwrap();
+
+    assert!(matches!(
+        eval_expr(&first, &mut ectx),
+        Ok(RuntimeValue::Bool(true))
+    ));
+    assert_eq!(ectx.regex_cache().len(), 1);
+    ectx.reset();
+    assert!(matches!(
+        eval_expr(&second, &mut ectx),
+        Ok(RuntimeValue::Bool(true))
+    ));
+    assert_eq!(ectx.regex_cache().len(), 2);
+}
+
+#[test]
+fn test_regex_profiles() {
+    let mut engine = Engine::new();
+    engine.set_profile(Profile::PureMath);
+    assert!(engine.eval_str(r#"($regex_match "a" "a")"#).is_err());
+    engine.set_profile(Profile::DataTransform);
+    assert_eq!(
+        engine.eval_str(r#"($regex_match "a" "a")"#).unwrap(),
+        "true"
+    );
+}
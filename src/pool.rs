@@ -0,0 +1,69 @@
+//! Recycling of frequently allocated evaluator objects.
+//!
+//! Every argument of a lambda call gets a fresh `LazyValue`, whose outcome
+//! cell is a separate heap allocation. Most of those cells are unreachable
+//! again once the call returns, so the evaluator hands them back here and
+//! reuses them for later calls instead of going through the allocator.
+//!
+//! Building with the `plain-alloc` feature turns the pool into a plain
+//! pass-through to `Rc::new`, for comparison and debugging.
+
+use crate::eval::RuntimeValue;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub(crate) type OutcomeCell<'b> = Rc<RefCell<Option<RuntimeValue<'b>>>>;
+
+/// Upper bound on the number of idle cells kept around.
+const MAX_IDLE_CELLS: usize = 4096;
+
+#[derive(Debug, Default)]
+pub(crate) struct ValuePool<'b> {
+    idle: Vec<OutcomeCell<'b>>,
+    /// Cells handed out for calls that are still in progress, innermost
+    /// call last.
+    pending: Vec<OutcomeCell<'b>>,
+}
+
+impl<'b> ValuePool<'b> {
+    /// Returns an empty outcome cell tracked as belonging to the current
+    /// call.
+    pub fn alloc(&mut self) -> OutcomeCell<'b> {
+        if cfg!(feature = "plain-alloc") {
+            return Rc::new(RefCell::new(None));
+        }
+        let cell = self
+            .idle
+            .pop()
+            .unwrap_or_else(|| Rc::new(RefCell::new(None)));
+        self.pending.push(cell.clone());
+        cell
+    }
+
+    /// Marks the start of a call; pass the result to `release`.
+    pub fn mark(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Takes back the cells allocated since `mark` that are no longer
+    /// referenced from anywhere else.
+    pub fn release(&mut self, mark: usize) {
+        for cell in self.pending.drain(mark..) {
+            if Rc::strong_count(&cell) == 1 && self.idle.len() < MAX_IDLE_CELLS {
+                *cell.borrow_mut() = None;
+                self.idle.push(cell);
+            }
+        }
+    }
+
+    /// Forgets the cells allocated since `mark` without reusing them, for
+    /// when they escape the call.
+    pub fn forget(&mut self, mark: usize) {
+        self.pending.truncate(mark);
+    }
+
+    pub fn clear(&mut self) {
+        self.idle.clear();
+        self.pending.clear();
+    }
+}
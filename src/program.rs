@@ -0,0 +1,34 @@
+use crate::ast::{DataType, Expr, ExprBody};
+use crate::definitions::Definitions;
+use std::rc::Rc;
+
+/// A multi-module program: a set of named module definitions plus the
+/// name of the module that is evaluated.
+#[derive(Debug, Default)]
+pub struct Program {
+    pub definitions: Definitions,
+    pub entry: String,
+    /// The type the bundle's certificate gives the entry module, with the
+    /// generation of `definitions` it was loaded with.
+    pub(crate) certified: Option<(u64, DataType)>,
+}
+
+impl Program {
+    /// Returns the expression that evaluates the program, i.e. a reference
+    /// to the entry module.
+    pub fn main_expr(&self) -> Expr {
+        Expr {
+            body: Rc::new(ExprBody::Name(self.entry.clone())),
+        }
+    }
+
+    /// The type of the entry module recorded by the certificate of the
+    /// bundle the program was loaded from, if the loading engine trusted
+    /// it and `definitions` have not changed since.
+    pub fn certified_type(&self) -> Option<&DataType> {
+        match self.certified {
+            Some((generation, ref ty)) if generation == self.definitions.generation() => Some(ty),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Python bindings (`pyo3` based).
+//!
+//! Exposes an `Engine` class and an `eval_str` shortcut. Python `None`,
+//! `bool`, `int`, `float` and `list` convert to and from the corresponding
+//! x-lang values; Python values are passed into scripts as definitions.
+
+use crate::ast::{AbstractBody, ConstExpr, Expr, ExprBody};
+use crate::corelib::List;
+use crate::error::{Error, RuntimeError};
+use crate::eval::{EvalContext, RuntimeValue};
+use pyo3::exceptions::{PyRuntimeError, PySyntaxError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList};
+use std::rc::Rc;
+
+fn to_py_err(e: Error) -> PyErr {
+    match e {
+        Error::Parse(e) => PySyntaxError::new_err(format!("{:?}", e)),
+        Error::Type(e) => PyTypeError::new_err(format!("{:?}", e)),
+        Error::Runtime(e) => PyRuntimeError::new_err(format!("{:?}", e)),
+    }
+}
+
+fn const_expr(c: ConstExpr) -> Expr {
+    Expr {
+        body: Rc::new(ExprBody::Const(c)),
+    }
+}
+
+/// Converts a Python object into an expression producing the same value.
+fn from_python(obj: &Bound<PyAny>) -> PyResult<Expr> {
+    if obj.is_none() {
+        Ok(const_expr(ConstExpr::Empty))
+    } else if obj.is_instance_of::<PyBool>() {
+        Ok(const_expr(ConstExpr::Bool(obj.extract()?)))
+    } else if obj.is_instance_of::<PyInt>() {
+        Ok(const_expr(ConstExpr::Int(obj.extract()?)))
+    } else if obj.is_instance_of::<PyFloat>() {
+        Ok(const_expr(ConstExpr::Float(obj.extract()?)))
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        let mut e = const_expr(ConstExpr::Empty);
+        for item in list.iter().rev() {
+            e = Expr {
+                body: Rc::new(ExprBody::Apply {
+                    target: Expr {
+                        body: Rc::new(ExprBody::Abstract {
+                            params: vec![],
+                            body: AbstractBody::Host("list_push".into()),
+                        }),
+                    },
+                    params: vec![from_python(&item)?, e],
+                }),
+            };
+        }
+        Ok(e)
+    } else {
+        Err(PyValueError::new_err(format!(
+            "cannot convert {} to an x-lang value",
+            obj.get_type().name()?
+        )))
+    }
+}
+
+fn to_python<'b, 'c>(
+    py: Python,
+    v: RuntimeValue<'b>,
+    ectx: &mut EvalContext<'b, 'c>,
+) -> Result<PyObject, RuntimeError> {
+    Ok(match v {
+        RuntimeValue::Empty => py.None(),
+        RuntimeValue::Int(x) => x.into_pyobject(py).unwrap().into_any().unbind(),
+        RuntimeValue::Float(x) => x.into_pyobject(py).unwrap().into_any().unbind(),
+        RuntimeValue::Bool(x) => x.into_pyobject(py).unwrap().to_owned().into_any().unbind(),
+        RuntimeValue::Custom(ref cv) => match cv.inner.as_any().downcast_ref::<List>() {
+            Some(list) => {
+                let mut items = Vec::new();
+                for item in list.values(ectx)? {
+                    items.push(to_python(py, item, ectx)?);
+                }
+                PyList::new(py, items).unwrap().into_any().unbind()
+            }
+            None => {
+                return Err(RuntimeError::Custom(
+                    "cannot convert custom value to a Python object".into(),
+                ))
+            }
+        },
+        RuntimeValue::Function { .. }
+        | RuntimeValue::Host(_)
+        | RuntimeValue::PartialHost { .. } => {
+            return Err(RuntimeError::Custom(
+                "cannot convert function to a Python object".into(),
+            ))
+        }
+    })
+}
+
+#[pyclass(unsendable, name = "Engine")]
+pub struct PyEngine {
+    inner: crate::engine::Engine,
+}
+
+#[pymethods]
+impl PyEngine {
+    #[new]
+    fn new() -> PyEngine {
+        PyEngine {
+            inner: crate::engine::Engine::new(),
+        }
+    }
+
+    /// Adds (or replaces) a named definition from source.
+    fn define(&mut self, name: &str, source: &str) -> PyResult<()> {
+        self.inner.define(name, source).map_err(to_py_err)
+    }
+
+    /// Binds a Python value to `name` for subsequent evaluations.
+    fn set(&mut self, name: &str, value: &Bound<PyAny>) -> PyResult<()> {
+        let e = from_python(value)?;
+        self.inner.definitions_mut().define(name.to_string(), e);
+        Ok(())
+    }
+
+    /// Returns the type of `source` as a string.
+    fn typecheck(&self, source: &str) -> PyResult<String> {
+        let e = self.inner.parse(source).map_err(to_py_err)?;
+        let ty = self.inner.check(&e).map_err(to_py_err)?;
+        Ok(ty.to_string())
+    }
+
+    /// Evaluates `source`, with the entries of `variables` bound as
+    /// definitions, and converts the result to a Python object.
+    #[pyo3(signature = (source, variables = None))]
+    fn eval(
+        &mut self,
+        py: Python,
+        source: &str,
+        variables: Option<&Bound<PyDict>>,
+    ) -> PyResult<PyObject> {
+        if let Some(vars) = variables {
+            for (k, v) in vars.iter() {
+                self.set(&k.extract::<String>()?, &v)?;
+            }
+        }
+        let e = self.inner.parse(source).map_err(to_py_err)?;
+        self.inner
+            .eval_with(&e, |v, ectx| to_python(py, v, ectx))
+            .map_err(to_py_err)
+    }
+}
+
+/// Evaluates `source` with a fresh engine.
+#[pyfunction]
+#[pyo3(signature = (source, variables = None))]
+fn eval_str(py: Python, source: &str, variables: Option<&Bound<PyDict>>) -> PyResult<PyObject> {
+    PyEngine::new().eval(py, source, variables)
+}
+
+#[pymodule]
+fn x_lang(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<PyEngine>()?;
+    m.add_function(wrap_pyfunction!(eval_str, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! Finding the recursion a program goes through, to explain why it never
+//! terminates.
+//!
+//! The typechecker reports a program as divergent when checking it reaches
+//! an expression it is already checking, without saying which. This
+//! analysis names the cycles instead: definitions that call each other in
+//! a loop, such as `a -> b -> a`, and functions applied to themselves, as
+//! in `(f f)`, which is how recursion is written without definitions.
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+use crate::definitions::Definitions;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A call from `caller` to `callee` on a cycle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CycleStep {
+    pub caller: String,
+    pub callee: String,
+    /// The reference to `callee`, for looking up its span in a `SpanMap`.
+    pub call: Expr,
+}
+
+/// A cycle of calls, each step calling the next and the last calling the
+/// first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cycle {
+    pub steps: Vec<CycleStep>,
+}
+
+impl Cycle {
+    /// The location of the first call on the cycle that `spans` knows.
+    pub fn span(&self, spans: &SpanMap) -> Option<Span> {
+        self.steps.iter().find_map(|s| spans.get(&s.call))
+    }
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            write!(f, "{} -> ", step.caller)?;
+        }
+        match self.steps.first() {
+            Some(step) => write!(f, "{}", step.caller),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the cycles of calls `e` can reach: one for each group of
+/// definitions in `defs` that call each other, and one for each function
+/// applied to itself.
+pub fn find_cycles(e: &Expr, defs: Option<&Definitions>) -> Vec<Cycle> {
+    let mut roots = vec![e.clone()];
+    let mut cycles = Vec::new();
+    if let Some(defs) = defs {
+        let graph = defs.dependency_graph();
+        let free: Vec<String> = free_vars(e).into_iter().collect();
+        let reachable = graph.reachable(&free);
+        roots.extend(reachable.iter().map(|n| defs.get(n).unwrap().expr.clone()));
+
+        let mut covered: BTreeSet<&String> = BTreeSet::new();
+        for start in &reachable {
+            if covered.contains(start) {
+                continue;
+            }
+            let path = match shortest_cycle(&graph.definitions, start) {
+                Some(path) => path,
+                None => continue,
+            };
+            // Every definition on the cycle is in the same group as
+            // `start`; the group is reported once.
+            let group: BTreeSet<&String> = reachable
+                .iter()
+                .filter(|n| {
+                    graph.reachable(&[n.as_str()]).contains(start)
+                        && graph.reachable(&[start.as_str()]).contains(*n)
+                })
+                .collect();
+            covered.extend(group);
+            let steps = path
+                .iter()
+                .zip(path.iter().skip(1).chain(path.first()))
+                .map(|(caller, callee)| CycleStep {
+                    caller: caller.clone(),
+                    callee: callee.clone(),
+                    call: find_name(&defs.get(caller).unwrap().expr, callee).unwrap(),
+                })
+                .collect();
+            cycles.push(Cycle { steps });
+        }
+    }
+
+    cycles.extend(self_applications(
+        &roots,
+        defs.map(|d| d.names()).unwrap_or_default(),
+    ));
+    cycles
+}
+
+/// Returns a cycle for each parameter applied to itself in `roots`,
+/// ignoring the names in `defined`.
+pub(crate) fn self_applications(roots: &[Expr], defined: BTreeSet<String>) -> Vec<Cycle> {
+    let mut finder = SelfApplications {
+        defined,
+        found: Vec::new(),
+    };
+    for root in roots {
+        visit::walk(&mut finder, root).unwrap();
+    }
+    finder.found
+}
+
+/// Explains why `e`, which typechecks as divergent, never terminates.
+pub fn divergence_message(e: &Expr, defs: Option<&Definitions>) -> String {
+    let cycles = find_cycles(e, defs);
+    if cycles.is_empty() {
+        return "program will never terminate".into();
+    }
+    let cycles: BTreeSet<String> = cycles.iter().map(|c| c.to_string()).collect();
+    format!(
+        "program will never terminate: it recurses through {}",
+        cycles.into_iter().collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// Returns the shortest path of definitions from `start` that leads back
+/// to it, without repeating `start` at the end.
+fn shortest_cycle(graph: &BTreeMap<String, BTreeSet<String>>, start: &str) -> Option<Vec<String>> {
+    let mut paths: Vec<Vec<String>> = vec![vec![start.to_string()]];
+    let mut seen: BTreeSet<String> = BTreeSet::new();
+    while !paths.is_empty() {
+        let mut next = Vec::new();
+        for path in paths {
+            for callee in &graph[path.last().unwrap()] {
+                if callee == start {
+                    return Some(path);
+                }
+                if seen.insert(callee.clone()) {
+                    let mut longer = path.clone();
+                    longer.push(callee.clone());
+                    next.push(longer);
+                }
+            }
+        }
+        paths = next;
+    }
+    None
+}
+
+/// Returns the first reference to `name` in `e`.
+fn find_name(e: &Expr, name: &str) -> Option<Expr> {
+    struct Finder<'a>(&'a str);
+    impl<'a> Visitor for Finder<'a> {
+        type Error = Expr;
+
+        fn visit_name(&mut self, e: &Expr, name: &str) -> Result<(), Expr> {
+            if name == self.0 {
+                Err(e.clone())
+            } else {
+                Ok(())
+            }
+        }
+    }
+    visit::walk(&mut Finder(name), e).err()
+}
+
+/// Collects applications of a parameter to itself, such as `(f f)`.
+/// Definitions applied to themselves are on a cycle of definitions too, and
+/// are left to that analysis.
+struct SelfApplications {
+    defined: BTreeSet<String>,
+    found: Vec<Cycle>,
+}
+
+impl Visitor for SelfApplications {
+    type Error = ();
+
+    fn pre_apply(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Apply {
+            ref target,
+            ref params,
+        } = *e.body
+        {
+            if let ExprBody::Name(ref f) = *target.body {
+                if !self.defined.contains(f)
+                    && params.iter().any(|p| *p.body == ExprBody::Name(f.clone()))
+                {
+                    let name = source_name(f).to_string();
+                    self.found.push(Cycle {
+                        steps: vec![CycleStep {
+                            caller: name.clone(),
+                            callee: name,
+                            call: e.clone(),
+                        }],
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
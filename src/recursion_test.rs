@@ -0,0 +1,66 @@
+use crate::definitions::Definitions;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::parser::{parse_expr_with_globals, parse_expr_with_spans, ParseOptions};
+use crate::recursion::*;
+
+fn define(defs: &mut Definitions, name: &str, src: &str) {
+    let globals = ["ping", "pong", "count", "half"]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+    let e = parse_expr_with_globals(src, globals).unwrap();
+    defs.define(name.to_string(), e);
+}
+
+#[test]
+fn test_definition_cycles() {
+    let mut defs = Definitions::default();
+    define(&mut defs, "ping", r"(\n (pong ($add n 1)))");
+    define(&mut defs, "pong", r"(\n (ping ($half n)))");
+    define(&mut defs, "half", r"(\n ($div n 2))");
+    define(&mut defs, "count", r"(\n (count n))");
+
+    let root = parse_expr_with_globals("(pong 1)", defs.names()).unwrap();
+    let cycles = find_cycles(&root, Some(&defs));
+    assert_eq!(cycles.len(), 1);
+    assert_eq!(cycles[0].to_string(), "ping -> pong -> ping");
+    let steps: Vec<(&str, &str)> = cycles[0]
+        .steps
+        .iter()
+        .map(|s| (s.caller.as_str(), s.callee.as_str()))
+        .collect();
+    assert_eq!(steps, [("ping", "pong"), ("pong", "ping")]);
+
+    let root = parse_expr_with_globals("($add (count 1) (half 4))", defs.names()).unwrap();
+    assert_eq!(
+        divergence_message(&root, Some(&defs)),
+        "program will never terminate: it recurses through count -> count"
+    );
+    let root = parse_expr_with_globals("(half 4)", defs.names()).unwrap();
+    assert!(find_cycles(&root, Some(&defs)).is_empty());
+}
+
+#[test]
+fn test_self_application() {
+    let source = "((\\f (f f)) (\\g ($add 1 (g g))))";
+    let (e, spans) = parse_expr_with_spans(source, &ParseOptions::default()).unwrap();
+    let cycles = find_cycles(&e, None);
+    let names: Vec<String> = cycles.iter().map(|c| c.to_string()).collect();
+    assert_eq!(names, ["f -> f", "g -> g"]);
+    let span = cycles[0].span(&spans).unwrap();
+    assert_eq!(&source[span.start..span.end], "(f f)");
+}
+
+#[test]
+fn test_engine_reports_cycles() {
+    let mut engine = Engine::new();
+    engine.define("spin", r"(\n (spin ($add n 1)))").unwrap();
+    match engine.eval_str("(spin 0)") {
+        Err(Error::Type(TypeError::Custom(msg))) => assert_eq!(
+            msg,
+            "program will never terminate: it recurses through spin -> spin"
+        ),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,151 @@
+//! Language references generated from an engine's configuration.
+//!
+//! Which host functions a script can call depends on the profile, denied
+//! hosts and embedder hosts of the `Engine` running it, so a hand-written
+//! reference is wrong for most deployments. `Reference::of` lists exactly
+//! what a given engine accepts, for publishing as markdown or JSON.
+
+use crate::engine::Engine;
+use crate::host::HostInfo;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A syntactic form of the language, available whatever the engine.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FormInfo {
+    pub name: String,
+    /// How the form is written, e.g. `\x y (body)`.
+    pub syntax: String,
+    pub doc: String,
+}
+
+/// Everything a script running on a particular engine can use.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub forms: Vec<FormInfo>,
+    /// Type aliases declared with `Engine::define_type`, by name, with the
+    /// types they stand for.
+    pub types: BTreeMap<String, String>,
+    /// Host functions ordered by group, then name.
+    pub hosts: Vec<HostInfo>,
+}
+
+const FORMS: &[(&str, &str, &str)] = &[
+    (
+        "literal",
+        "42  1.5  true  false  \"text\"  0x\"00ff\"  ~",
+        "Ints, floats, bools, strings, bytes and the empty value.",
+    ),
+    (
+        "lambda",
+        "\\x y (body)",
+        "A function of the named parameters. Parameters starting with `_` may go unused.",
+    ),
+    (
+        "call",
+        "(f a b (name c))",
+        "Calls `f`. Arguments `(name value)` whose name is not in scope are named; they follow the positional ones and need a target with a known parameter list.",
+    ),
+    (
+        "host",
+        "$name",
+        "Refers to a host function, listed below.",
+    ),
+    (
+        "the",
+        "(the ty value)",
+        "Checks that `value` has type `ty` and evaluates to it.",
+    ),
+    (
+        "quote",
+        "(quote expr)",
+        "The expression `expr` as a value, for `$eval`. `(unquote value)` inside it splices in `value`.",
+    ),
+    (
+        "type",
+        "(type name ty)",
+        "Declares `name` as an alias of `ty` for the rest of the source.",
+    ),
+    (
+        "defmacro",
+        "(defmacro name (params...) template)",
+        "Declares a macro that expands calls to `name` into `template`.",
+    ),
+    ("comment", "# text", "Ignored up to the end of the line."),
+];
+
+impl Reference {
+    /// The reference for scripts run by `engine`.
+    pub fn of(engine: &Engine) -> Reference {
+        let forms = FORMS
+            .iter()
+            .map(|&(name, syntax, doc)| FormInfo {
+                name: name.to_string(),
+                syntax: syntax.to_string(),
+                doc: doc.to_string(),
+            })
+            .collect();
+        let types = engine
+            .parse_options()
+            .types
+            .iter()
+            .map(|(name, ty)| (name.clone(), ty.to_string()))
+            .collect();
+        let mut hosts = engine.describe_hosts();
+        hosts.sort_by(|a, b| (&a.group, &a.name).cmp(&(&b.group, &b.name)));
+        Reference {
+            forms,
+            types,
+            hosts,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("bug: reference serialization failed")
+    }
+
+    /// Renders the reference as a markdown document, with a section per
+    /// host group.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# Language reference").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "## Forms").unwrap();
+        for form in &self.forms {
+            writeln!(out).unwrap();
+            writeln!(out, "### {}", form.name).unwrap();
+            writeln!(out).unwrap();
+            writeln!(out, "`{}`", form.syntax).unwrap();
+            writeln!(out).unwrap();
+            writeln!(out, "{}", form.doc).unwrap();
+        }
+        if !self.types.is_empty() {
+            writeln!(out).unwrap();
+            writeln!(out, "## Types").unwrap();
+            writeln!(out).unwrap();
+            for (name, ty) in &self.types {
+                writeln!(out, "- `{}`: `{}`", name, ty).unwrap();
+            }
+        }
+        let mut group = None;
+        for host in &self.hosts {
+            if group != Some(&host.group) {
+                group = Some(&host.group);
+                writeln!(out).unwrap();
+                writeln!(out, "## Host functions: {}", host.group).unwrap();
+            }
+            writeln!(out).unwrap();
+            writeln!(out, "### ${}", host.name).unwrap();
+            writeln!(out).unwrap();
+            match host.signature {
+                Some(ref sig) => writeln!(out, "`{}` (version {})", sig, host.version).unwrap(),
+                None => writeln!(out, "Version {}.", host.version).unwrap(),
+            }
+            if let Some(ref doc) = host.doc {
+                writeln!(out).unwrap();
+                writeln!(out, "{}", doc).unwrap();
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,30 @@
+use crate::corelib::Profile;
+use crate::engine::Engine;
+use crate::reference::*;
+
+#[test]
+fn test_reference_follows_engine() {
+    let mut engine = Engine::new();
+    engine.define_type("prices", "(list float)").unwrap();
+    let reference = Reference::of(&engine);
+    assert!(reference.forms.iter().any(|f| f.name == "lambda"));
+    assert_eq!(reference.types["prices"], "list<float>");
+    let round = reference.hosts.iter().find(|h| h.name == "round").unwrap();
+    assert_eq!(round.group, "math");
+    let groups: Vec<&str> = reference.hosts.iter().map(|h| h.group.as_str()).collect();
+    let mut sorted = groups.clone();
+    sorted.sort();
+    assert_eq!(groups, sorted);
+
+    let markdown = reference.to_markdown();
+    assert!(markdown.contains("## Host functions: math"), "{}", markdown);
+    assert!(markdown.contains("### $round\n\n`$round(x, digits = 0)` (version 0)"));
+    assert!(markdown.contains("- `prices`: `list<float>`"));
+    let parsed: Reference = serde_json::from_str(&reference.to_json()).unwrap();
+    assert_eq!(parsed, reference);
+
+    engine.set_profile(Profile::PureMath);
+    let reference = Reference::of(&engine);
+    assert!(reference.hosts.iter().all(|h| h.name != "json_parse"));
+    assert!(!reference.to_markdown().contains("$json_parse"));
+}
@@ -0,0 +1,351 @@
+//! Replacing programs while a service keeps running them.
+//!
+//! A `ProgramStore` holds the current version of each of a set of named
+//! programs. New versions are pushed through a `ProgramUpdater`, from any
+//! thread, or read from a directory holding one program per `.x` file,
+//! named after the file. `ProgramStore::refresh` parses and checks the
+//! versions that arrived since it last ran and swaps in those that pass; a
+//! version that fails is reported and the previous one stays in use.
+//! Callers hold an `Rc` of the version they started with, so a swap never
+//! affects an evaluation in progress.
+//!
+//! A new version must also be callable the way the previous one was: it
+//! must have the type declared for the program with `ProgramStore::declare`
+//! or, without a declaration, the type of the previous version, where
+//! functions only need to take as many parameters. To change the type of a
+//! program on purpose, declare the new type or remove the program first.
+//!
+//! Engines are not `Send`, so checking happens in `refresh`, on the thread
+//! owning the engine; `ProgramStore::watch` only reads files in the
+//! background.
+
+use crate::ast::{DataType, Expr};
+use crate::engine::Engine;
+use crate::error::*;
+use crate::eval::OwnedValue;
+use crate::typeck::TypeDescription;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Extension of the program files in watched directories.
+pub const PROGRAM_EXTENSION: &str = "x";
+
+/// A version of a program that passed checks.
+#[derive(Debug)]
+pub struct LoadedProgram {
+    pub name: String,
+    pub source: String,
+    pub expr: Expr,
+    pub ty: DataType,
+    /// Number of versions of the program swapped in so far, this one
+    /// included.
+    pub version: u64,
+}
+
+/// A version of the program `name` that was rejected.
+#[derive(Debug)]
+pub struct ReloadError {
+    pub name: String,
+    pub failure: ReloadFailure,
+}
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.failure)
+    }
+}
+
+/// Why a version of a program was rejected.
+#[derive(Debug)]
+pub enum ReloadFailure {
+    /// It does not parse or check.
+    Invalid(Error),
+    /// It checks, but as `found` where `expected`, the declared type or that
+    /// of the previous version, was required; `reason` says how they
+    /// differ.
+    Incompatible {
+        expected: TypeDescription,
+        found: TypeDescription,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ReloadFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReloadFailure::Invalid(ref e) => write!(f, "{}", e),
+            ReloadFailure::Incompatible {
+                ref expected,
+                ref found,
+                ref reason,
+            } => write!(
+                f,
+                "incompatible with {}, found {}: {}",
+                expected, found, reason
+            ),
+        }
+    }
+}
+
+/// A new version of a program, or `None` to remove it.
+type Update = (String, Option<String>);
+
+/// Sends new versions of programs to a `ProgramStore`, from any thread.
+/// Updates sent after the store is dropped are ignored.
+#[derive(Debug, Clone)]
+pub struct ProgramUpdater {
+    tx: Sender<Update>,
+}
+
+impl ProgramUpdater {
+    pub fn push(&self, name: &str, source: &str) {
+        let _ = self.tx.send((name.to_string(), Some(source.to_string())));
+    }
+
+    pub fn remove(&self, name: &str) {
+        let _ = self.tx.send((name.to_string(), None));
+    }
+}
+
+/// Stops the thread started by `ProgramStore::watch` when dropped.
+#[derive(Debug)]
+pub struct WatchHandle {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct ProgramStore {
+    programs: BTreeMap<String, Rc<LoadedProgram>>,
+    /// Types declared with `declare`, by program name.
+    declared: BTreeMap<String, DataType>,
+    tx: Sender<Update>,
+    rx: Receiver<Update>,
+}
+
+impl Default for ProgramStore {
+    fn default() -> ProgramStore {
+        let (tx, rx) = channel();
+        ProgramStore {
+            programs: BTreeMap::new(),
+            declared: BTreeMap::new(),
+            tx,
+            rx,
+        }
+    }
+}
+
+impl ProgramStore {
+    pub fn new() -> ProgramStore {
+        ProgramStore::default()
+    }
+
+    pub fn updater(&self) -> ProgramUpdater {
+        ProgramUpdater {
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// Requires every version of the program `name` swapped in from now on
+    /// to have the type `expected`, e.g. one built with
+    /// `typeck::function_type`, instead of that of the previous version.
+    pub fn declare(&mut self, name: &str, expected: DataType) {
+        self.declared.insert(name.to_string(), expected);
+    }
+
+    /// Queues the programs in `dir` for the next `refresh`.
+    pub fn load_dir(&self, dir: &Path) -> io::Result<()> {
+        let updater = self.updater();
+        for (name, source) in read_programs(dir)? {
+            updater.push(&name, &source);
+        }
+        Ok(())
+    }
+
+    /// Reads `dir` every `interval` in a background thread and queues the
+    /// programs added, changed or removed since the last read, starting
+    /// with all of them. Reads that fail are retried at the next interval.
+    pub fn watch(&self, dir: PathBuf, interval: Duration) -> WatchHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = WatchHandle { stop: stop.clone() };
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let mut seen: BTreeMap<String, String> = BTreeMap::new();
+            while !stop.load(Ordering::Relaxed) {
+                if let Ok(current) = read_programs(&dir) {
+                    let mut updates: Vec<Update> = seen
+                        .keys()
+                        .filter(|name| !current.contains_key(*name))
+                        .map(|name| (name.clone(), None))
+                        .collect();
+                    for (name, source) in &current {
+                        if seen.get(name) != Some(source) {
+                            updates.push((name.clone(), Some(source.clone())));
+                        }
+                    }
+                    for update in updates {
+                        if tx.send(update).is_err() {
+                            return;
+                        }
+                    }
+                    seen = current;
+                }
+                thread::sleep(interval);
+            }
+        });
+        handle
+    }
+
+    /// Checks the versions queued since the last call against `engine` and
+    /// swaps in those that pass. Only the last version queued for each
+    /// program is checked, against no previous version if the program was
+    /// removed in between.
+    pub fn refresh(&mut self, engine: &Engine) -> Vec<ReloadError> {
+        // For each program, whether it was removed and the version queued
+        // last since then, if any.
+        let mut latest: BTreeMap<String, (bool, Option<String>)> = BTreeMap::new();
+        for (name, source) in self.rx.try_iter() {
+            let entry = latest.entry(name).or_insert((false, None));
+            entry.0 |= source.is_none();
+            entry.1 = source;
+        }
+        let mut errors = Vec::new();
+        for (name, (removed, source)) in latest {
+            if removed {
+                self.programs.remove(&name);
+            }
+            let source = match source {
+                Some(source) => source,
+                None => continue,
+            };
+            let previous = self.programs.get(&name);
+            if previous.map(|p| &p.source) == Some(&source) {
+                continue;
+            }
+            let version = previous.map_or(0, |p| p.version) + 1;
+            let checked = engine.prepare(&source).map_err(ReloadFailure::Invalid);
+            let checked = checked.and_then(|(expr, ty)| {
+                match self.declared.get(&name) {
+                    Some(expected) => check_declared(engine, &source, expected, &ty)?,
+                    None => {
+                        if let Some(previous) = previous {
+                            check_compatible(&previous.ty, &ty)?;
+                        }
+                    }
+                }
+                Ok((expr, ty))
+            });
+            match checked {
+                Ok((expr, ty)) => {
+                    let program = LoadedProgram {
+                        name: name.clone(),
+                        source,
+                        expr,
+                        ty,
+                        version,
+                    };
+                    self.programs.insert(name, Rc::new(program));
+                }
+                Err(failure) => errors.push(ReloadError { name, failure }),
+            }
+        }
+        errors
+    }
+
+    /// The current version of the program `name`.
+    pub fn get(&self, name: &str) -> Option<Rc<LoadedProgram>> {
+        self.programs.get(name).cloned()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.programs.keys().map(|name| name.as_str())
+    }
+
+    /// Calls the current version of the program `name` with `args`. See
+    /// `Engine::call`.
+    pub fn call(
+        &self,
+        engine: &Engine,
+        name: &str,
+        args: &[OwnedValue],
+    ) -> Result<OwnedValue, Error> {
+        let program = self
+            .get(name)
+            .ok_or_else(|| RuntimeError::Custom(format!("unknown program `{}`", name)))?;
+        engine.call(&program.expr, args)
+    }
+}
+
+fn check_declared(
+    engine: &Engine,
+    source: &str,
+    expected: &DataType,
+    found: &DataType,
+) -> Result<(), ReloadFailure> {
+    match engine.check_against(source, expected) {
+        Ok(()) => Ok(()),
+        Err(Error::Type(e)) => Err(ReloadFailure::Incompatible {
+            expected: TypeDescription::of(expected),
+            found: TypeDescription::of(found),
+            reason: e.to_string(),
+        }),
+        Err(e) => Err(ReloadFailure::Invalid(e)),
+    }
+}
+
+/// Checks that callers of a program of type `previous` can call one of type
+/// `found`. Function types do not record what their parameters take, so
+/// functions only need to take as many parameters.
+fn check_compatible(previous: &DataType, found: &DataType) -> Result<(), ReloadFailure> {
+    let expected = TypeDescription::of(previous).expand();
+    let found = TypeDescription::of(found).expand();
+    let reason = match (&expected, &found) {
+        (TypeDescription::Dynamic, _)
+        | (_, TypeDescription::Dynamic)
+        | (_, TypeDescription::Divergent) => return Ok(()),
+        (TypeDescription::Function { params: want }, TypeDescription::Function { params: got }) => {
+            if want.len() == got.len() {
+                return Ok(());
+            }
+            format!("takes {} parameters instead of {}", got.len(), want.len())
+        }
+        _ if expected == found => return Ok(()),
+        _ => "the types differ".to_string(),
+    };
+    Err(ReloadFailure::Incompatible {
+        expected,
+        found,
+        reason,
+    })
+}
+
+/// The sources of the programs in `dir`, by name.
+fn read_programs(dir: &Path) -> io::Result<BTreeMap<String, String>> {
+    let mut programs = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|x| x.to_str()) != Some(PROGRAM_EXTENSION) {
+            continue;
+        }
+        let name = match path.file_stem().and_then(|x| x.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        programs.insert(name, fs::read_to_string(&path)?);
+    }
+    Ok(programs)
+}
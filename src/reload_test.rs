@@ -0,0 +1,144 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::eval::OwnedValue;
+use crate::reload::{ProgramStore, ReloadFailure};
+use crate::typeck::{function_type, TypeDescription};
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_program_store_push() {
+    let engine = Engine::new();
+    let mut store = ProgramStore::new();
+    let updater = store.updater();
+    thread::spawn(move || {
+        updater.push("rule", r"(\x ($add x 1))");
+        updater.push("other", "(1)");
+    })
+    .join()
+    .unwrap();
+    assert!(store.refresh(&engine).is_empty());
+    assert_eq!(store.names().collect::<Vec<_>>(), vec!["other", "rule"]);
+
+    let args = [OwnedValue::Int(1)];
+    let running = store.get("rule").unwrap();
+    assert_eq!(running.version, 1);
+    assert_eq!(store.call(&engine, "rule", &args).unwrap().to_string(), "2");
+
+    // Only the last version pushed is checked, and swapped in if it passes.
+    let updater = store.updater();
+    updater.push("rule", "(");
+    updater.push("rule", r"(\x ($mul x 10))");
+    assert!(store.refresh(&engine).is_empty());
+    assert_eq!(store.get("rule").unwrap().version, 2);
+    assert_eq!(
+        store.call(&engine, "rule", &args).unwrap().to_string(),
+        "10"
+    );
+    // The version in use before the swap is unaffected.
+    assert_eq!(engine.call(&running.expr, &args).unwrap().to_string(), "2");
+
+    // Failing versions are reported and the previous one stays.
+    updater.push("rule", "($add 1 true)");
+    let errors = store.refresh(&engine);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].name, "rule");
+    match errors[0].failure {
+        ReloadFailure::Invalid(Error::Type(_)) => {}
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+    assert_eq!(store.get("rule").unwrap().version, 2);
+    assert_eq!(
+        store.call(&engine, "rule", &args).unwrap().to_string(),
+        "10"
+    );
+
+    updater.remove("other");
+    assert!(store.refresh(&engine).is_empty());
+    assert!(store.get("other").is_none());
+}
+
+#[test]
+fn test_program_store_compatibility() {
+    let engine = Engine::new();
+    let mut store = ProgramStore::new();
+    let updater = store.updater();
+    updater.push("rule", r"(\x ($add x 1))");
+    updater.push("limit", "(10)");
+    assert!(store.refresh(&engine).is_empty());
+
+    // New versions must be callable like the previous one.
+    updater.push("rule", r"(\x y ($add x y))");
+    updater.push("limit", "(10.5)");
+    let errors = store.refresh(&engine);
+    assert_eq!(
+        errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        vec![
+            "limit: incompatible with int, found float: the types differ",
+            "rule: incompatible with fn(x), found fn(x, y): \
+             takes 2 parameters instead of 1",
+        ]
+    );
+    match errors[0].failure {
+        ReloadFailure::Incompatible {
+            ref expected,
+            ref found,
+            ..
+        } => {
+            assert_eq!(*expected, TypeDescription::Int);
+            assert_eq!(*found, TypeDescription::Float);
+        }
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+    assert_eq!(store.get("limit").unwrap().version, 1);
+    updater.push("rule", r"(\y ($sub y 1))");
+    assert!(store.refresh(&engine).is_empty());
+    assert_eq!(store.get("rule").unwrap().version, 2);
+
+    // A declared type replaces that of the previous version.
+    let policy = function_type(&[TypeDescription::Int], &TypeDescription::Bool).unwrap();
+    store.declare("rule", policy);
+    updater.push("rule", r"(\x ($add x 2))");
+    let errors = store.refresh(&engine);
+    assert_eq!(errors.len(), 1);
+    match errors[0].failure {
+        ReloadFailure::Incompatible { .. } => {}
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+    updater.push("rule", r"(\x ($lt x 2))");
+    assert!(store.refresh(&engine).is_empty());
+    assert_eq!(store.get("rule").unwrap().version, 3);
+
+    // Removing a program lifts the requirement.
+    updater.remove("limit");
+    updater.push("limit", "(10.5)");
+    assert!(store.refresh(&engine).is_empty());
+}
+
+#[test]
+fn test_program_store_watch() {
+    let dir = std::env::temp_dir().join(format!("x-lang-reload-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("rule.x"), r"(\x ($add x 1))").unwrap();
+    fs::write(dir.join("notes.txt"), "not a program").unwrap();
+
+    let engine = Engine::new();
+    let mut store = ProgramStore::new();
+    store.load_dir(&dir).unwrap();
+    assert!(store.refresh(&engine).is_empty());
+    assert_eq!(store.names().collect::<Vec<_>>(), vec!["rule"]);
+
+    let _watch = store.watch(dir.clone(), Duration::from_millis(10));
+    fs::write(dir.join("rule.x"), r"(\x ($sub x 1))").unwrap();
+    let args = [OwnedValue::Int(1)];
+    let start = Instant::now();
+    while store.get("rule").unwrap().version < 2 {
+        assert!(start.elapsed() < Duration::from_secs(10), "no reload");
+        thread::sleep(Duration::from_millis(10));
+        assert!(store.refresh(&engine).is_empty());
+    }
+    assert_eq!(store.call(&engine, "rule", &args).unwrap().to_string(), "0");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,600 @@
+//! Evaluating programs in another process, over TCP or a Unix socket.
+//!
+//! Each message is a bincode-encoded `RemoteRequest` or `RemoteResponse`
+//! preceded by its length as a big-endian `u32`. A client sends requests
+//! one at a time and reads the response to each before sending the next.
+//! The server, `xl-serve`, gives every connection an engine of its own,
+//! set up from an `EngineConfig`, so the limits there apply to every
+//! request, and `ServerLimits` bounds each connection. Clients are not
+//! trusted: unless configured otherwise, requests run with
+//! `server_config()`, which keeps them from reading anything of the server
+//! and bounds their work.
+//!
+//! Values cross the wire as `WireValue`s: plain values, strings, bytes and
+//! lists of those. Other values, such as functions, are returned as
+//! displayed and cannot be passed back. Requests are decoded with
+//! `decode_request`, which bounds how deeply their lists nest.
+
+use crate::bytes::{as_bytes, bytes_value};
+use crate::corelib::{as_str, str_value, Profile};
+use crate::engine::{Engine, EngineConfig, DEFAULT_DEPTH_LIMIT};
+use crate::error::*;
+use crate::eval::{OwnedValue, RuntimeValue};
+use crate::typeck::TypeDescription;
+use bincode::Options;
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, SeqAccess, VariantAccess, Visitor,
+};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Largest message either side accepts by default.
+pub const DEFAULT_MAX_FRAME_BYTES: usize = 16 << 20;
+
+/// Deepest nesting of lists a request may have by default.
+pub const DEFAULT_MAX_VALUE_DEPTH: usize = 128;
+
+/// Variants of `WireValue`, in declaration order, which is how bincode
+/// identifies them. `ValueSeed` decodes them by position.
+const WIRE_VALUE_VARIANTS: &[&str] = &[
+    "Empty", "Int", "Float", "Bool", "String", "Bytes", "List", "Opaque",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WireValue {
+    Empty,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<WireValue>),
+    /// A value without a wire form, as displayed.
+    Opaque(String),
+}
+
+impl WireValue {
+    pub fn from_owned(v: &OwnedValue) -> WireValue {
+        match *v {
+            OwnedValue::Empty => WireValue::Empty,
+            OwnedValue::Int(x) => WireValue::Int(x),
+            OwnedValue::Float(x) => WireValue::Float(x),
+            OwnedValue::Bool(x) => WireValue::Bool(x),
+            OwnedValue::List(ref items) => {
+                WireValue::List(items.iter().map(WireValue::from_owned).collect())
+            }
+            OwnedValue::Custom(ref cv) => {
+                let v = RuntimeValue::Custom(cv.clone());
+                if let Some(s) = as_str(&v) {
+                    WireValue::String(s.to_string())
+                } else if let Some(b) = as_bytes(&v) {
+                    WireValue::Bytes(b.to_vec())
+                } else {
+                    WireValue::Opaque(v.to_string())
+                }
+            }
+            ref v => WireValue::Opaque(v.to_string()),
+        }
+    }
+
+    pub fn to_owned_value(&self) -> Result<OwnedValue, RuntimeError> {
+        Ok(match *self {
+            WireValue::Empty => OwnedValue::Empty,
+            WireValue::Int(x) => OwnedValue::Int(x),
+            WireValue::Float(x) => OwnedValue::Float(x),
+            WireValue::Bool(x) => OwnedValue::Bool(x),
+            WireValue::String(ref s) => custom(str_value(s)),
+            WireValue::Bytes(ref b) => custom(bytes_value(b)),
+            WireValue::List(ref items) => OwnedValue::List(
+                items
+                    .iter()
+                    .map(WireValue::to_owned_value)
+                    .collect::<Result<_, _>>()?,
+            ),
+            WireValue::Opaque(ref s) => {
+                return Err(RuntimeError::TypeMismatch(format!(
+                    "cannot pass {} back to a script",
+                    s
+                )))
+            }
+        })
+    }
+}
+
+fn custom(v: RuntimeValue) -> OwnedValue {
+    match v {
+        RuntimeValue::Custom(cv) => OwnedValue::Custom(cv),
+        _ => unreachable!(),
+    }
+}
+
+/// Decodes a `RemoteRequest` written by `write_frame`, rejecting lists
+/// nested more than `max_depth` deep. Decoding recurses as deep as the
+/// lists nest, so without a bound a request of a few megabytes could
+/// overflow the stack of the thread decoding it.
+pub fn decode_request(bytes: &[u8], max_depth: usize) -> bincode::Result<RemoteRequest> {
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .deserialize_seed(RequestSeed(max_depth), bytes)
+}
+
+/// Decodes a `RemoteRequest` whose lists nest at most this deep.
+struct RequestSeed(usize);
+
+impl<'de> DeserializeSeed<'de> for RequestSeed {
+    type Value = RemoteRequest;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<RemoteRequest, D::Error> {
+        d.deserialize_struct("RemoteRequest", &["source", "args"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for RequestSeed {
+    type Value = RemoteRequest;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a remote request")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<RemoteRequest, A::Error> {
+        let source = seq
+            .next_element::<String>()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let args = seq
+            .next_element_seed(ListSeed(self.0))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(RemoteRequest { source, args })
+    }
+}
+
+/// Decodes a list of `WireValue`s whose lists nest at most this deep.
+struct ListSeed(usize);
+
+impl<'de> DeserializeSeed<'de> for ListSeed {
+    type Value = Vec<WireValue>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Vec<WireValue>, D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ListSeed {
+    type Value = Vec<WireValue>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of values")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<WireValue>, A::Error> {
+        let mut items = Vec::new();
+        while let Some(v) = seq.next_element_seed(ValueSeed(self.0))? {
+            items.push(v);
+        }
+        Ok(items)
+    }
+}
+
+/// Decodes a `WireValue` whose lists nest at most this deep.
+struct ValueSeed(usize);
+
+impl<'de> DeserializeSeed<'de> for ValueSeed {
+    type Value = WireValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<WireValue, D::Error> {
+        d.deserialize_enum("WireValue", WIRE_VALUE_VARIANTS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for ValueSeed {
+    type Value = WireValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a value")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<WireValue, A::Error> {
+        let (variant, v) = data.variant::<u32>()?;
+        Ok(match variant {
+            0 => {
+                v.unit_variant()?;
+                WireValue::Empty
+            }
+            1 => WireValue::Int(v.newtype_variant()?),
+            2 => WireValue::Float(v.newtype_variant()?),
+            3 => WireValue::Bool(v.newtype_variant()?),
+            4 => WireValue::String(v.newtype_variant()?),
+            5 => WireValue::Bytes(v.newtype_variant()?),
+            6 => match self.0.checked_sub(1) {
+                Some(depth) => WireValue::List(v.newtype_variant_seed(ListSeed(depth))?),
+                None => return Err(de::Error::custom("lists nested too deeply")),
+            },
+            7 => WireValue::Opaque(v.newtype_variant()?),
+            n => {
+                return Err(de::Error::invalid_value(
+                    de::Unexpected::Unsigned(n.into()),
+                    &"a value variant",
+                ))
+            }
+        })
+    }
+}
+
+/// Evaluates `source`, or with `args`, calls the function it evaluates to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RemoteRequest {
+    pub source: String,
+    pub args: Vec<WireValue>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RemoteResponse {
+    Value {
+        ty: TypeDescription,
+        value: WireValue,
+    },
+    Error(RemoteError),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteErrorKind {
+    /// The request could not be decoded.
+    Request,
+    Parse,
+    Type,
+    Runtime,
+    /// A limit of the server or the connection was reached, e.g. the step
+    /// limit.
+    Limit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RemoteError {
+    pub kind: RemoteErrorKind,
+    pub message: String,
+}
+
+impl RemoteError {
+    fn new(kind: RemoteErrorKind, message: String) -> RemoteError {
+        RemoteError { kind, message }
+    }
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} error: {}", self.kind, self.message)
+    }
+}
+
+impl From<Error> for RemoteError {
+    fn from(e: Error) -> RemoteError {
+        let kind = match e {
+            Error::Parse(_) => RemoteErrorKind::Parse,
+            Error::Type(_) => RemoteErrorKind::Type,
+            Error::Runtime(RuntimeError::StepLimit)
+            | Error::Runtime(RuntimeError::SlotLimit { .. })
+            | Error::Runtime(RuntimeError::StackOverflow { .. }) => RemoteErrorKind::Limit,
+            Error::Runtime(_) => RemoteErrorKind::Runtime,
+        };
+        let message = match e {
+            Error::Parse(ref e) => format!("{:?}", e),
+            Error::Type(ref e) => e.to_string(),
+            Error::Runtime(ref e) => e.to_string(),
+        };
+        RemoteError::new(kind, message)
+    }
+}
+
+/// Writes `msg` as one length-prefixed message.
+pub fn write_frame<W: Write, T: serde::Serialize>(w: &mut W, msg: &T) -> io::Result<()> {
+    let bytes = bincode::serialize(msg).map_err(|e| invalid(e.to_string()))?;
+    let len = u32::try_from(bytes.len()).map_err(|_| invalid("message too long".into()))?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(&bytes)?;
+    w.flush()
+}
+
+/// Reads one length-prefixed message of at most `max_bytes`, or `None` if
+/// the stream ended before it.
+pub fn read_frame<R: Read>(r: &mut R, max_bytes: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_bytes {
+        return Err(invalid(format!(
+            "message of {} bytes exceeds the limit of {}",
+            len, max_bytes
+        )));
+    }
+    let mut bytes = vec![0; len];
+    r.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// What a server allows each connection.
+#[derive(Debug, Clone)]
+pub struct ServerLimits {
+    /// Largest request accepted; the connection is closed on larger ones.
+    pub max_frame_bytes: usize,
+    /// Requests answered before the connection is closed.
+    pub max_requests: Option<u64>,
+    /// How long a connection may wait between requests, and a response may
+    /// wait to be read.
+    pub idle_timeout: Option<Duration>,
+    /// Deepest nesting of lists in a request; deeper requests are answered
+    /// with a `Request` error.
+    pub max_value_depth: usize,
+    /// Connections served at once. Further ones are closed as soon as they
+    /// are accepted.
+    pub max_connections: usize,
+}
+
+impl Default for ServerLimits {
+    fn default() -> ServerLimits {
+        ServerLimits {
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            max_requests: None,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            max_value_depth: DEFAULT_MAX_VALUE_DEPTH,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+}
+
+/// How long a connection may be idle by default.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Connections served at once by default.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
+
+/// Most steps a request may take by default.
+pub const DEFAULT_SERVER_STEP_LIMIT: u64 = 1_000_000;
+
+/// Most slots a request may allocate at once by default.
+pub const DEFAULT_SERVER_SLOT_LIMIT: usize = 1_000_000;
+
+/// The configuration `xl-serve` runs requests with unless told otherwise:
+/// `Profile::DataTransform`, so that clients cannot read the clock, the
+/// environment or anything else of the server, and finite step, slot and
+/// depth limits, so that no request can exhaust it.
+pub fn server_config() -> EngineConfig {
+    EngineConfig {
+        profile: Profile::DataTransform,
+        step_limit: Some(DEFAULT_SERVER_STEP_LIMIT),
+        slot_limit: Some(DEFAULT_SERVER_SLOT_LIMIT),
+        depth_limit: Some(DEFAULT_DEPTH_LIMIT),
+        ..EngineConfig::default()
+    }
+}
+
+/// Answers the requests of one connection until it is closed or reaches
+/// `limits.max_requests`, which is answered with a `Limit` error. A request
+/// that makes the engine panic is answered with a `Runtime` error and ends
+/// the connection.
+pub fn serve_connection<S: Read + Write>(
+    mut stream: S,
+    config: &EngineConfig,
+    limits: &ServerLimits,
+) -> io::Result<()> {
+    let engine = Engine::with_config(config);
+    let mut served = 0;
+    while let Some(bytes) = read_frame(&mut stream, limits.max_frame_bytes)? {
+        if limits.max_requests == Some(served) {
+            let e = RemoteError::new(
+                RemoteErrorKind::Limit,
+                format!("connection limited to {} requests", served),
+            );
+            return write_frame(&mut stream, &RemoteResponse::Error(e));
+        }
+        served += 1;
+        let req = match decode_request(&bytes, limits.max_value_depth) {
+            Ok(req) => req,
+            Err(e) => {
+                let e = RemoteError::new(RemoteErrorKind::Request, e.to_string());
+                write_frame(&mut stream, &RemoteResponse::Error(e))?;
+                continue;
+            }
+        };
+        match panic::catch_unwind(AssertUnwindSafe(|| handle(&engine, &req))) {
+            Ok(response) => write_frame(&mut stream, &response)?,
+            Err(payload) => {
+                let e = RemoteError::new(
+                    RemoteErrorKind::Runtime,
+                    format!("evaluation failed: {}", panic_message(&*payload)),
+                );
+                return write_frame(&mut stream, &RemoteResponse::Error(e));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates one request.
+pub fn handle(engine: &Engine, req: &RemoteRequest) -> RemoteResponse {
+    let run = || -> Result<(OwnedValue, TypeDescription), Error> {
+        if req.args.is_empty() {
+            let ty = engine.infer_type(&req.source)?;
+            return Ok((engine.eval_owned(&req.source)?, ty));
+        }
+        let args = req
+            .args
+            .iter()
+            .map(WireValue::to_owned_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let (v, ty) = engine.call_typed(req.source.as_str(), &args)?;
+        Ok((v, TypeDescription::of(&ty)))
+    };
+    match run() {
+        Ok((v, ty)) => RemoteResponse::Value {
+            ty,
+            value: WireValue::from_owned(&v),
+        },
+        Err(e) => RemoteResponse::Error(e.into()),
+    }
+}
+
+/// How long to wait after failing to accept a connection, e.g. because the
+/// process ran out of file descriptors, before accepting again.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Serves every connection to `listener` on a thread of its own, up to
+/// `limits.max_connections` at once. Failures to accept a connection are
+/// reported on stderr and do not stop the server.
+pub fn serve_tcp(
+    listener: TcpListener,
+    config: EngineConfig,
+    limits: ServerLimits,
+) -> io::Result<()> {
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream.and_then(|s| {
+            s.set_read_timeout(limits.idle_timeout)?;
+            s.set_write_timeout(limits.idle_timeout)?;
+            Ok(s)
+        }) {
+            Ok(stream) => stream,
+            Err(e) => {
+                accept_failed(e);
+                continue;
+            }
+        };
+        let slot = match ConnectionSlot::take(&active, limits.max_connections) {
+            Some(slot) => slot,
+            None => continue,
+        };
+        let (config, limits) = (config.clone(), limits.clone());
+        thread::spawn(move || {
+            let _slot = slot;
+            serve_connection(stream, &config, &limits)
+        });
+    }
+    Ok(())
+}
+
+/// Serves every connection to `listener` on a thread of its own, like
+/// `serve_tcp`.
+#[cfg(unix)]
+pub fn serve_unix(
+    listener: UnixListener,
+    config: EngineConfig,
+    limits: ServerLimits,
+) -> io::Result<()> {
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream.and_then(|s| {
+            s.set_read_timeout(limits.idle_timeout)?;
+            s.set_write_timeout(limits.idle_timeout)?;
+            Ok(s)
+        }) {
+            Ok(stream) => stream,
+            Err(e) => {
+                accept_failed(e);
+                continue;
+            }
+        };
+        let slot = match ConnectionSlot::take(&active, limits.max_connections) {
+            Some(slot) => slot,
+            None => continue,
+        };
+        let (config, limits) = (config.clone(), limits.clone());
+        thread::spawn(move || {
+            let _slot = slot;
+            serve_connection(stream, &config, &limits)
+        });
+    }
+    Ok(())
+}
+
+/// One of the connections a server serves at once, given back when
+/// dropped.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn take(active: &Arc<AtomicUsize>, max: usize) -> Option<ConnectionSlot> {
+        active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n < max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .map(|_| ConnectionSlot(active.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn accept_failed(e: io::Error) {
+    eprintln!("xl-serve: cannot accept connection: {}", e);
+    thread::sleep(ACCEPT_RETRY_DELAY);
+}
+
+pub struct Client<S: Read + Write> {
+    stream: S,
+    /// Largest response accepted.
+    pub max_frame_bytes: usize,
+}
+
+impl Client<TcpStream> {
+    pub fn connect_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<Client<TcpStream>> {
+        Ok(Client::new(TcpStream::connect(addr)?))
+    }
+}
+
+#[cfg(unix)]
+impl Client<UnixStream> {
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> io::Result<Client<UnixStream>> {
+        Ok(Client::new(UnixStream::connect(path)?))
+    }
+}
+
+impl<S: Read + Write> Client<S> {
+    pub fn new(stream: S) -> Client<S> {
+        Client {
+            stream,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+        }
+    }
+
+    pub fn request(&mut self, req: &RemoteRequest) -> io::Result<RemoteResponse> {
+        write_frame(&mut self.stream, req)?;
+        let bytes = read_frame(&mut self.stream, self.max_frame_bytes)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server")
+        })?;
+        bincode::deserialize(&bytes).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Evaluates `source` on the server, calling it with `args` if there
+    /// are any.
+    pub fn eval(&mut self, source: &str, args: &[OwnedValue]) -> io::Result<RemoteResponse> {
+        self.request(&RemoteRequest {
+            source: source.to_string(),
+            args: args.iter().map(WireValue::from_owned).collect(),
+        })
+    }
+}
@@ -0,0 +1,221 @@
+use crate::engine::EngineConfig;
+use crate::eval::OwnedValue;
+use crate::remote::*;
+use crate::typeck::TypeDescription;
+use std::io::Write;
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+fn value(resp: RemoteResponse) -> (TypeDescription, WireValue) {
+    match resp {
+        RemoteResponse::Value { ty, value } => (ty, value),
+        RemoteResponse::Error(e) => panic!("unexpected error: {}", e),
+    }
+}
+
+fn error(resp: RemoteResponse) -> RemoteError {
+    match resp {
+        RemoteResponse::Error(e) => e,
+        RemoteResponse::Value { value, .. } => panic!("unexpected value: {:?}", value),
+    }
+}
+
+#[cfg(unix)]
+fn client(config: EngineConfig, limits: ServerLimits) -> Client<std::os::unix::net::UnixStream> {
+    let (a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+    thread::spawn(move || serve_connection(b, &config, &limits));
+    Client::new(a)
+}
+
+#[test]
+#[cfg(unix)]
+fn test_remote_eval() {
+    let mut client = client(EngineConfig::default(), ServerLimits::default());
+    let (ty, v) = value(client.eval("($add 1 2)", &[]).unwrap());
+    assert_eq!(ty, TypeDescription::Int);
+    assert_eq!(v, WireValue::Int(3));
+
+    let (ty, v) = value(
+        client
+            .eval(
+                r"(\x y ($mul x y))",
+                &[OwnedValue::Int(6), OwnedValue::Int(7)],
+            )
+            .unwrap(),
+    );
+    assert_eq!(ty, TypeDescription::Int);
+    assert_eq!(v, WireValue::Int(42));
+
+    let (_, v) = value(client.eval("(\"hi\")", &[]).unwrap());
+    assert_eq!(v, WireValue::String("hi".into()));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_remote_errors() {
+    let config = EngineConfig {
+        step_limit: Some(20),
+        ..EngineConfig::default()
+    };
+    let mut client = client(config, ServerLimits::default());
+    assert_eq!(
+        error(client.eval("($add 1 true)", &[]).unwrap()).kind,
+        RemoteErrorKind::Type
+    );
+    assert_eq!(
+        error(client.eval("(", &[]).unwrap()).kind,
+        RemoteErrorKind::Parse
+    );
+    let spin = r"((\f (f (f (f (f 1))))) (\y ($add y ($add y y))))";
+    assert_eq!(
+        error(client.eval(spin, &[]).unwrap()).kind,
+        RemoteErrorKind::Limit
+    );
+    // The connection outlives failed requests.
+    assert_eq!(value(client.eval("(1)", &[]).unwrap()).1, WireValue::Int(1));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_remote_request_limit() {
+    let limits = ServerLimits {
+        max_requests: Some(1),
+        ..ServerLimits::default()
+    };
+    let mut client = client(EngineConfig::default(), limits);
+    value(client.eval("(1)", &[]).unwrap());
+    assert_eq!(
+        error(client.eval("(1)", &[]).unwrap()).kind,
+        RemoteErrorKind::Limit
+    );
+    assert!(client.eval("(1)", &[]).is_err());
+}
+
+#[test]
+fn test_remote_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || serve_tcp(listener, EngineConfig::default(), ServerLimits::default()));
+    let mut client = Client::connect_tcp(addr).unwrap();
+    let (_, v) = value(
+        client
+            .eval(
+                r"(\xs ($list_tail xs))",
+                &[OwnedValue::List(vec![
+                    OwnedValue::Bool(false),
+                    OwnedValue::Bool(true),
+                ])],
+            )
+            .unwrap(),
+    );
+    assert_eq!(v, WireValue::List(vec![WireValue::Bool(true)]));
+}
+
+#[test]
+fn test_remote_frame_limit() {
+    let mut buf = vec![];
+    write_frame(&mut buf, &"x".repeat(100)).unwrap();
+    assert!(read_frame(&mut &buf[..], 10).is_err());
+    assert!(read_frame(&mut &buf[..], 1000).unwrap().is_some());
+    assert!(read_frame(&mut &[][..], 1000).unwrap().is_none());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_remote_server_config() {
+    let config = server_config();
+    assert!(config.step_limit.is_some() && config.depth_limit.is_some());
+    let mut client = client(config, ServerLimits::default());
+    assert_eq!(
+        error(client.eval("($getenv \"HOME\")", &[]).unwrap()).kind,
+        RemoteErrorKind::Type
+    );
+    assert_eq!(
+        value(client.eval("($add 1 2)", &[]).unwrap()).1,
+        WireValue::Int(3)
+    );
+}
+
+/// A request whose one argument is `depth` lists nested in each other,
+/// encoded as `write_frame` would.
+fn nested_request(depth: usize) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&1u64.to_le_bytes());
+    bytes.push(b'f');
+    bytes.extend_from_slice(&1u64.to_le_bytes());
+    for _ in 0..depth {
+        bytes.extend_from_slice(&6u32.to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+    }
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn test_remote_value_depth() {
+    let req = decode_request(&nested_request(3), 3).unwrap();
+    assert_eq!(req.source, "f");
+    assert_eq!(
+        req.args,
+        vec![WireValue::List(vec![WireValue::List(vec![
+            WireValue::List(vec![WireValue::Empty])
+        ])])]
+    );
+    assert!(decode_request(&nested_request(4), 3).is_err());
+
+    let req = RemoteRequest {
+        source: "($add 1 2)".into(),
+        args: vec![WireValue::String("x".into()), WireValue::Float(1.5)],
+    };
+    let bytes = bincode::serialize(&req).unwrap();
+    assert_eq!(decode_request(&bytes, 0).unwrap(), req);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_remote_deeply_nested_request() {
+    let (mut a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+    thread::spawn(move || serve_connection(b, &EngineConfig::default(), &ServerLimits::default()));
+    let bytes = nested_request(1_000_000);
+    assert!(bytes.len() < DEFAULT_MAX_FRAME_BYTES);
+    a.write_all(&(bytes.len() as u32).to_be_bytes()).unwrap();
+    a.write_all(&bytes).unwrap();
+    let resp = read_frame(&mut a, DEFAULT_MAX_FRAME_BYTES)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        error(bincode::deserialize(&resp).unwrap()).kind,
+        RemoteErrorKind::Request
+    );
+    // The connection outlives the request.
+    let mut client = Client::new(a);
+    assert_eq!(value(client.eval("(1)", &[]).unwrap()).1, WireValue::Int(1));
+}
+
+#[test]
+fn test_remote_connection_limit() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let limits = ServerLimits {
+        max_connections: 1,
+        ..ServerLimits::default()
+    };
+    thread::spawn(move || serve_tcp(listener, EngineConfig::default(), limits));
+    let mut first = Client::connect_tcp(addr).unwrap();
+    value(first.eval("(1)", &[]).unwrap());
+    let mut second = Client::connect_tcp(addr).unwrap();
+    assert!(second.eval("(1)", &[]).is_err());
+
+    // Closing a connection makes room for another.
+    drop(first);
+    for _ in 0..100 {
+        let mut client = Client::connect_tcp(addr).unwrap();
+        if let Ok(resp) = client.eval("(1)", &[]) {
+            assert_eq!(value(resp).1, WireValue::Int(1));
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("connection slot never given back");
+}
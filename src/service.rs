@@ -0,0 +1,213 @@
+//! JSON request/response protocol for playgrounds and grader backends.
+//!
+//! A request carries the program source and options; the response carries
+//! the inferred type and the value, or a structured error. Sources are not
+//! trusted: evaluation is bounded in steps, depth and time, and requests
+//! may tighten these bounds but not lift them.
+
+use crate::ast::{DataType, Span};
+use crate::corelib::HostManager;
+use crate::engine::DEFAULT_DEPTH_LIMIT;
+use crate::error::*;
+use crate::eval::{evaluate, EvalContext};
+use crate::host::verify_hosts;
+use crate::parser::{parse_expr_with_options, ParseOptions};
+use crate::recursion::divergence_message;
+use crate::typeck::{check_expr, TypeResolveState};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+/// Most steps a request may take.
+pub const SERVICE_STEP_LIMIT: u64 = 1_000_000;
+
+/// Most milliseconds a request may evaluate for before it is cancelled.
+pub const SERVICE_TIMEOUT_MS: u64 = 5_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServiceRequest {
+    pub source: String,
+    #[serde(default)]
+    pub options: ServiceOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceOptions {
+    /// Stop after typechecking and return only the type.
+    #[serde(default)]
+    pub typecheck_only: bool,
+    /// Most steps evaluation may take, at most `SERVICE_STEP_LIMIT`.
+    #[serde(default = "default_step_limit")]
+    pub step_limit: u64,
+    /// Deepest evaluation may nest, at most `DEFAULT_DEPTH_LIMIT`.
+    #[serde(default = "default_depth_limit")]
+    pub depth_limit: u32,
+    /// Milliseconds after which evaluation is cancelled, at most
+    /// `SERVICE_TIMEOUT_MS`. Not enforced on wasm, which has no threads to
+    /// time evaluation with.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for ServiceOptions {
+    fn default() -> ServiceOptions {
+        ServiceOptions {
+            typecheck_only: false,
+            step_limit: default_step_limit(),
+            depth_limit: default_depth_limit(),
+            timeout_ms: default_timeout_ms(),
+        }
+    }
+}
+
+fn default_step_limit() -> u64 {
+    SERVICE_STEP_LIMIT
+}
+
+fn default_depth_limit() -> u32 {
+    DEFAULT_DEPTH_LIMIT
+}
+
+fn default_timeout_ms() -> u64 {
+    SERVICE_TIMEOUT_MS
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServiceResponse {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ty: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Number of expressions evaluated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ServiceError>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceError {
+    /// One of `request`, `parse`, `type` or `runtime`.
+    pub kind: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+impl ServiceError {
+    fn new(kind: &str, message: String) -> ServiceError {
+        ServiceError {
+            kind: kind.to_string(),
+            message,
+            span: None,
+            suggestion: None,
+        }
+    }
+}
+
+impl From<ParseError> for ServiceError {
+    fn from(e: ParseError) -> ServiceError {
+        ServiceError {
+            span: e.span(),
+            suggestion: e.suggestion(),
+            ..ServiceError::new("parse", e.to_string())
+        }
+    }
+}
+
+impl From<TypeError> for ServiceError {
+    fn from(e: TypeError) -> ServiceError {
+        match e {
+            TypeError::Custom(msg) => ServiceError::new("type", msg),
+            e @ TypeError::Mismatch { .. } | e @ TypeError::IncompatibleHosts(_) => {
+                ServiceError::new("type", e.to_string())
+            }
+        }
+    }
+}
+
+impl From<RuntimeError> for ServiceError {
+    fn from(e: RuntimeError) -> ServiceError {
+        ServiceError::new("runtime", e.to_string())
+    }
+}
+
+/// Handles one JSON-encoded `ServiceRequest`, returning a JSON-encoded
+/// `ServiceResponse`.
+pub fn handle_json(request: &str) -> String {
+    let resp = match serde_json::from_str::<ServiceRequest>(request) {
+        Ok(req) => handle(&req),
+        Err(e) => ServiceResponse {
+            error: Some(ServiceError::new("request", e.to_string())),
+            ..Default::default()
+        },
+    };
+    serde_json::to_string(&resp).expect("bug: response serialization failed")
+}
+
+pub fn handle(req: &ServiceRequest) -> ServiceResponse {
+    let mut resp = ServiceResponse::default();
+    if let Err(e) = run(req, &mut resp) {
+        resp.error = Some(e);
+    }
+    resp
+}
+
+fn run(req: &ServiceRequest, resp: &mut ServiceResponse) -> Result<(), ServiceError> {
+    let hm = HostManager::new();
+    let mut opts = ParseOptions::default();
+    opts.add_hosts(hm.get_all());
+    let ast = parse_expr_with_options(&req.source, &opts)?;
+
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_all());
+
+    let ty = check_expr(&ast, &mut trs)?;
+    resp.ty = Some(ty.to_string());
+    resp.warnings = trs.warnings().iter().map(|w| w.to_string()).collect();
+    if req.options.typecheck_only {
+        return Ok(());
+    }
+    if ty == DataType::Divergent {
+        return Err(ServiceError::new("type", divergence_message(&ast, None)));
+    }
+
+    let options = &req.options;
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    ectx.set_step_limit(Some(options.step_limit.min(SERVICE_STEP_LIMIT)));
+    ectx.set_depth_limit(Some(options.depth_limit.min(DEFAULT_DEPTH_LIMIT)));
+    let cancel = Arc::new(AtomicBool::new(false));
+    ectx.set_cancel_flag(Some(cancel.clone()));
+    #[cfg(not(target_arch = "wasm32"))]
+    let _timer = cancel_after(options.timeout_ms.min(SERVICE_TIMEOUT_MS), cancel);
+    verify_hosts(&trs, &ectx)?;
+    let outcome = evaluate(&ast, &mut ectx)?;
+    resp.value = Some(outcome.value.to_string());
+    resp.steps = Some(outcome.steps);
+    resp.warnings
+        .extend(outcome.warnings.iter().map(|w| w.to_string()));
+    Ok(())
+}
+
+/// Sets `flag` once `ms` milliseconds have passed, unless the returned
+/// sender is dropped first.
+#[cfg(not(target_arch = "wasm32"))]
+fn cancel_after(ms: u64, flag: Arc<AtomicBool>) -> mpsc::Sender<()> {
+    let (tx, rx) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(Duration::from_millis(ms)) {
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+    tx
+}
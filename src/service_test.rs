@@ -0,0 +1,50 @@
+use crate::service::*;
+
+#[test]
+fn test_service_json() {
+    let resp: serde_json::Value =
+        serde_json::from_str(&handle_json(r#"{"source": "($mul 6 7)"}"#)).unwrap();
+    assert_eq!(resp["value"], "42");
+    assert_eq!(resp["steps"], 4);
+    assert!(resp.get("warnings").is_none());
+    assert!(resp.get("error").is_none());
+
+    let resp: serde_json::Value =
+        serde_json::from_str(&handle_json(r#"{"source": "($add 1 1.2.3)"}"#)).unwrap();
+    assert_eq!(resp["error"]["kind"], "parse");
+    assert_eq!(resp["error"]["span"]["start"], 8);
+}
+
+fn respond(request: serde_json::Value) -> serde_json::Value {
+    serde_json::from_str(&handle_json(&request.to_string())).unwrap()
+}
+
+#[test]
+fn test_service_messages() {
+    let resp = respond(serde_json::json!({"source": r"(\x ($add x 1))"}));
+    assert_eq!(resp["type"], "fn(x)");
+    let resp = respond(serde_json::json!({"source": "($div 1 0)"}));
+    assert_eq!(resp["error"]["message"], "division by zero");
+    let resp = respond(serde_json::json!({"source": "($add y 1)"}));
+    assert_eq!(resp["error"]["message"], "name not found: y");
+}
+
+#[test]
+fn test_service_limits() {
+    // Calls itself twice per level, taking about two million steps.
+    let source = r"((\f (f f 20)) ($dyn (\self n ($if ($eq n 0) 0
+        ($add (self self ($sub n 1)) (self self ($sub n 1)))))))";
+    let limited = |options: serde_json::Value| {
+        let resp = respond(serde_json::json!({"source": source, "options": options}));
+        resp["error"]["message"].as_str().unwrap().to_string()
+    };
+    assert_eq!(
+        limited(serde_json::json!({"step_limit": u64::MAX})),
+        "step limit exceeded"
+    );
+    assert_eq!(
+        limited(serde_json::json!({"timeout_ms": 0})),
+        "evaluation cancelled"
+    );
+    assert!(limited(serde_json::json!({"depth_limit": 10})).starts_with("stack overflow"));
+}
@@ -0,0 +1,61 @@
+use crate::ast::DataType;
+use crate::builtin::ValueType;
+use crate::corelib::HostManager;
+use crate::parser::parse_expr_with_globals;
+use crate::typeck::Session;
+use std::collections::BTreeSet;
+
+const NAMES: &[&str] = &["base", "double", "total", "flag", "late", "future"];
+
+fn define(session: &mut Session, name: &str, src: &str) -> Vec<String> {
+    let globals: BTreeSet<String> = NAMES.iter().map(|n| n.to_string()).collect();
+    let e = parse_expr_with_globals(src, globals).unwrap();
+    session.define(name, e).into_keys().collect()
+}
+
+#[test]
+fn test_session_rechecks_dependents() {
+    let mut session = Session::new(HostManager::new().into_owned_hosts());
+    let int = DataType::Value(ValueType::Int);
+    define(&mut session, "base", "(1)");
+    define(&mut session, "double", r"(\x ($add x x))");
+    assert_eq!(define(&mut session, "total", "(double base)"), ["total"]);
+    define(&mut session, "flag", "($not_a_host 1)");
+    assert_eq!(session.result("total").unwrap().as_ref().unwrap(), &int);
+    assert_eq!(
+        session.errors().map(|(k, _)| k).collect::<Vec<_>>(),
+        ["flag"]
+    );
+
+    // Only the changed definition and those depending on it are checked.
+    assert_eq!(define(&mut session, "base", "(true)"), ["base", "total"]);
+    assert!(session.result("total").unwrap().is_err());
+    assert_eq!(
+        define(&mut session, "double", r"(\x ($if x 2 3))"),
+        ["double", "total"]
+    );
+    assert_eq!(session.result("total").unwrap().as_ref().unwrap(), &int);
+    assert_eq!(define(&mut session, "flag", "(false)"), ["flag"]);
+    assert_eq!(session.errors().count(), 0);
+
+    assert_eq!(
+        session.remove("base").into_keys().collect::<Vec<_>>(),
+        ["total"]
+    );
+    assert!(session.result("base").is_none());
+    assert!(session.result("total").unwrap().is_err());
+    assert!(session.remove("base").is_empty());
+}
+
+#[test]
+fn test_session_forward_references() {
+    let mut session = Session::new(HostManager::new().into_owned_hosts());
+    define(&mut session, "late", "($add future 1)");
+    assert!(session.result("late").unwrap().is_err());
+    assert_eq!(define(&mut session, "future", "(41)"), ["future", "late"]);
+    assert!(session.result("late").unwrap().is_ok());
+
+    // References dropped by an edit no longer trigger checks.
+    define(&mut session, "late", "(2)");
+    assert_eq!(define(&mut session, "future", "(true)"), ["future"]);
+}
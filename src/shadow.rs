@@ -0,0 +1,117 @@
+//! Running a candidate version of a program next to the current one, to
+//! find the inputs they disagree on before rolling the candidate out.
+//!
+//! Results are compared structurally: lists element by element and custom
+//! values, such as strings, by type and displayed form, rather than by
+//! identity as `OwnedValue`'s `PartialEq` does. Two failures agree if
+//! their messages do.
+
+use crate::ast::Expr;
+use crate::engine::{Engine, Script};
+use crate::error::*;
+use crate::eval::OwnedValue;
+use crate::reload::ProgramStore;
+
+/// An input the versions disagree on.
+#[derive(Debug)]
+pub struct Divergence {
+    /// Position of the input in those given.
+    pub index: usize,
+    pub args: Vec<OwnedValue>,
+    pub current: Result<OwnedValue, Error>,
+    pub candidate: Result<OwnedValue, Error>,
+}
+
+#[derive(Debug, Default)]
+pub struct ShadowReport {
+    /// Number of inputs both versions were run on.
+    pub runs: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+impl ShadowReport {
+    /// Whether the versions agreed on every input.
+    pub fn is_consistent(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+impl Engine {
+    /// Calls both `current` and `candidate` with each of `inputs`, as
+    /// `Engine::call` would, and reports the inputs on which they disagree.
+    /// Fails without running anything if either does not check.
+    pub fn shadow_call<'a, S, T>(
+        &self,
+        current: S,
+        candidate: T,
+        inputs: &[Vec<OwnedValue>],
+    ) -> Result<ShadowReport, Error>
+    where
+        S: Into<Script<'a>>,
+        T: Into<Script<'a>>,
+    {
+        let current = self.checked(current.into())?;
+        let candidate = self.checked(candidate.into())?;
+        let mut report = ShadowReport::default();
+        for (index, args) in inputs.iter().enumerate() {
+            let a = self.call(&current, args);
+            let b = self.call(&candidate, args);
+            report.runs += 1;
+            if !same_outcome(&a, &b) {
+                report.divergences.push(Divergence {
+                    index,
+                    args: args.clone(),
+                    current: a,
+                    candidate: b,
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    fn checked(&self, script: Script) -> Result<Expr, Error> {
+        match script {
+            Script::Source(source) => self.prepare(source).map(|(e, _)| e),
+            Script::Compiled(e) => self.check(e).map(|_| e.clone()),
+        }
+    }
+}
+
+impl ProgramStore {
+    /// Runs `candidate` next to the current version of the program `name`.
+    /// See `Engine::shadow_call`.
+    pub fn shadow_call(
+        &self,
+        engine: &Engine,
+        name: &str,
+        candidate: &str,
+        inputs: &[Vec<OwnedValue>],
+    ) -> Result<ShadowReport, Error> {
+        let current = self
+            .get(name)
+            .ok_or_else(|| RuntimeError::Custom(format!("unknown program `{}`", name)))?;
+        engine.shadow_call(&current.expr, candidate, inputs)
+    }
+}
+
+fn same_outcome(a: &Result<OwnedValue, Error>, b: &Result<OwnedValue, Error>) -> bool {
+    match (a, b) {
+        (Ok(a), Ok(b)) => same_value(a, b),
+        (Err(a), Err(b)) => a.to_string() == b.to_string(),
+        _ => false,
+    }
+}
+
+/// Structural equality of values.
+pub fn same_value(a: &OwnedValue, b: &OwnedValue) -> bool {
+    match (a, b) {
+        (OwnedValue::List(a), OwnedValue::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a, b))
+        }
+        (OwnedValue::Custom(x), OwnedValue::Custom(y)) => {
+            x.inner.as_any().type_id() == y.inner.as_any().type_id()
+                && a.to_string() == b.to_string()
+        }
+        _ => a == b,
+    }
+}
@@ -0,0 +1,97 @@
+use crate::corelib::str_value;
+use crate::engine::Engine;
+use crate::error::{Error, RuntimeError};
+use crate::eval::{OwnedValue, RuntimeValue};
+use crate::reload::ProgramStore;
+use crate::shadow::same_value;
+
+fn owned(v: RuntimeValue) -> OwnedValue {
+    match v {
+        RuntimeValue::Custom(cv) => OwnedValue::Custom(cv),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_same_value() {
+    let a = owned(str_value("a"));
+    assert_ne!(a, owned(str_value("a")));
+    assert!(same_value(&a, &owned(str_value("a"))));
+    assert!(!same_value(&a, &owned(str_value("b"))));
+    assert!(same_value(
+        &OwnedValue::List(vec![OwnedValue::Int(1), a.clone()]),
+        &OwnedValue::List(vec![OwnedValue::Int(1), owned(str_value("a"))]),
+    ));
+    assert!(!same_value(
+        &OwnedValue::List(vec![OwnedValue::Int(1)]),
+        &OwnedValue::List(vec![OwnedValue::Int(1), OwnedValue::Int(2)]),
+    ));
+    assert!(!same_value(&OwnedValue::Int(1), &OwnedValue::Float(1.0)));
+}
+
+#[test]
+fn test_shadow_call() {
+    let engine = Engine::new();
+    let inputs: Vec<Vec<OwnedValue>> = (-2..3).map(|x| vec![OwnedValue::Int(x)]).collect();
+
+    let report = engine
+        .shadow_call(r"(\x ($mul x 2))", r"(\x ($add x x))", &inputs)
+        .unwrap();
+    assert_eq!(report.runs, 5);
+    assert!(report.is_consistent());
+
+    let report = engine
+        .shadow_call(
+            r"(\x ($div 10 ($add x 3)))",
+            r"(\x ($div 10 ($add x 2)))",
+            &inputs,
+        )
+        .unwrap();
+    let diverged: Vec<usize> = report.divergences.iter().map(|d| d.index).collect();
+    assert_eq!(diverged, vec![0, 1, 2, 3]);
+    let d = &report.divergences[0];
+    assert_eq!(d.args, vec![OwnedValue::Int(-2)]);
+    assert_eq!(d.current.as_ref().unwrap(), &OwnedValue::Int(10));
+    match d.candidate {
+        Err(Error::Runtime(RuntimeError::DivByZero)) => {}
+        ref x => panic!("unexpected result: {:?}", x),
+    }
+
+    // Candidates that do not check are rejected up front.
+    assert!(engine
+        .shadow_call(r"(\x x)", r"(\x ($add x true))", &inputs)
+        .is_err());
+}
+
+#[test]
+fn test_program_store_shadow_call() {
+    let engine = Engine::new();
+    let mut store = ProgramStore::new();
+    store
+        .updater()
+        .push("grade", r#"(\x ($if ($ge x 50) "pass" "fail"))"#);
+    assert!(store.refresh(&engine).is_empty());
+    let inputs: Vec<Vec<OwnedValue>> = [10, 45, 50, 90]
+        .iter()
+        .map(|&x| vec![OwnedValue::Int(x)])
+        .collect();
+    let report = store
+        .shadow_call(
+            &engine,
+            "grade",
+            r#"(\x ($if ($ge x 40) "pass" "fail"))"#,
+            &inputs,
+        )
+        .unwrap();
+    assert_eq!(report.runs, 4);
+    assert_eq!(report.divergences.len(), 1);
+    assert_eq!(report.divergences[0].args, vec![OwnedValue::Int(45)]);
+    assert_eq!(
+        report.divergences[0]
+            .candidate
+            .as_ref()
+            .unwrap()
+            .to_string(),
+        "\"pass\""
+    );
+}
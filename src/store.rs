@@ -0,0 +1,225 @@
+//! Content-addressed storage of compiled programs.
+//!
+//! A `ProgramDb` keeps expressions under the SHA-256 digest of their
+//! encoding, so a program stored twice is kept once, together with who
+//! stored it first, when, and the host functions it calls. Named refs, e.g.
+//! one per rule pointing at its current version, mark the entries in use;
+//! `ProgramDb::gc` deletes the others.
+//!
+//! The database lives in a `KvBackend`, a map from byte keys to byte
+//! values, so embedders can keep it in sled, SQLite, S3 or anything else.
+//! `MemoryBackend` keeps it in memory. Entries are encoded with bincode,
+//! under keys starting with `program/`, and refs under keys starting with
+//! `ref/`.
+
+use crate::ast::{collect_hosts, Expr};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PROGRAM_PREFIX: &str = "program/";
+const REF_PREFIX: &str = "ref/";
+
+/// Where a `ProgramDb` keeps its entries.
+pub trait KvBackend {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+    fn put(&mut self, key: &[u8], value: &[u8]) -> io::Result<()>;
+    /// Deleting a key that is not there is not an error.
+    fn delete(&mut self, key: &[u8]) -> io::Result<()>;
+    /// The keys starting with `prefix`, in any order.
+    fn keys(&self, prefix: &[u8]) -> io::Result<Vec<Vec<u8>>>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+}
+
+impl KvBackend for MemoryBackend {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.entries.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> io::Result<()> {
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+        Ok(self
+            .entries
+            .range(prefix.to_vec()..)
+            .map(|(k, _)| k)
+            .take_while(|k| k.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredMeta {
+    pub author: String,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    /// Host functions the program calls, without the `$`.
+    pub required_hosts: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredProgram {
+    pub expr: Expr,
+    pub meta: StoredMeta,
+}
+
+/// The key of `e` in a `ProgramDb`: the SHA-256 digest of its encoding, in
+/// hex.
+pub fn program_hash(e: &Expr) -> String {
+    let bytes = bincode::serialize(e).expect("bug: expression serialization failed");
+    Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub struct ProgramDb<B: KvBackend> {
+    backend: B,
+}
+
+impl<B: KvBackend> ProgramDb<B> {
+    pub fn new(backend: B) -> ProgramDb<B> {
+        ProgramDb { backend }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn into_backend(self) -> B {
+        self.backend
+    }
+
+    /// Stores `e` unless it is stored already, and returns its hash. The
+    /// metadata of a program stored again is that of the first time.
+    pub fn put(&mut self, e: &Expr, author: &str) -> io::Result<String> {
+        let hash = program_hash(e);
+        let key = program_key(&hash);
+        if self.backend.get(&key)?.is_some() {
+            return Ok(hash);
+        }
+        let mut required_hosts = BTreeSet::new();
+        collect_hosts(e, &mut required_hosts);
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let stored = StoredProgram {
+            expr: e.clone(),
+            meta: StoredMeta {
+                author: author.to_string(),
+                created_at,
+                required_hosts,
+            },
+        };
+        let bytes = bincode::serialize(&stored).expect("bug: program serialization failed");
+        self.backend.put(&key, &bytes)?;
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> io::Result<Option<StoredProgram>> {
+        match self.backend.get(&program_key(hash))? {
+            Some(bytes) => bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| corrupt(format!("program {}: {}", hash, e))),
+            None => Ok(None),
+        }
+    }
+
+    /// Hashes of every stored program.
+    pub fn hashes(&self) -> io::Result<BTreeSet<String>> {
+        self.names(PROGRAM_PREFIX)
+    }
+
+    /// Points the ref `name` at the stored program `hash`.
+    pub fn set_ref(&mut self, name: &str, hash: &str) -> io::Result<()> {
+        if self.backend.get(&program_key(hash))?.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no program {}", hash),
+            ));
+        }
+        self.backend.put(&ref_key(name), hash.as_bytes())
+    }
+
+    pub fn get_ref(&self, name: &str) -> io::Result<Option<String>> {
+        match self.backend.get(&ref_key(name))? {
+            Some(bytes) => String::from_utf8(bytes)
+                .map(Some)
+                .map_err(|_| corrupt(format!("ref {}", name))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove_ref(&mut self, name: &str) -> io::Result<()> {
+        self.backend.delete(&ref_key(name))
+    }
+
+    /// Every ref, by name.
+    pub fn refs(&self) -> io::Result<BTreeMap<String, String>> {
+        let mut refs = BTreeMap::new();
+        for name in self.names(REF_PREFIX)? {
+            if let Some(hash) = self.get_ref(&name)? {
+                refs.insert(name, hash);
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Deletes the programs no ref points at and returns their hashes.
+    pub fn gc(&mut self) -> io::Result<Vec<String>> {
+        let live: BTreeSet<String> = self.refs()?.into_values().collect();
+        let mut deleted = Vec::new();
+        for hash in self.hashes()? {
+            if !live.contains(&hash) {
+                self.backend.delete(&program_key(&hash))?;
+                deleted.push(hash);
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// The keys starting with `prefix`, without it.
+    fn names(&self, prefix: &str) -> io::Result<BTreeSet<String>> {
+        self.backend
+            .keys(prefix.as_bytes())?
+            .into_iter()
+            .map(|key| {
+                String::from_utf8(key[prefix.len()..].to_vec())
+                    .map_err(|_| corrupt(format!("key under {}", prefix)))
+            })
+            .collect()
+    }
+}
+
+fn program_key(hash: &str) -> Vec<u8> {
+    format!("{}{}", PROGRAM_PREFIX, hash).into_bytes()
+}
+
+fn ref_key(name: &str) -> Vec<u8> {
+    format!("{}{}", REF_PREFIX, name).into_bytes()
+}
+
+fn corrupt(what: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("corrupt {}", what))
+}
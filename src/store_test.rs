@@ -0,0 +1,49 @@
+use crate::engine::Engine;
+use crate::eval::OwnedValue;
+use crate::parser::parse_expr;
+use crate::store::{program_hash, MemoryBackend, ProgramDb};
+use std::collections::BTreeSet;
+
+#[test]
+fn test_program_db() {
+    let mut db = ProgramDb::new(MemoryBackend::new());
+    let v1 = parse_expr(r"(\x ($add x 1))").unwrap();
+    let v2 = parse_expr(r"(\x ($mul ($add x 1) 2))").unwrap();
+
+    let h1 = db.put(&v1, "alice").unwrap();
+    assert_eq!(h1, program_hash(&v1));
+    assert_eq!(h1.len(), 64);
+    // Storing the same program again keeps the first metadata.
+    assert_eq!(db.put(&v1, "bob").unwrap(), h1);
+    let h2 = db.put(&v2, "bob").unwrap();
+    assert_ne!(h1, h2);
+
+    let stored = db.get(&h2).unwrap().unwrap();
+    assert_eq!(stored.expr, v2);
+    assert_eq!(stored.meta.author, "bob");
+    assert!(stored.meta.created_at > 0);
+    let hosts: BTreeSet<String> = ["add", "mul"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(stored.meta.required_hosts, hosts);
+    assert_eq!(db.get(&h1).unwrap().unwrap().meta.author, "alice");
+    assert!(db.get("0000").unwrap().is_none());
+
+    // Stored programs run like parsed ones.
+    let engine = Engine::new();
+    let args = [OwnedValue::Int(4)];
+    assert_eq!(engine.call(&stored.expr, &args).unwrap().to_string(), "10");
+
+    db.set_ref("rule", &h1).unwrap();
+    assert!(db.set_ref("other", "0000").is_err());
+    assert_eq!(db.get_ref("rule").unwrap(), Some(h1.clone()));
+    assert_eq!(db.gc().unwrap(), vec![h2.clone()]);
+    assert!(db.get(&h2).unwrap().is_none());
+    assert_eq!(
+        db.hashes().unwrap().into_iter().collect::<Vec<_>>(),
+        vec![h1.clone()]
+    );
+
+    db.remove_ref("rule").unwrap();
+    assert!(db.refs().unwrap().is_empty());
+    assert_eq!(db.gc().unwrap(), vec![h1]);
+    assert!(db.hashes().unwrap().is_empty());
+}
@@ -0,0 +1,127 @@
+//! Evaluation that can be suspended partway through and resumed later.
+//!
+//! Evaluation recurses through host functions on the native stack, so it
+//! cannot return to its caller halfway and pick up again later. A
+//! `Suspendable` runs it on a stack of its own instead, as a coroutine.
+//! When evaluation needs its driver, at the end of a slice of steps or at
+//! an async host function call, `EvalContext::suspend` switches back to
+//! the driver's stack, leaving everything in progress on its own. Resuming
+//! switches back and evaluation carries on exactly where it stopped, so
+//! nothing is evaluated twice.
+//!
+//! Coroutines are not available on wasm, which has no way to switch
+//! stacks.
+
+#[cfg(feature = "async")]
+use crate::async_host::AsyncHostFunction;
+use crate::error::*;
+use crate::eval::HostState;
+#[cfg(feature = "async")]
+use crate::eval::OwnedValue;
+#[cfg(not(target_arch = "wasm32"))]
+use corosensei::stack::DefaultStack;
+#[cfg(not(target_arch = "wasm32"))]
+use corosensei::{CoroutineResult, ScopedCoroutine};
+#[cfg(not(target_arch = "wasm32"))]
+use std::cell::RefCell;
+use std::fmt::{self, Debug};
+#[cfg(feature = "async")]
+use std::rc::Rc;
+
+/// Size of the stack suspendable evaluation runs on: that of threads
+/// spawned by `std::thread`, which `DEFAULT_DEPTH_LIMIT` is sized for.
+pub const STACK_SIZE: usize = 2 << 20;
+
+/// Why evaluation suspended.
+pub(crate) enum Suspension {
+    /// It ran the steps it was last resumed with.
+    Steps,
+    /// It waits for the result of calling async host function `host`.
+    #[cfg(feature = "async")]
+    AsyncCall {
+        host: Rc<dyn AsyncHostFunction>,
+        args: Vec<OwnedValue>,
+    },
+}
+
+/// What suspended evaluation is resumed with.
+pub(crate) enum Resume {
+    /// Run up to this many more steps.
+    Steps(u64),
+    /// The result of the async call it waits for.
+    #[cfg(feature = "async")]
+    AsyncResult(Result<OwnedValue, RuntimeError>),
+}
+
+impl Resume {
+    /// The steps to run, for evaluations only ever resumed with steps.
+    pub fn steps(self) -> u64 {
+        match self {
+            Resume::Steps(n) => n,
+            #[cfg(feature = "async")]
+            Resume::AsyncResult(_) => panic!("bug: expecting steps, got an async result"),
+        }
+    }
+}
+
+/// Hands control back to the driver of a suspendable evaluation.
+#[derive(Clone, Copy)]
+pub(crate) struct Suspender<'a>(&'a dyn Fn(&mut HostState, Suspension) -> Resume);
+
+impl<'a> Suspender<'a> {
+    /// Suspends evaluation and returns what the driver resumed it with.
+    /// The engine keeps the host state `state` meanwhile, so that other
+    /// evaluations on it can use it.
+    pub fn suspend(&self, state: &mut HostState, why: Suspension) -> Resume {
+        (self.0)(state, why)
+    }
+}
+
+impl<'a> Debug for Suspender<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Suspender")
+    }
+}
+
+/// An evaluation running on a stack of its own, which produces a `T` once
+/// it has finished.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Suspendable<'a, T> {
+    coroutine: ScopedCoroutine<'a, Resume, Suspension, T, DefaultStack>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'a, T> Suspendable<'a, T> {
+    /// Prepares to run `f` once first resumed. `f` gets the suspender to
+    /// evaluate with and what it was first resumed with.
+    ///
+    /// `host_state` is where the engine keeps its host state, which `f`
+    /// takes for as long as it runs.
+    pub fn new<F>(host_state: &'a RefCell<HostState>, f: F) -> Result<Self, RuntimeError>
+    where
+        F: FnOnce(Suspender<'_>, Resume) -> T + 'a,
+    {
+        let stack = DefaultStack::new(STACK_SIZE).map_err(|e| {
+            RuntimeError::Custom(format!("cannot allocate evaluation stack: {}", e))
+        })?;
+        let coroutine = ScopedCoroutine::with_stack(stack, move |yielder, first| {
+            let suspend = |state: &mut HostState, why: Suspension| {
+                host_state.replace(::std::mem::take(state));
+                let resume = yielder.suspend(why);
+                *state = host_state.take();
+                resume
+            };
+            f(Suspender(&suspend), first)
+        });
+        Ok(Suspendable { coroutine })
+    }
+
+    /// Runs until evaluation suspends, returning why, or finishes,
+    /// returning its result. Must not be called again once it finished.
+    pub fn resume(&mut self, with: Resume) -> Result<T, Suspension> {
+        match self.coroutine.resume(with) {
+            CoroutineResult::Yield(why) => Err(why),
+            CoroutineResult::Return(out) => Ok(out),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Running programs for many tenants in one process, e.g. rules that the
+//! customers of a service write themselves.
+//!
+//! An `EnginePool` parses each program once, however many tenants run it,
+//! and gives every tenant an `Engine` of its own, set up from an
+//! `EngineConfig`. The host functions a tenant can call, its limits, its
+//! host state and its log sink are never shared with other tenants. Host
+//! functions added to the pool are registered with every tenant, whose
+//! profile still decides whether scripts can call them, since they belong
+//! to `HostGroup::Io`.
+//!
+//! Programs are checked against a tenant's host functions whenever the
+//! tenant calls them, so a program calling a host function a tenant lacks
+//! fails for that tenant alone. Programs cannot refer to the definitions of
+//! a tenant's engine.
+
+use crate::ast::Expr;
+use crate::corelib::HostGroup;
+use crate::engine::{Engine, EngineConfig};
+use crate::error::*;
+use crate::eval::OwnedValue;
+use crate::host::HostFunction;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub struct EnginePool {
+    /// Parses programs, with every host function any tenant may have.
+    parser: Engine,
+    hosts: Vec<(String, Arc<dyn HostFunction>)>,
+    programs: BTreeMap<String, Expr>,
+    tenants: BTreeMap<String, Engine>,
+}
+
+impl Default for EnginePool {
+    fn default() -> EnginePool {
+        let mut parser = Engine::new();
+        parser.allow_host_group(HostGroup::Eval);
+        EnginePool {
+            parser,
+            hosts: vec![],
+            programs: BTreeMap::new(),
+            tenants: BTreeMap::new(),
+        }
+    }
+}
+
+impl EnginePool {
+    pub fn new() -> EnginePool {
+        EnginePool::default()
+    }
+
+    /// Registers a host function with every tenant, current and future,
+    /// callable as `$name`.
+    pub fn add_host(&mut self, name: &str, hf: Arc<dyn HostFunction>) {
+        self.parser.add_host(name.to_string(), Box::new(hf.clone()));
+        for engine in self.tenants.values_mut() {
+            engine.add_host(name.to_string(), Box::new(hf.clone()));
+        }
+        self.hosts.push((name.to_string(), hf));
+    }
+
+    /// Adds the tenant `id`, replacing any tenant of that name, and returns
+    /// its engine for further setup, e.g. a log sink.
+    pub fn add_tenant(&mut self, id: &str, config: &EngineConfig) -> &mut Engine {
+        let mut engine = Engine::with_config(config);
+        for (name, hf) in &self.hosts {
+            engine.add_host(name.clone(), Box::new(hf.clone()));
+        }
+        self.tenants.insert(id.to_string(), engine);
+        self.tenants.get_mut(id).unwrap()
+    }
+
+    pub fn remove_tenant(&mut self, id: &str) -> Option<Engine> {
+        self.tenants.remove(id)
+    }
+
+    pub fn tenant(&self, id: &str) -> Option<&Engine> {
+        self.tenants.get(id)
+    }
+
+    pub fn tenant_mut(&mut self, id: &str) -> Option<&mut Engine> {
+        self.tenants.get_mut(id)
+    }
+
+    /// Names of the tenants, in order.
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.tenants.keys().map(|id| id.as_str())
+    }
+
+    /// Parses `source` as the program `name`, replacing any program of that
+    /// name for every tenant.
+    pub fn add_program(&mut self, name: &str, source: &str) -> Result<(), Error> {
+        let e = self.parser.parse(source)?;
+        self.programs.insert(name.to_string(), e);
+        Ok(())
+    }
+
+    pub fn remove_program(&mut self, name: &str) -> Option<Expr> {
+        self.programs.remove(name)
+    }
+
+    pub fn program(&self, name: &str) -> Option<&Expr> {
+        self.programs.get(name)
+    }
+
+    /// Calls the program `name` with `args` on behalf of `tenant`. See
+    /// `Engine::call`.
+    pub fn call(&self, tenant: &str, name: &str, args: &[OwnedValue]) -> Result<OwnedValue, Error> {
+        let engine = self
+            .tenants
+            .get(tenant)
+            .ok_or_else(|| RuntimeError::Custom(format!("unknown tenant `{}`", tenant)))?;
+        let e = self
+            .programs
+            .get(name)
+            .ok_or_else(|| RuntimeError::Custom(format!("unknown program `{}`", name)))?;
+        engine.call(e, args)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::corelib::Profile;
+use crate::engine::EngineConfig;
+use crate::error::{Error, RuntimeError};
+use crate::eval::OwnedValue;
+use crate::log::{LogOp, MemorySink};
+use crate::tenant::EnginePool;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[test]
+fn test_engine_pool() {
+    let mut pool = EnginePool::new();
+    pool.add_host("trace", Arc::new(LogOp));
+    pool.add_program("double", r"(\x ($mul x 2))").unwrap();
+    pool.add_program("logged", r#"(\x ($log "info" "x" ($add x 1)))"#)
+        .unwrap();
+    pool.add_program("traced", r#"(\x ($trace "info" "x" x))"#)
+        .unwrap();
+    pool.add_program(
+        "spin",
+        r"(\x ((\f (f (f (f (f x))))) (\y ($add y ($add y y)))))",
+    )
+    .unwrap();
+
+    let math = EngineConfig {
+        profile: Profile::PureMath,
+        step_limit: Some(20),
+        ..EngineConfig::default()
+    };
+    pool.add_tenant("math", &math);
+    let sink = Rc::new(MemorySink::new());
+    pool.add_tenant("full", &EngineConfig::default())
+        .set_log_sink(Some(sink.clone()));
+    assert_eq!(pool.tenants().collect::<Vec<_>>(), vec!["full", "math"]);
+
+    let args = [OwnedValue::Int(20)];
+    for tenant in &["math", "full"] {
+        assert_eq!(
+            pool.call(tenant, "double", &args).unwrap().to_string(),
+            "40"
+        );
+    }
+
+    // Each tenant has only the host functions of its own profile.
+    assert_eq!(
+        pool.call("full", "logged", &args).unwrap().to_string(),
+        "21"
+    );
+    assert_eq!(
+        pool.call("full", "traced", &args).unwrap().to_string(),
+        "20"
+    );
+    assert_eq!(sink.take().len(), 2);
+    for program in &["logged", "traced"] {
+        match pool.call("math", program, &args) {
+            Err(Error::Type(_)) => {}
+            x => panic!("unexpected result: {:?}", x),
+        }
+    }
+
+    // And its own limits.
+    match pool.call("math", "spin", &args) {
+        Err(Error::Runtime(RuntimeError::StepLimit)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert_eq!(
+        pool.call("full", "spin", &args).unwrap().to_string(),
+        "1620"
+    );
+
+    match pool.call("nobody", "double", &args) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert_eq!(msg, "unknown tenant `nobody`")
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+    match pool.call("full", "triple", &args) {
+        Err(Error::Runtime(RuntimeError::Custom(msg))) => {
+            assert_eq!(msg, "unknown program `triple`")
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+
+    assert!(pool.remove_tenant("math").is_some());
+    assert!(pool.call("math", "double", &args).is_err());
+}
@@ -0,0 +1,365 @@
+//! Proving that recursive definitions terminate, for embedders that run
+//! untrusted rules and want more than a fuel limit to stop runaway ones.
+//!
+//! The check is conservative: it accepts a group of definitions that call
+//! each other only if one argument position shrinks on every call within
+//! the group. An argument shrinks if it is
+//!
+//! - the tail of the caller's parameter at that position, as in
+//!   `(len ($list_tail l))`, or
+//! - the parameter minus a positive int literal, as in `(fact ($sub n 1))`,
+//!   where the call is in the branch of an `$if` that bounds the parameter
+//!   from below, such as the else branch of `($if ($le n 0) ...)`, and
+//!   typeck found the parameter to be an int wherever the call is reached.
+//!   Subtracting from a float, which may be NaN, need not reach the bound.
+//!
+//! Everything else is reported as unproven, including definitions passed
+//! around as values and parameters applied to themselves, whose calls the
+//! check cannot follow.
+//!
+//! Host functions are recognized by what they are registered as, through a
+//! `HostResolution`, so neither an alias of `$sub` nor an embedder's own
+//! function registered as `$sub` is mistaken for another.
+
+use crate::ast::*;
+use crate::builtin::ValueType;
+use crate::definitions::Definitions;
+use crate::recursion::self_applications;
+use crate::typeck::HostArgTypes;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Recursion the check could not prove terminating.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unproven {
+    /// The recursive definition or parameter, as written in the source.
+    pub function: String,
+    /// The call or reference the check could not follow, for looking up
+    /// its span in a `SpanMap`.
+    pub call: Expr,
+    pub reason: String,
+}
+
+impl Unproven {
+    pub fn span(&self, spans: &SpanMap) -> Option<Span> {
+        spans.get(&self.call)
+    }
+}
+
+impl fmt::Display for Unproven {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cannot prove that `{}` terminates: {}",
+            self.function, self.reason
+        )
+    }
+}
+
+/// What the check knows about the host functions of a program, from
+/// checking it.
+#[derive(Debug, Default)]
+pub struct HostResolution {
+    /// The core function, by name, that each host name calls, for the
+    /// names calling one.
+    pub core: BTreeMap<String, String>,
+    /// The argument types of the program's host function calls, recorded
+    /// while typechecking it; see `TypeResolveState::record_host_args`.
+    pub args: HostArgTypes,
+}
+
+impl HostResolution {
+    /// The core function `e` calls and its arguments, if it calls one.
+    fn core_call<'e>(&self, e: &'e Expr) -> Option<(&str, &'e [Expr])> {
+        let (name, args) = host_call(e)?;
+        Some((self.core.get(name)?.as_str(), args))
+    }
+
+    /// Whether the first argument of the call `e` is an int wherever
+    /// typeck reached the call. Where typeck cut a recursive check short,
+    /// the argument is divergent; its type is checked where the recursion
+    /// was entered.
+    fn int_arg(&self, e: &Expr) -> bool {
+        let int = DataType::Value(ValueType::Int);
+        match self.args.get(&(&*e.body as *const ExprBody)) {
+            Some(calls) => calls.iter().all(|args| match args.first() {
+                Some(ty) => *ty == int || *ty == DataType::Divergent,
+                None => false,
+            }),
+            None => false,
+        }
+    }
+}
+
+/// Checks the recursion `e` can reach through `defs`. Returns nothing if
+/// every recursive definition terminates. `hosts` describes the host
+/// functions `e` was typechecked with.
+pub fn check_termination(
+    e: &Expr,
+    defs: Option<&Definitions>,
+    hosts: &HostResolution,
+) -> Vec<Unproven> {
+    let mut roots = vec![e.clone()];
+    let mut out = Vec::new();
+    if let Some(defs) = defs {
+        let graph = defs.dependency_graph();
+        let free: Vec<String> = free_vars(e).into_iter().collect();
+        let reachable = graph.reachable(&free);
+        roots.extend(reachable.iter().map(|n| defs.get(n).unwrap().expr.clone()));
+
+        let mut covered: BTreeSet<&String> = BTreeSet::new();
+        for name in &reachable {
+            if covered.contains(name) {
+                continue;
+            }
+            let recursive = graph.definitions[name]
+                .iter()
+                .any(|callee| graph.reachable(&[callee]).contains(name));
+            if !recursive {
+                continue;
+            }
+            let group: BTreeSet<String> = graph
+                .reachable(&[name])
+                .into_iter()
+                .filter(|n| graph.reachable(&[n]).contains(name))
+                .collect();
+            covered.extend(reachable.iter().filter(|n| group.contains(*n)));
+            check_group(defs, &group, hosts, &mut out);
+        }
+    }
+
+    let defined = defs.map(|d| d.names()).unwrap_or_default();
+    for cycle in self_applications(&roots, defined) {
+        let step = &cycle.steps[0];
+        out.push(Unproven {
+            function: step.caller.clone(),
+            call: step.call.clone(),
+            reason: "it is applied to itself, so its calls cannot be followed".into(),
+        });
+    }
+    out
+}
+
+/// A call from one definition of a group to another.
+struct Call {
+    caller: String,
+    expr: Expr,
+    args: Vec<Expr>,
+    /// The parameters of the caller known to be bounded from below where
+    /// the call is made.
+    bounded: BTreeSet<String>,
+}
+
+fn check_group(
+    defs: &Definitions,
+    group: &BTreeSet<String>,
+    hosts: &HostResolution,
+    out: &mut Vec<Unproven>,
+) {
+    let params: BTreeMap<&str, &[String]> = group
+        .iter()
+        .map(|n| {
+            let params: &[String] = match *defs.get(n).unwrap().expr.body {
+                ExprBody::Abstract {
+                    ref params,
+                    body: AbstractBody::Expr(_),
+                } => params,
+                _ => &[],
+            };
+            (n.as_str(), params)
+        })
+        .collect();
+
+    let mut calls = Vec::new();
+    let mut escapes = Vec::new();
+    for name in group {
+        let mut collector = Collector {
+            group,
+            hosts,
+            caller: name,
+            calls: &mut calls,
+            escapes: &mut escapes,
+        };
+        collector.walk(&defs.get(name).unwrap().expr, &BTreeSet::new());
+    }
+
+    if let Some((caller, e)) = escapes.into_iter().next() {
+        out.push(Unproven {
+            function: caller,
+            call: e,
+            reason: "it is passed as a value, so its calls cannot be followed".into(),
+        });
+        return;
+    }
+
+    let arity = params.values().map(|p| p.len()).min().unwrap_or(0);
+    let shrinks_at = |call: &Call, i: usize| {
+        call.args
+            .get(i)
+            .is_some_and(|a| shrinks(a, &params[call.caller.as_str()][i], &call.bounded, hosts))
+    };
+    if (0..arity).any(|i| calls.iter().all(|c| shrinks_at(c, i))) {
+        return;
+    }
+
+    let (call, reason) = match calls.iter().find(|c| (0..arity).all(|i| !shrinks_at(c, i))) {
+        Some(call) => {
+            let decremented = params[call.caller.as_str()].iter().find_map(|p| {
+                let arg = call.args.iter().find(|a| decrements(a, p, hosts))?;
+                Some((p, arg))
+            });
+            let reason = match decremented {
+                Some((p, arg)) if !hosts.int_arg(arg) => format!(
+                    "`{}` decreases but may not be an int, so it need not reach its bound",
+                    source_name(p)
+                ),
+                Some((p, _)) => format!(
+                    "`{}` decreases without a lower bound, which an `$if` must check first",
+                    source_name(p)
+                ),
+                None => "no argument gets smaller in one of its recursive calls".into(),
+            };
+            (call, reason)
+        }
+        None => (
+            &calls[0],
+            "its recursive calls do not all shrink the same argument".into(),
+        ),
+    };
+    out.push(Unproven {
+        function: call.caller.clone(),
+        call: call.expr.clone(),
+        reason,
+    });
+}
+
+/// Whether `arg` is structurally smaller than the parameter `param`.
+fn shrinks(arg: &Expr, param: &str, bounded: &BTreeSet<String>, hosts: &HostResolution) -> bool {
+    match hosts.core_call(arg) {
+        Some(("list_tail", [list])) => {
+            *list.body == ExprBody::Name(param.to_string()) || shrinks(list, param, bounded, hosts)
+        }
+        Some(("sub", _)) => {
+            decrements(arg, param, hosts) && bounded.contains(param) && hosts.int_arg(arg)
+        }
+        _ => false,
+    }
+}
+
+/// Whether `arg` is `($sub param k)` with `k` a positive int literal.
+fn decrements(arg: &Expr, param: &str, hosts: &HostResolution) -> bool {
+    match hosts.core_call(arg) {
+        Some(("sub", [n, k])) => {
+            *n.body == ExprBody::Name(param.to_string())
+                && match *k.body {
+                    ExprBody::Const(ConstExpr::Int(k)) => k > 0,
+                    _ => false,
+                }
+        }
+        _ => false,
+    }
+}
+
+/// The name and arguments of `e` if it calls a host function, as written.
+fn host_call(e: &Expr) -> Option<(&str, &[Expr])> {
+    match *e.body {
+        ExprBody::Apply {
+            ref target,
+            ref params,
+        } => match *target.body {
+            ExprBody::Abstract {
+                body: AbstractBody::Host(ref name),
+                ..
+            } => Some((name.as_str(), params.as_slice())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The parameter `cond` bounds from below when it evaluates to `holds`,
+/// for conditions comparing a parameter to an int literal.
+fn lower_bound<'e>(cond: &'e Expr, holds: bool, hosts: &HostResolution) -> Option<&'e str> {
+    let (op, args) = hosts.core_call(cond)?;
+    let (left, right) = match args {
+        [left, right] => (left, right),
+        _ => return None,
+    };
+    let (param, flipped) = match (&*left.body, &*right.body) {
+        (ExprBody::Name(p), ExprBody::Const(ConstExpr::Int(_))) => (p, false),
+        (ExprBody::Const(ConstExpr::Int(_)), ExprBody::Name(p)) => (p, true),
+        _ => return None,
+    };
+    // With the parameter on the left, `gt` and `ge` bound it from below
+    // when they hold, and `lt` and `le` when they do not.
+    let bounds = match op {
+        "gt" | "ge" => holds,
+        "lt" | "le" => !holds,
+        _ => return None,
+    };
+    if bounds != flipped {
+        Some(param)
+    } else {
+        None
+    }
+}
+
+struct Collector<'a> {
+    group: &'a BTreeSet<String>,
+    hosts: &'a HostResolution,
+    caller: &'a str,
+    calls: &'a mut Vec<Call>,
+    escapes: &'a mut Vec<(String, Expr)>,
+}
+
+impl<'a> Collector<'a> {
+    fn walk(&mut self, e: &Expr, bounded: &BTreeSet<String>) {
+        match *e.body {
+            ExprBody::Apply {
+                ref target,
+                ref params,
+            } => {
+                if let Some(("if", [cond, then, otherwise])) = self.hosts.core_call(e) {
+                    self.walk(cond, bounded);
+                    for (branch, holds) in [(then, true), (otherwise, false)] {
+                        let mut bounded = bounded.clone();
+                        bounded.extend(lower_bound(cond, holds, self.hosts).map(|p| p.to_string()));
+                        self.walk(branch, &bounded);
+                    }
+                    return;
+                }
+                match *target.body {
+                    ExprBody::Name(ref callee) if self.group.contains(callee) => {
+                        self.calls.push(Call {
+                            caller: self.caller.to_string(),
+                            expr: e.clone(),
+                            args: params.clone(),
+                            bounded: bounded.clone(),
+                        });
+                    }
+                    _ => self.walk(target, bounded),
+                }
+                for p in params {
+                    self.walk(p, bounded);
+                }
+            }
+            ExprBody::Abstract {
+                body: AbstractBody::Expr(ref body),
+                ..
+            } => self.walk(body, bounded),
+            ExprBody::Match {
+                ref value,
+                ref branches,
+            } => {
+                self.walk(value, bounded);
+                for (_, branch) in branches {
+                    self.walk(branch, bounded);
+                }
+            }
+            ExprBody::Name(ref name) if self.group.contains(name) => {
+                self.escapes.push((self.caller.to_string(), e.clone()));
+            }
+            _ => {}
+        }
+    }
+}
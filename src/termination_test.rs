@@ -0,0 +1,155 @@
+use crate::corelib::HostManager;
+use crate::definitions::Definitions;
+use crate::engine::Engine;
+use crate::error::*;
+use crate::parser::{parse_expr_with_globals, parse_expr_with_spans, ParseOptions};
+use crate::termination::*;
+use crate::typeck::{check_expr, TypeResolveState};
+
+fn define(defs: &mut Definitions, name: &str, src: &str) {
+    let globals = ["fact", "len", "spin", "even", "odd", "swap", "apply"]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+    let e = parse_expr_with_globals(src, globals).unwrap();
+    defs.define(name.to_string(), e);
+}
+
+fn unproven(defs: &Definitions, root: &str) -> Vec<String> {
+    let root = parse_expr_with_globals(root, defs.names()).unwrap();
+    let hm = HostManager::new();
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_all());
+    trs.set_definitions(defs);
+    trs.record_host_args();
+    check_expr(&root, &mut trs).unwrap();
+    let hosts = HostResolution {
+        core: hm.get_all().map(|(k, _)| (k.clone(), k)).collect(),
+        args: trs.take_host_args(),
+    };
+    check_termination(&root, Some(defs), &hosts)
+        .iter()
+        .map(|u| u.to_string())
+        .collect()
+}
+
+#[test]
+fn test_structural_recursion() {
+    let mut defs = Definitions::default();
+    define(
+        &mut defs,
+        "fact",
+        r"(\n ($if ($le n 0) 1 ($mul n (fact ($sub n 1)))))",
+    );
+    define(
+        &mut defs,
+        "len",
+        r"(\l ($if ($list_is_empty l) 0 ($add 1 (len ($list_tail l)))))",
+    );
+    define(
+        &mut defs,
+        "even",
+        r"(\n ($if ($gt n 0) (odd ($sub n 1)) true))",
+    );
+    define(
+        &mut defs,
+        "odd",
+        r"(\n ($if ($lt 0 n) (even ($sub n 1)) false))",
+    );
+    assert!(unproven(&defs, "($add (fact 5) (len ($list_push 1 ~)))").is_empty());
+    assert!(unproven(&defs, "(even 4)").is_empty());
+}
+
+#[test]
+fn test_unproven_recursion() {
+    let mut defs = Definitions::default();
+    define(&mut defs, "spin", r"(\n (spin ($add n 1)))");
+    define(
+        &mut defs,
+        "fact",
+        r"(\n ($if ($eq n 0) 1 ($mul n (fact ($sub n 1)))))",
+    );
+    define(
+        &mut defs,
+        "swap",
+        r"(\a b ($if ($le a 0) b ($if ($le b 0) a ($add (swap ($sub a 1) b) (swap a ($sub b 1))))))",
+    );
+    define(&mut defs, "apply", r"(\f n (f n))");
+    define(&mut defs, "len", r"(\l (apply len l))");
+
+    assert_eq!(
+        unproven(&defs, "(spin 0)"),
+        ["cannot prove that `spin` terminates: no argument gets smaller in one of its recursive calls"]
+    );
+    assert_eq!(
+        unproven(&defs, "(fact 5)"),
+        ["cannot prove that `fact` terminates: `n` decreases without a lower bound, which an `$if` must check first"]
+    );
+    assert_eq!(
+        unproven(&defs, "(swap 2 2)"),
+        ["cannot prove that `swap` terminates: its recursive calls do not all shrink the same argument"]
+    );
+    assert_eq!(
+        unproven(&defs, "(len ~)"),
+        ["cannot prove that `len` terminates: it is passed as a value, so its calls cannot be followed"]
+    );
+}
+
+#[test]
+fn test_non_int_decrement() {
+    let mut defs = Definitions::default();
+    define(
+        &mut defs,
+        "fact",
+        r"(\n ($if ($le n 0) 1 ($mul n (fact ($sub n 1)))))",
+    );
+    let reason = "cannot prove that `fact` terminates: `n` decreases but may not be an int, so it need not reach its bound";
+    assert!(unproven(&defs, "(fact 5)").is_empty());
+    assert_eq!(unproven(&defs, "(fact 5.5)"), [reason]);
+    assert_eq!(unproven(&defs, "(fact ($div 0.0 0.0))"), [reason]);
+    assert_eq!(unproven(&defs, "(fact ($dyn 5))"), [reason]);
+}
+
+#[test]
+fn test_self_application() {
+    let source = "((\\f (f f)) (\\g (g)))";
+    let (e, spans) = parse_expr_with_spans(source, &ParseOptions::default()).unwrap();
+    let found = check_termination(&e, None, &HostResolution::default());
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].function, "f");
+    let span = found[0].span(&spans).unwrap();
+    assert_eq!(&source[span.start..span.end], "(f f)");
+}
+
+#[test]
+fn test_engine_requires_termination() {
+    let mut engine = Engine::new();
+    engine
+        .define("fact", r"(\n ($if ($le n 0) 1 ($mul n (fact ($sub n 1)))))")
+        .unwrap();
+    engine.define("count", r"(\n ($add 1 (count n)))").unwrap();
+    engine.set_require_termination(true);
+    assert_eq!(engine.eval_str("(fact 5)").unwrap(), "120");
+    match engine.check(&engine.parse("(count 1)").unwrap()) {
+        Err(Error::Type(TypeError::Custom(msg))) => assert_eq!(
+            msg,
+            "cannot prove that `count` terminates: no argument gets smaller in one of its recursive calls"
+        ),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_engine_resolves_host_names() {
+    let mut engine = Engine::new();
+    engine.set_require_termination(true);
+    engine.alias_host("minus", "sub");
+    engine
+        .define(
+            "fact",
+            r"(\n ($if ($le n 0) 1 ($mul n (fact ($minus n 1)))))",
+        )
+        .unwrap();
+    assert_eq!(engine.eval_str("(fact 5)").unwrap(), "120");
+    assert!(engine.eval_str("(fact 5.5)").is_err());
+}
@@ -0,0 +1,240 @@
+//! Generators of random well-typed expressions for property-based tests.
+//!
+//! The strategies here only produce expressions that typecheck against the
+//! host functions listed in a `GenConfig`, which makes them useful for
+//! checking that typeck and eval agree, and for exercising custom host
+//! functions from downstream crates (enable the `testing` feature).
+//!
+//! Generated expressions use plain, never-shadowed names, so printing one
+//! and parsing the output gives back the same structure.
+
+use crate::ast::*;
+use crate::builtin::ValueType;
+use proptest::prelude::*;
+use proptest::sample::select;
+use proptest::strategy::Union;
+use std::rc::Rc;
+
+/// A host function that generated expressions may call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Op {
+    /// Name of the host function, without the `$` prefix.
+    pub name: String,
+    pub params: Vec<ValueType>,
+    pub ret: ValueType,
+}
+
+impl Op {
+    pub fn new(name: &str, params: &[ValueType], ret: ValueType) -> Op {
+        Op {
+            name: name.to_string(),
+            params: params.to_vec(),
+            ret,
+        }
+    }
+}
+
+/// Controls the shape of generated expressions.
+#[derive(Debug, Clone)]
+pub struct GenConfig {
+    /// Maximum nesting depth of calls, conditionals and bindings.
+    ///
+    /// Integer arithmetic is not overflow-checked, so depths above the
+    /// default may overflow when `$mul` is among the operators.
+    pub max_depth: u32,
+
+    /// Host functions that may be called, one entry per accepted signature.
+    pub ops: Vec<Op>,
+
+    /// Whether to generate `$if` calls.
+    pub conditionals: bool,
+
+    /// Whether to bind intermediate values with `((\x (...)) value)`.
+    pub bindings: bool,
+}
+
+impl Default for GenConfig {
+    fn default() -> GenConfig {
+        GenConfig {
+            max_depth: 4,
+            ops: core_ops(),
+            conditionals: true,
+            bindings: true,
+        }
+    }
+}
+
+/// Signatures of the arithmetic, relational and logical operators provided by
+/// `corelib::HostManager`.
+pub fn core_ops() -> Vec<Op> {
+    use crate::builtin::ValueType::*;
+
+    let numeric = [
+        (Int, Int, Int),
+        (Int, Float, Float),
+        (Float, Int, Float),
+        (Float, Float, Float),
+    ];
+    let mut ops = Vec::new();
+    for name in &["add", "sub", "mul", "div", "mod"] {
+        for (a, b, r) in numeric.iter().cloned() {
+            ops.push(Op::new(name, &[a, b], r));
+        }
+    }
+    for name in &["eq", "ne", "lt", "le", "gt", "ge"] {
+        for (a, b, _) in numeric.iter().cloned() {
+            ops.push(Op::new(name, &[a, b], Bool));
+        }
+        ops.push(Op::new(name, &[Bool, Bool], Bool));
+    }
+    for name in &["and", "or"] {
+        ops.push(Op::new(name, &[Bool, Bool], Bool));
+    }
+    ops
+}
+
+/// Strategy producing expressions of type `ty`.
+pub fn expr_of_type(config: &GenConfig, ty: ValueType) -> BoxedStrategy<Expr> {
+    gen_expr(
+        Rc::new(config.clone()),
+        ty,
+        config.max_depth,
+        Rc::new(Vec::new()),
+    )
+}
+
+/// Strategy producing expressions of any value type, paired with that type.
+pub fn well_typed_expr(config: &GenConfig) -> BoxedStrategy<(Expr, ValueType)> {
+    let config = config.clone();
+    select(vec![ValueType::Int, ValueType::Float, ValueType::Bool])
+        .prop_flat_map(move |ty| (expr_of_type(&config, ty.clone()), Just(ty)))
+        .boxed()
+}
+
+type Scope = Rc<Vec<(String, ValueType)>>;
+
+fn mk(body: ExprBody) -> Expr {
+    Expr {
+        body: Rc::new(body),
+    }
+}
+
+fn host(name: &str) -> Expr {
+    mk(ExprBody::Abstract {
+        params: vec![],
+        body: AbstractBody::Host(name.to_string()),
+    })
+}
+
+fn const_expr(ty: ValueType) -> BoxedStrategy<Expr> {
+    match ty {
+        ValueType::Int => (0i64..10).prop_map(ConstExpr::Int).boxed(),
+        ValueType::Float => (0u32..80)
+            .prop_map(|x| ConstExpr::Float(f64::from(x) / 8.0))
+            .boxed(),
+        ValueType::Bool => any::<bool>().prop_map(ConstExpr::Bool).boxed(),
+    }
+    .prop_map(|c| mk(ExprBody::Const(c)))
+    .boxed()
+}
+
+fn leaf_expr(ty: ValueType, scope: &Scope) -> BoxedStrategy<Expr> {
+    let names: Vec<String> = scope
+        .iter()
+        .filter(|(_, t)| *t == ty)
+        .map(|(n, _)| n.clone())
+        .collect();
+    if names.is_empty() {
+        const_expr(ty)
+    } else {
+        prop_oneof![
+            const_expr(ty),
+            select(names).prop_map(|n| mk(ExprBody::Name(n))),
+        ]
+        .boxed()
+    }
+}
+
+/// Defers building a strategy until a value is drawn from it, so that only
+/// the chosen branch of the expression tree is ever constructed.
+fn lazy<F: Fn() -> BoxedStrategy<Expr> + 'static>(f: F) -> BoxedStrategy<Expr> {
+    Just(()).prop_flat_map(move |_| f()).boxed()
+}
+
+fn gen_expr(config: Rc<GenConfig>, ty: ValueType, depth: u32, scope: Scope) -> BoxedStrategy<Expr> {
+    let leaf = leaf_expr(ty.clone(), &scope);
+    if depth == 0 {
+        return leaf;
+    }
+    let mut choices = vec![leaf];
+
+    let ops: Vec<Op> = config
+        .ops
+        .iter()
+        .filter(|op| op.ret == ty)
+        .cloned()
+        .collect();
+    if !ops.is_empty() {
+        let (config, scope) = (config.clone(), scope.clone());
+        choices.push(
+            select(ops)
+                .prop_flat_map(move |op| {
+                    let params: Vec<_> = op
+                        .params
+                        .iter()
+                        .map(|t| gen_expr(config.clone(), t.clone(), depth - 1, scope.clone()))
+                        .collect();
+                    params.prop_map(move |params| {
+                        mk(ExprBody::Apply {
+                            target: host(&op.name),
+                            params,
+                        })
+                    })
+                })
+                .boxed(),
+        );
+    }
+
+    if config.conditionals {
+        let (config, ty, scope) = (config.clone(), ty.clone(), scope.clone());
+        choices.push(lazy(move || {
+            let sub = |ty| gen_expr(config.clone(), ty, depth - 1, scope.clone());
+            (sub(ValueType::Bool), sub(ty.clone()), sub(ty.clone()))
+                .prop_map(|(cond, a, b)| {
+                    mk(ExprBody::Apply {
+                        target: host("if"),
+                        params: vec![cond, a, b],
+                    })
+                })
+                .boxed()
+        }));
+    }
+
+    if config.bindings {
+        let (config, ty, scope) = (config.clone(), ty.clone(), scope.clone());
+        choices.push(
+            select(vec![ValueType::Int, ValueType::Float, ValueType::Bool])
+                .prop_flat_map(move |bound_ty| {
+                    let name = format!("v{}", scope.len());
+                    let mut inner = (*scope).clone();
+                    inner.push((name.clone(), bound_ty.clone()));
+                    (
+                        gen_expr(config.clone(), bound_ty, depth - 1, scope.clone()),
+                        gen_expr(config.clone(), ty.clone(), depth - 1, Rc::new(inner)),
+                    )
+                        .prop_map(move |(value, body)| {
+                            mk(ExprBody::Apply {
+                                target: mk(ExprBody::Abstract {
+                                    params: vec![name.clone()],
+                                    body: AbstractBody::Expr(body),
+                                }),
+                                params: vec![value],
+                            })
+                        })
+                })
+                .boxed(),
+        );
+    }
+
+    Union::new(choices).boxed()
+}
@@ -0,0 +1,52 @@
+use crate::ast::*;
+use crate::builtin::ValueType;
+use crate::engine::Engine;
+use crate::error::{Error, RuntimeError};
+use crate::eval::RuntimeValue;
+use crate::parser::parse_expr;
+use crate::testing::*;
+use proptest::prelude::*;
+
+fn value_type(v: &RuntimeValue) -> Option<ValueType> {
+    match *v {
+        RuntimeValue::Int(_) => Some(ValueType::Int),
+        RuntimeValue::Float(_) => Some(ValueType::Float),
+        RuntimeValue::Bool(_) => Some(ValueType::Bool),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_print_expr() {
+    let src = r"((\x y ($add x ($mul y 2.0))) 1 0.5)";
+    let e = parse_expr(src).unwrap();
+    assert_eq!(e.to_string(), src);
+    assert_eq!(parse_expr("(~)").unwrap().to_string(), "(~)");
+    assert_eq!(parse_expr("(true)").unwrap().to_string(), "(true)");
+    assert_eq!(parse_expr("(3.0)").unwrap().to_string(), "(3.0)");
+    assert_eq!(
+        parse_expr(r"(\x (\y (y)))").unwrap().to_string(),
+        r"(\x (\y (y)))"
+    );
+}
+
+proptest! {
+    #[test]
+    fn print_parse_roundtrip(e in expr_of_type(&GenConfig::default(), ValueType::Int)) {
+        let src = e.to_string();
+        let parsed = parse_expr(&src).unwrap();
+        prop_assert_eq!(parsed.to_string(), src);
+        prop_assert_eq!(parse_expr(&parsed.to_string()).unwrap(), parsed);
+    }
+
+    #[test]
+    fn typeck_agrees_with_eval((e, ty) in well_typed_expr(&GenConfig::default())) {
+        let engine = Engine::new();
+        prop_assert_eq!(engine.check(&e).unwrap(), DataType::Value(ty.clone()));
+        match engine.eval_with(&e, |v, _| Ok(value_type(&v))) {
+            Ok(v) => prop_assert_eq!(v, Some(ty)),
+            Err(Error::Runtime(RuntimeError::DivByZero)) => {}
+            Err(e) => return Err(TestCaseError::fail(format!("{:?}", e))),
+        }
+    }
+}
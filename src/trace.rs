@@ -0,0 +1,87 @@
+//! Spans for the `tracing` crate, with the `tracing` feature.
+//!
+//! The `Engine` opens an `xl.parse` span while parsing, `xl.typecheck`
+//! while checking and `xl.eval` while evaluating, each with a `program`
+//! field identifying what it works on, and evaluation opens an
+//! `xl.host_call` span around every host function call, with a `host`
+//! field. Every span records how long it was open in `duration_us` as it
+//! closes. Spans are at the `INFO` level, except host calls, which are at
+//! `DEBUG` since there are many.
+
+use crate::ast::Expr;
+use sha2::{Digest, Sha256};
+use std::time::Instant;
+use tracing::span::EnteredSpan;
+
+/// An entered span that records `duration_us` when dropped.
+#[derive(Debug)]
+pub(crate) struct Timed {
+    span: EnteredSpan,
+    start: Instant,
+}
+
+impl Timed {
+    fn enter(span: tracing::Span) -> Timed {
+        Timed {
+            span: span.entered(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timed {
+    fn drop(&mut self) {
+        self.span
+            .record("duration_us", self.start.elapsed().as_micros() as u64);
+    }
+}
+
+/// The first 8 bytes of the SHA-256 digest of `bytes`, in hex.
+fn short_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Identifies source text in spans.
+pub fn source_hash(source: &str) -> String {
+    short_hash(source.as_bytes())
+}
+
+/// Identifies an expression in spans, however it was built.
+pub fn expr_hash(e: &Expr) -> String {
+    short_hash(&bincode::serialize(e).expect("bug: expression serialization failed"))
+}
+
+pub(crate) fn parse(source: &str) -> Timed {
+    Timed::enter(tracing::info_span!(
+        "xl.parse",
+        program = %source_hash(source),
+        duration_us = tracing::field::Empty,
+    ))
+}
+
+pub(crate) fn typecheck(e: &Expr) -> Timed {
+    Timed::enter(tracing::info_span!(
+        "xl.typecheck",
+        program = %expr_hash(e),
+        duration_us = tracing::field::Empty,
+    ))
+}
+
+pub(crate) fn eval(e: &Expr) -> Timed {
+    Timed::enter(tracing::info_span!(
+        "xl.eval",
+        program = %expr_hash(e),
+        duration_us = tracing::field::Empty,
+    ))
+}
+
+pub(crate) fn host_call(name: &str) -> Timed {
+    Timed::enter(tracing::debug_span!(
+        "xl.host_call",
+        host = name,
+        duration_us = tracing::field::Empty,
+    ))
+}
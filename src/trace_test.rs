@@ -0,0 +1,86 @@
+use crate::engine::Engine;
+use crate::trace::source_hash;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+type Fields = BTreeMap<String, String>;
+
+/// Collects the spans opened and the fields recorded on them.
+#[derive(Clone, Default)]
+struct Collector {
+    spans: Arc<Mutex<Vec<(&'static str, Fields)>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().into(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Collector {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes) -> Id {
+        let mut fields = Fields::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((attrs.metadata().name(), fields));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record) {
+        let mut spans = self.spans.lock().unwrap();
+        let (_, ref mut fields) = spans[span.into_u64() as usize - 1];
+        values.record(&mut FieldVisitor(fields));
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn test_spans() {
+    let engine = Engine::new();
+    let collector = Collector::default();
+    let source = "($add 1 ($mul 2 3))";
+    tracing::subscriber::with_default(collector.clone(), || {
+        assert_eq!(engine.eval_str(source).unwrap(), "7");
+    });
+
+    let spans = collector.spans.lock().unwrap();
+    let names: Vec<&str> = spans.iter().map(|(name, _)| *name).collect();
+    assert_eq!(
+        names,
+        vec![
+            "xl.parse",
+            "xl.typecheck",
+            "xl.eval",
+            "xl.host_call",
+            "xl.host_call"
+        ]
+    );
+    assert_eq!(spans[0].1["program"], source_hash(source));
+    assert_eq!(spans[1].1["program"], spans[2].1["program"]);
+    assert_eq!(spans[3].1["host"], "add");
+    assert_eq!(spans[4].1["host"], "mul");
+    assert!(spans
+        .iter()
+        .all(|(_, fields)| fields.contains_key("duration_us")));
+}
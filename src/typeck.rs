@@ -1,10 +1,21 @@
 use crate::ast::*;
 use crate::builtin::ValueType;
+use crate::bytes::{bytes_type, BytesType};
+use crate::corelib::{list_type, string_type, ListType, StringType};
+use crate::decimal::{decimal_type, DecimalType};
+use crate::definitions::Definitions;
 use crate::error::TypeError;
-use crate::host::HostFunction;
+use crate::host::{qualified_name, HostFunction, HostHandle, Signature};
+use crate::warning::{Warning, WarningSink};
 use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::rc::Rc;
+use std::sync::Arc;
+
+mod session;
+
+pub use self::session::Session;
 
 fn never_expr() -> Expr {
     Expr {
@@ -15,18 +26,30 @@ fn never_expr() -> Expr {
 #[derive(Debug, Default)]
 pub struct TypeResolveState<'b> {
     subs: BTreeMap<String, Expr>,
-    host_functions: BTreeMap<String, &'b dyn HostFunction>,
-    expr_reach: Rc<RefCell<BTreeSet<*const ExprBody>>>,
+    host_functions: BTreeMap<String, HostHandle<'b>>,
+    definitions: Option<&'b Definitions>,
+    expr_reach: Rc<RefCell<BTreeSet<ReachKey>>>,
+    warnings: WarningSink,
+    host_args: Option<HostArgTypes>,
 }
 
+/// The argument types of host function calls, by call, with one list of
+/// types for each set of substitutions the call was checked under.
+pub type HostArgTypes = BTreeMap<*const ExprBody, Vec<Vec<DataType>>>;
+
+/// Identifies an expression being checked under a particular set of
+/// substitutions. Reaching the same key again while it is still being
+/// checked means the check would recurse forever.
+type ReachKey = (*const ExprBody, Vec<(String, *const ExprBody)>);
+
 pub struct ExprReachGuard {
-    me: *const ExprBody,
-    expr_reach: Rc<RefCell<BTreeSet<*const ExprBody>>>,
+    me: ReachKey,
+    expr_reach: Rc<RefCell<BTreeSet<ReachKey>>>,
 }
 
 impl Drop for ExprReachGuard {
     fn drop(&mut self) {
-        if self.expr_reach.borrow_mut().remove(&self.me) == false {
+        if !self.expr_reach.borrow_mut().remove(&self.me) {
             panic!("erg: not found");
         }
     }
@@ -34,13 +57,19 @@ impl Drop for ExprReachGuard {
 
 impl<'b> TypeResolveState<'b> {
     fn guarded_expr_reach(&self, e: &Expr) -> Option<ExprReachGuard> {
-        let b: *const ExprBody = &*e.body;
+        let b: ReachKey = (
+            &*e.body,
+            self.subs
+                .iter()
+                .map(|(k, v)| (k.clone(), &*v.body as *const ExprBody))
+                .collect(),
+        );
 
         let mut reach = self.expr_reach.borrow_mut();
         if reach.contains(&b) {
             None
         } else {
-            reach.insert(b);
+            reach.insert(b.clone());
             Some(ExprReachGuard {
                 me: b,
                 expr_reach: self.expr_reach.clone(),
@@ -52,7 +81,98 @@ impl<'b> TypeResolveState<'b> {
         &mut self,
         host_functions: H,
     ) {
-        self.host_functions.extend(host_functions);
+        self.host_functions.extend(
+            host_functions
+                .into_iter()
+                .map(|(k, v)| (k, HostHandle::Borrowed(v))),
+        );
+    }
+
+    /// Registers host functions owned by this state, so that it does not
+    /// borrow from whoever provides them.
+    pub fn add_hosts_owned<H: IntoIterator<Item = (String, Arc<dyn HostFunction>)>>(
+        &mut self,
+        host_functions: H,
+    ) {
+        self.host_functions.extend(
+            host_functions
+                .into_iter()
+                .map(|(k, v)| (k, HostHandle::Owned(v))),
+        );
+    }
+
+    /// Registers host functions under `namespace`, callable as
+    /// `$namespace.name`.
+    pub fn add_hosts_in<H: IntoIterator<Item = (String, &'b dyn HostFunction)>>(
+        &mut self,
+        namespace: &str,
+        host_functions: H,
+    ) {
+        self.add_hosts(
+            host_functions
+                .into_iter()
+                .map(|(k, v)| (qualified_name(namespace, &k), v)),
+        );
+    }
+
+    /// Makes `$alias` call the host function registered as `target`.
+    /// Returns false, registering nothing, if there is no such function or
+    /// `alias` already names a host function or alias.
+    pub fn alias_host(&mut self, alias: &str, target: &str) -> bool {
+        if self.host_functions.contains_key(alias) {
+            return false;
+        }
+        match self.host_functions.get(target) {
+            Some(hf) => {
+                let hf = hf.clone();
+                self.host_functions.insert(alias.to_string(), hf);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of the registered host functions, without the `$`.
+    pub fn host_names(&self) -> impl Iterator<Item = &str> {
+        self.host_functions.keys().map(|k| k.as_str())
+    }
+
+    /// Warnings found by the checks run with this state so far, each
+    /// reported once.
+    pub fn warnings(&self) -> &[Warning] {
+        self.warnings.warnings()
+    }
+
+    /// Expressions are checked again for every set of arguments they are
+    /// reached with; the sink keeps one warning per expression.
+    fn warn(&mut self, warning: Warning) {
+        self.warnings.warn(warning);
+    }
+
+    /// Makes checks record the argument types of every host function call,
+    /// for `check_termination`.
+    pub fn record_host_args(&mut self) {
+        self.host_args.get_or_insert_with(BTreeMap::new);
+    }
+
+    /// Returns the argument types recorded since `record_host_args`, and
+    /// stops recording.
+    pub fn take_host_args(&mut self) -> HostArgTypes {
+        self.host_args.take().unwrap_or_default()
+    }
+
+    fn record_call(&mut self, e: &Expr, arg_types: &[DataType]) {
+        if let Some(ref mut host_args) = self.host_args {
+            host_args
+                .entry(&*e.body)
+                .or_default()
+                .push(arg_types.to_vec());
+        }
+    }
+
+    /// Makes the definitions in `defs` resolvable as global names.
+    pub fn set_definitions(&mut self, defs: &'b Definitions) {
+        self.definitions = Some(defs);
     }
 
     pub fn resolve_name(&self, mut name: String) -> Option<Expr> {
@@ -64,10 +184,9 @@ impl<'b> TypeResolveState<'b> {
             }
             path.insert(name.clone());
 
-            let expr = if let Some(v) = self.subs.get(&name).cloned() {
-                v
-            } else {
-                return None;
+            let expr = match self.subs.get(&name) {
+                Some(v) => v.clone(),
+                None => self.definitions?.get(&name)?.expr.clone(),
             };
             if let ExprBody::Name(ref n) = *expr.body {
                 name = n.clone();
@@ -101,6 +220,26 @@ impl<'b> TypeResolveState<'b> {
     }
 }
 
+/// Names under which the arguments of a partially applied host function
+/// are bound. They contain `$`, so they never clash with source names.
+fn host_param_names(host: &str, sig: &Signature) -> Vec<String> {
+    sig.params
+        .iter()
+        .map(|p| format!("${}.{}", host, p.name))
+        .collect()
+}
+
+fn const_type(c: &ConstExpr) -> DataType {
+    match *c {
+        ConstExpr::Int(_) => DataType::Value(ValueType::Int),
+        ConstExpr::Bool(_) => DataType::Value(ValueType::Bool),
+        ConstExpr::Float(_) => DataType::Value(ValueType::Float),
+        ConstExpr::Empty => DataType::Empty,
+        ConstExpr::Str(_) => string_type(),
+        ConstExpr::Bytes(_) => bytes_type(),
+    }
+}
+
 pub fn check_expr<'b>(e: &Expr, trs: &mut TypeResolveState<'b>) -> Result<DataType, TypeError> {
     let ret = _check_expr(e, trs);
     //println!("CHECK {:?}, RESULT = {:?}", e, ret);
@@ -113,22 +252,43 @@ pub fn _check_expr<'b>(e: &Expr, trs: &mut TypeResolveState<'b>) -> Result<DataT
         None => return Ok(DataType::Divergent),
     };
     match *e.body {
-        ExprBody::Name(ref name) => match trs.resolve_name(name.clone()) {
-            Some(e) => {
-                if *e.body == ExprBody::Never {
-                    Ok(DataType::Divergent)
-                } else {
-                    check_expr(&e, trs)
+        ExprBody::Name(ref name) => {
+            let def = if trs.subs.contains_key(name) {
+                None
+            } else {
+                trs.definitions.and_then(|defs| defs.get(name))
+            };
+            if let Some(def) = def {
+                if let Some(ty) = def.cached_type() {
+                    return Ok(ty);
+                }
+
+                // Definitions are closed over globals only, so check them
+                // without any local substitutions in scope.
+                let mut subs = BTreeMap::new();
+                ::std::mem::swap(&mut subs, &mut trs.subs);
+                let ty = check_expr(&def.expr, trs);
+                ::std::mem::swap(&mut subs, &mut trs.subs);
+
+                let ty = ty?;
+                if ty != DataType::Divergent {
+                    def.set_cached_type(ty.clone());
                 }
+                return Ok(ty);
             }
-            None => Err(TypeError::Custom("cannot resolve name".into())),
-        },
-        ExprBody::Const(ref c) => Ok(match *c {
-            ConstExpr::Int(_) => DataType::Value(ValueType::Int),
-            ConstExpr::Bool(_) => DataType::Value(ValueType::Bool),
-            ConstExpr::Float(_) => DataType::Value(ValueType::Float),
-            ConstExpr::Empty => DataType::Empty,
-        }),
+
+            match trs.resolve_name(name.clone()) {
+                Some(e) => {
+                    if *e.body == ExprBody::Never {
+                        Ok(DataType::Divergent)
+                    } else {
+                        check_expr(&e, trs)
+                    }
+                }
+                None => Err(TypeError::Custom("cannot resolve name".into())),
+            }
+        }
+        ExprBody::Const(ref c) => Ok(const_type(c)),
         ExprBody::Apply {
             ref target,
             ref params,
@@ -158,31 +318,95 @@ pub fn _check_expr<'b>(e: &Expr, trs: &mut TypeResolveState<'b>) -> Result<DataT
                 } => {
                     let mut param_types: Vec<DataType> = Vec::new();
 
-                    for i in 0..apply_params.len() {
-                        let param_ty = check_expr(&apply_params[i], trs)?;
-                        param_types.push(param_ty.clone());
+                    for p in apply_params {
+                        param_types.push(check_expr(p, trs)?);
                     }
 
                     match *decl_expr.body {
                         ExprBody::Abstract { ref body, .. } => match *body {
-                            AbstractBody::Host(ref host) => {
-                                if let Some(ref host) = trs.host_functions.get(host) {
-                                    Ok(host.typeck(&param_types)?)
-                                } else {
-                                    Err(TypeError::Custom(format!(
-                                        "host function not found: {}",
-                                        host
-                                    )))
+                            AbstractBody::Host(ref name) => {
+                                let host = match trs.host_functions.get(name) {
+                                    Some(v) => v.clone(),
+                                    None => {
+                                        return Err(TypeError::Custom(format!(
+                                            "host function not found: {}",
+                                            name
+                                        )))
+                                    }
+                                };
+                                let sig = match host.signature() {
+                                    Some(v) => v,
+                                    None => {
+                                        trs.record_call(e, &param_types);
+                                        return host.typeck(&param_types);
+                                    }
+                                };
+
+                                // Arguments supplied by earlier partial
+                                // applications are bound in `param_set`.
+                                let all_params = host_param_names(name, &sig);
+                                let bound = &all_params[..all_params.len() - params.len()];
+                                let mut arg_types = Vec::new();
+                                let mut subs = param_set.clone();
+                                ::std::mem::swap(&mut subs, &mut trs.subs);
+                                let ret = bound
+                                    .iter()
+                                    .map(|p| check_expr(&param_set[p], trs))
+                                    .collect::<Result<Vec<_>, _>>();
+                                ::std::mem::swap(&mut subs, &mut trs.subs);
+                                arg_types.extend(ret?);
+                                arg_types.extend(param_types);
+
+                                if arg_types.len() < sig.min_params() {
+                                    let mut param_set = param_set.clone();
+                                    for (p, x) in params.iter().zip(apply_params) {
+                                        param_set.insert(p.clone(), x.clone());
+                                    }
+                                    return Ok(DataType::FunctionDecl {
+                                        params: params[apply_params.len()..].to_vec(),
+                                        decl_expr: decl_expr.clone(),
+                                        param_set,
+                                    });
                                 }
+
+                                sig.check_arity(arg_types.len())?;
+                                arg_types.extend(
+                                    sig.defaults_from(arg_types.len()).iter().map(const_type),
+                                );
+                                trs.record_call(e, &arg_types);
+                                let float = DataType::Value(ValueType::Float);
+                                if (name == "eq" || name == "ne") && arg_types.contains(&float) {
+                                    trs.warn(Warning::FloatEquality {
+                                        host: name.clone(),
+                                        expr: e.clone(),
+                                    });
+                                }
+                                let ty = host.typeck(&arg_types)?;
+                                if host.converts_int_to_float(&arg_types) {
+                                    trs.warn(Warning::IntToFloat {
+                                        host: name.clone(),
+                                        expr: e.clone(),
+                                    });
+                                }
+                                Ok(ty)
                             }
                             AbstractBody::Expr(ref e) => {
-                                if params.len() != apply_params.len() {
+                                if apply_params.len() > params.len() {
                                     Err(TypeError::Custom("param count mismatch".into()))
+                                } else if apply_params.len() < params.len() {
+                                    // Partial application binds the leading
+                                    // params and leaves a function of the rest.
+                                    let mut param_set = param_set.clone();
+                                    for (p, x) in params.iter().zip(apply_params) {
+                                        param_set.insert(p.clone(), x.clone());
+                                    }
+                                    Ok(DataType::FunctionDecl {
+                                        params: params[apply_params.len()..].to_vec(),
+                                        decl_expr: decl_expr.clone(),
+                                        param_set,
+                                    })
                                 } else {
-                                    let resolved: Vec<(
-                                        String,
-                                        Expr,
-                                    )> = (0..params.len())
+                                    let resolved: Vec<(String, Expr)> = (0..params.len())
                                         .map(|i| (params[i].clone(), apply_params[i].clone()))
                                         .collect();
 
@@ -201,10 +425,14 @@ pub fn _check_expr<'b>(e: &Expr, trs: &mut TypeResolveState<'b>) -> Result<DataT
                         _ => panic!("bug: invalid decl expr"),
                     }
                 }
+                DataType::Divergent => Ok(DataType::Divergent),
+                // Whether the value is a function is checked when it is
+                // called.
+                DataType::Dynamic => Ok(DataType::Dynamic),
                 _ => {
-                    if apply_params.len() != 0 {
+                    if !apply_params.is_empty() {
                         Err(TypeError::Custom(format!(
-                            "cannot apply with params on non-function value of type {:?}",
+                            "cannot apply with params on non-function value of type {}",
                             target_ty
                         )))
                     } else {
@@ -213,14 +441,358 @@ pub fn _check_expr<'b>(e: &Expr, trs: &mut TypeResolveState<'b>) -> Result<DataT
                 }
             }
         }
-        ExprBody::Abstract { ref params, .. } => Ok(DataType::FunctionDecl {
-            params: params.clone(),
-            decl_expr: e.clone(),
-            param_set: trs.subs.clone(),
-        }),
-        ExprBody::Match { .. } => {
-            unimplemented!();
+        ExprBody::Abstract {
+            ref params,
+            ref body,
+        } => {
+            if let AbstractBody::Expr(ref body) = *body {
+                warn_about_params(e, params, body, trs);
+            }
+            Ok(DataType::FunctionDecl {
+                params: match *body {
+                    AbstractBody::Host(ref name) => {
+                        match trs.host_functions.get(name).and_then(|x| x.signature()) {
+                            Some(sig) => host_param_names(name, &sig),
+                            None => params.clone(),
+                        }
+                    }
+                    AbstractBody::Expr(_) => params.clone(),
+                },
+                decl_expr: e.clone(),
+                param_set: trs.subs.clone(),
+            })
         }
+        ExprBody::Match { .. } => Err(TypeError::Custom("match is not supported".into())),
         ExprBody::Never => Err(TypeError::Custom("unexpected never expr".into())),
     }
 }
+
+/// Warns about the parameters of the lambda `e` that its body does not use,
+/// and about the lambdas in its body whose parameters shadow them.
+fn warn_about_params(e: &Expr, params: &[String], body: &Expr, trs: &mut TypeResolveState) {
+    let used = free_vars(body);
+    for p in params {
+        if !used.contains(p) && !source_name(p).starts_with('_') {
+            trs.warn(Warning::UnusedParameter {
+                name: source_name(p).to_string(),
+                expr: e.clone(),
+            });
+        }
+    }
+    let names: Vec<&str> = params.iter().map(|p| source_name(p)).collect();
+    let mut inner = Vec::new();
+    collect_lambdas(body, &mut inner);
+    for l in inner {
+        if let ExprBody::Abstract { ref params, .. } = *l.body {
+            for p in params {
+                if names.contains(&source_name(p)) {
+                    trs.warn(Warning::Shadowing {
+                        name: source_name(p).to_string(),
+                        expr: l.clone(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Adds the lambdas with a body in `e` to `out`.
+fn collect_lambdas(e: &Expr, out: &mut Vec<Expr>) {
+    match *e.body {
+        ExprBody::Apply {
+            ref target,
+            ref params,
+        } => {
+            collect_lambdas(target, out);
+            params.iter().for_each(|p| collect_lambdas(p, out));
+        }
+        ExprBody::Abstract {
+            body: AbstractBody::Expr(ref body),
+            ..
+        } => {
+            out.push(e.clone());
+            collect_lambdas(body, out);
+        }
+        ExprBody::Match {
+            ref value,
+            ref branches,
+        } => {
+            collect_lambdas(value, out);
+            branches.iter().for_each(|(_, e)| collect_lambdas(e, out));
+        }
+        ExprBody::Abstract { .. } | ExprBody::Const(_) | ExprBody::Name(_) | ExprBody::Never => {}
+    }
+}
+
+/// Checks that `e` has the type `expected`, usually one declared by the
+/// host rather than whatever the script happens to compute.
+///
+/// An expected function type is a `FunctionDecl` whose `param_set` binds
+/// each of its `params` to an argument, as built by `function_type`. `e`
+/// must then take the same number of parameters, and calling it with those
+/// arguments must give the type calling the expected declaration gives.
+pub fn check_against<'b>(
+    e: &Expr,
+    expected: &DataType,
+    trs: &mut TypeResolveState<'b>,
+) -> Result<(), TypeError> {
+    let found = check_expr(e, trs)?;
+    check_type_against("result", e, &found, expected, trs)
+}
+
+fn check_type_against<'b>(
+    what: &str,
+    e: &Expr,
+    found: &DataType,
+    expected: &DataType,
+    trs: &mut TypeResolveState<'b>,
+) -> Result<(), TypeError> {
+    let mismatch = || TypeError::Mismatch {
+        what: what.to_string(),
+        expected: TypeDescription::of(expected),
+        found: TypeDescription::of(found),
+    };
+    match (found, expected) {
+        (DataType::Divergent, _) | (DataType::Dynamic, _) | (_, DataType::Dynamic) => Ok(()),
+        (
+            DataType::FunctionDecl {
+                params: ref found_params,
+                ..
+            },
+            DataType::FunctionDecl {
+                ref params,
+                ref decl_expr,
+                ref param_set,
+            },
+        ) => {
+            if found_params.len() != params.len() {
+                return Err(mismatch());
+            }
+            let args = params
+                .iter()
+                .map(|p| {
+                    param_set.get(p).cloned().ok_or_else(|| {
+                        TypeError::Custom(format!(
+                            "expected function type binds no argument for `{}`",
+                            p
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let apply = |target: &Expr| Expr {
+                body: Rc::new(ExprBody::Apply {
+                    target: target.clone(),
+                    params: args.clone(),
+                }),
+            };
+
+            let expected_ret = check_expr(&apply(decl_expr), trs)?;
+            let call = apply(e);
+            let found_ret = check_expr(&call, trs).map_err(|err| {
+                TypeError::Custom(format!("{} with the declared arguments: {}", what, err))
+            })?;
+            check_type_against("return value", &call, &found_ret, &expected_ret, trs)
+        }
+        (DataType::FunctionDecl { .. }, _) | (_, DataType::FunctionDecl { .. }) => Err(mismatch()),
+        _ => {
+            if found == expected {
+                Ok(())
+            } else {
+                Err(mismatch())
+            }
+        }
+    }
+}
+
+/// Builds the type of functions taking arguments of the types `args` and
+/// returning `ret`, for use with `check_against`.
+pub fn function_type(
+    args: &[TypeDescription],
+    ret: &TypeDescription,
+) -> Result<DataType, TypeError> {
+    let witness = |ty: &TypeDescription| {
+        ty.witness()
+            .ok_or_else(|| TypeError::Custom(format!("cannot declare values of type {}", ty)))
+    };
+    let params: Vec<String> = (0..args.len()).map(|i| format!("arg{}", i)).collect();
+    let param_set = params
+        .iter()
+        .zip(args)
+        .map(|(p, ty)| Ok((p.clone(), witness(ty)?)))
+        .collect::<Result<BTreeMap<_, _>, TypeError>>()?;
+    Ok(DataType::FunctionDecl {
+        params: params.clone(),
+        decl_expr: Expr {
+            body: Rc::new(ExprBody::Abstract {
+                params,
+                body: AbstractBody::Expr(witness(ret)?),
+            }),
+        },
+        param_set,
+    })
+}
+
+/// A serializable description of a `DataType`, for embedders that need to
+/// know the shape of a script's value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TypeDescription {
+    Empty,
+    Int,
+    Float,
+    Decimal,
+    Bool,
+    String,
+    Bytes,
+    List(Box<TypeDescription>),
+    /// A function taking `params`, named as in the source. Functions are
+    /// checked anew for each set of argument types, so their result type is
+    /// only known for a given call; see `Engine::infer_call_type`.
+    Function {
+        params: Vec<String>,
+    },
+    /// The value never finishes computing.
+    Divergent,
+    /// The type is only known at runtime.
+    Dynamic,
+    /// An embedder-defined type, by the name `CustomDataType::display` gives.
+    Custom(String),
+    /// A type alias declared with `(type name ty)` or `Engine::define_type`.
+    /// Aliases stand for `ty` when types are compared; the name is kept for
+    /// error messages.
+    Named {
+        name: String,
+        ty: Box<TypeDescription>,
+    },
+}
+
+impl TypeDescription {
+    pub fn of(ty: &DataType) -> TypeDescription {
+        match *ty {
+            DataType::Empty => TypeDescription::Empty,
+            DataType::Value(ValueType::Int) => TypeDescription::Int,
+            DataType::Value(ValueType::Float) => TypeDescription::Float,
+            DataType::Value(ValueType::Bool) => TypeDescription::Bool,
+            DataType::FunctionDecl { ref params, .. } => TypeDescription::Function {
+                params: params.iter().map(|p| param_name(p).to_string()).collect(),
+            },
+            DataType::Divergent => TypeDescription::Divergent,
+            DataType::Dynamic => TypeDescription::Dynamic,
+            DataType::Custom(ref inner) => match inner.as_any().downcast_ref::<ListType>() {
+                Some(list) => {
+                    TypeDescription::List(Box::new(TypeDescription::of(list.inner_type())))
+                }
+                None if inner.as_any().is::<StringType>() => TypeDescription::String,
+                None if inner.as_any().is::<DecimalType>() => TypeDescription::Decimal,
+                None if inner.as_any().is::<BytesType>() => TypeDescription::Bytes,
+                None => TypeDescription::Custom(ty.to_string()),
+            },
+        }
+    }
+
+    /// The type described, unless it is a function or embedder-defined,
+    /// which descriptions do not hold enough to rebuild.
+    pub fn data_type(&self) -> Option<DataType> {
+        Some(match *self {
+            TypeDescription::Empty => DataType::Empty,
+            TypeDescription::Int => DataType::Value(ValueType::Int),
+            TypeDescription::Float => DataType::Value(ValueType::Float),
+            TypeDescription::Bool => DataType::Value(ValueType::Bool),
+            TypeDescription::Decimal => decimal_type(),
+            TypeDescription::String => string_type(),
+            TypeDescription::Bytes => bytes_type(),
+            TypeDescription::List(ref inner) => list_type(inner.data_type()?),
+            TypeDescription::Divergent => DataType::Divergent,
+            TypeDescription::Dynamic => DataType::Dynamic,
+            TypeDescription::Named { ref ty, .. } => return ty.data_type(),
+            TypeDescription::Function { .. } | TypeDescription::Custom(_) => return None,
+        })
+    }
+
+    /// Replaces aliases with the types they stand for.
+    pub fn expand(&self) -> TypeDescription {
+        match *self {
+            TypeDescription::List(ref inner) => TypeDescription::List(Box::new(inner.expand())),
+            TypeDescription::Named { ref ty, .. } => ty.expand(),
+            ref other => other.clone(),
+        }
+    }
+
+    /// Returns an expression of this type, for checking calls with
+    /// arguments of this type. Lists are built with `$list_push`, decimals
+    /// with `$decimal`, and `dyn` is `($dyn $dyn)`.
+    pub(crate) fn witness(&self) -> Option<Expr> {
+        let body = match *self {
+            TypeDescription::Named { ref ty, .. } => return ty.witness(),
+            TypeDescription::Empty => ExprBody::Const(ConstExpr::Empty),
+            TypeDescription::Int => ExprBody::Const(ConstExpr::Int(0)),
+            TypeDescription::Float => ExprBody::Const(ConstExpr::Float(0.0)),
+            TypeDescription::Bool => ExprBody::Const(ConstExpr::Bool(false)),
+            TypeDescription::String => ExprBody::Const(ConstExpr::Str(String::new())),
+            TypeDescription::Bytes => ExprBody::Const(ConstExpr::Bytes(Vec::new())),
+            TypeDescription::Decimal => ExprBody::Apply {
+                target: Expr {
+                    body: Rc::new(ExprBody::Abstract {
+                        params: vec![],
+                        body: AbstractBody::Host("decimal".into()),
+                    }),
+                },
+                params: vec![Expr {
+                    body: Rc::new(ExprBody::Const(ConstExpr::Int(0))),
+                }],
+            },
+            TypeDescription::Dynamic => {
+                let dyn_op = Expr {
+                    body: Rc::new(ExprBody::Abstract {
+                        params: vec![],
+                        body: AbstractBody::Host("dyn".into()),
+                    }),
+                };
+                ExprBody::Apply {
+                    target: dyn_op.clone(),
+                    params: vec![dyn_op],
+                }
+            }
+            TypeDescription::List(ref inner) => ExprBody::Apply {
+                target: Expr {
+                    body: Rc::new(ExprBody::Abstract {
+                        params: vec![],
+                        body: AbstractBody::Host("list_push".into()),
+                    }),
+                },
+                params: vec![
+                    inner.witness()?,
+                    Expr {
+                        body: Rc::new(ExprBody::Const(ConstExpr::Empty)),
+                    },
+                ],
+            },
+            TypeDescription::Function { .. }
+            | TypeDescription::Divergent
+            | TypeDescription::Custom(_) => return None,
+        };
+        Some(Expr {
+            body: Rc::new(body),
+        })
+    }
+}
+
+impl fmt::Display for TypeDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TypeDescription::Empty => write!(f, "empty"),
+            TypeDescription::Int => write!(f, "int"),
+            TypeDescription::Float => write!(f, "float"),
+            TypeDescription::Decimal => write!(f, "decimal"),
+            TypeDescription::Bool => write!(f, "bool"),
+            TypeDescription::String => write!(f, "string"),
+            TypeDescription::Bytes => write!(f, "bytes"),
+            TypeDescription::List(ref inner) => write!(f, "list<{}>", inner),
+            TypeDescription::Function { ref params } => write!(f, "fn({})", params.join(", ")),
+            TypeDescription::Divergent => write!(f, "never"),
+            TypeDescription::Dynamic => write!(f, "dyn"),
+            TypeDescription::Custom(ref name) | TypeDescription::Named { ref name, .. } => {
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
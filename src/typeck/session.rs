@@ -0,0 +1,131 @@
+//! Incremental typechecking of a set of definitions, for editors and other
+//! interactive tools that recheck a program after every edit.
+
+use super::*;
+use crate::ast::free_vars;
+
+/// Definitions being edited, with the result of checking each.
+///
+/// The session tracks which definitions refer to which names. When a
+/// definition changes, only it and the definitions that depend on it,
+/// directly or through others, are checked again; the types of the rest
+/// stay cached.
+pub struct Session {
+    hosts: Vec<(String, Arc<dyn HostFunction>)>,
+    definitions: Definitions,
+    results: BTreeMap<String, Result<DataType, TypeError>>,
+    /// For each name, the definitions referring to it. Names need not be
+    /// defined yet.
+    dependents: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Session {
+    /// Creates a session checking definitions against `hosts`, e.g.
+    /// `HostManager::new().into_owned_hosts()`.
+    pub fn new(hosts: Vec<(String, Arc<dyn HostFunction>)>) -> Session {
+        Session {
+            hosts,
+            definitions: Definitions::default(),
+            results: BTreeMap::new(),
+            dependents: BTreeMap::new(),
+        }
+    }
+
+    /// Adds or replaces the definition `name` and checks it and its
+    /// dependents again. Returns their new results.
+    ///
+    /// `expr` should be parsed with every name it refers to as a global,
+    /// as for `Definitions::define`.
+    pub fn define(
+        &mut self,
+        name: &str,
+        expr: Expr,
+    ) -> BTreeMap<String, Result<DataType, TypeError>> {
+        let refs = free_vars(&expr);
+        self.unlink(name);
+        for r in refs {
+            self.dependents
+                .entry(r)
+                .or_default()
+                .insert(name.to_string());
+        }
+        self.definitions.define(name.to_string(), expr);
+        let affected = self.affected_by(name);
+        self.recheck(affected)
+    }
+
+    /// Removes the definition `name` and checks its dependents again.
+    /// Returns their new results.
+    pub fn remove(&mut self, name: &str) -> BTreeMap<String, Result<DataType, TypeError>> {
+        if self.definitions.remove(name).is_none() {
+            return BTreeMap::new();
+        }
+        self.unlink(name);
+        self.results.remove(name);
+        let mut affected = self.affected_by(name);
+        affected.remove(name);
+        self.recheck(affected)
+    }
+
+    /// The result of checking `name`, if it is defined.
+    pub fn result(&self, name: &str) -> Option<&Result<DataType, TypeError>> {
+        self.results.get(name)
+    }
+
+    /// The definitions that currently fail to check.
+    pub fn errors(&self) -> impl Iterator<Item = (&str, &TypeError)> {
+        self.results
+            .iter()
+            .filter_map(|(k, r)| r.as_ref().err().map(|e| (k.as_str(), e)))
+    }
+
+    pub fn definitions(&self) -> &Definitions {
+        &self.definitions
+    }
+
+    /// Forgets what the current definition of `name` refers to.
+    fn unlink(&mut self, name: &str) {
+        if let Some(def) = self.definitions.get(name) {
+            for r in free_vars(&def.expr) {
+                if let Some(d) = self.dependents.get_mut(&r) {
+                    d.remove(name);
+                }
+            }
+        }
+    }
+
+    /// Returns `name` and the definitions that depend on it.
+    fn affected_by(&self, name: &str) -> BTreeSet<String> {
+        let mut affected = BTreeSet::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(n) = pending.pop() {
+            if let Some(d) = self.dependents.get(&n) {
+                pending.extend(d.iter().filter(|x| !affected.contains(*x)).cloned());
+            }
+            affected.insert(n);
+        }
+        affected
+    }
+
+    fn recheck(
+        &mut self,
+        names: BTreeSet<String>,
+    ) -> BTreeMap<String, Result<DataType, TypeError>> {
+        let mut trs = TypeResolveState::default();
+        trs.add_hosts_owned(self.hosts.iter().cloned());
+        trs.set_definitions(&self.definitions);
+        let mut out = BTreeMap::new();
+        for name in names {
+            if self.definitions.get(&name).is_none() {
+                continue;
+            }
+            let e = Expr {
+                body: Rc::new(ExprBody::Name(name.clone())),
+            };
+            let result = check_expr(&e, &mut trs);
+            self.results.insert(name.clone(), result.clone());
+            out.insert(name, result);
+        }
+        out
+    }
+}
@@ -21,7 +21,7 @@ impl HostFunction for NotFunction {
     fn eval<'b, 'c>(
         &self,
         _ectx: &mut EvalContext<'b, 'c>,
-        _params: &mut Iterator<Item = LazyValue<'b>>,
+        _params: &mut dyn Iterator<Item = LazyValue<'b>>,
     ) -> Result<RuntimeValue<'b>, RuntimeError> {
         unreachable!()
     }
@@ -29,7 +29,7 @@ impl HostFunction for NotFunction {
 
 #[test]
 fn test_typeck() {
-    let mut e = Expr {
+    let e = Expr {
         body: Rc::new(ExprBody::Apply {
             target: Expr {
                 body: Rc::new(ExprBody::Abstract {
@@ -58,8 +58,32 @@ fn test_typeck() {
     let not_f = NotFunction {};
     let mut trs = TypeResolveState::default();
     trs.add_hosts(vec![("not".to_string(), &not_f as &dyn HostFunction)]);
-    let out = check_expr(&mut e, &mut trs).unwrap();
+    let out = check_expr(&e, &mut trs).unwrap();
     if out != DataType::Value(ValueType::Bool) {
         panic!("output type mismatch");
     }
 }
+
+#[test]
+fn test_data_type_display() {
+    let engine = crate::engine::Engine::new();
+    let ty = |source: &str| {
+        let e = engine.parse(source).unwrap();
+        engine.check(&e).unwrap().to_string()
+    };
+    assert_eq!(ty("($add 1 2)"), "int");
+    assert_eq!(ty("(~)"), "empty");
+    assert_eq!(ty("($list_push ($list_push 1.5 ~) ~)"), "list<list<float>>");
+    assert_eq!(ty(r"(\x y ($add x y))"), "fn(x, y)");
+    assert_eq!(ty("($round 1.5)"), "float");
+    assert_eq!(ty("($round)"), "fn(x, digits)");
+
+    let e = engine.parse("($lt 1 ($list_push 1 ~))").unwrap();
+    match engine.check(&e) {
+        Err(crate::error::Error::Type(e)) => assert_eq!(
+            e.to_string(),
+            "unsupported types for rel operator: int and list<int>"
+        ),
+        _ => panic!("expecting a type error"),
+    }
+}
@@ -0,0 +1,95 @@
+use crate::ast::visit::*;
+use crate::ast::*;
+use crate::parser::parse_expr;
+use std::rc::Rc;
+
+/// Collects host function names and the maximum lambda nesting depth.
+#[derive(Default)]
+struct Stats {
+    hosts: Vec<String>,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Visitor for Stats {
+    type Error = ();
+
+    fn pre_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        match *e.body {
+            ExprBody::Abstract {
+                body: AbstractBody::Host(ref name),
+                ..
+            } => self.hosts.push(name.clone()),
+            _ => {
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+        }
+        Ok(())
+    }
+
+    fn post_abstract(&mut self, e: &Expr) -> Result<(), ()> {
+        if let ExprBody::Abstract {
+            body: AbstractBody::Expr(_),
+            ..
+        } = *e.body
+        {
+            self.depth -= 1;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_visitor() {
+    let e = parse_expr(r"((\x ((\y ($add x y)) 1)) ($mul 2 3))").unwrap();
+    let mut stats = Stats::default();
+    walk(&mut stats, &e).unwrap();
+    assert_eq!(stats.hosts, vec!["add", "mul"]);
+    assert_eq!(stats.max_depth, 2);
+    assert_eq!(stats.depth, 0);
+}
+
+/// Replaces `($add a b)` on two int literals with its value.
+struct ConstAdd;
+
+impl Folder for ConstAdd {
+    type Error = String;
+
+    fn fold_name(&mut self, _e: &Expr, name: &str) -> Result<Expr, String> {
+        Err(format!("unexpected name {}", name))
+    }
+
+    fn post_apply(&mut self, _e: &Expr, target: Expr, params: Vec<Expr>) -> Result<Expr, String> {
+        if let ExprBody::Abstract {
+            body: AbstractBody::Host(ref name),
+            ..
+        } = *target.body
+        {
+            if let [ref a, ref b] = *params {
+                if let (ExprBody::Const(ConstExpr::Int(a)), ExprBody::Const(ConstExpr::Int(b))) =
+                    (&*a.body, &*b.body)
+                {
+                    if name == "add" {
+                        return Ok(Expr {
+                            body: Rc::new(ExprBody::Const(ConstExpr::Int(a + b))),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(Expr {
+            body: Rc::new(ExprBody::Apply { target, params }),
+        })
+    }
+}
+
+#[test]
+fn test_folder() {
+    let e = parse_expr("($mul ($add 1 ($add 2 3)) ($add 4 5.0))").unwrap();
+    let folded = fold(&mut ConstAdd, &e).unwrap();
+    assert_eq!(folded.to_string(), "($mul 6 ($add 4 5.0))");
+
+    let e = parse_expr(r"(\x ($add x 1))").unwrap();
+    assert_eq!(fold(&mut ConstAdd, &e).unwrap_err(), "unexpected name x#1");
+}
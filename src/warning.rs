@@ -0,0 +1,103 @@
+//! Non-fatal issues found while checking or evaluating a program.
+//!
+//! Typechecking and evaluation each collect what they find in a
+//! `WarningSink`; the `Engine` returns both together, and the binaries print
+//! them the same way whichever stage raised them.
+
+use crate::ast::*;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// `$eq` or `$ne` comparing floats, which rounding makes unreliable.
+    /// `expr` is the call.
+    FloatEquality { host: String, expr: Expr },
+    /// An arithmetic or comparison operator called with an int and a
+    /// float, converting the int to a float. `expr` is the call.
+    IntToFloat { host: String, expr: Expr },
+    /// A lambda parameter its body never refers to. Parameters starting
+    /// with `_` are exempt. `expr` is the lambda.
+    UnusedParameter { name: String, expr: Expr },
+    /// A lambda parameter with the same name as a parameter of an
+    /// enclosing lambda. `expr` is the inner lambda.
+    Shadowing { name: String, expr: Expr },
+    /// A host function returned NaN or an infinite float, which would
+    /// have failed with `RuntimeError::NonFiniteFloat` under strict floats.
+    NonFiniteFloat { host: String },
+    /// Evaluation used most of its step limit.
+    FuelLow { limit: u64 },
+}
+
+impl Warning {
+    /// The expression the warning is about, for looking up its span in a
+    /// `SpanMap`. Warnings raised during evaluation have none.
+    pub fn expr(&self) -> Option<&Expr> {
+        match *self {
+            Warning::FloatEquality { ref expr, .. }
+            | Warning::IntToFloat { ref expr, .. }
+            | Warning::UnusedParameter { ref expr, .. }
+            | Warning::Shadowing { ref expr, .. } => Some(expr),
+            Warning::NonFiniteFloat { .. } | Warning::FuelLow { .. } => None,
+        }
+    }
+
+    /// Whether `self` and `other` report the same thing. Warnings about an
+    /// expression must be about the same node, not merely an equal one.
+    fn same_as(&self, other: &Warning) -> bool {
+        match (self.expr(), other.expr()) {
+            (Some(a), Some(b)) => {
+                Rc::ptr_eq(&a.body, &b.body) && self.to_string() == other.to_string()
+            }
+            _ => self == other,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::FloatEquality { ref host, .. } => write!(
+                f,
+                "comparing floats with `${}` is unreliable; compare their difference against a tolerance instead",
+                host
+            ),
+            Warning::IntToFloat { ref host, .. } => {
+                write!(f, "`${}` implicitly converts an int operand to a float", host)
+            }
+            Warning::UnusedParameter { ref name, .. } => {
+                write!(f, "unused parameter `{}`", name)
+            }
+            Warning::Shadowing { ref name, .. } => {
+                write!(f, "parameter `{}` shadows an outer binding", name)
+            }
+            Warning::NonFiniteFloat { ref host } => {
+                write!(f, "`${}` returned a non-finite float", host)
+            }
+            Warning::FuelLow { limit } => {
+                write!(f, "evaluation used over 90% of its limit of {} steps", limit)
+            }
+        }
+    }
+}
+
+/// Collects warnings, each once.
+#[derive(Debug, Clone, Default)]
+pub struct WarningSink {
+    warnings: Vec<Warning>,
+}
+
+impl WarningSink {
+    /// Records `w` unless the same warning has been recorded already, as
+    /// happens when checking reaches an expression once for every set of
+    /// arguments or evaluation runs a loop.
+    pub fn warn(&mut self, w: Warning) {
+        if !self.warnings.iter().any(|x| x.same_as(&w)) {
+            self.warnings.push(w);
+        }
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+}
@@ -0,0 +1,84 @@
+use crate::corelib::HostManager;
+use crate::engine::Engine;
+use crate::eval::*;
+use crate::parser::parse_expr;
+use crate::warning::*;
+
+fn messages(warnings: &[Warning]) -> Vec<String> {
+    warnings.iter().map(|w| w.to_string()).collect()
+}
+
+#[test]
+fn test_type_warnings() {
+    let engine = Engine::new();
+    let e = engine
+        .parse(r"((\x y ($add x ((\x ($mul x 2.0)) 3))) 1 2)")
+        .unwrap();
+    let (_, warnings) = engine.check_with_warnings(&e).unwrap();
+    assert_eq!(
+        messages(&warnings),
+        vec![
+            "unused parameter `y`",
+            "parameter `x` shadows an outer binding",
+            "`$mul` implicitly converts an int operand to a float",
+            "`$add` implicitly converts an int operand to a float",
+        ]
+    );
+    match warnings[1] {
+        Warning::Shadowing { ref expr, .. } => assert_eq!(expr.to_string(), r"(\x ($mul x 2.0))"),
+        ref w => panic!("unexpected warning: {:?}", w),
+    }
+
+    let e = engine.parse(r"($add 1 2)").unwrap();
+    assert!(engine.check_with_warnings(&e).unwrap().1.is_empty());
+
+    // `$round` takes an int number of digits, but converts nothing.
+    for source in ["($round 2.567)", "($round 2.567 2)"] {
+        let e = engine.parse(source).unwrap();
+        assert_eq!(
+            messages(&engine.check_with_warnings(&e).unwrap().1),
+            Vec::<String>::new()
+        );
+    }
+
+    let e = engine.parse(r"($lt 1 2.5)").unwrap();
+    assert_eq!(
+        messages(&engine.check_with_warnings(&e).unwrap().1),
+        vec!["`$lt` implicitly converts an int operand to a float"]
+    );
+}
+
+#[test]
+fn test_eval_warnings() {
+    let hm = HostManager::new();
+    let e = parse_expr("($add 1 ($add 2 ($add 3 4)))").unwrap();
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    ectx.set_step_limit(Some(12));
+    assert_eq!(evaluate(&e, &mut ectx).unwrap().warnings, vec![]);
+
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+    ectx.set_step_limit(Some(11));
+    assert_eq!(
+        evaluate(&e, &mut ectx).unwrap().warnings,
+        vec![Warning::FuelLow { limit: 11 }]
+    );
+}
+
+#[test]
+fn test_engine_surfaces_all_warnings() {
+    let engine = Engine::new();
+    let e = engine.parse(r"((\unused ($div 1 0.0)) 5)").unwrap();
+    let outcome = engine.evaluate_with(&e, |v, _| Ok(v.to_string())).unwrap();
+    assert_eq!(outcome.value, "inf");
+    assert_eq!(
+        messages(&outcome.warnings),
+        vec![
+            "unused parameter `unused`",
+            "`$div` implicitly converts an int operand to a float",
+            "`$div` returned a non-finite float",
+        ]
+    );
+}
@@ -0,0 +1,59 @@
+//! wasm-bindgen entry points for running x-lang in the browser.
+//!
+//! Every function takes the program source and registers the full core
+//! library. Results and errors are returned as strings.
+
+use crate::ast::DataType;
+use crate::corelib::HostManager;
+use crate::eval::{eval_expr, EvalContext};
+use crate::host::verify_hosts;
+use crate::parser::parse_expr;
+use crate::recursion::divergence_message;
+use crate::typeck::{check_expr, TypeResolveState};
+use std::fmt::Debug;
+use wasm_bindgen::prelude::*;
+
+fn to_js_error<E: Debug>(e: E) -> JsValue {
+    JsValue::from_str(&format!("{:?}", e))
+}
+
+/// Parses `source` and returns the renamed AST.
+#[wasm_bindgen]
+pub fn parse(source: &str) -> Result<String, JsValue> {
+    let ast = parse_expr(source).map_err(to_js_error)?;
+    Ok(format!("{:?}", ast))
+}
+
+/// Parses and typechecks `source`, returning its type.
+#[wasm_bindgen]
+pub fn typecheck(source: &str) -> Result<String, JsValue> {
+    let ast = parse_expr(source).map_err(to_js_error)?;
+
+    let hm = HostManager::new();
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_all());
+
+    let ty = check_expr(&ast, &mut trs).map_err(to_js_error)?;
+    Ok(ty.to_string())
+}
+
+/// Parses, typechecks and evaluates `source`, returning its value.
+#[wasm_bindgen]
+pub fn eval(source: &str) -> Result<String, JsValue> {
+    let ast = parse_expr(source).map_err(to_js_error)?;
+
+    let hm = HostManager::new();
+    let mut trs = TypeResolveState::default();
+    trs.add_hosts(hm.get_all());
+    let mut ectx = EvalContext::default();
+    ectx.add_hosts(hm.get_all());
+
+    let ty = check_expr(&ast, &mut trs).map_err(to_js_error)?;
+    if ty == DataType::Divergent {
+        return Err(JsValue::from_str(&divergence_message(&ast, None)));
+    }
+
+    verify_hosts(&trs, &ectx).map_err(to_js_error)?;
+    let value = eval_expr(&ast, &mut ectx).map_err(to_js_error)?;
+    Ok(value.to_string())
+}
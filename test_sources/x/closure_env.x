@@ -0,0 +1,32 @@
+# Closures that outlive the scope they were made in, where a large value
+# they do not refer to was bound.
+(
+    (\grow build sum (sum (build grow 40) 0))
+    # 4096 copies of `b`.
+    (\b (
+        (\x2 (x2 (x2 (x2 (x2 (x2 (x2 (x2 (x2 (x2 (x2 (x2 (x2 b)))))))))))))
+        (\v ($bytes_concat v v))
+    ))
+    # n closures, each made where 64 KB of bytes is bound and evaluated.
+    (
+        \y ((\x (y (x x))) (\x (y (x x)))) # Y Combinator
+        \self (\grow n
+            ($if
+                ($eq n 0)
+                ~
+                ($list_push
+                    ((\big ($if ($eq ($bytes_len big) 0) ($dyn (\x (x))) ($dyn (\x ($add x n)))))
+                        (grow 0x"00000000000000000000000000000000"))
+                    (self grow ($sub n 1))
+                )
+            )
+        )
+    )
+    # Applies every closure to 1 and adds up the results.
+    (
+        \y ((\x (y (x x))) (\x (y (x x)))) # Y Combinator
+        \self (\fs acc
+            ($if ($list_is_empty fs) acc (self ($list_tail fs) ($add acc (($list_head fs) 1))))
+        )
+    )
+)
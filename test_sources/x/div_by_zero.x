@@ -0,0 +1,2 @@
+# Typechecks, but fails at runtime.
+($div 10 ($sub 3 3))
@@ -0,0 +1,2 @@
+# Adding a bool to an int is rejected by the typechecker.
+($add 1 ($eq 1 1))
@@ -0,0 +1,28 @@
+extern crate x_lang;
+
+use x_lang::engine::Engine;
+
+const EXPECTED: &[(&str, &str)] = &[
+    ("closures", "16"),
+    ("list_map", "41"),
+    ("list_sum", "55"),
+    ("rules", "184.0"),
+];
+
+#[test]
+fn examples_evaluate() {
+    let engine = Engine::new();
+    assert_eq!(x_lang::examples().len(), EXPECTED.len());
+    for &(name, expected) in EXPECTED {
+        let ex = x_lang::examples::example(name).unwrap();
+        match engine.eval_str(ex.source) {
+            Ok(v) => assert_eq!(v, expected, "example {}", name),
+            Err(e) => panic!("example {} failed: {:?}", name, e),
+        }
+    }
+}
+
+#[test]
+fn example_lookup() {
+    assert!(x_lang::examples::example("nonexistent").is_none());
+}
@@ -0,0 +1,98 @@
+//! Runs every program in the corpus through the full pipeline (parse with
+//! the core library hosts, typecheck, evaluate) and compares the result with
+//! the `.golden` file next to it.
+//!
+//! Set `XL_UPDATE_GOLDEN=1` to rewrite the golden files from the current
+//! output instead of comparing.
+
+extern crate x_lang;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use x_lang::engine::Engine;
+
+const CORPUS_DIRS: &[&str] = &["examples", "test_sources/x"];
+
+fn run(engine: &Engine, source: &str) -> String {
+    let e = match engine.parse(source) {
+        Ok(e) => e,
+        Err(e) => return format!("error: {:?}\n", e),
+    };
+    let mut out = match engine.check(&e) {
+        Ok(ty) => format!("type: {:?}\n", ty),
+        Err(e) => return format!("error: {:?}\n", e),
+    };
+    match engine.eval_with(&e, |v, _| Ok(v.to_string())) {
+        Ok(v) => out.push_str(&format!("value: {}\n", v)),
+        Err(e) => out.push_str(&format!("error: {:?}\n", e)),
+    }
+    out
+}
+
+fn diff(expected: &str, actual: &str) -> String {
+    let mut out = String::new();
+    let (expected, actual): (Vec<_>, Vec<_>) =
+        (expected.lines().collect(), actual.lines().collect());
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(a), Some(b)) if a == b => out.push_str(&format!("  {}\n", a)),
+            (a, b) => {
+                if let Some(a) = a {
+                    out.push_str(&format!("- {}\n", a));
+                }
+                if let Some(b) = b {
+                    out.push_str(&format!("+ {}\n", b));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn corpus() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths = Vec::new();
+    for dir in CORPUS_DIRS {
+        for entry in fs::read_dir(root.join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|x| x == "x") {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    paths
+}
+
+#[test]
+fn golden() {
+    let update = env::var_os("XL_UPDATE_GOLDEN").is_some();
+    let engine = Engine::new();
+    let mut failures = Vec::new();
+
+    for path in corpus() {
+        let source = fs::read_to_string(&path).unwrap();
+        let actual = run(&engine, &source);
+        let golden_path = path.with_extension("golden");
+
+        if update {
+            fs::write(&golden_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&golden_path) {
+            Ok(ref expected) if *expected == actual => {}
+            Ok(expected) => {
+                failures.push(format!("{}:\n{}", path.display(), diff(&expected, &actual)))
+            }
+            Err(_) => failures.push(format!("{}: missing golden file", path.display())),
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!(
+            "golden mismatches (rerun with XL_UPDATE_GOLDEN=1 to accept):\n\n{}",
+            failures.join("\n")
+        );
+    }
+}